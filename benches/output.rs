@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+use nasdaq_itch_orderbook::features::{FeatureColumns, TradeStats};
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, LuldCollar, OrderBook, OrderBookOptions, PriceLevel, RetailInterest, MAX_BOOK_DEPTH};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::sink::CsvSink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::timestamps::MarketHours;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+// A full book: ten levels a side around $150
+fn snapshot() -> BookSnapshot {
+    let level = |i: u32, price: u32| PriceLevel { price, total_volume: 100 * (i + 1) };
+    BookSnapshot {
+        timestamp: 34_200_123_456_789,
+        bid_levels: (0..MAX_BOOK_DEPTH as u32).map(|i| level(i, 1_500_000 - 100 * i)).collect(),
+        ask_levels: (0..MAX_BOOK_DEPTH as u32).map(|i| level(i, 1_500_100 + 100 * i)).collect(),
+        mid_price: 150.005,
+        imbalance: 0.123456,
+        trades: TradeStats::default(),
+        ofi: 0,
+        windows: Vec::new(),
+        retail_interest: RetailInterest::None,
+        luld: LuldCollar::default(),
+        mwcb_level: 0,
+        short_sale_restricted: false,
+        operational_halts: 0,
+        market_hours: MarketHours::default(),
+    }
+}
+
+// Formatting one orderbook row
+fn csv_row(c: &mut Criterion) {
+    let snapshot = snapshot();
+    let columns = FeatureColumns::default();
+    let mut row = Vec::with_capacity(1024);
+    let mut group = c.benchmark_group("csv_row");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("depth", |b| b.iter(|| {
+        row.clear();
+        black_box(&snapshot).write_depth_row(&mut row, MAX_BOOK_DEPTH, &columns).unwrap();
+    }));
+    group.bench_function("bbo", |b| b.iter(|| {
+        row.clear();
+        black_box(&snapshot).write_bbo_row(&mut row, &columns).unwrap();
+    }));
+    group.finish();
+}
+
+// The whole path from the feed to a full depth CSV file, where every update writes a row
+fn build_book_csv(c: &mut Criterion) {
+    let data = SyntheticFeed { messages: 100_000, symbols: vec!["AAPL".to_string()], ..Default::default() }.generate();
+    let path = std::env::temp_dir().join(format!("bench-output-{}.csv", std::process::id()));
+    let mut group = c.benchmark_group("build_book_csv");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("every_message", |b| b.iter(|| {
+        let sink = CsvSink::create(&path, false, FeatureColumns::default(), None).unwrap();
+        let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![Box::new(sink)], ..Default::default() })];
+        parser::process_itch_file(black_box(&data), &mut books, &ParserOptions::default()).unwrap();
+    }));
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+}
+
+criterion_group!(benches, csv_row, build_book_csv);
+criterion_main!(benches);
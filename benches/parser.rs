@@ -0,0 +1,128 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hint::black_box;
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+
+use rustc_hash::FxHashMap;
+
+use nasdaq_itch_orderbook::ladder::PriceLadder;
+use nasdaq_itch_orderbook::orderbook::{Order, OrderBook, OrderBookOptions, Side, MAX_BOOK_DEPTH};
+use nasdaq_itch_orderbook::pool::OrderPool;
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::prescan;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+// Two hundred thousand order messages over eight symbols
+fn feed() -> Vec<u8> {
+    SyntheticFeed { messages: 200_000, ..Default::default() }.generate()
+}
+
+// Framing only: how fast the messages are walked without touching a book
+fn scan(c: &mut Criterion) {
+    let data = feed();
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("scan_messages", |b| b.iter(|| {
+        let mut count = 0u64;
+        parser::scan_messages(black_box(&data), |_, _| {
+            count += 1;
+            ControlFlow::Continue(())
+        });
+        count
+    }));
+    group.finish();
+}
+
+// The full parse into one symbol's book, with and without the lookups the filters avoid
+fn build_book(c: &mut Criterion) {
+    let data = feed();
+    let symbol = pad_stock_symbol("AAPL");
+    let mut group = c.benchmark_group("build_book");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    for fast_filter in [false, true] {
+        group.bench_with_input(BenchmarkId::new("fast_filter", fast_filter), &fast_filter, |b, &fast_filter| b.iter(|| {
+            let mut books = [OrderBook::new(symbol, OrderBookOptions { fast_filter, ..Default::default() })];
+            parser::process_itch_file(black_box(&data), &mut books, &ParserOptions::default()).unwrap();
+        }));
+    }
+    group.bench_function("prescan", |b| b.iter(|| {
+        let ranges = prescan::relevant_ranges(black_box(&data), &[symbol]);
+        let mut books = [OrderBook::new(symbol, OrderBookOptions::default())];
+        parser::process_itch_file(&data, &mut books, &ParserOptions { ranges: Some(ranges), ..Default::default() }).unwrap();
+    }));
+    group.finish();
+}
+
+// Orders added and taken off within fifty cents of the inside, reading the top levels after each,
+// against the BTreeMap the book used before the ladder
+fn price_levels(c: &mut Criterion) {
+    let mut seed = 7u64;
+    let mut next = move || {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        (seed >> 33) as u32
+    };
+    let adds: Vec<(u32, u32)> = (0..100_000).map(|_| (1_500_000 - 100 * (next() % 50), 100 * (1 + next() % 5))).collect();
+    let mut group = c.benchmark_group("price_levels");
+    group.throughput(Throughput::Elements(adds.len() as u64));
+    group.bench_function("btreemap", |b| b.iter(|| {
+        let mut levels = BTreeMap::new();
+        for (i, &(price, shares)) in adds.iter().enumerate() {
+            *levels.entry(price).or_insert(0u32) += shares;
+            if i >= 1_000 {
+                let (price, shares) = adds[i - 1_000];
+                let volume = levels.get_mut(&price).unwrap();
+                *volume -= shares;
+                if *volume == 0 {
+                    levels.remove(&price);
+                }
+            }
+            black_box(levels.iter().rev().take(MAX_BOOK_DEPTH).count());
+        }
+    }));
+    group.bench_function("ladder", |b| b.iter(|| {
+        let mut levels = PriceLadder::new(Side::Buy);
+        for (i, &(price, shares)) in adds.iter().enumerate() {
+            levels.add(price, shares);
+            if i >= 1_000 {
+                let (price, shares) = adds[i - 1_000];
+                levels.reduce(price, shares);
+            }
+            black_box(levels.top(MAX_BOOK_DEPTH));
+        }
+    }));
+    group.finish();
+}
+
+// A million orders added and deleted with a hundred thousand resting, the churn of a whole-market day,
+// against a map holding the orders themselves
+fn order_pool(c: &mut Criterion) {
+    const RESTING: u64 = 100_000;
+    let order = |ref_number: u64| Order { ref_number, timestamp: ref_number, price: 1_500_000, shares: 100, side: Side::Buy, mpid: None };
+    let mut group = c.benchmark_group("order_pool");
+    group.throughput(Throughput::Elements(1_000_000));
+    group.bench_function("hashmap", |b| b.iter(|| {
+        let mut orders = FxHashMap::default();
+        for ref_number in 0..1_000_000u64 {
+            orders.insert(ref_number, order(ref_number));
+            if ref_number >= RESTING {
+                black_box(orders.remove(&(ref_number - RESTING)));
+            }
+        }
+    }));
+    for expected in [0, RESTING as usize] {
+        group.bench_with_input(BenchmarkId::new("pool", expected), &expected, |b, &expected| b.iter(|| {
+            let mut orders = OrderPool::with_capacity(expected);
+            for ref_number in 0..1_000_000u64 {
+                orders.insert(order(ref_number));
+                if ref_number >= RESTING {
+                    black_box(orders.remove(ref_number - RESTING));
+                }
+            }
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scan, build_book, price_levels, order_pool);
+criterion_main!(benches);
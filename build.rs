@@ -0,0 +1,26 @@
+// Generates the C header of the ffi feature's API into include/, and the grpc feature's service from proto/
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        cbindgen::Builder::new()
+            .with_src(format!("{crate_dir}/src/ffi.rs"))
+            .with_language(cbindgen::Language::C)
+            .with_include_guard("NASDAQ_ITCH_ORDERBOOK_H")
+            .with_header("/* C API of nasdaq-itch-orderbook, generated by build.rs with --features ffi. Do not edit */")
+            .with_documentation(true)
+            .with_style(cbindgen::Style::Both)
+            .generate()
+            .expect("Failed to generate the C header")
+            .write_to_file(format!("{crate_dir}/include/nasdaq_itch_orderbook.h"));
+    }
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/orderbook.proto");
+        // protoc comes with the build rather than from the system
+        unsafe { std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().expect("No protoc for this platform")) };
+        tonic_prost_build::compile_protos("proto/orderbook.proto").expect("Failed to compile proto/orderbook.proto");
+    }
+}
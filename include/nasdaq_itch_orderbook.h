@@ -0,0 +1,73 @@
+/* C API of nasdaq-itch-orderbook, generated by build.rs with --features ffi. Do not edit */
+
+#ifndef NASDAQ_ITCH_ORDERBOOK_H
+#define NASDAQ_ITCH_ORDERBOOK_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define ITCH_MAX_LEVELS 10
+
+typedef struct ItchReader ItchReader;
+
+/**
+ * Prices are in 1/10000 dollars
+ */
+typedef struct ItchLevel {
+  uint32_t price;
+  uint32_t volume;
+} ItchLevel;
+
+typedef struct ItchSnapshot {
+  /**
+   * Space padded, not NUL terminated
+   */
+  uint8_t symbol[8];
+  /**
+   * Nanoseconds since midnight
+   */
+  uint64_t timestamp;
+  uint32_t bid_count;
+  uint32_t ask_count;
+  /**
+   * Best level first; only the first bid_count and ask_count are set
+   */
+  struct ItchLevel bids[ITCH_MAX_LEVELS];
+  struct ItchLevel asks[ITCH_MAX_LEVELS];
+  double imbalance;
+} ItchSnapshot;
+
+/**
+ * Map an ITCH 5.0 file and build the books of the comma separated symbols. Returns NULL on failure
+ *
+ * # Safety
+ * path and symbols must be NUL terminated strings
+ */
+struct ItchReader *itch_open(const char *path, const char *symbols);
+
+/**
+ * Write the next snapshot of any of the symbols to out. Returns 1 when one was written, 0 at the end of
+ * the file and -1 on error
+ *
+ * # Safety
+ * reader must come from itch_open and not be closed; out must point to an ItchSnapshot
+ */
+int itch_next_snapshot(struct ItchReader *reader,
+                       struct ItchSnapshot *out);
+
+/**
+ * Unmap the file and free the books
+ *
+ * # Safety
+ * reader must come from itch_open (or be NULL) and not be used afterwards
+ */
+void itch_close(struct ItchReader *reader);
+
+/**
+ * Message of the last failed call on this thread, or NULL. Valid until the next failing call
+ */
+const char *itch_last_error(void);
+
+#endif  /* NASDAQ_ITCH_ORDERBOOK_H */
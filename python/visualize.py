@@ -162,6 +162,31 @@ def websocket_process_main(uri, data_queue, stop_event, skip_premarket):
     finally:
         print("WebSocket process exiting")
 
+def itch_process_main(path, symbol, data_queue, stop_event, skip_premarket):
+    """Build the book with the Rust engine (the nasdaq_itch_orderbook extension) and feed its snapshots to the queue"""
+    import time
+    import nasdaq_itch_orderbook
+
+    book = nasdaq_itch_orderbook.ItchReader(path).book(symbol, write_mode='on-change')
+    snapshots = book.snapshots()
+    print(f"Built {len(book)} snapshots of {book.symbol} from {path}")
+    for row, ts in enumerate(snapshots['timestamp']):
+        if stop_event.is_set():
+            break
+        if skip_premarket and ts < MARKET_OPEN_NS:
+            continue
+        data = {'timestamp': int(ts),
+                'mid_price': float(snapshots['mid_price'][row]),
+                'orderbook_imbalance': float(snapshots['orderbook_imbalance'][row])}
+        for i in range(num_levels):
+            data[f'{i + 1}_bid_price'] = float(snapshots['bid_price'][row, i])
+            data[f'{i + 1}_bid_vol'] = int(snapshots['bid_vol'][row, i])
+            data[f'{i + 1}_ask_price'] = float(snapshots['ask_price'][row, i])
+            data[f'{i + 1}_ask_vol'] = int(snapshots['ask_vol'][row, i])
+        data_queue.put(data)
+        # Same drip as the WebSocket server
+        time.sleep(0.05)
+
 # Extract bid/ask levels from row
 def extract_orderbook_levels(row):
     bids = [(row[f'{i}_bid_price'], row[f'{i}_bid_vol']) for i in range(1, num_levels + 1)]
@@ -323,6 +348,8 @@ def main():
     parser = argparse.ArgumentParser(description='Visualize ITCH orderbook data')
     parser.add_argument('--skip-premarket', action = 'store_true', help='Skip premarket data')
     parser.add_argument('--uri', '-u', type=str, default='ws://localhost:8473', help='WebSocket server URI')
+    parser.add_argument('--itch', type=str, help='Build the book from this ITCH file with the Rust extension instead of connecting to the server')
+    parser.add_argument('--symbol', type=str, default='AAPL', help='Symbol to build with --itch')
     args = parser.parse_args()
 
     # Set the start method for multiprocessing
@@ -342,14 +369,23 @@ def main():
     signal.signal(signal.SIGINT, signal_handler)
     signal.signal(signal.SIGTERM, signal_handler)
 
-    # Start WebSocket client in a separate process
-    ws_process = Process(
-        target=websocket_process_main,
-        args=(args.uri, data_queue, stop_event, args.skip_premarket),
-        daemon=True
-    )
-    ws_process.start()
-    print(f"WebSocket process started. Connecting to {args.uri}...")
+    # Start WebSocket client (or the ITCH reader) in a separate process
+    if args.itch:
+        ws_process = Process(
+            target=itch_process_main,
+            args=(args.itch, args.symbol, data_queue, stop_event, args.skip_premarket),
+            daemon=True
+        )
+        ws_process.start()
+        print(f"Reading {args.symbol} from {args.itch}...")
+    else:
+        ws_process = Process(
+            target=websocket_process_main,
+            args=(args.uri, data_queue, stop_event, args.skip_premarket),
+            daemon=True
+        )
+        ws_process.start()
+        print(f"WebSocket process started. Connecting to {args.uri}...")
 
     try:
         # Start animation in main process
@@ -0,0 +1,57 @@
+// Pinning the threads of a run to chosen cores, so a live session on a multi-socket capture box isn't
+// moved between cores or sockets by the scheduler halfway through
+
+use std::io;
+
+// The cores of --pin-cores in the order they are handed out: the parser, the output writer, then the
+// runtime threads serving clients share whatever is left
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CorePlan {
+    pub parser: Option<usize>,
+    pub writer: Option<usize>,
+    pub runtime: Vec<usize>,
+}
+
+impl CorePlan {
+    pub fn new(cores: &[usize]) -> Self {
+        CorePlan {
+            parser: cores.first().copied(),
+            writer: cores.get(1).copied(),
+            runtime: cores.get(2..).unwrap_or_default().to_vec(),
+        }
+    }
+}
+
+// Restrict the calling thread to the given cores; no cores leaves it as it is
+pub fn pin_current_thread(cores: &[usize]) -> io::Result<()> {
+    if cores.is_empty() {
+        return Ok(());
+    }
+    #[cfg(all(feature = "native", target_os = "linux"))]
+    {
+        // SAFETY: the set is a plain bitmask, initialized before use and only read by the call
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for &core in cores {
+                if core >= libc::CPU_SETSIZE as usize {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Core {} is out of range", core)));
+                }
+                libc::CPU_SET(core, &mut set);
+            }
+            if libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) != 0 {
+                let error = io::Error::last_os_error();
+                return Err(io::Error::new(error.kind(), format!("Can't pin to cores {:?}: {}", cores, error)));
+            }
+        }
+        Ok(())
+    }
+    #[cfg(not(all(feature = "native", target_os = "linux")))]
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Pinning threads to cores is only supported on Linux"))
+}
+
+// NUMA node of a core from sysfs, None where the system doesn't say
+pub fn numa_node(core: usize) -> Option<usize> {
+    let entries = std::fs::read_dir(format!("/sys/devices/system/cpu/cpu{}", core)).ok()?;
+    entries.filter_map(Result::ok)
+        .find_map(|entry| entry.file_name().to_str()?.strip_prefix("node")?.parse().ok())
+}
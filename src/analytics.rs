@@ -0,0 +1,322 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rustc_hash::FxHashMap;
+
+use crate::metrics::METRICS;
+use crate::orderbook::PriceLevel;
+use crate::queue::OrderEvent;
+
+// How many of the busiest milliseconds are reported
+const BUSIEST_MILLISECONDS: usize = 10;
+
+// Upper bounds of the lifetime histogram buckets, in nanoseconds, with their labels
+const LIFETIME_BUCKETS: [(u64, &str); 9] = [
+    (1_000, "<1us"),
+    (10_000, "<10us"),
+    (100_000, "<100us"),
+    (1_000_000, "<1ms"),
+    (10_000_000, "<10ms"),
+    (100_000_000, "<100ms"),
+    (1_000_000_000, "<1s"),
+    (10_000_000_000, "<10s"),
+    (60_000_000_000, "<1m"),
+];
+
+// Collects order lifecycle statistics of the tracked symbol and writes a JSON summary at the end
+pub struct OrderAnalytics {
+    path: PathBuf,
+    // Live orders: ref -> (add timestamp, remaining shares)
+    orders: FxHashMap<u64, (u64, u32)>,
+    // Lifetimes of orders that left the book, by how they left
+    executed_lifetimes: Vec<u64>,
+    cancelled_lifetimes: Vec<u64>,
+    replaced_lifetimes: Vec<u64>,
+    adds: u64,
+    executes: u64,
+    cancels: u64,
+    deletes: u64,
+    replaces: u64,
+    added_shares: u64,
+    executed_shares: u64,
+    cancelled_shares: u64,
+    // Order messages of the symbol by ITCH message type
+    message_counts: [u64; 256],
+    // Order messages of the symbol per millisecond since midnight
+    per_millisecond: FxHashMap<u64, u32>,
+    top: TopOfBookTime,
+    // Adds outside the price band, and how many of them were kept off the book
+    out_of_band: u64,
+    dropped_out_of_band: u64,
+}
+
+// Time spent in each top of book state, for time-weighted averages. A top holds from the update that set it
+// until the next one, and the last one until the symbol's last message
+#[derive(Default)]
+struct TopOfBookTime {
+    bid: Option<PriceLevel>,
+    ask: Option<PriceLevel>,
+    // When the current top was set, None before the first update
+    since: Option<u64>,
+    // Latest timestamp of the symbol's messages
+    last: u64,
+    observed_ns: u64,
+    // Time with a bid, with an ask, and with both, and the shares or spread times those durations
+    bid_ns: u64,
+    bid_depth: u128,
+    ask_ns: u64,
+    ask_depth: u128,
+    two_sided_ns: u64,
+    spread: i128,
+    locked_ns: u64,
+    crossed_ns: u64,
+}
+
+impl TopOfBookTime {
+    // Add the time the current top held until `timestamp`
+    fn advance(&mut self, timestamp: u64) {
+        self.last = self.last.max(timestamp);
+        let Some(since) = self.since else {
+            return;
+        };
+        let duration = timestamp.saturating_sub(since);
+        self.since = Some(since.max(timestamp));
+        self.observed_ns += duration;
+        if let Some(bid) = self.bid {
+            self.bid_ns += duration;
+            self.bid_depth += bid.total_volume as u128 * duration as u128;
+        }
+        if let Some(ask) = self.ask {
+            self.ask_ns += duration;
+            self.ask_depth += ask.total_volume as u128 * duration as u128;
+        }
+        if let (Some(bid), Some(ask)) = (self.bid, self.ask) {
+            self.two_sided_ns += duration;
+            self.spread += (ask.price as i128 - bid.price as i128) * duration as i128;
+            match ask.price.cmp(&bid.price) {
+                std::cmp::Ordering::Equal => self.locked_ns += duration,
+                std::cmp::Ordering::Less => self.crossed_ns += duration,
+                std::cmp::Ordering::Greater => {}
+            }
+        }
+    }
+
+    fn summary(&mut self) -> serde_json::Value {
+        self.advance(self.last);
+        let average = |sum: u128, ns: u64| (ns > 0).then(|| sum as f64 / ns as f64);
+        let percent = |ns: u64| (self.two_sided_ns > 0).then(|| 100.0 * ns as f64 / self.two_sided_ns as f64);
+        serde_json::json!({
+            "observed_ns": self.observed_ns,
+            "two_sided_ns": self.two_sided_ns,
+            "average_spread": (self.two_sided_ns > 0).then(|| self.spread as f64 / self.two_sided_ns as f64 / 10000.0),
+            "average_bid_depth": average(self.bid_depth, self.bid_ns),
+            "average_ask_depth": average(self.ask_depth, self.ask_ns),
+            "locked_percent": percent(self.locked_ns),
+            "crossed_percent": percent(self.crossed_ns),
+        })
+    }
+}
+
+// Percentiles and histogram of a set of lifetimes, in nanoseconds
+fn lifetime_summary(lifetimes: &mut [u64]) -> serde_json::Value {
+    if lifetimes.is_empty() {
+        return serde_json::json!({ "count": 0 });
+    }
+    lifetimes.sort_unstable();
+    let percentile = |p: f64| lifetimes[((lifetimes.len() - 1) as f64 * p).round() as usize];
+    let mean = lifetimes.iter().map(|&l| l as f64).sum::<f64>() / lifetimes.len() as f64;
+
+    // Buckets stay in order as an array, JSON objects are written with sorted keys
+    let mut histogram = Vec::new();
+    let mut counted = 0;
+    for (bound, label) in LIFETIME_BUCKETS {
+        let below = lifetimes.partition_point(|&l| l < bound);
+        histogram.push(serde_json::json!({ "bucket": label, "count": below - counted }));
+        counted = below;
+    }
+    histogram.push(serde_json::json!({ "bucket": ">=1m", "count": lifetimes.len() - counted }));
+
+    serde_json::json!({
+        "count": lifetimes.len(),
+        "mean_ns": mean.round() as u64,
+        "min_ns": lifetimes[0],
+        "p10_ns": percentile(0.10),
+        "p25_ns": percentile(0.25),
+        "p50_ns": percentile(0.50),
+        "p75_ns": percentile(0.75),
+        "p90_ns": percentile(0.90),
+        "p99_ns": percentile(0.99),
+        "max_ns": lifetimes[lifetimes.len() - 1],
+        "histogram": histogram,
+    })
+}
+
+// A ratio that is null instead of infinite or NaN when the denominator is 0
+fn ratio(numerator: u64, denominator: u64) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+impl OrderAnalytics {
+    pub fn new(path: &Path) -> Self {
+        OrderAnalytics {
+            path: path.to_path_buf(),
+            orders: FxHashMap::default(),
+            executed_lifetimes: Vec::new(),
+            cancelled_lifetimes: Vec::new(),
+            replaced_lifetimes: Vec::new(),
+            adds: 0,
+            executes: 0,
+            cancels: 0,
+            deletes: 0,
+            replaces: 0,
+            added_shares: 0,
+            executed_shares: 0,
+            cancelled_shares: 0,
+            message_counts: [0; 256],
+            per_millisecond: FxHashMap::default(),
+            top: TopOfBookTime::default(),
+            out_of_band: 0,
+            dropped_out_of_band: 0,
+        }
+    }
+
+    // The best bid and ask after a message of the symbol, None for an empty side
+    pub fn update_top(&mut self, timestamp: u64, bid: Option<PriceLevel>, ask: Option<PriceLevel>) {
+        self.top.advance(timestamp);
+        self.top.since.get_or_insert(timestamp);
+        (self.top.bid, self.top.ask) = (bid, ask);
+    }
+
+    // An add outside the price band; a dropped one never reaches `add`
+    pub fn out_of_band(&mut self, dropped: bool) {
+        self.out_of_band += 1;
+        self.dropped_out_of_band += dropped as u64;
+    }
+
+    fn count_message(&mut self, message_type: u8, timestamp: u64) {
+        self.top.last = self.top.last.max(timestamp);
+        self.message_counts[message_type as usize] += 1;
+        *self.per_millisecond.entry(timestamp / 1_000_000).or_insert(0) += 1;
+    }
+
+    // A new order; the order a replace creates carries on the replaced one, so it isn't counted as an add
+    pub fn add(&mut self, message_type: u8, order_ref: u64, timestamp: u64, shares: u32) {
+        self.orders.insert(order_ref, (timestamp, shares));
+        if message_type == b'U' {
+            return;
+        }
+        self.count_message(message_type, timestamp);
+        self.adds += 1;
+        self.added_shares += shares as u64;
+    }
+
+    // An execute, cancel, delete or replace of an order; None takes all its shares.
+    // Messages for orders that were never added are ignored
+    pub fn reduce(&mut self, message_type: u8, timestamp: u64, order_ref: u64, event: OrderEvent, shares: Option<u32>) {
+        let Some(&(added, remaining)) = self.orders.get(&order_ref) else {
+            return;
+        };
+        self.count_message(message_type, timestamp);
+        let shares = shares.unwrap_or(remaining).min(remaining);
+        match event {
+            OrderEvent::Execute => {
+                self.executes += 1;
+                self.executed_shares += shares as u64;
+            }
+            OrderEvent::Cancel => {
+                self.cancels += 1;
+                self.cancelled_shares += shares as u64;
+            }
+            OrderEvent::Delete => {
+                self.deletes += 1;
+                self.cancelled_shares += shares as u64;
+            }
+            // The replaced shares aren't cancelled, they move to the new order
+            OrderEvent::Replace => self.replaces += 1,
+            OrderEvent::Add | OrderEvent::Open => {}
+        }
+
+        if shares < remaining {
+            self.orders.insert(order_ref, (added, remaining - shares));
+            return;
+        }
+        self.orders.remove(&order_ref);
+        let lifetime = timestamp.saturating_sub(added);
+        match event {
+            OrderEvent::Execute => self.executed_lifetimes.push(lifetime),
+            OrderEvent::Replace => self.replaced_lifetimes.push(lifetime),
+            _ => self.cancelled_lifetimes.push(lifetime),
+        }
+    }
+
+    // Write the summary as pretty printed JSON
+    pub fn finalize(&mut self, symbol: &str) -> io::Result<()> {
+        let mut messages = serde_json::Map::new();
+        for (message_type, &count) in self.message_counts.iter().enumerate() {
+            if count > 0 {
+                messages.insert((message_type as u8 as char).to_string(), count.into());
+            }
+        }
+        let mut file_messages = serde_json::Map::new();
+        for (message_type, count) in METRICS.message_counts() {
+            file_messages.insert((message_type as char).to_string(), count.into());
+        }
+
+        let mut busiest: Vec<(u64, u32)> = self.per_millisecond.iter().map(|(&ms, &count)| (ms, count)).collect();
+        busiest.sort_unstable_by_key(|&(ms, count)| (std::cmp::Reverse(count), ms));
+        let busiest: Vec<serde_json::Value> = busiest.iter().take(BUSIEST_MILLISECONDS)
+            .map(|&(ms, count)| serde_json::json!({
+                "time": format!("{:02}:{:02}:{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000),
+                "millisecond": ms,
+                "messages": count,
+            }))
+            .collect();
+
+        let mut all_lifetimes: Vec<u64> = self.executed_lifetimes.iter()
+            .chain(&self.cancelled_lifetimes)
+            .chain(&self.replaced_lifetimes)
+            .copied()
+            .collect();
+        let summary = serde_json::json!({
+            "symbol": symbol,
+            "orders": {
+                "adds": self.adds,
+                "executes": self.executes,
+                "cancels": self.cancels,
+                "deletes": self.deletes,
+                "replaces": self.replaces,
+                "resting_at_end": self.orders.len(),
+                "add_to_cancel_ratio": ratio(self.adds, self.cancels + self.deletes),
+                // Share of the orders that left the book that were cancelled or deleted rather than executed or replaced
+                "cancel_rate": ratio(self.cancelled_lifetimes.len() as u64, all_lifetimes.len() as u64),
+            },
+            "shares": {
+                "added": self.added_shares,
+                "executed": self.executed_shares,
+                "cancelled": self.cancelled_shares,
+                "executed_fraction": ratio(self.executed_shares, self.added_shares),
+                "cancelled_fraction": ratio(self.cancelled_shares, self.added_shares),
+            },
+            "lifetimes": {
+                "all": lifetime_summary(&mut all_lifetimes),
+                "executed": lifetime_summary(&mut self.executed_lifetimes),
+                "cancelled": lifetime_summary(&mut self.cancelled_lifetimes),
+                "replaced": lifetime_summary(&mut self.replaced_lifetimes),
+            },
+            "symbol_messages": messages,
+            "file_messages": file_messages,
+            "busiest_milliseconds": busiest,
+            "top_of_book": self.top.summary(),
+            "price_band": {
+                "out_of_band_adds": self.out_of_band,
+                "dropped": self.dropped_out_of_band,
+            },
+        });
+
+        let json = serde_json::to_string_pretty(&summary).map_err(io::Error::other)?;
+        fs::write(&self.path, json + "\n")?;
+        tracing::info!("Wrote order analytics to {}", self.path.display());
+        Ok(())
+    }
+}
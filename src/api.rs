@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use tokio::net::TcpListener;
+
+use crate::metrics::METRICS;
+use crate::features::FeatureColumns;
+use crate::orderbook::{BookSnapshot, TradeRecord};
+use crate::sink::OutputSink;
+use crate::utils::parse_time_of_day;
+
+// State shared between the orderbook and the HTTP API
+#[derive(Debug)]
+pub struct ApiState {
+    // Latest snapshot per symbol
+    books: RwLock<HashMap<String, Arc<BookSnapshot>>>,
+    // Every trade seen per symbol, in timestamp order
+    trades: RwLock<HashMap<String, Vec<TradeRecord>>>,
+    messages: AtomicU64,
+    book_updates: AtomicU64,
+    skipped_updates: AtomicU64,
+    finished: AtomicBool,
+    started: Instant,
+}
+
+impl Default for ApiState {
+    fn default() -> Self {
+        ApiState {
+            books: RwLock::new(HashMap::new()),
+            trades: RwLock::new(HashMap::new()),
+            messages: AtomicU64::new(0),
+            book_updates: AtomicU64::new(0),
+            skipped_updates: AtomicU64::new(0),
+            finished: AtomicBool::new(false),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl ApiState {
+    pub fn publish_snapshot(&self, symbol: &str, snapshot: &BookSnapshot) {
+        let mut books = self.books.write().unwrap();
+        match books.get_mut(symbol) {
+            Some(latest) => *latest = Arc::new(snapshot.clone()),
+            None => { books.insert(symbol.to_string(), Arc::new(snapshot.clone())); },
+        }
+    }
+
+    pub fn record_trade(&self, symbol: &str, trade: TradeRecord) {
+        let mut trades = self.trades.write().unwrap();
+        match trades.get_mut(symbol) {
+            Some(list) => list.push(trade),
+            None => { trades.insert(symbol.to_string(), vec![trade]); },
+        }
+    }
+
+    pub fn update_counts(&self, messages: u64, book_updates: u64, skipped_updates: u64) {
+        self.messages.store(messages, Ordering::Relaxed);
+        self.book_updates.store(book_updates, Ordering::Relaxed);
+        self.skipped_updates.store(skipped_updates, Ordering::Relaxed);
+    }
+
+    pub fn mark_finished(&self) {
+        self.finished.store(true, Ordering::Relaxed);
+    }
+}
+
+// Feeds the orderbook's output into the shared API state
+pub struct ApiSink(pub Arc<ApiState>);
+
+impl OutputSink for ApiSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        self.0.publish_snapshot(symbol, snapshot);
+        Ok(())
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.0.record_trade(symbol, trade.clone());
+        Ok(())
+    }
+
+    fn update_counts(&mut self, messages: u64, book_updates: u64, skipped_updates: u64) {
+        self.0.update_counts(messages, book_updates, skipped_updates);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.0.mark_finished();
+        Ok(())
+    }
+}
+
+fn json_response(status: StatusCode, body: String) -> Response {
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response {
+    let message = serde_json::to_string(message).unwrap_or_else(|_| "\"\"".to_string());
+    json_response(status, format!("{{\"error\":{}}}", message))
+}
+
+// GET /book/{symbol}: latest snapshot for the symbol
+async fn get_book(State(state): State<Arc<ApiState>>, Path(symbol): Path<String>) -> Response {
+    let symbol = symbol.to_uppercase();
+    let snapshot = state.books.read().unwrap().get(&symbol).cloned();
+    match snapshot {
+        Some(snapshot) => {
+            let depth = snapshot.bid_levels.len().max(snapshot.ask_levels.len());
+            json_response(StatusCode::OK, snapshot.to_json(&symbol, depth, &FeatureColumns::default()))
+        }
+        None => error_response(StatusCode::NOT_FOUND, &format!("No book for symbol {}", symbol)),
+    }
+}
+
+// GET /trades/{symbol}?from=HH:MM:SS&to=HH:MM:SS: trades in the inclusive time range
+async fn get_trades(
+    State(state): State<Arc<ApiState>>,
+    Path(symbol): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let symbol = symbol.to_uppercase();
+    let bound = |key: &str, default: u64| params.get(key).map_or(Ok(default), |v| parse_time_of_day(v));
+    let (from, to) = match (bound("from", 0), bound("to", u64::MAX)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => return error_response(StatusCode::BAD_REQUEST, &e),
+    };
+
+    let trades = state.trades.read().unwrap();
+    let Some(list) = trades.get(&symbol) else {
+        return error_response(StatusCode::NOT_FOUND, &format!("No trades for symbol {}", symbol));
+    };
+
+    // Trades are appended in timestamp order so the range can be found by bisection
+    let start = list.partition_point(|t| t.timestamp < from);
+    let end = list.partition_point(|t| t.timestamp <= to);
+    let rows: Vec<String> = list[start..end.max(start)].iter().map(|t| t.to_json()).collect();
+    json_response(StatusCode::OK, format!("[{}]", rows.join(", ")))
+}
+
+// GET /stats: processing counters
+async fn get_stats(State(state): State<Arc<ApiState>>) -> Response {
+    let symbols: Vec<String> = state.books.read().unwrap().keys().cloned().collect();
+    let trades: usize = state.trades.read().unwrap().values().map(|t| t.len()).sum();
+
+    let stats = serde_json::json!({
+        "symbols": symbols,
+        "messages": state.messages.load(Ordering::Relaxed),
+        "book_updates": state.book_updates.load(Ordering::Relaxed),
+        "skipped_updates": state.skipped_updates.load(Ordering::Relaxed),
+        "trades": trades,
+        "finished": state.finished.load(Ordering::Relaxed),
+        "uptime_secs": state.started.elapsed().as_secs_f64(),
+    });
+    json_response(StatusCode::OK, stats.to_string())
+}
+
+// GET /metrics: Prometheus text exposition
+async fn get_metrics() -> Response {
+    (StatusCode::OK, [(header::CONTENT_TYPE, "text/plain; version=0.0.4")], METRICS.render()).into_response()
+}
+
+// Serve the HTTP API until the process exits
+pub async fn serve(state: Arc<ApiState>, port: u16) -> io::Result<()> {
+    let app = Router::new()
+        .route("/book/{symbol}", get(get_book))
+        .route("/trades/{symbol}", get(get_trades))
+        .route("/stats", get(get_stats))
+        .route("/metrics", get(get_metrics))
+        .with_state(state);
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = TcpListener::bind(&addr).await?;
+    tracing::info!("HTTP API server started on: {}", addr);
+
+    axum::serve(listener, app).await
+}
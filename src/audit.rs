@@ -0,0 +1,164 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rustc_hash::FxHashMap;
+
+use crate::message_types::MessageType;
+use crate::wire::{self, MessageView};
+
+// Order message counts for one stock locate
+#[derive(Debug, Default, Clone)]
+struct LocateCounts {
+    adds: u64,
+    executes: u64,
+    cancels: u64,
+    deletes: u64,
+    replaces: u64,
+    // Messages referencing an order ref that was never added (or already gone)
+    unknown_executes: u64,
+    unknown_cancels: u64,
+    unknown_deletes: u64,
+    unknown_replaces: u64,
+    resting_orders: u64,
+    resting_shares: u64,
+}
+
+impl LocateCounts {
+    fn unknown(&self) -> u64 {
+        self.unknown_executes + self.unknown_cancels + self.unknown_deletes + self.unknown_replaces
+    }
+}
+
+// Follows every order of every symbol in --audit mode, to reconcile order refs against the adds seen
+pub struct OrderAudit {
+    report_path: PathBuf,
+    // Live orders: ref -> (stock locate, remaining shares)
+    orders: FxHashMap<u64, (u16, u32)>,
+    counts: Vec<LocateCounts>,
+    symbols: FxHashMap<u16, String>,
+}
+
+impl OrderAudit {
+    pub fn new(report_path: &Path) -> Self {
+        OrderAudit {
+            report_path: report_path.to_path_buf(),
+            orders: FxHashMap::default(),
+            counts: vec![LocateCounts::default(); u16::MAX as usize + 1],
+            symbols: FxHashMap::default(),
+        }
+    }
+
+    // Take shares off a live order, dropping it once empty; false if the ref isn't live
+    fn reduce(&mut self, order_ref: u64, shares: Option<u32>) -> bool {
+        let Some((_, remaining)) = self.orders.get_mut(&order_ref) else {
+            return false;
+        };
+        *remaining = shares.map_or(0, |shares| remaining.saturating_sub(shares));
+        if *remaining == 0 {
+            self.orders.remove(&order_ref);
+        }
+        true
+    }
+
+    // Track one message (without its type byte)
+    pub fn observe(&mut self, message_type: MessageType, data: &[u8]) {
+        let Some(header) = wire::Header::view(data) else {
+            return;
+        };
+        let locate = header.stock_locate.get();
+        match message_type {
+            MessageType::StockDirectory => {
+                if let Some(directory) = wire::StockDirectory::view(data) {
+                    self.symbols.insert(locate, String::from_utf8_lossy(&directory.stock).trim_end().to_string());
+                }
+            }
+            // An add with MPID starts like an add
+            MessageType::AddOrder | MessageType::AddOrderWithMpid => {
+                if let Some(add) = wire::AddOrder::view(data) {
+                    self.counts[locate as usize].adds += 1;
+                    self.orders.insert(add.order_ref_number.get(), (locate, add.shares.get()));
+                }
+            }
+            // So does an execution with price an execution
+            MessageType::OrderExecuted | MessageType::OrderExecutedWithPrice => {
+                if let Some(executed) = wire::OrderExecuted::view(data) {
+                    let known = self.reduce(executed.order_ref_number.get(), Some(executed.executed_shares.get()));
+                    let counts = &mut self.counts[locate as usize];
+                    counts.executes += 1;
+                    counts.unknown_executes += !known as u64;
+                }
+            }
+            MessageType::OrderCancel => {
+                if let Some(cancel) = wire::OrderCancel::view(data) {
+                    let known = self.reduce(cancel.order_ref_number.get(), Some(cancel.cancelled_shares.get()));
+                    let counts = &mut self.counts[locate as usize];
+                    counts.cancels += 1;
+                    counts.unknown_cancels += !known as u64;
+                }
+            }
+            MessageType::OrderDelete => {
+                if let Some(delete) = wire::OrderDelete::view(data) {
+                    let known = self.reduce(delete.order_ref_number.get(), None);
+                    let counts = &mut self.counts[locate as usize];
+                    counts.deletes += 1;
+                    counts.unknown_deletes += !known as u64;
+                }
+            }
+            MessageType::OrderReplace => {
+                if let Some(replace) = wire::OrderReplace::view(data) {
+                    let known = self.reduce(replace.original_order_ref_number.get(), None);
+                    // The replacement rests either way, so its own lifecycle can still be followed
+                    self.orders.insert(replace.new_order_ref_number.get(), (locate, replace.shares.get()));
+                    let counts = &mut self.counts[locate as usize];
+                    counts.replaces += 1;
+                    counts.unknown_replaces += !known as u64;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Write the per-symbol reconciliation report and log the totals
+    pub fn finish(mut self) -> io::Result<()> {
+        for &(locate, shares) in self.orders.values() {
+            let counts = &mut self.counts[locate as usize];
+            counts.resting_orders += 1;
+            counts.resting_shares += shares as u64;
+        }
+
+        let mut writer = BufWriter::new(File::create(&self.report_path)?);
+        writer.write_all(b"symbol,stock_locate,adds,executes,cancels,deletes,replaces,unknown_executes,unknown_cancels,unknown_deletes,unknown_replaces,resting_orders,resting_shares\n")?;
+        let mut total = LocateCounts::default();
+        let mut symbols_with_unknown = 0;
+        for (locate, counts) in self.counts.iter().enumerate() {
+            let active = counts.adds + counts.executes + counts.cancels + counts.deletes + counts.replaces;
+            if active == 0 {
+                continue;
+            }
+            let symbol = self.symbols.get(&(locate as u16)).map_or("", |s| s.as_str());
+            writeln!(writer, "{},{},{},{},{},{},{},{},{},{},{},{},{}", symbol, locate,
+                     counts.adds, counts.executes, counts.cancels, counts.deletes, counts.replaces,
+                     counts.unknown_executes, counts.unknown_cancels, counts.unknown_deletes, counts.unknown_replaces,
+                     counts.resting_orders, counts.resting_shares)?;
+            total.unknown_executes += counts.unknown_executes;
+            total.unknown_cancels += counts.unknown_cancels;
+            total.unknown_deletes += counts.unknown_deletes;
+            total.unknown_replaces += counts.unknown_replaces;
+            total.resting_orders += counts.resting_orders;
+            symbols_with_unknown += (counts.unknown() > 0) as u64;
+        }
+        writer.flush()?;
+
+        tracing::info!("Wrote order-ref audit to {}", self.report_path.display());
+        tracing::info!("{} orders left resting at the end of the file", total.resting_orders);
+        if total.unknown() == 0 {
+            tracing::info!("Every order message references a known order: the file starts at the beginning of the session");
+        } else {
+            tracing::warn!("{} messages in {} symbols reference unknown orders ({} executes, {} cancels, {} deletes, {} replaces): the file may not start at the beginning of the session",
+                           total.unknown(), symbols_with_unknown,
+                           total.unknown_executes, total.unknown_cancels, total.unknown_deletes, total.unknown_replaces);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+// Tokens a WebSocket client must present before it is served, given with --ws-auth-token or a file of them.
+// A client sends one as the `token` query parameter (ws://host:8473/?token=...) or as its first message,
+// {"token": "..."}; anything else closes the connection
+
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct AuthTokens(Vec<String>);
+
+impl AuthTokens {
+    pub fn new(tokens: Vec<String>) -> Self {
+        AuthTokens(tokens.into_iter().filter(|token| !token.is_empty()).collect())
+    }
+
+    // One token per line; blank lines and lines starting with # are skipped
+    pub fn read(path: &Path) -> io::Result<Vec<String>> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Can't read tokens file {}: {}", path.display(), e)))?;
+        Ok(text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Compares against every token in full, so the time taken says nothing about how much of one matched
+    pub fn accepts(&self, token: &str) -> bool {
+        self.0.iter().fold(false, |found, known| found | constant_time_eq(known.as_bytes(), token.as_bytes()))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// The `token` parameter of a request's query string
+pub fn query_token(query: Option<&str>) -> Option<&str> {
+    query?.split('&').filter_map(|pair| pair.split_once('=')).find(|(key, _)| *key == "token").map(|(_, value)| value)
+}
+
+// The token of a {"token": "..."} message
+pub fn message_token(text: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    value.get("token")?.as_str().map(str::to_string)
+}
@@ -0,0 +1,483 @@
+// Market replay backtesting. A strategy places simulated passive orders into the replayed book of a
+// symbol and each one follows its place in the queue of its price level against the real order flow:
+// it joins the back of the level, so the real orders resting there are ahead of it and move it up as
+// they execute or cancel. It fills when the feed shows it would have traded: a real order on its side
+// executes behind it or at a worse price, or a real order on the other side is added at or through its
+// price. Simulated orders never change the real flow, and hidden (P) trades don't fill them
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use rustc_hash::FxHashSet;
+
+use crate::cli::BacktestArgs;
+use crate::file_io;
+use crate::handler::MessageHandler;
+use crate::message_types::MessageType;
+use crate::orderbook::{Order, OrderBook, OrderBookOptions, Side};
+use crate::parser::{self, ParserOptions};
+use crate::utils::{pad_stock_symbol, parse_price, parse_time_of_day};
+use crate::wire;
+
+#[derive(Debug, Clone)]
+pub struct SimulatedOrder {
+    pub id: u64,
+    pub side: Side,
+    pub price: u32,
+    pub shares: u32,
+    pub remaining: u32,
+    pub placed: u64,
+    // Real shares still ahead of the order in its level's queue
+    pub queue_ahead: u64,
+    // Real orders that were resting at the level when it was placed and still are
+    ahead: FxHashSet<u64>,
+}
+
+impl SimulatedOrder {
+    // Whether a price is at or through the order's: worse on its own side, better on the other
+    fn reached_by(&self, price: u32) -> bool {
+        match self.side {
+            Side::Buy => price <= self.price,
+            Side::Sell => price >= self.price,
+        }
+    }
+}
+
+// A hypothetical fill of a simulated order, at its limit price
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub timestamp: u64,
+    pub order_id: u64,
+    pub side: Side,
+    pub price: u32,
+    pub shares: u32,
+    // Shares of the order left after this fill
+    pub remaining: u32,
+    // Time since the order was placed
+    pub age_ns: u64,
+}
+
+// The simulated orders of a backtest, placed and cancelled by its strategy
+#[derive(Default)]
+pub struct SimulatedOrders {
+    // Open orders by id; ids count up from 1 in placement order
+    open: BTreeMap<u64, SimulatedOrder>,
+    placed: u64,
+    // Timestamp of the message being replayed
+    timestamp: u64,
+    // Fills not yet handed to the strategy
+    new_fills: Vec<Fill>,
+}
+
+impl SimulatedOrders {
+    // Place a passive order at the back of its price level. Orders that would cross the book are
+    // rejected, since only resting orders are simulated
+    pub fn place(&mut self, book: &OrderBook, side: Side, price: u32, shares: u32) -> io::Result<u64> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        if price == 0 || shares == 0 {
+            return Err(invalid("Simulated orders need a price and shares".to_string()));
+        }
+        let crosses = match (side, book.best_prices()) {
+            (Side::Buy, (_, Some(ask))) => price >= ask,
+            (Side::Sell, (Some(bid), _)) => price <= bid,
+            _ => false,
+        };
+        if crosses {
+            return Err(invalid(format!("Simulated {:?} order at {}.{:04} would cross the book", side, price / 10000, price % 10000)));
+        }
+
+        let ahead: FxHashSet<u64> = book.orders_at(side, price).map(|order| order.ref_number).collect();
+        let queue_ahead = book.orders_at(side, price).map(|order| order.shares as u64).sum();
+        self.placed += 1;
+        let id = self.placed;
+        self.open.insert(id, SimulatedOrder { id, side, price, shares, remaining: shares, placed: self.timestamp, queue_ahead, ahead });
+        Ok(id)
+    }
+
+    // Cancel an open order, returning it with its unfilled shares
+    pub fn cancel(&mut self, id: u64) -> Option<SimulatedOrder> {
+        self.open.remove(&id)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&SimulatedOrder> {
+        self.open.get(&id)
+    }
+
+    // Open orders in placement order
+    pub fn open(&self) -> impl Iterator<Item = &SimulatedOrder> {
+        self.open.values()
+    }
+
+    // Orders placed so far, including the ones filled or cancelled since
+    pub fn placed(&self) -> u64 {
+        self.placed
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    // Fill the open orders of a side that pass the filter with up to `shares`, best price first and
+    // earliest first within a price
+    fn fill(&mut self, side: Side, mut shares: u32, filter: impl Fn(&SimulatedOrder) -> bool) {
+        let mut eligible: Vec<(u32, u64)> = self.open.values()
+            .filter(|order| order.side == side && filter(order))
+            .map(|order| (order.price, order.id))
+            .collect();
+        match side {
+            Side::Buy => eligible.sort_by_key(|&(price, id)| (std::cmp::Reverse(price), id)),
+            Side::Sell => eligible.sort_unstable(),
+        }
+        for (_, id) in eligible {
+            if shares == 0 {
+                break;
+            }
+            let Some(order) = self.open.get_mut(&id) else {
+                continue;
+            };
+            let filled = shares.min(order.remaining);
+            shares -= filled;
+            order.remaining -= filled;
+            self.new_fills.push(Fill {
+                timestamp: self.timestamp,
+                order_id: id,
+                side,
+                price: order.price,
+                shares: filled,
+                remaining: order.remaining,
+                age_ns: self.timestamp.saturating_sub(order.placed),
+            });
+            if order.remaining == 0 {
+                self.open.remove(&id);
+            }
+        }
+    }
+
+    // A real order was added; it trades with the simulated orders of the other side it reaches
+    fn on_add(&mut self, side: Side, price: u32, shares: u32) {
+        let other = if side == Side::Buy { Side::Sell } else { Side::Buy };
+        self.fill(other, shares, |order| order.reached_by(price));
+    }
+
+    // A real order executed; the aggressor reached every simulated order of its side it was behind
+    fn on_execute(&mut self, order: &Order, shares: u32) {
+        self.fill(order.side, shares, |simulated| simulated.reached_by(order.price) && !simulated.ahead.contains(&order.ref_number));
+        self.on_reduce(order, shares);
+    }
+
+    // Shares of a real order left the book, moving up the simulated orders it was ahead of
+    fn on_reduce(&mut self, order: &Order, shares: u32) {
+        for simulated in self.open.values_mut() {
+            if simulated.side == order.side && simulated.ahead.contains(&order.ref_number) {
+                simulated.queue_ahead = simulated.queue_ahead.saturating_sub(shares.min(order.shares) as u64);
+                if shares >= order.shares {
+                    simulated.ahead.remove(&order.ref_number);
+                }
+            }
+        }
+    }
+}
+
+// The trading logic of a backtest
+#[allow(unused_variables)]
+pub trait Strategy {
+    // Called before every message is applied, with the book as of the previous message; the orders'
+    // timestamp is the time of the message
+    fn on_message(&mut self, book: &OrderBook, orders: &mut SimulatedOrders) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Called after the message that filled a simulated order
+    fn on_fill(&mut self, fill: &Fill) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Replays a symbol's book with a strategy's simulated orders in it, as a handler for the parser
+pub struct Backtest<S> {
+    book: OrderBook,
+    symbol: [u8; 8],
+    orders: SimulatedOrders,
+    strategy: S,
+    fills: Vec<Fill>,
+}
+
+impl<S: Strategy> Backtest<S> {
+    pub fn new(book: OrderBook, strategy: S) -> Self {
+        let symbol = pad_stock_symbol(book.symbol_name());
+        Backtest { book, symbol, orders: SimulatedOrders::default(), strategy, fills: Vec::new() }
+    }
+
+    pub fn book(&self) -> &OrderBook {
+        &self.book
+    }
+
+    pub fn orders(&self) -> &SimulatedOrders {
+        &self.orders
+    }
+
+    pub fn strategy(&self) -> &S {
+        &self.strategy
+    }
+
+    // Every fill so far, in the order they happened
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    pub fn finalize(&mut self) -> io::Result<()> {
+        self.book.finalize()
+    }
+
+    fn report_fills(&mut self) -> io::Result<()> {
+        for fill in self.orders.new_fills.drain(..) {
+            self.strategy.on_fill(&fill)?;
+            self.fills.push(fill);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, order_ref: u64, shares: u32) -> io::Result<()> {
+        if let Some(order) = self.book.order(order_ref) {
+            self.orders.on_execute(order, shares);
+        }
+        self.report_fills()
+    }
+}
+
+// The book sees every message; the simulated orders see the order messages first, while the real
+// orders they touch are still in the book
+impl<S: Strategy> MessageHandler for Backtest<S> {
+    fn on_message(&mut self, message_type: MessageType, timestamp: u64) -> io::Result<()> {
+        self.orders.timestamp = timestamp;
+        self.strategy.on_message(&self.book, &mut self.orders)?;
+        self.book.on_message(message_type, timestamp)
+    }
+
+    fn on_system_event(&mut self, message: &wire::SystemEvent, timestamp: u64) -> io::Result<()> {
+        self.book.on_system_event(message, timestamp)
+    }
+
+    fn on_stock_directory(&mut self, message: &wire::StockDirectory, timestamp: u64) -> io::Result<()> {
+        self.book.on_stock_directory(message, timestamp)
+    }
+
+    fn on_trading_action(&mut self, message: &wire::StockTradingAction, timestamp: u64) -> io::Result<()> {
+        self.book.on_trading_action(message, timestamp)
+    }
+
+    fn on_reg_sho(&mut self, message: &wire::RegShoRestriction, timestamp: u64) -> io::Result<()> {
+        self.book.on_reg_sho(message, timestamp)
+    }
+
+    fn on_mwcb_decline_level(&mut self, message: &wire::MwcbDeclineLevel, timestamp: u64) -> io::Result<()> {
+        self.book.on_mwcb_decline_level(message, timestamp)
+    }
+
+    fn on_mwcb_status(&mut self, message: &wire::MwcbStatus, timestamp: u64) -> io::Result<()> {
+        self.book.on_mwcb_status(message, timestamp)
+    }
+
+    fn on_ipo_quoting_period(&mut self, message: &wire::IpoQuotingPeriodUpdate, timestamp: u64) -> io::Result<()> {
+        self.book.on_ipo_quoting_period(message, timestamp)
+    }
+
+    fn on_luld_collar(&mut self, message: &wire::LuldAuctionCollar, timestamp: u64) -> io::Result<()> {
+        self.book.on_luld_collar(message, timestamp)
+    }
+
+    fn on_operational_halt(&mut self, message: &wire::OperationalHalt, timestamp: u64) -> io::Result<()> {
+        self.book.on_operational_halt(message, timestamp)
+    }
+
+    fn on_add_order(&mut self, message: &wire::AddOrder, timestamp: u64) -> io::Result<()> {
+        if message.stock == self.symbol {
+            self.orders.on_add(Side::from(message.buy_sell_indicator), message.price.get(), message.shares.get());
+            self.report_fills()?;
+        }
+        self.book.on_add_order(message, timestamp)
+    }
+
+    fn on_add_order_with_mpid(&mut self, message: &wire::AddOrderWithMpid, timestamp: u64) -> io::Result<()> {
+        if message.stock == self.symbol {
+            self.orders.on_add(Side::from(message.buy_sell_indicator), message.price.get(), message.shares.get());
+            self.report_fills()?;
+        }
+        self.book.on_add_order_with_mpid(message, timestamp)
+    }
+
+    fn on_execute(&mut self, message: &wire::OrderExecuted, timestamp: u64) -> io::Result<()> {
+        self.execute(message.order_ref_number.get(), message.executed_shares.get())?;
+        self.book.on_execute(message, timestamp)
+    }
+
+    fn on_execute_with_price(&mut self, message: &wire::OrderExecutedWithPrice, timestamp: u64) -> io::Result<()> {
+        self.execute(message.order_ref_number.get(), message.executed_shares.get())?;
+        self.book.on_execute_with_price(message, timestamp)
+    }
+
+    fn on_cancel(&mut self, message: &wire::OrderCancel, timestamp: u64) -> io::Result<()> {
+        if let Some(order) = self.book.order(message.order_ref_number.get()) {
+            self.orders.on_reduce(order, message.cancelled_shares.get());
+        }
+        self.book.on_cancel(message, timestamp)
+    }
+
+    fn on_delete(&mut self, message: &wire::OrderDelete, timestamp: u64) -> io::Result<()> {
+        if let Some(order) = self.book.order(message.order_ref_number.get()) {
+            self.orders.on_reduce(order, order.shares);
+        }
+        self.book.on_delete(message, timestamp)
+    }
+
+    // The original order leaves its level and the new one is added, losing its place
+    fn on_replace(&mut self, message: &wire::OrderReplace, timestamp: u64) -> io::Result<()> {
+        if let Some(order) = self.book.order(message.original_order_ref_number.get()) {
+            self.orders.on_reduce(order, order.shares);
+            self.orders.on_add(order.side, message.price.get(), message.shares.get());
+            self.report_fills()?;
+        }
+        self.book.on_replace(message, timestamp)
+    }
+
+    fn on_trade(&mut self, message: &wire::Trade, timestamp: u64) -> io::Result<()> {
+        self.book.on_trade(message, timestamp)
+    }
+
+    fn on_rpii(&mut self, message: &wire::Rpii, timestamp: u64) -> io::Result<()> {
+        self.book.on_rpii(message, timestamp)
+    }
+
+    fn on_direct_listing(&mut self, message: &wire::DirectListingWithCapitalRaisePriceDiscovery, timestamp: u64) -> io::Result<()> {
+        self.book.on_direct_listing(message, timestamp)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ScriptAction {
+    Place { side: Side, price: u32, shares: u32 },
+    // Number of the script's order, counting its buy and sell lines from 1
+    Cancel(usize),
+}
+
+// A strategy read from a file of orders to place and cancel at times of day, one per line:
+//   09:30:00,buy,150.25,100
+//   09:45:00.5,cancel,1
+// Blank lines and lines starting with # are skipped
+pub struct OrderScript {
+    actions: VecDeque<(u64, ScriptAction)>,
+    // Ids of the script's orders once placed, None when the order was rejected
+    placed: Vec<Option<u64>>,
+    rejected: u64,
+}
+
+impl OrderScript {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut actions = VecDeque::new();
+        let mut last_time = 0;
+        let mut orders = 0;
+        for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData,
+                                                           format!("{} line {}: {}", path.display(), number + 1, message));
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let time = parse_time_of_day(fields[0]).map_err(invalid)?;
+            if time < last_time {
+                return Err(invalid("Times must not go backwards".to_string()));
+            }
+            last_time = time;
+            let action = match &fields[1..] {
+                [side @ ("buy" | "sell"), price, shares] => {
+                    orders += 1;
+                    ScriptAction::Place {
+                        side: if *side == "buy" { Side::Buy } else { Side::Sell },
+                        price: parse_price(price).map_err(invalid)?,
+                        shares: shares.parse().map_err(|_| invalid(format!("Invalid shares \"{}\"", shares)))?,
+                    }
+                }
+                ["cancel", order] => match order.parse::<usize>() {
+                    Ok(order) if (1..=orders).contains(&order) => ScriptAction::Cancel(order),
+                    _ => return Err(invalid(format!("Cancel of \"{}\", which isn't an earlier order of the script", order))),
+                },
+                _ => return Err(invalid("Expected TIME,buy|sell,PRICE,SHARES or TIME,cancel,ORDER".to_string())),
+            };
+            actions.push_back((time, action));
+        }
+        Ok(OrderScript { actions, placed: Vec::new(), rejected: 0 })
+    }
+
+    // Orders of the script that would have crossed the book when their time came
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    // Actions still waiting for their time, after the end of the file
+    pub fn pending(&self) -> usize {
+        self.actions.len()
+    }
+}
+
+impl Strategy for OrderScript {
+    fn on_message(&mut self, book: &OrderBook, orders: &mut SimulatedOrders) -> io::Result<()> {
+        while let Some(&(time, action)) = self.actions.front() && time <= orders.timestamp() {
+            self.actions.pop_front();
+            match action {
+                ScriptAction::Place { side, price, shares } => match orders.place(book, side, price, shares) {
+                    Ok(id) => self.placed.push(Some(id)),
+                    Err(e) => {
+                        tracing::warn!("{}", e);
+                        self.rejected += 1;
+                        self.placed.push(None);
+                    }
+                },
+                ScriptAction::Cancel(order) => {
+                    // Filled or rejected orders have nothing left to cancel
+                    if let Some(Some(id)) = self.placed.get(order - 1) {
+                        orders.cancel(*id);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Replay the file with the orders of the script and write their fills
+pub fn run(args: BacktestArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = file_io::load_input(args.input.file()?, args.input.encap)?;
+    let script = OrderScript::read(&args.orders)?;
+
+    let book = OrderBook::new(pad_stock_symbol(&args.symbol), OrderBookOptions::default());
+    let mut backtests = [Backtest::new(book, script)];
+    let options = ParserOptions { feed: args.input.feed, strict: args.input.strict, events: args.input.events_output.clone(), ..Default::default() };
+    parser::process_itch_file(&input, &mut backtests, &options)?;
+    let [mut backtest] = backtests;
+    backtest.finalize()?;
+
+    let mut out = BufWriter::new(File::create(&args.output_file)?);
+    out.write_all(b"timestamp,order_id,side,price,shares,remaining,age_ns\n")?;
+    for fill in backtest.fills() {
+        writeln!(out, "{},{},{},{}.{:04},{},{},{}", fill.timestamp, fill.order_id, if fill.side == Side::Buy { "B" } else { "S" },
+                 fill.price / 10000, fill.price % 10000, fill.shares, fill.remaining, fill.age_ns)?;
+    }
+    out.flush()?;
+
+    let orders = backtest.orders();
+    let filled: u64 = backtest.fills().iter().map(|fill| fill.shares as u64).sum();
+    println!("Placed {} simulated orders ({} rejected)", orders.placed(), backtest.strategy().rejected());
+    println!("Filled {} shares in {} fills", filled, backtest.fills().len());
+    if backtest.strategy().pending() > 0 {
+        println!("{} actions of the script are after the end of the file", backtest.strategy().pending());
+    }
+    for order in orders.open() {
+        println!("Order {} still open: {} of {} shares, {} shares ahead", order.id, order.remaining, order.shares, order.queue_ahead);
+    }
+    tracing::info!("Wrote fills of {} to {}", args.symbol, args.output_file.display());
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::utils::format_duration;
+
+// OHLCV bar of a symbol's printable trades over one interval of market time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bar {
+    // Length of the bar in nanoseconds
+    pub interval: u64,
+    // Start of the interval, a multiple of its length since midnight
+    pub start: u64,
+    pub open: u32,
+    pub high: u32,
+    pub low: u32,
+    pub close: u32,
+    pub volume: u64,
+    // Sum of price * shares, in price units
+    pub notional: u64,
+    pub trades: u64,
+}
+
+impl Bar {
+    fn new(interval: u64, timestamp: u64, price: u32, shares: u32) -> Self {
+        Bar {
+            interval,
+            start: timestamp - timestamp % interval,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: shares as u64,
+            notional: price as u64 * shares as u64,
+            trades: 1,
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.start + self.interval
+    }
+
+    pub fn vwap(&self) -> f64 {
+        self.notional as f64 / self.volume.max(1) as f64 / 10000.0
+    }
+
+    pub fn to_json(&self, symbol: &str) -> String {
+        format!("{{\"type\":\"bar\",\"symbol\":\"{}\",\"interval\":\"{}\",\"start\":\"{}\",\"open\":{:.4},\"high\":{:.4},\"low\":{:.4},\"close\":{:.4},\"volume\":{},\"trades\":{},\"vwap\":{:.4}}}",
+                symbol, format_duration(self.interval), self.start, self.open as f64 / 10000.0, self.high as f64 / 10000.0,
+                self.low as f64 / 10000.0, self.close as f64 / 10000.0, self.volume, self.trades, self.vwap())
+    }
+}
+
+// Aggregates trades into bars of each interval. A bar is complete once market time passes its end, which
+// every message moves, so bars close on time even when no trade follows; intervals without trades get no bar
+pub struct BarBuilder {
+    // One bar in progress per interval
+    open: Vec<(u64, Option<Bar>)>,
+    // Earliest end of the bars in progress
+    next_close: u64,
+    output: Option<BufWriter<File>>,
+}
+
+impl BarBuilder {
+    pub fn new(intervals: &[u64], output: Option<&Path>) -> io::Result<Self> {
+        let output = match output {
+            Some(path) => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                writer.write_all(b"symbol,interval,start,open,high,low,close,volume,trades,vwap\n")?;
+                Some(writer)
+            }
+            None => None,
+        };
+        Ok(BarBuilder { open: intervals.iter().map(|&interval| (interval.max(1), None)).collect(), next_close: u64::MAX, output })
+    }
+
+    // Add a printable trade; bars it ends go to `closed` first
+    pub fn add(&mut self, timestamp: u64, price: u32, shares: u32, closed: &mut Vec<Bar>) {
+        self.close_until(timestamp, closed);
+        for (interval, bar) in &mut self.open {
+            match bar {
+                Some(bar) => {
+                    bar.high = bar.high.max(price);
+                    bar.low = bar.low.min(price);
+                    bar.close = price;
+                    bar.volume += shares as u64;
+                    bar.notional += price as u64 * shares as u64;
+                    bar.trades += 1;
+                }
+                None => *bar = Some(Bar::new(*interval, timestamp, price, shares)),
+            }
+        }
+        self.update_next_close();
+    }
+
+    // Whether market time at `timestamp` completes a bar; cheap enough for every message
+    #[inline]
+    pub fn due(&self, timestamp: u64) -> bool {
+        timestamp >= self.next_close
+    }
+
+    // Move the bars that end at or before `timestamp` to `closed`
+    pub fn close_until(&mut self, timestamp: u64, closed: &mut Vec<Bar>) {
+        if !self.due(timestamp) {
+            return;
+        }
+        for (_, bar) in &mut self.open {
+            if bar.is_some_and(|bar| bar.end() <= timestamp) {
+                closed.extend(bar.take());
+            }
+        }
+        self.update_next_close();
+    }
+
+    // The bars still in progress, at the end of the input
+    pub fn close_all(&mut self, closed: &mut Vec<Bar>) {
+        closed.extend(self.open.iter_mut().filter_map(|(_, bar)| bar.take()));
+        self.next_close = u64::MAX;
+    }
+
+    fn update_next_close(&mut self) {
+        self.next_close = self.open.iter().filter_map(|(_, bar)| bar.map(|bar| bar.end())).min().unwrap_or(u64::MAX);
+    }
+
+    // Append closed bars to the output file, if any
+    pub fn write(&mut self, symbol: &str, bars: &[Bar]) -> io::Result<()> {
+        let Some(writer) = &mut self.output else {
+            return Ok(());
+        };
+        for bar in bars {
+            writeln!(writer, "{},{},{},{}.{:04},{}.{:04},{}.{:04},{}.{:04},{},{},{:.4}", symbol, format_duration(bar.interval), bar.start,
+                     bar.open / 10000, bar.open % 10000, bar.high / 10000, bar.high % 10000,
+                     bar.low / 10000, bar.low % 10000, bar.close / 10000, bar.close % 10000,
+                     bar.volume, bar.trades, bar.vwap())?;
+        }
+        Ok(())
+    }
+
+    pub fn finalize(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
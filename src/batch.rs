@@ -0,0 +1,121 @@
+// Multi-day runs of build-book with --file-glob: the daily files matching a pattern, an output file per
+// day named after its input, and a report of every day with the totals across them
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::features::TradeStats;
+
+// Placeholder of the output file name replaced by the day's name
+const DAY_PLACEHOLDER: &str = "{file}";
+
+// Whether a file name matches a pattern of literal characters, * (any run) and ? (any one)
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some(('*', rest)), _) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+        (Some((p, rest)), Some((n, name))) => (*p == '?' || p == n) && matches(rest, name),
+        (Some(_), None) => false,
+    }
+}
+
+// The files matching a pattern with wildcards in its file name, like data/2020-01-*.NASDAQ_ITCH50, in name order
+pub fn expand_glob(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let pattern = Path::new(pattern);
+    let dir = pattern.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name_pattern: Vec<char> = pattern.file_name().map(|name| name.to_string_lossy().chars().collect()).unwrap_or_default();
+    if dir.to_string_lossy().contains(['*', '?']) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Wildcards are only supported in the file name of --file-glob"));
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name: Vec<char> = entry.file_name().to_string_lossy().chars().collect();
+        if entry.file_type()?.is_file() && matches(&name_pattern, &name) {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    if files.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("No files match {}", pattern.display())));
+    }
+    Ok(files)
+}
+
+// Name of a day: its input file name up to the first dot, like 01302020 for 01302020.NASDAQ_ITCH50
+pub fn day_name(input: &Path) -> String {
+    let name = input.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    name.split('.').next().unwrap_or_default().to_string()
+}
+
+// Output file of a day: {file} in the output path is replaced by the day's name, or without it the
+// name goes before the extension (AAPL.csv -> AAPL.01302020.csv). A partitioned output with {date}
+// already has a path per day
+pub fn day_output(output: &Path, input: &Path) -> PathBuf {
+    let day = day_name(input);
+    let text = output.to_string_lossy();
+    if text.contains(DAY_PLACEHOLDER) {
+        return PathBuf::from(text.replace(DAY_PLACEHOLDER, &day));
+    }
+    if text.contains("{date}") {
+        return output.to_path_buf();
+    }
+    match output.extension() {
+        Some(extension) => output.with_extension(format!("{}.{}", day, extension.to_string_lossy())),
+        None => output.with_extension(day),
+    }
+}
+
+// What a day's run produced
+#[derive(Debug, Clone)]
+pub struct DaySummary {
+    pub day: String,
+    pub output: PathBuf,
+    pub messages: u64,
+    pub updates: u64,
+    pub trades: TradeStats,
+    pub seconds: f64,
+}
+
+const REPORT_HEADER: &str = "day,output,messages,updates,trades,volume,vwap,seconds";
+
+fn report_row(summary: &DaySummary) -> String {
+    format!("{},{},{},{},{},{},{:.4},{:.3}", summary.day, summary.output.display(), summary.messages, summary.updates,
+            summary.trades.trades, summary.trades.volume, summary.trades.vwap(), summary.seconds)
+}
+
+// The days together, with the trade totals and VWAP across them
+pub fn total(days: &[DaySummary]) -> DaySummary {
+    let mut total = DaySummary { day: "total".to_string(), output: PathBuf::new(), messages: 0, updates: 0, trades: TradeStats::default(), seconds: 0.0 };
+    for day in days {
+        total.messages += day.messages;
+        total.updates += day.updates;
+        total.trades.trades += day.trades.trades;
+        total.trades.volume += day.trades.volume;
+        total.trades.notional += day.trades.notional;
+        total.seconds += day.seconds;
+    }
+    total
+}
+
+// Print a row per day and the totals, and write them as CSV when a report file is given
+pub fn report(days: &[DaySummary], path: Option<&Path>) -> io::Result<()> {
+    let total = total(days);
+    println!("{:<12}{:>14}{:>12}{:>10}{:>14}{:>12}{:>10}", "Day", "Messages", "Updates", "Trades", "Volume", "VWAP", "Seconds");
+    for summary in days.iter().chain([&total]) {
+        println!("{:<12}{:>14}{:>12}{:>10}{:>14}{:>12.4}{:>10.1}", summary.day, summary.messages, summary.updates,
+                 summary.trades.trades, summary.trades.volume, summary.trades.vwap(), summary.seconds);
+    }
+
+    if let Some(path) = path {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", REPORT_HEADER)?;
+        for summary in days.iter().chain([&total]) {
+            writeln!(out, "{}", report_row(summary))?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
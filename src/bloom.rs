@@ -0,0 +1,36 @@
+// Bits of the filter; with two hashes this keeps false positives to a few percent for millions of orders
+const FILTER_BITS: usize = 1 << 24;
+
+// Bloom filter of the order refs added to the book, so the executes, cancels, deletes and replaces
+// of other symbols' orders skip the order map lookups. Refs are never removed, so a ref that
+// passes the filter may still not be on the book
+pub struct RefFilter {
+    bits: Vec<u64>,
+}
+
+impl Default for RefFilter {
+    fn default() -> Self {
+        RefFilter { bits: vec![0; FILTER_BITS / 64] }
+    }
+}
+
+impl RefFilter {
+    // Two bit positions from one multiplicative hash of the ref
+    #[inline]
+    fn positions(order_ref: u64) -> [usize; 2] {
+        let hash = order_ref.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        [(hash >> 40) as usize, (hash >> 16) as usize & (FILTER_BITS - 1)]
+    }
+
+    pub fn insert(&mut self, order_ref: u64) {
+        for position in Self::positions(order_ref) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    // False only if the ref was never inserted
+    #[inline]
+    pub fn may_contain(&self, order_ref: u64) -> bool {
+        Self::positions(order_ref).iter().all(|&position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
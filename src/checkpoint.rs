@@ -0,0 +1,293 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::features::TradeStats;
+use crate::orderbook::{BookSnapshot, Levels, LuldCollar, Order, PriceLevel, RetailInterest, Side};
+use crate::participants::ParticipantActivity;
+use crate::timestamps::MarketHours;
+
+const MAGIC: &[u8; 8] = b"ITCHCKPT";
+const VERSION: u32 = 11;
+
+// Where and how often the parser checkpoints the book
+#[derive(Debug, Clone)]
+pub struct CheckpointOptions {
+    pub path: PathBuf,
+    // Market time between checkpoints, in nanoseconds
+    pub interval: u64,
+}
+
+// Full orderbook state at a message boundary, enough to continue parsing from `offset`
+#[derive(Debug, Default)]
+pub struct Checkpoint {
+    pub symbol: [u8; 8],
+    // Byte offset of the next message in the input
+    pub offset: u64,
+    // Timestamp of the last message applied
+    pub timestamp: u64,
+    pub stock_locate: Option<u16>,
+    // Running trade statistics, so VWAP and volume continue across a resume
+    pub trade_stats: TradeStats,
+    // Order flow imbalance accumulated since the last written row
+    pub ofi: i64,
+    // Last trade price and the direction of the last price change, for the tick test
+    pub last_trade_price: Option<u32>,
+    pub last_tick: Option<Side>,
+    pub retail_interest: RetailInterest,
+    pub luld: LuldCollar,
+    pub mwcb_level: u8,
+    pub short_sale_restricted: bool,
+    pub operational_halts: u8,
+    pub market_hours: MarketHours,
+    pub orders: Vec<Order>,
+    pub round_lot: u32,
+    // Orders kept off the book by --odd-lots exclude or dropped by --price-band
+    pub excluded_orders: Vec<Order>,
+    // Activity per MPID so far for --participants-output, empty without it
+    pub participants: Vec<(Option<[u8; 4]>, ParticipantActivity)>,
+    // (price, volume) of every level, in price order
+    pub bid_levels: Vec<(u32, u32)>,
+    pub ask_levels: Vec<(u32, u32)>,
+    // Last snapshot written, which --write-mode compares the next one's levels against
+    pub last_state: Option<BookSnapshot>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(read_array(reader)?))
+}
+
+fn write_orders(writer: &mut impl Write, orders: &[Order]) -> io::Result<()> {
+    writer.write_all(&(orders.len() as u64).to_le_bytes())?;
+    for order in orders {
+        writer.write_all(&order.ref_number.to_le_bytes())?;
+        writer.write_all(&order.timestamp.to_le_bytes())?;
+        writer.write_all(&order.price.to_le_bytes())?;
+        writer.write_all(&order.shares.to_le_bytes())?;
+        writer.write_all(&[if order.side == Side::Buy { b'B' } else { b'S' }])?;
+        writer.write_all(&[order.mpid.is_some() as u8])?;
+        writer.write_all(&order.mpid.unwrap_or_default())?;
+    }
+    Ok(())
+}
+
+fn read_orders(reader: &mut impl Read) -> io::Result<Vec<Order>> {
+    let count = read_u64(reader)?;
+    let mut orders = Vec::new();
+    for _ in 0..count {
+        let ref_number = read_u64(reader)?;
+        let timestamp = read_u64(reader)?;
+        let price = read_u32(reader)?;
+        let shares = read_u32(reader)?;
+        let side = Side::from(read_array::<1>(reader)?[0]);
+        let [has_mpid] = read_array(reader)?;
+        let mpid: [u8; 4] = read_array(reader)?;
+        orders.push(Order { ref_number, timestamp, price, shares, side, mpid: (has_mpid != 0).then_some(mpid) });
+    }
+    Ok(orders)
+}
+
+fn write_levels(writer: &mut impl Write, levels: &[(u32, u32)]) -> io::Result<()> {
+    writer.write_all(&(levels.len() as u64).to_le_bytes())?;
+    for &(price, volume) in levels {
+        writer.write_all(&price.to_le_bytes())?;
+        writer.write_all(&volume.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_levels(reader: &mut impl Read) -> io::Result<Vec<(u32, u32)>> {
+    let count = read_u64(reader)?;
+    (0..count).map(|_| Ok((read_u32(reader)?, read_u32(reader)?))).collect()
+}
+
+fn write_snapshot(writer: &mut impl Write, snapshot: &BookSnapshot) -> io::Result<()> {
+    writer.write_all(&snapshot.timestamp.to_le_bytes())?;
+    for levels in [&snapshot.bid_levels, &snapshot.ask_levels] {
+        let levels: Vec<(u32, u32)> = levels.iter().map(|l| (l.price, l.total_volume)).collect();
+        write_levels(writer, &levels)?;
+    }
+    Ok(())
+}
+
+fn read_snapshot(reader: &mut impl Read) -> io::Result<BookSnapshot> {
+    let timestamp = read_u64(reader)?;
+    let mut levels = [Levels::default(), Levels::default()];
+    for side in &mut levels {
+        *side = read_levels(reader)?.into_iter().map(|(price, total_volume)| PriceLevel { price, total_volume }).collect();
+    }
+    let [bid_levels, ask_levels] = levels;
+    // Only the levels are compared, so the derived columns are left empty
+    Ok(BookSnapshot { timestamp, bid_levels, ask_levels, ..Default::default() })
+}
+
+impl Checkpoint {
+    // Little-endian: magic, u32 version, [u8; 8] symbol, u64 offset, u64 timestamp,
+    // u8 has locate + u16 stock locate, u64 traded volume, u64 notional, u64 trade count,
+    // i64 pending order flow imbalance, u8 has last trade + u32 last trade price, u8 last tick (B, S or 0),
+    // u8 retail interest flag, u32 LULD reference price, u32 upper and u32 lower collar, u32 LULD extensions,
+    // u8 MWCB level, u8 short sale restricted, u8 operational halt mask, u8 has market open + u64 open, u8 has
+    // market close + u64 close, u64 order count then (u64 ref, u64 timestamp, u32 price, u32 shares, u8 side,
+    // u8 has MPID + [u8; 4] MPID) per order, u32 round lot, the excluded orders as u64 count + the same per order,
+    // u64 participant count then (u8 has MPID + [u8; 4] MPID, u64 adds, added shares, executions, executed shares,
+    // cancels, cancelled shares, replaces) per participant, then the bid and ask levels as u64 count + (u32 price,
+    // u32 volume) each, then u8 has last state + the last snapshot written (u64 timestamp, its bid and ask levels
+    // like the book's)
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        // Written next to the target and renamed, so a crash never leaves a partial checkpoint
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&self.symbol)?;
+        writer.write_all(&self.offset.to_le_bytes())?;
+        writer.write_all(&self.timestamp.to_le_bytes())?;
+        writer.write_all(&[self.stock_locate.is_some() as u8])?;
+        writer.write_all(&self.stock_locate.unwrap_or(0).to_le_bytes())?;
+        for value in [self.trade_stats.volume, self.trade_stats.notional, self.trade_stats.trades] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&self.ofi.to_le_bytes())?;
+        writer.write_all(&[self.last_trade_price.is_some() as u8])?;
+        writer.write_all(&self.last_trade_price.unwrap_or(0).to_le_bytes())?;
+        writer.write_all(&[match self.last_tick {
+            Some(Side::Buy) => b'B',
+            Some(Side::Sell) => b'S',
+            None => 0,
+        }])?;
+        writer.write_all(&[self.retail_interest.code()])?;
+        for value in [self.luld.reference_price, self.luld.upper_price, self.luld.lower_price, self.luld.extensions] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&[self.mwcb_level, self.short_sale_restricted as u8, self.operational_halts])?;
+        for time in [self.market_hours.open, self.market_hours.close] {
+            writer.write_all(&[time.is_some() as u8])?;
+            writer.write_all(&time.unwrap_or(0).to_le_bytes())?;
+        }
+        write_orders(&mut writer, &self.orders)?;
+        writer.write_all(&self.round_lot.to_le_bytes())?;
+        write_orders(&mut writer, &self.excluded_orders)?;
+        writer.write_all(&(self.participants.len() as u64).to_le_bytes())?;
+        for (mpid, activity) in &self.participants {
+            writer.write_all(&[mpid.is_some() as u8])?;
+            writer.write_all(&mpid.unwrap_or_default())?;
+            for value in [activity.adds, activity.added_shares, activity.executions, activity.executed_shares,
+                          activity.cancels, activity.cancelled_shares, activity.replaces] {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        write_levels(&mut writer, &self.bid_levels)?;
+        write_levels(&mut writer, &self.ask_levels)?;
+        writer.write_all(&[self.last_state.is_some() as u8])?;
+        if let Some(snapshot) = &self.last_state {
+            write_snapshot(&mut writer, snapshot)?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        fs::rename(&temp_path, path)
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if &read_array::<8>(&mut reader)? != MAGIC {
+            return Err(invalid("Not a checkpoint file"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid(&format!("Unsupported checkpoint version {}", version)));
+        }
+
+        let symbol = read_array(&mut reader)?;
+        let offset = read_u64(&mut reader)?;
+        let timestamp = read_u64(&mut reader)?;
+        let [has_locate] = read_array(&mut reader)?;
+        let stock_locate = u16::from_le_bytes(read_array(&mut reader)?);
+        let trade_stats = TradeStats {
+            volume: read_u64(&mut reader)?,
+            notional: read_u64(&mut reader)?,
+            trades: read_u64(&mut reader)?,
+        };
+        let ofi = i64::from_le_bytes(read_array(&mut reader)?);
+        let [has_last_trade] = read_array(&mut reader)?;
+        let last_trade_price = read_u32(&mut reader)?;
+        let [last_tick] = read_array(&mut reader)?;
+        let retail_interest = RetailInterest::from(read_array::<1>(&mut reader)?[0]);
+        let luld = LuldCollar {
+            reference_price: read_u32(&mut reader)?,
+            upper_price: read_u32(&mut reader)?,
+            lower_price: read_u32(&mut reader)?,
+            extensions: read_u32(&mut reader)?,
+        };
+        let [mwcb_level, short_sale_restricted, operational_halts] = read_array(&mut reader)?;
+        let mut read_time = || -> io::Result<Option<u64>> {
+            let [has_time] = read_array(&mut reader)?;
+            let time = read_u64(&mut reader)?;
+            Ok((has_time != 0).then_some(time))
+        };
+        let market_hours = MarketHours { open: read_time()?, close: read_time()? };
+        let orders = read_orders(&mut reader)?;
+        let round_lot = read_u32(&mut reader)?;
+        let excluded_orders = read_orders(&mut reader)?;
+        let participant_count = read_u64(&mut reader)?;
+        let mut participants = Vec::new();
+        for _ in 0..participant_count {
+            let [has_mpid] = read_array(&mut reader)?;
+            let mpid: [u8; 4] = read_array(&mut reader)?;
+            let activity = ParticipantActivity {
+                adds: read_u64(&mut reader)?,
+                added_shares: read_u64(&mut reader)?,
+                executions: read_u64(&mut reader)?,
+                executed_shares: read_u64(&mut reader)?,
+                cancels: read_u64(&mut reader)?,
+                cancelled_shares: read_u64(&mut reader)?,
+                replaces: read_u64(&mut reader)?,
+            };
+            participants.push(((has_mpid != 0).then_some(mpid), activity));
+        }
+        let bid_levels = read_levels(&mut reader)?;
+        let ask_levels = read_levels(&mut reader)?;
+        let [has_last_state] = read_array(&mut reader)?;
+        let last_state = if has_last_state != 0 { Some(read_snapshot(&mut reader)?) } else { None };
+
+        Ok(Checkpoint {
+            symbol,
+            offset,
+            timestamp,
+            stock_locate: (has_locate != 0).then_some(stock_locate),
+            trade_stats,
+            ofi,
+            last_trade_price: (has_last_trade != 0).then_some(last_trade_price),
+            last_tick: (last_tick != 0).then(|| Side::from(last_tick)),
+            retail_interest,
+            luld,
+            mwcb_level,
+            short_sale_restricted: short_sale_restricted != 0,
+            operational_halts,
+            market_hours,
+            orders,
+            round_lot,
+            excluded_orders,
+            participants,
+            bid_levels,
+            ask_levels,
+            last_state,
+        })
+    }
+}
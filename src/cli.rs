@@ -0,0 +1,935 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::error::ErrorKind;
+use clap::{ArgGroup, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use crate::affinity::CorePlan;
+use crate::batch;
+use crate::compress::Compression;
+use crate::config;
+use crate::directory::DirectoryFormat;
+use crate::message_types::FeedVariant;
+use crate::features::{FeatureColumns, ImbalanceWeighting, ImbalanceWeights, PriceFormat, SnapshotFeature};
+use crate::integrity::BandAction;
+use crate::lots::OddLotMode;
+use crate::orderbook::{OutputFormat, WriteMode, MAX_BOOK_DEPTH};
+use crate::parser;
+use crate::partition::{self, Partitioning};
+use crate::pcap::Encapsulation;
+use crate::snapshot::SnapshotFormat;
+use crate::timestamps::{parse_date, parse_timezone, Date, TimestampFormat, Timestamps, Timezone};
+use crate::utils::{parse_decay, parse_duration, parse_offset, parse_price, parse_size, parse_speed, parse_time_of_day};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None, arg_required_else_help = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Run the bundled ITCH sample through the parser and books and check the output against the expected files
+    #[arg(long, exclusive = true)]
+    pub selftest: bool,
+
+    /// TOML file of options for the subcommand; options on the command line override it
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Format of the log: text lines, or one JSON object per event for log aggregation
+    #[cfg(feature = "native")]
+    #[arg(long, value_enum, default_value_t = crate::logging::LogFormat::Text, global = true)]
+    pub log_format: crate::logging::LogFormat,
+
+    /// Append the log to this file instead of writing it to stdout
+    #[cfg(feature = "native")]
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Print the time spent parsing, updating the book and writing to the sinks when the command finishes
+    #[cfg(feature = "profiling")]
+    #[arg(long, global = true)]
+    pub profile_report: bool,
+}
+
+impl Cli {
+    // Parse the command line, with the options of --config filled in underneath it
+    pub fn parse_with_config() -> Cli {
+        let mut command = Cli::command();
+        let args = config::merge_config(&command, std::env::args_os().collect())
+            .unwrap_or_else(|e| command.clone().error(ErrorKind::InvalidValue, e).exit());
+        let matches = command.clone().get_matches_from(args);
+        let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        // --selftest runs instead of a subcommand, which is required otherwise
+        match (&cli.command, cli.selftest) {
+            (None, false) => command.error(ErrorKind::MissingSubcommand, "A subcommand or --selftest is required").exit(),
+            (Some(_), true) => command.error(ErrorKind::ArgumentConflict, "--selftest can't be combined with a subcommand").exit(),
+            _ => {}
+        }
+        cli
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Build the orderbook time series of a symbol and write it to a file
+    BuildBook(BuildBookArgs),
+    /// Write the trades of a symbol to a CSV file
+    Trades(TradesArgs),
+    /// Count the messages of a file by type
+    Stats(StatsArgs),
+    /// Stream the orderbook over WebSocket (and optionally HTTP) live while parsing
+    Serve(ServeArgs),
+    /// Dump every resting order of the book at a point in time
+    Snapshot(SnapshotArgs),
+    /// Replay a finished orderbook CSV file over WebSocket, paced by its timestamps
+    Replay(ReplayArgs),
+    /// Export the reference data of every instrument in the file's Stock Directory
+    Directory(DirectoryArgs),
+    /// List the symbols of the file with their locate codes and round lot sizes
+    ListSymbols(ListSymbolsArgs),
+    /// Count the messages of a file by type, by hour and by symbol without building a book
+    Profile(ProfileArgs),
+    /// Replay a symbol's book with simulated passive orders from a script and report their hypothetical fills
+    Backtest(BacktestArgs),
+    /// Run a price-time priority matching engine seeded with a symbol's book at a point in time, taking orders over WebSocket
+    Match(MatchArgs),
+    /// Compare two orderbook outputs and report the first timestamp where the books differ
+    Compare(CompareArgs),
+    /// Replay a symbol's book in a terminal depth-of-market view with trade prints and a mid sparkline
+    View(ViewArgs),
+    /// Write the messages of some symbols and/or a time range to a new ITCH file, for small test slices of a capture
+    Filter(FilterArgs),
+    /// Rewrite an ITCH file with renamed symbols, permuted order refs and shifted timestamps, for test data that can be shared
+    Scramble(ScrambleArgs),
+}
+
+// Where the ITCH messages come from and how strictly they are read
+#[derive(Args, Debug)]
+pub struct InputArgs {
+    /// Path to the ITCH 5.0 data file
+    #[arg(short, long, required_unless_present = "file_glob")]
+    pub file: Option<PathBuf>,
+
+    /// Process every daily file matching the pattern (wildcards * and ? in the file name, e.g. 'data/*.NASDAQ_ITCH50') instead of one file, with an output file per day (build-book only)
+    #[arg(long, conflicts_with = "file")]
+    pub file_glob: Option<String>,
+
+    /// How the messages are packaged: a plain ITCH file, or a pcap capture of MoldUDP64 packets
+    #[arg(long, value_enum, default_value_t = Encapsulation::Raw)]
+    pub encap: Encapsulation,
+
+    /// Exchange feed the ITCH file was captured from
+    #[arg(long, value_enum, default_value_t = FeedVariant::Nasdaq)]
+    pub feed: FeedVariant,
+
+    /// Validate message types, lengths and timestamp order, and exit with an error report on malformed input
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Write every administrative message (system events, directory, trading actions, halts, Reg SHO, MWCB, LULD, IPO) of all symbols with its decoded fields to this CSV file
+    #[arg(long)]
+    pub events_output: Option<PathBuf>,
+}
+
+impl InputArgs {
+    // The single input file of the subcommands that don't take --file-glob
+    pub fn file(&self) -> io::Result<&Path> {
+        match &self.file {
+            Some(file) => Ok(file),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "--file-glob is only supported by build-book")),
+        }
+    }
+}
+
+// Which symbol's book is built and which of its updates are written
+#[derive(Args, Debug, Clone)]
+pub struct BookArgs {
+    /// Stock symbol to track
+    #[arg(short, long)]
+    pub symbol: Option<String>,
+
+    /// Which orderbook updates to write
+    #[arg(long, value_enum, default_value_t = WriteMode::EveryMessage)]
+    pub write_mode: WriteMode,
+
+    /// Skip orderbook rows where no visible level changed (same as --write-mode on-change)
+    #[arg(long)]
+    pub dedupe_snapshots: bool,
+
+    /// Write only best bid/offer rows, and only when the BBO changes
+    #[arg(long)]
+    pub bbo_only: bool,
+
+    /// Check book invariants (crossed/locked book, unknown order refs, negative levels) and write anomalies to this CSV file
+    #[arg(long)]
+    pub anomalies_output: Option<PathBuf>,
+
+    /// Track the queue position of every order and write its lifecycle events (with the shares ahead of it) to this CSV file
+    #[arg(long)]
+    pub order_lifecycle: Option<PathBuf>,
+
+    /// Write order lifetime, cancel rate, message rate and time-weighted spread and depth analytics for the symbol to this JSON file at the end
+    #[arg(long)]
+    pub stats_output: Option<PathBuf>,
+
+    /// Write the add, cancel and execute volume of the symbol per market participant (MPID of attributed orders) to this CSV file at the end
+    #[arg(long)]
+    pub participants_output: Option<PathBuf>,
+
+    /// Split the symbol's executions into displayed and hidden liquidity (non-displayed trades and executions away from the displayed price) and write the statistics to this JSON file at the end
+    #[arg(long)]
+    pub hidden_output: Option<PathBuf>,
+
+    /// Aggregate the symbol's printable trades into OHLCV bars of each --bar-interval and write them to this CSV file
+    #[arg(long)]
+    pub bars_output: Option<PathBuf>,
+
+    /// Comma separated bar lengths in market time (e.g. 1s,1m,5m)
+    #[arg(long, value_parser = parse_duration, value_delimiter = ',', default_value = "1m")]
+    pub bar_interval: Vec<u64>,
+
+    /// Keep odd-lot orders (fewer shares than the symbol's round lot) on the book, or exclude them and odd-lot trades from the output
+    #[arg(long, value_enum, default_value_t = OddLotMode::Include)]
+    pub odd_lots: OddLotMode,
+
+    /// Write every odd-lot order and odd-lot trade of the symbol to this CSV file
+    #[arg(long)]
+    pub odd_lots_output: Option<PathBuf>,
+
+    /// Write level sizes in round lots of the symbol instead of shares, leaving out levels of less than a round lot
+    #[arg(long)]
+    pub lots: bool,
+
+    /// Check every add against the prevailing price (the mid, else the quoted side, else the last trade) and flag or drop those further from it than this percent
+    #[arg(long, value_parser = parse_band)]
+    pub price_band: Option<f64>,
+
+    /// What to do with adds outside --price-band: flag them (count them in --stats-output and report them in --anomalies-output) or also drop them from the book
+    #[arg(long, value_enum, default_value_t = BandAction::Flag, requires = "price_band")]
+    pub price_band_action: BandAction,
+
+    /// Sample the resting shares per price bucket near the mid every --heatmap-interval and write them to this Parquet file as a dense price x time grid
+    #[arg(long)]
+    pub heatmap_output: Option<PathBuf>,
+
+    /// Market time between heatmap samples
+    #[arg(long, value_parser = parse_duration, default_value = "1s", requires = "heatmap_output")]
+    pub heatmap_interval: u64,
+
+    /// Width of the heatmap's price buckets in dollars
+    #[arg(long, value_parser = parse_tick, default_value = "0.01", requires = "heatmap_output")]
+    pub heatmap_tick: u32,
+
+    /// Price buckets sampled on each side of the mid
+    #[arg(long, default_value_t = crate::heatmap::DEFAULT_HEATMAP_LEVELS, requires = "heatmap_output")]
+    pub heatmap_levels: u32,
+
+    /// Comma separated computed columns to add to the output file and the live feed: vwap, volume (cumulative volume and trade count), ofi (top level order flow imbalance), microprice, spread-ticks, returns (log mid returns), volatility (realized volatility), retail-interest (RPII buy/sell/both/none), luld (auction collar), mwcb (circuit breaker level), ssr (Reg SHO short sale restriction), halts (operational halts per market)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub features: Vec<SnapshotFeature>,
+
+    /// Comma separated windows of market time for the returns and volatility features (e.g. 500ms,1s,10s,1m)
+    #[arg(long, value_parser = parse_duration, value_delimiter = ',', default_value = "1s,10s,1m")]
+    pub feature_windows: Vec<u64>,
+
+    /// Comma separated columns to write to the output file, in this order, instead of all of them (e.g. timestamp,1_bid_price,1_ask_price,mid_price); any column of the full row, computed ones included. Not for SQLite output
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Vec<String>,
+
+    /// Price levels per side of the orderbook file of --output-format lobster
+    #[arg(long, default_value_t = MAX_BOOK_DEPTH as u16, value_parser = clap::value_parser!(u16).range(1..=MAX_BOOK_DEPTH as i64))]
+    pub lobster_levels: u16,
+
+    /// How the book's prices are written in the output file and the live feed: decimal (dollars with 4 decimals), raw (integer 1/10000 dollars, as in the feed) or ticks (integer $0.01 ticks, $0.0001 below $1)
+    #[arg(long, value_enum, default_value_t = PriceFormat::Decimal)]
+    pub price_format: PriceFormat,
+
+    /// How the timestamp column of the output file and the live feed is written: ns (nanoseconds since midnight), us (microseconds), seconds (with 9 decimals) or iso8601 (HH:MM:SS.fffffffff)
+    #[arg(long, value_enum, default_value_t = TimestampFormat::Ns)]
+    pub timestamp_format: TimestampFormat,
+
+    /// Trading day of the input (YYYY-MM-DD): ns, us and seconds timestamps count from the Unix epoch, and ISO 8601 ones get the date and the Eastern time UTC offset
+    #[arg(long, value_parser = parse_date)]
+    pub date: Option<Date>,
+
+    /// Time zone of the feed's timestamps (America/New_York for Nasdaq): with --date, adds utc_timestamp (nanoseconds since the Unix epoch) and session (pre_market, regular, post_market or closed) columns to the output file and the live feed
+    #[arg(long, value_parser = parse_timezone, requires = "date")]
+    pub timezone: Option<Timezone>,
+
+    /// How the levels are weighted in the orderbook_imbalance column: flat, or exponential decay from the top level
+    #[arg(long, value_enum, default_value_t = ImbalanceWeighting::Flat)]
+    pub imbalance_weighting: ImbalanceWeighting,
+
+    /// Decay per level of the exponential imbalance weighting: level i (0 at the top) has weight exp(-decay * i)
+    #[arg(long, default_value_t = 0.5, value_parser = parse_decay)]
+    pub imbalance_decay: f64,
+
+    /// Only count the top N levels per side in the imbalance (default: every visible level)
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub imbalance_levels: Option<u16>,
+
+    /// Check the refs of executes, cancels, deletes and replaces against a bloom filter of the symbol's orders before looking them up
+    #[arg(long)]
+    pub fast_filter: bool,
+
+    /// Resting orders to allocate room for up front, so the order pool doesn't grow while parsing (default: grow as needed)
+    #[arg(long, default_value_t = 0)]
+    pub expected_orders: usize,
+}
+
+impl BookArgs {
+    // The tracked symbol, which every subcommand requires unless build-book picks its symbols with --auto-top
+    pub fn symbol(&self) -> &str {
+        self.symbol.as_deref().unwrap_or_default()
+    }
+
+    // The book's own output files besides the orderbook, which get the same day and symbol names
+    pub fn outputs_mut(&mut self) -> [&mut Option<PathBuf>; 8] {
+        [&mut self.anomalies_output, &mut self.order_lifecycle, &mut self.stats_output, &mut self.participants_output,
+         &mut self.hidden_output, &mut self.odd_lots_output, &mut self.bars_output, &mut self.heatmap_output]
+    }
+
+    // Fill {symbol} and {date} into the output files (e.g. --stats-output 'stats/{symbol}_{date}.json') and create
+    // their directories
+    pub fn fill_outputs(&mut self, partitioning: &Partitioning) -> io::Result<()> {
+        for path in self.outputs_mut().into_iter().flatten() {
+            *path = partitioning.fill(path);
+            partition::create_parent(path)?;
+        }
+        Ok(())
+    }
+
+    // --dedupe-snapshots only upgrades the default mode, an explicit --write-mode wins
+    pub fn write_mode(&self) -> WriteMode {
+        match (self.write_mode, self.dedupe_snapshots) {
+            (WriteMode::EveryMessage, true) => WriteMode::OnChange,
+            (mode, _) => mode,
+        }
+    }
+
+    pub fn feature_columns(&self) -> FeatureColumns {
+        FeatureColumns::new(&self.features, &self.feature_windows)
+            .with_timestamps(Timestamps { format: self.timestamp_format, date: self.date, timezone: self.timezone })
+            .with_prices(self.price_format)
+            .with_selection(self.columns.clone())
+            .with_levels(self.lobster_levels as usize)
+    }
+
+    // An option whose state checkpoints don't keep, so it couldn't continue after --resume-from
+    pub fn unresumable(&self) -> Option<&'static str> {
+        if !self.feature_columns().estimator_windows().is_empty() {
+            Some("the returns and volatility features")
+        } else if self.stats_output.is_some() {
+            Some("--stats-output")
+        } else if self.bars_output.is_some() {
+            Some("--bars-output")
+        } else {
+            None
+        }
+    }
+
+    pub fn imbalance_weights(&self) -> ImbalanceWeights {
+        ImbalanceWeights {
+            weighting: self.imbalance_weighting,
+            decay: self.imbalance_decay,
+            levels: self.imbalance_levels.map_or(usize::MAX, usize::from),
+        }
+    }
+}
+
+// Updates queued for the output writer thread by default
+const DEFAULT_WRITER_QUEUE: usize = 4096;
+
+// When the output file is rotated into a new part
+#[derive(Args, Debug)]
+pub struct RotationArgs {
+    /// Start a new part of the output file when it reaches this size (e.g. 512MB, 2GB)
+    #[arg(long, value_parser = parse_size, requires = "output_file")]
+    pub rotate_size: Option<u64>,
+
+    /// Start a new part of the output file every interval of market time (e.g. 30m, 1h)
+    #[arg(long, value_parser = parse_duration, requires = "output_file")]
+    pub rotate_interval: Option<u64>,
+}
+
+impl RotationArgs {
+    // Partitioning of a symbol's output built from an input file
+    pub fn partitioning(&self, symbol: &str, input: &Path) -> Partitioning {
+        Partitioning {
+            symbol: symbol.to_string(),
+            date: batch::day_name(input),
+            max_bytes: self.rotate_size,
+            interval: self.rotate_interval,
+        }
+    }
+}
+
+// Where the threads of a run are pinned and how the allocator prepares its memory
+#[derive(Args, Debug, Clone, Default)]
+pub struct AffinityArgs {
+    /// Comma separated cores to pin threads to, in order: the parser, the output writer, then the runtime threads (WebSocket and HTTP servers) share the rest. Linux only
+    #[arg(long, value_delimiter = ',')]
+    pub pin_cores: Vec<usize>,
+
+    /// Reserve this many 1 GiB huge pages for the allocator at startup, on the NUMA node of the parser's core when it is pinned
+    #[arg(long)]
+    pub reserve_huge_pages: Option<usize>,
+}
+
+impl AffinityArgs {
+    pub fn plan(&self) -> CorePlan {
+        CorePlan::new(&self.pin_cores)
+    }
+}
+
+// A part of the input to process, to continue where an earlier run ended or split a file across machines
+#[derive(Args, Debug, Clone, Default)]
+pub struct ChunkArgs {
+    /// Start parsing at this byte offset, which must be the start of a message, like the offset an earlier run ended at (logged at the end of a run). The book starts empty
+    #[arg(long, conflicts_with = "file_glob")]
+    pub start_offset: Option<usize>,
+
+    /// Stop after this many messages
+    #[arg(long)]
+    pub max_messages: Option<u64>,
+}
+
+impl ChunkArgs {
+    // The start offset, checked against the input
+    pub fn start(&self, data: &[u8]) -> io::Result<usize> {
+        let Some(offset) = self.start_offset else {
+            return Ok(0);
+        };
+        if !parser::is_message_boundary(data, offset) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("--start-offset {} is not the start of a message", offset)));
+        }
+        Ok(offset)
+    }
+}
+
+// Options of the WebSocket server of serve, replay and match: who may connect (clients with a token, over
+// wss:// with the `tls` feature) and how slow clients are handled
+#[derive(Args, Debug, Clone)]
+pub struct WebSocketArgs {
+    /// What to do when a client's queue of updates is full: conflate to the latest snapshot of each symbol,
+    /// buffer by dropping the oldest update, or disconnect the client with a reason
+    #[cfg(feature = "native")]
+    #[arg(long, value_enum, default_value_t = crate::outbox::SlowClientPolicy::Disconnect)]
+    pub slow_client: crate::outbox::SlowClientPolicy,
+
+    /// Updates queued per client before --slow-client applies
+    #[cfg(feature = "native")]
+    #[arg(long, default_value_t = crate::websocket::BROADCAST_CAPACITY as u32, value_parser = clap::value_parser!(u32).range(1..))]
+    pub client_buffer: u32,
+
+    /// Ping clients this often and disconnect those that haven't answered by the next ping (0 to not ping)
+    #[cfg(feature = "native")]
+    #[arg(long, default_value = "30s", value_parser = parse_ping_interval)]
+    pub ping_interval: u64,
+
+    /// With ?format=delta, send a full snapshot of a symbol again after this many deltas (0 for never)
+    #[cfg(feature = "native")]
+    #[arg(long, default_value_t = crate::delta::DEFAULT_RESNAPSHOT_INTERVAL)]
+    pub resnapshot_every: u64,
+
+    /// Let clients ask for zstd-compressed frames with ?compression=zstd, at this level (1-22, default 3)
+    #[cfg(feature = "native")]
+    #[arg(long, value_name = "LEVEL", num_args = 0..=1, default_missing_value = "3", value_parser = clap::value_parser!(i32).range(1..=22))]
+    pub ws_compression: Option<i32>,
+
+    /// Only serve clients that present this token, as ?token=... or as their first message {"token": "..."}
+    #[arg(long, value_parser = clap::builder::NonEmptyStringValueParser::new())]
+    pub ws_auth_token: Option<String>,
+
+    /// Only serve clients that present one of the tokens in this file, one per line (# starts a comment)
+    #[arg(long)]
+    pub ws_auth_tokens_file: Option<PathBuf>,
+
+    /// Serve wss:// with this PEM certificate chain, leaf first
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key of --tls-cert
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+}
+
+impl WebSocketArgs {
+    // The server with the client policy, tokens and TLS that were asked for
+    #[cfg(feature = "native")]
+    pub fn apply(&self, server: crate::websocket::WebSocketServer) -> io::Result<crate::websocket::WebSocketServer> {
+        let mut server = server.with_client_policy(crate::outbox::ClientPolicy {
+            slow_client: self.slow_client,
+            buffer: self.client_buffer as usize,
+            ping_interval: (self.ping_interval > 0).then(|| std::time::Duration::from_nanos(self.ping_interval)),
+            resnapshot_every: self.resnapshot_every,
+            compression: self.ws_compression,
+        });
+        let mut tokens: Vec<String> = self.ws_auth_token.iter().cloned().collect();
+        if let Some(path) = &self.ws_auth_tokens_file {
+            let read = crate::auth::AuthTokens::read(path)?;
+            // An empty file would otherwise leave the server open to everyone
+            if read.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("No tokens in {}", path.display())));
+            }
+            tokens.extend(read);
+        }
+        let tokens = crate::auth::AuthTokens::new(tokens);
+        if !tokens.is_empty() {
+            server = server.with_auth(tokens);
+        }
+        #[cfg(feature = "tls")]
+        if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+            server = server.with_tls(crate::tls::acceptor(cert, key)?);
+        }
+        Ok(server)
+    }
+}
+
+// A duration, or 0 for none
+#[cfg(feature = "native")]
+fn parse_ping_interval(text: &str) -> Result<u64, String> {
+    if text == "0" { Ok(0) } else { parse_duration(text) }
+}
+
+// A price step, more than 0
+fn parse_tick(text: &str) -> Result<u32, String> {
+    match parse_price(text)? {
+        0 => Err(format!("Invalid tick \"{}\", expected more than 0", text)),
+        tick => Ok(tick),
+    }
+}
+
+// A price band in percent, more than 0
+fn parse_band(text: &str) -> Result<f64, String> {
+    match text.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if percent.is_finite() && percent > 0.0 => Ok(percent),
+        _ => Err(format!("Invalid price band \"{}\", expected a percent more than 0", text)),
+    }
+}
+
+// External databases and brokers the snapshots are also sent to
+#[derive(Args, Debug)]
+pub struct ExternalSinkArgs {
+    /// Publish snapshots and trades to Kafka: brokers=HOST:PORT[,HOST:PORT],topic=NAME[,trades_topic=NAME][,format=json|avro][,batch=N]
+    #[cfg(feature = "kafka")]
+    #[arg(long, value_parser = crate::kafka::KafkaConfig::parse)]
+    pub kafka: Option<crate::kafka::KafkaConfig>,
+
+    /// Load snapshots into Postgres / TimescaleDB ("host=... user=... dbname=..." or a postgres:// URL)
+    #[cfg(feature = "postgres")]
+    #[arg(long)]
+    pub postgres: Option<String>,
+
+    /// Postgres table for snapshots, created if it doesn't exist
+    #[cfg(feature = "postgres")]
+    #[arg(long, default_value = "orderbook_snapshots", requires = "postgres")]
+    pub postgres_table: String,
+
+    /// Snapshot rows per COPY batch
+    #[cfg(feature = "postgres")]
+    #[arg(long, default_value_t = 10_000, requires = "postgres")]
+    pub postgres_batch_size: usize,
+
+    /// Create the Postgres table as a TimescaleDB hypertable
+    #[cfg(feature = "postgres")]
+    #[arg(long, requires = "postgres")]
+    pub timescale: bool,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("book_symbols").required(true).args(["symbol", "auto_top"])))]
+pub struct BuildBookArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub book: BookArgs,
+
+    /// Output file for the orderbook; {symbol}, {date} (the input file name up to the first dot) and {part} in it
+    /// partition the output (e.g. out/{symbol}/{date}/part-{part}.parquet); with --file-glob, {file} is replaced
+    /// by each day's name (by default the name goes before the extension). Optional with --interactive
+    #[arg(short, long, required_unless_present = "interactive")]
+    pub output_file: Option<PathBuf>,
+
+    /// Format of the output file
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    pub output_format: OutputFormat,
+
+    /// Compress a csv or jsonl output file: gzip[:LEVEL] (0-9, default 6) or zstd[:LEVEL] (1-22, default 3); .gz or .zst is added to the file name
+    #[arg(long, value_parser = Compression::parse)]
+    pub compress: Option<Compression>,
+
+    #[command(flatten)]
+    pub rotation: RotationArgs,
+
+    /// Write the output file on its own thread, with up to this many updates queued for it (0 writes on the parsing thread)
+    #[arg(long, default_value_t = DEFAULT_WRITER_QUEUE)]
+    pub writer_queue: usize,
+
+    #[command(flatten)]
+    pub affinity: AffinityArgs,
+
+    /// Files of --file-glob to process at the same time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), requires = "file_glob")]
+    pub jobs: u16,
+
+    /// Write the per-day and total messages, updates, trades, volume and VWAP of a --file-glob run to this CSV file
+    #[arg(long, requires = "file_glob")]
+    pub batch_report: Option<PathBuf>,
+
+    /// Write a JSON summary of the run (input SHA-256, messages by type, rows per sink, anomalies, wall time, throughput, status) to this file, also when it fails
+    #[arg(long)]
+    pub summary_output: Option<PathBuf>,
+
+    /// Follow every order ref and write a per-symbol reconciliation report (unknown refs, orders left resting) next to the output file
+    #[arg(long)]
+    pub audit: bool,
+
+    /// Write the full book state to a checkpoint file every interval of market time (e.g. 30s, 5m, 1h)
+    #[arg(long, value_parser = parse_duration)]
+    pub checkpoint_interval: Option<u64>,
+
+    /// Checkpoint file (default: next to the output file, with a .checkpoint.bin extension)
+    #[arg(long, requires = "checkpoint_interval")]
+    pub checkpoint_file: Option<PathBuf>,
+
+    /// Instead of --symbol, build the books of the N symbols with the most messages in the input, found by a quick scan first; every output file gets the symbol's name ({symbol} in the path, or before the extension)
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..), conflicts_with_all = ["symbol", "resume_from", "checkpoint_interval"])]
+    pub auto_top: Option<u16>,
+
+    /// Pick the --auto-top symbols by their messages in this much market time after the first add (e.g. 10m) instead of the whole input
+    #[arg(long, value_parser = parse_duration, requires = "auto_top")]
+    pub auto_top_window: Option<u64>,
+
+    /// Restore the book from a checkpoint and continue parsing from its byte offset
+    #[arg(long, conflicts_with_all = ["file_glob", "start_offset"])]
+    pub resume_from: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub chunk: ChunkArgs,
+
+    /// Scan the file in parallel first for the symbol's messages and only parse those (not with --strict, --audit or --events-output, which need every message)
+    #[arg(long, conflicts_with_all = ["strict", "audit", "events_output"])]
+    pub prescan: bool,
+
+    /// After processing, read queries from the terminal against the books in memory: book SYMBOL, depth SYMBOL [LEVELS], trades SYMBOL [FROM [TO]] (type help for the list)
+    #[arg(long, conflicts_with = "file_glob")]
+    pub interactive: bool,
+
+    #[command(flatten)]
+    pub external: ExternalSinkArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct TradesArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Stock symbol to track
+    #[arg(short, long)]
+    pub symbol: String,
+
+    /// Output CSV file for the trades
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub chunk: ChunkArgs,
+}
+
+#[derive(Args, Debug)]
+#[command(mut_arg("symbol", |arg| arg.required(true)))]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub book: BookArgs,
+
+    /// WebSocket server port
+    #[arg(short = 'p', long, default_value_t = 8473)]
+    pub port: u16,
+
+    #[command(flatten)]
+    pub websocket: WebSocketArgs,
+
+    /// Also send each completed OHLCV bar of --bar-interval to the WebSocket clients, as {"type":"bar",...} messages
+    #[arg(long)]
+    pub stream_bars: bool,
+
+    /// Also serve the HTTP API (/book, /trades, /stats, /metrics) on this port
+    #[arg(long)]
+    pub http_port: Option<u16>,
+
+    /// Also serve the gRPC API (SubscribeBook, SubscribeTrades, GetSnapshot in proto/orderbook.proto) on this port
+    #[cfg(feature = "grpc")]
+    #[arg(long)]
+    pub grpc_port: Option<u16>,
+
+    /// Also serve the processed snapshots over Arrow Flight on this port, for bulk pulls by symbol and time range
+    #[cfg(feature = "flight")]
+    #[arg(long)]
+    pub flight_port: Option<u16>,
+
+    /// Replay the file in market time instead of as fast as possible: messages keep their original spacing,
+    /// divided by this multiplier (1 = real time, 10 = ten times faster)
+    #[arg(long, value_parser = parse_speed)]
+    pub speed: Option<f64>,
+
+    /// While replaying with --speed, log the p50/p99/p99.9 latency from each message's due time to the end
+    /// of its processing this often, e.g. 10s or 1m (the whole run's is logged at the end)
+    #[arg(long, value_parser = parse_duration, default_value = "10s")]
+    pub latency_interval: u64,
+
+    /// Also write the orderbook to this file, partitioned like build-book's output file
+    #[arg(short, long)]
+    pub output_file: Option<PathBuf>,
+
+    /// Format of the output file
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv, requires = "output_file")]
+    pub output_format: OutputFormat,
+
+    /// Compress that file, like build-book's --compress
+    #[arg(long, value_parser = Compression::parse, requires = "output_file")]
+    pub compress: Option<Compression>,
+
+    #[command(flatten)]
+    pub rotation: RotationArgs,
+
+    /// Write that file on its own thread, with up to this many updates queued for it (0 writes on the parsing thread)
+    #[arg(long, default_value_t = DEFAULT_WRITER_QUEUE, requires = "output_file")]
+    pub writer_queue: usize,
+
+    #[command(flatten)]
+    pub affinity: AffinityArgs,
+
+    #[command(flatten)]
+    pub external: ExternalSinkArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct SnapshotArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Time of the snapshot, HH:MM:SS[.fraction] or nanoseconds since midnight
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub at: u64,
+
+    /// Comma separated stock symbols to snapshot
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub symbols: Vec<String>,
+
+    /// Output file for the snapshot; with {symbol} in it, a file per symbol ({date} is the input file name up to the first dot)
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+
+    /// Format of the snapshot
+    #[arg(long, value_enum, default_value_t = SnapshotFormat::Csv)]
+    pub format: SnapshotFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ViewArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Stock symbol to view
+    #[arg(short, long)]
+    pub symbol: String,
+
+    /// Market time per wall clock time (1 = real time, 10 = ten times faster); + and - double and halve it while viewing
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    pub speed: f64,
+
+    /// Time to start at, HH:MM:SS[.fraction] or nanoseconds since midnight (default: the symbol's first update)
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub start: Option<u64>,
+
+    /// Price levels shown on each side of the ladder
+    #[arg(long, default_value_t = MAX_BOOK_DEPTH as u16, value_parser = clap::value_parser!(u16).range(1..=MAX_BOOK_DEPTH as i64))]
+    pub levels: u16,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    /// Orderbook CSV file written by build-book
+    #[arg(short, long)]
+    pub input_file: PathBuf,
+
+    /// Symbol the orderbook file is for
+    #[arg(short, long)]
+    pub symbol: String,
+
+    /// WebSocket server port
+    #[arg(short = 'p', long, default_value_t = 8473)]
+    pub port: u16,
+
+    #[command(flatten)]
+    pub websocket: WebSocketArgs,
+
+    /// Multiplier on the rows' original spacing in market time; clients can change their own with {"speed": x}
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    pub speed: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct DirectoryArgs {
+    /// Path to the ITCH 5.0 data file
+    #[arg(short, long)]
+    pub file: PathBuf,
+
+    /// How the messages are packaged: a plain ITCH file, or a pcap capture of MoldUDP64 packets
+    #[arg(long, value_enum, default_value_t = Encapsulation::Raw)]
+    pub encap: Encapsulation,
+
+    /// Output file for the directory
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+
+    /// Format of the directory
+    #[arg(long, value_enum, default_value_t = DirectoryFormat::Csv)]
+    pub format: DirectoryFormat,
+}
+
+#[derive(Args, Debug)]
+pub struct ListSymbolsArgs {
+    /// Path to the ITCH 5.0 data file
+    #[arg(short, long)]
+    pub file: PathBuf,
+
+    /// How the messages are packaged: a plain ITCH file, or a pcap capture of MoldUDP64 packets
+    #[arg(long, value_enum, default_value_t = Encapsulation::Raw)]
+    pub encap: Encapsulation,
+
+    /// Scan the whole file, to also list symbols added during the day (by default the scan stops at the first order)
+    #[arg(long)]
+    pub whole_file: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ProfileArgs {
+    /// Path to the ITCH 5.0 data file
+    #[arg(short, long)]
+    pub file: PathBuf,
+
+    /// How the messages are packaged: a plain ITCH file, or a pcap capture of MoldUDP64 packets
+    #[arg(long, value_enum, default_value_t = Encapsulation::Raw)]
+    pub encap: Encapsulation,
+
+    /// How many of the busiest symbols to print
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("filters").required(true).multiple(true).args(["symbols", "from", "to"])))]
+pub struct FilterArgs {
+    /// Path to the ITCH 5.0 data file
+    #[arg(short, long)]
+    pub file: PathBuf,
+
+    /// How the messages are packaged: a plain ITCH file, or a pcap capture of MoldUDP64 packets (written out as a plain ITCH file)
+    #[arg(long, value_enum, default_value_t = Encapsulation::Raw)]
+    pub encap: Encapsulation,
+
+    /// Output ITCH file
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+
+    /// Comma separated symbols whose messages are kept (default: every symbol)
+    #[arg(short, long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+
+    /// Keep messages from this time on, HH:MM:SS[.fraction] or nanoseconds since midnight; messages of orders added before it are dropped
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub from: Option<u64>,
+
+    /// Keep messages before this time, HH:MM:SS[.fraction] or nanoseconds since midnight
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub to: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct ScrambleArgs {
+    /// Path to the ITCH 5.0 data file
+    #[arg(short, long)]
+    pub file: PathBuf,
+
+    /// How the messages are packaged: a plain ITCH file, or a pcap capture of MoldUDP64 packets (written out as a plain ITCH file)
+    #[arg(long, value_enum, default_value_t = Encapsulation::Raw)]
+    pub encap: Encapsulation,
+
+    /// Output ITCH file
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+
+    /// Seed of the symbol names, order refs and default time shift; the same seed gives the same output
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Move every timestamp by this much, e.g. -15m or 90s, 0 for none (default: whole seconds within 30 minutes either way, picked from the seed)
+    #[arg(long, value_parser = parse_offset, allow_hyphen_values = true)]
+    pub time_shift: Option<i64>,
+}
+
+#[derive(Args, Debug)]
+pub struct BacktestArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Stock symbol to trade
+    #[arg(short, long)]
+    pub symbol: String,
+
+    /// Script of simulated orders, one TIME,buy|sell,PRICE,SHARES or TIME,cancel,ORDER per line
+    #[arg(long)]
+    pub orders: PathBuf,
+
+    /// Output CSV file for the fills
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct MatchArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Stock symbol whose book seeds the engine
+    #[arg(short, long)]
+    pub symbol: String,
+
+    /// Time of the book that seeds the engine, HH:MM:SS[.fraction] or nanoseconds since midnight
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub at: u64,
+
+    /// WebSocket server port
+    #[arg(short = 'p', long, default_value_t = 8473)]
+    pub port: u16,
+
+    #[command(flatten)]
+    pub websocket: WebSocketArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Orderbook output (CSV, JSONL or Parquet, full depth or BBO, optionally .gz or .zst)
+    pub left: PathBuf,
+
+    /// Orderbook output to compare it with, e.g. from a reference implementation
+    pub right: PathBuf,
+
+    /// Compare only the top N levels per side (default: every level both files have)
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub depth: Option<u16>,
+}
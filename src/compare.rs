@@ -0,0 +1,281 @@
+// Compares two orderbook outputs, e.g. before and after a parser change or against another implementation,
+// and reports the first timestamp where the books differ. The files may write different rows: each file's
+// book at a timestamp is its last row at or before it, so a file of every message and one of changes only
+// still line up. Rows are matched by their level columns, CSV, JSONL or Parquet, full depth or BBO
+
+use std::fs::File;
+use std::io::{self, BufRead, Lines};
+use std::path::{Path, PathBuf};
+
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::reader::RowIter;
+
+use crate::cli::CompareArgs;
+use crate::compress;
+use crate::utils::format_time_of_day;
+
+// Price and shares of the levels of a side, best first; (0, 0) for a missing level
+type Levels = Vec<(u32, u32)>;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Book {
+    pub timestamp: u64,
+    pub bids: Levels,
+    pub asks: Levels,
+}
+
+impl Book {
+    fn set(&mut self, key: &str, value: &str) {
+        // Prices are dollars with any number of decimals
+        let price = || (value.parse::<f64>().unwrap_or(0.0) * 10000.0).round() as u32;
+        let shares = || value.parse::<f64>().unwrap_or(0.0) as u32;
+        let (levels, index, is_price) = match key {
+            "timestamp" => {
+                self.timestamp = value.parse().unwrap_or(0);
+                return;
+            }
+            "bid" => (&mut self.bids, 0, true),
+            "bid_size" => (&mut self.bids, 0, false),
+            "ask" => (&mut self.asks, 0, true),
+            "ask_size" => (&mut self.asks, 0, false),
+            _ => match level_column(key) {
+                Some((index, "bid_price")) => (&mut self.bids, index, true),
+                Some((index, "bid_vol")) => (&mut self.bids, index, false),
+                Some((index, "ask_price")) => (&mut self.asks, index, true),
+                Some((index, "ask_vol")) => (&mut self.asks, index, false),
+                _ => return,
+            },
+        };
+        if levels.len() <= index {
+            levels.resize(index + 1, (0, 0));
+        }
+        if is_price {
+            levels[index].0 = price();
+        } else {
+            levels[index].1 = shares();
+        }
+    }
+
+    fn level(levels: &Levels, index: usize) -> (u32, u32) {
+        match levels.get(index) {
+            // A level without shares is an empty one, whatever its price
+            Some(&(_, 0)) | None => (0, 0),
+            Some(&level) => level,
+        }
+    }
+}
+
+// Index of a "<level>_<field>" depth column, 0 for level 1
+fn level_column(key: &str) -> Option<(usize, &str)> {
+    let (level, field) = key.split_once('_')?;
+    Some((level.parse::<usize>().ok()?.checked_sub(1)?, field))
+}
+
+// Levels per side the columns cover: 1 for BBO columns, N for depth columns up to "N_..."
+fn column_levels<'a>(keys: impl Iterator<Item = &'a str>) -> usize {
+    keys.map(|key| match key {
+        "bid" | "ask" => 1,
+        _ => level_column(key).map_or(0, |(index, _)| index + 1),
+    }).max().unwrap_or(0)
+}
+
+// The next line that isn't empty
+fn next_line(lines: &mut Lines<Box<dyn BufRead>>) -> io::Result<Option<String>> {
+    for line in lines {
+        let line = line?;
+        if !line.is_empty() {
+            return Ok(Some(line));
+        }
+    }
+    Ok(None)
+}
+
+enum Rows {
+    Csv(Vec<String>, Lines<Box<dyn BufRead>>),
+    Jsonl(Lines<Box<dyn BufRead>>),
+    Parquet(RowIter<'static>),
+}
+
+// One of the files, read a timestamp at a time
+struct Input {
+    path: PathBuf,
+    rows: Rows,
+    // Row read ahead, with its row number
+    pending: Option<(usize, Book)>,
+    row: usize,
+    // Row number of the current book
+    book_row: usize,
+    levels: usize,
+    book: Book,
+}
+
+impl Input {
+    fn open(path: &Path) -> io::Result<Self> {
+        let name = path.to_string_lossy();
+        let name = name.trim_end_matches(".gz").trim_end_matches(".zst");
+        let (rows, levels) = if name.ends_with(".parquet") {
+            let reader = SerializedFileReader::new(File::open(path)?).map_err(io::Error::other)?;
+            let schema = reader.metadata().file_metadata().schema_descr_ptr();
+            let levels = column_levels(schema.columns().iter().map(|column| column.name()));
+            (Rows::Parquet(reader.into_iter()), levels)
+        } else if name.ends_with(".jsonl") || name.ends_with(".json") {
+            (Rows::Jsonl(compress::open_reader(path)?.lines()), 0)
+        } else {
+            let mut lines = compress::open_reader(path)?.lines();
+            let header: Vec<String> = lines.next().transpose()?.unwrap_or_default().split(',').map(str::to_string).collect();
+            let levels = column_levels(header.iter().map(String::as_str));
+            (Rows::Csv(header, lines), levels)
+        };
+        Ok(Input { path: path.to_path_buf(), rows, pending: None, row: 0, book_row: 0, levels, book: Book::default() })
+    }
+
+    fn read_row(&mut self) -> io::Result<Option<Book>> {
+        let mut book = Book::default();
+        match &mut self.rows {
+            Rows::Csv(header, lines) => {
+                let Some(line) = next_line(lines)? else {
+                    return Ok(None);
+                };
+                for (key, value) in header.iter().zip(line.split(',')) {
+                    book.set(key, value);
+                }
+            }
+            Rows::Jsonl(lines) => {
+                let Some(line) = next_line(lines)? else {
+                    return Ok(None);
+                };
+                let value: serde_json::Value = serde_json::from_str(&line).map_err(io::Error::other)?;
+                let Some(fields) = value.as_object() else {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Expected a JSON object per line"));
+                };
+                self.levels = self.levels.max(column_levels(fields.keys().map(String::as_str)));
+                for (key, value) in fields {
+                    match value {
+                        serde_json::Value::String(text) => book.set(key, text),
+                        value => book.set(key, &value.to_string()),
+                    }
+                }
+            }
+            Rows::Parquet(rows) => {
+                let Some(row) = rows.next().transpose().map_err(io::Error::other)? else {
+                    return Ok(None);
+                };
+                for (key, value) in row.get_column_iter() {
+                    book.set(key, &value.to_string());
+                }
+            }
+        }
+        self.row += 1;
+        Ok(Some(book))
+    }
+
+    // Timestamp of the next row, None at the end
+    fn peek(&mut self) -> io::Result<Option<u64>> {
+        if self.pending.is_none() {
+            self.pending = self.read_row()?.map(|book| (self.row, book));
+        }
+        Ok(self.pending.as_ref().map(|(_, book)| book.timestamp))
+    }
+
+    // Move to the last row at `timestamp`
+    fn advance(&mut self, timestamp: u64) -> io::Result<()> {
+        while self.peek()? == Some(timestamp) {
+            (self.book_row, self.book) = self.pending.take().unwrap_or_default();
+        }
+        if let Some(next) = self.peek()?
+            && next < timestamp {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Row {} of {} goes back in time", self.row, self.path.display())));
+        }
+        Ok(())
+    }
+}
+
+// A level that differs between the books: side, level (1 for the best) and each book's (price, shares)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelDiff {
+    pub side: &'static str,
+    pub level: usize,
+    pub left: (u32, u32),
+    pub right: (u32, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub timestamp: u64,
+    // Row numbers of the books compared, 0 before a file's first row
+    pub left_row: usize,
+    pub right_row: usize,
+    pub levels: Vec<LevelDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    // Timestamps compared, up to and including the divergence
+    pub timestamps: u64,
+    pub depth: usize,
+    pub divergence: Option<Divergence>,
+}
+
+fn diff_levels(left: &Book, right: &Book, depth: usize) -> Vec<LevelDiff> {
+    let mut diffs = Vec::new();
+    for (side, left_levels, right_levels) in [("bid", &left.bids, &right.bids), ("ask", &left.asks, &right.asks)] {
+        for index in 0..depth {
+            let (left, right) = (Book::level(left_levels, index), Book::level(right_levels, index));
+            if left != right {
+                diffs.push(LevelDiff { side, level: index + 1, left, right });
+            }
+        }
+    }
+    diffs
+}
+
+// Walk both files in timestamp order up to the first timestamp where the books differ in their top `depth`
+// levels, or in as many levels as both files have
+pub fn compare(left: &Path, right: &Path, depth: Option<usize>) -> io::Result<Comparison> {
+    let (mut left, mut right) = (Input::open(left)?, Input::open(right)?);
+    let mut timestamps = 0;
+    loop {
+        let timestamp = match (left.peek()?, right.peek()?) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(t), None) | (None, Some(t)) => t,
+            (None, None) => break,
+        };
+        left.advance(timestamp)?;
+        right.advance(timestamp)?;
+        timestamps += 1;
+        let depth = depth.unwrap_or(usize::MAX).min(left.levels).min(right.levels);
+        let levels = diff_levels(&left.book, &right.book, depth);
+        if !levels.is_empty() {
+            let divergence = Divergence { timestamp, left_row: left.book_row, right_row: right.book_row, levels };
+            return Ok(Comparison { timestamps, depth, divergence: Some(divergence) });
+        }
+    }
+    let depth = depth.unwrap_or(usize::MAX).min(left.levels).min(right.levels);
+    Ok(Comparison { timestamps, depth, divergence: None })
+}
+
+fn format_level((price, shares): (u32, u32)) -> String {
+    if shares == 0 { "-".to_string() } else { format!("{}.{:04} x {}", price / 10000, price % 10000, shares) }
+}
+
+// Compare the files and print where they diverge; a divergence is an error, for scripts
+pub fn run(args: CompareArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let comparison = compare(&args.left, &args.right, args.depth.map(usize::from))?;
+    let (left, right) = (args.left.display().to_string(), args.right.display().to_string());
+    let Some(divergence) = comparison.divergence else {
+        println!("The books match at all {} timestamps, over {} levels per side", comparison.timestamps, comparison.depth);
+        return Ok(());
+    };
+
+    println!("The books diverge at {} (timestamp {}), after {} matching timestamps",
+             format_time_of_day(divergence.timestamp), divergence.timestamp, comparison.timestamps - 1);
+    println!("Row {} of {} and row {} of {}", divergence.left_row, left, divergence.right_row, right);
+    println!();
+    let width = left.len().max(right.len()).max(20);
+    println!("{:<6}{:>6}  {:>width$}  {:>width$}", "Side", "Level", left, right);
+    for diff in &divergence.levels {
+        println!("{:<6}{:>6}  {:>width$}  {:>width$}", diff.side, diff.level, format_level(diff.left), format_level(diff.right));
+    }
+    Err(format!("The books diverge at timestamp {}", divergence.timestamp).into())
+}
@@ -0,0 +1,130 @@
+// Gzip and zstd compression of the CSV and JSONL output files, applied by the writer under the sink
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    // Level 0 (none) to 9 (best)
+    Gzip(u32),
+    // Level 1 (fastest) to 22 (best)
+    Zstd(i32),
+}
+
+const GZIP_DEFAULT_LEVEL: u32 = 6;
+const ZSTD_DEFAULT_LEVEL: i32 = 3;
+
+impl Compression {
+    // Parse gzip or zstd with an optional level, like "zstd:9"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid compression \"{}\", expected gzip[:0-9] or zstd[:1-22]", text);
+        let (name, level) = match text.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (text, None),
+        };
+        match name {
+            "gzip" => match level.map(str::parse::<u32>) {
+                None => Ok(Compression::Gzip(GZIP_DEFAULT_LEVEL)),
+                Some(Ok(level)) if level <= 9 => Ok(Compression::Gzip(level)),
+                _ => Err(invalid()),
+            },
+            "zstd" => match level.map(str::parse::<i32>) {
+                None => Ok(Compression::Zstd(ZSTD_DEFAULT_LEVEL)),
+                Some(Ok(level)) if (1..=22).contains(&level) => Ok(Compression::Zstd(level)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip(_) => "gz",
+            Compression::Zstd(_) => "zst",
+        }
+    }
+}
+
+// Path the output is written to: compressed files get the compression's extension (AAPL.csv -> AAPL.csv.gz)
+// unless the path already ends with it
+pub fn output_path(path: &Path, compression: Option<Compression>) -> PathBuf {
+    match compression {
+        Some(compression) if path.extension().is_none_or(|extension| extension != compression.extension()) => {
+            let mut path = path.as_os_str().to_owned();
+            path.push(".");
+            path.push(compression.extension());
+            PathBuf::from(path)
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+// Read a file written earlier, decompressed by its extension (.gz or .zst)
+pub fn open_reader(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(match path.extension().and_then(|extension| extension.to_str()) {
+        #[cfg(feature = "native")]
+        Some("gz") => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))),
+        #[cfg(feature = "native")]
+        Some("zst") => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        _ => Box::new(BufReader::new(file)),
+    })
+}
+
+// The output file, compressed or not
+pub enum OutputWriter {
+    Plain(File),
+    #[cfg(feature = "native")]
+    Gzip(flate2::write::GzEncoder<File>),
+    #[cfg(feature = "native")]
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl OutputWriter {
+    pub fn create(path: &Path, compression: Option<Compression>) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(match compression {
+            None => OutputWriter::Plain(file),
+            #[cfg(feature = "native")]
+            Some(Compression::Gzip(level)) => OutputWriter::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::new(level))),
+            #[cfg(feature = "native")]
+            Some(Compression::Zstd(level)) => OutputWriter::Zstd(zstd::Encoder::new(file, level)?),
+            #[cfg(not(feature = "native"))]
+            Some(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "Compressed output needs the native feature")),
+        })
+    }
+
+    // Write the end of the compressed stream; the file is complete after this
+    pub fn finish(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(file) => file.flush(),
+            #[cfg(feature = "native")]
+            OutputWriter::Gzip(encoder) => encoder.try_finish(),
+            #[cfg(feature = "native")]
+            OutputWriter::Zstd(encoder) => encoder.do_finish(),
+        }
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(file) => file.write(buf),
+            #[cfg(feature = "native")]
+            OutputWriter::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "native")]
+            OutputWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(file) => file.flush(),
+            #[cfg(feature = "native")]
+            OutputWriter::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "native")]
+            OutputWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::ffi::OsString;
+use std::fs;
+
+use clap::Command;
+use toml::{Table, Value};
+
+// Key naming the subcommand to run when none is given on the command line
+const COMMAND_KEY: &str = "command";
+
+// Find the value of --config on the raw command line
+fn config_path(args: &[OsString]) -> Option<OsString> {
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let text = arg.to_string_lossy();
+        if text == "--config" {
+            return iter.next().cloned();
+        }
+        if let Some(path) = text.strip_prefix("--config=") {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+// Whether an option (by long and short name) is given on the command line
+fn on_command_line(args: &[OsString], long: &str, short: Option<char>) -> bool {
+    let long = format!("--{}", long);
+    let short = short.map(|c| format!("-{}", c));
+    args.iter().any(|arg| {
+        let arg = arg.to_string_lossy();
+        arg == long || arg.starts_with(&format!("{}=", long))
+            || short.as_ref().is_some_and(|short| !arg.starts_with("--") && arg.starts_with(short.as_str()))
+    })
+}
+
+// Command line tokens for one option; None if the value can't be an option value
+fn option_tokens(name: &str, value: &Value) -> Option<Vec<OsString>> {
+    let flag = OsString::from(format!("--{}", name));
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Integer(i) => Some(i.to_string()),
+        Value::Float(f) => Some(f.to_string()),
+        _ => None,
+    };
+    match value {
+        Value::Boolean(true) => Some(vec![flag]),
+        Value::Boolean(false) => Some(Vec::new()),
+        // Lists are passed comma separated, like --symbols AAPL,MSFT
+        Value::Array(items) => {
+            let items: Option<Vec<String>> = items.iter().map(scalar).collect();
+            Some(vec![flag, items?.join(",").into()])
+        }
+        _ => Some(vec![flag, scalar(value)?.into()]),
+    }
+}
+
+// Insert the options of a TOML run file into the command line, right after the subcommand.
+// Top-level keys apply to every subcommand that has that option and a [subcommand] table
+// to that one only, overriding them; options given on the command line win over both.
+pub fn merge_config(command: &Command, args: Vec<OsString>) -> Result<Vec<OsString>, String> {
+    let Some(path) = config_path(&args) else {
+        return Ok(args);
+    };
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Can't read config file {}: {}", path.to_string_lossy(), e))?;
+    let table: Table = text.parse()
+        .map_err(|e| format!("Invalid config file {}: {}", path.to_string_lossy(), e))?;
+
+    let subcommand_at = args.iter().enumerate().skip(1)
+        .find(|(i, arg)| args[i - 1] != "--config" && command.find_subcommand(arg).is_some())
+        .map(|(i, _)| i);
+    let (mut merged, rest, name) = match subcommand_at {
+        Some(i) => (args[..=i].to_vec(), args[i + 1..].to_vec(), args[i].to_string_lossy().into_owned()),
+        None => {
+            let Some(Value::String(name)) = table.get(COMMAND_KEY) else {
+                // Let clap report the missing subcommand
+                return Ok(args);
+            };
+            let mut merged = args.clone();
+            merged.push(name.into());
+            (merged, Vec::new(), name.clone())
+        }
+    };
+    let Some(subcommand) = command.find_subcommand(&name) else {
+        return Err(format!("Unknown command \"{}\" in config file", name));
+    };
+    // Global options like --log-format are accepted after the subcommand too
+    let options: Vec<(&str, Option<char>)> = subcommand.get_arguments()
+        .chain(command.get_arguments().filter(|arg| arg.is_global_set()))
+        .filter_map(|arg| Some((arg.get_long()?, arg.get_short())))
+        .collect();
+
+    // The subcommand's own table wins over the shared keys
+    let mut values: Vec<(String, &Value)> = Vec::new();
+    let shared = table.iter()
+        .filter(|(key, _)| key.as_str() != COMMAND_KEY && command.find_subcommand(key.as_str()).is_none());
+    for (key, value) in shared {
+        values.push((key.replace('_', "-"), value));
+    }
+    if let Some(value) = table.get(&name) {
+        let Value::Table(section) = value else {
+            return Err(format!("\"{}\" in config file must be a table", name));
+        };
+        for (key, value) in section {
+            let key = key.replace('_', "-");
+            if !options.iter().any(|(long, _)| *long == key) {
+                return Err(format!("Unknown option \"{}\" for {} in config file", key, name));
+            }
+            values.retain(|(shared, _)| *shared != key);
+            values.push((key, value));
+        }
+    }
+
+    for (key, value) in values {
+        let Some(&(long, short)) = options.iter().find(|(long, _)| *long == key) else {
+            continue;
+        };
+        if on_command_line(&rest, long, short) {
+            continue;
+        }
+        merged.extend(option_tokens(&key, value).ok_or_else(|| format!("Invalid value for \"{}\" in config file", key))?);
+    }
+
+    merged.extend(rest);
+    Ok(merged)
+}
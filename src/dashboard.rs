@@ -0,0 +1,87 @@
+// A live dashboard served by the WebSocket server itself: a plain HTTP GET / (no Upgrade header) gets a static
+// page that connects back to the same address and draws the depth ladder and a mid-price chart of the updates.
+// The request head is read before the WebSocket handshake and replayed to it, so both share the port (and TLS)
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+const PAGE: &str = include_str!("dashboard.html");
+
+// Largest request head read before giving up on it
+const MAX_HEAD: usize = 16 * 1024;
+const HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A stream whose first bytes were already read, and are read again before the rest
+pub struct Replayed<S> {
+    head: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Replayed<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.position < self.head.len() {
+            let count = buf.remaining().min(self.head.len() - self.position);
+            let start = self.position;
+            buf.put_slice(&self.head[start..start + count]);
+            self.position += count;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Replayed<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+// Read the request head; WebSocket upgrades come back to be handshaken, anything else is answered here
+pub async fn route<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) -> io::Result<Option<Replayed<S>>> {
+    let mut head = Vec::new();
+    let read = tokio::time::timeout(HEAD_TIMEOUT, async {
+        let mut chunk = [0u8; 2048];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_HEAD {
+            let count = stream.read(&mut chunk).await?;
+            if count == 0 {
+                break;
+            }
+            head.extend_from_slice(&chunk[..count]);
+        }
+        io::Result::Ok(())
+    }).await;
+    read.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "No request"))??;
+
+    let text = String::from_utf8_lossy(&head);
+    let mut lines = text.lines();
+    let request_line = lines.next().unwrap_or("");
+    let upgrade = lines.filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| name.trim().eq_ignore_ascii_case("upgrade") && value.trim().eq_ignore_ascii_case("websocket"));
+    if upgrade {
+        return Ok(Some(Replayed { head, position: 0, inner: stream }));
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let path = path.split('?').next().unwrap_or("");
+    let response = match (method, path) {
+        ("GET", "/" | "/index.html") => format!("HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", PAGE.len(), PAGE),
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(None)
+}
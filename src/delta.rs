@@ -0,0 +1,111 @@
+// The `?format=delta` protocol of the WebSocket server: a full snapshot of a symbol's levels first, then only
+// the levels that changed, as exchange feeds do. Every message of a symbol carries the next sequence number,
+// so a client can tell it missed one, and a full snapshot is sent again every so many deltas. Deltas are
+// worked out against what the client was last sent, so dropped or conflated updates never leave it with a
+// wrong book
+//
+//   {"type":"snapshot","symbol":"AAPL","seq":1,"timestamp":"34200000308110","bids":[[100.4200,500]],"asks":[[100.4300,700]]}
+//   {"type":"delta","symbol":"AAPL","seq":2,"timestamp":"34200000500417","changes":[{"side":"ask","action":"add","price":100.4600,"volume":300}]}
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::orderbook::{BookSnapshot, Levels, PriceLevel};
+
+// Deltas between full snapshots of a symbol by default
+pub const DEFAULT_RESNAPSHOT_INTERVAL: u64 = 1000;
+
+struct SymbolState {
+    bids: Levels,
+    asks: Levels,
+    seq: u64,
+    // Deltas since the last full snapshot, None until one is sent
+    deltas: Option<u64>,
+}
+
+pub struct DeltaEncoder {
+    symbols: HashMap<Arc<str>, SymbolState>,
+    // 0 never sends a snapshot again
+    resnapshot_every: u64,
+}
+
+impl DeltaEncoder {
+    pub fn new(resnapshot_every: u64) -> Self {
+        DeltaEncoder { symbols: HashMap::new(), resnapshot_every }
+    }
+
+    // Send a full snapshot of every symbol next, e.g. after the client changed its subscription
+    pub fn resnapshot(&mut self) {
+        for state in self.symbols.values_mut() {
+            state.deltas = None;
+        }
+    }
+
+    // The message for a new snapshot of a symbol, None when none of its levels changed
+    pub fn encode(&mut self, symbol: &Arc<str>, snapshot: &BookSnapshot) -> Option<String> {
+        let state = self.symbols.entry(symbol.clone())
+            .or_insert_with(|| SymbolState { bids: Levels::default(), asks: Levels::default(), seq: 0, deltas: None });
+        let full = match state.deltas {
+            None => true,
+            Some(deltas) => self.resnapshot_every > 0 && deltas >= self.resnapshot_every,
+        };
+
+        let json = if full {
+            let mut json = format!("{{\"type\":\"snapshot\",\"symbol\":\"{}\",\"seq\":{},\"timestamp\":\"{}\",\"bids\":", symbol, state.seq + 1, snapshot.timestamp);
+            write_levels(&mut json, &snapshot.bid_levels);
+            json.push_str(",\"asks\":");
+            write_levels(&mut json, &snapshot.ask_levels);
+            json.push('}');
+            state.deltas = Some(0);
+            json
+        } else {
+            let mut changes = String::new();
+            write_changes(&mut changes, "bid", &state.bids, &snapshot.bid_levels);
+            write_changes(&mut changes, "ask", &state.asks, &snapshot.ask_levels);
+            if changes.is_empty() {
+                return None;
+            }
+            state.deltas = state.deltas.map(|deltas| deltas + 1);
+            format!("{{\"type\":\"delta\",\"symbol\":\"{}\",\"seq\":{},\"timestamp\":\"{}\",\"changes\":[{}]}}", symbol, state.seq + 1, snapshot.timestamp, changes)
+        };
+        state.seq += 1;
+        state.bids = snapshot.bid_levels;
+        state.asks = snapshot.ask_levels;
+        Some(json)
+    }
+}
+
+fn write_levels(json: &mut String, levels: &[PriceLevel]) {
+    json.push('[');
+    for (index, level) in levels.iter().enumerate() {
+        let separator = if index > 0 { "," } else { "" };
+        let _ = write!(json, "{}[{:.4},{}]", separator, level.price as f64 / 10000.0, level.total_volume);
+    }
+    json.push(']');
+}
+
+// Levels of a side that went away, then those that are new or changed volume, best first
+fn write_changes(changes: &mut String, side: &str, old: &[PriceLevel], new: &[PriceLevel]) {
+    for level in old.iter().filter(|level| !new.iter().any(|other| other.price == level.price)) {
+        push_change(changes, side, "delete", level.price, None);
+    }
+    for level in new {
+        match old.iter().find(|other| other.price == level.price) {
+            None => push_change(changes, side, "add", level.price, Some(level.total_volume)),
+            Some(other) if other.total_volume != level.total_volume => push_change(changes, side, "update", level.price, Some(level.total_volume)),
+            Some(_) => {}
+        }
+    }
+}
+
+fn push_change(changes: &mut String, side: &str, action: &str, price: u32, volume: Option<u32>) {
+    if !changes.is_empty() {
+        changes.push(',');
+    }
+    let _ = write!(changes, "{{\"side\":\"{}\",\"action\":\"{}\",\"price\":{:.4}", side, action, price as f64 / 10000.0);
+    if let Some(volume) = volume {
+        let _ = write!(changes, ",\"volume\":{}", volume);
+    }
+    changes.push('}');
+}
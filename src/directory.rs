@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::ControlFlow;
+
+use crate::cli::{DirectoryArgs, ListSymbolsArgs};
+use crate::file_io;
+use crate::message_types::MessageType;
+use crate::parser;
+use crate::wire::{self, MessageView};
+
+// Format of the directory export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DirectoryFormat {
+    // One row per instrument
+    #[default]
+    Csv,
+    // One JSON object per instrument per line
+    Json,
+}
+
+// Reference data of one instrument from its Stock Directory message
+#[derive(Debug, Clone)]
+pub struct DirectoryEntry {
+    pub stock_locate: u16,
+    pub symbol: String,
+    pub market_category: char,
+    pub financial_status: char,
+    pub round_lot_size: u32,
+    pub round_lots_only: bool,
+    pub issue_classification: char,
+    pub issue_subtype: String,
+    pub authenticity: char,
+    pub short_sale_threshold: char,
+    pub ipo_flag: char,
+    pub luld_tier: char,
+    pub etp_flag: char,
+    pub etp_leverage_factor: u32,
+    pub inverse: bool,
+}
+
+impl DirectoryEntry {
+    // Decode a Stock Directory message (without its type byte), None if it is too short
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let message = wire::StockDirectory::view(data)?;
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).trim().to_string();
+        Some(DirectoryEntry {
+            stock_locate: message.header.stock_locate.get(),
+            symbol: text(&message.stock),
+            market_category: message.market_category as char,
+            financial_status: message.financial_status_indicator as char,
+            round_lot_size: message.round_lot_size.get(),
+            round_lots_only: message.round_lots_only == b'Y',
+            issue_classification: message.issue_classification as char,
+            issue_subtype: text(&message.issue_sub_type),
+            authenticity: message.authenticity as char,
+            short_sale_threshold: message.short_sale_threshold_indicator as char,
+            ipo_flag: message.ipo_flag as char,
+            luld_tier: message.luld_reference_price_tier as char,
+            etp_flag: message.etp_flag as char,
+            etp_leverage_factor: message.etp_leverage_factor.get(),
+            inverse: message.inverse_indicator == b'Y',
+        })
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "stock_locate": self.stock_locate,
+            "symbol": self.symbol,
+            "market_category": self.market_category.to_string(),
+            "financial_status": self.financial_status.to_string(),
+            "round_lot_size": self.round_lot_size,
+            "round_lots_only": self.round_lots_only,
+            "issue_classification": self.issue_classification.to_string(),
+            "issue_subtype": self.issue_subtype,
+            "authenticity": self.authenticity.to_string(),
+            "short_sale_threshold": self.short_sale_threshold.to_string(),
+            "ipo_flag": self.ipo_flag.to_string(),
+            "luld_tier": self.luld_tier.to_string(),
+            "etp_flag": self.etp_flag.to_string(),
+            "etp_leverage_factor": self.etp_leverage_factor,
+            "inverse": self.inverse,
+        })
+    }
+}
+
+// Every instrument of the file by locate code; a later directory message for a locate replaces the earlier one.
+// The directory is sent before the first order, so unless whole_file is set the scan stops there
+pub fn read_directory(data: &[u8], whole_file: bool) -> BTreeMap<u16, DirectoryEntry> {
+    let mut directory = BTreeMap::new();
+    parser::scan_messages(data, |message_type, message| {
+        match message_type {
+            MessageType::StockDirectory => {
+                if let Some(entry) = DirectoryEntry::parse(message) {
+                    directory.insert(entry.stock_locate, entry);
+                }
+            }
+            MessageType::AddOrder | MessageType::AddOrderWithMpid if !whole_file => return ControlFlow::Break(()),
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    });
+    directory
+}
+
+fn write_csv(out: &mut impl Write, directory: &BTreeMap<u16, DirectoryEntry>) -> io::Result<()> {
+    out.write_all(b"stock_locate,symbol,market_category,financial_status,round_lot_size,round_lots_only,issue_classification,\
+issue_subtype,authenticity,short_sale_threshold,ipo_flag,luld_tier,etp_flag,etp_leverage_factor,inverse\n")?;
+    let flag = |set: bool| if set { "Y" } else { "N" };
+    for e in directory.values() {
+        writeln!(out, "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}", e.stock_locate, e.symbol, e.market_category,
+                 e.financial_status, e.round_lot_size, flag(e.round_lots_only), e.issue_classification, e.issue_subtype,
+                 e.authenticity, e.short_sale_threshold, e.ipo_flag, e.luld_tier, e.etp_flag, e.etp_leverage_factor,
+                 flag(e.inverse))?;
+    }
+    Ok(())
+}
+
+// Write the reference data of every instrument in the file
+pub fn run(args: DirectoryArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = file_io::load_input(&args.file, args.encap)?;
+    let directory = read_directory(&input, true);
+
+    let mut out = BufWriter::new(File::create(&args.output_file)?);
+    match args.format {
+        DirectoryFormat::Csv => write_csv(&mut out, &directory)?,
+        DirectoryFormat::Json => {
+            for entry in directory.values() {
+                writeln!(out, "{}", entry.to_json())?;
+            }
+        }
+    }
+    out.flush()?;
+    tracing::info!("Wrote {} instruments to {}", directory.len(), args.output_file.display());
+    Ok(())
+}
+
+// Print the symbols of the file with their locate codes and round lot sizes
+pub fn list_symbols(args: ListSymbolsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = file_io::load_input(&args.file, args.encap)?;
+    let directory = read_directory(&input, args.whole_file);
+
+    println!("{:<8}{:<10}{:>10}", "Locate", "Symbol", "Round lot");
+    for entry in directory.values() {
+        println!("{:<8}{:<10}{:>10}", entry.stock_locate, entry.symbol, entry.round_lot_size);
+    }
+    println!("{} symbols", directory.len());
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+
+// Log mid return and realized volatility over one window of market time
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WindowStats {
+    // Log return from the last mid at or before the window start (or the first mid seen) to now
+    pub log_return: f64,
+    // Square root of the sum of squared mid-to-mid log returns inside the window
+    pub volatility: f64,
+}
+
+// Mid samples of one window, kept online: the front sample is the base at or before the
+// window start and the squared returns of the samples after it are summed
+#[derive(Debug)]
+struct RollingWindow {
+    window: u64,
+    // (timestamp, log mid, squared log return from the previous sample)
+    samples: VecDeque<(u64, f64, f64)>,
+    sum_squares: f64,
+}
+
+impl RollingWindow {
+    fn new(window: u64) -> Self {
+        RollingWindow { window, samples: VecDeque::new(), sum_squares: 0.0 }
+    }
+
+    fn push(&mut self, timestamp: u64, log_mid: f64, squared_return: f64) {
+        if !self.samples.is_empty() {
+            self.sum_squares += squared_return;
+        }
+        self.samples.push_back((timestamp, log_mid, squared_return));
+    }
+
+    // Drop the samples that fell out of the window ending at `timestamp`
+    fn advance(&mut self, timestamp: u64) {
+        let start = timestamp.saturating_sub(self.window);
+        while self.samples.len() >= 2 && self.samples[1].0 <= start {
+            self.samples.pop_front();
+            // The new base's return happened before the window
+            self.sum_squares -= self.samples[0].2;
+        }
+        // Recompute when empty so rounding from the running sum can't build up
+        if self.samples.len() == 1 {
+            self.sum_squares = 0.0;
+        }
+    }
+
+    fn stats(&self, log_mid: f64) -> WindowStats {
+        let Some(&(_, base, _)) = self.samples.front() else {
+            return WindowStats::default();
+        };
+        WindowStats { log_return: log_mid - base, volatility: self.sum_squares.max(0.0).sqrt() }
+    }
+}
+
+// Online log returns and realized volatility of the mid price over several windows
+#[derive(Debug)]
+pub struct MidEstimators {
+    windows: Vec<RollingWindow>,
+    last_log_mid: Option<f64>,
+}
+
+impl MidEstimators {
+    pub fn new(windows: &[u64]) -> Self {
+        MidEstimators { windows: windows.iter().map(|&w| RollingWindow::new(w)).collect(), last_log_mid: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    // Feed the mid (in price units) after a book update; None while either side is empty.
+    // Only changes of the mid are samples, so repeated mids don't dilute the windows
+    pub fn update(&mut self, timestamp: u64, mid: Option<f64>) {
+        if let Some(mid) = mid {
+            let log_mid = mid.ln();
+            let squared_return = self.last_log_mid.map_or(0.0, |last| (log_mid - last).powi(2));
+            if self.last_log_mid != Some(log_mid) {
+                self.last_log_mid = Some(log_mid);
+                for window in &mut self.windows {
+                    window.push(timestamp, log_mid, squared_return);
+                }
+            }
+        }
+        for window in &mut self.windows {
+            window.advance(timestamp);
+        }
+    }
+
+    // Stats of every window at the last update, in the order the windows were given
+    pub fn stats(&self) -> Vec<WindowStats> {
+        let Some(log_mid) = self.last_log_mid else {
+            return vec![WindowStats::default(); self.windows.len()];
+        };
+        self.windows.iter().map(|window| window.stats(log_mid)).collect()
+    }
+}
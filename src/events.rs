@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use zerocopy::byteorder::big_endian::{U32, U64};
+
+use crate::message_types::MessageType;
+use crate::utils::format_time_of_day;
+use crate::wire::{self, ItchMessage};
+
+// Alphanumeric field without its space padding
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim().to_string()
+}
+
+fn flag(byte: u8) -> String {
+    text(&[byte])
+}
+
+// Price(4) in dollars
+fn price(price: U32) -> String {
+    let price = price.get();
+    format!("{}.{:04}", price / 10000, price % 10000)
+}
+
+// Price(8) in dollars
+fn price8(price: U64) -> String {
+    let price = price.get();
+    format!("{}.{:08}", price / 100_000_000, price % 100_000_000)
+}
+
+// Event name and space separated key=value fields of an administrative message, None for other messages
+fn describe(message: &ItchMessage) -> Option<(&'static str, String)> {
+    let decoded = match message {
+        ItchMessage::SystemEvent(m) => {
+            let event = match m.event_code {
+                b'O' => "start_of_messages",
+                b'S' => "start_of_system_hours",
+                b'Q' => "start_of_market_hours",
+                b'M' => "end_of_market_hours",
+                b'E' => "end_of_system_hours",
+                b'C' => "end_of_messages",
+                _ => "unknown_system_event",
+            };
+            (event, String::new())
+        }
+        ItchMessage::StockDirectory(m) => ("stock_directory", format!(
+            "market_category={} financial_status={} round_lot_size={} round_lots_only={} issue_classification={} issue_subtype={} \
+             authenticity={} short_sale_threshold={} ipo_flag={} luld_tier={} etp_flag={} etp_leverage_factor={} inverse={}",
+            flag(m.market_category), flag(m.financial_status_indicator), m.round_lot_size, flag(m.round_lots_only),
+            flag(m.issue_classification), text(&m.issue_sub_type), flag(m.authenticity), flag(m.short_sale_threshold_indicator),
+            flag(m.ipo_flag), flag(m.luld_reference_price_tier), flag(m.etp_flag), m.etp_leverage_factor, flag(m.inverse_indicator))),
+        ItchMessage::StockTradingAction(m) => {
+            let event = match m.trading_state {
+                b'H' => "halted",
+                b'P' => "paused",
+                b'Q' => "quotation_only",
+                b'T' => "trading",
+                _ => "unknown_trading_state",
+            };
+            (event, format!("reason={}", text(&m.reason)))
+        }
+        ItchMessage::RegShoRestriction(m) => {
+            let event = match m.reg_sho_action {
+                b'0' => "reg_sho_no_restriction",
+                b'1' => "reg_sho_restriction_intraday_drop",
+                b'2' => "reg_sho_restriction_remains",
+                _ => "reg_sho_unknown",
+            };
+            (event, String::new())
+        }
+        ItchMessage::MarketParticipantPosition(m) => ("market_participant_position", format!(
+            "mpid={} primary_market_maker={} market_maker_mode={} participant_state={}",
+            text(&m.mpid), flag(m.primary_market_maker), flag(m.market_maker_mode), flag(m.market_participant_state))),
+        ItchMessage::MwcbDeclineLevel(m) => ("mwcb_decline_levels", format!(
+            "level_1={} level_2={} level_3={}", price8(m.level_1), price8(m.level_2), price8(m.level_3))),
+        ItchMessage::MwcbStatus(m) => ("mwcb_breach", format!("level={}", flag(m.breached_level))),
+        ItchMessage::IpoQuotingPeriodUpdate(m) => {
+            let qualifier = match m.ipo_quotation_release_qualifier {
+                b'A' => "anticipated",
+                b'C' => "canceled",
+                _ => "unknown",
+            };
+            ("ipo_quoting_period_update", format!("release_time={} qualifier={} ipo_price={}",
+                format_time_of_day(m.ipo_quotation_release_time.get() as u64 * 1_000_000_000), qualifier, price(m.ipo_price)))
+        }
+        ItchMessage::LuldAuctionCollar(m) => ("luld_auction_collar", format!(
+            "reference_price={} upper_collar={} lower_collar={} extension={}",
+            price(m.auction_collar_reference_price), price(m.upper_auction_collar_price), price(m.lower_auction_collar_price),
+            m.auction_collar_extension)),
+        ItchMessage::OperationalHalt(m) => {
+            let event = if m.operational_halt_action == b'H' { "operational_halt" } else { "operational_halt_resumed" };
+            (event, format!("market={}", flag(m.market_code)))
+        }
+        ItchMessage::DirectListingWithCapitalRaisePriceDiscovery(m) => ("direct_listing_price_discovery", format!(
+            "open_eligible={} min_price={} max_price={} near_execution_price={} near_execution_time={} lower_collar={} upper_collar={}",
+            flag(m.open_eligibility_status), price(m.minimum_allowable_price), price(m.maximum_allowable_price),
+            price(m.near_execution_price), format_time_of_day(m.near_execution_time.get()), price(m.lower_price_range_collar),
+            price(m.upper_price_range_collar))),
+        _ => return None,
+    };
+    Some(decoded)
+}
+
+// Writes every administrative message of the file (all symbols) with its decoded fields to a CSV file
+pub struct EventLog {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    count: u64,
+}
+
+impl EventLog {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"timestamp,message_type,stock_locate,symbol,event,fields\n")?;
+        Ok(EventLog { path: path.to_path_buf(), writer, count: 0 })
+    }
+
+    // Record one message (without its type byte) if it is an administrative one
+    pub fn observe(&mut self, message_type: MessageType, data: &[u8]) -> io::Result<()> {
+        let Some(message) = wire::decode_body(message_type, data) else {
+            return Ok(());
+        };
+        let (Some((event, fields)), Some(header)) = (describe(&message), message.header()) else {
+            return Ok(());
+        };
+        let symbol = message.stock().map(|stock| text(stock)).unwrap_or_default();
+        self.count += 1;
+        writeln!(self.writer, "{},{},{},{},{},{}", header.timestamp.get(), message_type as u8 as char, header.stock_locate,
+                 symbol, event, fields)
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        tracing::info!("Wrote {} events to {}", self.count, self.path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+use std::fmt;
+
+use crate::orderbook::{BookSnapshot, PriceLevel, HALT_MARKETS, MAX_BOOK_DEPTH};
+use crate::timestamps::Timestamps;
+use crate::utils::format_duration;
+
+// Optional computed columns of the snapshot output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum SnapshotFeature {
+    // Running volume weighted average trade price
+    Vwap,
+    // Cumulative traded shares and number of trades
+    Volume,
+    // Order flow imbalance at the top level since the previous row
+    Ofi,
+    // Size weighted mid price
+    Microprice,
+    // Bid/ask spread in ticks
+    SpreadTicks,
+    // Log mid return over each feature window
+    Returns,
+    // Realized volatility of the mid over each feature window
+    Volatility,
+    // Retail Price Improvement interest of the symbol (buy, sell, both or none)
+    RetailInterest,
+    // Reference price and collars of the symbol's latest LULD halt auction
+    Luld,
+    // Highest market wide circuit breaker level breached
+    Mwcb,
+    // Whether the Reg SHO short sale restriction is in effect (1) or not (0)
+    Ssr,
+    // Whether the symbol is operationally halted on each market
+    Halts,
+}
+
+// Type of a computed column in the typed outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    Integer,
+    Double,
+    Text,
+}
+
+impl SnapshotFeature {
+    // Column names and types; the window features have one column per window
+    fn columns(self, windows: &[u64]) -> Vec<(String, ColumnKind)> {
+        use ColumnKind::*;
+        let fixed = |names: &[(&str, ColumnKind)]| names.iter().map(|&(name, kind)| (name.to_string(), kind)).collect();
+        let per_window = |prefix: &str| windows.iter().map(|&w| (format!("{}_{}", prefix, format_duration(w)), Double)).collect();
+        match self {
+            SnapshotFeature::Vwap => fixed(&[("vwap", Double)]),
+            SnapshotFeature::Volume => fixed(&[("volume", Integer), ("trade_count", Integer)]),
+            SnapshotFeature::Ofi => fixed(&[("ofi", Integer)]),
+            SnapshotFeature::Microprice => fixed(&[("microprice", Double)]),
+            SnapshotFeature::SpreadTicks => fixed(&[("spread_ticks", Integer)]),
+            SnapshotFeature::Returns => per_window("return"),
+            SnapshotFeature::Volatility => per_window("volatility"),
+            SnapshotFeature::RetailInterest => fixed(&[("retail_interest", Text)]),
+            SnapshotFeature::Luld => fixed(&[("luld_reference_price", Double), ("luld_upper_collar", Double),
+                                             ("luld_lower_collar", Double), ("luld_extensions", Integer)]),
+            SnapshotFeature::Mwcb => fixed(&[("mwcb_level", Integer)]),
+            SnapshotFeature::Ssr => fixed(&[("short_sale_restricted", Integer)]),
+            SnapshotFeature::Halts => HALT_MARKETS.iter().map(|(_, name)| (format!("halted_{}", name.to_lowercase()), Integer)).collect(),
+        }
+    }
+
+    fn uses_windows(self) -> bool {
+        matches!(self, SnapshotFeature::Returns | SnapshotFeature::Volatility)
+    }
+}
+
+// How the book's prices are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PriceFormat {
+    // Dollars with 4 decimals
+    #[default]
+    Decimal,
+    // Integer 1/10000 dollars, as in the feed
+    Raw,
+    // Integer ticks of $0.01, or of $0.0001 below $1 (Reg NMS minimum increments)
+    Ticks,
+}
+
+impl PriceFormat {
+    // Column type in the typed outputs
+    pub fn kind(self) -> ColumnKind {
+        match self {
+            PriceFormat::Decimal => ColumnKind::Double,
+            PriceFormat::Raw | PriceFormat::Ticks => ColumnKind::Integer,
+        }
+    }
+
+    fn tick(price: u32) -> u32 {
+        if price >= 10000 { 100 } else { 1 }
+    }
+
+    // The raw or ticks value
+    pub fn integer(self, price: u32) -> u32 {
+        match self {
+            PriceFormat::Ticks => price / Self::tick(price),
+            _ => price,
+        }
+    }
+
+    // A spread, in ticks of the bid's increment
+    pub fn spread(self, spread: i64, bid: u32) -> i64 {
+        match self {
+            PriceFormat::Ticks => spread / Self::tick(bid) as i64,
+            _ => spread,
+        }
+    }
+
+    // The value of a typed column, exact for the integer formats
+    pub fn value(self, price: u32) -> f64 {
+        match self {
+            PriceFormat::Decimal => price as f64 / 10000.0,
+            format => format.integer(price) as f64,
+        }
+    }
+
+    // A price for JSON
+    pub fn json(self, price: u32) -> String {
+        match self {
+            PriceFormat::Decimal => format!("{:.4}", price as f64 / 10000.0),
+            format => format.integer(price).to_string(),
+        }
+    }
+}
+
+// One computed value, formatted like the book columns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeatureValue {
+    Int(i64),
+    // In dollars, written with 4 decimals like the prices
+    Price(f64),
+    // Small ratios such as returns, written with 8 decimals
+    Decimal(f64),
+    // A state name, quoted in JSON
+    Text(&'static str),
+}
+
+impl FeatureValue {
+    // NaN for text
+    pub fn as_f64(self) -> f64 {
+        match self {
+            FeatureValue::Int(value) => value as f64,
+            FeatureValue::Price(value) | FeatureValue::Decimal(value) => value,
+            FeatureValue::Text(_) => f64::NAN,
+        }
+    }
+
+    pub fn to_json(self) -> String {
+        match self {
+            FeatureValue::Text(text) => format!("\"{}\"", text),
+            value => value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FeatureValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FeatureValue::Int(value) => write!(f, "{}", value),
+            FeatureValue::Price(value) => write!(f, "{:.4}", value),
+            FeatureValue::Decimal(value) => write!(f, "{:.8}", value),
+            FeatureValue::Text(text) => f.write_str(text),
+        }
+    }
+}
+
+// Which computed columns a sink writes after the book columns, in declaration order, how it writes the
+// timestamp and the book's prices, which of all these columns the output file keeps, and the levels of a
+// LOBSTER orderbook file
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FeatureColumns {
+    features: Vec<SnapshotFeature>,
+    // Windows of the returns and volatility columns, in nanoseconds of market time
+    windows: Vec<u64>,
+    timestamps: Timestamps,
+    prices: PriceFormat,
+    // Names of the columns to write, in order; empty for all of them
+    selection: Vec<String>,
+    // Levels per side of the LOBSTER orderbook file, None for MAX_BOOK_DEPTH
+    levels: Option<usize>,
+}
+
+impl FeatureColumns {
+    pub fn new(features: &[SnapshotFeature], windows: &[u64]) -> Self {
+        let mut features = features.to_vec();
+        features.sort();
+        features.dedup();
+        FeatureColumns { features, windows: windows.to_vec(), ..Default::default() }
+    }
+
+    pub fn with_timestamps(self, timestamps: Timestamps) -> Self {
+        FeatureColumns { timestamps, ..self }
+    }
+
+    pub fn timestamps(&self) -> Timestamps {
+        self.timestamps
+    }
+
+    pub fn with_prices(self, prices: PriceFormat) -> Self {
+        FeatureColumns { prices, ..self }
+    }
+
+    pub fn prices(&self) -> PriceFormat {
+        self.prices
+    }
+
+    pub fn with_selection(self, selection: Vec<String>) -> Self {
+        FeatureColumns { selection, ..self }
+    }
+
+    pub fn selection(&self) -> &[String] {
+        &self.selection
+    }
+
+    pub fn with_levels(self, levels: usize) -> Self {
+        FeatureColumns { levels: Some(levels), ..self }
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.unwrap_or(MAX_BOOK_DEPTH)
+    }
+
+    // Windows the book has to estimate returns and volatility over; empty if no column needs them
+    pub fn estimator_windows(&self) -> Vec<u64> {
+        if self.features.iter().any(|feature| feature.uses_windows()) {
+            self.windows.clone()
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty() && !self.timestamps.has_sessions()
+    }
+
+    // (name, type) of every column, the session columns of --timezone last
+    pub fn names(&self) -> Vec<(String, ColumnKind)> {
+        let mut names: Vec<(String, ColumnKind)> = self.features.iter().flat_map(|feature| feature.columns(&self.windows)).collect();
+        if self.timestamps.has_sessions() {
+            names.push(("utc_timestamp".to_string(), ColumnKind::Integer));
+            names.push(("session".to_string(), ColumnKind::Text));
+        }
+        names
+    }
+
+    // Values of the columns for one snapshot, in the same order as the names
+    pub fn values(&self, snapshot: &BookSnapshot) -> Vec<FeatureValue> {
+        let mut values = Vec::with_capacity(self.features.len() + 2 * self.windows.len());
+        for feature in &self.features {
+            match feature {
+                SnapshotFeature::Vwap => values.push(FeatureValue::Price(snapshot.trades.vwap())),
+                SnapshotFeature::Volume => {
+                    values.push(FeatureValue::Int(snapshot.trades.volume as i64));
+                    values.push(FeatureValue::Int(snapshot.trades.trades as i64));
+                }
+                SnapshotFeature::Ofi => values.push(FeatureValue::Int(snapshot.ofi)),
+                SnapshotFeature::Microprice => values.push(FeatureValue::Price(microprice(snapshot))),
+                SnapshotFeature::SpreadTicks => values.push(FeatureValue::Int(spread_ticks(snapshot))),
+                // Zeros if the snapshot has no estimates, like a book built without windows
+                SnapshotFeature::Returns => values.extend((0..self.windows.len())
+                    .map(|i| FeatureValue::Decimal(snapshot.windows.get(i).map_or(0.0, |w| w.log_return)))),
+                SnapshotFeature::Volatility => values.extend((0..self.windows.len())
+                    .map(|i| FeatureValue::Decimal(snapshot.windows.get(i).map_or(0.0, |w| w.volatility)))),
+                SnapshotFeature::RetailInterest => values.push(FeatureValue::Text(snapshot.retail_interest.name())),
+                SnapshotFeature::Luld => {
+                    let luld = &snapshot.luld;
+                    for price in [luld.reference_price, luld.upper_price, luld.lower_price] {
+                        values.push(FeatureValue::Price(price as f64 / 10000.0));
+                    }
+                    values.push(FeatureValue::Int(luld.extensions as i64));
+                }
+                SnapshotFeature::Mwcb => values.push(FeatureValue::Int(snapshot.mwcb_level as i64)),
+                SnapshotFeature::Ssr => values.push(FeatureValue::Int(snapshot.short_sale_restricted as i64)),
+                SnapshotFeature::Halts => values.extend((0..HALT_MARKETS.len())
+                    .map(|i| FeatureValue::Int((snapshot.operational_halts >> i & 1) as i64))),
+            }
+        }
+        if let Some(utc) = self.timestamps.utc(snapshot.timestamp)
+            && self.timestamps.has_sessions() {
+            values.push(FeatureValue::Int(utc as i64));
+            values.push(FeatureValue::Text(snapshot.market_hours.session(snapshot.timestamp).name()));
+        }
+        values
+    }
+}
+
+// How the levels of the book are weighted in the orderbook imbalance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ImbalanceWeighting {
+    // Every level counts the same
+    #[default]
+    Flat,
+    // Level i (0 at the top) is weighted by exp(-decay * i)
+    Exponential,
+}
+
+// Weights of the orderbook imbalance column: a scheme, its decay and how many levels count
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImbalanceWeights {
+    pub weighting: ImbalanceWeighting,
+    pub decay: f64,
+    // Levels per side that count, from the top
+    pub levels: usize,
+}
+
+impl Default for ImbalanceWeights {
+    fn default() -> Self {
+        ImbalanceWeights { weighting: ImbalanceWeighting::Flat, decay: 0.5, levels: usize::MAX }
+    }
+}
+
+impl ImbalanceWeights {
+    fn weighted_volume(&self, levels: &[PriceLevel]) -> f64 {
+        levels.iter().take(self.levels).enumerate()
+            .map(|(i, level)| match self.weighting {
+                ImbalanceWeighting::Flat => level.total_volume as f64,
+                ImbalanceWeighting::Exponential => level.total_volume as f64 * (-self.decay * i as f64).exp(),
+            })
+            .sum()
+    }
+
+    // (bid volume - ask volume) / (bid volume + ask volume) over the weighted levels, 0 for an empty book
+    pub fn imbalance(&self, bids: &[PriceLevel], asks: &[PriceLevel]) -> f64 {
+        let bid_volume = self.weighted_volume(bids);
+        let ask_volume = self.weighted_volume(asks);
+        let total_volume = bid_volume + ask_volume;
+        if total_volume == 0.0 {
+            return 0.0;
+        }
+        (bid_volume - ask_volume) / total_volume
+    }
+}
+
+// Running totals of the symbol's printable executions and non-cross trades
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TradeStats {
+    pub volume: u64,
+    // Sum of price * shares, in price units
+    pub notional: u64,
+    pub trades: u64,
+}
+
+impl TradeStats {
+    pub fn add(&mut self, price: u32, shares: u32) {
+        self.volume += shares as u64;
+        self.notional += price as u64 * shares as u64;
+        self.trades += 1;
+    }
+
+    // Volume weighted average price in dollars, 0 before the first trade
+    pub fn vwap(&self) -> f64 {
+        if self.volume == 0 {
+            0.0
+        } else {
+            self.notional as f64 / self.volume as f64 / 10000.0
+        }
+    }
+}
+
+// Cont, Kukanov and Stoikov's order flow imbalance between two top of book states: bid size
+// added at or above the old best bid minus bid size removed at or below it, and the reverse
+// for the ask. An empty bid counts as price 0 and an empty ask as the highest price, size 0
+pub fn order_flow_imbalance(previous: (Option<&PriceLevel>, Option<&PriceLevel>), current: (Option<&PriceLevel>, Option<&PriceLevel>)) -> i64 {
+    let bid = |level: Option<&PriceLevel>| level.map_or((0, 0), |l| (l.price, l.total_volume as i64));
+    let ask = |level: Option<&PriceLevel>| level.map_or((u32::MAX, 0), |l| (l.price, l.total_volume as i64));
+    let (old_bid, old_bid_size) = bid(previous.0);
+    let (new_bid, new_bid_size) = bid(current.0);
+    let (old_ask, old_ask_size) = ask(previous.1);
+    let (new_ask, new_ask_size) = ask(current.1);
+
+    let mut ofi = 0;
+    if new_bid >= old_bid {
+        ofi += new_bid_size;
+    }
+    if new_bid <= old_bid {
+        ofi -= old_bid_size;
+    }
+    if new_ask <= old_ask {
+        ofi -= new_ask_size;
+    }
+    if new_ask >= old_ask {
+        ofi += old_ask_size;
+    }
+    ofi
+}
+
+// Mid weighted towards the side with less size, in dollars; 0 unless both sides have orders
+pub fn microprice(snapshot: &BookSnapshot) -> f64 {
+    let (Some(bid), Some(ask)) = (snapshot.bid_levels.first(), snapshot.ask_levels.first()) else {
+        return 0.0;
+    };
+    let size = bid.total_volume as f64 + ask.total_volume as f64;
+    if size == 0.0 {
+        return 0.0;
+    }
+    (bid.price as f64 * ask.total_volume as f64 + ask.price as f64 * bid.total_volume as f64) / size / 10000.0
+}
+
+// Spread in ticks of $0.01, or $0.0001 for stocks quoted below $1 (Reg NMS minimum increments)
+pub fn spread_ticks(snapshot: &BookSnapshot) -> i64 {
+    let (Some(bid), Some(ask)) = (snapshot.bid_levels.first(), snapshot.ask_levels.first()) else {
+        return 0;
+    };
+    let tick = if bid.price >= 10000 { 100 } else { 1 };
+    (ask.price as i64 - bid.price as i64) / tick
+}
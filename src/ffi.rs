@@ -0,0 +1,155 @@
+// C API (feature `ffi`) for embedding the book builder in other trading stacks. A reader maps a file and
+// builds the books of its symbols as snapshots are pulled from it; the header in include/ is generated
+// from this file by build.rs
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::io;
+use std::path::Path;
+use std::ptr;
+
+use crate::file_io::{self, InputData};
+use crate::orderbook::{BookSnapshot, MAX_BOOK_DEPTH};
+use crate::pcap::Encapsulation;
+use crate::replay::SnapshotReplay;
+use crate::utils::pad_stock_symbol;
+
+pub const ITCH_MAX_LEVELS: usize = 10;
+const _: () = assert!(ITCH_MAX_LEVELS == MAX_BOOK_DEPTH);
+
+/// Prices are in 1/10000 dollars
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ItchLevel {
+    pub price: u32,
+    pub volume: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ItchSnapshot {
+    /// Space padded, not NUL terminated
+    pub symbol: [u8; 8],
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    pub bid_count: u32,
+    pub ask_count: u32,
+    /// Best level first; only the first bid_count and ask_count are set
+    pub bids: [ItchLevel; ITCH_MAX_LEVELS],
+    pub asks: [ItchLevel; ITCH_MAX_LEVELS],
+    pub imbalance: f64,
+}
+
+impl ItchSnapshot {
+    fn new(symbol: [u8; 8], snapshot: &BookSnapshot) -> Self {
+        let mut out = ItchSnapshot {
+            symbol,
+            timestamp: snapshot.timestamp,
+            bid_count: snapshot.bid_levels.len().min(ITCH_MAX_LEVELS) as u32,
+            ask_count: snapshot.ask_levels.len().min(ITCH_MAX_LEVELS) as u32,
+            imbalance: snapshot.imbalance,
+            ..Default::default()
+        };
+        for (level, out) in snapshot.bid_levels.iter().zip(&mut out.bids) {
+            *out = ItchLevel { price: level.price, volume: level.total_volume };
+        }
+        for (level, out) in snapshot.ask_levels.iter().zip(&mut out.asks) {
+            *out = ItchLevel { price: level.price, volume: level.total_volume };
+        }
+        out
+    }
+}
+
+pub struct ItchReader {
+    replay: SnapshotReplay<InputData>,
+    symbols: Vec<[u8; 8]>,
+}
+
+impl ItchReader {
+    fn open(path: &Path, symbols: &str) -> io::Result<Self> {
+        let input = file_io::load_input(path, Encapsulation::Raw)?;
+        let symbols: Vec<&str> = symbols.split(',').map(str::trim).filter(|symbol| !symbol.is_empty()).collect();
+        Ok(ItchReader {
+            replay: SnapshotReplay::new(input, &symbols),
+            symbols: symbols.iter().map(|symbol| pad_stock_symbol(symbol)).collect(),
+        })
+    }
+
+    fn next_snapshot(&mut self) -> io::Result<Option<ItchSnapshot>> {
+        Ok(self.replay.next_snapshot()?.map(|(book, snapshot)| ItchSnapshot::new(self.symbols[book], &snapshot)))
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: impl ToString) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Map an ITCH 5.0 file and build the books of the comma separated symbols. Returns NULL on failure
+///
+/// # Safety
+/// path and symbols must be NUL terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn itch_open(path: *const c_char, symbols: *const c_char) -> *mut ItchReader {
+    if path.is_null() || symbols.is_null() {
+        set_last_error("path and symbols must not be NULL");
+        return ptr::null_mut();
+    }
+    let (path, symbols) = unsafe { (CStr::from_ptr(path), CStr::from_ptr(symbols)) };
+    let (Ok(path), Ok(symbols)) = (path.to_str(), symbols.to_str()) else {
+        set_last_error("path and symbols must be UTF-8");
+        return ptr::null_mut();
+    };
+    match ItchReader::open(Path::new(path), symbols) {
+        Ok(reader) => Box::into_raw(Box::new(reader)),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Write the next snapshot of any of the symbols to out. Returns 1 when one was written, 0 at the end of
+/// the file and -1 on error
+///
+/// # Safety
+/// reader must come from itch_open and not be closed; out must point to an ItchSnapshot
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn itch_next_snapshot(reader: *mut ItchReader, out: *mut ItchSnapshot) -> c_int {
+    let (Some(reader), Some(out)) = (unsafe { reader.as_mut() }, unsafe { out.as_mut() }) else {
+        set_last_error("reader and out must not be NULL");
+        return -1;
+    };
+    match reader.next_snapshot() {
+        Ok(Some(snapshot)) => {
+            *out = snapshot;
+            1
+        }
+        Ok(None) => 0,
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// Unmap the file and free the books
+///
+/// # Safety
+/// reader must come from itch_open (or be NULL) and not be used afterwards
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn itch_close(reader: *mut ItchReader) {
+    if !reader.is_null() {
+        drop(unsafe { Box::from_raw(reader) });
+    }
+}
+
+/// Message of the last failed call on this thread, or NULL. Valid until the next failing call
+#[unsafe(no_mangle)]
+pub extern "C" fn itch_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
@@ -1,9 +1,59 @@
+#[cfg(feature = "native")]
 use memmap2::{Mmap, MmapOptions};
-use std::fs::File;
 use std::io;
 use std::path::Path;
 
+use crate::pcap::{self, Encapsulation};
+
+#[cfg(feature = "native")]
 pub fn map_file(path: &Path) -> io::Result<Mmap> {
-    let file = File::open(path)?;
+    let file = std::fs::File::open(path)?;
     unsafe { MmapOptions::new().map(&file) }
-}
\ No newline at end of file
+}
+
+// Without mmap the file is read into memory
+#[cfg(not(feature = "native"))]
+pub fn map_file(path: &Path) -> io::Result<Vec<u8>> {
+    std::fs::read(path)
+}
+
+// Bytes of ITCH messages to parse, either mapped straight from the file or extracted from a capture
+pub enum InputData {
+    #[cfg(feature = "native")]
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl std::ops::Deref for InputData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(feature = "native")]
+            InputData::Mapped(mmap) => mmap,
+            InputData::Owned(bytes) => bytes,
+        }
+    }
+}
+
+// Map the input file, reassembling captures into a plain message stream up front
+pub fn load_input(path: &Path, encap: Encapsulation) -> io::Result<InputData> {
+    let mapped_file = map_file(path)?;
+    tracing::info!("File mapped: {} bytes", mapped_file.len());
+
+    Ok(match encap {
+        #[cfg(feature = "native")]
+        Encapsulation::Raw => InputData::Mapped(mapped_file),
+        #[cfg(not(feature = "native"))]
+        Encapsulation::Raw => InputData::Owned(mapped_file),
+        Encapsulation::Moldudp64 => {
+            let (messages, stats) = pcap::extract_moldudp64(&mapped_file)?;
+            tracing::info!("Extracted {} messages from {} packets ({} skipped packets, {} duplicate and {} missing messages)",
+                           stats.messages, stats.packets, stats.skipped_packets, stats.duplicate_messages, stats.missing_messages);
+            if stats.missing_messages > 0 {
+                tracing::warn!("Capture has gaps: {} messages are missing, the book may be incomplete", stats.missing_messages);
+            }
+            InputData::Owned(messages)
+        }
+    })
+}
@@ -0,0 +1,123 @@
+// The filter subcommand: copy the messages of some symbols and/or a time range to a new ITCH file, byte for byte
+// with their length prefixes, for small test slices of a day's capture
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::ControlFlow;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::cli::FilterArgs;
+use crate::file_io;
+use crate::message_types::MessageType;
+use crate::parser::{self, MSG_HEADER_SIZE};
+use crate::simd::SymbolSet;
+use crate::utils::{format_time_of_day, pad_stock_symbol};
+use crate::wire::{self, MessageView};
+
+// Which messages are kept
+#[derive(Debug, Clone, Default)]
+pub struct FilterOptions {
+    // Symbols whose messages are kept, empty for every symbol
+    pub symbols: Vec<String>,
+    // Messages from this time (inclusive) up to `to` (exclusive), in nanoseconds since midnight
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+// Messages read and written, by type
+#[derive(Debug, Clone, Default)]
+pub struct FilterStats {
+    pub read: u64,
+    pub written: u64,
+    pub by_type: FxHashMap<u8, u64>,
+}
+
+// Messages of every type carry their symbol's stock locate, so the locates of the symbols' messages pick out
+// their executes, cancels, deletes, replaces and broken trades. System events and the symbols' Stock Directory
+// messages are kept whatever their time. With a start time, the messages of orders added before it are dropped,
+// so every kept order message refers to a kept add
+pub fn filter_messages<W: Write>(data: &[u8], options: &FilterOptions, output: &mut W) -> io::Result<FilterStats> {
+    let symbols: Vec<[u8; 8]> = options.symbols.iter().map(|symbol| pad_stock_symbol(symbol)).collect();
+    let symbol_set = SymbolSet::new(&symbols);
+    let (from, to) = (options.from.unwrap_or(0), options.to.unwrap_or(u64::MAX));
+    let mut locates: FxHashSet<u16> = FxHashSet::default();
+    // Refs of the kept orders, only followed with a start time
+    let mut orders: FxHashSet<u64> = FxHashSet::default();
+    let track_orders = options.from.is_some();
+    let mut stats = FilterStats::default();
+    let mut result = Ok(());
+    parser::scan_range(data, 0, data.len(), |offset, message_type, message| {
+        stats.read += 1;
+        let Some(header) = wire::Header::view(message) else {
+            return ControlFlow::Continue(());
+        };
+        let in_range = (from..to).contains(&header.timestamp.get());
+        let locate = header.stock_locate.get();
+        let keep = match wire::decode_body(message_type, message).and_then(|decoded| decoded.stock()) {
+            Some(stock) => {
+                let chosen = symbols.is_empty() || symbol_set.contains(stock);
+                if chosen {
+                    locates.insert(locate);
+                }
+                let kept = chosen && (in_range || message_type == MessageType::StockDirectory);
+                if kept && track_orders && matches!(message_type, MessageType::AddOrder | MessageType::AddOrderWithMpid)
+                    && let Some(add) = wire::AddOrder::view(message) {
+                    orders.insert(add.order_ref_number.get());
+                }
+                kept
+            }
+            None => match message_type {
+                MessageType::SystemEvent => true,
+                MessageType::OrderExecuted | MessageType::OrderExecutedWithPrice | MessageType::OrderCancel | MessageType::OrderDelete => {
+                    let chosen = symbols.is_empty() || locates.contains(&locate);
+                    let known = !track_orders || wire::OrderDelete::view(message).is_some_and(|order| {
+                        let order_ref = order.order_ref_number.get();
+                        if message_type == MessageType::OrderDelete { orders.remove(&order_ref) } else { orders.contains(&order_ref) }
+                    });
+                    chosen && in_range && known
+                }
+                MessageType::OrderReplace => {
+                    let chosen = symbols.is_empty() || locates.contains(&locate);
+                    let known = !track_orders || wire::OrderReplace::view(message).is_some_and(|replace| {
+                        orders.remove(&replace.original_order_ref_number.get()) && orders.insert(replace.new_order_ref_number.get())
+                    });
+                    chosen && in_range && known
+                }
+                // Market wide messages have locate 0
+                _ => in_range && (symbols.is_empty() || locate == 0 || locates.contains(&locate)),
+            },
+        };
+        if keep {
+            stats.written += 1;
+            *stats.by_type.entry(message_type as u8).or_insert(0) += 1;
+            if let Err(e) = output.write_all(&data[offset..offset + MSG_HEADER_SIZE + message.len()]) {
+                result = Err(e);
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    });
+    result.map(|()| stats)
+}
+
+pub fn run(args: FilterArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if let (Some(from), Some(to)) = (args.from, args.to)
+        && to <= from {
+        return Err("--to must be after --from".into());
+    }
+    let input = file_io::load_input(&args.file, args.encap)?;
+    let options = FilterOptions { symbols: args.symbols, from: args.from, to: args.to };
+    let mut output = BufWriter::new(File::create(&args.output_file)?);
+    let stats = filter_messages(&input, &options, &mut output)?;
+    output.flush()?;
+
+    let range = format!("{} to {}", options.from.map_or("the start".to_string(), format_time_of_day),
+                        options.to.map_or("the end".to_string(), format_time_of_day));
+    let symbols = if options.symbols.is_empty() { "every symbol".to_string() } else { options.symbols.join(", ") };
+    tracing::info!("Wrote {} of {} messages ({}, {}) to {}", stats.written, stats.read, symbols, range, args.output_file.display());
+    let mut by_type: Vec<(u8, u64)> = stats.by_type.into_iter().collect();
+    by_type.sort();
+    let counts: Vec<String> = by_type.iter().map(|(message_type, count)| format!("{} {}", *message_type as char, count)).collect();
+    tracing::info!("Messages written by type: {}", counts.join(", "));
+    Ok(())
+}
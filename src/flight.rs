@@ -0,0 +1,232 @@
+// Arrow Flight server of the snapshots processed by serve, so Python and R clients can pull a symbol's day as
+// Arrow record batches instead of re-reading the CSV output. The columns are those of the CSV output, prices
+// in dollars and missing levels as zeros. A ticket selects a symbol and an optional time range, inclusive:
+//
+//   {"symbol": "AAPL", "from": "09:30:00", "to": "10:00:00"}
+//
+// ListFlights has a flight per symbol with its schema, row count and ticket
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, UInt32Array, UInt64Array};
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo, HandshakeRequest,
+                   HandshakeResponse, PollInfo, PutResult, SchemaResult, Ticket};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt, TryStreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::orderbook::{BookSnapshot, MAX_BOOK_DEPTH};
+use crate::shutdown;
+use crate::sink::OutputSink;
+use crate::utils::parse_time_of_day;
+
+// Rows per record batch sent
+const BATCH_ROWS: usize = 65_536;
+
+// timestamp, the four columns of each level, mid_price and orderbook_imbalance
+static SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    let mut fields = vec![Field::new("timestamp", DataType::UInt64, false)];
+    for level in 1..=MAX_BOOK_DEPTH {
+        fields.push(Field::new(format!("{}_bid_price", level), DataType::Float64, false));
+        fields.push(Field::new(format!("{}_bid_vol", level), DataType::UInt32, false));
+        fields.push(Field::new(format!("{}_ask_price", level), DataType::Float64, false));
+        fields.push(Field::new(format!("{}_ask_vol", level), DataType::UInt32, false));
+    }
+    fields.push(Field::new("mid_price", DataType::Float64, false));
+    fields.push(Field::new("orderbook_imbalance", DataType::Float64, false));
+    Arc::new(Schema::new(fields))
+});
+
+// A symbol's snapshots as columns; the level columns hold MAX_BOOK_DEPTH values per row, best first
+#[derive(Default)]
+struct SnapshotColumns {
+    timestamp: Vec<u64>,
+    bid_price: Vec<f64>,
+    bid_vol: Vec<u32>,
+    ask_price: Vec<f64>,
+    ask_vol: Vec<u32>,
+    mid_price: Vec<f64>,
+    imbalance: Vec<f64>,
+}
+
+impl SnapshotColumns {
+    fn push(&mut self, snapshot: &BookSnapshot) {
+        self.timestamp.push(snapshot.timestamp);
+        for i in 0..MAX_BOOK_DEPTH {
+            let bid = snapshot.bid_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            let ask = snapshot.ask_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            self.bid_price.push(bid.0 as f64 / 10000.0);
+            self.bid_vol.push(bid.1);
+            self.ask_price.push(ask.0 as f64 / 10000.0);
+            self.ask_vol.push(ask.1);
+        }
+        self.mid_price.push(snapshot.mid_price);
+        self.imbalance.push(snapshot.imbalance);
+    }
+
+    fn batch(&self, rows: std::ops::Range<usize>) -> Result<RecordBatch, FlightError> {
+        let level = |values: &[f64], i: usize| -> ArrayRef {
+            Arc::new(Float64Array::from_iter_values(rows.clone().map(|row| values[row * MAX_BOOK_DEPTH + i])))
+        };
+        let volume = |values: &[u32], i: usize| -> ArrayRef {
+            Arc::new(UInt32Array::from_iter_values(rows.clone().map(|row| values[row * MAX_BOOK_DEPTH + i])))
+        };
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from(self.timestamp[rows.clone()].to_vec()))];
+        for i in 0..MAX_BOOK_DEPTH {
+            columns.push(level(&self.bid_price, i));
+            columns.push(volume(&self.bid_vol, i));
+            columns.push(level(&self.ask_price, i));
+            columns.push(volume(&self.ask_vol, i));
+        }
+        columns.push(Arc::new(Float64Array::from(self.mid_price[rows.clone()].to_vec())));
+        columns.push(Arc::new(Float64Array::from(self.imbalance[rows].to_vec())));
+        Ok(RecordBatch::try_new(SCHEMA.clone(), columns)?)
+    }
+}
+
+// Every snapshot written so far, per symbol
+#[derive(Default)]
+pub struct FlightStore {
+    symbols: RwLock<HashMap<String, SnapshotColumns>>,
+}
+
+impl FlightStore {
+    fn info(&self, symbol: &str) -> Result<FlightInfo, Status> {
+        let rows = self.symbols.read().unwrap().get(symbol).map(|columns| columns.timestamp.len())
+            .ok_or_else(|| Status::not_found(format!("No snapshots for symbol {}", symbol)))?;
+        let ticket = serde_json::json!({ "symbol": symbol }).to_string();
+        let info = FlightInfo::new()
+            .try_with_schema(&SCHEMA)
+            .map_err(|e| Status::internal(e.to_string()))?
+            .with_descriptor(FlightDescriptor::new_path(vec![symbol.to_string()]))
+            .with_endpoint(FlightEndpoint::new().with_ticket(Ticket::new(ticket)))
+            .with_total_records(rows as i64);
+        Ok(info)
+    }
+}
+
+// Feeds the orderbook's snapshots into the store
+pub struct FlightSink(pub Arc<FlightStore>);
+
+impl OutputSink for FlightSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let mut symbols = self.0.symbols.write().unwrap();
+        match symbols.get_mut(symbol) {
+            Some(columns) => columns.push(snapshot),
+            None => symbols.entry(symbol.to_string()).or_default().push(snapshot),
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The symbol and inclusive time range of a ticket
+fn parse_ticket(ticket: &[u8]) -> Result<(String, u64, u64), String> {
+    let value: serde_json::Value = serde_json::from_slice(ticket).map_err(|e| format!("Invalid ticket: {}", e))?;
+    let symbol = value.get("symbol").and_then(|symbol| symbol.as_str())
+        .ok_or("The ticket needs a \"symbol\"")?.trim().to_uppercase();
+    let bound = |key: &str, default: u64| match value.get(key) {
+        None => Ok(default),
+        Some(serde_json::Value::String(time)) => parse_time_of_day(time),
+        Some(other) => Err(format!("\"{}\" expects a time of day, got {}", key, other)),
+    };
+    Ok((symbol, bound("from", 0)?, bound("to", u64::MAX)?))
+}
+
+pub struct FlightServer(Arc<FlightStore>);
+
+#[tonic::async_trait]
+impl FlightService for FlightServer {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+
+    async fn handshake(&self, _: Request<Streaming<HandshakeRequest>>) -> Result<Response<Self::HandshakeStream>, Status> {
+        Err(Status::unimplemented("No handshake is needed"))
+    }
+
+    async fn list_flights(&self, _: Request<Criteria>) -> Result<Response<Self::ListFlightsStream>, Status> {
+        let mut symbols: Vec<String> = self.0.symbols.read().unwrap().keys().cloned().collect();
+        symbols.sort();
+        let infos: Vec<_> = symbols.iter().map(|symbol| self.0.info(symbol)).collect();
+        Ok(Response::new(futures_util::stream::iter(infos).boxed()))
+    }
+
+    async fn get_flight_info(&self, request: Request<FlightDescriptor>) -> Result<Response<FlightInfo>, Status> {
+        let descriptor = request.into_inner();
+        let [symbol] = descriptor.path.as_slice() else {
+            return Err(Status::invalid_argument("The descriptor path is a symbol"));
+        };
+        Ok(Response::new(self.0.info(&symbol.trim().to_uppercase())?))
+    }
+
+    async fn poll_flight_info(&self, _: Request<FlightDescriptor>) -> Result<Response<PollInfo>, Status> {
+        Err(Status::unimplemented("Use GetFlightInfo"))
+    }
+
+    async fn get_schema(&self, _: Request<FlightDescriptor>) -> Result<Response<SchemaResult>, Status> {
+        Err(Status::unimplemented("The schema is in the FlightInfo"))
+    }
+
+    async fn do_get(&self, request: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        let (symbol, from, to) = parse_ticket(&request.into_inner().ticket).map_err(Status::invalid_argument)?;
+        let (start, end) = match self.0.symbols.read().unwrap().get(&symbol) {
+            // Snapshots are written in timestamp order, so the range can be found by bisection
+            Some(columns) => (columns.timestamp.partition_point(|&t| t < from), columns.timestamp.partition_point(|&t| t <= to)),
+            None => return Err(Status::not_found(format!("No snapshots for symbol {}", symbol))),
+        };
+
+        // Batches are built as the client takes them, holding the store only while one is copied out
+        let store = self.0.clone();
+        let batches = futures_util::stream::iter((start..end.max(start)).step_by(BATCH_ROWS)).map(move |first| {
+            let symbols = store.symbols.read().unwrap();
+            symbols[&symbol].batch(first..(first + BATCH_ROWS).min(end))
+        });
+        let stream = FlightDataEncoderBuilder::new()
+            .with_schema(SCHEMA.clone())
+            .build(batches)
+            .map_err(Status::from);
+        Ok(Response::new(stream.boxed()))
+    }
+
+    async fn do_put(&self, _: Request<Streaming<FlightData>>) -> Result<Response<Self::DoPutStream>, Status> {
+        Err(Status::unimplemented("The snapshots are read only"))
+    }
+
+    async fn do_exchange(&self, _: Request<Streaming<FlightData>>) -> Result<Response<Self::DoExchangeStream>, Status> {
+        Err(Status::unimplemented("The snapshots are read only"))
+    }
+
+    async fn do_action(&self, _: Request<Action>) -> Result<Response<Self::DoActionStream>, Status> {
+        Err(Status::unimplemented("No actions"))
+    }
+
+    async fn list_actions(&self, _: Request<Empty>) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(futures_util::stream::empty().boxed()))
+    }
+}
+
+// Serve the snapshots until shut down
+pub async fn serve(store: Arc<FlightStore>, port: u16) -> io::Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    tracing::info!("Arrow Flight server started on: {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(FlightServiceServer::new(FlightServer(store)))
+        .serve_with_shutdown(addr, shutdown::wait())
+        .await
+        .map_err(io::Error::other)
+}
@@ -0,0 +1,196 @@
+// gRPC API of the serve command, for consumers that prefer typed streams to WebSocket JSON: book updates and
+// trades of some or all symbols as they are parsed, and the latest book of a symbol. The service is generated
+// from proto/orderbook.proto. A stream that falls too far behind the parser ends with RESOURCE_EXHAUSTED, like
+// the WebSocket server's default slow-client policy
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use futures_util::Stream;
+use tokio::select;
+use tokio::sync::broadcast;
+use tonic::{Request, Response, Status};
+
+use crate::orderbook::{BookSnapshot, PriceLevel, Side, TradeRecord};
+use crate::shutdown;
+use crate::sink::OutputSink;
+use crate::websocket::BROADCAST_CAPACITY;
+
+pub mod proto {
+    tonic::include_proto!("itch.orderbook.v1");
+}
+
+use proto::order_book_server::{OrderBook, OrderBookServer};
+
+// Book updates and trades published by the orderbook, and the latest book of every symbol
+pub struct GrpcState {
+    books: RwLock<HashMap<String, Arc<BookSnapshot>>>,
+    snapshots: broadcast::Sender<(Arc<str>, Arc<BookSnapshot>)>,
+    trades: broadcast::Sender<(Arc<str>, TradeRecord)>,
+}
+
+impl Default for GrpcState {
+    fn default() -> Self {
+        GrpcState {
+            books: RwLock::new(HashMap::new()),
+            snapshots: broadcast::channel(BROADCAST_CAPACITY).0,
+            trades: broadcast::channel(BROADCAST_CAPACITY).0,
+        }
+    }
+}
+
+// Feeds the orderbook's output into the gRPC state
+pub struct GrpcSink {
+    state: Arc<GrpcState>,
+    // Shared by all updates of the same symbol
+    symbol: Option<Arc<str>>,
+}
+
+impl GrpcSink {
+    pub fn new(state: Arc<GrpcState>) -> Self {
+        GrpcSink { state, symbol: None }
+    }
+
+    fn shared_symbol(&mut self, symbol: &str) -> Arc<str> {
+        match &self.symbol {
+            Some(shared) if &**shared == symbol => shared.clone(),
+            _ => self.symbol.insert(Arc::from(symbol)).clone(),
+        }
+    }
+}
+
+impl OutputSink for GrpcSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let snapshot = Arc::new(snapshot.clone());
+        self.state.books.write().unwrap().insert(symbol.to_string(), snapshot.clone());
+        if self.state.snapshots.receiver_count() > 0 {
+            let symbol = self.shared_symbol(symbol);
+            let _ = self.state.snapshots.send((symbol, snapshot));
+        }
+        Ok(())
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        if self.state.trades.receiver_count() > 0 {
+            let symbol = self.shared_symbol(symbol);
+            let _ = self.state.trades.send((symbol, trade.clone()));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn levels(levels: &[PriceLevel]) -> Vec<proto::PriceLevel> {
+    levels.iter().map(|level| proto::PriceLevel { price: level.price, volume: level.total_volume }).collect()
+}
+
+fn book_message(symbol: &str, snapshot: &BookSnapshot) -> proto::BookSnapshot {
+    proto::BookSnapshot {
+        symbol: symbol.to_string(),
+        timestamp: snapshot.timestamp,
+        bids: levels(&snapshot.bid_levels),
+        asks: levels(&snapshot.ask_levels),
+        mid_price: snapshot.mid_price,
+        imbalance: snapshot.imbalance,
+    }
+}
+
+fn side(side: Option<Side>) -> proto::Side {
+    match side {
+        Some(Side::Buy) => proto::Side::Buy,
+        Some(Side::Sell) => proto::Side::Sell,
+        None => proto::Side::Unspecified,
+    }
+}
+
+fn trade_message(symbol: &str, trade: &TradeRecord) -> proto::Trade {
+    proto::Trade {
+        symbol: symbol.to_string(),
+        timestamp: trade.timestamp,
+        price: trade.price,
+        shares: trade.shares,
+        side: side(Some(trade.side)).into(),
+        match_number: trade.match_number,
+        message_type: (trade.message_type as char).to_string(),
+        aggressor: side(trade.aggressor).into(),
+    }
+}
+
+type MessageStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+// The messages of the wanted symbols from a broadcast channel, until the server shuts down
+fn subscribe<T: Clone + Send + 'static, M: Send + 'static>(
+    rx: broadcast::Receiver<(Arc<str>, T)>,
+    symbols: Vec<String>,
+    message: fn(&str, &T) -> M,
+) -> MessageStream<M> {
+    let symbols: Vec<String> = symbols.iter().map(|symbol| symbol.trim().to_uppercase()).collect();
+    Box::pin(futures_util::stream::unfold(Some(rx), move |rx| {
+        let symbols = symbols.clone();
+        async move {
+            let mut rx = rx?;
+            loop {
+                let received = select! {
+                    received = rx.recv() => received,
+                    _ = shutdown::wait() => return None,
+                };
+                match received {
+                    Ok((symbol, item)) if symbols.is_empty() || symbols.iter().any(|wanted| **wanted == *symbol) => {
+                        return Some((Ok(message(&symbol, &item)), Some(rx)));
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        let status = Status::resource_exhausted(format!("Too slow, missed {} messages", skipped));
+                        return Some((Err(status), None));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }
+    }))
+}
+
+pub struct OrderBookService(Arc<GrpcState>);
+
+#[tonic::async_trait]
+impl OrderBook for OrderBookService {
+    type SubscribeBookStream = MessageStream<proto::BookSnapshot>;
+    type SubscribeTradesStream = MessageStream<proto::Trade>;
+
+    async fn subscribe_book(&self, request: Request<proto::SubscribeRequest>) -> Result<Response<Self::SubscribeBookStream>, Status> {
+        let rx = self.0.snapshots.subscribe();
+        let stream = subscribe(rx, request.into_inner().symbols, |symbol, snapshot: &Arc<BookSnapshot>| book_message(symbol, snapshot));
+        Ok(Response::new(stream))
+    }
+
+    async fn subscribe_trades(&self, request: Request<proto::SubscribeRequest>) -> Result<Response<Self::SubscribeTradesStream>, Status> {
+        let rx = self.0.trades.subscribe();
+        Ok(Response::new(subscribe(rx, request.into_inner().symbols, trade_message)))
+    }
+
+    async fn get_snapshot(&self, request: Request<proto::GetSnapshotRequest>) -> Result<Response<proto::BookSnapshot>, Status> {
+        let symbol = request.into_inner().symbol.trim().to_uppercase();
+        let snapshot = self.0.books.read().unwrap().get(&symbol).cloned();
+        match snapshot {
+            Some(snapshot) => Ok(Response::new(book_message(&symbol, &snapshot))),
+            None => Err(Status::not_found(format!("No book for symbol {}", symbol))),
+        }
+    }
+}
+
+// Serve the gRPC API until shut down
+pub async fn serve(state: Arc<GrpcState>, port: u16) -> io::Result<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    tracing::info!("gRPC server started on: {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(OrderBookServer::new(OrderBookService(state)))
+        .serve_with_shutdown(addr, shutdown::wait())
+        .await
+        .map_err(io::Error::other)
+}
@@ -0,0 +1,154 @@
+// Callbacks for the messages of a feed, driven by parser::process_itch_file. Every callback does
+// nothing by default, so a handler only implements the messages it cares about. The orderbook is
+// one implementation; custom analytics can be another without touching the parser loop
+
+use std::io;
+
+use crate::checkpoint::Checkpoint;
+use crate::message_types::MessageType;
+use crate::wire::{self, MessageView};
+
+#[allow(unused_variables)]
+pub trait MessageHandler {
+    // Called for every message before its typed callback
+    fn on_message(&mut self, message_type: MessageType, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_system_event(&mut self, message: &wire::SystemEvent, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_stock_directory(&mut self, message: &wire::StockDirectory, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_trading_action(&mut self, message: &wire::StockTradingAction, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_reg_sho(&mut self, message: &wire::RegShoRestriction, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_market_participant_position(&mut self, message: &wire::MarketParticipantPosition, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_mwcb_decline_level(&mut self, message: &wire::MwcbDeclineLevel, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_mwcb_status(&mut self, message: &wire::MwcbStatus, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_ipo_quoting_period(&mut self, message: &wire::IpoQuotingPeriodUpdate, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_luld_collar(&mut self, message: &wire::LuldAuctionCollar, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_operational_halt(&mut self, message: &wire::OperationalHalt, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_add_order(&mut self, message: &wire::AddOrder, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_add_order_with_mpid(&mut self, message: &wire::AddOrderWithMpid, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_execute(&mut self, message: &wire::OrderExecuted, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_execute_with_price(&mut self, message: &wire::OrderExecutedWithPrice, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_cancel(&mut self, message: &wire::OrderCancel, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_delete(&mut self, message: &wire::OrderDelete, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_replace(&mut self, message: &wire::OrderReplace, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_trade(&mut self, message: &wire::Trade, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_cross_trade(&mut self, message: &wire::CrossTrade, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_broken_trade(&mut self, message: &wire::BrokenTrade, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_noii(&mut self, message: &wire::Noii, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_rpii(&mut self, message: &wire::Rpii, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_direct_listing(&mut self, message: &wire::DirectListingWithCapitalRaisePriceDiscovery, timestamp: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Called once when the parser stops, with the byte offset after the last message it parsed: the end of the
+    // input, or where --max-messages, a stop time or a stop request ended the run
+    fn on_end(&mut self, offset: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    // State to resume from at the byte offset with --resume-from; only a single handler run is checkpointed
+    fn checkpoint_at(&self, offset: u64, timestamp: u64) -> Option<Checkpoint> {
+        None
+    }
+}
+
+// Hand a message (after its type byte) to the handler: on_message, then the callback of its type with
+// the message's view. Messages shorter than their type's layout only get on_message
+#[inline(always)]
+pub fn dispatch<H: MessageHandler + ?Sized>(handler: &mut H, message_type: MessageType, data: &[u8], timestamp: u64) -> io::Result<()> {
+    handler.on_message(message_type, timestamp)?;
+    match message_type {
+        MessageType::SystemEvent => MessageView::view(data).map_or(Ok(()), |message| handler.on_system_event(message, timestamp)),
+        MessageType::StockDirectory => MessageView::view(data).map_or(Ok(()), |message| handler.on_stock_directory(message, timestamp)),
+        MessageType::StockTradingAction => MessageView::view(data).map_or(Ok(()), |message| handler.on_trading_action(message, timestamp)),
+        MessageType::RegShoRestriction => MessageView::view(data).map_or(Ok(()), |message| handler.on_reg_sho(message, timestamp)),
+        MessageType::MarketParticipantPosition => MessageView::view(data).map_or(Ok(()), |message| handler.on_market_participant_position(message, timestamp)),
+        MessageType::MwcbDeclineLevel => MessageView::view(data).map_or(Ok(()), |message| handler.on_mwcb_decline_level(message, timestamp)),
+        MessageType::MwcbStatus => MessageView::view(data).map_or(Ok(()), |message| handler.on_mwcb_status(message, timestamp)),
+        MessageType::IpoQuotingPeriodUpdate => MessageView::view(data).map_or(Ok(()), |message| handler.on_ipo_quoting_period(message, timestamp)),
+        MessageType::LuldAuctionCollar => MessageView::view(data).map_or(Ok(()), |message| handler.on_luld_collar(message, timestamp)),
+        MessageType::OperationalHalt => MessageView::view(data).map_or(Ok(()), |message| handler.on_operational_halt(message, timestamp)),
+        MessageType::AddOrder => MessageView::view(data).map_or(Ok(()), |message| handler.on_add_order(message, timestamp)),
+        MessageType::AddOrderWithMpid => MessageView::view(data).map_or(Ok(()), |message| handler.on_add_order_with_mpid(message, timestamp)),
+        MessageType::OrderExecuted => MessageView::view(data).map_or(Ok(()), |message| handler.on_execute(message, timestamp)),
+        MessageType::OrderExecutedWithPrice => MessageView::view(data).map_or(Ok(()), |message| handler.on_execute_with_price(message, timestamp)),
+        MessageType::OrderCancel => MessageView::view(data).map_or(Ok(()), |message| handler.on_cancel(message, timestamp)),
+        MessageType::OrderDelete => MessageView::view(data).map_or(Ok(()), |message| handler.on_delete(message, timestamp)),
+        MessageType::OrderReplace => MessageView::view(data).map_or(Ok(()), |message| handler.on_replace(message, timestamp)),
+        MessageType::Trade => MessageView::view(data).map_or(Ok(()), |message| handler.on_trade(message, timestamp)),
+        MessageType::CrossTrade => MessageView::view(data).map_or(Ok(()), |message| handler.on_cross_trade(message, timestamp)),
+        MessageType::BrokenTrade => MessageView::view(data).map_or(Ok(()), |message| handler.on_broken_trade(message, timestamp)),
+        MessageType::Noii => MessageView::view(data).map_or(Ok(()), |message| handler.on_noii(message, timestamp)),
+        MessageType::Rpii => MessageView::view(data).map_or(Ok(()), |message| handler.on_rpii(message, timestamp)),
+        MessageType::DirectListingWithCapitalRaisePriceDiscovery => MessageView::view(data).map_or(Ok(()), |message| handler.on_direct_listing(message, timestamp)),
+        MessageType::Unknown => Ok(()),
+    }
+}
+
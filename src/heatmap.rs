@@ -0,0 +1,170 @@
+// Resting liquidity on a price x time grid for heatmaps. At the end of every interval of market time the
+// shares resting near the mid are summed into price buckets; at the end of the run the samples are written
+// to Parquet as a dense grid, every price bucket of the day in every interval, so the file reshapes straight
+// into a matrix instead of being pivoted from the per-update output
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parquet::data_type::{DataType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+
+use crate::ladder::PriceLadder;
+use crate::sink::{parquet_error, PARQUET_ROW_GROUP_SIZE};
+
+// Buckets recorded on each side of the mid by default
+pub const DEFAULT_HEATMAP_LEVELS: u32 = 100;
+
+fn write_column<T: DataType>(row_group: &mut SerializedRowGroupWriter<'_, File>, values: &[T::T]) -> io::Result<()> {
+    let mut column = row_group.next_column().map_err(parquet_error)?.ok_or_else(|| io::Error::other("Missing heatmap column"))?;
+    column.typed::<T>().write_batch(values, None, None).map_err(parquet_error)?;
+    column.close().map_err(parquet_error)
+}
+
+// Bid and ask shares per bucket, from bucket `low` up, at `timestamp`
+struct Sample {
+    timestamp: u64,
+    low: u32,
+    cells: Vec<(u32, u32)>,
+}
+
+pub struct Heatmap {
+    path: PathBuf,
+    interval: u64,
+    // Bucket width in price units
+    tick: u32,
+    // Buckets kept on each side of the mid's
+    levels: u32,
+    // End of the interval being built, None before the first message
+    next_sample: Option<u64>,
+    samples: Vec<Sample>,
+}
+
+impl Heatmap {
+    pub fn new(path: &Path, interval: u64, tick: u32, levels: u32) -> Self {
+        Heatmap { path: path.to_path_buf(), interval: interval.max(1), tick: tick.max(1), levels, next_sample: None, samples: Vec::new() }
+    }
+
+    // Whether market time at `timestamp` ends an interval; cheap enough for every message
+    #[inline]
+    pub fn due(&mut self, timestamp: u64) -> bool {
+        match self.next_sample {
+            Some(next) => timestamp >= next,
+            None => {
+                self.next_sample = Some(timestamp - timestamp % self.interval + self.interval);
+                false
+            }
+        }
+    }
+
+    // Sample the book, as it was before the message at `timestamp`, for every interval that ended by then.
+    // Intervals without messages get the same book
+    pub fn sample_until(&mut self, timestamp: u64, bids: &PriceLadder, asks: &PriceLadder) {
+        let Some(mut next) = self.next_sample else {
+            return;
+        };
+        if timestamp < next {
+            return;
+        }
+        let first = self.sample(next, bids, asks);
+        next += self.interval;
+        while next <= timestamp {
+            let cells = self.samples[first].cells.clone();
+            let low = self.samples[first].low;
+            self.samples.push(Sample { timestamp: next, low, cells });
+            next += self.interval;
+        }
+        self.next_sample = Some(next);
+    }
+
+    fn sample(&mut self, timestamp: u64, bids: &PriceLadder, asks: &PriceLadder) -> usize {
+        let mid = match (bids.best(), asks.best()) {
+            (Some(bid), Some(ask)) => Some((bid as u64 + ask as u64) / 2),
+            (Some(price), None) | (None, Some(price)) => Some(price as u64),
+            (None, None) => None,
+        };
+        let mut sample = Sample { timestamp, low: 0, cells: Vec::new() };
+        if let Some(mid) = mid {
+            let mid = (mid / self.tick as u64) as u32;
+            sample.low = mid.saturating_sub(self.levels);
+            let high = mid.saturating_add(self.levels);
+            sample.cells = vec![(0, 0); (high - sample.low + 1) as usize];
+            // Best first, so each side stops at the first level outside the window
+            for (price, volume) in bids.iter() {
+                let bucket = price / self.tick;
+                if bucket < sample.low {
+                    break;
+                }
+                if bucket <= high {
+                    let cell = &mut sample.cells[(bucket - sample.low) as usize].0;
+                    *cell = cell.saturating_add(volume);
+                }
+            }
+            for (price, volume) in asks.iter() {
+                let bucket = price / self.tick;
+                if bucket > high {
+                    break;
+                }
+                if bucket >= sample.low {
+                    let cell = &mut sample.cells[(bucket - sample.low) as usize].1;
+                    *cell = cell.saturating_add(volume);
+                }
+            }
+        }
+        self.samples.push(sample);
+        self.samples.len() - 1
+    }
+
+    // Sample the book at the end of the input for the interval in progress, then write the grid:
+    // (timestamp, price, bid_volume, ask_volume) for every sample and every bucket from the lowest to the
+    // highest one recorded, prices ascending within each timestamp
+    pub fn finalize(&mut self, bids: &PriceLadder, asks: &PriceLadder) -> io::Result<()> {
+        if let Some(next) = self.next_sample.take() {
+            self.sample(next, bids, asks);
+        }
+        let recorded = self.samples.iter().filter(|sample| !sample.cells.is_empty());
+        let low = recorded.clone().map(|sample| sample.low).min();
+        let high = recorded.map(|sample| sample.low + sample.cells.len() as u32 - 1).max();
+        let (low, high) = (low.unwrap_or(1), high.unwrap_or(0));
+        let buckets = (high + 1).saturating_sub(low) as usize;
+
+        let schema = parse_message_type("message heatmap { REQUIRED INT64 timestamp; REQUIRED DOUBLE price; REQUIRED INT64 bid_volume; REQUIRED INT64 ask_volume; }")
+            .map_err(parquet_error)?;
+        let properties = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(File::create(&self.path)?, Arc::new(schema), properties).map_err(parquet_error)?;
+        let samples_per_group = (PARQUET_ROW_GROUP_SIZE / buckets.max(1)).max(1);
+        for group in self.samples.chunks(samples_per_group).filter(|_| buckets > 0) {
+            let rows = group.len() * buckets;
+            let mut timestamps = Vec::with_capacity(rows);
+            let mut prices = Vec::with_capacity(rows);
+            let mut bid_volumes = Vec::with_capacity(rows);
+            let mut ask_volumes = Vec::with_capacity(rows);
+            for sample in group {
+                for bucket in low..=high {
+                    let (bid, ask) = bucket.checked_sub(sample.low)
+                        .and_then(|index| sample.cells.get(index as usize))
+                        .copied()
+                        .unwrap_or((0, 0));
+                    timestamps.push(sample.timestamp as i64);
+                    prices.push(bucket as f64 * self.tick as f64 / 10000.0);
+                    bid_volumes.push(bid as i64);
+                    ask_volumes.push(ask as i64);
+                }
+            }
+
+            let mut row_group = writer.next_row_group().map_err(parquet_error)?;
+            write_column::<Int64Type>(&mut row_group, &timestamps)?;
+            write_column::<DoubleType>(&mut row_group, &prices)?;
+            write_column::<Int64Type>(&mut row_group, &bid_volumes)?;
+            write_column::<Int64Type>(&mut row_group, &ask_volumes)?;
+            row_group.close().map_err(parquet_error)?;
+        }
+        writer.close().map_err(parquet_error)?;
+        tracing::info!("Wrote a heatmap of {} intervals x {} price buckets to {}", self.samples.len(), buckets, self.path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+// Infers the symbol's hidden liquidity from its executions. Executions of displayed orders (E, and C at the
+// order's own price) took visible liquidity; non-displayed trades (P) and executions with price (C) at a price
+// other than the displayed order's took shares the book never showed. Each of those is placed against the
+// best bid and ask before it, e.g. hidden midpoint liquidity trades at the mid and reserve size at the quote
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
+
+// Where a hidden execution traded against the book before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteLocation {
+    Midpoint,
+    // Between the best bid and ask, but not at the mid
+    InsideSpread,
+    AtQuote,
+    OutsideQuote,
+    // The book was one-sided, empty, locked or crossed
+    NoQuote,
+}
+
+impl QuoteLocation {
+    pub fn of(price: u32, bid: Option<u32>, ask: Option<u32>) -> Self {
+        let (Some(bid), Some(ask)) = (bid, ask) else {
+            return QuoteLocation::NoQuote;
+        };
+        if bid >= ask {
+            return QuoteLocation::NoQuote;
+        }
+        if price == bid || price == ask {
+            QuoteLocation::AtQuote
+        } else if price < bid || price > ask {
+            QuoteLocation::OutsideQuote
+        } else if 2 * price as u64 == bid as u64 + ask as u64 {
+            QuoteLocation::Midpoint
+        } else {
+            QuoteLocation::InsideSpread
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+const LOCATIONS: [(QuoteLocation, &str); 5] = [
+    (QuoteLocation::Midpoint, "midpoint"),
+    (QuoteLocation::InsideSpread, "inside_spread"),
+    (QuoteLocation::AtQuote, "at_quote"),
+    (QuoteLocation::OutsideQuote, "outside_quote"),
+    (QuoteLocation::NoQuote, "no_quote"),
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Volume {
+    executions: u64,
+    shares: u64,
+}
+
+impl Volume {
+    fn add(&mut self, shares: u32) {
+        self.executions += 1;
+        self.shares += shares as u64;
+    }
+
+    fn summary(&self, total_shares: u64) -> serde_json::Value {
+        json!({
+            "executions": self.executions,
+            "shares": self.shares,
+            "percent_of_volume": (total_shares > 0).then(|| 100.0 * self.shares as f64 / total_shares as f64),
+            "average_size": (self.executions > 0).then(|| self.shares as f64 / self.executions as f64),
+        })
+    }
+}
+
+// Counts the symbol's printable executions, split into displayed and hidden, and writes them as JSON at the end
+pub struct HiddenLiquidityReport {
+    path: PathBuf,
+    displayed: Volume,
+    // P messages
+    non_displayed_trades: Volume,
+    // C messages away from the displayed order's price, and how far from it in price units
+    off_display: Volume,
+    off_display_distance: u64,
+    locations: [Volume; LOCATIONS.len()],
+}
+
+impl HiddenLiquidityReport {
+    pub fn new(path: &Path) -> Self {
+        HiddenLiquidityReport {
+            path: path.to_path_buf(),
+            displayed: Volume::default(),
+            non_displayed_trades: Volume::default(),
+            off_display: Volume::default(),
+            off_display_distance: 0,
+            locations: [Volume::default(); LOCATIONS.len()],
+        }
+    }
+
+    // A printable execution of a displayed order at `display_price`, against the best bid and ask before it
+    pub fn execution(&mut self, display_price: u32, price: u32, shares: u32, bid: Option<u32>, ask: Option<u32>) {
+        if price == display_price {
+            self.displayed.add(shares);
+            return;
+        }
+        self.off_display.add(shares);
+        self.off_display_distance += price.abs_diff(display_price) as u64 * shares as u64;
+        self.locations[QuoteLocation::of(price, bid, ask).index()].add(shares);
+    }
+
+    // A trade of a non-displayed order, against the best bid and ask when it printed
+    pub fn non_displayed_trade(&mut self, price: u32, shares: u32, bid: Option<u32>, ask: Option<u32>) {
+        self.non_displayed_trades.add(shares);
+        self.locations[QuoteLocation::of(price, bid, ask).index()].add(shares);
+    }
+
+    pub fn finalize(&mut self, symbol: &str) -> io::Result<()> {
+        let hidden = Volume {
+            executions: self.non_displayed_trades.executions + self.off_display.executions,
+            shares: self.non_displayed_trades.shares + self.off_display.shares,
+        };
+        let total = self.displayed.shares + hidden.shares;
+        let mut locations = serde_json::Map::new();
+        for (location, name) in LOCATIONS {
+            locations.insert(name.to_string(), self.locations[location.index()].summary(hidden.shares));
+        }
+        let mut off_display = self.off_display.summary(total);
+        off_display["average_distance"] = (self.off_display.shares > 0)
+            .then(|| self.off_display_distance as f64 / self.off_display.shares as f64 / 10000.0)
+            .into();
+
+        let summary = json!({
+            "symbol": symbol,
+            "executed_shares": total,
+            "displayed": self.displayed.summary(total),
+            "hidden": hidden.summary(total),
+            "non_displayed_trades": self.non_displayed_trades.summary(total),
+            "off_display_price": off_display,
+            // Percents of the hidden shares
+            "hidden_by_location": locations,
+        });
+        let json = serde_json::to_string_pretty(&summary).map_err(io::Error::other)?;
+        fs::write(&self.path, json + "\n")?;
+        tracing::info!("Wrote hidden liquidity of {} to {}: {} of {} executed shares", symbol, self.path.display(), hidden.shares, total);
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Book invariant that was broken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyKind {
+    // Best bid above best ask
+    Crossed,
+    // Best bid equal to best ask
+    Locked,
+    // An execute, cancel, delete or replace for the tracked symbol's order that isn't on the book
+    UnknownOrderRef,
+    // More shares taken off an order or price level than it holds
+    NegativeLevel,
+    // An add priced further from the prevailing price than the price band allows
+    OutOfBand,
+}
+
+pub const ANOMALY_KINDS: [AnomalyKind; 5] = [AnomalyKind::Crossed, AnomalyKind::Locked, AnomalyKind::UnknownOrderRef, AnomalyKind::NegativeLevel,
+                                             AnomalyKind::OutOfBand];
+
+impl AnomalyKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            AnomalyKind::Crossed => "crossed",
+            AnomalyKind::Locked => "locked",
+            AnomalyKind::UnknownOrderRef => "unknown_order_ref",
+            AnomalyKind::NegativeLevel => "negative_level",
+            AnomalyKind::OutOfBand => "out_of_band",
+        }
+    }
+}
+
+// What happens to an add outside the price band
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BandAction {
+    // Keep the order on the book and count it, and report it with --anomalies-output
+    #[default]
+    Flag,
+    // Also keep the order off the book, with the messages that follow for it
+    Drop,
+}
+
+// Sanity check of add prices against the prevailing price, so stub quotes can be kept out of the depth
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceBand {
+    // Largest distance from the reference price, in percent of it
+    pub percent: f64,
+    pub action: BandAction,
+}
+
+impl PriceBand {
+    pub fn contains(&self, price: u32, reference: u32) -> bool {
+        price.abs_diff(reference) as f64 <= reference as f64 * self.percent / 100.0
+    }
+}
+
+// A broken invariant and the message that broke it
+#[derive(Debug, Clone)]
+pub struct Anomaly {
+    pub timestamp: u64,
+    pub kind: AnomalyKind,
+    pub message_type: u8,
+    pub order_ref: u64,
+    pub price: u32,
+    pub shares: u32,
+    pub detail: String,
+}
+
+// Checks book invariants as messages are applied and writes every violation to a CSV file
+pub struct IntegrityChecker {
+    writer: BufWriter<File>,
+    // Whether the book was crossed or locked after the previous update, so only transitions are reported
+    crossed: bool,
+    counts: [u64; ANOMALY_KINDS.len()],
+}
+
+impl IntegrityChecker {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"timestamp,anomaly,message_type,order_ref,price,shares,detail\n")?;
+        Ok(IntegrityChecker { writer, crossed: false, counts: [0; ANOMALY_KINDS.len()] })
+    }
+
+    pub fn report(&mut self, anomaly: Anomaly) -> io::Result<()> {
+        self.counts[anomaly.kind as usize] += 1;
+        writeln!(self.writer, "{},{},{},{},{}.{:04},{},{}", anomaly.timestamp, anomaly.kind.name(), anomaly.message_type as char,
+                 anomaly.order_ref, anomaly.price / 10000, anomaly.price % 10000, anomaly.shares, anomaly.detail)
+    }
+
+    // Check the top of book after an update; a missing side is never crossed
+    pub fn check_top(&mut self, timestamp: u64, message_type: u8, best_bid: Option<u32>, best_ask: Option<u32>) -> io::Result<()> {
+        let (Some(bid), Some(ask)) = (best_bid, best_ask) else {
+            self.crossed = false;
+            return Ok(());
+        };
+        let crossed = bid >= ask;
+        if crossed && !self.crossed {
+            let kind = if bid > ask { AnomalyKind::Crossed } else { AnomalyKind::Locked };
+            let detail = format!("ask {}.{:04}", ask / 10000, ask % 10000);
+            self.report(Anomaly { timestamp, kind, message_type, order_ref: 0, price: bid, shares: 0, detail })?;
+        }
+        self.crossed = crossed;
+        Ok(())
+    }
+
+    // Take the top of a book restored from a checkpoint as the previous update's
+    pub fn restore_top(&mut self, best_bid: Option<u32>, best_ask: Option<u32>) {
+        self.crossed = matches!((best_bid, best_ask), (Some(bid), Some(ask)) if bid >= ask);
+    }
+
+    // Anomalies reported so far by kind, in ANOMALY_KINDS order
+    pub fn counts(&self) -> [u64; ANOMALY_KINDS.len()] {
+        self.counts
+    }
+
+    pub fn finalize(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let total: u64 = self.counts.iter().sum();
+        tracing::info!("Found {} book anomalies ({} crossed, {} locked, {} unknown order refs, {} negative levels, {} out of band adds)",
+                       total, self.counts[AnomalyKind::Crossed as usize], self.counts[AnomalyKind::Locked as usize],
+                       self.counts[AnomalyKind::UnknownOrderRef as usize], self.counts[AnomalyKind::NegativeLevel as usize],
+                       self.counts[AnomalyKind::OutOfBand as usize]);
+        Ok(())
+    }
+}
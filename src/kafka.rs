@@ -0,0 +1,202 @@
+use std::io;
+use std::time::Duration;
+
+use kafka::producer::{Producer, Record, RequiredAcks};
+
+use crate::features::FeatureColumns;
+use crate::orderbook::{BookSnapshot, PriceLevel, Side, TradeRecord, MAX_BOOK_DEPTH};
+use crate::sink::OutputSink;
+
+// Records are buffered and sent in one produce request per batch
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+// Avro schemas of the records written in `format=avro`; prices are in 1/10000 dollars
+const SNAPSHOT_AVRO_SCHEMA: &str = r#"{"type":"record","name":"BookSnapshot","namespace":"itch","fields":[{"name":"symbol","type":"string"},{"name":"timestamp","type":"long"},{"name":"bids","type":{"type":"array","items":{"type":"record","name":"Level","fields":[{"name":"price","type":"long"},{"name":"volume","type":"long"}]}}},{"name":"asks","type":{"type":"array","items":"Level"}},{"name":"mid_price","type":"double"},{"name":"imbalance","type":"double"}]}"#;
+const TRADE_AVRO_SCHEMA: &str = r#"{"type":"record","name":"Trade","namespace":"itch","fields":[{"name":"symbol","type":"string"},{"name":"timestamp","type":"long"},{"name":"price","type":"long"},{"name":"shares","type":"long"},{"name":"side","type":"string"},{"name":"match_number","type":"long"},{"name":"message_type","type":"string"},{"name":"aggressor","type":"string"},{"name":"sign_method","type":"string"}]}"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    Json,
+    Avro,
+}
+
+// Settings parsed from `--kafka brokers=host:9092,host2:9092,topic=books,...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaConfig {
+    pub brokers: Vec<String>,
+    pub topic: String,
+    // Defaults to "<topic>-trades"
+    pub trades_topic: String,
+    pub format: RecordFormat,
+    pub batch_size: usize,
+}
+
+impl KafkaConfig {
+    // Comma separated key=value pairs; a bare entry continues the previous key's list (for brokers)
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut pairs: Vec<(String, Vec<String>)> = Vec::new();
+        for part in text.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some((key, value)) => pairs.push((key.trim().to_lowercase(), vec![value.trim().to_string()])),
+                None => match pairs.last_mut() {
+                    Some((_, values)) => values.push(part.to_string()),
+                    None => return Err(format!("Expected key=value, got '{}'", part)),
+                },
+            }
+        }
+
+        let mut brokers = Vec::new();
+        let mut topic = None;
+        let mut trades_topic = None;
+        let mut format = RecordFormat::Json;
+        let mut batch_size = DEFAULT_BATCH_SIZE;
+        for (key, values) in pairs {
+            let value = values.join(",");
+            match key.as_str() {
+                "brokers" => brokers = values,
+                "topic" => topic = Some(value),
+                "trades_topic" => trades_topic = Some(value),
+                "format" => format = match value.as_str() {
+                    "json" => RecordFormat::Json,
+                    "avro" => RecordFormat::Avro,
+                    _ => return Err(format!("Unknown Kafka record format '{}', expected json or avro", value)),
+                },
+                "batch" => batch_size = value.parse().ok().filter(|&n| n > 0)
+                    .ok_or_else(|| format!("Invalid Kafka batch size '{}'", value))?,
+                _ => return Err(format!("Unknown Kafka option '{}'", key)),
+            }
+        }
+
+        if brokers.is_empty() {
+            return Err("Kafka option 'brokers' is required".to_string());
+        }
+        let topic = topic.ok_or("Kafka option 'topic' is required")?;
+        let trades_topic = trades_topic.unwrap_or_else(|| format!("{}-trades", topic));
+        Ok(KafkaConfig { brokers, topic, trades_topic, format, batch_size })
+    }
+}
+
+// Publishes snapshots and trades to Kafka, keyed by symbol
+pub struct KafkaSink {
+    producer: Producer,
+    config: KafkaConfig,
+    bbo_only: bool,
+    // Pending (topic is trades, key, value) records
+    pending: Vec<(bool, String, Vec<u8>)>,
+}
+
+fn kafka_error(e: kafka::Error) -> io::Error {
+    io::Error::other(format!("Kafka error: {}", e))
+}
+
+impl KafkaSink {
+    pub fn connect(config: KafkaConfig, bbo_only: bool) -> io::Result<Self> {
+        let producer = Producer::from_hosts(config.brokers.clone())
+            .with_ack_timeout(Duration::from_secs(5))
+            .with_required_acks(RequiredAcks::One)
+            .create()
+            .map_err(kafka_error)?;
+        tracing::info!("Publishing snapshots to Kafka topic {} and trades to {}", config.topic, config.trades_topic);
+        if config.format == RecordFormat::Avro {
+            // Logged so consumers can register the writer schemas
+            tracing::info!("Snapshot Avro schema: {}", SNAPSHOT_AVRO_SCHEMA);
+            tracing::info!("Trade Avro schema: {}", TRADE_AVRO_SCHEMA);
+        }
+        let pending = Vec::with_capacity(config.batch_size);
+        Ok(KafkaSink { producer, config, bbo_only, pending })
+    }
+
+    fn push(&mut self, trade: bool, symbol: &str, value: Vec<u8>) -> io::Result<()> {
+        self.pending.push((trade, symbol.to_string(), value));
+        if self.pending.len() >= self.config.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for KafkaSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let value = match (self.config.format, self.bbo_only) {
+            (RecordFormat::Json, true) => snapshot.to_bbo_json(symbol, &FeatureColumns::default()).into_bytes(),
+            (RecordFormat::Json, false) => snapshot.to_json(symbol, MAX_BOOK_DEPTH, &FeatureColumns::default()).into_bytes(),
+            (RecordFormat::Avro, _) => encode_snapshot_avro(symbol, snapshot),
+        };
+        self.push(false, symbol, value)
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        let value = match self.config.format {
+            RecordFormat::Json => trade.to_json().into_bytes(),
+            RecordFormat::Avro => encode_trade_avro(symbol, trade),
+        };
+        self.push(true, symbol, value)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let records: Vec<_> = self.pending.iter()
+            .map(|(trade, key, value)| {
+                let topic = if *trade { &self.config.trades_topic } else { &self.config.topic };
+                Record::from_key_value(topic, key.as_bytes(), value.as_slice())
+            })
+            .collect();
+        self.producer.send_all(&records).map_err(kafka_error)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+// Avro binary encoding: zig-zag varint longs, little-endian doubles, length prefixed strings
+fn avro_long(out: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn avro_string(out: &mut Vec<u8>, value: &str) {
+    avro_long(out, value.len() as i64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn avro_levels(out: &mut Vec<u8>, levels: &[PriceLevel]) {
+    // Arrays are written as one block followed by the empty terminating block
+    if !levels.is_empty() {
+        avro_long(out, levels.len() as i64);
+        for level in levels {
+            avro_long(out, level.price as i64);
+            avro_long(out, level.total_volume as i64);
+        }
+    }
+    avro_long(out, 0);
+}
+
+fn encode_snapshot_avro(symbol: &str, snapshot: &BookSnapshot) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64 + 8 * (snapshot.bid_levels.len() + snapshot.ask_levels.len()));
+    avro_string(&mut out, symbol);
+    avro_long(&mut out, snapshot.timestamp as i64);
+    avro_levels(&mut out, &snapshot.bid_levels);
+    avro_levels(&mut out, &snapshot.ask_levels);
+    out.extend_from_slice(&snapshot.mid_price.to_le_bytes());
+    out.extend_from_slice(&snapshot.imbalance.to_le_bytes());
+    out
+}
+
+fn encode_trade_avro(symbol: &str, trade: &TradeRecord) -> Vec<u8> {
+    let mut out = Vec::with_capacity(48);
+    avro_string(&mut out, symbol);
+    avro_long(&mut out, trade.timestamp as i64);
+    avro_long(&mut out, trade.price as i64);
+    avro_long(&mut out, trade.shares as i64);
+    avro_string(&mut out, if trade.side == Side::Buy { "B" } else { "S" });
+    avro_long(&mut out, trade.match_number as i64);
+    avro_string(&mut out, &(trade.message_type as char).to_string());
+    avro_string(&mut out, trade.aggressor_code());
+    avro_string(&mut out, trade.sign_method.name());
+    out
+}
@@ -0,0 +1,315 @@
+// Price levels of one side of a book. Levels on the cent grid near the inside live in an array indexed by
+// tick, so adding to a level is a slot update; prices off the grid or far from the inside fall back to a
+// BTreeMap. The array follows the inside: it is recentered when a new best price lands outside it. The best
+// MAX_BOOK_DEPTH levels are also kept in order as they change, so reading the top of the book is a copy
+
+use std::collections::{btree_map, BTreeMap};
+
+use crate::orderbook::{Levels, PriceLevel, Side, MAX_BOOK_DEPTH};
+
+// One cent in price units (4 decimals)
+const TICK: u32 = 100;
+// Ticks covered by the array, 4 KB per side, allocated with the first level
+const WINDOW_TICKS: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct PriceLadder {
+    side: Side,
+    // Volume per tick from `base` up, 0 for no level
+    volumes: Vec<u32>,
+    base: u32,
+    // Lowest and highest occupied slot, valid while `dense_levels` > 0
+    low: usize,
+    high: usize,
+    dense_levels: usize,
+    // Levels outside the array; a price the array covers is never here
+    sparse: BTreeMap<u32, u32>,
+    // The best levels, best first
+    top: Levels,
+}
+
+impl PriceLadder {
+    pub fn new(side: Side) -> Self {
+        PriceLadder { side, volumes: Vec::new(), base: 0, low: 0, high: 0, dense_levels: 0, sparse: BTreeMap::new(), top: Levels::default() }
+    }
+
+    // Levels from (price, volume) pairs in any order
+    pub fn from_levels(side: Side, levels: impl IntoIterator<Item = (u32, u32)>) -> Self {
+        let mut ladder = PriceLadder::new(side);
+        for (price, volume) in levels {
+            ladder.add(price, volume);
+        }
+        ladder
+    }
+
+    fn slot(&self, price: u32) -> Option<usize> {
+        if self.volumes.is_empty() {
+            return None;
+        }
+        let offset = price.checked_sub(self.base)?;
+        let index = (offset / TICK) as usize;
+        (offset % TICK == 0 && index < WINDOW_TICKS).then_some(index)
+    }
+
+    fn price(&self, index: usize) -> u32 {
+        self.base + index as u32 * TICK
+    }
+
+    // Whether a price would be better than every level in the array
+    fn beats_array(&self, price: u32) -> bool {
+        if self.dense_levels == 0 {
+            return true;
+        }
+        match self.side {
+            Side::Buy => price > self.price(self.high),
+            Side::Sell => price < self.price(self.low),
+        }
+    }
+
+    // Move the array to be centered on a price, swapping levels between it and the map
+    fn recenter(&mut self, price: u32) {
+        if self.volumes.is_empty() {
+            self.volumes = vec![0; WINDOW_TICKS];
+        }
+        if self.dense_levels > 0 {
+            for index in self.low..=self.high {
+                let volume = std::mem::take(&mut self.volumes[index]);
+                if volume > 0 {
+                    self.sparse.insert(self.price(index), volume);
+                }
+            }
+        }
+        self.dense_levels = 0;
+        self.base = (price / TICK).saturating_sub(WINDOW_TICKS as u32 / 2) * TICK;
+
+        let end = self.base as u64 + (WINDOW_TICKS as u64) * TICK as u64;
+        let covered: Vec<u32> = self.sparse.range(self.base..)
+            .map(|(&price, _)| price)
+            .take_while(|&price| (price as u64) < end)
+            .filter(|&price| (price - self.base).is_multiple_of(TICK))
+            .collect();
+        for price in covered {
+            if let Some(volume) = self.sparse.remove(&price) {
+                self.add_to_slot((price - self.base) as usize / TICK as usize, volume);
+            }
+        }
+    }
+
+    fn add_to_slot(&mut self, index: usize, shares: u32) {
+        if self.volumes[index] == 0 {
+            if self.dense_levels == 0 {
+                self.low = index;
+                self.high = index;
+            } else {
+                self.low = self.low.min(index);
+                self.high = self.high.max(index);
+            }
+            self.dense_levels += 1;
+        }
+        self.volumes[index] += shares;
+    }
+
+    // Add shares at a price; a level with no shares is not kept
+    pub fn add(&mut self, price: u32, shares: u32) {
+        if shares == 0 {
+            return;
+        }
+        if self.slot(price).is_none() && price.is_multiple_of(TICK) && self.beats_array(price) {
+            self.recenter(price);
+        }
+        let volume = match self.slot(price) {
+            Some(index) => {
+                self.add_to_slot(index, shares);
+                self.volumes[index]
+            }
+            None => {
+                let volume = self.sparse.entry(price).or_insert(0);
+                *volume += shares;
+                *volume
+            }
+        };
+        self.update_top(price, volume);
+    }
+
+    // Take shares off a level, removing it once empty; false if the level held fewer shares
+    pub fn reduce(&mut self, price: u32, shares: u32) -> bool {
+        let Some(index) = self.slot(price) else {
+            let Some(volume) = self.sparse.get_mut(&price) else {
+                return shares == 0;
+            };
+            let enough = *volume >= shares;
+            *volume = volume.saturating_sub(shares);
+            let remaining = *volume;
+            if remaining == 0 {
+                self.sparse.remove(&price);
+            }
+            self.update_top(price, remaining);
+            return enough;
+        };
+
+        let volume = &mut self.volumes[index];
+        if *volume == 0 {
+            return shares == 0;
+        }
+        let enough = *volume >= shares;
+        *volume = volume.saturating_sub(shares);
+        let remaining = *volume;
+        if remaining == 0 {
+            self.dense_levels -= 1;
+            if self.dense_levels > 0 {
+                while self.volumes[self.low] == 0 {
+                    self.low += 1;
+                }
+                while self.volumes[self.high] == 0 {
+                    self.high -= 1;
+                }
+            }
+        }
+        self.update_top(price, remaining);
+        enough
+    }
+
+    // Whether price `a` is better than `b` for this side
+    fn better(&self, a: u32, b: u32) -> bool {
+        match self.side {
+            Side::Buy => a > b,
+            Side::Sell => a < b,
+        }
+    }
+
+    // Bring the best levels up to date after a level's volume changed to `volume`
+    fn update_top(&mut self, price: u32, volume: u32) {
+        let index = self.top.iter().position(|level| !self.better(level.price, price));
+        match index {
+            Some(index) if self.top[index].price == price => {
+                if volume > 0 {
+                    if let Some(level) = self.top.level_mut(index) {
+                        level.total_volume = volume;
+                    }
+                    return;
+                }
+                self.top.remove(index);
+                // The level after the last one moves up into the array
+                if self.top.len() == MAX_BOOK_DEPTH - 1
+                    && let Some((price, total_volume)) = self.iter().nth(MAX_BOOK_DEPTH - 1) {
+                    self.top.push(PriceLevel { price, total_volume });
+                }
+            }
+            _ if volume == 0 => {}
+            Some(index) => self.top.insert(index, PriceLevel { price, total_volume: volume }),
+            None => {
+                self.top.push(PriceLevel { price, total_volume: volume });
+            }
+        }
+    }
+
+    // Volume at a price, None without a level
+    pub fn get(&self, price: u32) -> Option<u32> {
+        match self.slot(price) {
+            Some(index) => Some(self.volumes[index]).filter(|&volume| volume > 0),
+            None => self.sparse.get(&price).copied(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense_levels + self.sparse.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The best price, None for an empty side
+    pub fn best(&self) -> Option<u32> {
+        self.top.first().map(|level| level.price)
+    }
+
+    // The best MAX_BOOK_DEPTH levels, best first
+    pub fn top_levels(&self) -> &Levels {
+        &self.top
+    }
+
+    // Up to `count` levels, best first
+    pub fn top(&self, count: usize) -> Levels {
+        let mut levels = self.top;
+        levels.truncate(count);
+        levels
+    }
+
+    // (price, volume) of every level, best first
+    pub fn iter(&self) -> LadderIter<'_> {
+        let (front, back) = if self.dense_levels > 0 { (self.low, self.high + 1) } else { (0, 0) };
+        let mut iter = LadderIter { ladder: self, front, back, sparse: self.sparse.iter(), next_dense: None, next_sparse: None };
+        iter.next_dense = iter.advance_dense();
+        iter.next_sparse = iter.advance_sparse();
+        iter
+    }
+
+    // (price, volume) of every level, lowest price first
+    pub fn ascending(&self) -> Vec<(u32, u32)> {
+        let mut levels: Vec<(u32, u32)> = self.iter().collect();
+        if self.side == Side::Buy {
+            levels.reverse();
+        }
+        levels
+    }
+}
+
+// Merges the array and the map, best price first
+pub struct LadderIter<'a> {
+    ladder: &'a PriceLadder,
+    // Array slots not yet visited
+    front: usize,
+    back: usize,
+    sparse: btree_map::Iter<'a, u32, u32>,
+    next_dense: Option<(u32, u32)>,
+    next_sparse: Option<(u32, u32)>,
+}
+
+impl LadderIter<'_> {
+    fn advance_dense(&mut self) -> Option<(u32, u32)> {
+        while self.front < self.back {
+            let index = match self.ladder.side {
+                Side::Sell => { self.front += 1; self.front - 1 }
+                Side::Buy => { self.back -= 1; self.back }
+            };
+            let volume = self.ladder.volumes[index];
+            if volume > 0 {
+                return Some((self.ladder.price(index), volume));
+            }
+        }
+        None
+    }
+
+    fn advance_sparse(&mut self) -> Option<(u32, u32)> {
+        let entry = match self.ladder.side {
+            Side::Sell => self.sparse.next(),
+            Side::Buy => self.sparse.next_back(),
+        };
+        entry.map(|(&price, &volume)| (price, volume))
+    }
+}
+
+impl Iterator for LadderIter<'_> {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<(u32, u32)> {
+        let dense_first = match (self.next_dense, self.next_sparse) {
+            (Some((dense, _)), Some((sparse, _))) => match self.ladder.side {
+                Side::Buy => dense > sparse,
+                Side::Sell => dense < sparse,
+            },
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if dense_first {
+            let level = self.next_dense;
+            self.next_dense = self.advance_dense();
+            level
+        } else {
+            let level = self.next_sparse;
+            self.next_sparse = self.advance_sparse();
+            level
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// Processing latency of a paced replay: how long after its due time (its exchange timestamp, scaled by the
+// speed) each message was through the book and the sinks. Recorded in a log-linear histogram, which like
+// an HDR histogram keeps a fixed relative precision from nanoseconds to minutes in a fixed amount of memory,
+// and reported as percentiles at intervals and at the end
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::metrics::METRICS;
+use crate::utils::format_nanos;
+
+// Sub-buckets per power of two, so a recorded value is within 1/128 (0.8%) of the bucket it lands in
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+// Percentiles of the report, as fractions
+pub const REPORTED_QUANTILES: [f64; 3] = [0.5, 0.99, 0.999];
+
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram { counts: vec![0; BUCKETS], count: 0, sum: 0, max: 0 }
+    }
+}
+
+// Values below SUB_BUCKETS get a bucket each; above, each power of two is split into SUB_BUCKETS
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let magnitude = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> magnitude) as usize - SUB_BUCKETS;
+    (magnitude as usize + 1) * SUB_BUCKETS + sub_bucket
+}
+
+// Highest value that lands in a bucket
+fn bucket_max(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let magnitude = (index / SUB_BUCKETS - 1) as u32;
+    let sub_bucket = (index % SUB_BUCKETS + SUB_BUCKETS) as u64;
+    (sub_bucket << magnitude) + ((1 << magnitude) - 1)
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, nanos: u64) {
+        self.counts[bucket(nanos)] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    // The value at or below which a fraction of the recordings fall, to the histogram's precision;
+    // 0 when nothing was recorded
+    pub fn quantile(&self, fraction: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((fraction.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_max(index).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    pub fn add(&mut self, other: &LatencyHistogram) {
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.count = 0;
+        self.sum = 0;
+        self.max = 0;
+    }
+
+    // "p50 12.1 µs, p99 310.4 µs, p99.9 1.2 ms, max 3.4 ms over 52310 messages"
+    pub fn summary(&self) -> String {
+        let percentiles: Vec<String> = REPORTED_QUANTILES.iter()
+            .map(|&fraction| format!("p{} {}", (fraction * 1000.0).round() / 10.0, format_nanos(self.quantile(fraction))))
+            .collect();
+        format!("{}, max {} over {} messages", percentiles.join(", "), format_nanos(self.max), self.count)
+    }
+}
+
+// Records the latency of every paced message, logging the percentiles of each interval and of the whole run
+pub struct LatencyMonitor {
+    total: LatencyHistogram,
+    window: LatencyHistogram,
+    interval: Option<Duration>,
+    last_report: Instant,
+}
+
+impl LatencyMonitor {
+    pub fn new(interval: Option<Duration>) -> Self {
+        LatencyMonitor { total: LatencyHistogram::default(), window: LatencyHistogram::default(), interval, last_report: Instant::now() }
+    }
+
+    // A message due at `due` has been processed
+    pub fn processed(&mut self, due: Instant) {
+        let now = Instant::now();
+        self.window.record(now.saturating_duration_since(due).as_nanos() as u64);
+        if let Some(interval) = self.interval
+            && now.duration_since(self.last_report) >= interval {
+            tracing::info!("Latency over the last {:.0?}: {}", now.duration_since(self.last_report), self.window.summary());
+            self.flush_window();
+            self.last_report = now;
+        }
+    }
+
+    fn flush_window(&mut self) {
+        self.total.add(&self.window);
+        self.window.clear();
+        publish(&self.total);
+    }
+
+    // Log the whole run's percentiles and return its histogram
+    pub fn finish(mut self) -> LatencyHistogram {
+        self.flush_window();
+        if self.total.count() > 0 {
+            tracing::info!("Latency from due time to processed: {}", self.total.summary());
+        }
+        self.total
+    }
+}
+
+fn publish(histogram: &LatencyHistogram) {
+    for (gauge, &fraction) in METRICS.latency_quantiles.iter().zip(&REPORTED_QUANTILES) {
+        gauge.store(histogram.quantile(fraction), Ordering::Relaxed);
+    }
+    METRICS.latency_count.store(histogram.count(), Ordering::Relaxed);
+    METRICS.latency_sum_ns.store(histogram.sum(), Ordering::Relaxed);
+}
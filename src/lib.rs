@@ -0,0 +1,89 @@
+// The parser, orderbook and sinks as a library; the command line tool is src/main.rs
+pub mod affinity;
+pub mod analytics;
+#[cfg(feature = "native")]
+pub mod auth;
+#[cfg(feature = "native")]
+pub mod api;
+pub mod audit;
+pub mod backtest;
+pub mod bars;
+pub mod batch;
+pub mod bloom;
+pub mod checkpoint;
+pub mod cli;
+pub mod compare;
+pub mod compress;
+pub mod config;
+#[cfg(feature = "native")]
+pub mod dashboard;
+pub mod delta;
+pub mod directory;
+pub mod estimators;
+pub mod events;
+pub mod features;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod file_io;
+pub mod filter;
+#[cfg(feature = "flight")]
+pub mod flight;
+pub mod handler;
+pub mod heatmap;
+pub mod hidden;
+pub mod integrity;
+pub mod ladder;
+pub mod latency;
+pub mod lobster;
+#[cfg(feature = "native")]
+pub mod logging;
+pub mod lots;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod matching;
+pub mod message_types;
+pub mod metrics;
+pub mod orderbook;
+#[cfg(feature = "native")]
+pub mod outbox;
+pub mod parser;
+pub mod participants;
+pub mod partition;
+pub mod pcap;
+pub mod pool;
+pub mod prescan;
+pub mod profile;
+pub mod profiling;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod queue;
+pub mod replay;
+pub mod repl;
+pub mod scramble;
+#[cfg(feature = "native")]
+pub mod shutdown;
+pub mod simd;
+pub mod selftest;
+pub mod sink;
+pub mod snapshot;
+#[cfg(feature = "native")]
+pub mod sqlite;
+pub mod summary;
+pub mod testdata;
+pub mod timestamps;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod utils;
+pub mod validation;
+#[cfg(feature = "native")]
+pub mod viewer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "native")]
+pub mod websocket;
+pub mod wire;
+pub mod writer;
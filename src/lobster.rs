@@ -0,0 +1,128 @@
+// LOBSTER output (--output-format lobster): the message and orderbook file pair of the LOBSTER academic data
+// format, so existing research code can read the books. Each order message gets a message row and, on the same
+// line of the orderbook file, the levels it left. Neither file has a header
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::orderbook::{BookSnapshot, Levels, MarketEvent, OrderMessage, Side, TradeRecord};
+use crate::queue::OrderEvent;
+use crate::sink::OutputSink;
+
+// Prices of the empty levels of the orderbook file
+const EMPTY_ASK: i64 = 9_999_999_999;
+const EMPTY_BID: i64 = -9_999_999_999;
+
+// Event types of the message file
+const NEW_ORDER: u8 = 1;
+const CANCEL: u8 = 2;
+const DELETE: u8 = 3;
+const EXECUTION: u8 = 4;
+const HIDDEN_EXECUTION: u8 = 5;
+const HALT: u8 = 7;
+
+// The message and orderbook files for an output path, named like LOBSTER's: the path without its extension,
+// then _message_LEVELS.csv and _orderbook_LEVELS.csv (AAPL_2012-06-21.csv gives AAPL_2012-06-21_message_10.csv)
+pub fn lobster_paths(path: &Path, levels: usize) -> (PathBuf, PathBuf) {
+    let stem = path.with_extension("");
+    let named = |kind: &str| {
+        let mut name = stem.clone().into_os_string();
+        name.push(format!("_{}_{}.csv", kind, levels));
+        PathBuf::from(name)
+    };
+    (named("message"), named("orderbook"))
+}
+
+// Ask price, ask size, bid price, bid size of each level, best first
+fn book_row(bids: &Levels, asks: &Levels, levels: usize) -> String {
+    let mut row = String::new();
+    for i in 0..levels {
+        let (ask, ask_size) = asks.get(i).map_or((EMPTY_ASK, 0), |level| (level.price as i64, level.total_volume));
+        let (bid, bid_size) = bids.get(i).map_or((EMPTY_BID, 0), |level| (level.price as i64, level.total_volume));
+        if i > 0 {
+            row.push(',');
+        }
+        row.push_str(&format!("{},{},{},{}", ask, ask_size, bid, bid_size));
+    }
+    row
+}
+
+fn direction(side: Side) -> i8 {
+    if side == Side::Buy { 1 } else { -1 }
+}
+
+pub struct LobsterSink {
+    messages: BufWriter<File>,
+    orderbook: BufWriter<File>,
+    levels: usize,
+    // Orderbook row of the last order message, repeated for hidden executions and halts
+    row: String,
+}
+
+impl LobsterSink {
+    pub fn create(path: &Path, levels: usize) -> io::Result<Self> {
+        let (messages, orderbook) = lobster_paths(path, levels);
+        Ok(LobsterSink {
+            messages: BufWriter::new(File::create(messages)?),
+            orderbook: BufWriter::new(File::create(orderbook)?),
+            levels,
+            row: book_row(&Levels::default(), &Levels::default(), levels),
+        })
+    }
+
+    // Time in seconds after midnight, prices in 1/10000 dollars as in the feed
+    fn write_row(&mut self, timestamp: u64, event: u8, order_id: u64, size: u32, price: i64, direction: i8) -> io::Result<()> {
+        writeln!(self.messages, "{}.{:09},{},{},{},{},{}", timestamp / 1_000_000_000, timestamp % 1_000_000_000, event, order_id, size, price, direction)?;
+        writeln!(self.orderbook, "{}", self.row)
+    }
+}
+
+impl OutputSink for LobsterSink {
+    fn write_snapshot(&mut self, _symbol: &str, _snapshot: &BookSnapshot) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_order(&mut self, _symbol: &str, message: &OrderMessage, bids: &Levels, asks: &Levels) -> io::Result<()> {
+        let event = match message.event {
+            OrderEvent::Add => NEW_ORDER,
+            OrderEvent::Cancel => CANCEL,
+            OrderEvent::Delete | OrderEvent::Replace => DELETE,
+            OrderEvent::Execute => EXECUTION,
+            OrderEvent::Open => return Ok(()),
+        };
+        self.row = book_row(bids, asks, self.levels);
+        self.write_row(message.timestamp, event, message.order_ref, message.shares, message.price as i64, direction(message.side))
+    }
+
+    fn wants_orders(&self) -> bool {
+        true
+    }
+
+    // Executions of visible orders come as order messages; non-displayed trades have no order of the book
+    fn write_trade(&mut self, _symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        if trade.message_type != b'P' {
+            return Ok(());
+        }
+        self.write_row(trade.timestamp, HIDDEN_EXECUTION, 0, trade.shares, trade.price as i64, direction(trade.side))
+    }
+
+    // Trading actions of the symbol, with -1 in the price column for a halt or pause, 0 for quotation only and 1
+    // for trading again
+    fn write_event(&mut self, event: &MarketEvent) -> io::Result<()> {
+        if event.message_type != b'H' {
+            return Ok(());
+        }
+        let state = match event.description.split(" (").next() {
+            Some("Halted" | "Paused") => -1,
+            Some("Quotation only") => 0,
+            Some("Trading") => 1,
+            _ => return Ok(()),
+        };
+        self.write_row(event.timestamp, HALT, 0, 0, state, 0)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.messages.flush()?;
+        self.orderbook.flush()
+    }
+}
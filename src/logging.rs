@@ -0,0 +1,123 @@
+// Logging of the command line tool: tracing's text lines, or one JSON object per event for log aggregation,
+// on stdout or appended to a file. RUST_LOG filters by target and level (info by default)
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde_json::Value;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    // Human-readable lines
+    #[default]
+    Text,
+    // One JSON object per line
+    Json,
+}
+
+// Install the global subscriber; a log file is created if needed and appended to
+pub fn init(format: LogFormat, file: Option<&Path>) -> io::Result<()> {
+    let writer = match file {
+        Some(path) => BoxMakeWriter::new(Mutex::new(OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Can't open log file {}: {}", path.display(), e)))?)),
+        None => BoxMakeWriter::new(io::stdout),
+    };
+    // Also forwards the records of crates that use `log`
+    subscriber(format, writer, file.is_none()).try_init().map_err(io::Error::other)
+}
+
+// The subscriber init installs, writing to any writer
+pub fn subscriber(format: LogFormat, writer: BoxMakeWriter, ansi: bool) -> Box<dyn Subscriber + Send + Sync> {
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer).with_ansi(ansi && format == LogFormat::Text);
+    let registry = tracing_subscriber::registry().with(targets());
+    match format {
+        LogFormat::Text => Box::new(registry.with(layer)),
+        LogFormat::Json => Box::new(registry.with(layer.event_format(JsonFormat))),
+    }
+}
+
+fn targets() -> Targets {
+    let default = Targets::new().with_default(LevelFilter::INFO);
+    match std::env::var("RUST_LOG") {
+        Ok(var) => Targets::from_str(&var).unwrap_or_else(|e| {
+            eprintln!("Ignoring RUST_LOG={:?}: {}", var, e);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+// {"timestamp":"2026-01-30T14:30:00.123456Z","level":"INFO","target":"...","message":"...", other fields, "spans":[...]}
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, context: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let metadata = event.metadata();
+        let mut fields = JsonFields(vec![
+            ("timestamp", timestamp.into()),
+            ("level", metadata.level().as_str().into()),
+            ("target", metadata.target().into()),
+        ]);
+        event.record(&mut fields);
+        if let Some(scope) = context.event_scope() {
+            let spans: Vec<Value> = scope.from_root().map(|span| span.name().into()).collect();
+            fields.0.push(("spans", spans.into()));
+        }
+        // Written by hand to keep the order, which a serde_json map would sort
+        write!(writer, "{{")?;
+        for (index, (name, value)) in fields.0.iter().enumerate() {
+            let separator = if index > 0 { "," } else { "" };
+            write!(writer, "{}{}:{}", separator, Value::from(*name), value)?;
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+// An event's fields in order, numbers and booleans kept as such
+struct JsonFields(Vec<(&'static str, Value)>);
+
+impl Visit for JsonFields {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.push((field.name(), value.into()));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.push((field.name(), value.into()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.push((field.name(), value.into()));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.push((field.name(), value.into()));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name(), value.into()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push((field.name(), format!("{:?}", value).into()));
+    }
+}
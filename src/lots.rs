@@ -0,0 +1,59 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::orderbook::{Order, Side, TradeRecord};
+
+// Round lot until the symbol's stock directory message says otherwise, the size of most stocks
+pub const DEFAULT_ROUND_LOT: u32 = 100;
+
+// What the book does with odd-lot orders, those of fewer shares than a round lot when added
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OddLotMode {
+    // Odd lots rest on the book and their trades are written like any other
+    #[default]
+    Include,
+    // Odd-lot orders are kept off the book and trades of fewer shares than a round lot aren't written
+    Exclude,
+}
+
+fn side_code(side: Side) -> char {
+    match side {
+        Side::Buy => 'B',
+        Side::Sell => 'S',
+    }
+}
+
+// Writes every odd-lot order and odd-lot trade of the symbol to a CSV file, whatever the OddLotMode
+pub struct OddLotReport {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    orders: u64,
+    trades: u64,
+}
+
+impl OddLotReport {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"timestamp,kind,message_type,order_ref,match_number,side,price,shares,round_lot\n")?;
+        Ok(OddLotReport { path: path.to_path_buf(), writer, orders: 0, trades: 0 })
+    }
+
+    pub fn order(&mut self, message_type: u8, order: &Order, round_lot: u32) -> io::Result<()> {
+        self.orders += 1;
+        writeln!(self.writer, "{},order,{},{},,{},{}.{:04},{},{}", order.timestamp, message_type as char, order.ref_number,
+                 side_code(order.side), order.price / 10000, order.price % 10000, order.shares, round_lot)
+    }
+
+    pub fn trade(&mut self, trade: &TradeRecord, round_lot: u32) -> io::Result<()> {
+        self.trades += 1;
+        writeln!(self.writer, "{},trade,{},,{},{},{}.{:04},{},{}", trade.timestamp, trade.message_type as char, trade.match_number,
+                 side_code(trade.side), trade.price / 10000, trade.price % 10000, trade.shares, round_lot)
+    }
+
+    pub fn finalize(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        tracing::info!("Wrote {} odd-lot orders and {} odd-lot trades to {}", self.orders, self.trades, self.path.display());
+        Ok(())
+    }
+}
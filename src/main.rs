@@ -1,45 +1,35 @@
-use clap::Parser;
-use std::path::PathBuf;
+use std::path::Path;
+use std::sync::Arc;
 
 use std::time::Instant;
-use crate::websocket::WebSocketServer;
-
-mod file_io;
-mod message_types;
-mod orderbook;
-mod parser;
-mod utils;
-mod websocket;
+use futures_util::{StreamExt, TryStreamExt};
+use tokio::sync::broadcast;
+use nasdaq_itch_orderbook::affinity::CorePlan;
+use nasdaq_itch_orderbook::cli::{AffinityArgs, BookArgs, BuildBookArgs, Cli, Command, ExternalSinkArgs, MatchArgs, ReplayArgs, ServeArgs, StatsArgs, TradesArgs};
+use nasdaq_itch_orderbook::features::TradeStats;
+use nasdaq_itch_orderbook::file_io::InputData;
+use nasdaq_itch_orderbook::matching::MatchingEngine;
+use nasdaq_itch_orderbook::message_types::MessageType;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions, OutputFormat};
+use nasdaq_itch_orderbook::parser::ParserOptions;
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::websocket::WebSocketServer;
+use nasdaq_itch_orderbook::{affinity, analytics, api, backtest, bars, batch, checkpoint, compare, directory, file_io, filter, heatmap, hidden, integrity, logging, lots, metrics, parser, participants, partition,
+                            prescan, profile, queue, repl, scramble, selftest, shutdown, sink, snapshot, summary, utils, viewer, websocket, writer};
+#[cfg(feature = "flight")]
+use nasdaq_itch_orderbook::flight;
+#[cfg(feature = "grpc")]
+use nasdaq_itch_orderbook::grpc;
+#[cfg(feature = "kafka")]
+use nasdaq_itch_orderbook::kafka;
+#[cfg(feature = "postgres")]
+use nasdaq_itch_orderbook::postgres;
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Path to the ITCH 5.0 data file
-    #[arg(short, long)]
-    file: PathBuf,
-
-    /// Stock symbol to track
-    #[arg(short, long)]
-    symbol: String,
-
-    /// Output file for the orderbook
-    #[arg(short, long)]
-    output_file: PathBuf,
-
-    /// Enable WebSocket server
-    #[arg(short, long, value_parser, default_value = "false")]
-    websocket: bool,
-
-    /// WebSocket server port
-    #[arg(short = 'p', long, value_parser, default_value = "8473")]
-    port: u16,
-}
-
 /*
-samply record ./target/release/nasdaq-itch-orderbook \
+samply record ./target/release/nasdaq-itch-orderbook build-book \
 -f ~/Downloads/01302020.NASDAQ_ITCH50 \
 -s INTC \
 -o ~/RustProjects/nasdaq-itch-orderbook/orderbooks/AAPL_orderbook.csv
@@ -48,48 +38,529 @@ conda activate base
 
 python visualize.py ../orderbooks/AAPL_orderbook.csv
  */
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+// Reserves huge OS pages for mimalloc on a NUMA node (-1 for none), giving up after the timeout.
+// Part of mimalloc's C API, which the crate only binds with its extended feature
+unsafe extern "C" {
+    fn mi_reserve_huge_os_pages_at(pages: usize, numa_node: std::ffi::c_int, timeout_msecs: usize) -> std::ffi::c_int;
+}
 
-    // Parse command line arguments
-    let args = Args::parse();
+// Milliseconds to wait for the kernel to hand over huge pages
+const HUGE_PAGE_TIMEOUT_MS: usize = 10_000;
 
-    // Convert stock symbol to fixed-length array expected by ITCH format
-    let symbol = utils::pad_stock_symbol(&args.symbol);
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Parse command line arguments, then start logging as they ask
+    let cli = Cli::parse_with_config();
+    logging::init(cli.log_format, cli.log_file.as_deref())?;
+    let Some(command) = cli.command else {
+        return selftest::run();
+    };
 
-    tracing::info!("Processing ITCH data for symbol: {}", args.symbol);
+    let affinity_args = match &command {
+        Command::BuildBook(args) => args.affinity.clone(),
+        Command::Serve(args) => args.affinity.clone(),
+        _ => AffinityArgs::default(),
+    };
+    reserve_huge_pages(&affinity_args);
+    // A signal ends the servers normally, but cuts the commands that work through a file short
+    let batch = matches!(command, Command::BuildBook(_) | Command::Trades(_) | Command::Stats(_));
 
+    // The runtime is built here rather than by #[tokio::main] so its threads can be pinned
+    let cores = affinity_args.plan();
+    let runtime_cores = cores.runtime.clone();
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if !runtime_cores.is_empty() {
+        runtime.on_thread_start(move || {
+            if let Err(e) = affinity::pin_current_thread(&runtime_cores) {
+                tracing::warn!("{}", e);
+            }
+        });
+    }
+    runtime.build()?.block_on(async {
+        tokio::spawn(async {
+            if let Err(e) = shutdown::handle_signals().await {
+                tracing::warn!("Can't handle signals: {}", e);
+            }
+        });
+        run(command, cores).await
+    })?;
 
-    // Memory map the input file
-    let mapped_file = file_io::map_file(&args.file)?;
-    tracing::info!("File mapped: {} bytes", mapped_file.len());
+    #[cfg(feature = "profiling")]
+    if cli.profile_report {
+        print!("{}", nasdaq_itch_orderbook::profiling::report());
+    }
+    if batch && shutdown::requested() {
+        std::process::exit(summary::RunStatus::Interrupted.exit_code());
+    }
+    Ok(())
+}
 
-    // Create orderbook
-    let mut order_book = orderbook::OrderBook::new(symbol, &args.output_file)?;
-    tracing::info!("Created Limit Orderbook for symbol: {}", args.symbol);
+// Put the allocator's memory on the parser's NUMA node before the book fills it
+fn reserve_huge_pages(args: &AffinityArgs) {
+    let Some(pages) = args.reserve_huge_pages else {
+        return;
+    };
+    let node = args.plan().parser.and_then(affinity::numa_node);
+    // SAFETY: only reserves memory for the allocator, which may be called at any time
+    let result = unsafe { mi_reserve_huge_os_pages_at(pages, node.map_or(-1, |node| node as std::ffi::c_int), HUGE_PAGE_TIMEOUT_MS) };
+    match (result, node) {
+        (0, Some(node)) => tracing::info!("Reserved {} huge pages on NUMA node {}", pages, node),
+        (0, None) => tracing::info!("Reserved {} huge pages", pages),
+        (error, _) => tracing::warn!("Could not reserve {} huge pages ({}), continuing without them", pages, std::io::Error::from_raw_os_error(error)),
+    }
+}
 
-    let start_time = Instant::now();
-    // Process the file
-    parser::process_itch_file(&mapped_file, &mut order_book)?;
+async fn run(command: Command, cores: CorePlan) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::BuildBook(args) => build_book(args, cores).await,
+        Command::Trades(args) => trades(args).await,
+        Command::Stats(args) => stats(args).await,
+        Command::Serve(args) => serve(args, cores).await,
+        Command::Snapshot(args) => snapshot::run(args),
+        Command::Replay(args) => replay(args).await,
+        Command::Directory(args) => directory::run(args),
+        Command::ListSymbols(args) => directory::list_symbols(args),
+        Command::Profile(args) => profile::run(args),
+        Command::Backtest(args) => backtest::run(args),
+        Command::Match(args) => match_orders(args).await,
+        Command::Compare(args) => compare::run(args),
+        Command::View(args) => viewer::run(args),
+        Command::Filter(args) => filter::run(args),
+        Command::Scramble(args) => scramble::run(args),
+    }
+}
 
+// Sinks for the external databases and brokers that were configured
+#[allow(unused_variables, unused_mut)]
+async fn external_sinks(args: &ExternalSinkArgs, bbo_only: bool) -> Result<Vec<(&'static str, Box<dyn OutputSink>)>, Box<dyn std::error::Error>> {
+    let mut sinks: Vec<(&'static str, Box<dyn OutputSink>)> = Vec::new();
+    #[cfg(feature = "kafka")]
+    if let Some(config) = args.kafka.clone() {
+        sinks.push(("kafka", Box::new(kafka::KafkaSink::connect(config, bbo_only)?)));
+    }
+    #[cfg(feature = "postgres")]
+    if let Some(url) = args.postgres.clone() {
+        let config = postgres::PostgresConfig {
+            url,
+            table: args.postgres_table.clone(),
+            batch_size: args.postgres_batch_size,
+            timescale: args.timescale,
+        };
+        let depth = if bbo_only { 1 } else { nasdaq_itch_orderbook::orderbook::MAX_BOOK_DEPTH };
+        // The synchronous client runs its own runtime, so keep it off the async threads
+        let sink = tokio::task::spawn_blocking(move || postgres::PostgresSink::connect(&config, bbo_only, depth)).await??;
+        sinks.push(("postgres", Box::new(sink)));
+    }
+    Ok(sinks)
+}
 
-    // Finalize and print statistics
-    order_book.finalize()?;
+// Move the output file's sink to a writer thread, unless the queue is 0, nothing is written or there is
+// no second core to run it on
+fn on_writer_thread(sink: Box<dyn OutputSink>, format: OutputFormat, queue: usize, core: Option<usize>) -> std::io::Result<Box<dyn OutputSink>> {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    if queue == 0 || format == OutputFormat::Null || cores == 1 {
+        return Ok(sink);
+    }
+    Ok(Box::new(writer::WriterThread::spawn_pinned(sink, queue, core)?))
+}
+
+// Create the orderbook for the tracked symbol, writing to the given sinks. Bars are built for --bars-output,
+// or when a sink streams them
+fn create_order_book(args: &BookArgs, sinks: Vec<Box<dyn OutputSink>>, stream_bars: bool) -> std::io::Result<OrderBook> {
+    // Convert stock symbol to fixed-length array expected by ITCH format
+    let symbol = utils::pad_stock_symbol(args.symbol());
+    let integrity = args.anomalies_output.as_deref().map(integrity::IntegrityChecker::create).transpose()?;
+    let queue = args.order_lifecycle.as_deref().map(queue::QueueTracker::create).transpose()?;
+    let analytics = args.stats_output.as_deref().map(analytics::OrderAnalytics::new);
+    let participants = args.participants_output.as_deref().map(participants::ParticipantReport::new);
+    let hidden = args.hidden_output.as_deref().map(hidden::HiddenLiquidityReport::new);
+    let odd_lot_report = args.odd_lots_output.as_deref().map(lots::OddLotReport::create).transpose()?;
+    let bars = (args.bars_output.is_some() || stream_bars)
+        .then(|| bars::BarBuilder::new(&args.bar_interval, args.bars_output.as_deref()))
+        .transpose()?;
+    let heatmap = args.heatmap_output.as_deref()
+        .map(|path| heatmap::Heatmap::new(path, args.heatmap_interval, args.heatmap_tick, args.heatmap_levels));
+    let options = OrderBookOptions {
+        write_mode: args.write_mode(),
+        bbo_only: args.bbo_only,
+        sinks,
+        integrity,
+        imbalance_weights: args.imbalance_weights(),
+        estimator_windows: args.feature_columns().estimator_windows(),
+        queue,
+        analytics,
+        participants,
+        bars,
+        heatmap,
+        hidden,
+        odd_lots: args.odd_lots,
+        odd_lot_report,
+        lots: args.lots,
+        price_band: args.price_band.map(|percent| integrity::PriceBand { percent, action: args.price_band_action }),
+        fast_filter: args.fast_filter,
+        expected_orders: args.expected_orders,
+    };
+
+    let order_book = OrderBook::new(symbol, options);
+    tracing::info!("Created Limit Orderbook for symbol: {}", args.symbol());
+    Ok(order_book)
+}
+
+// Parse the input into the books off the async runtime so servers keep serving clients, on the given
+// core if any. Finalize and print statistics on the same thread, which also flushes the sinks
+async fn process(input: InputData, mut order_books: Vec<OrderBook>, options: ParserOptions, core: Option<usize>) -> Result<Vec<OrderBook>, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    let input_len = input.len();
+    // Ctrl-C stops the parser at a message boundary, and the books are finalized as at the end of the input
+    let options = ParserOptions { stop: Some(shutdown::flag()), ..options };
+    let order_books = tokio::task::spawn_blocking(move || {
+        affinity::pin_current_thread(core.as_slice())?;
+        // Finish the output even when strict validation fails, so it can be inspected
+        let result = parser::process_itch_file(&input, &mut order_books, &options);
+        for order_book in &mut order_books {
+            order_book.finalize()?;
+        }
+        result.map(|()| order_books)
+    }).await??;
 
     let duration = start_time.elapsed();
-    let throughput = mapped_file.len() as f64 / (1024.0 * 1024.0) / duration.as_secs_f64();
+    let throughput = input_len as f64 / (1024.0 * 1024.0) / duration.as_secs_f64();
 
     tracing::info!("Processing completed in {:.2?}", duration);
     tracing::info!("Throughput: {:.2} MB/s", throughput);
+    Ok(order_books)
+}
 
-    // Start WebSocket server if enabled
-    if args.websocket {
-        println!("Starting WebSocket server on port {}", args.port);
-        let server = WebSocketServer::new(&args.output_file.to_string_lossy(), args.port);
-        server.start().await?;
+async fn build_book(args: BuildBookArgs, cores: CorePlan) -> Result<(), Box<dyn std::error::Error>> {
+    if let (Some(_), Some(option)) = (&args.resume_from, args.book.unresumable()) {
+        return Err(format!("--resume-from doesn't support {}", option).into());
     }
+    let Some(pattern) = &args.input.file_glob else {
+        build_day(&args, args.input.file()?, &cores).await?;
+        return Ok(());
+    };
+    let files = batch::expand_glob(pattern)?;
+    tracing::info!("Processing {} files matching {}", files.len(), pattern);
 
+    // Up to --jobs days run at once, and are reported in file order
+    let days: Vec<batch::DaySummary> = futures_util::stream::iter(&files)
+        // No new days are started after Ctrl-C
+        .take_while(|_| std::future::ready(!shutdown::requested()))
+        .map(|file| build_day(&args, file, &cores))
+        .buffered(args.jobs as usize)
+        .try_collect()
+        .await?;
+    batch::report(&days, args.batch_report.as_deref())?;
     Ok(())
-}
\ No newline at end of file
+}
+
+// Build the book of one input file, and write its summary if asked, also when it fails
+async fn build_day(args: &BuildBookArgs, file: &Path, cores: &CorePlan) -> Result<batch::DaySummary, Box<dyn std::error::Error>> {
+    let Some(summary_path) = &args.summary_output else {
+        return run_day(args, file, cores, None).await;
+    };
+    let summary_path = if args.input.file_glob.is_some() { batch::day_output(summary_path, file) } else { summary_path.clone() };
+    // The input is hashed on another thread while it is parsed
+    let input = file.to_path_buf();
+    let hasher = std::thread::spawn(move || summary::sha256_file(&input));
+    let mut run = summary::RunSummary::new(file, args.book.symbol.clone().into_iter().collect());
+    let result = run_day(args, file, cores, Some(&mut run)).await;
+    match hasher.join() {
+        Ok(Ok(hash)) => run.sha256 = Some(hash),
+        Ok(Err(e)) => tracing::warn!("Can't hash {}: {}", file.display(), e),
+        Err(_) => tracing::warn!("Hashing {} failed", file.display()),
+    }
+    run.finish(result.as_ref().err().map(|e| e.to_string()), shutdown::requested());
+    run.write(&summary_path)?;
+    tracing::info!("Wrote run summary to {}", summary_path.display());
+    result
+}
+
+// The books of one input file, counting what the sinks are handed into the summary if any. With
+// --file-glob, every output path gets the day's name, and with --auto-top each book's also gets its symbol
+async fn run_day(args: &BuildBookArgs, file: &Path, cores: &CorePlan, mut run: Option<&mut summary::RunSummary>) -> Result<batch::DaySummary, Box<dyn std::error::Error>> {
+    let start_time = Instant::now();
+    let day_path = |path: &Path| if args.input.file_glob.is_some() { batch::day_output(path, file) } else { path.to_path_buf() };
+    // With --interactive and no output file, the books are only kept in memory; the run's other files are named
+    // after the symbol then
+    let output_format = if args.output_file.is_some() { args.output_format } else { OutputFormat::Null };
+    let output_file = day_path(args.output_file.as_deref().unwrap_or(Path::new(args.book.symbol.as_deref().unwrap_or("orderbook"))));
+
+    // Memory map the input file, and check --start-offset against it before creating any output
+    let mapped_file = file_io::load_input(file, args.input.encap)?;
+    if let Some(run) = run.as_deref_mut() {
+        run.input_bytes = Some(mapped_file.len() as u64);
+    }
+    let mut start_offset = args.chunk.start(&mapped_file)?;
+
+    let symbols = match args.auto_top {
+        Some(top) => {
+            let busiest = profile::most_active_symbols(&mapped_file, top as usize, args.auto_top_window);
+            if busiest.is_empty() {
+                return Err(format!("No symbol of the directory has messages in {}", file.display()).into());
+            }
+            let listed: Vec<String> = busiest.iter().map(|(symbol, messages)| format!("{} ({} messages)", symbol, messages)).collect();
+            tracing::info!("Most active symbols of {}: {}", file.display(), listed.join(", "));
+            busiest.into_iter().map(|(symbol, _)| symbol).collect()
+        }
+        None => vec![args.book.symbol().to_string()],
+    };
+    tracing::info!("Processing {} for symbols: {}", file.display(), symbols.join(", "));
+    if let Some(run) = run.as_deref_mut() {
+        run.symbols = symbols.clone();
+    }
+    let symbol_path = |path: &Path, symbol: &str| if args.auto_top.is_some() { partition::symbol_output(path, symbol) } else { path.to_path_buf() };
+    // The files of the whole run (audit report, checkpoint) are named after the output of the first symbol
+    let base_path = args.rotation.partitioning(&symbols[0], file).base_path(&symbol_path(&output_file, &symbols[0]));
+
+    let mut order_books = Vec::with_capacity(symbols.len());
+    let mut trade_logs = Vec::new();
+    for symbol in &symbols {
+        let mut book_args = args.book.clone();
+        book_args.symbol = Some(symbol.clone());
+        for path in book_args.outputs_mut() {
+            *path = path.as_deref().map(|path| symbol_path(&day_path(path), symbol));
+        }
+        let output_file = symbol_path(&output_file, symbol);
+        let partitioning = args.rotation.partitioning(symbol, file);
+        book_args.fill_outputs(&partitioning)?;
+
+        // The main output file comes first, then the external sinks
+        let sink = partition::create_output_sink(output_format, &output_file, args.book.bbo_only, args.book.feature_columns(), args.compress, partitioning)?;
+        let mut sinks = vec![(format!("{:?}", output_format).to_lowercase(), on_writer_thread(sink, output_format, args.writer_queue, cores.writer)?)];
+        sinks.extend(external_sinks(&args.external, args.book.bbo_only).await?.into_iter().map(|(name, sink)| (name.to_string(), sink)));
+        let mut sinks: Vec<Box<dyn OutputSink>> = sinks.into_iter().enumerate().map(|(index, (name, sink))| match run.as_deref_mut() {
+            Some(run) => run.count(sink, &name, (index == 0 && args.output_file.is_some()).then_some(output_file.as_path())),
+            None => sink,
+        }).collect();
+        if args.interactive {
+            let log = repl::TradeLog::default();
+            sinks.push(Box::new(log.clone()));
+            trade_logs.push(log);
+        }
+        order_books.push(create_order_book(&book_args, sinks, false)?);
+    }
+
+    // Resuming skips everything before the checkpoint's offset; there is only one book then
+    if let Some(path) = &args.resume_from
+        && let [order_book] = order_books.as_mut_slice() {
+        let checkpoint = checkpoint::Checkpoint::read(path)?;
+        if checkpoint.offset as usize > mapped_file.len() {
+            return Err(format!("Checkpoint offset {} is past the end of the input", checkpoint.offset).into());
+        }
+        start_offset = checkpoint.offset as usize;
+        tracing::info!("Resuming from {} at offset {} ({} open orders, timestamp {})",
+                       path.display(), checkpoint.offset, checkpoint.orders.len(), checkpoint.timestamp);
+        order_book.restore(checkpoint)?;
+    }
+    if let Some(run) = run.as_deref_mut() {
+        run.start_offset = start_offset as u64;
+    }
+
+    let parser_options = ParserOptions {
+        feed: args.input.feed,
+        strict: args.input.strict,
+        audit: args.audit.then(|| base_path.with_extension("audit.csv")),
+        events: args.input.events_output.as_deref().map(day_path),
+        checkpoint: args.checkpoint_interval.map(|interval| checkpoint::CheckpointOptions {
+            path: args.checkpoint_file.as_deref().map_or_else(|| base_path.with_extension("checkpoint.bin"), day_path),
+            interval,
+        }),
+        start_offset,
+        stop_at: None,
+        ranges: args.prescan.then(|| {
+            let symbols: Vec<[u8; 8]> = symbols.iter().map(|symbol| utils::pad_stock_symbol(symbol)).collect();
+            prescan::relevant_ranges(&mapped_file, &symbols)
+        }),
+        pace: None,
+        latency_interval: None,
+        stop: None,
+        max_messages: args.chunk.max_messages,
+    };
+    let books = process(mapped_file, order_books, parser_options, cores.parser).await?;
+    if let Some(run) = run {
+        run.record_books(&books);
+    }
+    // Every book sees every message
+    let (messages, _) = books[0].counts();
+    let mut trades = TradeStats::default();
+    for book in &books {
+        let stats = book.trade_stats();
+        trades.volume += stats.volume;
+        trades.notional += stats.notional;
+        trades.trades += stats.trades;
+    }
+    let day = batch::DaySummary {
+        day: batch::day_name(file),
+        output: output_file,
+        messages,
+        updates: books.iter().map(|book| book.counts().1).sum(),
+        trades,
+        seconds: start_time.elapsed().as_secs_f64(),
+    };
+    if args.interactive {
+        let explorer = repl::Explorer::new(books, &trade_logs);
+        tokio::task::spawn_blocking(move || explorer.run(std::io::stdin().lock(), &mut std::io::stdout())).await??;
+    }
+    Ok(day)
+}
+
+async fn trades(args: TradesArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mapped_file = file_io::load_input(args.input.file()?, args.input.encap)?;
+
+    let sink = sink::TradesCsvSink::create(&args.output_file)?;
+    let options = OrderBookOptions { sinks: vec![Box::new(sink)], ..Default::default() };
+    let order_book = OrderBook::new(utils::pad_stock_symbol(&args.symbol), options);
+
+    let parser_options = ParserOptions { feed: args.input.feed, strict: args.input.strict, events: args.input.events_output.clone(), ..Default::default() };
+    process(mapped_file, vec![order_book], parser_options, None).await?;
+    Ok(())
+}
+
+async fn stats(args: StatsArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mapped_file = file_io::load_input(args.input.file()?, args.input.encap)?;
+
+    // No books, only the parser's per-type counts
+    let parser_options = ParserOptions {
+        feed: args.input.feed,
+        strict: args.input.strict,
+        events: args.input.events_output.clone(),
+        start_offset: args.chunk.start(&mapped_file)?,
+        max_messages: args.chunk.max_messages,
+        ..Default::default()
+    };
+    process(mapped_file, Vec::new(), parser_options, None).await?;
+
+    let counts = metrics::METRICS.message_counts();
+    let total: u64 = counts.iter().map(|&(_, count)| count).sum();
+    println!("{:<6}{:<46}{:>14}{:>9}", "Type", "Message", "Count", "Share");
+    for (byte, count) in counts {
+        println!("{:<6}{:<46}{:>14}{:>8.2}%", byte as char, MessageType::from(byte).name(), count,
+                 count as f64 * 100.0 / total as f64);
+    }
+    println!("{:<52}{:>14}", "Total", total);
+    Ok(())
+}
+
+async fn serve(args: ServeArgs, cores: CorePlan) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!("Processing ITCH data for symbol: {}", args.book.symbol());
+
+    // Memory map the input file
+    let mapped_file = file_io::load_input(args.input.file()?, args.input.encap)?;
+
+    // The server is started first and streams snapshots while the file is parsed
+    let (tx, _) = broadcast::channel::<websocket::BookUpdate>(websocket::BROADCAST_CAPACITY);
+    tracing::info!("Starting live WebSocket server on port {}", args.port);
+    let server = args.websocket.apply(WebSocketServer::live(tx.clone(), args.port))?;
+    let server = tokio::spawn(async move {
+        if let Err(e) = server.start().await {
+            tracing::error!("WebSocket server error: {}", e);
+        }
+    });
+
+    // The HTTP API is also started before parsing so its data fills in as the file is processed
+    let api = args.http_port.map(|port| {
+        let state = Arc::new(api::ApiState::default());
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = api::serve(server_state, port).await {
+                tracing::error!("HTTP API server error: {}", e);
+            }
+        });
+        state
+    });
+
+    #[cfg(feature = "grpc")]
+    let grpc = args.grpc_port.map(|port| {
+        let state = Arc::new(grpc::GrpcState::default());
+        let server_state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = grpc::serve(server_state, port).await {
+                tracing::error!("gRPC server error: {}", e);
+            }
+        });
+        state
+    });
+
+    #[cfg(feature = "flight")]
+    let flight = args.flight_port.map(|port| {
+        let store = Arc::new(flight::FlightStore::default());
+        let server_store = store.clone();
+        tokio::spawn(async move {
+            if let Err(e) = flight::serve(server_store, port).await {
+                tracing::error!("Arrow Flight server error: {}", e);
+            }
+        });
+        store
+    });
+
+    // The output file comes first, then the live and external sinks
+    let mut sinks = Vec::new();
+    let partitioning = args.rotation.partitioning(args.book.symbol(), args.input.file()?);
+    let mut book_args = args.book.clone();
+    book_args.fill_outputs(&partitioning)?;
+    if let Some(path) = &args.output_file {
+        let sink = partition::create_output_sink(args.output_format, path, args.book.bbo_only, args.book.feature_columns(), args.compress, partitioning)?;
+        sinks.push(on_writer_thread(sink, args.output_format, args.writer_queue, cores.writer)?);
+    }
+    let live = websocket::LiveSink::new(tx, args.book.bbo_only, args.book.feature_columns());
+    sinks.push(Box::new(if args.stream_bars { live.with_bars() } else { live }));
+    if let Some(state) = &api {
+        sinks.push(Box::new(api::ApiSink(state.clone())));
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(state) = &grpc {
+        sinks.push(Box::new(grpc::GrpcSink::new(state.clone())));
+    }
+    #[cfg(feature = "flight")]
+    if let Some(store) = &flight {
+        sinks.push(Box::new(flight::FlightSink(store.clone())));
+    }
+    sinks.extend(external_sinks(&args.external, args.book.bbo_only).await?.into_iter().map(|(_, sink)| sink));
+    let order_book = create_order_book(&book_args, sinks, args.stream_bars)?;
+
+    let parser_options = ParserOptions {
+        feed: args.input.feed,
+        strict: args.input.strict,
+        events: args.input.events_output.clone(),
+        pace: args.speed,
+        latency_interval: Some(args.latency_interval),
+        ..Default::default()
+    };
+    process(mapped_file, vec![order_book], parser_options, cores.parser).await?;
+
+    // Keep serving connected clients until interrupted, then let the server close their sessions
+    if !shutdown::requested() {
+        tracing::info!("Processing finished, press Ctrl-C to stop the servers");
+    }
+    shutdown::wait().await;
+    server.await?;
+    Ok(())
+}
+
+async fn replay(args: ReplayArgs) -> Result<(), Box<dyn std::error::Error>> {
+    tracing::info!("Starting WebSocket server on port {}", args.port);
+    let server = args.websocket.apply(WebSocketServer::new(&args.input_file.to_string_lossy(), &args.symbol, args.port, args.speed))?;
+    server.start().await?;
+    Ok(())
+}
+
+async fn match_orders(args: MatchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mapped_file = file_io::load_input(args.input.file()?, args.input.encap)?;
+
+    // Only the resting orders at the time are needed, so the book writes nothing
+    let book = OrderBook::new(utils::pad_stock_symbol(&args.symbol), OrderBookOptions::default());
+    let parser_options = ParserOptions { feed: args.input.feed, strict: args.input.strict, events: args.input.events_output.clone(), stop_at: Some(args.at), ..Default::default() };
+    let (engine, bids, asks) = tokio::task::spawn_blocking(move || -> std::io::Result<_> {
+        let mut books = [book];
+        parser::process_itch_file(&mapped_file, &mut books, &parser_options)?;
+        let (bids, asks) = books[0].resting_orders();
+        Ok((MatchingEngine::from_book(&books[0]), bids.len(), asks.len()))
+    }).await??;
+    tracing::info!("Seeded the matching engine with {} bids and {} asks of {} at {}", bids, asks, args.symbol,
+                   utils::format_time_of_day(args.at));
+
+    tracing::info!("Starting matching engine WebSocket server on port {}", args.port);
+    args.websocket.apply(WebSocketServer::matching(engine, &args.symbol, args.port))?.start().await?;
+    Ok(())
+}
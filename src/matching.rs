@@ -0,0 +1,199 @@
+// Price-time priority matching engine for synthetic order flow. It starts from the resting orders of a
+// replayed book (an ITCH snapshot), keeping their refs as ids and their queue order, and then matches
+// new, cancel and replace requests against them, so a strategy can be tested end to end against a
+// realistic book. The `match` subcommand serves it over WebSocket
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+
+use rustc_hash::FxHashMap;
+
+use crate::orderbook::{OrderBook, PriceLevel, Side};
+
+// Owner of the orders seeded from the book, which clients can't cancel or replace
+pub const SEEDED_OWNER: u64 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineOrder {
+    pub id: u64,
+    pub owner: u64,
+    pub side: Side,
+    pub price: u32,
+    pub shares: u32,
+}
+
+// A trade between an incoming order and a resting one, at the resting order's price
+#[derive(Debug, Clone, PartialEq)]
+pub struct Execution {
+    pub match_number: u64,
+    pub price: u32,
+    pub shares: u32,
+    pub aggressor_id: u64,
+    pub aggressor_side: Side,
+    pub resting_id: u64,
+    pub resting_owner: u64,
+    // Shares of the resting order left after the trade
+    pub resting_remaining: u32,
+}
+
+impl Execution {
+    pub fn to_json(&self, symbol: &str) -> String {
+        format!("{{\"type\":\"execution\", \"symbol\":\"{}\", \"match_number\":{}, \"price\":{:.4}, \"shares\":{}, \"aggressor_id\":{}, \"aggressor_side\":\"{}\", \"resting_id\":{}, \"resting_remaining\":{}}}",
+                symbol, self.match_number, self.price as f64 / 10000.0, self.shares, self.aggressor_id,
+                if self.aggressor_side == Side::Buy { "B" } else { "S" }, self.resting_id, self.resting_remaining)
+    }
+}
+
+#[derive(Default)]
+pub struct MatchingEngine {
+    orders: FxHashMap<u64, EngineOrder>,
+    // Price -> ids in time priority
+    bids: BTreeMap<u32, VecDeque<u64>>,
+    asks: BTreeMap<u32, VecDeque<u64>>,
+    next_id: u64,
+    next_match: u64,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl MatchingEngine {
+    // Seed the engine with the book's resting orders; new orders get ids above the book's refs
+    pub fn from_book(book: &OrderBook) -> Self {
+        let mut engine = MatchingEngine { next_id: 1, next_match: 1, ..Default::default() };
+        let (bids, asks) = book.resting_orders();
+        for order in bids.into_iter().chain(asks) {
+            engine.next_id = engine.next_id.max(order.ref_number + 1);
+            engine.rest(EngineOrder { id: order.ref_number, owner: SEEDED_OWNER, side: order.side, price: order.price, shares: order.shares });
+        }
+        engine
+    }
+
+    pub fn order(&self, id: u64) -> Option<&EngineOrder> {
+        self.orders.get(&id)
+    }
+
+    // Aggregated levels, best first, up to `depth` per side
+    pub fn levels(&self, depth: usize) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        let level = |(&price, ids): (&u32, &VecDeque<u64>)| PriceLevel {
+            price,
+            total_volume: ids.iter().map(|id| self.orders[id].shares).sum(),
+        };
+        (self.bids.iter().rev().take(depth).map(level).collect(), self.asks.iter().take(depth).map(level).collect())
+    }
+
+    // The top levels as JSON, with [price, shares] pairs best first
+    pub fn book_json(&self, symbol: &str, depth: usize) -> String {
+        let levels = |levels: Vec<PriceLevel>| levels.iter()
+            .map(|level| format!("[{:.4},{}]", level.price as f64 / 10000.0, level.total_volume))
+            .collect::<Vec<_>>()
+            .join(",");
+        let (bids, asks) = self.levels(depth);
+        format!("{{\"type\":\"book\", \"symbol\":\"{}\", \"bids\":[{}], \"asks\":[{}]}}", symbol, levels(bids), levels(asks))
+    }
+
+    // Match a new limit order and rest what's left of it. Returns its id and the trades
+    pub fn submit(&mut self, owner: u64, side: Side, price: u32, shares: u32) -> io::Result<(u64, Vec<Execution>)> {
+        if price == 0 || shares == 0 {
+            return Err(invalid("Orders need a price and shares".to_string()));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let executions = self.enter(EngineOrder { id, owner, side, price, shares });
+        Ok((id, executions))
+    }
+
+    // Remove an open order of the owner, returning it with its unfilled shares
+    pub fn cancel(&mut self, owner: u64, id: u64) -> io::Result<EngineOrder> {
+        self.owned(owner, id)?;
+        let order = self.orders.remove(&id).ok_or_else(|| invalid(format!("Unknown order {}", id)))?;
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        if let Some(queue) = levels.get_mut(&order.price) {
+            queue.retain(|&queued| queued != id);
+            if queue.is_empty() {
+                levels.remove(&order.price);
+            }
+        }
+        Ok(order)
+    }
+
+    // Change an open order's price or shares. Reducing the shares at the same price keeps its place
+    // in the queue; anything else sends it to the back, matching it first if it now crosses
+    pub fn replace(&mut self, owner: u64, id: u64, price: u32, shares: u32) -> io::Result<Vec<Execution>> {
+        let order = self.owned(owner, id)?;
+        if price == 0 || shares == 0 {
+            return Err(invalid("Orders need a price and shares".to_string()));
+        }
+        if price == order.price && shares <= order.shares {
+            if let Some(order) = self.orders.get_mut(&id) {
+                order.shares = shares;
+            }
+            return Ok(Vec::new());
+        }
+        let order = self.cancel(owner, id)?;
+        Ok(self.enter(EngineOrder { price, shares, ..order }))
+    }
+
+    fn owned(&self, owner: u64, id: u64) -> io::Result<&EngineOrder> {
+        match self.orders.get(&id) {
+            Some(order) if order.owner == owner => Ok(order),
+            Some(_) => Err(invalid(format!("Order {} belongs to another session", id))),
+            None => Err(invalid(format!("Unknown order {}", id))),
+        }
+    }
+
+    fn rest(&mut self, order: EngineOrder) {
+        let levels = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels.entry(order.price).or_default().push_back(order.id);
+        self.orders.insert(order.id, order);
+    }
+
+    // Trade the order against the other side while it crosses, best price then earliest first
+    fn enter(&mut self, mut order: EngineOrder) -> Vec<Execution> {
+        let mut executions = Vec::new();
+        while order.shares > 0 {
+            let best = match order.side {
+                Side::Buy => self.asks.first_entry().filter(|level| *level.key() <= order.price),
+                Side::Sell => self.bids.last_entry().filter(|level| *level.key() >= order.price),
+            };
+            let Some(mut level) = best else {
+                break;
+            };
+            let price = *level.key();
+            let resting_id = level.get()[0];
+            let resting = self.orders.get_mut(&resting_id).expect("queued orders are open");
+            let shares = order.shares.min(resting.shares);
+            order.shares -= shares;
+            resting.shares -= shares;
+            executions.push(Execution {
+                match_number: self.next_match,
+                price,
+                shares,
+                aggressor_id: order.id,
+                aggressor_side: order.side,
+                resting_id,
+                resting_owner: resting.owner,
+                resting_remaining: resting.shares,
+            });
+            self.next_match += 1;
+            if resting.shares == 0 {
+                self.orders.remove(&resting_id);
+                level.get_mut().pop_front();
+                if level.get().is_empty() {
+                    level.remove();
+                }
+            }
+        }
+        if order.shares > 0 {
+            self.rest(order);
+        }
+        executions
+    }
+}
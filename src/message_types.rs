@@ -59,8 +59,157 @@ impl From<u8> for MessageType {
     }
 }
 
+impl MessageType {
+    // Name of the message type in reports, the same for every subcommand and the metrics
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageType::SystemEvent => "SystemEvent",
+            MessageType::StockDirectory => "StockDirectory",
+            MessageType::StockTradingAction => "StockTradingAction",
+            MessageType::RegShoRestriction => "RegShoRestriction",
+            MessageType::MarketParticipantPosition => "MarketParticipantPosition",
+            MessageType::MwcbDeclineLevel => "MwcbDeclineLevel",
+            MessageType::MwcbStatus => "MwcbStatus",
+            MessageType::IpoQuotingPeriodUpdate => "IpoQuotingPeriodUpdate",
+            MessageType::LuldAuctionCollar => "LuldAuctionCollar",
+            MessageType::OperationalHalt => "OperationalHalt",
+            MessageType::AddOrder => "AddOrder",
+            MessageType::AddOrderWithMpid => "AddOrderWithMpid",
+            MessageType::OrderExecuted => "OrderExecuted",
+            MessageType::OrderExecutedWithPrice => "OrderExecutedWithPrice",
+            MessageType::OrderCancel => "OrderCancel",
+            MessageType::OrderDelete => "OrderDelete",
+            MessageType::OrderReplace => "OrderReplace",
+            MessageType::Trade => "Trade",
+            MessageType::CrossTrade => "CrossTrade",
+            MessageType::BrokenTrade => "BrokenTrade",
+            MessageType::Noii => "Noii",
+            MessageType::Rpii => "Rpii",
+            MessageType::DirectListingWithCapitalRaisePriceDiscovery => "DirectListingWithCapitalRaisePriceDiscovery",
+            MessageType::Unknown => "Unknown",
+        }
+    }
+
+    // Message length (including the type byte) defined by the ITCH 5.0 spec
+    pub const fn spec_length(self) -> Option<usize> {
+        match self {
+            MessageType::SystemEvent => Some(12),
+            MessageType::StockDirectory => Some(39),
+            MessageType::StockTradingAction => Some(25),
+            MessageType::RegShoRestriction => Some(20),
+            MessageType::MarketParticipantPosition => Some(26),
+            MessageType::MwcbDeclineLevel => Some(35),
+            MessageType::MwcbStatus => Some(12),
+            MessageType::IpoQuotingPeriodUpdate => Some(28),
+            MessageType::LuldAuctionCollar => Some(35),
+            MessageType::OperationalHalt => Some(21),
+            MessageType::AddOrder => Some(36),
+            MessageType::AddOrderWithMpid => Some(40),
+            MessageType::OrderExecuted => Some(31),
+            MessageType::OrderExecutedWithPrice => Some(36),
+            MessageType::OrderCancel => Some(23),
+            MessageType::OrderDelete => Some(19),
+            MessageType::OrderReplace => Some(35),
+            MessageType::Trade => Some(44),
+            MessageType::CrossTrade => Some(40),
+            MessageType::BrokenTrade => Some(19),
+            MessageType::Noii => Some(50),
+            MessageType::Rpii => Some(20),
+            MessageType::DirectListingWithCapitalRaisePriceDiscovery => Some(48),
+            MessageType::Unknown => None,
+        }
+    }
+
+    // Spec lengths indexed by message type byte, 0 for bytes that aren't an ITCH 5.0 message
+    pub fn spec_length_table() -> [u16; 256] {
+        let mut table = [0u16; 256];
+        for &message_type in NASDAQ_MESSAGES {
+            table[message_type as u8 as usize] = message_type.spec_length().unwrap_or(0) as u16;
+        }
+        table
+    }
+}
+
+// Every message type of the Nasdaq TotalView-ITCH 5.0 feed
+const NASDAQ_MESSAGES: &[MessageType] = &[
+    MessageType::SystemEvent,
+    MessageType::StockDirectory,
+    MessageType::StockTradingAction,
+    MessageType::RegShoRestriction,
+    MessageType::MarketParticipantPosition,
+    MessageType::MwcbDeclineLevel,
+    MessageType::MwcbStatus,
+    MessageType::IpoQuotingPeriodUpdate,
+    MessageType::LuldAuctionCollar,
+    MessageType::OperationalHalt,
+    MessageType::AddOrder,
+    MessageType::AddOrderWithMpid,
+    MessageType::OrderExecuted,
+    MessageType::OrderExecutedWithPrice,
+    MessageType::OrderCancel,
+    MessageType::OrderDelete,
+    MessageType::OrderReplace,
+    MessageType::Trade,
+    MessageType::CrossTrade,
+    MessageType::BrokenTrade,
+    MessageType::Noii,
+    MessageType::Rpii,
+    MessageType::DirectListingWithCapitalRaisePriceDiscovery,
+];
+
+// BX and PSX aren't listing exchanges, so they have no crosses, IPO or halt auction
+// messages, and no direct listing price discovery
+const BX_PSX_MESSAGES: &[MessageType] = &[
+    MessageType::SystemEvent,
+    MessageType::StockDirectory,
+    MessageType::StockTradingAction,
+    MessageType::RegShoRestriction,
+    MessageType::MarketParticipantPosition,
+    MessageType::MwcbDeclineLevel,
+    MessageType::MwcbStatus,
+    MessageType::OperationalHalt,
+    MessageType::AddOrder,
+    MessageType::AddOrderWithMpid,
+    MessageType::OrderExecuted,
+    MessageType::OrderExecutedWithPrice,
+    MessageType::OrderCancel,
+    MessageType::OrderDelete,
+    MessageType::OrderReplace,
+    MessageType::Trade,
+    MessageType::BrokenTrade,
+    MessageType::Rpii,
+];
+
+// Exchange whose TotalView-ITCH 5.0 feed is being parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum FeedVariant {
+    #[default]
+    Nasdaq,
+    Bx,
+    Psx,
+}
+
+impl FeedVariant {
+    // Message types defined for this feed
+    pub fn message_types(self) -> &'static [MessageType] {
+        match self {
+            FeedVariant::Nasdaq => NASDAQ_MESSAGES,
+            FeedVariant::Bx | FeedVariant::Psx => BX_PSX_MESSAGES,
+        }
+    }
+
+    // Lookup table indexed by message type byte, for the parser's hot loop
+    pub fn message_table(self) -> [bool; 256] {
+        let mut table = [false; 256];
+        for &message_type in self.message_types() {
+            table[message_type as u8 as usize] = true;
+        }
+        table
+    }
+}
+
 // Message header (common to all messages)
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct MessageHeader {
     pub length: u16,
@@ -69,7 +218,7 @@ pub struct MessageHeader {
 
 // System Event Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct SystemEventMessage {
     pub stock_locate: u16,
@@ -80,7 +229,7 @@ pub struct SystemEventMessage {
 
 // Stock Directory Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct StockDirectoryMessage {
     pub stock_locate: u16,
@@ -104,7 +253,7 @@ pub struct StockDirectoryMessage {
 
 // Add Order Message (without MPID)
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct AddOrderMessage {
     pub stock_locate: u16,
@@ -119,7 +268,7 @@ pub struct AddOrderMessage {
 
 // Add Order with MPID Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct AddOrderWithMpidMessage {
     pub stock_locate: u16,
@@ -135,7 +284,7 @@ pub struct AddOrderWithMpidMessage {
 
 // Order Executed Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct OrderExecutedMessage {
     pub stock_locate: u16,
@@ -148,7 +297,7 @@ pub struct OrderExecutedMessage {
 
 // Order Executed With Price Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct OrderExecutedWithPriceMessage {
     pub stock_locate: u16,
@@ -163,7 +312,7 @@ pub struct OrderExecutedWithPriceMessage {
 
 // Order Cancel Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct OrderCancelMessage {
     pub stock_locate: u16,
@@ -175,7 +324,7 @@ pub struct OrderCancelMessage {
 
 // Order Delete Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct OrderDeleteMessage {
     pub stock_locate: u16,
@@ -186,7 +335,7 @@ pub struct OrderDeleteMessage {
 
 // Order Replace Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct OrderReplaceMessage {
     pub stock_locate: u16,
@@ -200,7 +349,7 @@ pub struct OrderReplaceMessage {
 
 // Trade Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct TradeMessage {
     pub stock_locate: u16,
@@ -214,9 +363,9 @@ pub struct TradeMessage {
     pub match_number: u64,
 }
 
-// Stock Trading Action Message#[allow(dead_code)]
+// Stock Trading Action Message
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C, packed)]
 pub struct StockTradingActionMessage {
     pub stock_locate: u16,
@@ -226,4 +375,104 @@ pub struct StockTradingActionMessage {
     pub trading_state: u8,
     pub reserved: u8,
     pub reason: [u8; 4],
-}
\ No newline at end of file
+}
+
+// A field of a message in its big endian wire format
+pub trait WireField: Sized {
+    fn write(self, out: &mut Vec<u8>);
+    // Take the field off the front of the data, None if it is too short
+    fn read(data: &mut &[u8]) -> Option<Self>;
+}
+
+macro_rules! wire_integer {
+    ($($int:ty),*) => {$(
+        impl WireField for $int {
+            fn write(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn read(data: &mut &[u8]) -> Option<Self> {
+                let (bytes, rest) = data.split_first_chunk()?;
+                *data = rest;
+                Some(<$int>::from_be_bytes(*bytes))
+            }
+        }
+    )*};
+}
+
+wire_integer!(u8, u16, u32, u64);
+
+impl<const N: usize> WireField for [u8; N] {
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self);
+    }
+
+    fn read(data: &mut &[u8]) -> Option<Self> {
+        let (bytes, rest) = data.split_first_chunk()?;
+        *data = rest;
+        Some(*bytes)
+    }
+}
+
+// Conversion of a message struct from and to the bytes of the feed; timestamps are 6 bytes on the wire
+pub trait WireMessage: Sized {
+    const MESSAGE_TYPE: MessageType;
+
+    // The message after its type byte
+    fn encode_body(&self, out: &mut Vec<u8>);
+
+    // From the message after its type byte, as the book handlers get it; None if it is too short
+    fn decode(data: &[u8]) -> Option<Self>;
+
+    // Length prefix, type byte and body, as the message appears in a file
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::with_capacity(64);
+        self.encode_body(&mut body);
+        let mut out = Vec::with_capacity(body.len() + 3);
+        ((body.len() + 1) as u16).write(&mut out);
+        out.push(Self::MESSAGE_TYPE as u8);
+        out.extend_from_slice(&body);
+        out
+    }
+}
+
+// Every message starts with the stock locate, tracking number and timestamp; the fields after them are listed in order
+macro_rules! wire_message {
+    ($name:ident, $message_type:ident, $($field:ident),* $(,)?) => {
+        impl WireMessage for $name {
+            const MESSAGE_TYPE: MessageType = MessageType::$message_type;
+
+            fn encode_body(&self, out: &mut Vec<u8>) {
+                { self.stock_locate }.write(out);
+                { self.tracking_number }.write(out);
+                out.extend_from_slice(&{ self.timestamp }.to_be_bytes()[2..]);
+                $( { self.$field }.write(out); )*
+            }
+
+            fn decode(mut data: &[u8]) -> Option<Self> {
+                let data = &mut data;
+                Some($name {
+                    stock_locate: WireField::read(data)?,
+                    tracking_number: WireField::read(data)?,
+                    timestamp: <[u8; 6]>::read(data)?.iter().fold(0, |ts, &b| ts << 8 | b as u64),
+                    $( $field: WireField::read(data)?, )*
+                })
+            }
+        }
+    };
+}
+
+wire_message!(SystemEventMessage, SystemEvent, event_code);
+wire_message!(StockDirectoryMessage, StockDirectory, stock, market_category, financial_status_indicator, round_lot_size,
+              round_lots_only, issue_classification, issue_sub_type, authenticity, short_sale_threshold_indicator, ipo_flag,
+              luld_reference_price_tier, etp_flag, etp_leverage_factor, inverse_indicator);
+wire_message!(AddOrderMessage, AddOrder, order_ref_number, buy_sell_indicator, shares, stock, price);
+wire_message!(AddOrderWithMpidMessage, AddOrderWithMpid, order_ref_number, buy_sell_indicator, shares, stock, price, attribution);
+wire_message!(OrderExecutedMessage, OrderExecuted, order_ref_number, executed_shares, match_number);
+wire_message!(OrderExecutedWithPriceMessage, OrderExecutedWithPrice, order_ref_number, executed_shares, match_number, printable,
+              execution_price);
+wire_message!(OrderCancelMessage, OrderCancel, order_ref_number, cancelled_shares);
+wire_message!(OrderDeleteMessage, OrderDelete, order_ref_number);
+wire_message!(OrderReplaceMessage, OrderReplace, original_order_ref_number, new_order_ref_number, shares, price);
+wire_message!(TradeMessage, Trade, order_ref_number, buy_sell_indicator, shares, stock, price, match_number);
+wire_message!(StockTradingActionMessage, StockTradingAction, stock, trading_state, reserved, reason);
@@ -0,0 +1,193 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use crate::latency::REPORTED_QUANTILES;
+use crate::message_types::MessageType;
+
+// Process-wide metrics, rendered in the Prometheus text format on /metrics
+pub static METRICS: Metrics = Metrics::new();
+
+// Upper bounds of the broadcast backlog histogram buckets
+const BACKLOG_BUCKETS: [u64; 6] = [0, 1, 10, 100, 500, 1000];
+
+// Fixed bucket histogram of integer observations
+pub struct Histogram {
+    buckets: [AtomicU64; BACKLOG_BUCKETS.len()],
+    sum: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; BACKLOG_BUCKETS.len()],
+            sum: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        // Buckets are stored non-cumulative and summed when rendering
+        if let Some(i) = BACKLOG_BUCKETS.iter().position(|&bound| value <= bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, bucket) in BACKLOG_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum.load(Ordering::Relaxed));
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+pub struct Metrics {
+    // Indexed by the ITCH message type byte
+    messages_by_type: [AtomicU64; 256],
+    bytes_processed: AtomicU64,
+    // f64 bits
+    parse_throughput_mb: AtomicU64,
+    book_updates: AtomicU64,
+    skipped_updates: AtomicU64,
+    pub websocket_clients: AtomicI64,
+    pub broadcast_lagged: AtomicU64,
+    // Live WebSocket snapshots replaced by a newer one of the same symbol, clients disconnected for falling
+    // behind, and clients disconnected for not answering pings
+    pub websocket_conflated: AtomicU64,
+    pub websocket_slow_disconnects: AtomicU64,
+    pub websocket_ping_timeouts: AtomicU64,
+    // Bytes of the messages compressed for WebSocket clients, before and after
+    pub websocket_uncompressed_bytes: AtomicU64,
+    pub websocket_compressed_bytes: AtomicU64,
+    // Messages waiting in a client's broadcast queue when it receives one
+    pub broadcast_backlog: Histogram,
+    // Times the parser found the output writer's queue full, and how long it waited
+    pub writer_queue_full: AtomicU64,
+    pub writer_blocked_ns: AtomicU64,
+    // Percentiles of the paced replay's processing latency (see latency::REPORTED_QUANTILES), with the
+    // count and sum they cover
+    pub latency_quantiles: [AtomicU64; REPORTED_QUANTILES.len()],
+    pub latency_count: AtomicU64,
+    pub latency_sum_ns: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            messages_by_type: [const { AtomicU64::new(0) }; 256],
+            bytes_processed: AtomicU64::new(0),
+            parse_throughput_mb: AtomicU64::new(0),
+            book_updates: AtomicU64::new(0),
+            skipped_updates: AtomicU64::new(0),
+            websocket_clients: AtomicI64::new(0),
+            broadcast_lagged: AtomicU64::new(0),
+            websocket_conflated: AtomicU64::new(0),
+            websocket_slow_disconnects: AtomicU64::new(0),
+            websocket_ping_timeouts: AtomicU64::new(0),
+            websocket_uncompressed_bytes: AtomicU64::new(0),
+            websocket_compressed_bytes: AtomicU64::new(0),
+            broadcast_backlog: Histogram::new(),
+            writer_queue_full: AtomicU64::new(0),
+            writer_blocked_ns: AtomicU64::new(0),
+            latency_quantiles: [const { AtomicU64::new(0) }; REPORTED_QUANTILES.len()],
+            latency_count: AtomicU64::new(0),
+            latency_sum_ns: AtomicU64::new(0),
+        }
+    }
+
+    // Fold the parser's local per-type counts in and reset them
+    pub fn record_parse(&self, counts: &mut [u64; 256], bytes_processed: u64, elapsed_secs: f64) {
+        for (total, count) in self.messages_by_type.iter().zip(counts.iter_mut()) {
+            if *count > 0 {
+                total.fetch_add(*count, Ordering::Relaxed);
+                *count = 0;
+            }
+        }
+        self.bytes_processed.store(bytes_processed, Ordering::Relaxed);
+        if elapsed_secs > 0.0 {
+            let throughput = bytes_processed as f64 / (1024.0 * 1024.0) / elapsed_secs;
+            self.parse_throughput_mb.store(throughput.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_book_counts(&self, book_updates: u64, skipped_updates: u64) {
+        self.book_updates.store(book_updates, Ordering::Relaxed);
+        self.skipped_updates.store(skipped_updates, Ordering::Relaxed);
+    }
+
+    // (type byte, count) of every message type seen so far
+    pub fn message_counts(&self) -> Vec<(u8, u64)> {
+        self.messages_by_type.iter().enumerate()
+            .map(|(byte, count)| (byte as u8, count.load(Ordering::Relaxed)))
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# HELP itch_messages_total ITCH messages parsed by message type");
+        let _ = writeln!(out, "# TYPE itch_messages_total counter");
+        for (byte, count) in self.messages_by_type.iter().enumerate() {
+            let count = count.load(Ordering::Relaxed);
+            if count == 0 {
+                continue;
+            }
+            let name = MessageType::from(byte as u8).name();
+            let label = if (byte as u8).is_ascii_graphic() { (byte as u8 as char).to_string() } else { format!("0x{:02x}", byte) };
+            let _ = writeln!(out, "itch_messages_total{{type=\"{}\",name=\"{}\"}} {}", label, name, count);
+        }
+
+        let counters = [
+            ("itch_bytes_processed_total", "Bytes of ITCH data parsed", self.bytes_processed.load(Ordering::Relaxed)),
+            ("itch_book_updates_total", "Orderbook snapshots written", self.book_updates.load(Ordering::Relaxed)),
+            ("itch_book_updates_skipped_total", "Orderbook snapshots skipped by the write mode", self.skipped_updates.load(Ordering::Relaxed)),
+            ("itch_broadcast_lagged_total", "Messages dropped for lagging WebSocket clients", self.broadcast_lagged.load(Ordering::Relaxed)),
+            ("itch_websocket_conflated_total", "Snapshots replaced by a newer one for a lagging WebSocket client", self.websocket_conflated.load(Ordering::Relaxed)),
+            ("itch_websocket_slow_disconnects_total", "WebSocket clients disconnected for falling behind", self.websocket_slow_disconnects.load(Ordering::Relaxed)),
+            ("itch_websocket_ping_timeouts_total", "WebSocket clients disconnected for not answering pings", self.websocket_ping_timeouts.load(Ordering::Relaxed)),
+            ("itch_websocket_uncompressed_bytes_total", "Bytes of WebSocket messages before compression", self.websocket_uncompressed_bytes.load(Ordering::Relaxed)),
+            ("itch_websocket_compressed_bytes_total", "Bytes of WebSocket messages after compression", self.websocket_compressed_bytes.load(Ordering::Relaxed)),
+            ("itch_writer_queue_full_total", "Times the parser waited for the output writer's queue", self.writer_queue_full.load(Ordering::Relaxed)),
+            ("itch_writer_blocked_nanoseconds_total", "Time the parser waited for the output writer's queue", self.writer_blocked_ns.load(Ordering::Relaxed)),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        let _ = writeln!(out, "# HELP itch_parse_throughput_mb_per_second Parse throughput in MB/s");
+        let _ = writeln!(out, "# TYPE itch_parse_throughput_mb_per_second gauge");
+        let _ = writeln!(out, "itch_parse_throughput_mb_per_second {:.2}", f64::from_bits(self.parse_throughput_mb.load(Ordering::Relaxed)));
+
+        let _ = writeln!(out, "# HELP itch_websocket_clients Connected WebSocket clients");
+        let _ = writeln!(out, "# TYPE itch_websocket_clients gauge");
+        let _ = writeln!(out, "itch_websocket_clients {}", self.websocket_clients.load(Ordering::Relaxed));
+
+        self.broadcast_backlog.render(&mut out, "itch_broadcast_backlog", "Messages queued for a live WebSocket client when it receives one");
+
+        let latency_count = self.latency_count.load(Ordering::Relaxed);
+        if latency_count > 0 {
+            let name = "itch_processing_latency_nanoseconds";
+            let _ = writeln!(out, "# HELP {} Time from a paced message's due time to the end of its processing", name);
+            let _ = writeln!(out, "# TYPE {} summary", name);
+            for (fraction, value) in REPORTED_QUANTILES.iter().zip(&self.latency_quantiles) {
+                let _ = writeln!(out, "{}{{quantile=\"{}\"}} {}", name, fraction, value.load(Ordering::Relaxed));
+            }
+            let _ = writeln!(out, "{}_sum {}", name, self.latency_sum_ns.load(Ordering::Relaxed));
+            let _ = writeln!(out, "{}_count {}", name, latency_count);
+        }
+
+        out
+    }
+}
@@ -1,18 +1,126 @@
 use crate::message_types::*;
+use std::io::Write;
+use std::sync::Arc;
+
 use rustc_hash::FxHashMap;
-use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::Path;
 
-const MAX_BOOK_DEPTH: usize = 10;
+use crate::analytics::OrderAnalytics;
+use crate::bars::{Bar, BarBuilder};
+use crate::bloom::RefFilter;
+use crate::checkpoint::Checkpoint;
+use crate::estimators::{MidEstimators, WindowStats};
+use crate::features::{order_flow_imbalance, ColumnKind, FeatureColumns, ImbalanceWeights, PriceFormat, TradeStats};
+use crate::handler::MessageHandler;
+use crate::heatmap::Heatmap;
+use crate::hidden::HiddenLiquidityReport;
+use crate::integrity::{Anomaly, AnomalyKind, BandAction, IntegrityChecker, PriceBand, ANOMALY_KINDS};
+use crate::ladder::PriceLadder;
+use crate::lots::{OddLotMode, OddLotReport, DEFAULT_ROUND_LOT};
+use crate::metrics::METRICS;
+use crate::participants::ParticipantReport;
+use crate::pool::OrderPool;
+use crate::profiling::{self, Stage};
+use crate::queue::{OrderEvent, QueueTracker};
+use crate::sink::OutputSink;
+use crate::timestamps::{MarketHours, Timestamps};
+use crate::utils::format_time_of_day;
+use crate::wire;
+
+pub const MAX_BOOK_DEPTH: usize = 10;
+
+// How often (in messages) the sink and metrics counters are refreshed
+const STATS_INTERVAL: u64 = 65536;
+
+// Frame type byte of binary snapshot frames
+pub const BINARY_FRAME_SNAPSHOT: u8 = 1;
+// Size of the binary snapshot frame before the price levels
+pub const BINARY_HEADER_SIZE: usize = 26;
+
+// Controls which orderbook updates get written to the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum WriteMode {
+    // Write a row for every message that touches the book
+    #[default]
+    EveryMessage,
+    // Write a row only when any visible level changed
+    OnChange,
+    // Write a row only when the best bid or best ask changed
+    OnTopOfBookChange,
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+// Format of the output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    // Orderbook rows as CSV
+    #[default]
+    Csv,
+    // One JSON snapshot per line
+    Jsonl,
+    // Columnar snapshots with the CSV columns
+    Parquet,
+    // A SQLite database with snapshots, trades and events tables
+    Sqlite,
+    // LOBSTER message and orderbook files: a row per order message with the book it left
+    Lobster,
+    // Discard the output, for benchmarking
+    Null,
+}
+
+// Options controlling how the orderbook writes its output
+#[derive(Default)]
+pub struct OrderBookOptions {
+    pub write_mode: WriteMode,
+    // Write only the best bid/offer instead of the full depth
+    pub bbo_only: bool,
+    // Every written snapshot, trade and event goes to each sink in order
+    pub sinks: Vec<Box<dyn OutputSink>>,
+    // Check book invariants and write anomalies to a file
+    pub integrity: Option<IntegrityChecker>,
+    // Weighting of the levels in the imbalance column
+    pub imbalance_weights: ImbalanceWeights,
+    // Windows (in nanoseconds) to estimate mid returns and volatility over, none to skip them
+    pub estimator_windows: Vec<u64>,
+    // Follow every order's queue position and write its lifecycle events to a file
+    pub queue: Option<QueueTracker>,
+    // Order lifetime and cancel statistics, written as JSON when finalized
+    pub analytics: Option<OrderAnalytics>,
+    // Per-MPID order activity, written as CSV when finalized
+    pub participants: Option<ParticipantReport>,
+    // OHLCV bars of the printable trades, written to their file and passed to the sinks as they close
+    pub bars: Option<BarBuilder>,
+    // Resting liquidity per price bucket sampled every interval, written as a grid at the end
+    pub heatmap: Option<Heatmap>,
+    // Executions split into displayed and hidden liquidity, written as JSON when finalized
+    pub hidden: Option<HiddenLiquidityReport>,
+    // Whether odd-lot orders rest on the book and odd-lot trades are written
+    pub odd_lots: OddLotMode,
+    // Odd-lot orders and trades, written to their own file
+    pub odd_lot_report: Option<OddLotReport>,
+    // Write level sizes in round lots, leaving out the levels of less than one
+    pub lots: bool,
+    // Flag or drop adds priced too far from the prevailing price
+    pub price_band: Option<PriceBand>,
+    // Check order refs against a bloom filter of the added ones before looking them up
+    pub fast_filter: bool,
+    // Resting orders to make room for up front, 0 to grow as they come
+    pub expected_orders: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Side {
     Buy,
     Sell,
 }
 
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
 impl From<u8> for Side {
     fn from(byte: u8) -> Self {
         match byte {
@@ -22,6 +130,85 @@ impl From<u8> for Side {
     }
 }
 
+// Retail Price Improvement interest of a symbol, from its latest RPII (N) message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetailInterest {
+    #[default]
+    None,
+    Buy,
+    Sell,
+    Both,
+}
+
+impl RetailInterest {
+    pub fn name(self) -> &'static str {
+        match self {
+            RetailInterest::None => "none",
+            RetailInterest::Buy => "buy",
+            RetailInterest::Sell => "sell",
+            RetailInterest::Both => "both",
+        }
+    }
+
+    // The ITCH interest flag
+    pub fn code(self) -> u8 {
+        match self {
+            RetailInterest::None => b'N',
+            RetailInterest::Buy => b'B',
+            RetailInterest::Sell => b'S',
+            RetailInterest::Both => b'A',
+        }
+    }
+}
+
+impl From<u8> for RetailInterest {
+    fn from(byte: u8) -> Self {
+        match byte {
+            b'B' => RetailInterest::Buy,
+            b'S' => RetailInterest::Sell,
+            b'A' => RetailInterest::Both,
+            _ => RetailInterest::None,
+        }
+    }
+}
+
+// Price collar of a LULD halt auction, from the symbol's latest auction collar (J) message; zeros before one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LuldCollar {
+    pub reference_price: u32,
+    pub upper_price: u32,
+    pub lower_price: u32,
+    // How many times the halt has been extended
+    pub extensions: u32,
+}
+
+// Markets that report operational halts, in the bit order of the halt masks
+pub const HALT_MARKETS: [(u8, &str); 3] = [(b'Q', "Nasdaq"), (b'B', "BX"), (b'X', "PSX")];
+
+// How the aggressor of a trade was determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignMethod {
+    // The execution names the resting order, so the aggressor is the other side
+    Itch,
+    // Lee-Ready: above the prevailing mid is a buy, below a sell
+    Quote,
+    // Lee-Ready tick test at the mid or without a quote: up from the last trade price is a buy
+    Tick,
+    // No quote and no earlier trade to compare with
+    Unknown,
+}
+
+impl SignMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            SignMethod::Itch => "itch",
+            SignMethod::Quote => "quote",
+            SignMethod::Tick => "tick",
+            SignMethod::Unknown => "unknown",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Order {
     pub ref_number: u64,
@@ -29,159 +216,583 @@ pub struct Order {
     pub price: u32,
     pub shares: u32,
     pub side: Side,
+    // Market participant of an attributed (F) order
+    pub mpid: Option<[u8; 4]>,
+}
+
+// A system event or trading state change
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketEvent {
+    pub timestamp: u64,
+    // ITCH message type that reported the event (S, H, h, J, K, O, V, W or Y)
+    pub message_type: u8,
+    // None for market wide events
+    pub symbol: Option<Arc<str>>,
+    pub description: String,
+}
+
+// An execution against a resting order, or a non-displayed trade, for the tracked symbol
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeRecord {
+    pub timestamp: u64,
+    pub price: u32,
+    pub shares: u32,
+    // Side of the resting order
+    pub side: Side,
+    pub match_number: u64,
+    // ITCH message type that reported the trade (E, C or P)
+    pub message_type: u8,
+    // Side that took liquidity, if it could be inferred
+    pub aggressor: Option<Side>,
+    pub sign_method: SignMethod,
+}
+
+impl TradeRecord {
+    // "B" or "S", or empty when the aggressor is unknown
+    pub fn aggressor_code(&self) -> &'static str {
+        match self.aggressor {
+            Some(Side::Buy) => "B",
+            Some(Side::Sell) => "S",
+            None => "",
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        format!("{{\"timestamp\":\"{}\", \"price\":{:.4}, \"shares\":{}, \"side\":\"{}\", \"match_number\":{}, \"message_type\":\"{}\", \"aggressor\":\"{}\", \"sign_method\":\"{}\"}}",
+                self.timestamp,
+                self.price as f64 / 10000.0,
+                self.shares,
+                if self.side == Side::Buy { "B" } else { "S" },
+                self.match_number,
+                self.message_type as char,
+                self.aggressor_code(),
+                self.sign_method.name())
+    }
 }
 
+// A change to a resting order of the tracked symbol, for the sinks that write order level messages
 #[derive(Debug, Clone, PartialEq)]
+pub struct OrderMessage {
+    pub timestamp: u64,
+    // Add, Execute, Cancel, Delete, or Replace for the removal of a replaced order (its new order follows as an Add)
+    pub event: OrderEvent,
+    pub order_ref: u64,
+    // Shares added, executed or cancelled, or the shares the order had left when deleted or replaced
+    pub shares: u32,
+    // The resting order's price, also for executions at another price
+    pub price: u32,
+    pub side: Side,
+}
+
+// Why shares come off a resting order
+#[derive(Debug, Clone, Copy)]
+enum Reduction {
+    // Executed at the order's price (E)
+    Execute { match_number: u64 },
+    // Executed at another price, printable or not (C)
+    ExecuteWithPrice { match_number: u64, price: u32, printable: bool },
+    Cancel,
+}
+
+impl Reduction {
+    // The trade of an execution of shares off an order at the price and side, and whether it prints; None for a cancel
+    fn trade(self, timestamp: u64, order_price: u32, shares: u32, side: Side) -> Option<(TradeRecord, bool)> {
+        let (match_number, price, message_type, printable) = match self {
+            Reduction::Execute { match_number } => (match_number, order_price, b'E', true),
+            Reduction::ExecuteWithPrice { match_number, price, printable } => (match_number, price, b'C', printable),
+            Reduction::Cancel => return None,
+        };
+        let trade = TradeRecord { timestamp, price, shares, side, match_number, message_type, aggressor: Some(side.opposite()),
+                                  sign_method: SignMethod::Itch };
+        Some((trade, printable))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct PriceLevel {
     pub price: u32,
     pub total_volume: u32,
 }
 
+// Up to MAX_BOOK_DEPTH levels of one side, best first, stored inline so a snapshot doesn't allocate.
+// Derefs to the levels present
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Levels {
+    levels: [PriceLevel; MAX_BOOK_DEPTH],
+    len: usize,
+}
+
+impl Levels {
+    // Add a level after the others; false when all MAX_BOOK_DEPTH are taken
+    pub fn push(&mut self, level: PriceLevel) -> bool {
+        if self.len == MAX_BOOK_DEPTH {
+            return false;
+        }
+        self.levels[self.len] = level;
+        self.len += 1;
+        true
+    }
+
+    // The level at an index, adding empty levels up to it; None past MAX_BOOK_DEPTH
+    pub fn level_mut(&mut self, index: usize) -> Option<&mut PriceLevel> {
+        if index >= MAX_BOOK_DEPTH {
+            return None;
+        }
+        self.len = self.len.max(index + 1);
+        Some(&mut self.levels[index])
+    }
+
+    // Insert a level before `index`, dropping the last one when all are taken
+    pub fn insert(&mut self, index: usize, level: PriceLevel) {
+        if index > self.len || index >= MAX_BOOK_DEPTH {
+            return;
+        }
+        self.len = (self.len + 1).min(MAX_BOOK_DEPTH);
+        self.levels.copy_within(index..self.len - 1, index + 1);
+        self.levels[index] = level;
+    }
+
+    pub fn remove(&mut self, index: usize) -> PriceLevel {
+        let level = self[index];
+        self.levels.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        level
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+impl std::ops::Deref for Levels {
+    type Target = [PriceLevel];
+
+    fn deref(&self) -> &[PriceLevel] {
+        &self.levels[..self.len]
+    }
+}
+
+impl PartialEq for Levels {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl FromIterator<PriceLevel> for Levels {
+    // Levels past MAX_BOOK_DEPTH are dropped
+    fn from_iter<I: IntoIterator<Item = PriceLevel>>(iter: I) -> Self {
+        let mut levels = Levels::default();
+        for level in iter.into_iter().take(MAX_BOOK_DEPTH) {
+            levels.push(level);
+        }
+        levels
+    }
+}
+
 pub struct OrderBook {
     symbol: [u8; 8],
-    buy_orders: FxHashMap<u64, Order>,
-    sell_orders: FxHashMap<u64, Order>,
-    // Price to volume mapping for quick access - using BTreeMap to keep prices sorted
-    buy_price_map: BTreeMap<u32, u32>,  // Descending price order for bids
-    sell_price_map: BTreeMap<u32, u32>, // Ascending price order for asks
-    writer: BufWriter<File>,
+    // Trimmed symbol used to tag published updates
+    symbol_name: Arc<str>,
+    // Resting orders of both sides by order ref
+    orders: OrderPool,
+    // Volume per price level of each side
+    buy_levels: PriceLadder,
+    sell_levels: PriceLadder,
+    sinks: Vec<Box<dyn OutputSink>>,
+    // Whether any sink takes order messages, so the levels are only copied for them when needed
+    order_sinks: bool,
+    write_mode: WriteMode,
+    bbo_only: bool,
     // Track last known state for delta comparison
-    last_state: Option<OrderbookState>,
+    last_state: Option<BookSnapshot>,
     // Counters for statistics
     message_count: u64,
+    // Messages seen by type byte
+    message_types: Box<[u64; 256]>,
     update_count: u64,
+    skipped_count: u64,
+    // Byte offset after the last message parsed, once the parser has stopped
+    end_offset: Option<u64>,
+    integrity: Option<IntegrityChecker>,
+    queue: Option<QueueTracker>,
+    analytics: Option<OrderAnalytics>,
+    participants: Option<ParticipantReport>,
+    bars: Option<BarBuilder>,
+    // Bars closed by the current message, reused between messages
+    closed_bars: Vec<Bar>,
+    heatmap: Option<Heatmap>,
+    hidden: Option<HiddenLiquidityReport>,
+    odd_lots: OddLotMode,
+    odd_lot_report: Option<OddLotReport>,
+    // Orders kept off the book, odd lots with OddLotMode::Exclude and adds outside the price band with
+    // BandAction::Drop, by order ref
+    excluded_orders: FxHashMap<u64, Order>,
+    lots: bool,
+    price_band: Option<PriceBand>,
+    // Round lot size from the symbol's stock directory message
+    round_lot: u32,
+    ref_filter: Option<RefFilter>,
+    // Stock locate of the tracked symbol, learned from its directory or add order messages
+    stock_locate: Option<u16>,
+    // Type of the message being applied, for anomaly reports
+    message_type: u8,
+    trade_stats: TradeStats,
+    // Last trade price and the direction of the last price change, for the tick test
+    last_trade_price: Option<u32>,
+    last_tick: Option<Side>,
+    // Best bid and ask after the previous update, and the order flow imbalance since the last written row
+    last_top: (Option<PriceLevel>, Option<PriceLevel>),
+    ofi: i64,
+    estimators: MidEstimators,
+    imbalance_weights: ImbalanceWeights,
+    retail_interest: RetailInterest,
+    luld: LuldCollar,
+    mwcb_level: u8,
+    short_sale_restricted: bool,
+    operational_halts: u8,
+    market_hours: MarketHours,
     // Pre-allocate buffers for string operations
     //line_buffer: String,
 }
 
-// Snapshot of orderbook state used for delta comparison
-#[derive(Clone, PartialEq)]
-struct OrderbookState {
-    timestamp: u64,
-    bid_levels: Vec<PriceLevel>,
-    ask_levels: Vec<PriceLevel>,
-    mid_price: u32,
-    imbalance: f64,
+// Snapshot of orderbook state, used for delta comparison and published to subscribers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BookSnapshot {
+    pub timestamp: u64,
+    pub bid_levels: Levels,
+    pub ask_levels: Levels,
+    // Halfway between the best bid and ask in dollars, 0 unless both sides have orders
+    pub mid_price: f64,
+    pub imbalance: f64,
+    // Trade statistics up to and including this update
+    pub trades: TradeStats,
+    // Top level order flow imbalance since the previous written snapshot
+    pub ofi: i64,
+    // Mid returns and volatility of each estimator window, empty without windows
+    pub windows: Vec<WindowStats>,
+    pub retail_interest: RetailInterest,
+    pub luld: LuldCollar,
+    // Highest market wide circuit breaker level breached today, 0 for none
+    pub mwcb_level: u8,
+    // Whether the Reg SHO short sale price test restriction is in effect
+    pub short_sale_restricted: bool,
+    // Markets with an operational halt of the symbol in effect, a bit per HALT_MARKETS entry
+    pub operational_halts: u8,
+    // Market hours of the day so far, for the session column
+    pub market_hours: MarketHours,
 }
 
-#[inline(always)]
-unsafe fn read_u32_be(data: &[u8], offset: usize) -> u32 {
-    let bytes = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
-    u32::from_be_bytes(bytes)
-}
+impl BookSnapshot {
+    // Best bid, best ask, mid and spread (in price units); mid and spread are 0 unless both sides exist
+    pub fn bbo(&self) -> (PriceLevel, PriceLevel, f64, i64) {
+        let bid = self.bid_levels.first().copied().unwrap_or_default();
+        let ask = self.ask_levels.first().copied().unwrap_or_default();
+
+        if bid.price > 0 && ask.price > 0 {
+            let spread = ask.price as i64 - bid.price as i64;
+            (bid, ask, self.mid_price, spread)
+        } else {
+            (bid, ask, 0.0, 0)
+        }
+    }
 
+    // Optional computed columns as JSON fields, each with a leading comma
+    fn feature_json(&self, columns: &FeatureColumns) -> String {
+        let mut json = String::new();
+        for ((name, _), value) in columns.names().into_iter().zip(columns.values(self)) {
+            json.push_str(&format!(", \"{}\":{}", name, value.to_json()));
+        }
+        json
+    }
+
+    // Optional computed columns as CSV fields, each with a leading comma
+    fn write_feature_columns<W: Write>(&self, out: &mut W, columns: &FeatureColumns) -> Result<(), std::io::Error> {
+        if columns.is_empty() {
+            return Ok(());
+        }
+        for value in columns.values(self) {
+            write!(out, ",{}", value)?;
+        }
+        Ok(())
+    }
 
-#[inline(always)]
-fn read_order_ref_be(data: &[u8], offset: usize) -> u64 {
-    let mut result = 0u64;
-    for i in 0..8 {
-        result <<= 8 ;
-        result |= data[offset + i] as u64;
+    // Serialize with the same keys and value formats as the CSV columns
+    pub fn to_json(&self, symbol: &str, depth: usize, columns: &FeatureColumns) -> String {
+        let mut json = format!("{{\"symbol\":\"{}\", \"timestamp\":\"{}\"", symbol, columns.timestamps().format(self.timestamp));
+        let prices = columns.prices();
+        for i in 0..depth {
+            let bid = self.bid_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            let ask = self.ask_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            json.push_str(&format!(", \"{0}_bid_price\":{1}, \"{0}_bid_vol\":{2}, \"{0}_ask_price\":{3}, \"{0}_ask_vol\":{4}",
+                                   i + 1, prices.json(bid.0), bid.1, prices.json(ask.0), ask.1));
+        }
+        json.push_str(&format!(", \"mid_price\":{:.4}, \"orderbook_imbalance\":{:.6}", self.mid_price, self.imbalance));
+        json.push_str(&self.feature_json(columns));
+        json.push('}');
+        json
+    }
+
+    // Encode as a compact little-endian binary frame:
+    //   u8 frame type (1), u8 level count N, [u8; 8] symbol, u64 timestamp, f64 imbalance,
+    //   then N x (u32 bid_price, u32 bid_vol, u32 ask_price, u32 ask_vol), prices in 1/10000 dollars
+    pub fn to_binary(&self, symbol: &[u8; 8]) -> Vec<u8> {
+        let depth = self.bid_levels.len().max(self.ask_levels.len()).min(u8::MAX as usize);
+        let mut frame = Vec::with_capacity(BINARY_HEADER_SIZE + depth * 16);
+        frame.push(BINARY_FRAME_SNAPSHOT);
+        frame.push(depth as u8);
+        frame.extend_from_slice(symbol);
+        frame.extend_from_slice(&self.timestamp.to_le_bytes());
+        frame.extend_from_slice(&self.imbalance.to_le_bytes());
+        for i in 0..depth {
+            let bid = self.bid_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            let ask = self.ask_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            for value in [bid.0, bid.1, ask.0, ask.1] {
+                frame.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        frame
+    }
+
+    // Serialize the best bid/offer with the same keys as the BBO CSV columns
+    pub fn to_bbo_json(&self, symbol: &str, columns: &FeatureColumns) -> String {
+        let (bid, ask, mid, spread) = self.bbo();
+        let prices = columns.prices();
+        let spread = match prices {
+            PriceFormat::Decimal => format!("{:.4}", spread as f64 / 10000.0),
+            format => format.spread(spread, bid.price).to_string(),
+        };
+        format!("{{\"symbol\":\"{}\", \"timestamp\":\"{}\", \"bid\":{}, \"bid_size\":{}, \"ask\":{}, \"ask_size\":{}, \"mid\":{:.4}, \"spread\":{}{}}}",
+                symbol, columns.timestamps().format(self.timestamp),
+                prices.json(bid.price), bid.total_volume,
+                prices.json(ask.price), ask.total_volume,
+                mid, spread, self.feature_json(columns))
+    }
+
+    // Write a full depth CSV row, padding missing levels with zeros. Integers and prices are formatted
+    // by hand into a row on the stack, and only the mid and the imbalance go through the formatting machinery
+    pub fn write_depth_row<W: Write>(&self, out: &mut W, depth: usize, columns: &FeatureColumns) -> Result<(), std::io::Error> {
+        let prices = columns.prices();
+        let mut row = RowBuffer::new();
+        row.timestamp(self.timestamp, columns.timestamps());
+        for i in 0..depth {
+            // Write out what the row holds before a level could overflow it
+            if row.remaining() < 4 * (MAX_FIELD_LEN + 1) {
+                out.write_all(row.as_bytes())?;
+                row.clear();
+            }
+            let bid = self.bid_levels.get(i).copied().unwrap_or_default();
+            let ask = self.ask_levels.get(i).copied().unwrap_or_default();
+            row.byte(b',');
+            row.book_price(bid.price, prices);
+            row.byte(b',');
+            row.int(bid.total_volume);
+            row.byte(b',');
+            row.book_price(ask.price, prices);
+            row.byte(b',');
+            row.int(ask.total_volume);
+        }
+        out.write_all(row.as_bytes())?;
+
+        write!(out, ",{:.4},{:.06}", self.mid_price, self.imbalance)?;
+        self.write_feature_columns(out, columns)?;
+
+        // End the line
+        out.write_all(b"\n")
+    }
+
+    // Write a compact best bid/offer CSV row
+    pub fn write_bbo_row<W: Write>(&self, out: &mut W, columns: &FeatureColumns) -> Result<(), std::io::Error> {
+        let (bid, ask, mid, spread) = self.bbo();
+        let prices = columns.prices();
+
+        let mut row = RowBuffer::new();
+        row.timestamp(self.timestamp, columns.timestamps());
+        row.byte(b',');
+        row.book_price(bid.price, prices);
+        row.byte(b',');
+        row.int(bid.total_volume);
+        row.byte(b',');
+        row.book_price(ask.price, prices);
+        row.byte(b',');
+        row.int(ask.total_volume);
+        out.write_all(row.as_bytes())?;
+        write!(out, ",{:.4},", mid)?;
+
+        row.clear();
+        if prices != PriceFormat::Decimal {
+            row.int(prices.spread(spread, bid.price));
+        } else {
+            if spread < 0 {
+                row.byte(b'-');
+            }
+            row.price(spread.unsigned_abs());
+        }
+        out.write_all(row.as_bytes())?;
+        self.write_feature_columns(out, columns)?;
+        out.write_all(b"\n")
     }
-    result
 }
 
+// Longest field a RowBuffer writes: a u64 with a decimal point
+const MAX_FIELD_LEN: usize = 21;
 
-#[inline(always)]
-unsafe fn read_stock(data: &[u8], offset: usize) -> [u8; 8] {
-    let mut stock = [0u8; 8];
-    stock.copy_from_slice(&data[offset..offset + 8]);
-    stock
+// Part of a CSV row, formatted on the stack and written out in one piece
+struct RowBuffer {
+    bytes: [u8; 256],
+    len: usize,
 }
 
-#[inline(always)]
-// Fix the calculate_imbalance method to use buy_price_map and sell_price_map
-fn calculate_imbalance(bids : &[PriceLevel], asks : &[PriceLevel]) -> f64 {
-    let total_bid_volume: u32 = bids.iter().map(|price_level| price_level.total_volume).sum();
-    let total_ask_volume: u32 = asks.iter().map(|price_level| price_level.total_volume).sum();
+impl RowBuffer {
+    fn new() -> Self {
+        RowBuffer { bytes: [0; 256], len: 0 }
+    }
 
-    if total_bid_volume == 0 && total_ask_volume == 0 {
-        return 0.0;
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.len
     }
 
-    let total_volume = total_bid_volume as f64 + total_ask_volume as f64;
-    (total_bid_volume as f64 - total_ask_volume as f64) / total_volume
-}
+    fn clear(&mut self) {
+        self.len = 0;
+    }
 
-impl OrderBook {
-    pub fn new(symbol: [u8; 8], output_path: &Path) -> Result<Self, std::io::Error> {
-        let file = File::create(output_path)?;
-        let mut writer = BufWriter::new(file);
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    #[inline]
+    fn byte(&mut self, byte: u8) {
+        self.bytes[self.len] = byte;
+        self.len += 1;
+    }
+
+    // The first field of a row; seconds and ISO 8601 times are the only ones formatted on the heap
+    fn timestamp(&mut self, timestamp: u64, timestamps: Timestamps) {
+        match timestamps.kind() {
+            ColumnKind::Integer => self.int(timestamps.integer(timestamp)),
+            _ => {
+                let text = timestamps.format(timestamp);
+                self.bytes[self.len..self.len + text.len()].copy_from_slice(text.as_bytes());
+                self.len += text.len();
+            }
+        }
+    }
 
-        // Write CSV header
-        let mut header = String::from("timestamp");
-        for level in 1..=MAX_BOOK_DEPTH {
-            header.push_str(&format!(",{}_bid_price,{}_bid_vol,{}_ask_price,{}_ask_vol",
-                                     level, level, level, level));
+    #[inline]
+    fn book_price(&mut self, price: u32, format: PriceFormat) {
+        match format {
+            PriceFormat::Decimal => self.price(price as u64),
+            format => self.int(format.integer(price)),
         }
-        header.push_str(",mid_price,orderbook_imbalance");
-        header.push('\n');
-        writer.write_all(header.as_bytes())?;
+    }
+
+    #[inline]
+    fn int<I: itoa::Integer>(&mut self, value: I) {
+        let mut buffer = itoa::Buffer::new();
+        let digits = buffer.format(value).as_bytes();
+        self.bytes[self.len..self.len + digits.len()].copy_from_slice(digits);
+        self.len += digits.len();
+    }
+
+    // A price in 1/10000 dollars as dollars with 4 decimals, like 150.0100
+    #[inline]
+    fn price(&mut self, price: u64) {
+        self.int(price / 10000);
+        let decimal = (price % 10000) as u16;
+        self.bytes[self.len..self.len + 5].copy_from_slice(&[b'.', b'0' + (decimal / 1000) as u8, b'0' + (decimal / 100 % 10) as u8,
+                                                             b'0' + (decimal / 10 % 10) as u8, b'0' + (decimal % 10) as u8]);
+        self.len += 5;
+    }
+}
+
+// A price in dollars with 4 decimals, for event descriptions
+fn format_price(price: u32) -> String {
+    format!("{}.{:04}", price / 10000, price % 10000)
+}
+
+impl OrderBook {
+    pub fn new(symbol: [u8; 8], options: OrderBookOptions) -> Self {
+        // BBO output is only written when the top of book moves
+        let write_mode = if options.bbo_only {
+            WriteMode::OnTopOfBookChange
+        } else {
+            options.write_mode
+        };
 
-        Ok(OrderBook {
+        OrderBook {
             symbol,
-            buy_orders: FxHashMap::default(),
-            sell_orders: FxHashMap::default(),
-            buy_price_map: BTreeMap::new(),
-            sell_price_map: BTreeMap::new(),
-            writer,
+            symbol_name: Arc::from(String::from_utf8_lossy(&symbol).trim_end()),
+            orders: OrderPool::with_capacity(options.expected_orders),
+            buy_levels: PriceLadder::new(Side::Buy),
+            sell_levels: PriceLadder::new(Side::Sell),
+            order_sinks: options.sinks.iter().any(|sink| sink.wants_orders()),
+            sinks: options.sinks,
+            write_mode,
+            bbo_only: options.bbo_only,
             last_state: None,
             message_count: 0,
+            message_types: Box::new([0; 256]),
             update_count: 0,
+            skipped_count: 0,
+            end_offset: None,
+            integrity: options.integrity,
+            queue: options.queue,
+            analytics: options.analytics,
+            participants: options.participants,
+            bars: options.bars,
+            closed_bars: Vec::new(),
+            heatmap: options.heatmap,
+            hidden: options.hidden,
+            odd_lots: options.odd_lots,
+            odd_lot_report: options.odd_lot_report,
+            excluded_orders: FxHashMap::default(),
+            lots: options.lots,
+            price_band: options.price_band,
+            round_lot: DEFAULT_ROUND_LOT,
+            ref_filter: options.fast_filter.then(RefFilter::default),
+            stock_locate: None,
+            message_type: 0,
+            trade_stats: TradeStats::default(),
+            last_trade_price: None,
+            last_tick: None,
+            last_top: (None, None),
+            ofi: 0,
+            estimators: MidEstimators::new(&options.estimator_windows),
+            imbalance_weights: options.imbalance_weights,
+            retail_interest: RetailInterest::None,
+            luld: LuldCollar::default(),
+            mwcb_level: 0,
+            short_sale_restricted: false,
+            operational_halts: 0,
+            market_hours: MarketHours::default(),
             //line_buffer: String::new(),
-        })
+        }
     }
 
 
-    pub fn handle_message(&mut self, message_type: MessageType, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        self.message_count+=1;
-        unsafe {
-            match message_type {
-                MessageType::AddOrder => self.handle_add_order(data, timestamp),
-                MessageType::AddOrderWithMpid => self.handle_add_order_with_mpid(data, timestamp),
-                MessageType::OrderExecuted => self.handle_order_executed(data, timestamp),
-                MessageType::OrderExecutedWithPrice => self.handle_order_executed_with_price(data, timestamp),
-                MessageType::OrderCancel => self.handle_order_cancel(data, timestamp),
-                MessageType::OrderDelete => self.handle_order_delete(data, timestamp),
-                MessageType::OrderReplace => self.handle_order_replace(data, timestamp),
-                MessageType::Trade => self.handle_trade(data),
-                _ => Ok(()),
-            }
+    // Whether an add or trade is for the symbol. Once its stock locate is known, the 2-byte locate at the start
+    // of the message decides and the 8-byte symbol is only compared until then
+    #[inline(always)]
+    fn is_own(&self, header: &wire::Header, stock: &[u8; 8]) -> bool {
+        match self.stock_locate {
+            Some(locate) => header.stock_locate.get() == locate,
+            None => *stock == self.symbol,
         }
     }
 
-    pub fn handle_add_order(&mut self, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        // Directly read fields from data slice - offsets based on struct layout
-        // ITCH 5.0 field layout for Add Order:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - order_ref_number (8 bytes) -> offset 10
-        // - buy_sell_indicator (1 byte) -> offset 18
-        // - shares (4 bytes) -> offset 19
-        // - stock (8 bytes) -> offset 23
-        // - price (4 bytes) -> offset 31
-
-        // Check if the message is for our symbol before doing more work
-        let stock = unsafe {read_stock(data, 23)};
-
+    pub fn handle_add_order(&mut self, message: &wire::AddOrder, timestamp: u64) -> Result<(), std::io::Error> {
         // Check if the message is for our symbol
-        if stock != self.symbol {
+        if !self.is_own(&message.header, &message.stock) {
             return Ok(());
         }
+        self.stock_locate = Some(message.header.stock_locate.get());
 
-        let order_ref_number = read_order_ref_be(data, 10);
-        let buy_sell_indicator = data[18];
-        let shares = unsafe{read_u32_be(data, 19)};
-        let price = unsafe{read_u32_be(data, 31)};
-
-        let side = Side::from(buy_sell_indicator);
         let order = Order {
-            ref_number: order_ref_number,
+            ref_number: message.order_ref_number.get(),
             timestamp,
-            price,
-            shares,
-            side,
+            price: message.price.get(),
+            shares: message.shares.get(),
+            side: Side::from(message.buy_sell_indicator),
+            mpid: None,
         };
 
         self.add_order(order)?;
@@ -189,38 +800,20 @@ impl OrderBook {
         Ok(())
     }
 
-    unsafe fn handle_add_order_with_mpid(&mut self, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        // Similar to handle_add_order but with MPID field
-        // ITCH 5.0 field layout for Add Order with MPID:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - order_ref_number (8 bytes) -> offset 10
-        // - buy_sell_indicator (1 byte) -> offset 18
-        // - shares (4 bytes) -> offset 19
-        // - stock (8 bytes) -> offset 23
-        // - price (4 bytes) -> offset 31
-        // - attribution (4 bytes) -> offset 35
-
-        let stock = unsafe{read_stock(data, 23)};
-
+    fn handle_add_order_with_mpid(&mut self, message: &wire::AddOrderWithMpid, timestamp: u64) -> Result<(), std::io::Error> {
         // Check if the message is for our symbol
-        if stock != self.symbol {
+        if !self.is_own(&message.header, &message.stock) {
             return Ok(());
         }
+        self.stock_locate = Some(message.header.stock_locate.get());
 
-        let order_ref_number = read_order_ref_be(data, 10);
-        let buy_sell_indicator = data[18];
-        let shares = unsafe{read_u32_be(data, 19)};
-        let price = unsafe{read_u32_be(data, 31)};
-
-        let side = Side::from(buy_sell_indicator);
         let order = Order {
-            ref_number: order_ref_number,
+            ref_number: message.order_ref_number.get(),
             timestamp,
-            price,
-            shares,
-            side,
+            price: message.price.get(),
+            shares: message.shares.get(),
+            side: Side::from(message.buy_sell_indicator),
+            mpid: Some(message.attribution),
         };
 
         self.add_order(order)?;
@@ -228,342 +821,546 @@ impl OrderBook {
         Ok(())
     }
 
-    unsafe fn handle_order_executed(&mut self, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        // ITCH 5.0 field layout for Order Executed:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - order_ref_number (8 bytes) -> offset 10
-        // - executed_shares (4 bytes) -> offset 18
-        // - match_number (8 bytes) -> offset 22
-
-        let order_ref_number = read_order_ref_be(data, 10);
-        let executed_shares = read_order_ref_be(data, 18);
-
-        // Look up the order
-        if let Some(order) = self.buy_orders.get_mut(&order_ref_number) {
-            // Reduce the shares
-            order.shares = order.shares.saturating_sub(executed_shares as u32);
-
-            // Update the price map
-            if let Some(volume) = self.buy_price_map.get_mut(&order.price) {
-                *volume = volume.saturating_sub(executed_shares as u32);
-                if *volume == 0 {
-                    self.buy_price_map.remove(&order.price);
-                }
-            }
+    // Take shares off a resting order and its price level, removing the order once it has none left.
+    // The order's price and side, or None for an unknown order
+    fn take_shares(&mut self, timestamp: u64, order_ref_number: u64, shares: u32) -> Result<Option<(u32, Side)>, std::io::Error> {
+        let Some(order) = self.orders.get_mut(order_ref_number) else {
+            return Ok(None);
+        };
+        let (price, side) = (order.price, order.side);
+        // Reduce the shares, noting if the order held fewer than that
+        let mut level_ok = order.shares >= shares;
+        order.shares = order.shares.saturating_sub(shares);
+        if order.shares == 0 {
+            self.orders.remove(order_ref_number);
+        }
+        level_ok &= self.levels_mut(side).reduce(price, shares);
+        if !level_ok {
+            self.report_negative_level(timestamp, order_ref_number, price, shares)?;
+        }
+        Ok(Some((price, side)))
+    }
 
-            // Remove the order if no shares left
-            if order.shares == 0 {
-                self.buy_orders.remove(&order_ref_number);
-            }
+    // Take a whole resting order off the book, or None for an unknown order
+    fn remove_order(&mut self, timestamp: u64, order_ref_number: u64) -> Result<Option<Order>, std::io::Error> {
+        let Some(order) = self.orders.remove(order_ref_number) else {
+            return Ok(None);
+        };
+        if !self.levels_mut(order.side).reduce(order.price, order.shares) {
+            self.report_negative_level(timestamp, order_ref_number, order.price, order.shares)?;
+        }
+        Ok(Some(order))
+    }
 
-            // Write updated orderbook state
-            self.write_orderbook(timestamp)?;
-        } else if let Some(order) = self.sell_orders.get_mut(&order_ref_number) {
-            // Reduce the shares
-            order.shares = order.shares.saturating_sub(executed_shares as u32);
-
-            // Update the price map
-            if let Some(volume) = self.sell_price_map.get_mut(&order.price) {
-                *volume = volume.saturating_sub(executed_shares as u32);
-                if *volume == 0 {
-                    self.sell_price_map.remove(&order.price);
-                }
-            }
+    fn levels_mut(&mut self, side: Side) -> &mut PriceLadder {
+        match side {
+            Side::Buy => &mut self.buy_levels,
+            Side::Sell => &mut self.sell_levels,
+        }
+    }
 
-            // Remove the order if no shares left
-            if order.shares == 0 {
-                self.sell_orders.remove(&order_ref_number);
-            }
+    fn handle_order_executed(&mut self, message: &wire::OrderExecuted, timestamp: u64) -> Result<(), std::io::Error> {
+        let reason = Reduction::Execute { match_number: message.match_number.get() };
+        self.reduce_order(message.header.stock_locate.get(), message.order_ref_number.get(), message.executed_shares.get(), timestamp, reason)
+    }
 
-            // Write updated orderbook state
-            self.write_orderbook(timestamp)?;
-        }
+    fn handle_order_executed_with_price(&mut self, message: &wire::OrderExecutedWithPrice, timestamp: u64) -> Result<(), std::io::Error> {
+        let reason = Reduction::ExecuteWithPrice { match_number: message.match_number.get(), price: message.execution_price.get(),
+                                                   printable: message.printable == b'Y' };
+        self.reduce_order(message.header.stock_locate.get(), message.order_ref_number.get(), message.executed_shares.get(), timestamp, reason)
+    }
 
-        Ok(())
+    fn handle_order_cancel(&mut self, message: &wire::OrderCancel, timestamp: u64) -> Result<(), std::io::Error> {
+        self.reduce_order(message.header.stock_locate.get(), message.order_ref_number.get(), message.cancelled_shares.get(), timestamp, Reduction::Cancel)
     }
 
-    unsafe fn handle_order_executed_with_price(&mut self, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        // ITCH 5.0 field layout for Order Executed With Price:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - order_ref_number (8 bytes) -> offset 10
-        // - executed_shares (4 bytes) -> offset 18
-        // - match_number (8 bytes) -> offset 22
-        // - printable (1 byte) -> offset 30
-        // - execution_price (4 bytes) -> offset 31
-
-        let order_ref_number = read_order_ref_be(data, 10);
-        let executed_shares = unsafe{read_u32_be(data, 18)};
-
-        // Similar to handle_order_executed but with price override
-        if let Some(order) = self.buy_orders.get_mut(&order_ref_number) {
-            // Reduce the shares
-            order.shares = order.shares.saturating_sub(executed_shares);
-
-            // Update the price map
-            if let Some(volume) = self.buy_price_map.get_mut(&order.price) {
-                *volume = volume.saturating_sub(executed_shares);
-                if *volume == 0 {
-                    self.buy_price_map.remove(&order.price);
-                }
-            }
+    // Executes and cancels: take the shares off the order, following it, writing the order message and the trade of
+    // an execution, then the book
+    fn reduce_order(&mut self, stock_locate: u16, order_ref: u64, shares: u32, timestamp: u64, reason: Reduction) -> Result<(), std::io::Error> {
+        let event = match reason {
+            Reduction::Cancel => OrderEvent::Cancel,
+            Reduction::Execute { .. } | Reduction::ExecuteWithPrice { .. } => OrderEvent::Execute,
+        };
+        // Orders kept off the book still trade
+        if let Some(order) = self.reduce_excluded(order_ref, Some(shares)) {
+            return match reason.trade(timestamp, order.price, shares, order.side) {
+                Some((trade, printable)) => self.record_trade(trade, printable),
+                None => Ok(()),
+            };
+        }
+        if self.filtered_out(order_ref) {
+            return self.report_unknown_order(stock_locate, timestamp, order_ref, shares);
+        }
+        self.track_order(timestamp, order_ref, event, Some(shares))?;
 
-            // Remove the order if no shares left
-            if order.shares == 0 {
-                self.buy_orders.remove(&order_ref_number);
+        let top = (self.buy_levels.best(), self.sell_levels.best());
+        let Some((price, side)) = self.take_shares(timestamp, order_ref, shares)? else {
+            return self.report_unknown_order(stock_locate, timestamp, order_ref, shares);
+        };
+        self.write_order(OrderMessage { timestamp, event, order_ref, shares, price, side })?;
+        if let Some((trade, printable)) = reason.trade(timestamp, price, shares, side) {
+            if printable
+                && let Some(hidden) = &mut self.hidden {
+                hidden.execution(price, trade.price, shares, top.0, top.1);
             }
+            self.record_trade(trade, printable)?;
+        }
 
-            // Write updated orderbook state
-            self.write_orderbook(timestamp)?;
-        } else if let Some(order) = self.sell_orders.get_mut(&order_ref_number) {
-            // Reduce the shares
-            order.shares = order.shares.saturating_sub(executed_shares);
-
-            // Update the price map
-            if let Some(volume) = self.sell_price_map.get_mut(&order.price) {
-                *volume = volume.saturating_sub(executed_shares);
-                if *volume == 0 {
-                    self.sell_price_map.remove(&order.price);
-                }
+        // Write updated orderbook state
+        self.write_orderbook(timestamp)
+    }
+
+    fn handle_order_delete(&mut self, message: &wire::OrderDelete, timestamp: u64) -> Result<(), std::io::Error> {
+        let stock_locate = message.header.stock_locate.get();
+        let order_ref_number = message.order_ref_number.get();
+        if self.reduce_excluded(order_ref_number, None).is_some() {
+            return Ok(());
+        }
+        if self.filtered_out(order_ref_number) {
+            return self.report_unknown_order(stock_locate, timestamp, order_ref_number, 0);
+        }
+        self.track_order(timestamp, order_ref_number, OrderEvent::Delete, None)?;
+
+        match self.remove_order(timestamp, order_ref_number)? {
+            Some(order) => {
+                self.write_order(OrderMessage { timestamp, event: OrderEvent::Delete, order_ref: order_ref_number, shares: order.shares, price: order.price, side: order.side })?;
+                self.write_orderbook(timestamp)
             }
+            None => self.report_unknown_order(stock_locate, timestamp, order_ref_number, 0),
+        }
+    }
 
-            // Remove the order if no shares left
-            if order.shares == 0 {
-                self.sell_orders.remove(&order_ref_number);
+    fn handle_order_replace(&mut self, message: &wire::OrderReplace, timestamp: u64) -> Result<(), std::io::Error> {
+        let stock_locate = message.header.stock_locate.get();
+        let original_order_ref_number = message.original_order_ref_number.get();
+        let new_order_ref_number = message.new_order_ref_number.get();
+        let new_shares = message.shares.get();
+        let new_price = message.price.get();
+        // An order kept off the book is added again when its replacement passes the checks
+        if let Some(order) = self.reduce_excluded(original_order_ref_number, None) {
+            return self.add_order(Order { ref_number: new_order_ref_number, timestamp, price: new_price, shares: new_shares, side: order.side, mpid: order.mpid });
+        }
+        if self.filtered_out(original_order_ref_number) {
+            return self.report_unknown_order(stock_locate, timestamp, original_order_ref_number, new_shares);
+        }
+        self.track_order(timestamp, original_order_ref_number, OrderEvent::Replace, None)?;
+
+        match self.remove_order(timestamp, original_order_ref_number)? {
+            // The new order keeps the side and attribution of the replaced one
+            Some(order) => {
+                self.write_order(OrderMessage { timestamp, event: OrderEvent::Replace, order_ref: original_order_ref_number, shares: order.shares,
+                                                price: order.price, side: order.side })?;
+                self.add_order(Order {
+                    ref_number: new_order_ref_number,
+                    timestamp,
+                    price: new_price,
+                    shares: new_shares,
+                    side: order.side,
+                    mpid: order.mpid,
+                })
             }
+            None => self.report_unknown_order(stock_locate, timestamp, original_order_ref_number, new_shares),
+        }
+    }
 
-            // Write updated orderbook state
-            self.write_orderbook(timestamp)?;
+    pub fn handle_trade(&mut self, message: &wire::Trade, timestamp: u64) -> Result<(), std::io::Error> {
+        // Check if the message is for our symbol
+        if !self.is_own(&message.header, &message.stock) {
+            return Ok(());
+        }
+
+        // Trades don't directly affect the orderbook unless they're executed against an order
+        // This is already handled by the order executed messages, so only record the print
+        let side = Side::from(message.buy_sell_indicator);
+        let shares = message.shares.get();
+        let price = message.price.get();
+        let match_number = message.match_number.get();
+        // The indicator of non-displayed trades isn't reliable, so sign them against the quote
+        let (aggressor, sign_method) = self.lee_ready(price);
+        if let Some(hidden) = &mut self.hidden {
+            hidden.non_displayed_trade(price, shares, self.buy_levels.best(), self.sell_levels.best());
         }
+        self.record_trade(TradeRecord { timestamp, price, shares, side, match_number, message_type: b'P', aggressor, sign_method }, true)?;
 
         Ok(())
     }
 
-    unsafe fn handle_order_cancel(&mut self, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        // ITCH 5.0 field layout for Order Cancel:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - order_ref_number (8 bytes) -> offset 10
-        // - cancelled_shares (4 bytes) -> offset 18
-
-        let order_ref_number = read_order_ref_be(data, 10);
-        let cancelled_shares = unsafe{read_u32_be(data, 18)};
+    fn handle_system_event(&mut self, message: &wire::SystemEvent, timestamp: u64) -> Result<(), std::io::Error> {
+        match message.event_code {
+            b'Q' => self.market_hours.open = Some(timestamp),
+            b'M' => self.market_hours.close = Some(timestamp),
+            _ => {}
+        }
+        let event = match message.event_code {
+            b'O' => "Start of Messages",
+            b'S' => "Start of System Hours",
+            b'Q' => "Start of Market Hours",
+            b'M' => "End of Market Hours",
+            b'E' => "End of System Hours",
+            b'C' => "End of Messages",
+            _ => "Unknown system event",
+        };
+        self.publish_event(MarketEvent { timestamp, message_type: b'S', symbol: None, description: event.to_string() })
+    }
 
-        // Look up the order
-        if let Some(order) = self.buy_orders.get_mut(&order_ref_number) {
-            // Reduce the shares
-            order.shares = order.shares.saturating_sub(cancelled_shares);
+    fn handle_trading_action(&mut self, message: &wire::StockTradingAction, timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock != self.symbol {
+            return Ok(());
+        }
 
-            // Update the price map
-            if let Some(volume) = self.buy_price_map.get_mut(&order.price) {
-                *volume = volume.saturating_sub(cancelled_shares);
-                if *volume == 0 {
-                    self.buy_price_map.remove(&order.price);
-                }
-            }
+        let state = match message.trading_state {
+            b'H' => "Halted",
+            b'P' => "Paused",
+            b'Q' => "Quotation only",
+            b'T' => "Trading",
+            _ => "Unknown trading state",
+        };
+        let reason = String::from_utf8_lossy(&message.reason);
+        let reason = reason.trim();
+        let description = if reason.is_empty() { state.to_string() } else { format!("{} ({})", state, reason) };
+        self.publish_event(MarketEvent { timestamp, message_type: b'H', symbol: Some(self.symbol_name.clone()), description })
+    }
 
-            // Remove the order if no shares left
-            if order.shares == 0 {
-                self.buy_orders.remove(&order_ref_number);
-            }
+    fn handle_rpii(&mut self, message: &wire::Rpii, timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock != self.symbol {
+            return Ok(());
+        }
+        let interest = RetailInterest::from(message.interest_flag);
+        if interest == self.retail_interest {
+            return Ok(());
+        }
+        self.retail_interest = interest;
+        self.write_orderbook(timestamp)
+    }
 
-            // Write updated orderbook state
-            self.write_orderbook(timestamp)?;
-        } else if let Some(order) = self.sell_orders.get_mut(&order_ref_number) {
-            // Reduce the shares
-            order.shares = order.shares.saturating_sub(cancelled_shares);
-
-            // Update the price map
-            if let Some(volume) = self.sell_price_map.get_mut(&order.price) {
-                *volume = volume.saturating_sub(cancelled_shares);
-                if *volume == 0 {
-                    self.sell_price_map.remove(&order.price);
-                }
-            }
+    fn handle_luld_collar(&mut self, message: &wire::LuldAuctionCollar, timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock != self.symbol {
+            return Ok(());
+        }
+        self.luld = LuldCollar {
+            reference_price: message.auction_collar_reference_price.get(),
+            upper_price: message.upper_auction_collar_price.get(),
+            lower_price: message.lower_auction_collar_price.get(),
+            extensions: message.auction_collar_extension.get(),
+        };
+        let description = format!("LULD auction collar: reference {}, upper {}, lower {}, extension {}",
+                                  format_price(self.luld.reference_price), format_price(self.luld.upper_price),
+                                  format_price(self.luld.lower_price), self.luld.extensions);
+        self.publish_event(MarketEvent { timestamp, message_type: b'J', symbol: Some(self.symbol_name.clone()), description })?;
+        self.write_orderbook(timestamp)
+    }
 
-            // Remove the order if no shares left
-            if order.shares == 0 {
-                self.sell_orders.remove(&order_ref_number);
-            }
+    fn handle_mwcb_decline_level(&mut self, message: &wire::MwcbDeclineLevel, timestamp: u64) -> Result<(), std::io::Error> {
+        // Levels have 8 decimals, shown to the cent
+        let level = |value: u64| format!("{}.{:02}", value / 100_000_000, value % 100_000_000 / 1_000_000);
+        let description = format!("MWCB decline levels: 1 at {}, 2 at {}, 3 at {}", level(message.level_1.get()),
+                                  level(message.level_2.get()), level(message.level_3.get()));
+        self.publish_event(MarketEvent { timestamp, message_type: b'V', symbol: None, description })
+    }
 
-            // Write updated orderbook state
-            self.write_orderbook(timestamp)?;
+    fn handle_mwcb_status(&mut self, message: &wire::MwcbStatus, timestamp: u64) -> Result<(), std::io::Error> {
+        // '1', '2' or '3'
+        let level = message.breached_level.wrapping_sub(b'0');
+        if !(1..=3).contains(&level) {
+            return Ok(());
         }
-
-        Ok(())
+        self.mwcb_level = self.mwcb_level.max(level);
+        self.publish_event(MarketEvent { timestamp, message_type: b'W', symbol: None, description: format!("MWCB level {} breached", level) })?;
+        self.write_orderbook(timestamp)
     }
 
+    fn handle_reg_sho(&mut self, message: &wire::RegShoRestriction, timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock != self.symbol {
+            return Ok(());
+        }
+        let (restricted, description) = match message.reg_sho_action {
+            b'0' => (false, "No Reg SHO short sale price test restriction"),
+            b'1' => (true, "Reg SHO short sale price test restriction in effect due to an intraday price drop"),
+            b'2' => (true, "Reg SHO short sale price test restriction remains in effect"),
+            _ => return Ok(()),
+        };
+        self.publish_event(MarketEvent { timestamp, message_type: b'Y', symbol: Some(self.symbol_name.clone()), description: description.to_string() })?;
+        if restricted == self.short_sale_restricted {
+            return Ok(());
+        }
+        self.short_sale_restricted = restricted;
+        self.write_orderbook(timestamp)
+    }
 
-    unsafe fn handle_order_delete(&mut self, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        // ITCH 5.0 field layout for Order Delete:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - order_ref_number (8 bytes) -> offset 10
-
-        let order_ref_number = read_order_ref_be(data, 10);
-
-        // Use peek_entry and take to avoid double hash computation
-        let mut price = 0;
-        let mut shares = 0;
-        let mut side = Side::Buy;
-        let mut exists = false;
-
-        // Check buy orders first as they're typically more frequent
-        if let Some(entry) = self.buy_orders.get(&order_ref_number) {
-            price = entry.price;
-            shares = entry.shares;
-            side = Side::Buy;
-            exists = true;
-        } else if let Some(entry) = self.sell_orders.get(&order_ref_number) {
-            price = entry.price;
-            shares = entry.shares;
-            side = Side::Sell;
-            exists = true;
-        }
-
-        if exists {
-            // Now perform the actual removal with the cached order info
-            match side {
-                Side::Buy => {
-                    self.buy_orders.remove(&order_ref_number);
-                    if let Some(volume) = self.buy_price_map.get_mut(&price) {
-                        *volume = volume.saturating_sub(shares);
-                        if *volume == 0 {
-                            self.buy_price_map.remove(&price);
-                        }
-                    }
-                },
-                Side::Sell => {
-                    self.sell_orders.remove(&order_ref_number);
-                    if let Some(volume) = self.sell_price_map.get_mut(&price) {
-                        *volume = volume.saturating_sub(shares);
-                        if *volume == 0 {
-                            self.sell_price_map.remove(&price);
-                        }
-                    }
-                }
-            }
+    fn handle_operational_halt(&mut self, message: &wire::OperationalHalt, timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock != self.symbol {
+            return Ok(());
+        }
+        let Some(market) = HALT_MARKETS.iter().position(|&(code, _)| code == message.market_code) else {
+            return Ok(());
+        };
+        let halted = match message.operational_halt_action {
+            b'H' => true,
+            b'T' => false,
+            _ => return Ok(()),
+        };
+        let name = HALT_MARKETS[market].1;
+        let description = if halted { format!("Operational halt on {}", name) } else { format!("Operational halt lifted on {}", name) };
+        self.publish_event(MarketEvent { timestamp, message_type: b'h', symbol: Some(self.symbol_name.clone()), description })?;
 
-            // Write updated orderbook state
-            self.write_orderbook(timestamp)?;
+        let halts = if halted { self.operational_halts | 1 << market } else { self.operational_halts & !(1 << market) };
+        if halts == self.operational_halts {
+            return Ok(());
         }
+        self.operational_halts = halts;
+        self.write_orderbook(timestamp)
+    }
 
-        Ok(())
+    fn handle_ipo_quoting_period(&mut self, message: &wire::IpoQuotingPeriodUpdate, timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock != self.symbol {
+            return Ok(());
+        }
+        let release_time = message.ipo_quotation_release_time.get() as u64 * 1_000_000_000;
+        let price = message.ipo_price.get();
+        let description = match message.ipo_quotation_release_qualifier {
+            b'C' => format!("IPO release canceled or postponed (was {}, price {})", format_time_of_day(release_time), format_price(price)),
+            _ => format!("IPO quotation release at {}, price {}", format_time_of_day(release_time), format_price(price)),
+        };
+        self.publish_event(MarketEvent { timestamp, message_type: b'K', symbol: Some(self.symbol_name.clone()), description })
     }
 
+    fn handle_direct_listing(&mut self, message: &wire::DirectListingWithCapitalRaisePriceDiscovery, timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock != self.symbol {
+            return Ok(());
+        }
+        let description = format!(
+            "Direct listing price discovery: {}, allowable {}-{}, near execution {} at {}, collar {}-{}",
+            if message.open_eligibility_status == b'Y' { "eligible to open" } else { "not eligible to open" },
+            format_price(message.minimum_allowable_price.get()), format_price(message.maximum_allowable_price.get()),
+            format_price(message.near_execution_price.get()), format_time_of_day(message.near_execution_time.get()),
+            format_price(message.lower_price_range_collar.get()), format_price(message.upper_price_range_collar.get()));
+        self.publish_event(MarketEvent { timestamp, message_type: b'O', symbol: Some(self.symbol_name.clone()), description })
+    }
 
-    unsafe fn handle_order_replace(&mut self, data: &[u8], timestamp: u64) -> Result<(), std::io::Error> {
-        // ITCH 5.0 field layout for Order Replace:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - original_order_ref_number (8 bytes) -> offset 10
-        // - new_order_ref_number (8 bytes) -> offset 18
-        // - shares (4 bytes) -> offset 26
-        // - price (4 bytes) -> offset 30
-
-        let original_order_ref_number = read_order_ref_be(data, 10);
-        let new_order_ref_number = read_order_ref_be(data, 18);
-        let new_shares = unsafe{read_u32_be(data, 26)};
-        let new_price = unsafe{read_u32_be(data, 30)};
-
-        // Two-phase approach: first check, then remove
-        let mut side = Side::Buy;
-        let mut old_price = 0;
-        let mut old_shares = 0;
-        let mut found = false;
-
-        // Check buy orders first
-        if let Some(order) = self.buy_orders.get(&original_order_ref_number) {
-            side = order.side;
-            old_price = order.price;
-            old_shares = order.shares;
-            found = true;
-        } else if let Some(order) = self.sell_orders.get(&original_order_ref_number) {
-            side = order.side;
-            old_price = order.price;
-            old_shares = order.shares;
-            found = true;
-        }
-
-        if found {
-            // Now actually remove the old order
-            match side {
-                Side::Buy => {
-                    self.buy_orders.remove(&original_order_ref_number);
-                    if let Some(volume) = self.buy_price_map.get_mut(&old_price) {
-                        *volume = volume.saturating_sub(old_shares);
-                        if *volume == 0 {
-                            self.buy_price_map.remove(&old_price);
-                        }
-                    }
-                },
-                Side::Sell => {
-                    self.sell_orders.remove(&original_order_ref_number);
-                    if let Some(volume) = self.sell_price_map.get_mut(&old_price) {
-                        *volume = volume.saturating_sub(old_shares);
-                        if *volume == 0 {
-                            self.sell_price_map.remove(&old_price);
-                        }
-                    }
-                }
+    fn handle_stock_directory(&mut self, message: &wire::StockDirectory, _timestamp: u64) -> Result<(), std::io::Error> {
+        if message.stock == self.symbol {
+            self.stock_locate = Some(message.header.stock_locate.get());
+            if message.round_lot_size.get() > 0 {
+                self.round_lot = message.round_lot_size.get();
             }
+        }
+        Ok(())
+    }
+
+    // An order message for the tracked symbol whose order isn't on the book. Other symbols'
+    // orders are never added, so only messages with the symbol's stock locate are checked
+    fn report_unknown_order(&mut self, stock_locate: u16, timestamp: u64, order_ref: u64, shares: u32) -> Result<(), std::io::Error> {
+        let Some(checker) = &mut self.integrity else {
+            return Ok(());
+        };
+        if self.stock_locate != Some(stock_locate) {
+            return Ok(());
+        }
+        checker.report(Anomaly { timestamp, kind: AnomalyKind::UnknownOrderRef, message_type: self.message_type,
+                                 order_ref, price: 0, shares, detail: String::new() })
+    }
 
-            // Add the new order
-            let new_order = Order {
-                ref_number: new_order_ref_number,
+    fn report_negative_level(&mut self, timestamp: u64, order_ref: u64, price: u32, shares: u32) -> Result<(), std::io::Error> {
+        match &mut self.integrity {
+            Some(checker) => checker.report(Anomaly {
                 timestamp,
-                price: new_price,
-                shares: new_shares,
-                side,
-            };
+                kind: AnomalyKind::NegativeLevel,
+                message_type: self.message_type,
+                order_ref,
+                price,
+                shares,
+                detail: "removed more shares than the order or level held".to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
 
-            self.add_order(new_order)?;
+    // Infer the aggressor of a trade from the prevailing quote, falling back to the tick test at
+    // the mid or when a side is empty; a zero tick takes the direction of the last price change
+    fn lee_ready(&self, price: u32) -> (Option<Side>, SignMethod) {
+        if let (Some(bid), Some(ask)) = (self.buy_levels.best(), self.sell_levels.best()) {
+            let doubled_mid = bid as u64 + ask as u64;
+            let doubled_price = 2 * price as u64;
+            if doubled_price > doubled_mid {
+                return (Some(Side::Buy), SignMethod::Quote);
+            }
+            if doubled_price < doubled_mid {
+                return (Some(Side::Sell), SignMethod::Quote);
+            }
+        }
+        match self.last_trade_price {
+            Some(last) if price > last => (Some(Side::Buy), SignMethod::Tick),
+            Some(last) if price < last => (Some(Side::Sell), SignMethod::Tick),
+            _ if self.last_tick.is_some() => (self.last_tick, SignMethod::Tick),
+            _ => (None, SignMethod::Unknown),
         }
+    }
 
-        Ok(())
+    // Whether the ref surely isn't one of the book's orders, by the bloom filter when it's enabled
+    #[inline]
+    fn filtered_out(&self, order_ref: u64) -> bool {
+        self.ref_filter.as_ref().is_some_and(|filter| !filter.may_contain(order_ref))
     }
 
-    pub fn handle_trade(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
-        // ITCH 5.0 field layout for Trade:
-        // - stock_locate (2 bytes)
-        // - tracking_number (2 bytes)
-        // - timestamp (6 bytes)
-        // - order_ref_number (8 bytes) -> offset 10
-        // - buy_sell_indicator (1 byte) -> offset 18
-        // - shares (4 bytes) -> offset 19
-        // - stock (8 bytes) -> offset 23
-        // - price (4 bytes) -> offset 31
-        // - match_number (8 bytes) -> offset 35
+    // Reference price for the --price-band check: the mid, else the best price of the quoted side, else the last trade
+    fn band_reference(&self) -> Option<u32> {
+        match (self.buy_levels.best(), self.sell_levels.best()) {
+            (Some(bid), Some(ask)) => Some(((bid as u64 + ask as u64) / 2) as u32),
+            (Some(price), None) | (None, Some(price)) => Some(price),
+            (None, None) => self.last_trade_price,
+        }
+    }
 
-        let stock = unsafe{read_stock(data, 23)};
+    // Take shares off an order kept off the book, all of them for None. The order as it was before,
+    // or None when the ref isn't one of them
+    fn reduce_excluded(&mut self, order_ref: u64, shares: Option<u32>) -> Option<Order> {
+        if self.excluded_orders.is_empty() {
+            return None;
+        }
+        let order = self.excluded_orders.get_mut(&order_ref)?;
+        let before = order.clone();
+        match shares {
+            Some(shares) if shares < order.shares => order.shares -= shares,
+            _ => {
+                self.excluded_orders.remove(&order_ref);
+            }
+        }
+        Some(before)
+    }
 
-        // Check if the message is for our symbol
-        if stock != self.symbol {
+    // Follow an execute, cancel, delete or replace in the order trackers that are enabled
+    fn track_order(&mut self, timestamp: u64, order_ref: u64, event: OrderEvent, shares: Option<u32>) -> Result<(), std::io::Error> {
+        if let Some(analytics) = &mut self.analytics {
+            analytics.reduce(self.message_type, timestamp, order_ref, event, shares);
+        }
+        if let Some(participants) = &mut self.participants
+            && let Some(order) = self.orders.get(order_ref) {
+            participants.reduce(order.mpid, event, shares.unwrap_or(order.shares).min(order.shares));
+        }
+        match &mut self.queue {
+            Some(tracker) => tracker.reduce(timestamp, order_ref, event, shares),
+            None => Ok(()),
+        }
+    }
+
+    // Hand an order message to the sinks that take them, with the levels it left
+    fn write_order(&mut self, message: OrderMessage) -> Result<(), std::io::Error> {
+        if !self.order_sinks {
             return Ok(());
         }
+        let _timer = profiling::start(Stage::Write);
+        let depth = if self.bbo_only { 1 } else { MAX_BOOK_DEPTH };
+        let (bids, asks) = if self.lots {
+            (self.round_lot_levels(Side::Buy, depth), self.round_lot_levels(Side::Sell, depth))
+        } else {
+            (self.buy_levels.top(depth), self.sell_levels.top(depth))
+        };
+        for sink in &mut self.sinks {
+            sink.write_order(&self.symbol_name, &message, &bids, &asks)?;
+        }
+        Ok(())
+    }
+
+    fn publish_event(&mut self, event: MarketEvent) -> Result<(), std::io::Error> {
+        let _timer = profiling::start(Stage::Write);
+        for sink in &mut self.sinks {
+            sink.write_event(&event)?;
+        }
+        Ok(())
+    }
 
-        // Trades don't directly affect the orderbook unless they're executed against an order
-        // This is already handled by the order executed messages
+    // Write the bars that just closed to the bars file and the sinks
+    fn write_bars(&mut self) -> Result<(), std::io::Error> {
+        if self.closed_bars.is_empty() {
+            return Ok(());
+        }
+        if let Some(bars) = &mut self.bars {
+            bars.write(&self.symbol_name, &self.closed_bars)?;
+        }
+        for sink in &mut self.sinks {
+            for bar in &self.closed_bars {
+                sink.write_bar(&self.symbol_name, bar)?;
+            }
+        }
+        self.closed_bars.clear();
+        Ok(())
+    }
 
+    // Non-printable executions are still published but left out of the trade statistics,
+    // since their volume is reported again by a later message
+    #[inline]
+    fn record_trade(&mut self, trade: TradeRecord, printable: bool) -> Result<(), std::io::Error> {
+        if trade.shares < self.round_lot {
+            if let Some(report) = &mut self.odd_lot_report {
+                report.trade(&trade, self.round_lot)?;
+            }
+            if self.odd_lots == OddLotMode::Exclude {
+                return Ok(());
+            }
+        }
+        if printable {
+            self.trade_stats.add(trade.price, trade.shares);
+            if let Some(bars) = &mut self.bars {
+                bars.add(trade.timestamp, trade.price, trade.shares, &mut self.closed_bars);
+                self.write_bars()?;
+            }
+        }
+        if let Some(last) = self.last_trade_price
+            && trade.price != last {
+            self.last_tick = Some(if trade.price > last { Side::Buy } else { Side::Sell });
+        }
+        self.last_trade_price = Some(trade.price);
+        let _timer = profiling::start(Stage::Write);
+        for sink in &mut self.sinks {
+            sink.write_trade(&self.symbol_name, &trade)?;
+        }
         Ok(())
     }
 
     fn add_order(&mut self, order: Order) -> Result<(), std::io::Error> {
         let ts = order.timestamp;
-        // Update the price map
-        if order.side == Side::Buy {
-            *self.buy_price_map.entry(order.price).or_insert(0) += order.shares;
-            self.buy_orders.insert(order.ref_number, order);
-        } else {
-            *self.sell_price_map.entry(order.price).or_insert(0) += order.shares;
-            self.sell_orders.insert(order.ref_number, order);
+        if let Some(band) = self.price_band
+            && let Some(reference) = self.band_reference()
+            && !band.contains(order.price, reference) {
+            let dropped = band.action == BandAction::Drop;
+            if let Some(analytics) = &mut self.analytics {
+                analytics.out_of_band(dropped);
+            }
+            if let Some(checker) = &mut self.integrity {
+                checker.report(Anomaly { timestamp: ts, kind: AnomalyKind::OutOfBand, message_type: self.message_type, order_ref: order.ref_number,
+                                         price: order.price, shares: order.shares, detail: format!("reference {}", format_price(reference)) })?;
+            }
+            if dropped {
+                self.excluded_orders.insert(order.ref_number, order);
+                return Ok(());
+            }
+        }
+        if order.shares < self.round_lot {
+            if let Some(report) = &mut self.odd_lot_report {
+                report.order(self.message_type, &order, self.round_lot)?;
+            }
+            if self.odd_lots == OddLotMode::Exclude {
+                self.excluded_orders.insert(order.ref_number, order);
+                return Ok(());
+            }
+        }
+        if let Some(tracker) = &mut self.queue {
+            tracker.add(&order)?;
+        }
+        if let Some(analytics) = &mut self.analytics {
+            analytics.add(self.message_type, order.ref_number, order.timestamp, order.shares);
+        }
+        if let Some(participants) = &mut self.participants {
+            participants.add(self.message_type, order.mpid, order.shares);
         }
+        if let Some(filter) = &mut self.ref_filter {
+            filter.insert(order.ref_number);
+        }
+        // Update the price map
+        self.levels_mut(order.side).add(order.price, order.shares);
+        let message = OrderMessage { timestamp: ts, event: OrderEvent::Add, order_ref: order.ref_number, shares: order.shares, price: order.price, side: order.side };
+        self.orders.insert(order);
+        self.write_order(message)?;
 
         // Write updated orderbook state
         self.write_orderbook(ts)?;
@@ -571,119 +1368,402 @@ impl OrderBook {
         Ok(())
     }
 
-    #[inline]
-    fn price_to_decimal_fast(&self, price: u32) -> (u32, u32) {
-        // Returns the integer part and 4 decimal places
-        let integer = price / 10000;
-        let decimal = price % 10000;
-        (integer, decimal)
-    }
-
     fn write_orderbook(&mut self, timestamp: u64) -> Result<(), std::io::Error> {
-        // Get the top levels for bids and asks
-        let bids = self.get_top_bids(MAX_BOOK_DEPTH);
-        let asks = self.get_top_asks(MAX_BOOK_DEPTH);
+        // The top levels are kept up to date by the ladders, so this is a copy
+        let depth = if self.bbo_only { 1 } else { MAX_BOOK_DEPTH };
+        let bids = self.buy_levels.top(depth);
+        let asks = self.sell_levels.top(depth);
+        if let Some(checker) = &mut self.integrity {
+            checker.check_top(timestamp, self.message_type, bids.first().map(|l| l.price), asks.first().map(|l| l.price))?;
+        }
+        if let Some(analytics) = &mut self.analytics {
+            analytics.update_top(timestamp, bids.first().copied(), asks.first().copied());
+        }
 
-        let mid_price = bids.get(0).map_or(0, |p| p.price)  +
-            asks.get(0).map_or(0, |p| p.price) ;
-        let imbalance = calculate_imbalance(&bids, &asks);
+        // Order flow accumulates over skipped updates until the next written row
+        self.ofi += order_flow_imbalance((self.last_top.0.as_ref(), self.last_top.1.as_ref()), (bids.first(), asks.first()));
+        self.last_top = (bids.first().copied(), asks.first().copied());
+        if !self.estimators.is_empty() {
+            let mid = match (bids.first(), asks.first()) {
+                (Some(bid), Some(ask)) => Some((bid.price as f64 + ask.price as f64) / 2.0),
+                _ => None,
+            };
+            self.estimators.update(timestamp, mid);
+        }
+        let (bids, asks) = if self.lots {
+            (self.round_lot_levels(Side::Buy, depth), self.round_lot_levels(Side::Sell, depth))
+        } else {
+            (bids, asks)
+        };
+
+        // Skip the row if nothing the current write mode cares about has changed
+        if let Some(last) = &self.last_state {
+            let unchanged = match self.write_mode {
+                WriteMode::EveryMessage => false,
+                WriteMode::OnChange => last.bid_levels == bids && last.ask_levels == asks,
+                WriteMode::OnTopOfBookChange => {
+                    last.bid_levels.first() == bids.first() && last.ask_levels.first() == asks.first()
+                }
+            };
+            if unchanged {
+                self.skipped_count += 1;
+                return Ok(());
+            }
+        }
+
+        let mid_price = match (bids.first(), asks.first()) {
+            (Some(bid), Some(ask)) => (bid.price as f64 + ask.price as f64) / 20000.0,
+            _ => 0.0,
+        };
+        let imbalance = self.imbalance_weights.imbalance(&bids, &asks);
 
 
         // Create a new state to check for changes
-        let new_state = OrderbookState {
+        let new_state = BookSnapshot {
             timestamp,
-            bid_levels: bids.clone(),
-            ask_levels: asks.clone(),
-            mid_price,      // Initialize with calculated mid price
+            bid_levels: bids,
+            ask_levels: asks,
+            mid_price,
             imbalance,      // Initialize with calculated imbalance
+            trades: self.trade_stats,
+            ofi: self.ofi,
+            windows: self.estimators.stats(),
+            retail_interest: self.retail_interest,
+            luld: self.luld,
+            mwcb_level: self.mwcb_level,
+            short_sale_restricted: self.short_sale_restricted,
+            operational_halts: self.operational_halts,
+            market_hours: self.market_hours,
         };
+        self.ofi = 0;
 
         // Increment update counter
         self.update_count += 1;
 
+        let _timer = profiling::start(Stage::Write);
+        for sink in &mut self.sinks {
+            sink.write_snapshot(&self.symbol_name, &new_state)?;
+        }
+
         // Update the last known state
         self.last_state = Some(new_state);
 
-        // Add padded bids and asks
-        let padded_bids = self.pad_levels(bids, MAX_BOOK_DEPTH);
-        let padded_asks = self.pad_levels(asks, MAX_BOOK_DEPTH);
+        Ok(())
+    }
+
+    // The best `depth` levels of a side holding at least a round lot, with their size in round lots
+    fn round_lot_levels(&self, side: Side, depth: usize) -> Levels {
+        let ladder = match side {
+            Side::Buy => &self.buy_levels,
+            Side::Sell => &self.sell_levels,
+        };
+        ladder.iter()
+            .filter(|&(_, volume)| volume >= self.round_lot)
+            .map(|(price, volume)| PriceLevel { price, total_volume: volume / self.round_lot })
+            .take(depth)
+            .collect()
+    }
+
+    // The best MAX_BOOK_DEPTH bid and ask levels, best first
+    pub fn top_levels(&self) -> (&Levels, &Levels) {
+        (self.buy_levels.top_levels(), self.sell_levels.top_levels())
+    }
 
-        use std::io::Write;
+    // Up to `depth` bid and ask levels, best first, past MAX_BOOK_DEPTH too
+    pub fn levels(&self, depth: usize) -> (Vec<PriceLevel>, Vec<PriceLevel>) {
+        let side = |ladder: &PriceLadder| ladder.iter().take(depth).map(|(price, total_volume)| PriceLevel { price, total_volume }).collect();
+        (side(&self.buy_levels), side(&self.sell_levels))
+    }
+
+    pub fn symbol_name(&self) -> &str {
+        &self.symbol_name
+    }
 
-        // Write timestamp directly
-        write!(self.writer, "{}", timestamp)?;
+    // Every resting order in priority order: bids best price first, asks best price first,
+    // and earliest first within a price level
+    pub fn resting_orders(&self) -> (Vec<&Order>, Vec<&Order>) {
+        let (mut bids, mut asks): (Vec<&Order>, Vec<&Order>) = self.orders.values().partition(|o| o.side == Side::Buy);
+        bids.sort_by_key(|o| (std::cmp::Reverse(o.price), o.timestamp, o.ref_number));
+        asks.sort_by_key(|o| (o.price, o.timestamp, o.ref_number));
+        (bids, asks)
+    }
 
-        // Use a specialized approach for price decimal formatting
-        // that avoids floating-point operations entirely
-        for i in 0..MAX_BOOK_DEPTH {
-            // Get integer and decimal parts for prices
-            let (bid_int, bid_dec) = self.price_to_decimal_fast(padded_bids[i].price);
-            let (ask_int, ask_dec) = self.price_to_decimal_fast(padded_asks[i].price);
-            //let (mid_int, mid_dec) = self.price_to_decimal_fast(mid_price);
+    // Messages seen and snapshots written so far
+    pub fn counts(&self) -> (u64, u64) {
+        (self.message_count, self.update_count)
+    }
 
+    // Messages seen so far by type byte, only the types that occurred
+    pub fn message_counts(&self) -> Vec<(u8, u64)> {
+        (0..=u8::MAX).zip(self.message_types.iter().copied()).filter(|&(_, count)| count > 0).collect()
+    }
 
-            // Write formatted prices with proper decimal padding
-            write!(self.writer, ",{}.{:04},{},{}.{:04},{}",
-                   bid_int, bid_dec,
-                   padded_bids[i].total_volume,
-                   ask_int, ask_dec,
-                   padded_asks[i].total_volume
-                   )?;
-        }
+    // Unchanged snapshots not written
+    pub fn skipped_count(&self) -> u64 {
+        self.skipped_count
+    }
 
-        write!(self.writer, ",{:.04},{:.06}", mid_price, imbalance)?;
+    // Book anomalies by kind, in integrity::ANOMALY_KINDS order; None unless they are checked
+    pub fn anomaly_counts(&self) -> Option<[u64; ANOMALY_KINDS.len()]> {
+        self.integrity.as_ref().map(IntegrityChecker::counts)
+    }
 
-        // End the line
-        self.writer.write_all(b"\n")?;
+    // Where the parser stopped, to continue from with --start-offset; None until it has
+    pub fn end_offset(&self) -> Option<u64> {
+        self.end_offset
+    }
+
+    pub fn trade_stats(&self) -> TradeStats {
+        self.trade_stats
+    }
+
+    pub fn order(&self, order_ref: u64) -> Option<&Order> {
+        self.orders.get(order_ref)
+    }
+
+    // The resting orders at a price level, in no particular order
+    pub fn orders_at(&self, side: Side, price: u32) -> impl Iterator<Item = &Order> {
+        self.orders.values().filter(move |order| order.side == side && order.price == price)
+    }
 
-        // Only flush periodically to reduce I/O overhead
-        if self.update_count % 100 == 0 {
-            self.writer.flush()?;
+    // Best bid and ask prices, None for an empty side
+    pub fn best_prices(&self) -> (Option<u32>, Option<u32>) {
+        (self.buy_levels.best(), self.sell_levels.best())
+    }
+
+    // Capture the book state after the message ending at `offset`
+    pub fn checkpoint(&self, offset: u64, timestamp: u64) -> Checkpoint {
+        Checkpoint {
+            symbol: self.symbol,
+            offset,
+            timestamp,
+            stock_locate: self.stock_locate,
+            trade_stats: self.trade_stats,
+            ofi: self.ofi,
+            last_trade_price: self.last_trade_price,
+            last_tick: self.last_tick,
+            retail_interest: self.retail_interest,
+            luld: self.luld,
+            mwcb_level: self.mwcb_level,
+            short_sale_restricted: self.short_sale_restricted,
+            operational_halts: self.operational_halts,
+            market_hours: self.market_hours,
+            orders: self.orders.values().cloned().collect(),
+            round_lot: self.round_lot,
+            excluded_orders: self.excluded_orders.values().cloned().collect(),
+            participants: self.participants.as_ref().map(ParticipantReport::activity).unwrap_or_default(),
+            bid_levels: self.buy_levels.ascending(),
+            ask_levels: self.sell_levels.ascending(),
+            last_state: self.last_state.clone(),
         }
+    }
 
+    // Load the book state from a checkpoint taken for the same symbol
+    pub fn restore(&mut self, checkpoint: Checkpoint) -> Result<(), std::io::Error> {
+        if checkpoint.symbol != self.symbol {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!(
+                "Checkpoint is for symbol {}, not {}", String::from_utf8_lossy(&checkpoint.symbol).trim_end(), self.symbol_name)));
+        }
+        self.stock_locate = checkpoint.stock_locate;
+        self.trade_stats = checkpoint.trade_stats;
+        self.ofi = checkpoint.ofi;
+        self.last_trade_price = checkpoint.last_trade_price;
+        self.last_tick = checkpoint.last_tick;
+        if let Some(participants) = &mut self.participants {
+            participants.restore(checkpoint.participants);
+        }
+        self.retail_interest = checkpoint.retail_interest;
+        self.luld = checkpoint.luld;
+        self.mwcb_level = checkpoint.mwcb_level;
+        self.short_sale_restricted = checkpoint.short_sale_restricted;
+        self.operational_halts = checkpoint.operational_halts;
+        self.market_hours = checkpoint.market_hours;
+        self.round_lot = checkpoint.round_lot;
+        self.excluded_orders = checkpoint.excluded_orders.into_iter().map(|order| (order.ref_number, order)).collect();
+        if let Some(tracker) = &mut self.queue {
+            // Arrival order is approximated by timestamp, then order ref
+            let mut orders: Vec<&Order> = checkpoint.orders.iter().collect();
+            orders.sort_by_key(|o| (o.timestamp, o.ref_number));
+            for order in orders {
+                tracker.restore(order);
+            }
+        }
+        for order in checkpoint.orders {
+            if let Some(filter) = &mut self.ref_filter {
+                filter.insert(order.ref_number);
+            }
+            self.orders.insert(order);
+        }
+        self.buy_levels = PriceLadder::from_levels(Side::Buy, checkpoint.bid_levels);
+        self.sell_levels = PriceLadder::from_levels(Side::Sell, checkpoint.ask_levels);
+        self.last_state = checkpoint.last_state;
+        // The checker only reports the book becoming crossed or locked, so it starts from the restored top
+        if let Some(checker) = &mut self.integrity {
+            checker.restore_top(self.buy_levels.best(), self.sell_levels.best());
+        }
+        self.last_top = (self.buy_levels.top_levels().first().copied(), self.sell_levels.top_levels().first().copied());
         Ok(())
     }
 
+    fn update_counts(&mut self) {
+        METRICS.set_book_counts(self.update_count, self.skipped_count);
+        for sink in &mut self.sinks {
+            sink.update_counts(self.message_count, self.update_count, self.skipped_count);
+        }
+    }
 
-    // Ensure we have exactly 'count' levels by padding with zeros if needed
-    fn pad_levels(&self, mut levels: Vec<PriceLevel>, count: usize) -> Vec<PriceLevel> {
-        while levels.len() < count {
-            levels.push(PriceLevel { price: 0, total_volume: 0 });
+    pub fn finalize(&mut self) -> Result<(), std::io::Error> {
+        // The bars in progress and the final counters go out before the sinks close
+        if let Some(bars) = &mut self.bars {
+            bars.close_all(&mut self.closed_bars);
+            self.write_bars()?;
+            if let Some(bars) = &mut self.bars {
+                bars.finalize()?;
+            }
         }
-        levels
+        self.update_counts();
+        for sink in &mut self.sinks {
+            sink.finalize()?;
+        }
+        if let Some(checker) = &mut self.integrity {
+            checker.finalize()?;
+        }
+        if let Some(tracker) = &mut self.queue {
+            tracker.finalize()?;
+        }
+        if let Some(analytics) = &mut self.analytics {
+            analytics.finalize(&self.symbol_name)?;
+        }
+        if let Some(participants) = &mut self.participants {
+            participants.finalize()?;
+        }
+        if let Some(heatmap) = &mut self.heatmap {
+            heatmap.finalize(&self.buy_levels, &self.sell_levels)?;
+        }
+        if let Some(hidden) = &mut self.hidden {
+            hidden.finalize(&self.symbol_name)?;
+        }
+        if let Some(report) = &mut self.odd_lot_report {
+            report.finalize()?;
+        }
+
+        tracing::info!("Processed {} messages", self.message_count);
+        tracing::info!("Wrote {} orderbook updates", self.update_count);
+        if self.write_mode != WriteMode::EveryMessage {
+            tracing::info!("Skipped {} unchanged orderbook updates", self.skipped_count);
+        }
+        if let Some(offset) = self.end_offset {
+            tracing::info!("Ended at byte offset {}", offset);
+        }
+
+        Ok(())
     }
+}
 
-    fn get_top_bids(&self, count: usize) -> Vec<PriceLevel> {
-        // Get keys in reverse order (highest to lowest) for bids
-        self.buy_price_map.iter()
-            .rev() // Reverse to get highest prices first
-            .take(count)
-            .map(|(&price, &volume)| PriceLevel {
-                price,
-                total_volume: volume,
-            })
-            .collect()
+// The book's handlers for the messages that move it or its market state
+impl MessageHandler for OrderBook {
+    fn on_message(&mut self, message_type: MessageType, timestamp: u64) -> Result<(), std::io::Error> {
+        self.message_count+=1;
+        if let Some(bars) = &mut self.bars
+            && bars.due(timestamp) {
+            bars.close_until(timestamp, &mut self.closed_bars);
+            self.write_bars()?;
+        }
+        if let Some(heatmap) = &mut self.heatmap
+            && heatmap.due(timestamp) {
+            heatmap.sample_until(timestamp, &self.buy_levels, &self.sell_levels);
+        }
+        self.message_types[message_type as usize] += 1;
+        // Keep the sink and metrics counters reasonably fresh without touching them on every message
+        if self.message_count.is_multiple_of(STATS_INTERVAL) {
+            self.update_counts();
+        }
+        self.message_type = message_type as u8;
+        Ok(())
     }
 
-    fn get_top_asks(&self, count: usize) -> Vec<PriceLevel> {
-        // BTreeMap already gives us keys in ascending order (lowest to highest) for asks
-        self.sell_price_map.iter()
-            .take(count)
-            .map(|(&price, &volume)| PriceLevel {
-                price,
-                total_volume: volume,
-            })
-            .collect()
+    fn on_system_event(&mut self, message: &wire::SystemEvent, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_system_event(message, timestamp)
     }
 
-    pub fn finalize(&mut self) -> Result<(), std::io::Error> {
-        // Ensure all data is flushed to disk
-        self.writer.flush()?;
+    fn on_stock_directory(&mut self, message: &wire::StockDirectory, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_stock_directory(message, timestamp)
+    }
+
+    fn on_trading_action(&mut self, message: &wire::StockTradingAction, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_trading_action(message, timestamp)
+    }
+
+    fn on_reg_sho(&mut self, message: &wire::RegShoRestriction, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_reg_sho(message, timestamp)
+    }
+
+    fn on_mwcb_decline_level(&mut self, message: &wire::MwcbDeclineLevel, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_mwcb_decline_level(message, timestamp)
+    }
+
+    fn on_mwcb_status(&mut self, message: &wire::MwcbStatus, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_mwcb_status(message, timestamp)
+    }
 
-        // Print statistics
-        println!("Processed {} messages", self.message_count);
-        println!("Wrote {} orderbook updates", self.update_count);
+    fn on_ipo_quoting_period(&mut self, message: &wire::IpoQuotingPeriodUpdate, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_ipo_quoting_period(message, timestamp)
+    }
+
+    fn on_luld_collar(&mut self, message: &wire::LuldAuctionCollar, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_luld_collar(message, timestamp)
+    }
+
+    fn on_operational_halt(&mut self, message: &wire::OperationalHalt, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_operational_halt(message, timestamp)
+    }
+
+    fn on_add_order(&mut self, message: &wire::AddOrder, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_add_order(message, timestamp)
+    }
+
+    fn on_add_order_with_mpid(&mut self, message: &wire::AddOrderWithMpid, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_add_order_with_mpid(message, timestamp)
+    }
+
+    fn on_execute(&mut self, message: &wire::OrderExecuted, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_order_executed(message, timestamp)
+    }
+
+    fn on_execute_with_price(&mut self, message: &wire::OrderExecutedWithPrice, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_order_executed_with_price(message, timestamp)
+    }
+
+    fn on_cancel(&mut self, message: &wire::OrderCancel, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_order_cancel(message, timestamp)
+    }
+
+    fn on_delete(&mut self, message: &wire::OrderDelete, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_order_delete(message, timestamp)
+    }
+
+    fn on_replace(&mut self, message: &wire::OrderReplace, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_order_replace(message, timestamp)
+    }
+
+    fn on_trade(&mut self, message: &wire::Trade, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_trade(message, timestamp)
+    }
 
+    fn on_rpii(&mut self, message: &wire::Rpii, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_rpii(message, timestamp)
+    }
+
+    fn on_direct_listing(&mut self, message: &wire::DirectListingWithCapitalRaisePriceDiscovery, timestamp: u64) -> Result<(), std::io::Error> {
+        self.handle_direct_listing(message, timestamp)
+    }
+
+    fn on_end(&mut self, offset: u64) -> Result<(), std::io::Error> {
+        self.end_offset = Some(offset);
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn checkpoint_at(&self, offset: u64, timestamp: u64) -> Option<Checkpoint> {
+        Some(self.checkpoint(offset, timestamp))
+    }
+}
@@ -0,0 +1,241 @@
+// Outgoing messages of a WebSocket client. Book updates are taken off the broadcast channel as they come and
+// queued per client, and a writer sends them at the client's own pace, so a slow client only falls behind in
+// its own queue. What happens when that queue is full is the slow-client policy: disconnect with a reason,
+// keep buffering by dropping the oldest update, or conflate to the latest snapshot of each symbol. Clients that
+// asked for compression get every message as a binary frame of zstd data
+
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::{Sink, SinkExt};
+use tokio::sync::Notify;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+
+use crate::delta::{DeltaEncoder, DEFAULT_RESNAPSHOT_INTERVAL};
+use crate::metrics::METRICS;
+use crate::websocket::{BookUpdate, WireFormat, BROADCAST_CAPACITY};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SlowClientPolicy {
+    // Replace a queued snapshot of a symbol with its latest one, dropping the oldest message if there is none
+    Conflate,
+    // Drop the oldest queued message and tell the client how many it missed
+    Buffer,
+    // Close the connection with a reason
+    #[default]
+    Disconnect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientPolicy {
+    pub slow_client: SlowClientPolicy,
+    // Updates queued for a client before the policy applies
+    pub buffer: usize,
+    // How often to ping the client; a client that hasn't answered by the next ping is disconnected
+    pub ping_interval: Option<Duration>,
+    // Deltas between full snapshots of a symbol with the delta format, 0 for none
+    pub resnapshot_every: u64,
+    // zstd level for clients that connect with ?compression=zstd, None to refuse them
+    pub compression: Option<i32>,
+}
+
+impl Default for ClientPolicy {
+    fn default() -> Self {
+        ClientPolicy {
+            slow_client: SlowClientPolicy::default(),
+            buffer: BROADCAST_CAPACITY,
+            ping_interval: Some(Duration::from_secs(30)),
+            resnapshot_every: DEFAULT_RESNAPSHOT_INTERVAL,
+            compression: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Queues {
+    // Replies, pings and close frames, sent before any update
+    control: VecDeque<Message>,
+    updates: VecDeque<BookUpdate>,
+    // Updates dropped since the client was last told
+    dropped: u64,
+    // A close frame is queued; nothing is queued after it
+    closed: bool,
+    // What the client was last sent of each symbol, with the delta format
+    deltas: Option<DeltaEncoder>,
+}
+
+pub struct Outbox {
+    queues: Mutex<Queues>,
+    ready: Notify,
+    // Signalled whenever the writer has sent everything queued
+    drained: Notify,
+    format: WireFormat,
+    policy: ClientPolicy,
+}
+
+impl Outbox {
+    pub fn new(format: WireFormat, policy: ClientPolicy) -> Self {
+        let deltas = (format == WireFormat::Delta).then(|| DeltaEncoder::new(policy.resnapshot_every));
+        Outbox { queues: Mutex::new(Queues { deltas, ..Default::default() }), ready: Notify::new(), drained: Notify::new(), format, policy }
+    }
+
+    pub fn push_control(&self, message: Message) {
+        let mut queues = self.queues.lock().unwrap();
+        if !queues.closed {
+            queues.control.push_back(message);
+            self.ready.notify_one();
+        }
+    }
+
+    // Queue an update, applying the slow-client policy when the queue is full; Err is the reason to disconnect
+    pub fn push_update(&self, update: BookUpdate) -> Result<(), String> {
+        let mut queues = self.queues.lock().unwrap();
+        if queues.closed {
+            return Ok(());
+        }
+        if queues.updates.len() >= self.policy.buffer.max(1) {
+            match self.policy.slow_client {
+                SlowClientPolicy::Disconnect => {
+                    METRICS.websocket_slow_disconnects.fetch_add(1, Ordering::Relaxed);
+                    return Err(format!("Too slow, {} updates behind", queues.updates.len()));
+                }
+                SlowClientPolicy::Conflate if update.snapshot.is_some() => {
+                    let same_symbol = queues.updates.iter().position(|queued| queued.snapshot.is_some() && queued.symbol == update.symbol);
+                    match same_symbol {
+                        Some(index) => {
+                            queues.updates.remove(index);
+                            METRICS.websocket_conflated.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => Self::drop_oldest(&mut queues),
+                    }
+                }
+                SlowClientPolicy::Conflate | SlowClientPolicy::Buffer => Self::drop_oldest(&mut queues),
+            }
+        }
+        queues.updates.push_back(update);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    fn drop_oldest(queues: &mut Queues) {
+        queues.updates.pop_front();
+        queues.dropped += 1;
+        METRICS.broadcast_lagged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // The broadcast channel dropped updates before they could be queued; Err is the reason to disconnect
+    pub fn lagged(&self, skipped: u64) -> Result<(), String> {
+        if self.policy.slow_client == SlowClientPolicy::Disconnect {
+            METRICS.websocket_slow_disconnects.fetch_add(1, Ordering::Relaxed);
+            return Err(format!("Too slow, missed {} updates", skipped));
+        }
+        self.queues.lock().unwrap().dropped += skipped;
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    // Queue a close frame after the control messages, dropping the updates
+    pub fn close(&self, frame: CloseFrame<'static>) {
+        let mut queues = self.queues.lock().unwrap();
+        if !queues.closed {
+            queues.updates.clear();
+            queues.control.push_back(Message::Close(Some(frame)));
+            queues.closed = true;
+            self.ready.notify_one();
+        }
+    }
+
+    // Send full snapshots next with the delta format
+    pub fn resnapshot(&self) {
+        if let Some(deltas) = &mut self.queues.lock().unwrap().deltas {
+            deltas.resnapshot();
+        }
+    }
+
+    // Drop the queued updates, for feeds that moved on from them
+    pub fn discard_updates(&self) {
+        self.queues.lock().unwrap().updates.clear();
+    }
+
+    // Nothing waiting to be sent, for feeds that produce at the client's pace
+    pub fn is_idle(&self) -> bool {
+        let queues = self.queues.lock().unwrap();
+        queues.control.is_empty() && queues.updates.is_empty()
+    }
+
+    // Wait until everything queued has been sent
+    pub async fn wait_idle(&self) {
+        loop {
+            let drained = self.drained.notified();
+            if self.is_idle() {
+                return;
+            }
+            drained.await;
+        }
+    }
+
+    fn pop(&self) -> Option<Message> {
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(message) = queues.control.pop_front() {
+            return Some(message);
+        }
+        if queues.dropped > 0 {
+            let notice = format!("{{\"type\":\"lagged\",\"dropped\":{}}}", queues.dropped);
+            queues.dropped = 0;
+            return Some(Message::Text(notice));
+        }
+        while let Some(update) = queues.updates.pop_front() {
+            // Deltas are taken against what was actually sent, after any drops and conflation
+            if let (Some(deltas), Some(snapshot), Some(symbol)) = (&mut queues.deltas, &update.snapshot, &update.symbol) {
+                match deltas.encode(symbol, snapshot) {
+                    Some(json) => return Some(Message::Text(json)),
+                    None => continue,
+                }
+            }
+            return Some(self.format.encode(update));
+        }
+        None
+    }
+
+    // Send queued messages as they come until a close frame is sent or the connection fails
+    pub async fn write_to<S: Sink<Message> + Unpin>(&self, mut sender: S) {
+        loop {
+            match self.pop() {
+                Some(message) => {
+                    let close = message.is_close();
+                    let message = match self.policy.compression {
+                        Some(level) => compress(message, level),
+                        None => message,
+                    };
+                    if sender.send(message).await.is_err() || close {
+                        return;
+                    }
+                }
+                None => {
+                    self.drained.notify_waiters();
+                    self.ready.notified().await
+                }
+            }
+        }
+    }
+}
+
+// Text and binary messages as zstd frames; pings, pongs and close frames stay as they are
+fn compress(message: Message, level: i32) -> Message {
+    let payload = match &message {
+        Message::Text(text) => text.as_bytes(),
+        Message::Binary(data) => data.as_slice(),
+        _ => return message,
+    };
+    match zstd::bulk::compress(payload, level) {
+        Ok(compressed) => {
+            METRICS.websocket_uncompressed_bytes.fetch_add(payload.len() as u64, Ordering::Relaxed);
+            METRICS.websocket_compressed_bytes.fetch_add(compressed.len() as u64, Ordering::Relaxed);
+            Message::Binary(compressed)
+        }
+        // Only fails for an invalid level, which the command line rules out
+        Err(_) => message,
+    }
+}
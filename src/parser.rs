@@ -1,12 +1,217 @@
+use crate::audit::OrderAudit;
+use crate::checkpoint::CheckpointOptions;
+use crate::events::EventLog;
+use crate::handler::{self, MessageHandler};
+use crate::latency::LatencyMonitor;
 use crate::message_types::*;
-use crate::orderbook::OrderBook;
+use crate::metrics::METRICS;
+use crate::profiling::{self, Stage};
+use crate::validation::StrictValidator;
+use crate::wire;
 use std::io;
 use std::mem::size_of;
+use std::ops::{ControlFlow, Range};
+use std::path::PathBuf;
 use std::ptr;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-const MSG_HEADER_SIZE: usize = size_of::<MessageHeader>();
+pub const MSG_HEADER_SIZE: usize = size_of::<MessageHeader>();
+// How often the per-type counts are folded into the shared metrics
+const METRICS_INTERVAL: u128 = 1 << 20;
+// Consecutive well-formed messages needed to accept a position as a message boundary after corruption
+const RESYNC_CONFIRMATIONS: usize = 3;
+// How often the stop flag is checked when not pacing, in messages
+const STOP_INTERVAL: u128 = 1 << 10;
+// Longest the pacer sleeps before checking the stop flag again
+const PACER_SLICE: Duration = Duration::from_millis(100);
+// Timestamps are nanoseconds since midnight
+pub const NANOS_PER_DAY: u64 = 86_400_000_000_000;
 
+// How the input is read
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    pub feed: FeedVariant,
+    // Validate every message and fail on malformed input instead of skipping it
+    pub strict: bool,
+    // Follow every order ref and write a per-symbol reconciliation report here
+    pub audit: Option<PathBuf>,
+    // Write every administrative message with its decoded fields here
+    pub events: Option<PathBuf>,
+    // Periodically write the book state so a later run can resume from it
+    pub checkpoint: Option<CheckpointOptions>,
+    // Byte offset of the first message to parse, when resuming from a checkpoint
+    pub start_offset: usize,
+    // Stop before the first message after this time (nanoseconds since midnight)
+    pub stop_at: Option<u64>,
+    // Only parse the messages in these byte ranges (in file order), found by a prescan for the symbols
+    pub ranges: Option<Vec<Range<usize>>>,
+    // Hold every message back to its original spacing in market time divided by this speed, to replay
+    // the file to live consumers instead of parsing it as fast as possible
+    pub pace: Option<f64>,
+    // While pacing, log the processing latency percentiles every this many nanoseconds of wall time
+    // (they are always logged at the end)
+    pub latency_interval: Option<u64>,
+    // Stop at the next message boundary once this is set, e.g. on Ctrl-C, leaving the books as they are
+    pub stop: Option<Arc<AtomicBool>>,
+    // Stop after this many messages from the start offset
+    pub max_messages: Option<u64>,
+}
+
+// Sleeps until each message's market time since the first one, divided by the speed, has passed, and
+// measures how long after that the message was processed
+struct Pacer {
+    speed: f64,
+    // Wall clock and market time of the first timestamped message
+    origin: Option<(Instant, u64)>,
+    latency: LatencyMonitor,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl Pacer {
+    // Returns when the message was due
+    fn wait(&mut self, timestamp: u64) -> Instant {
+        let (start, first) = *self.origin.get_or_insert((Instant::now(), timestamp));
+        let due = start + Duration::from_nanos(timestamp.saturating_sub(first)).div_f64(self.speed);
+        // In slices, so a stop request doesn't wait out a long gap in the market
+        loop {
+            let now = Instant::now();
+            if due <= now || self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed)) {
+                return due;
+            }
+            std::thread::sleep((due - now).min(PACER_SLICE));
+        }
+    }
+}
+
+// Corrupt regions skipped while parsing
+#[derive(Debug, Default)]
+pub struct ResyncStats {
+    pub resyncs: u64,
+    pub skipped_bytes: u64,
+}
+
+// Whether a well-formed message starts at offset: a known type with its spec length, fully
+// inside the data, and a timestamp within the day. Returns the framed size (header included)
+fn plausible_message(data: &[u8], offset: usize, spec_lengths: &[u16; 256]) -> Option<usize> {
+    let header = data.get(offset..offset + MSG_HEADER_SIZE)?;
+    let length = u16::from_be_bytes([header[0], header[1]]);
+    if length == 0 || spec_lengths[header[2] as usize] != length {
+        return None;
+    }
+    let size = 2 + length as usize;
+    let message = data.get(offset + MSG_HEADER_SIZE..offset + size)?;
+    let header = wire::Header::view(message)?;
+    (header.timestamp.get() < NANOS_PER_DAY).then_some(size)
+}
+
+// Whether a run of well-formed messages (or well-formed messages up to the end of the data) starts at an offset
+fn starts_run(data: &[u8], start: usize, spec_lengths: &[u16; 256]) -> bool {
+    let mut offset = start;
+    for _ in 0..RESYNC_CONFIRMATIONS {
+        if offset == data.len() {
+            break;
+        }
+        match plausible_message(data, offset, spec_lengths) {
+            Some(size) => offset += size,
+            None => return false,
+        }
+    }
+    true
+}
+
+// Scan forward from a corrupt header for the next offset where a run of well-formed messages starts
+fn find_next_message(data: &[u8], from: usize, spec_lengths: &[u16; 256]) -> Option<usize> {
+    (from..data.len()).find(|&start| starts_run(data, start, spec_lengths))
+}
+
+// Whether messages start at an offset given by hand, like --start-offset; the end of the data counts
+pub fn is_message_boundary(data: &[u8], offset: usize) -> bool {
+    offset <= data.len() && starts_run(data, offset, &MessageType::spec_length_table())
+}
+
+
+// Walk the messages of the data without building books, for the subcommands that only look at the
+// messages; visit gets each message without its type byte and can break to stop early. Corrupt
+// regions are skipped the same way as while parsing
+pub fn scan_messages(data: &[u8], mut visit: impl FnMut(MessageType, &[u8]) -> ControlFlow<()>) {
+    scan_range(data, 0, data.len(), |_, message_type, message| visit(message_type, message));
+}
+
+// Walk the messages that start in [start, end), also passing visit the offset of each message's header.
+// Returns the offset after the last message, which is end unless a message crosses it or visit broke
+pub fn scan_range(data: &[u8], start: usize, end: usize, mut visit: impl FnMut(usize, MessageType, &[u8]) -> ControlFlow<()>) -> usize {
+    let mut messages = ItchIterator::range(data, start, end);
+    while let Some((offset, message_type, message)) = messages.next_framed() {
+        if visit(offset, message_type, message).is_break() {
+            return offset;
+        }
+    }
+    messages.offset()
+}
+
+// The messages of the data in file order as (timestamp, type, message without its type byte), for
+// library consumers that want iterator adapters instead of a callback. Corrupt regions are skipped
+// the same way as while parsing
+#[derive(Debug, Clone)]
+pub struct ItchIterator<'a> {
+    data: &'a [u8],
+    offset: usize,
+    end: usize,
+    spec_lengths: [u16; 256],
+}
+
+impl<'a> ItchIterator<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::range(data, 0, data.len())
+    }
+
+    // Only the messages that start in [start, end)
+    pub fn range(data: &'a [u8], start: usize, end: usize) -> Self {
+        ItchIterator { data, offset: start, end, spec_lengths: MessageType::spec_length_table() }
+    }
+
+    // Offset of the next message's header, or where the iteration stopped
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // The messages decoded into their typed views
+    pub fn typed(self) -> impl Iterator<Item = wire::ItchMessage<'a>> {
+        self.filter_map(|(_, message_type, message)| wire::decode_body(message_type, message))
+    }
+
+    // Next message with the offset of its header
+    fn next_framed(&mut self) -> Option<(usize, MessageType, &'a [u8])> {
+        let data = self.data;
+        while self.offset < self.end && self.offset + MSG_HEADER_SIZE <= data.len() {
+            let offset = self.offset;
+            let Some(size) = plausible_message(data, offset, &self.spec_lengths) else {
+                self.offset = find_next_message(data, offset + 1, &self.spec_lengths).unwrap_or(data.len());
+                continue;
+            };
+            self.offset += size;
+            return Some((offset, MessageType::from(data[offset + 2]), &data[offset + MSG_HEADER_SIZE..offset + size]));
+        }
+        None
+    }
+}
+
+impl<'a> Iterator for ItchIterator<'a> {
+    type Item = (u64, MessageType, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, message_type, message) = self.next_framed()?;
+        let timestamp = wire::Header::view(message).map_or(0, |header| header.timestamp.get());
+        Some((timestamp, message_type, message))
+    }
+}
+
+// First offset at or after from where well-formed messages start, for splitting the data into segments
+pub fn next_message_boundary(data: &[u8], from: usize) -> Option<usize> {
+    find_next_message(data, from, &MessageType::spec_length_table())
+}
 
 #[cfg(not(target_arch = "x86_64"))]
 #[inline]
@@ -17,14 +222,14 @@ pub fn stock_symbol_matches(a: &[u8; 8], b: &[u8; 8]) -> bool {
 // Prefetch the next message header
 #[cfg(target_arch = "x86_64")]
 #[inline]
-pub unsafe fn prefetch_next(ptr: *const u8, offset: usize) {
+unsafe fn prefetch_next(ptr: *const u8, offset: usize) {
     use std::arch::x86_64::*;
     unsafe{_mm_prefetch::<_MM_HINT_T0>(ptr.add(offset) as *const i8)};
 }
 
 #[cfg(not(target_arch = "x86_64"))]
 #[inline]
-pub unsafe fn prefetch_next(_ptr: *const u8, _offset: usize) {
+unsafe fn prefetch_next(_ptr: *const u8, _offset: usize) {
     // No prefetch available on this architecture
 }
 
@@ -36,26 +241,66 @@ unsafe fn read_u16_be(ptr: *const u8) -> u16 {
     u16::from_be_bytes(val)
 }
 
-// Safe function to read a 6-byte timestamp into u64
-#[inline]
-unsafe fn read_timestamp_be(ptr: *const u8) -> u64 {
-    let mut val = [0u8; 8]; // Use 8 bytes (filling first 2 with zeros)
-    // Copy the 6 bytes of the timestamp into the buffer (skipping the leading 2 zeros)
-    unsafe{ptr::copy_nonoverlapping(ptr, val.as_mut_ptr().add(2), 6)};
-    u64::from_be_bytes(val)
-}
-
 
-// Process the entire ITCH file
-pub fn process_itch_file(data: &[u8], order_book: &mut OrderBook) -> io::Result<()> {
-    let mut offset = 0;
+// Process the entire ITCH file, handing every message to each handler (the books of the symbols)
+pub fn process_itch_file<H: MessageHandler>(data: &[u8], handlers: &mut [H], options: &ParserOptions) -> io::Result<()> {
+    let mut offset = options.start_offset;
     let data_len = data.len();
     let mut count:u128 = 0;
     let start_time = Instant::now();
     // Pre-calculate the prefetch distance - helps with cache efficiency
     let prefetch_distance = 16 * 4; // 4 cache lines ahead
+    // Counted locally and published in batches to keep atomics off the hot path
+    let mut type_counts = [0u64; 256];
+    // Messages that exist in ITCH 5.0 but not in this feed are skipped
+    let supported = options.feed.message_table();
+    let mut unsupported_count: u64 = 0;
+    // A header that doesn't match the spec means the message boundaries were lost
+    let spec_lengths = MessageType::spec_length_table();
+    let mut resync_stats = ResyncStats::default();
+    let mut validator = options.strict.then(|| StrictValidator::new(options.feed));
+    let mut audit = options.audit.as_deref().map(OrderAudit::new);
+    let mut events = options.events.as_deref().map(EventLog::create).transpose()?;
+    // Market time of the next checkpoint, set from the first timestamped message
+    let mut next_checkpoint: Option<u64> = None;
+    let mut stopped = false;
+    let mut next_range = 0;
+    let mut pacer = options.pace.map(|speed| Pacer {
+        speed,
+        origin: None,
+        latency: LatencyMonitor::new(options.latency_interval.map(Duration::from_nanos)),
+        stop: options.stop.clone(),
+    });
 
+    let parse_timer = profiling::start(Stage::Parse);
     while offset + MSG_HEADER_SIZE <= data_len {
+        // Paced runs are slow enough to check before every message
+        if let Some(stop) = &options.stop
+            && (pacer.is_some() || count.is_multiple_of(STOP_INTERVAL))
+            && stop.load(Ordering::Relaxed) {
+            tracing::warn!("Stopped on request at offset {} after {} messages", offset, count);
+            stopped = true;
+            break;
+        }
+
+        if options.max_messages.is_some_and(|max| count >= max as u128) {
+            tracing::info!("Stopped after {} messages at offset {}", count, offset);
+            stopped = true;
+            break;
+        }
+
+        // Jump over the messages the prescan found irrelevant
+        if let Some(ranges) = &options.ranges {
+            while next_range < ranges.len() && ranges[next_range].end <= offset {
+                next_range += 1;
+            }
+            let Some(range) = ranges.get(next_range) else {
+                offset = data_len;
+                break;
+            };
+            offset = offset.max(range.start);
+        }
+
         // Prefetch the next message header
         if offset + prefetch_distance < data_len {
             unsafe{prefetch_next(data.as_ptr(), offset + prefetch_distance)};
@@ -69,48 +314,123 @@ pub fn process_itch_file(data: &[u8], order_book: &mut OrderBook) -> io::Result<
         // Move past the header
         offset += MSG_HEADER_SIZE;
 
-        // Check if we have the full message
-        if offset + msg_length as usize > data_len {
-            break;
+        // Check the length against the spec and that we have the full message (the type byte is already consumed)
+        if msg_length == 0 || spec_lengths[msg_type_byte as usize] != msg_length
+            || offset + msg_length as usize - 1 > data_len {
+            let corrupt_offset = offset - MSG_HEADER_SIZE;
+            match find_next_message(data, corrupt_offset + 1, &spec_lengths) {
+                Some(next) => {
+                    let skipped = next - corrupt_offset;
+                    resync_stats.resyncs += 1;
+                    resync_stats.skipped_bytes += skipped as u64;
+                    if let Some(validator) = &mut validator {
+                        validator.resync(corrupt_offset, msg_type_byte, msg_length, skipped);
+                    }
+                    offset = next;
+                    continue;
+                }
+                None => {
+                    // Nothing well-formed follows, so the rest is a truncated tail
+                    offset = corrupt_offset;
+                    break;
+                }
+            }
         }
 
         let message_type = MessageType::from(msg_type_byte);
         let message_data = &data[offset..offset + msg_length as usize - 1]; // -1 for the type byte
 
-        // Extract timestamp if needed
-        let timestamp = match message_type {
-            MessageType::AddOrder |
-            MessageType::AddOrderWithMpid |
-            MessageType::OrderExecuted |
-            MessageType::OrderExecutedWithPrice |
-            MessageType::OrderCancel |
-            MessageType::OrderDelete |
-            MessageType::OrderReplace |
-            MessageType::Trade => {
-                // All these messages have timestamp at the same offset (4 bytes in)
-                if message_data.len() >= 10 { // Make sure we have enough data
-                    unsafe{read_timestamp_be(message_data.as_ptr().add(4))}
-                } else {
-                    0
-                }
-            },
-            _ => 0,
-        };
+        // Every message has its timestamp at the same place
+        let timestamp = wire::Header::view(message_data).map_or(0, |header| header.timestamp.get());
 
-        // Process message (with sampling if requested)
-        if message_type != MessageType::Unknown {
-            order_book.handle_message(message_type, message_data, timestamp)?;
+        if let Some(stop_at) = options.stop_at && timestamp > stop_at {
+            offset -= MSG_HEADER_SIZE;
+            stopped = true;
+            break;
+        }
 
+        // Process message (with sampling if requested)
+        if let Some(validator) = &mut validator
+            && !validator.check(offset - MSG_HEADER_SIZE, msg_type_byte, message_data) {
+            // Malformed messages are reported instead of being applied to the book
+        } else if !supported[msg_type_byte as usize] {
+            unsupported_count += 1;
+        } else {
+            if let Some(audit) = &mut audit {
+                audit.observe(message_type, message_data);
+            }
+            if let Some(events) = &mut events {
+                events.observe(message_type, message_data)?;
+            }
+            let due = match &mut pacer {
+                Some(pacer) if timestamp > 0 => Some(pacer.wait(timestamp)),
+                _ => None,
+            };
+            {
+                let _timer = profiling::start(Stage::Book);
+                for handler in handlers.iter_mut() {
+                    handler::dispatch(handler, message_type, message_data, timestamp)?;
+                }
+            }
+            if let (Some(pacer), Some(due)) = (&mut pacer, due) {
+                pacer.latency.processed(due);
+            }
         }
+        type_counts[msg_type_byte as usize] += 1;
         count += 1;
-        if count % 10_000_000 == 0 {
+        if count.is_multiple_of(METRICS_INTERVAL) {
+            METRICS.record_parse(&mut type_counts, offset as u64, start_time.elapsed().as_secs_f64());
+        }
+        if count.is_multiple_of(10_000_000) {
             let diff = start_time.elapsed().as_millis();
             tracing::info!("Processed {} Million messages, {} Million messages per second", count/1_000_000,count/diff/1000);
         }
 
         // Move to next message
         offset += msg_length as usize - 1; // -1 for the type byte already consumed
+
+        // A checkpoint holds a single symbol's book
+        if let Some(checkpoint) = &options.checkpoint && timestamp > 0 && let [handler] = &*handlers {
+            let due = *next_checkpoint.get_or_insert(timestamp + checkpoint.interval);
+            if timestamp >= due && let Some(state) = handler.checkpoint_at(offset as u64, timestamp) {
+                state.write(&checkpoint.path)?;
+                tracing::info!("Wrote checkpoint at offset {} to {}", offset, checkpoint.path.display());
+                next_checkpoint = Some(timestamp + checkpoint.interval);
+            }
+        }
+    }
+    parse_timer.stop(count as u64);
+    METRICS.record_parse(&mut type_counts, offset as u64, start_time.elapsed().as_secs_f64());
+    if unsupported_count > 0 {
+        tracing::warn!("Skipped {} messages not defined for the {:?} feed", unsupported_count, options.feed);
+    }
+    if resync_stats.resyncs > 0 {
+        tracing::warn!("Input is corrupt: resynchronized {} times, skipping {} bytes", resync_stats.resyncs, resync_stats.skipped_bytes);
+    }
+    if offset < data_len && !stopped {
+        tracing::warn!("Ignored {} trailing bytes that don't form a complete message", data_len - offset);
+    }
+
+    for handler in handlers.iter_mut() {
+        handler.on_end(offset as u64)?;
+    }
+    if let Some(pacer) = pacer {
+        pacer.latency.finish();
+    }
+    if let Some(audit) = audit {
+        audit.finish()?;
+    }
+    if let Some(events) = events {
+        events.finish()?;
     }
 
-    Ok(())
+    match validator {
+        Some(mut validator) => {
+            if offset < data_len && !stopped {
+                validator.truncated(offset, data_len - offset);
+            }
+            validator.finish()
+        }
+        None => Ok(()),
+    }
 }
\ No newline at end of file
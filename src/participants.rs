@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use rustc_hash::FxHashMap;
+
+use crate::queue::OrderEvent;
+
+// Order activity of one market participant in the tracked symbol
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParticipantActivity {
+    pub adds: u64,
+    pub added_shares: u64,
+    pub executions: u64,
+    pub executed_shares: u64,
+    pub cancels: u64,
+    pub cancelled_shares: u64,
+    pub replaces: u64,
+}
+
+// Attributes the symbol's adds, cancels and executions to the MPID of the order and writes a
+// CSV with a row per participant at the end; unattributed orders get a row with an empty MPID
+pub struct ParticipantReport {
+    path: PathBuf,
+    participants: FxHashMap<Option<[u8; 4]>, ParticipantActivity>,
+}
+
+impl ParticipantReport {
+    pub fn new(path: &Path) -> Self {
+        ParticipantReport { path: path.to_path_buf(), participants: FxHashMap::default() }
+    }
+
+    // A new order; the order a replace creates carries on the replaced one, so it isn't counted as an add
+    pub fn add(&mut self, message_type: u8, mpid: Option<[u8; 4]>, shares: u32) {
+        let activity = self.participants.entry(mpid).or_default();
+        if message_type != b'U' {
+            activity.adds += 1;
+            activity.added_shares += shares as u64;
+        }
+    }
+
+    // Shares taken off an order of the participant by an execute, cancel, delete or replace
+    pub fn reduce(&mut self, mpid: Option<[u8; 4]>, event: OrderEvent, shares: u32) {
+        let activity = self.participants.entry(mpid).or_default();
+        match event {
+            OrderEvent::Execute => {
+                activity.executions += 1;
+                activity.executed_shares += shares as u64;
+            }
+            OrderEvent::Cancel | OrderEvent::Delete => {
+                activity.cancels += 1;
+                activity.cancelled_shares += shares as u64;
+            }
+            OrderEvent::Replace => activity.replaces += 1,
+            OrderEvent::Add | OrderEvent::Open => {}
+        }
+    }
+
+    // Activity so far of every participant, for checkpoints
+    pub fn activity(&self) -> Vec<(Option<[u8; 4]>, ParticipantActivity)> {
+        self.participants.iter().map(|(&mpid, &activity)| (mpid, activity)).collect()
+    }
+
+    pub fn restore(&mut self, activity: Vec<(Option<[u8; 4]>, ParticipantActivity)>) {
+        self.participants = activity.into_iter().collect();
+    }
+
+    // Write the participants, most added shares first
+    pub fn finalize(&mut self) -> io::Result<()> {
+        let mut rows = self.activity();
+        rows.sort_by_key(|&(mpid, activity)| (std::cmp::Reverse(activity.added_shares), mpid.is_none(), mpid));
+
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        writer.write_all(b"mpid,adds,added_shares,executions,executed_shares,cancels,cancelled_shares,replaces\n")?;
+        for (mpid, activity) in &rows {
+            let mpid = mpid.map(|mpid| String::from_utf8_lossy(&mpid).trim_end().to_string()).unwrap_or_default();
+            writeln!(writer, "{},{},{},{},{},{},{},{}", mpid, activity.adds, activity.added_shares, activity.executions,
+                     activity.executed_shares, activity.cancels, activity.cancelled_shares, activity.replaces)?;
+        }
+        writer.flush()?;
+        tracing::info!("Wrote activity of {} participants to {}", rows.len(), self.path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+// Partitioned output: the output path is a template like out/{symbol}/{date}/part-{part}.parquet, and
+// the file is rotated into a new part when it reaches a size or when market time crosses an interval
+// boundary, so long runs write many bounded files instead of one giant one
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bars::Bar;
+use crate::compress::{self, Compression};
+use crate::features::FeatureColumns;
+use crate::orderbook::{BookSnapshot, MarketEvent, OutputFormat, TradeRecord};
+use crate::sink::{self, OutputSink};
+
+const SYMBOL_PLACEHOLDER: &str = "{symbol}";
+const DATE_PLACEHOLDER: &str = "{date}";
+const PART_PLACEHOLDER: &str = "{part}";
+
+// Snapshots between checks of the current part's size on disk
+const SIZE_CHECK_INTERVAL: u64 = 256;
+
+// What the placeholders of an output path stand for, and when a new part is started
+#[derive(Debug, Clone, Default)]
+pub struct Partitioning {
+    pub symbol: String,
+    // Name of the input's day, see batch::day_name
+    pub date: String,
+    // Start a new part once the current one has this many bytes on disk
+    pub max_bytes: Option<u64>,
+    // Start a new part when market time enters the next interval (in nanoseconds since midnight)
+    pub interval: Option<u64>,
+}
+
+impl Partitioning {
+    fn rotates(&self) -> bool {
+        self.max_bytes.is_some() || self.interval.is_some()
+    }
+
+    // Whether the output path needs a PartitionedSink rather than a single file
+    pub fn applies_to(&self, template: &Path) -> bool {
+        let text = template.to_string_lossy();
+        self.rotates() || [SYMBOL_PLACEHOLDER, DATE_PLACEHOLDER, PART_PLACEHOLDER].iter().any(|placeholder| text.contains(placeholder))
+    }
+
+    // The output path with the placeholders filled in for the first part, which the files named after
+    // the output (audit report, checkpoint) are based on
+    pub fn base_path(&self, template: &Path) -> PathBuf {
+        Partitioning { max_bytes: None, interval: None, ..self.clone() }.part_path(template, 1)
+    }
+
+    // The path with {symbol} and {date} filled in, for the outputs that are a single file
+    pub fn fill(&self, template: &Path) -> PathBuf {
+        PathBuf::from(template.to_string_lossy()
+            .replace(SYMBOL_PLACEHOLDER, &self.symbol)
+            .replace(DATE_PLACEHOLDER, &self.date))
+    }
+
+    // Path of a part, numbered from 1. Rotated output without {part} gets it before the extension
+    // (AAPL.csv -> AAPL.part-0001.csv)
+    pub fn part_path(&self, template: &Path, part: u32) -> PathBuf {
+        let part = format!("{:04}", part);
+        let text = self.fill(template).to_string_lossy().into_owned();
+        if text.contains(PART_PLACEHOLDER) {
+            return PathBuf::from(text.replace(PART_PLACEHOLDER, &part));
+        }
+        let path = PathBuf::from(text);
+        if !self.rotates() {
+            return path;
+        }
+        match path.extension() {
+            Some(extension) => path.with_extension(format!("part-{}.{}", part, extension.to_string_lossy())),
+            None => path.with_extension(format!("part-{}", part)),
+        }
+    }
+}
+
+// Whether the path has a file per symbol
+pub fn routes_symbols(template: &Path) -> bool {
+    template.to_string_lossy().contains(SYMBOL_PLACEHOLDER)
+}
+
+// Output file of one of several symbols built at once: a template with {symbol} is left for Partitioning
+// to fill in, and without it the symbol goes before the extension (books.csv -> books.AAPL.csv)
+pub fn symbol_output(output: &Path, symbol: &str) -> PathBuf {
+    if routes_symbols(output) {
+        return output.to_path_buf();
+    }
+    match output.extension() {
+        Some(extension) => output.with_extension(format!("{}.{}", symbol, extension.to_string_lossy())),
+        None => output.with_extension(symbol),
+    }
+}
+
+// Create the sink for the main output file, partitioned when its path has placeholders or rotation is on
+pub fn create_output_sink(format: OutputFormat, template: &Path, bbo_only: bool, columns: FeatureColumns, compression: Option<Compression>,
+                          partitioning: Partitioning) -> io::Result<Box<dyn OutputSink>> {
+    if format == OutputFormat::Null || !partitioning.applies_to(template) {
+        return sink::create_file_sink(format, template, bbo_only, columns, compression);
+    }
+    if format == OutputFormat::Lobster {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "LOBSTER output can't be partitioned or rotated"));
+    }
+    Ok(Box::new(PartitionedSink::create(format, template, bbo_only, columns, compression, partitioning)?))
+}
+
+// Writes to one part at a time, finalizing it and opening the next one when it rotates
+pub struct PartitionedSink {
+    format: OutputFormat,
+    template: PathBuf,
+    bbo_only: bool,
+    columns: FeatureColumns,
+    compression: Option<Compression>,
+    partitioning: Partitioning,
+    current: Box<dyn OutputSink>,
+    // The current part's file, with the compression's extension
+    path: PathBuf,
+    part: u32,
+    // Interval the current part's snapshots fall in
+    window: Option<u64>,
+    rows: u64,
+}
+
+impl PartitionedSink {
+    pub fn create(format: OutputFormat, template: &Path, bbo_only: bool, columns: FeatureColumns, compression: Option<Compression>,
+                  partitioning: Partitioning) -> io::Result<Self> {
+        let path = partitioning.part_path(template, 1);
+        let current = open_part(format, &path, bbo_only, &columns, compression)?;
+        Ok(PartitionedSink {
+            format,
+            template: template.to_path_buf(),
+            bbo_only,
+            columns,
+            compression,
+            partitioning,
+            current,
+            path: compress::output_path(&path, compression),
+            part: 1,
+            window: None,
+            rows: 0,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.current.finalize()?;
+        self.part += 1;
+        let path = self.partitioning.part_path(&self.template, self.part);
+        self.current = open_part(self.format, &path, self.bbo_only, &self.columns, self.compression)?;
+        self.path = compress::output_path(&path, self.compression);
+        self.rows = 0;
+        tracing::info!("Rotated output to {}", self.path.display());
+        Ok(())
+    }
+}
+
+// Templated paths can point into directories that don't exist yet
+pub fn create_parent(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => fs::create_dir_all(dir),
+        None => Ok(()),
+    }
+}
+
+fn open_part(format: OutputFormat, path: &Path, bbo_only: bool, columns: &FeatureColumns, compression: Option<Compression>) -> io::Result<Box<dyn OutputSink>> {
+    create_parent(path)?;
+    sink::create_file_sink(format, path, bbo_only, columns.clone(), compression)
+}
+
+impl OutputSink for PartitionedSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        if let Some(interval) = self.partitioning.interval {
+            let window = snapshot.timestamp / interval;
+            if self.window.is_some_and(|current| window > current) {
+                self.rotate()?;
+            }
+            self.window = Some(window);
+        }
+        self.current.write_snapshot(symbol, snapshot)?;
+
+        // The sinks buffer, so the size is what reached the disk and parts end up slightly larger (compressed
+        // parts more so, since the encoder holds back a block)
+        self.rows += 1;
+        if let Some(max_bytes) = self.partitioning.max_bytes
+            && self.rows.is_multiple_of(SIZE_CHECK_INTERVAL)
+            && fs::metadata(&self.path)?.len() >= max_bytes {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.current.write_trade(symbol, trade)
+    }
+
+    fn write_event(&mut self, event: &MarketEvent) -> io::Result<()> {
+        self.current.write_event(event)
+    }
+
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> io::Result<()> {
+        self.current.write_bar(symbol, bar)
+    }
+
+    fn update_counts(&mut self, messages: u64, book_updates: u64, skipped_updates: u64) {
+        self.current.update_counts(messages, book_updates, skipped_updates);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.current.flush()
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.current.finalize()
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::io;
+
+// How the ITCH messages are packaged in the input file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Encapsulation {
+    // A plain ITCH file of length prefixed messages
+    #[default]
+    Raw,
+    // A pcap capture of MoldUDP64 packets
+    Moldudp64,
+}
+
+// Link layer types of the capture
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+// session (10 bytes), sequence number (8 bytes), message count (2 bytes)
+const MOLD_HEADER_SIZE: usize = 20;
+const MOLD_END_OF_SESSION: u16 = 0xFFFF;
+
+// What happened while reassembling the capture
+#[derive(Debug, Default)]
+pub struct CaptureStats {
+    pub packets: u64,
+    // Packets that weren't IPv4/UDP MoldUDP64 (ARP, TCP, fragments, ...)
+    pub skipped_packets: u64,
+    pub messages: u64,
+    // Messages seen more than once, e.g. from both A and B feeds
+    pub duplicate_messages: u64,
+    // Sequence numbers never seen
+    pub missing_messages: u64,
+    pub sessions: usize,
+}
+
+// A MoldUDP64 packet's messages, located in the capture
+struct MoldPacket<'a> {
+    sequence: u64,
+    count: u16,
+    // Message blocks (u16 length + message) after the header
+    blocks: &'a [u8],
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Read the pcap byte order from its magic number; true for big endian files
+fn byte_order(magic: [u8; 4]) -> io::Result<bool> {
+    match magic {
+        // Microsecond and nanosecond timestamp variants
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => Ok(false),
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => Ok(true),
+        [0x0a, 0x0d, 0x0d, 0x0a] => Err(invalid("pcapng captures are not supported, convert with: editcap -F pcap in.pcapng out.pcap")),
+        _ => Err(invalid("Not a pcap file")),
+    }
+}
+
+// Strip the link, IP and UDP headers, returning the UDP payload of IPv4 packets
+fn udp_payload(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    let ip = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            // Skip 802.1Q VLAN tags
+            while ethertype == ETHERTYPE_VLAN {
+                offset += 4;
+                ethertype = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            }
+            if ethertype != ETHERTYPE_IPV4 {
+                return None;
+            }
+            frame.get(offset + 2..)?
+        }
+        LINKTYPE_LINUX_SLL => {
+            if u16::from_be_bytes([*frame.get(14)?, *frame.get(15)?]) != ETHERTYPE_IPV4 {
+                return None;
+            }
+            frame.get(16..)?
+        }
+        LINKTYPE_RAW | LINKTYPE_IPV4 => frame,
+        _ => return None,
+    };
+
+    if ip.first()? >> 4 != 4 || *ip.get(9)? != IP_PROTOCOL_UDP {
+        return None;
+    }
+    // Fragments can't be reassembled into MoldUDP64 packets here
+    let flags_offset = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]);
+    if flags_offset & 0x3fff != 0 {
+        return None;
+    }
+    let header_len = ((ip[0] & 0x0f) as usize) * 4;
+    let total_len = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+    let udp = ip.get(header_len..total_len.min(ip.len()))?;
+    let udp_len = u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]) as usize;
+    udp.get(8..udp_len.min(udp.len()))
+}
+
+// Read a pcap capture of MoldUDP64 packets and return its ITCH messages, in sequence
+// order and without duplicates, framed like a plain ITCH file (u16 length + message)
+pub fn extract_moldudp64(data: &[u8]) -> io::Result<(Vec<u8>, CaptureStats)> {
+    if data.len() < 24 {
+        return Err(invalid("File is too short for a pcap header"));
+    }
+    let big_endian = byte_order([data[0], data[1], data[2], data[3]])?;
+    let read_u32 = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    };
+    let link_type = read_u32(&data[20..24]);
+
+    let mut stats = CaptureStats::default();
+    let mut packets = Vec::new();
+    let mut sessions = HashSet::new();
+    let mut offset = 24;
+
+    // Each record: ts_sec, ts_frac, captured length, original length
+    while offset + 16 <= data.len() {
+        let captured = read_u32(&data[offset + 8..offset + 12]) as usize;
+        let start = offset + 16;
+        let end = (start + captured).min(data.len());
+        offset = start + captured;
+        stats.packets += 1;
+
+        let Some(payload) = udp_payload(link_type, &data[start..end]) else {
+            stats.skipped_packets += 1;
+            continue;
+        };
+        if payload.len() < MOLD_HEADER_SIZE {
+            stats.skipped_packets += 1;
+            continue;
+        }
+
+        let count = u16::from_be_bytes([payload[18], payload[19]]);
+        // Heartbeats and end of session markers carry no messages
+        if count == 0 || count == MOLD_END_OF_SESSION {
+            continue;
+        }
+        sessions.insert(&payload[..10]);
+        packets.push(MoldPacket {
+            sequence: u64::from_be_bytes(payload[10..18].try_into().unwrap()),
+            count,
+            blocks: &payload[MOLD_HEADER_SIZE..],
+        });
+    }
+    stats.sessions = sessions.len();
+    if stats.sessions > 1 {
+        tracing::warn!("Capture contains {} MoldUDP64 sessions, their sequence numbers are merged", stats.sessions);
+    }
+
+    // Captures can hold late or retransmitted packets, so order by sequence number
+    packets.sort_by_key(|p| p.sequence);
+
+    let mut output = Vec::with_capacity(data.len());
+    let mut next_sequence = packets.first().map_or(0, |p| p.sequence);
+    for packet in &packets {
+        if packet.sequence > next_sequence {
+            stats.missing_messages += packet.sequence - next_sequence;
+            next_sequence = packet.sequence;
+        }
+
+        let end_sequence = packet.sequence + packet.count as u64;
+        let mut block_offset = 0;
+        for sequence in packet.sequence..end_sequence {
+            let block = packet.blocks.get(block_offset..block_offset + 2).and_then(|len_bytes| {
+                let len = u16::from_be_bytes([len_bytes[0], len_bytes[1]]) as usize;
+                packet.blocks.get(block_offset..block_offset + 2 + len)
+            });
+            // A packet cut short by the capture's snapshot length loses the rest of its messages
+            let Some(block) = block else {
+                stats.missing_messages += end_sequence.saturating_sub(sequence.max(next_sequence));
+                break;
+            };
+            block_offset += block.len();
+
+            if sequence < next_sequence {
+                stats.duplicate_messages += 1;
+                continue;
+            }
+            output.extend_from_slice(block);
+            stats.messages += 1;
+            next_sequence = sequence + 1;
+        }
+        next_sequence = next_sequence.max(end_sequence);
+    }
+
+    Ok((output, stats))
+}
@@ -0,0 +1,73 @@
+// Resting orders by order ref. The orders live in a slab whose freed slots are reused by later adds, and
+// the hash map only holds slab indices, so it stays small and moves 12 bytes an entry instead of the whole
+// order when it grows or rehashes
+
+use rustc_hash::FxHashMap;
+
+use crate::orderbook::Order;
+
+#[derive(Debug, Default)]
+pub struct OrderPool {
+    index: FxHashMap<u64, u32>,
+    // Slots not in `free` hold a resting order, the others a stale one
+    slots: Vec<Order>,
+    free: Vec<u32>,
+}
+
+impl OrderPool {
+    // Room for `capacity` resting orders before anything grows
+    pub fn with_capacity(capacity: usize) -> Self {
+        OrderPool {
+            index: FxHashMap::with_capacity_and_hasher(capacity, Default::default()),
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn get(&self, order_ref: u64) -> Option<&Order> {
+        self.index.get(&order_ref).map(|&slot| &self.slots[slot as usize])
+    }
+
+    pub fn get_mut(&mut self, order_ref: u64) -> Option<&mut Order> {
+        self.index.get(&order_ref).map(|&slot| &mut self.slots[slot as usize])
+    }
+
+    // Add an order under its ref, returning the order it replaced
+    pub fn insert(&mut self, order: Order) -> Option<Order> {
+        if let Some(&slot) = self.index.get(&order.ref_number) {
+            return Some(std::mem::replace(&mut self.slots[slot as usize], order));
+        }
+        let order_ref = order.ref_number;
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot as usize] = order;
+                slot
+            }
+            None => {
+                self.slots.push(order);
+                (self.slots.len() - 1) as u32
+            }
+        };
+        self.index.insert(order_ref, slot);
+        None
+    }
+
+    pub fn remove(&mut self, order_ref: u64) -> Option<Order> {
+        let slot = self.index.remove(&order_ref)?;
+        self.free.push(slot);
+        Some(self.slots[slot as usize].clone())
+    }
+
+    // The resting orders, in no particular order
+    pub fn values(&self) -> impl Iterator<Item = &Order> {
+        self.index.values().map(|&slot| &self.slots[slot as usize])
+    }
+}
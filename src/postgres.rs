@@ -0,0 +1,144 @@
+use std::io::{self, Write};
+
+use postgres::{Client, NoTls};
+
+use crate::features::FeatureColumns;
+use crate::orderbook::BookSnapshot;
+use crate::sink::OutputSink;
+
+// Settings for the Postgres / TimescaleDB sink
+#[derive(Debug, Clone)]
+pub struct PostgresConfig {
+    // libpq style "host=... user=... dbname=..." or a postgres:// URL
+    pub url: String,
+    pub table: String,
+    // Rows per COPY
+    pub batch_size: usize,
+    // Turn the table into a TimescaleDB hypertable on timestamp_ns
+    pub timescale: bool,
+}
+
+// Batches snapshot rows and loads them with COPY ... FROM STDIN
+pub struct PostgresSink {
+    // Closed by finalize
+    client: Option<Client>,
+    copy_statement: String,
+    batch_size: usize,
+    // CSV rows waiting for the next COPY
+    buffer: Vec<u8>,
+    rows: usize,
+    bbo_only: bool,
+    depth: usize,
+}
+
+fn pg_error(e: postgres::Error) -> io::Error {
+    // The server's message is more useful than the generic "db error"
+    match e.as_db_error() {
+        Some(db) => io::Error::other(format!("Postgres error: {}", db.message())),
+        None => io::Error::other(format!("Postgres error: {}", e)),
+    }
+}
+
+// Column names and types of the snapshot table, matching the CSV columns
+fn columns(bbo_only: bool, depth: usize) -> Vec<(String, &'static str)> {
+    let mut columns = vec![("symbol".to_string(), "TEXT NOT NULL"), ("timestamp_ns".to_string(), "BIGINT NOT NULL")];
+    if bbo_only {
+        for (name, kind) in [("bid", "NUMERIC(12,4)"), ("bid_size", "BIGINT"), ("ask", "NUMERIC(12,4)"), ("ask_size", "BIGINT"), ("mid", "DOUBLE PRECISION"), ("spread", "NUMERIC(12,4)")] {
+            columns.push((name.to_string(), kind));
+        }
+    } else {
+        for level in 1..=depth {
+            columns.push((format!("bid_price_{}", level), "NUMERIC(12,4)"));
+            columns.push((format!("bid_vol_{}", level), "BIGINT"));
+            columns.push((format!("ask_price_{}", level), "NUMERIC(12,4)"));
+            columns.push((format!("ask_vol_{}", level), "BIGINT"));
+        }
+        columns.push(("mid_price".to_string(), "DOUBLE PRECISION"));
+        columns.push(("orderbook_imbalance".to_string(), "DOUBLE PRECISION"));
+    }
+    columns
+}
+
+impl PostgresSink {
+    // Connect and create the table (and hypertable) if it doesn't exist yet
+    pub fn connect(config: &PostgresConfig, bbo_only: bool, depth: usize) -> io::Result<Self> {
+        // The table name is interpolated into SQL, so only allow plain identifiers
+        let valid = !config.table.is_empty()
+            && config.table.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+        if !valid {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid Postgres table name '{}'", config.table)));
+        }
+        if config.batch_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Postgres batch size must be at least 1"));
+        }
+
+        let mut client = Client::connect(&config.url, NoTls).map_err(pg_error)?;
+
+        let columns = columns(bbo_only, depth);
+        let definitions: Vec<String> = columns.iter().map(|(name, kind)| format!("{} {}", name, kind)).collect();
+        let index = format!("{}_symbol_ts_idx", config.table.replace('.', "_"));
+        let mut schema = format!("CREATE TABLE IF NOT EXISTS {} ({});\nCREATE INDEX IF NOT EXISTS {} ON {} (symbol, timestamp_ns);\n",
+                                 config.table, definitions.join(", "), index, config.table);
+        if config.timescale {
+            // Integer time column, chunked by hour of nanoseconds since midnight
+            schema.push_str(&format!("CREATE EXTENSION IF NOT EXISTS timescaledb;\nSELECT create_hypertable('{}', 'timestamp_ns', chunk_time_interval => 3600000000000, if_not_exists => TRUE);\n",
+                                     config.table));
+        }
+        client.batch_execute(&schema).map_err(pg_error)?;
+
+        let names: Vec<&str> = columns.iter().map(|(name, _)| name.as_str()).collect();
+        let copy_statement = format!("COPY {} ({}) FROM STDIN WITH (FORMAT csv)", config.table, names.join(", "));
+        tracing::info!("Writing snapshots to Postgres table {}", config.table);
+
+        Ok(PostgresSink {
+            client: Some(client),
+            copy_statement,
+            batch_size: config.batch_size,
+            buffer: Vec::with_capacity(config.batch_size * 256),
+            rows: 0,
+            bbo_only,
+            depth,
+        })
+    }
+}
+
+impl OutputSink for PostgresSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        write!(self.buffer, "{},", symbol)?;
+        if self.bbo_only {
+            snapshot.write_bbo_row(&mut self.buffer, &FeatureColumns::default())?;
+        } else {
+            snapshot.write_depth_row(&mut self.buffer, self.depth, &FeatureColumns::default())?;
+        }
+        self.rows += 1;
+        if self.rows >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(client) = &mut self.client else {
+            return Ok(());
+        };
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut writer = client.copy_in(self.copy_statement.as_str()).map_err(pg_error)?;
+        writer.write_all(&self.buffer)?;
+        writer.finish().map_err(pg_error)?;
+        self.buffer.clear();
+        self.rows = 0;
+        Ok(())
+    }
+
+    // The client blocks on a runtime of its own when it closes, which can't happen on the async threads the
+    // books end up on, so close it here on the parser's thread
+    fn finalize(&mut self) -> io::Result<()> {
+        self.flush()?;
+        if let Some(client) = self.client.take() {
+            client.close().map_err(pg_error)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::ops::{ControlFlow, Range};
+use std::thread;
+use std::time::Instant;
+
+use rustc_hash::FxHashSet;
+
+use crate::message_types::MessageType;
+use crate::parser;
+use crate::simd::SymbolSet;
+use crate::wire::{self, MessageView};
+
+// Segments smaller than this aren't worth a thread
+const MIN_SEGMENT_SIZE: usize = 1 << 20;
+
+// An add with MPID starts like an add, and every order message starts with its order ref like a delete
+fn add_view(message: &[u8]) -> Option<&wire::AddOrder> {
+    wire::AddOrder::view(message)
+}
+
+fn order_ref(message: &[u8]) -> Option<u64> {
+    wire::OrderDelete::view(message).map(|delete| delete.order_ref_number.get())
+}
+
+// Split the data into one segment per thread, each starting at a message boundary
+fn segments(data: &[u8]) -> Vec<Range<usize>> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(data.len() / MIN_SEGMENT_SIZE).max(1);
+    let mut starts = vec![0];
+    for i in 1..threads {
+        match parser::next_message_boundary(data, data.len() / threads * i) {
+            Some(start) if start > *starts.last().unwrap() => starts.push(start),
+            _ => {}
+        }
+    }
+    starts.iter().enumerate()
+        .map(|(i, &start)| start..starts.get(i + 1).copied().unwrap_or(data.len()))
+        .collect()
+}
+
+// Refs of the segment's adds for the symbols, and the (original, new) refs of all its replaces in order
+struct SegmentRefs {
+    adds: Vec<u64>,
+    replaces: Vec<(u64, u64)>,
+    // Where the segment's last message ended, which is the next segment's start if the split was right
+    end: usize,
+}
+
+fn collect_refs(data: &[u8], segment: &Range<usize>, symbols: &SymbolSet) -> SegmentRefs {
+    let mut refs = SegmentRefs { adds: Vec::new(), replaces: Vec::new(), end: 0 };
+    refs.end = parser::scan_range(data, segment.start, segment.end, |_, message_type, message| {
+        match message_type {
+            MessageType::AddOrder | MessageType::AddOrderWithMpid => {
+                if let Some(add) = add_view(message) && symbols.contains(&add.stock) {
+                    refs.adds.push(add.order_ref_number.get());
+                }
+            }
+            MessageType::OrderReplace => {
+                if let Some(replace) = wire::OrderReplace::view(message) {
+                    refs.replaces.push((replace.original_order_ref_number.get(), replace.new_order_ref_number.get()));
+                }
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    });
+    refs
+}
+
+// Byte ranges of the segment's messages the books of the symbols need; runs of them are merged
+fn collect_ranges(data: &[u8], segment: &Range<usize>, symbols: &SymbolSet, refs: &FxHashSet<u64>) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    parser::scan_range(data, segment.start, segment.end, |offset, message_type, message| {
+        let relevant = match message_type {
+            MessageType::AddOrder | MessageType::AddOrderWithMpid => add_view(message).is_some_and(|add| symbols.contains(&add.stock)),
+            MessageType::OrderExecuted | MessageType::OrderExecutedWithPrice | MessageType::OrderCancel
+            | MessageType::OrderDelete | MessageType::OrderReplace => order_ref(message).is_some_and(|order_ref| refs.contains(&order_ref)),
+            MessageType::Trade => wire::Trade::view(message).is_some_and(|trade| symbols.contains(&trade.stock)),
+            // Administrative messages are few and some of them apply to every symbol
+            _ => true,
+        };
+        if relevant {
+            let end = offset + parser::MSG_HEADER_SIZE + message.len();
+            match ranges.last_mut() {
+                Some(last) if last.end == offset => last.end = end,
+                _ => ranges.push(offset..end),
+            }
+        }
+        ControlFlow::Continue(())
+    });
+    ranges
+}
+
+// Scan the data in parallel for the messages the books of the symbols need: their adds and trades,
+// every execute, cancel, delete and replace of those orders (following replaces), and the
+// administrative messages. Returns the byte ranges of those messages in file order
+pub fn relevant_ranges(data: &[u8], symbols: &[[u8; 8]]) -> Vec<Range<usize>> {
+    let start_time = Instant::now();
+    let symbols = &SymbolSet::new(symbols);
+    let mut segments = segments(data);
+    let scan_refs = |segments: &[Range<usize>]| -> Vec<SegmentRefs> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = segments.iter()
+                .map(|segment| scope.spawn(move || collect_refs(data, segment, symbols)))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        })
+    };
+    let mut segment_refs = scan_refs(&segments);
+    // A split that wasn't on a real message boundary shows as a segment ending off the next one's start
+    if segment_refs.iter().zip(&segments).any(|(refs, segment)| refs.end != segment.end && segment.end != data.len()) {
+        tracing::warn!("Could not split the file on message boundaries, prescanning it on one thread");
+        segments.truncate(1);
+        segments[0].end = data.len();
+        segment_refs = scan_refs(&segments);
+    }
+
+    // Refs are unique for the day, and a replace's original ref was added (or replaced) before it
+    let mut refs: FxHashSet<u64> = segment_refs.iter().flat_map(|refs| refs.adds.iter().copied()).collect();
+    for &(original, new) in segment_refs.iter().flat_map(|refs| &refs.replaces) {
+        if refs.contains(&original) {
+            refs.insert(new);
+        }
+    }
+    drop(segment_refs);
+
+    let refs = &refs;
+    let ranges: Vec<Range<usize>> = thread::scope(|scope| {
+        let handles: Vec<_> = segments.iter()
+            .map(|segment| scope.spawn(move || collect_ranges(data, segment, symbols, refs)))
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    });
+    let kept: usize = ranges.iter().map(|range| range.len()).sum();
+    tracing::info!("Prescan on {} threads kept {} of {} bytes ({} orders) in {} ranges in {:.2}s", segments.len(), kept,
+                   data.len(), refs.len(), ranges.len(), start_time.elapsed().as_secs_f64());
+    ranges
+}
@@ -0,0 +1,107 @@
+use std::ops::ControlFlow;
+
+use rustc_hash::FxHashMap;
+
+use crate::cli::ProfileArgs;
+use crate::directory::DirectoryEntry;
+use crate::file_io;
+use crate::message_types::MessageType;
+use crate::parser;
+use crate::wire;
+
+const NANOS_PER_HOUR: u64 = 3_600_000_000_000;
+
+// Percentage of the total, 0 for an empty file
+fn share(count: u64, total: u64) -> f64 {
+    if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 }
+}
+
+// The `top` symbols of the directory with the most messages, busiest first with their message counts. With a
+// window, only the messages up to that much market time after the first add are counted and the scan stops there
+pub fn most_active_symbols(data: &[u8], top: usize, window: Option<u64>) -> Vec<(String, u64)> {
+    let mut by_locate: FxHashMap<u16, u64> = FxHashMap::default();
+    let mut symbols: FxHashMap<u16, String> = FxHashMap::default();
+    let mut end: Option<u64> = None;
+    parser::scan_messages(data, |message_type, message| {
+        let Some(header) = wire::Header::view(message) else {
+            return ControlFlow::Continue(());
+        };
+        let timestamp = header.timestamp.get();
+        if let Some(window) = window
+            && end.is_none()
+            && matches!(message_type, MessageType::AddOrder | MessageType::AddOrderWithMpid) {
+            end = Some(timestamp.saturating_add(window));
+        }
+        if end.is_some_and(|end| timestamp >= end) {
+            return ControlFlow::Break(());
+        }
+        let locate = header.stock_locate.get();
+        if locate != 0 {
+            *by_locate.entry(locate).or_insert(0) += 1;
+        }
+        if message_type == MessageType::StockDirectory && let Some(entry) = DirectoryEntry::parse(message) {
+            symbols.insert(entry.stock_locate, entry.symbol);
+        }
+        ControlFlow::Continue(())
+    });
+
+    let mut busiest: Vec<(String, u64)> = by_locate.into_iter()
+        .filter_map(|(locate, count)| Some((symbols.remove(&locate)?, count)))
+        .collect();
+    busiest.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    busiest.truncate(top);
+    busiest
+}
+
+// Count the messages of the file by type, by hour and by symbol and print the tables
+pub fn run(args: ProfileArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = file_io::load_input(&args.file, args.encap)?;
+
+    let mut by_type = [0u64; 256];
+    let mut by_hour = [0u64; 24];
+    // Messages per stock locate; locate 0 is for market wide messages
+    let mut by_locate: FxHashMap<u16, u64> = FxHashMap::default();
+    let mut symbols: FxHashMap<u16, String> = FxHashMap::default();
+    parser::scan_messages(&input, |message_type, message| {
+        by_type[message_type as usize] += 1;
+        let Some(header) = wire::Header::view(message) else {
+            return ControlFlow::Continue(());
+        };
+        by_hour[(header.timestamp.get() / NANOS_PER_HOUR).min(23) as usize] += 1;
+        let locate = header.stock_locate.get();
+        if locate != 0 {
+            *by_locate.entry(locate).or_insert(0) += 1;
+        }
+        if message_type == MessageType::StockDirectory && let Some(entry) = DirectoryEntry::parse(message) {
+            symbols.insert(entry.stock_locate, entry.symbol);
+        }
+        ControlFlow::Continue(())
+    });
+    let total: u64 = by_type.iter().sum();
+
+    println!("{:<6}{:<46}{:>14}{:>9}", "Type", "Message", "Count", "Share");
+    for (byte, &count) in by_type.iter().enumerate().filter(|&(_, &count)| count > 0) {
+        println!("{:<6}{:<46}{:>14}{:>8.2}%", byte as u8 as char, MessageType::from(byte as u8).name(), count,
+                 share(count, total));
+    }
+    println!("{:<52}{:>14}", "Total", total);
+
+    println!();
+    println!("{:<52}{:>14}{:>9}", "Hour", "Count", "Share");
+    for (hour, &count) in by_hour.iter().enumerate().filter(|&(_, &count)| count > 0) {
+        println!("{:<52}{:>14}{:>8.2}%", format!("{:02}:00-{:02}:00", hour, hour + 1), count, share(count, total));
+    }
+
+    let mut busiest: Vec<(u16, u64)> = by_locate.into_iter().collect();
+    busiest.sort_unstable_by_key(|&(locate, count)| (std::cmp::Reverse(count), locate));
+    println!();
+    println!("{:<8}{:<44}{:>14}{:>9}", "Locate", "Symbol", "Count", "Share");
+    for &(locate, count) in busiest.iter().take(args.top) {
+        let symbol = symbols.get(&locate).map_or("?", String::as_str);
+        println!("{:<8}{:<44}{:>14}{:>8.2}%", locate, symbol, count, share(count, total));
+    }
+    if busiest.len() > args.top {
+        println!("... {} more symbols", busiest.len() - args.top);
+    }
+    Ok(())
+}
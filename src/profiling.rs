@@ -0,0 +1,130 @@
+// Where processing time goes, with the `profiling` feature: parsing, updating the book and writing to the
+// sinks each run inside a tracing span (nested in that order, so a flamegraph layer draws them as a stack)
+// and their time and calls are counted for --profile-report. Without the feature the timers are empty and
+// compile away
+
+#[cfg(feature = "profiling")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "profiling")]
+use std::time::Instant;
+
+use crate::utils::format_nanos;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    // Walking the file and decoding messages, per message
+    Parse,
+    // Applying a message to the books, per message
+    Book,
+    // Handing snapshots, trades and events to the sinks, per write
+    Write,
+}
+
+pub const STAGES: [Stage; 3] = [Stage::Parse, Stage::Book, Stage::Write];
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Parse => "parse",
+            Stage::Book => "book",
+            Stage::Write => "write",
+        }
+    }
+}
+
+#[cfg(feature = "profiling")]
+struct StageCounter {
+    calls: AtomicU64,
+    nanos: AtomicU64,
+}
+
+// Totals over every thread; a stage's time includes the stages nested in it
+#[cfg(feature = "profiling")]
+static COUNTERS: [StageCounter; 3] = [const { StageCounter { calls: AtomicU64::new(0), nanos: AtomicU64::new(0) } }; 3];
+
+// Times a stage until dropped
+#[cfg(feature = "profiling")]
+pub struct StageTimer {
+    stage: Stage,
+    start: Instant,
+    calls: u64,
+    _span: tracing::span::EnteredSpan,
+}
+
+#[cfg(not(feature = "profiling"))]
+pub struct StageTimer;
+
+#[cfg(feature = "profiling")]
+#[inline]
+pub fn start(stage: Stage) -> StageTimer {
+    // Per-message spans are at trace level, so they cost next to nothing unless a subscriber asks for them
+    let span = match stage {
+        Stage::Parse => tracing::info_span!("parse"),
+        Stage::Book => tracing::trace_span!("book"),
+        Stage::Write => tracing::trace_span!("write"),
+    };
+    StageTimer { stage, start: Instant::now(), calls: 1, _span: span.entered() }
+}
+
+#[cfg(not(feature = "profiling"))]
+#[inline(always)]
+pub fn start(_stage: Stage) -> StageTimer {
+    StageTimer
+}
+
+impl StageTimer {
+    // End the stage now, counting it as this many calls, e.g. the messages of a whole parse
+    #[inline]
+    pub fn stop(self, _calls: u64) {
+        #[cfg(feature = "profiling")]
+        {
+            let mut timer = self;
+            timer.calls = _calls;
+        }
+    }
+}
+
+#[cfg(feature = "profiling")]
+impl Drop for StageTimer {
+    #[inline]
+    fn drop(&mut self) {
+        let counter = &COUNTERS[self.stage as usize];
+        counter.calls.fetch_add(self.calls, Ordering::Relaxed);
+        counter.nanos.fetch_add(self.start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageTotals {
+    pub calls: u64,
+    // Including the nested stages
+    pub nanos: u64,
+    // Excluding them
+    pub self_nanos: u64,
+}
+
+// Totals of each stage in STAGES order, all zero without the feature
+pub fn totals() -> [StageTotals; 3] {
+    #[cfg(feature = "profiling")]
+    let raw = COUNTERS.each_ref().map(|counter| (counter.calls.load(Ordering::Relaxed), counter.nanos.load(Ordering::Relaxed)));
+    #[cfg(not(feature = "profiling"))]
+    let raw = [(0, 0); 3];
+    let mut totals = raw.map(|(calls, nanos)| StageTotals { calls, nanos, self_nanos: nanos });
+    for inner in 1..totals.len() {
+        totals[inner - 1].self_nanos = totals[inner - 1].nanos.saturating_sub(totals[inner].nanos);
+    }
+    totals
+}
+
+// Table of the stage totals for the end of a run
+pub fn report() -> String {
+    let totals = totals();
+    let all = totals.iter().map(|stage| stage.self_nanos).sum::<u64>().max(1);
+    let mut out = format!("{:<6} {:>12} {:>12} {:>12} {:>7} {:>10}\n", "Stage", "Calls", "Time", "Self time", "Self %", "Per call");
+    for (stage, total) in STAGES.iter().zip(&totals) {
+        out.push_str(&format!("{:<6} {:>12} {:>12} {:>12} {:>6.1}% {:>10}\n",
+                              stage.name(), total.calls, format_nanos(total.nanos), format_nanos(total.self_nanos),
+                              total.self_nanos as f64 * 100.0 / all as f64, format_nanos(total.nanos / total.calls.max(1))));
+    }
+    out
+}
@@ -0,0 +1,209 @@
+// Python bindings, built as the nasdaq_itch_orderbook extension module with maturin (feature `python`).
+// ItchReader walks the messages of a file and builds books from it; an OrderBook holds the snapshots of a
+// symbol as numpy arrays or an Arrow table, so notebooks don't have to round-trip through the CSV output
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
+use numpy::{PyArray1, PyArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+
+use crate::file_io::{self, InputData};
+use crate::message_types::FeedVariant;
+use crate::orderbook::{BookSnapshot, OrderBook, OrderBookOptions, Side, WriteMode, MAX_BOOK_DEPTH};
+use crate::parser::{self, ItchIterator, ParserOptions};
+use crate::pcap::Encapsulation;
+use crate::sink::OutputSink;
+use crate::utils::pad_stock_symbol;
+
+fn parse_enum<T: ValueEnum>(name: &str, value: &str) -> PyResult<T> {
+    T::from_str(value, true).map_err(|_| PyValueError::new_err(format!("invalid {}: {}", name, value)))
+}
+
+// Snapshots as columns, prices in dollars and missing levels as zeros like the CSV output
+#[derive(Default)]
+struct SnapshotColumns {
+    timestamp: Vec<u64>,
+    bid_price: Vec<f64>,
+    bid_vol: Vec<u32>,
+    ask_price: Vec<f64>,
+    ask_vol: Vec<u32>,
+    mid_price: Vec<f64>,
+    imbalance: Vec<f64>,
+}
+
+// Collects the snapshots written by the book; shared so the columns outlive the book
+struct ColumnSink(Arc<Mutex<SnapshotColumns>>);
+
+impl OutputSink for ColumnSink {
+    fn write_snapshot(&mut self, _symbol: &str, snapshot: &BookSnapshot) -> std::io::Result<()> {
+        let mut columns = self.0.lock().unwrap();
+        columns.timestamp.push(snapshot.timestamp);
+        for i in 0..MAX_BOOK_DEPTH {
+            let bid = snapshot.bid_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            let ask = snapshot.ask_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+            columns.bid_price.push(bid.0 as f64 / 10000.0);
+            columns.bid_vol.push(bid.1);
+            columns.ask_price.push(ask.0 as f64 / 10000.0);
+            columns.ask_vol.push(ask.1);
+        }
+        columns.mid_price.push(snapshot.mid_price);
+        columns.imbalance.push(snapshot.imbalance);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// A resting order as (side, price, shares, order_ref, timestamp)
+type RestingOrder = (char, f64, u32, u64, u64);
+
+#[pyclass(name = "OrderBook", frozen)]
+pub struct PyOrderBook {
+    #[pyo3(get)]
+    symbol: String,
+    columns: SnapshotColumns,
+    orders: Vec<RestingOrder>,
+}
+
+#[pymethods]
+impl PyOrderBook {
+    fn __len__(&self) -> usize {
+        self.columns.timestamp.len()
+    }
+
+    // The snapshots as numpy arrays; the level arrays are (snapshots, 10), best level first
+    fn snapshots<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let columns = &self.columns;
+        let rows = columns.timestamp.len();
+        let dict = PyDict::new(py);
+        dict.set_item("timestamp", PyArray1::from_slice(py, &columns.timestamp))?;
+        dict.set_item("bid_price", PyArray1::from_slice(py, &columns.bid_price).reshape([rows, MAX_BOOK_DEPTH])?)?;
+        dict.set_item("bid_vol", PyArray1::from_slice(py, &columns.bid_vol).reshape([rows, MAX_BOOK_DEPTH])?)?;
+        dict.set_item("ask_price", PyArray1::from_slice(py, &columns.ask_price).reshape([rows, MAX_BOOK_DEPTH])?)?;
+        dict.set_item("ask_vol", PyArray1::from_slice(py, &columns.ask_vol).reshape([rows, MAX_BOOK_DEPTH])?)?;
+        dict.set_item("mid_price", PyArray1::from_slice(py, &columns.mid_price))?;
+        dict.set_item("orderbook_imbalance", PyArray1::from_slice(py, &columns.imbalance))?;
+        Ok(dict)
+    }
+
+    // The snapshots as a pyarrow Table with the columns of the CSV output
+    fn to_arrow<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let columns = &self.columns;
+        let level = |values: &[f64], i: usize| values.iter().skip(i).step_by(MAX_BOOK_DEPTH).copied().collect::<Vec<_>>();
+        let volume = |values: &[u32], i: usize| values.iter().skip(i).step_by(MAX_BOOK_DEPTH).copied().collect::<Vec<_>>();
+        let dict = PyDict::new(py);
+        dict.set_item("timestamp", PyArray1::from_slice(py, &columns.timestamp))?;
+        for i in 0..MAX_BOOK_DEPTH {
+            dict.set_item(format!("{}_bid_price", i + 1), PyArray1::from_vec(py, level(&columns.bid_price, i)))?;
+            dict.set_item(format!("{}_bid_vol", i + 1), PyArray1::from_vec(py, volume(&columns.bid_vol, i)))?;
+            dict.set_item(format!("{}_ask_price", i + 1), PyArray1::from_vec(py, level(&columns.ask_price, i)))?;
+            dict.set_item(format!("{}_ask_vol", i + 1), PyArray1::from_vec(py, volume(&columns.ask_vol, i)))?;
+        }
+        dict.set_item("mid_price", PyArray1::from_slice(py, &columns.mid_price))?;
+        dict.set_item("orderbook_imbalance", PyArray1::from_slice(py, &columns.imbalance))?;
+        py.import("pyarrow")?.call_method1("table", (dict,))
+    }
+
+    // Resting orders at the end of the run, bids then asks in queue order
+    fn orders(&self) -> Vec<RestingOrder> {
+        self.orders.clone()
+    }
+}
+
+#[pyclass(frozen)]
+pub struct ItchReader {
+    input: InputData,
+    feed: FeedVariant,
+}
+
+#[pymethods]
+impl ItchReader {
+    #[new]
+    #[pyo3(signature = (path, encap = "raw", feed = "nasdaq"))]
+    fn new(path: PathBuf, encap: &str, feed: &str) -> PyResult<Self> {
+        let encap: Encapsulation = parse_enum("encapsulation", encap)?;
+        Ok(ItchReader { input: file_io::load_input(&path, encap)?, feed: parse_enum("feed", feed)? })
+    }
+
+    fn __len__(&self) -> usize {
+        self.input.len()
+    }
+
+    // The messages as (timestamp, type, message after its type byte)
+    fn __iter__(slf: Py<Self>) -> MessageIter {
+        MessageIter { reader: slf, offset: 0 }
+    }
+
+    // Build the books of the symbols in one pass, up to the time given in nanoseconds since midnight
+    #[pyo3(signature = (symbols, until = None, write_mode = "every-message"))]
+    fn books(&self, py: Python<'_>, symbols: Vec<String>, until: Option<u64>, write_mode: &str) -> PyResult<Vec<PyOrderBook>> {
+        let write_mode: WriteMode = parse_enum("write mode", write_mode)?;
+        let input = &self.input;
+        let options = ParserOptions { feed: self.feed, stop_at: until, ..Default::default() };
+        py.detach(|| {
+            let columns: Vec<_> = symbols.iter().map(|_| Arc::new(Mutex::new(SnapshotColumns::default()))).collect();
+            let mut books: Vec<OrderBook> = symbols.iter().zip(&columns)
+                .map(|(symbol, columns)| {
+                    let sink: Box<dyn OutputSink> = Box::new(ColumnSink(Arc::clone(columns)));
+                    OrderBook::new(pad_stock_symbol(symbol), OrderBookOptions { write_mode, sinks: vec![sink], ..Default::default() })
+                })
+                .collect();
+            parser::process_itch_file(input, &mut books, &options)?;
+
+            let orders: Vec<Vec<RestingOrder>> = books.iter().map(|book| {
+                let (bids, asks) = book.resting_orders();
+                bids.into_iter().chain(asks).map(|order| {
+                    let side = if order.side == Side::Buy { 'B' } else { 'S' };
+                    (side, order.price as f64 / 10000.0, order.shares, order.ref_number, order.timestamp)
+                }).collect()
+            }).collect();
+            // Dropping the books releases their sinks' share of the columns
+            drop(books);
+            Ok(symbols.into_iter().zip(columns).zip(orders).map(|((symbol, columns), orders)| PyOrderBook {
+                symbol,
+                columns: Arc::into_inner(columns).map(|columns| columns.into_inner().unwrap()).unwrap_or_default(),
+                orders,
+            }).collect())
+        })
+    }
+
+    #[pyo3(signature = (symbol, until = None, write_mode = "every-message"))]
+    fn book(&self, py: Python<'_>, symbol: String, until: Option<u64>, write_mode: &str) -> PyResult<PyOrderBook> {
+        let mut books = self.books(py, vec![symbol], until, write_mode)?;
+        Ok(books.remove(0))
+    }
+}
+
+#[pyclass]
+pub struct MessageIter {
+    reader: Py<ItchReader>,
+    offset: usize,
+}
+
+#[pymethods]
+impl MessageIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> Option<(u64, char, Bound<'py, PyBytes>)> {
+        let input = &self.reader.get().input;
+        let mut messages = ItchIterator::range(input, self.offset, input.len());
+        let (timestamp, message_type, message) = messages.next()?;
+        self.offset = messages.offset();
+        Some((timestamp, message_type as u8 as char, PyBytes::new(py, message)))
+    }
+}
+
+#[pymodule(name = "nasdaq_itch_orderbook")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<ItchReader>()?;
+    m.add_class::<PyOrderBook>()?;
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use rustc_hash::FxHashMap;
+
+use crate::orderbook::{Order, Side};
+
+// What happened to an order of the tracked symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderEvent {
+    Add,
+    Execute,
+    Cancel,
+    Delete,
+    // The order was replaced and lost its place; the new order gets its own add row
+    Replace,
+    // Still resting at the end of the file
+    Open,
+}
+
+impl OrderEvent {
+    fn name(self) -> &'static str {
+        match self {
+            OrderEvent::Add => "add",
+            OrderEvent::Execute => "execute",
+            OrderEvent::Cancel => "cancel",
+            OrderEvent::Delete => "delete",
+            OrderEvent::Replace => "replace",
+            OrderEvent::Open => "open",
+        }
+    }
+}
+
+// A tracked order's place in its price level
+#[derive(Debug, Clone)]
+struct QueuedOrder {
+    side: Side,
+    price: u32,
+    // Arrival sequence, lower is ahead in the queue
+    sequence: u64,
+    remaining: u32,
+    added: u64,
+}
+
+// Follows the time priority queue of every price level of the tracked symbol and writes each
+// order event with the shares ahead of the order at that moment, for fill probability research
+pub struct QueueTracker {
+    writer: BufWriter<File>,
+    orders: FxHashMap<u64, QueuedOrder>,
+    // (side, price) -> sequence -> remaining shares, in queue order
+    levels: FxHashMap<(Side, u32), BTreeMap<u64, u32>>,
+    next_sequence: u64,
+    // Timestamp of the last event, for the open rows
+    last_timestamp: u64,
+}
+
+impl QueueTracker {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"timestamp,order_ref,event,side,price,shares,remaining,queue_ahead,level_shares,age_ns\n")?;
+        Ok(QueueTracker { writer, orders: FxHashMap::default(), levels: FxHashMap::default(), next_sequence: 0, last_timestamp: 0 })
+    }
+
+    // Shares ahead of the order at its level and the level's total shares
+    fn position(&self, order: &QueuedOrder) -> (u64, u64) {
+        let Some(level) = self.levels.get(&(order.side, order.price)) else {
+            return (0, 0);
+        };
+        let ahead = level.range(..order.sequence).map(|(_, &shares)| shares as u64).sum();
+        let total = level.values().map(|&shares| shares as u64).sum();
+        (ahead, total)
+    }
+
+    fn write_row(&mut self, timestamp: u64, order_ref: u64, event: OrderEvent, order: &QueuedOrder, shares: u32) -> io::Result<()> {
+        self.last_timestamp = timestamp;
+        let (ahead, total) = self.position(order);
+        writeln!(self.writer, "{},{},{},{},{}.{:04},{},{},{},{},{}", timestamp, order_ref, event.name(),
+                 if order.side == Side::Buy { "B" } else { "S" }, order.price / 10000, order.price % 10000,
+                 shares, order.remaining, ahead, total, timestamp.saturating_sub(order.added))
+    }
+
+    // Join the back of the order's price level
+    fn enqueue(&mut self, order: &Order) -> QueuedOrder {
+        let queued = QueuedOrder {
+            side: order.side,
+            price: order.price,
+            sequence: self.next_sequence,
+            remaining: order.shares,
+            added: order.timestamp,
+        };
+        self.next_sequence += 1;
+        self.levels.entry((order.side, order.price)).or_default().insert(queued.sequence, order.shares);
+        self.orders.insert(order.ref_number, queued.clone());
+        queued
+    }
+
+    pub fn add(&mut self, order: &Order) -> io::Result<()> {
+        let queued = self.enqueue(order);
+        self.write_row(order.timestamp, order.ref_number, OrderEvent::Add, &queued, order.shares)
+    }
+
+    // Queue orders restored from a checkpoint without writing rows; they're expected in priority order
+    pub fn restore(&mut self, order: &Order) {
+        self.enqueue(order);
+    }
+
+    // Take shares off a tracked order (all of them for None), writing the row with the queue
+    // as it was before the event; orders that were never added are ignored
+    pub fn reduce(&mut self, timestamp: u64, order_ref: u64, event: OrderEvent, shares: Option<u32>) -> io::Result<()> {
+        let Some(order) = self.orders.get(&order_ref).cloned() else {
+            return Ok(());
+        };
+        let shares = shares.unwrap_or(order.remaining).min(order.remaining);
+        let remaining = order.remaining - shares;
+        self.write_row(timestamp, order_ref, event, &QueuedOrder { remaining, ..order.clone() }, shares)?;
+
+        let key = (order.side, order.price);
+        if remaining == 0 {
+            self.orders.remove(&order_ref);
+            if let Some(level) = self.levels.get_mut(&key) {
+                level.remove(&order.sequence);
+                if level.is_empty() {
+                    self.levels.remove(&key);
+                }
+            }
+        } else {
+            if let Some(tracked) = self.orders.get_mut(&order_ref) {
+                tracked.remaining = remaining;
+            }
+            if let Some(level) = self.levels.get_mut(&key) {
+                level.insert(order.sequence, remaining);
+            }
+        }
+        Ok(())
+    }
+
+    // Write an open row for every order still resting, in queue order
+    pub fn finalize(&mut self) -> io::Result<()> {
+        let timestamp = self.last_timestamp;
+        let mut open: Vec<(u64, QueuedOrder)> = self.orders.iter().map(|(&order_ref, order)| (order_ref, order.clone())).collect();
+        open.sort_by_key(|(_, order)| order.sequence);
+        for (order_ref, order) in &open {
+            self.write_row(timestamp, *order_ref, OrderEvent::Open, order, 0)?;
+        }
+        self.writer.flush()
+    }
+}
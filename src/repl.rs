@@ -0,0 +1,177 @@
+// Queries typed against the books once the input is processed (build-book --interactive), for a quick look at
+// a symbol's book, depth and trades without writing or reading any files
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::features::TradeStats;
+use crate::orderbook::{BookSnapshot, OrderBook, PriceLevel, TradeRecord};
+use crate::sink::OutputSink;
+use crate::utils::{format_time_of_day, parse_time_of_day};
+
+// Levels per side `book` shows, and `depth` without a count
+const BOOK_LEVELS: usize = 5;
+const DEFAULT_DEPTH: usize = 10;
+
+const HELP: &str = "\
+book SYMBOL                best bid and ask, the top 5 levels and the day's trades
+depth SYMBOL [LEVELS]      the top levels of the book, 10 by default
+trades SYMBOL [FROM [TO]]  trades from FROM up to TO (HH:MM[:SS[.fraction]])
+symbols                    symbols with a book
+help                       this list
+quit                       leave (or Ctrl-D)";
+
+// Keeps the trades of a book in memory for the trades query
+#[derive(Clone, Default)]
+pub struct TradeLog(Arc<Mutex<Vec<TradeRecord>>>);
+
+impl TradeLog {
+    fn take(&self) -> Vec<TradeRecord> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl OutputSink for TradeLog {
+    fn write_snapshot(&mut self, _symbol: &str, _snapshot: &BookSnapshot) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_trade(&mut self, _symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.0.lock().unwrap().push(trade.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn price(price: u32) -> String {
+    format!("{}.{:04}", price / 10000, price % 10000)
+}
+
+// HH:MM is taken as HH:MM:00
+fn parse_time(text: &str) -> Result<u64, String> {
+    if text.matches(':').count() == 1 {
+        parse_time_of_day(&format!("{}:00", text))
+    } else {
+        parse_time_of_day(text)
+    }
+}
+
+// Bid and ask levels side by side, best first
+fn ladder(bids: &[PriceLevel], asks: &[PriceLevel]) -> String {
+    let row = |level: &str, bid_size: &str, bid: &str, ask: &str, ask_size: &str| {
+        format!("{:>5} {:>10} {:>12} | {:<12} {}", level, bid_size, bid, ask, ask_size).trim_end().to_string()
+    };
+    let mut rows = vec![row("level", "bid size", "bid", "ask", "ask size")];
+    for i in 0..bids.len().max(asks.len()) {
+        let (bid_size, bid) = bids.get(i).map_or((String::new(), String::new()), |l| (l.total_volume.to_string(), price(l.price)));
+        let (ask, ask_size) = asks.get(i).map_or((String::new(), String::new()), |l| (price(l.price), l.total_volume.to_string()));
+        rows.push(row(&(i + 1).to_string(), &bid_size, &bid, &ask, &ask_size));
+    }
+    rows.join("\n")
+}
+
+// The books of a run and the trades each saw
+pub struct Explorer {
+    books: Vec<(OrderBook, Vec<TradeRecord>)>,
+}
+
+impl Explorer {
+    // A log per book, in the same order
+    pub fn new(books: Vec<OrderBook>, logs: &[TradeLog]) -> Self {
+        Explorer { books: books.into_iter().zip(logs.iter().map(TradeLog::take)).collect() }
+    }
+
+    fn find(&self, symbol: &str) -> Result<&(OrderBook, Vec<TradeRecord>), String> {
+        self.books.iter().find(|(book, _)| book.symbol_name().eq_ignore_ascii_case(symbol))
+            .ok_or_else(|| format!("No book for {}; the books are {}", symbol, self.symbols()))
+    }
+
+    fn symbols(&self) -> String {
+        self.books.iter().map(|(book, _)| book.symbol_name()).collect::<Vec<_>>().join(", ")
+    }
+
+    // The answer to one query line
+    pub fn query(&self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["help"] => Ok(HELP.to_string()),
+            ["symbols"] => Ok(self.symbols()),
+            ["book", symbol] => self.book(symbol),
+            ["depth", symbol] => self.depth(symbol, DEFAULT_DEPTH),
+            ["depth", symbol, levels] => {
+                let levels = levels.parse().map_err(|_| format!("Invalid level count \"{}\"", levels))?;
+                self.depth(symbol, levels)
+            }
+            ["trades", symbol, range @ ..] if range.len() <= 2 => {
+                let from = range.first().map_or(Ok(0), |text| parse_time(text))?;
+                let to = range.get(1).map_or(Ok(u64::MAX), |text| parse_time(text))?;
+                if to <= from {
+                    return Err("The end of the trades range must be after its start".to_string());
+                }
+                self.trades(symbol, from, to)
+            }
+            _ => Err(format!("Unknown query \"{}\"; type help for the queries", line)),
+        }
+    }
+
+    fn book(&self, symbol: &str) -> Result<String, String> {
+        let (book, _) = self.find(symbol)?;
+        let (bids, asks) = book.levels(BOOK_LEVELS);
+        let side = |level: Option<&PriceLevel>| level.map_or("none".to_string(), |l| format!("{} x {}", price(l.price), l.total_volume));
+        let mut text = format!("{}: bid {}, ask {}", book.symbol_name(), side(bids.first()), side(asks.first()));
+        if let (Some(bid), Some(ask)) = (bids.first(), asks.first()) {
+            let mid = (bid.price as f64 + ask.price as f64) / 20000.0;
+            let spread = ask.price as i64 - bid.price as i64;
+            text.push_str(&format!(", mid {:.4}, spread {}{}", mid, if spread < 0 { "-" } else { "" }, price(spread.unsigned_abs() as u32)));
+        }
+        text.push('\n');
+        text.push_str(&ladder(&bids, &asks));
+        let (resting_bids, resting_asks) = book.resting_orders();
+        let trades = book.trade_stats();
+        text.push_str(&format!("\n{} resting orders ({} bids, {} asks); {} trades, {} shares, vwap {:.4}",
+                               resting_bids.len() + resting_asks.len(), resting_bids.len(), resting_asks.len(),
+                               trades.trades, trades.volume, trades.vwap()));
+        Ok(text)
+    }
+
+    fn depth(&self, symbol: &str, levels: usize) -> Result<String, String> {
+        let (book, _) = self.find(symbol)?;
+        let (bids, asks) = book.levels(levels);
+        Ok(ladder(&bids, &asks))
+    }
+
+    fn trades(&self, symbol: &str, from: u64, to: u64) -> Result<String, String> {
+        let (_, trades) = self.find(symbol)?;
+        let mut text = format!("{:<18} {:>12} {:>8} {:>9} {:>4} {:>12}", "time", "price", "shares", "aggressor", "type", "match");
+        let mut stats = TradeStats::default();
+        for trade in trades.iter().filter(|trade| (from..to).contains(&trade.timestamp)) {
+            stats.add(trade.price, trade.shares);
+            text.push_str(&format!("\n{:<18} {:>12} {:>8} {:>9} {:>4} {:>12}", format_time_of_day(trade.timestamp), price(trade.price),
+                                   trade.shares, trade.aggressor_code(), trade.message_type as char, trade.match_number));
+        }
+        text.push_str(&format!("\n{} trades, {} shares, vwap {:.4}", stats.trades, stats.volume, stats.vwap()));
+        Ok(text)
+    }
+
+    // Answer the queries read from `input` until quit or the end of the input
+    pub fn run<R: BufRead, W: Write>(&self, input: R, output: &mut W) -> io::Result<()> {
+        writeln!(output, "Books of {}; type help for the queries", self.symbols())?;
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            match line?.trim() {
+                "" => {}
+                "quit" | "exit" => return Ok(()),
+                query => match self.query(query) {
+                    Ok(answer) => writeln!(output, "{}", answer)?,
+                    Err(e) => writeln!(output, "Error: {}", e)?,
+                },
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+}
@@ -0,0 +1,127 @@
+// Builds books from ITCH bytes a message at a time, handing out the snapshots they write one by one, for the
+// embeddings (the C API, the browser build) that pull snapshots instead of running the parser over a file
+
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use crate::handler;
+use crate::orderbook::{BookSnapshot, OrderBook, OrderBookOptions, TradeRecord};
+use crate::parser::ItchIterator;
+use crate::sink::OutputSink;
+use crate::utils::pad_stock_symbol;
+
+// Snapshots written by the books and not pulled yet, with the index of their book
+type Pending = Arc<Mutex<VecDeque<(usize, BookSnapshot)>>>;
+// Trades not taken yet, likewise
+type PendingTrades = Arc<Mutex<Vec<(usize, TradeRecord)>>>;
+
+struct PendingSink {
+    book: usize,
+    pending: Pending,
+    trades: Option<PendingTrades>,
+}
+
+impl OutputSink for PendingSink {
+    fn write_snapshot(&mut self, _symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        self.pending.lock().unwrap().push_back((self.book, snapshot.clone()));
+        Ok(())
+    }
+
+    fn write_trade(&mut self, _symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        if let Some(trades) = &self.trades {
+            trades.lock().unwrap().push((self.book, trade.clone()));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct SnapshotReplay<D> {
+    input: D,
+    // Offset of the next message to apply
+    offset: usize,
+    books: Vec<OrderBook>,
+    pending: Pending,
+    trades: Option<PendingTrades>,
+}
+
+impl<D: Deref<Target = [u8]>> SnapshotReplay<D> {
+    // Books of the symbols, writing a snapshot for every message that touches them
+    pub fn new(input: D, symbols: &[&str]) -> Self {
+        Self::create(input, symbols, None)
+    }
+
+    // Also keeping the books' trades, to be taken with take_trades
+    pub fn with_trades(input: D, symbols: &[&str]) -> Self {
+        Self::create(input, symbols, Some(PendingTrades::default()))
+    }
+
+    fn create(input: D, symbols: &[&str], trades: Option<PendingTrades>) -> Self {
+        let pending = Pending::default();
+        let books = Self::books(symbols, &pending, &trades);
+        SnapshotReplay { input, offset: 0, books, pending, trades }
+    }
+
+    fn books(symbols: &[&str], pending: &Pending, trades: &Option<PendingTrades>) -> Vec<OrderBook> {
+        symbols.iter().enumerate()
+            .map(|(book, symbol)| {
+                let sink = PendingSink { book, pending: Arc::clone(pending), trades: trades.clone() };
+                OrderBook::new(pad_stock_symbol(symbol), OrderBookOptions { sinks: vec![Box::new(sink)], ..Default::default() })
+            })
+            .collect()
+    }
+
+    // Start over from the beginning of the input with empty books
+    pub fn rewind(&mut self) {
+        let symbols: Vec<String> = self.books.iter().map(|book| book.symbol_name().to_string()).collect();
+        let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+        self.pending.lock().unwrap().clear();
+        self.take_trades();
+        self.books = Self::books(&symbols, &self.pending, &self.trades);
+        self.offset = 0;
+    }
+
+    // Apply messages until one of them writes a snapshot. Returns it with the index of its symbol, or None
+    // at the end of the data
+    pub fn next_snapshot(&mut self) -> io::Result<Option<(usize, BookSnapshot)>> {
+        let mut messages = ItchIterator::range(&self.input, self.offset, self.input.len());
+        while self.pending.lock().unwrap().is_empty() {
+            let Some((timestamp, message_type, message)) = messages.next() else {
+                break;
+            };
+            for book in &mut self.books {
+                handler::dispatch(book, message_type, message, timestamp)?;
+            }
+        }
+        self.offset = messages.offset();
+        Ok(self.pending.lock().unwrap().pop_front())
+    }
+
+    // The trades of the messages applied so far, with the index of their symbol; empty unless built
+    // with_trades
+    pub fn take_trades(&mut self) -> Vec<(usize, TradeRecord)> {
+        self.trades.as_ref().map_or_else(Vec::new, |trades| std::mem::take(&mut *trades.lock().unwrap()))
+    }
+
+    pub fn book(&self, index: usize) -> &OrderBook {
+        &self.books[index]
+    }
+
+    // Bytes of the input applied so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn len(&self) -> usize {
+        self.input.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+}
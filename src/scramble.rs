@@ -0,0 +1,162 @@
+// The scramble subcommand: rewrite an ITCH file with renamed symbols, renumbered stock locates, permuted order refs
+// and shifted timestamps, keeping prices, sizes, sides and the order of the messages, so the books move exactly as
+// in the original. The same input and seed always give the same output
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem::offset_of;
+use std::ops::ControlFlow;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::cli::ScrambleArgs;
+use crate::file_io;
+use crate::message_types::MessageType;
+use crate::parser::{self, MSG_HEADER_SIZE, NANOS_PER_DAY};
+use crate::utils::{format_duration, format_time_of_day};
+use crate::wire;
+
+// Largest time shift picked from the seed, either way
+const MAX_DEFAULT_SHIFT_SECONDS: u64 = 1800;
+
+// The splitmix64 finalizer, a bijection of u64
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// A time shift of whole seconds within half an hour either way, for runs without --time-shift
+pub fn default_time_shift(seed: u64) -> i64 {
+    let seconds = (mix(seed ^ 0x7469_6d65) % (2 * MAX_DEFAULT_SHIFT_SECONDS + 1)) as i64 - MAX_DEFAULT_SHIFT_SECONDS as i64;
+    seconds * 1_000_000_000
+}
+
+// What each identifier became so far
+pub struct Scrambler {
+    seed: u64,
+    time_shift: i64,
+    symbols: FxHashMap<[u8; 8], [u8; 8]>,
+    names: FxHashSet<[u8; 8]>,
+    // Locates are numbered from 1 in order of first appearance; 0 stays market wide
+    locates: FxHashMap<u16, u16>,
+}
+
+impl Scrambler {
+    pub fn new(seed: u64, time_shift: i64) -> Self {
+        Scrambler { seed, time_shift, symbols: FxHashMap::default(), names: FxHashSet::default(), locates: FxHashMap::default() }
+    }
+
+    // Distinct refs stay distinct, and 0 (no order) stays 0: the ref mixed and offset by the seed, once more for the
+    // one ref that lands on 0, which then takes the place of 0
+    pub fn order_ref(&self, order_ref: u64) -> u64 {
+        if order_ref == 0 {
+            return 0;
+        }
+        let scrambled = mix(order_ref).wrapping_add(self.seed);
+        if scrambled == 0 { mix(0).wrapping_add(self.seed) } else { scrambled }
+    }
+
+    // Four letters picked from the seed and the symbol, different for every symbol
+    pub fn symbol(&mut self, stock: [u8; 8]) -> [u8; 8] {
+        if let Some(&name) = self.symbols.get(&stock) {
+            return name;
+        }
+        let mut key = mix(self.seed ^ u64::from_be_bytes(stock));
+        let name = loop {
+            let mut name = [b' '; 8];
+            let mut letters = key;
+            for byte in &mut name[..4] {
+                *byte = b'A' + (letters % 26) as u8;
+                letters /= 26;
+            }
+            if self.names.insert(name) {
+                break name;
+            }
+            key = mix(key.wrapping_add(1));
+        };
+        self.symbols.insert(stock, name);
+        name
+    }
+
+    fn locate(&mut self, locate: u16) -> u16 {
+        if locate == 0 {
+            return 0;
+        }
+        let next = self.locates.len() as u16 + 1;
+        *self.locates.entry(locate).or_insert(next)
+    }
+
+    pub fn symbols(&self) -> usize {
+        self.symbols.len()
+    }
+
+    // Rewrite a message after its type byte in place
+    pub fn scramble(&mut self, message_type: MessageType, message: &mut [u8]) -> io::Result<()> {
+        let Some(header) = wire::Header::view(message) else {
+            return Ok(());
+        };
+        let timestamp = header.timestamp.get() as i64 + self.time_shift;
+        if !(0..NANOS_PER_DAY as i64).contains(&timestamp) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "The time shift moves a message at {} out of the day", format_time_of_day(header.timestamp.get()))));
+        }
+        let locate = self.locate(header.stock_locate.get());
+        // Where the symbol is in the message, for the types that carry one
+        let stock_offset = wire::decode_body(message_type, message).and_then(|decoded| decoded.stock())
+            .map(|stock| stock.as_ptr() as usize - message.as_ptr() as usize);
+
+        let locate_at = offset_of!(wire::Header, stock_locate);
+        message[locate_at..locate_at + 2].copy_from_slice(&locate.to_be_bytes());
+        let timestamp_at = offset_of!(wire::Header, timestamp);
+        message[timestamp_at..timestamp_at + 6].copy_from_slice(&(timestamp as u64).to_be_bytes()[2..]);
+        if let Some(at) = stock_offset {
+            let stock = message[at..at + 8].try_into().unwrap();
+            message[at..at + 8].copy_from_slice(&self.symbol(stock));
+        }
+        // Every order message has its ref right after the header, like a delete
+        let refs: &[usize] = match message_type {
+            MessageType::AddOrder | MessageType::AddOrderWithMpid | MessageType::OrderExecuted | MessageType::OrderExecutedWithPrice
+            | MessageType::OrderCancel | MessageType::OrderDelete | MessageType::Trade => &[offset_of!(wire::OrderDelete, order_ref_number)],
+            MessageType::OrderReplace => &[offset_of!(wire::OrderReplace, original_order_ref_number), offset_of!(wire::OrderReplace, new_order_ref_number)],
+            _ => &[],
+        };
+        for &at in refs {
+            let order_ref = u64::from_be_bytes(message[at..at + 8].try_into().unwrap());
+            message[at..at + 8].copy_from_slice(&self.order_ref(order_ref).to_be_bytes());
+        }
+        Ok(())
+    }
+}
+
+// Write every message of the data scrambled, with its length prefix; the number of messages
+pub fn scramble_messages<W: Write>(data: &[u8], scrambler: &mut Scrambler, output: &mut W) -> io::Result<u64> {
+    let mut messages = 0;
+    let mut buffer = Vec::new();
+    let mut result = Ok(());
+    parser::scan_range(data, 0, data.len(), |offset, message_type, message| {
+        buffer.clear();
+        buffer.extend_from_slice(&data[offset..offset + MSG_HEADER_SIZE + message.len()]);
+        result = scrambler.scramble(message_type, &mut buffer[MSG_HEADER_SIZE..]).and_then(|()| output.write_all(&buffer));
+        if result.is_err() {
+            return ControlFlow::Break(());
+        }
+        messages += 1;
+        ControlFlow::Continue(())
+    });
+    result.map(|()| messages)
+}
+
+pub fn run(args: ScrambleArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = file_io::load_input(&args.file, args.encap)?;
+    let time_shift = args.time_shift.unwrap_or_else(|| default_time_shift(args.seed));
+    let mut scrambler = Scrambler::new(args.seed, time_shift);
+    let mut output = BufWriter::new(File::create(&args.output_file)?);
+    let messages = scramble_messages(&input, &mut scrambler, &mut output)?;
+    output.flush()?;
+    let shift = match time_shift {
+        0 => "0".to_string(),
+        _ => format!("{}{}", if time_shift < 0 { "-" } else { "" }, format_duration(time_shift.unsigned_abs())),
+    };
+    tracing::info!("Wrote {} messages of {} symbols to {}, shifted by {}", messages, scrambler.symbols(), args.output_file.display(), shift);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+// Golden-file check of the whole pipeline: a small spec-valid ITCH sample (two symbols, every order message
+// type, generated by testdata::SyntheticFeed) goes through the parser and the books, and the message offsets,
+// a depth file, a BBO file and a trades file must match the expected outputs byte for byte. The sample and
+// the expected files are compiled in, so `--selftest` checks an installed binary; tests/golden.rs runs the
+// same check, and regenerates the expected files with UPDATE_GOLDEN=1 after an intended change
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::features::FeatureColumns;
+use crate::orderbook::{OrderBook, OrderBookOptions, OutputFormat};
+use crate::parser::{self, ItchIterator, ParserOptions};
+use crate::sink::{self, TradesCsvSink};
+use crate::utils::pad_stock_symbol;
+
+pub const SAMPLE: &[u8] = include_bytes!("../tests/data/golden/sample.itch");
+
+// Name and expected contents of each output
+pub const GOLDEN_FILES: [(&str, &str); 4] = [
+    ("messages.csv", include_str!("../tests/data/golden/messages.csv")),
+    ("AAPL.csv", include_str!("../tests/data/golden/AAPL.csv")),
+    ("AAPL_trades.csv", include_str!("../tests/data/golden/AAPL_trades.csv")),
+    ("MSFT_bbo.csv", include_str!("../tests/data/golden/MSFT_bbo.csv")),
+];
+
+// Run the sample through the pipeline, writing the outputs of GOLDEN_FILES to `dir`
+pub fn write_outputs(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    // Where every message starts, so framing changes show up on their own
+    let mut messages = String::from("offset,type,timestamp\n");
+    let mut iter = ItchIterator::new(SAMPLE);
+    loop {
+        let offset = iter.offset();
+        let Some((timestamp, message_type, _)) = iter.next() else {
+            break;
+        };
+        let _ = writeln!(messages, "{},{},{}", offset, message_type as u8 as char, timestamp);
+    }
+    fs::write(dir.join("messages.csv"), messages)?;
+
+    let aapl = OrderBookOptions {
+        sinks: vec![
+            sink::create_file_sink(OutputFormat::Csv, &dir.join("AAPL.csv"), false, FeatureColumns::default(), None)?,
+            Box::new(TradesCsvSink::create(&dir.join("AAPL_trades.csv"))?),
+        ],
+        ..Default::default()
+    };
+    let msft = OrderBookOptions {
+        bbo_only: true,
+        sinks: vec![sink::create_file_sink(OutputFormat::Csv, &dir.join("MSFT_bbo.csv"), true, FeatureColumns::default(), None)?],
+        ..Default::default()
+    };
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), aapl), OrderBook::new(pad_stock_symbol("MSFT"), msft)];
+    parser::process_itch_file(SAMPLE, &mut books, &ParserOptions { strict: true, ..Default::default() })?;
+    for book in &mut books {
+        book.finalize()?;
+    }
+    Ok(())
+}
+
+// The first line where an output differs from its expected contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub file: &'static str,
+    // 1 for the first line
+    pub line: usize,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_end = |line: &Option<String>| line.clone().unwrap_or_else(|| "(end of file)".to_string());
+        write!(f, "{} differs at line {}\n  expected: {}\n  actual:   {}", self.file, self.line, or_end(&self.expected), or_end(&self.actual))
+    }
+}
+
+// Compare the outputs in `dir` with the expected ones
+pub fn check_outputs(dir: &Path) -> io::Result<Vec<Mismatch>> {
+    let mut mismatches = Vec::new();
+    for (file, expected) in GOLDEN_FILES {
+        let actual = fs::read_to_string(dir.join(file))?;
+        let (mut expected_lines, mut actual_lines) = (expected.lines(), actual.lines());
+        for line in 1.. {
+            let (expected, actual) = (expected_lines.next(), actual_lines.next());
+            if expected != actual {
+                mismatches.push(Mismatch { file, line, expected: expected.map(str::to_string), actual: actual.map(str::to_string) });
+                break;
+            }
+            if expected.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+// --selftest: run the check in a temporary directory and report the result
+pub fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("nasdaq-itch-orderbook-selftest-{}", std::process::id()));
+    let result = write_outputs(&dir).and_then(|()| check_outputs(&dir));
+    let _ = fs::remove_dir_all(&dir);
+    let mismatches = result?;
+    if mismatches.is_empty() {
+        println!("Self test passed: {} bytes of ITCH, {} output files as expected", SAMPLE.len(), GOLDEN_FILES.len());
+        return Ok(());
+    }
+    for mismatch in &mismatches {
+        println!("{}", mismatch);
+    }
+    Err(format!("Self test failed: {} of {} output files differ", mismatches.len(), GOLDEN_FILES.len()).into())
+}
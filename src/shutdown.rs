@@ -0,0 +1,68 @@
+// Stopping the command line tool cleanly on SIGINT or SIGTERM. The first signal sets a flag the parser checks
+// at message boundaries, so the books finalize their sinks as at the end of the file, and tells the WebSocket
+// servers to close their sessions with a close frame. A second signal exits at once
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+static REQUESTED: LazyLock<Arc<AtomicBool>> = LazyLock::new(Arc::default);
+static NOTIFY: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::channel(false).0);
+
+// The flag for ParserOptions::stop
+pub fn flag() -> Arc<AtomicBool> {
+    REQUESTED.clone()
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+    NOTIFY.send_replace(true);
+}
+
+// Resolves once a shutdown has been requested
+pub async fn wait() {
+    let mut receiver = NOTIFY.subscribe();
+    let _ = receiver.wait_for(|&requested| requested).await;
+}
+
+// Wait up to `timeout` for a condition, e.g. for the WebSocket sessions to close
+pub async fn drain(timeout: Duration, done: impl Fn() -> bool) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !done() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+// Turn SIGINT and SIGTERM into shutdown requests for as long as the process runs
+pub async fn handle_signals() -> io::Result<()> {
+    loop {
+        signal().await?;
+        if requested() {
+            tracing::warn!("Exiting without cleaning up");
+            std::process::exit(130);
+        }
+        tracing::info!("Shutting down, press Ctrl-C again to exit at once");
+        request();
+    }
+}
+
+#[cfg(unix)]
+async fn signal() -> io::Result<()> {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() -> io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,78 @@
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// Padded stock symbols as u64 keys, matched against the stock field of a message with one load.
+// With AVX2 four symbols are compared per instruction, for runs over many symbols
+pub struct SymbolSet {
+    // Padded to a multiple of four by repeating the first symbol, so the padding matches nothing extra
+    keys: Vec<u64>,
+    len: usize,
+    #[cfg(target_arch = "x86_64")]
+    avx2: bool,
+}
+
+impl SymbolSet {
+    pub fn new(symbols: &[[u8; 8]]) -> Self {
+        let mut keys: Vec<u64> = symbols.iter().map(|symbol| u64::from_ne_bytes(*symbol)).collect();
+        let len = keys.len();
+        if let Some(&first) = keys.first() {
+            keys.resize(len.next_multiple_of(4), first);
+        }
+        SymbolSet {
+            keys,
+            len,
+            #[cfg(target_arch = "x86_64")]
+            avx2: is_x86_feature_detected!("avx2"),
+        }
+    }
+
+    // Whether the stock field of a message is one of the symbols
+    #[inline]
+    pub fn contains(&self, stock: &[u8; 8]) -> bool {
+        let stock = u64::from_ne_bytes(*stock);
+        #[cfg(target_arch = "x86_64")]
+        if self.avx2 {
+            return unsafe { self.contains_avx2(stock) };
+        }
+        self.keys[..self.len].contains(&stock)
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn contains_avx2(&self, stock: u64) -> bool {
+        let needle = _mm256_set1_epi64x(stock as i64);
+        self.keys.chunks_exact(4).any(|chunk| {
+            let keys = unsafe { _mm256_loadu_si256(chunk.as_ptr() as *const __m256i) };
+            _mm256_movemask_epi8(_mm256_cmpeq_epi64(keys, needle)) != 0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolSet;
+
+    fn padded(symbol: &str) -> [u8; 8] {
+        let mut padded = [b' '; 8];
+        padded[..symbol.len()].copy_from_slice(symbol.as_bytes());
+        padded
+    }
+
+    #[test]
+    fn avx2_agrees_with_scalar() {
+        let fields = [padded("AAPL"), padded("AAPLW"), padded("AAP"), padded("MSFT"), padded("NVDA"), padded("ZVZZT"), [0; 8],
+                      *b"AAPL\0\0\0\0"];
+        for count in 0..=6 {
+            let symbols: Vec<[u8; 8]> = ["AAPL", "MSFT", "NVDA", "QQQ", "SPY", "TSLA"][..count].iter().map(|s| padded(s)).collect();
+            let detected = SymbolSet::new(&symbols);
+            #[cfg(target_arch = "x86_64")]
+            let scalar = SymbolSet { avx2: false, ..SymbolSet::new(&symbols) };
+            #[cfg(not(target_arch = "x86_64"))]
+            let scalar = SymbolSet::new(&symbols);
+            for field in &fields {
+                assert_eq!(detected.contains(field), symbols.contains(field), "{:?} in {} symbols", field, count);
+                assert_eq!(scalar.contains(field), symbols.contains(field), "{:?} in {} symbols", field, count);
+            }
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::bars::Bar;
+use crate::compress::{self, Compression, OutputWriter};
+use crate::features::{ColumnKind, FeatureColumns, FeatureValue, PriceFormat};
+use crate::lobster::LobsterSink;
+use crate::orderbook::{BookSnapshot, Levels, MarketEvent, OrderMessage, OutputFormat, Side, TradeRecord, MAX_BOOK_DEPTH};
+#[cfg(feature = "native")]
+use crate::sqlite::SqliteSink;
+
+// Destination for everything the orderbook produces. Sinks must be Send because the
+// orderbook is moved to the parsing thread.
+pub trait OutputSink: Send {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()>;
+
+    // Trades and events are ignored by sinks that only record the book
+    fn write_trade(&mut self, _symbol: &str, _trade: &TradeRecord) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_event(&mut self, _event: &MarketEvent) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Order level messages, each with the levels it left, for the sinks whose wants_orders is true
+    fn write_order(&mut self, _symbol: &str, _message: &OrderMessage, _bids: &Levels, _asks: &Levels) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn wants_orders(&self) -> bool {
+        false
+    }
+
+    // Completed OHLCV bars, with --bars-output or --stream-bars
+    fn write_bar(&mut self, _symbol: &str, _bar: &Bar) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Processing counters, refreshed periodically and once more before finalize
+    fn update_counts(&mut self, _messages: u64, _book_updates: u64, _skipped_updates: u64) {}
+
+    fn flush(&mut self) -> io::Result<()>;
+
+    // Called once after the last message
+    fn finalize(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+// Create the sink for the main output file, with the computed columns that were asked for. Only CSV
+// and JSONL can be compressed, and get the compression's extension; SQLite always gets every column
+pub fn create_file_sink(format: OutputFormat, path: &Path, bbo_only: bool, columns: FeatureColumns, compression: Option<Compression>) -> io::Result<Box<dyn OutputSink>> {
+    if compression.is_some() && !matches!(format, OutputFormat::Csv | OutputFormat::Jsonl | OutputFormat::Null) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--compress only applies to csv and jsonl output"));
+    }
+    if !columns.selection().is_empty() && matches!(format, OutputFormat::Sqlite | OutputFormat::Lobster) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--columns only applies to csv, jsonl and parquet output"));
+    }
+    let path = &compress::output_path(path, compression);
+    Ok(match format {
+        OutputFormat::Csv => Box::new(CsvSink::create(path, bbo_only, columns, compression)?),
+        OutputFormat::Jsonl => Box::new(JsonlSink::create(path, bbo_only, columns, compression)?),
+        OutputFormat::Parquet => Box::new(ParquetSink::create(path, bbo_only, columns)?),
+        #[cfg(feature = "native")]
+        OutputFormat::Sqlite => Box::new(SqliteSink::create(path, bbo_only, if bbo_only { 1 } else { MAX_BOOK_DEPTH }, columns)?),
+        #[cfg(not(feature = "native"))]
+        OutputFormat::Sqlite => return Err(io::Error::new(io::ErrorKind::Unsupported, "SQLite output needs the native feature")),
+        OutputFormat::Lobster => Box::new(LobsterSink::create(path, if bbo_only { 1 } else { columns.levels() })?),
+        OutputFormat::Null => Box::new(NullSink),
+    })
+}
+
+// Every column of a snapshot row, in file order: the timestamp, the depth or best bid/offer columns, then the
+// computed ones
+pub fn row_columns(bbo_only: bool, columns: &FeatureColumns) -> Vec<(String, ColumnKind)> {
+    use ColumnKind::*;
+    let mut fields = vec![("timestamp".to_string(), columns.timestamps().kind())];
+    let price = columns.prices().kind();
+    if bbo_only {
+        for (name, kind) in [("bid", price), ("bid_size", Integer), ("ask", price), ("ask_size", Integer), ("mid", Double), ("spread", price)] {
+            fields.push((name.to_string(), kind));
+        }
+    } else {
+        for level in 1..=MAX_BOOK_DEPTH {
+            fields.push((format!("{}_bid_price", level), price));
+            fields.push((format!("{}_bid_vol", level), Integer));
+            fields.push((format!("{}_ask_price", level), price));
+            fields.push((format!("{}_ask_vol", level), Integer));
+        }
+        fields.push(("mid_price".to_string(), Double));
+        fields.push(("orderbook_imbalance".to_string(), Double));
+    }
+    fields.extend(columns.names());
+    fields
+}
+
+// Positions in the full row of the columns picked with --columns, in the order they were given; None to
+// write them all
+fn selected_columns(all: &[(String, ColumnKind)], selection: &[String]) -> io::Result<Option<Vec<usize>>> {
+    if selection.is_empty() {
+        return Ok(None);
+    }
+    let mut indices = Vec::with_capacity(selection.len());
+    for name in selection {
+        let Some(index) = all.iter().position(|(column, _)| column == name) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown column \"{}\" in --columns", name)));
+        };
+        if indices.contains(&index) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Column \"{}\" is listed twice in --columns", name)));
+        }
+        indices.push(index);
+    }
+    Ok(Some(indices))
+}
+
+// The columns picked with --columns, cut out of the full CSV row so they keep its formats
+struct Projection {
+    indices: Vec<usize>,
+    names: Vec<(String, ColumnKind)>,
+    row: Vec<u8>,
+}
+
+impl Projection {
+    fn create(bbo_only: bool, columns: &FeatureColumns) -> io::Result<Option<Self>> {
+        let all = row_columns(bbo_only, columns);
+        Ok(selected_columns(&all, columns.selection())?.map(|indices| {
+            let names = indices.iter().map(|&index| all[index].clone()).collect();
+            Projection { indices, names, row: Vec::new() }
+        }))
+    }
+
+    fn fields<'a>(row: &'a mut Vec<u8>, indices: &[usize], snapshot: &BookSnapshot, bbo_only: bool, columns: &FeatureColumns) -> io::Result<Vec<&'a str>> {
+        row.clear();
+        if bbo_only {
+            snapshot.write_bbo_row(row, columns)?;
+        } else {
+            snapshot.write_depth_row(row, MAX_BOOK_DEPTH, columns)?;
+        }
+        let line = std::str::from_utf8(row.strip_suffix(b"\n").unwrap_or(row)).map_err(io::Error::other)?;
+        let fields: Vec<&str> = line.split(',').collect();
+        Ok(indices.iter().map(|&index| fields[index]).collect())
+    }
+
+    fn write_csv<W: Write>(&mut self, out: &mut W, snapshot: &BookSnapshot, bbo_only: bool, columns: &FeatureColumns) -> io::Result<()> {
+        let fields = Self::fields(&mut self.row, &self.indices, snapshot, bbo_only, columns)?;
+        out.write_all(fields.join(",").as_bytes())?;
+        out.write_all(b"\n")
+    }
+
+    // Like to_json, the symbol first and the timestamp as a string
+    fn json(&mut self, symbol: &str, snapshot: &BookSnapshot, bbo_only: bool, columns: &FeatureColumns) -> io::Result<String> {
+        let fields = Self::fields(&mut self.row, &self.indices, snapshot, bbo_only, columns)?;
+        let mut json = format!("{{\"symbol\":\"{}\"", symbol);
+        for ((name, kind), field) in self.names.iter().zip(fields) {
+            if *kind == ColumnKind::Text || name == "timestamp" {
+                json.push_str(&format!(", \"{}\":\"{}\"", name, field));
+            } else {
+                json.push_str(&format!(", \"{}\":{}", name, field));
+            }
+        }
+        json.push('}');
+        Ok(json)
+    }
+}
+
+// Orderbook rows as CSV, either full depth or best bid/offer
+pub struct CsvSink {
+    writer: BufWriter<OutputWriter>,
+    bbo_only: bool,
+    columns: FeatureColumns,
+    projection: Option<Projection>,
+    rows: u64,
+}
+
+impl CsvSink {
+    pub fn create(path: &Path, bbo_only: bool, columns: FeatureColumns, compression: Option<Compression>) -> io::Result<Self> {
+        let projection = Projection::create(bbo_only, &columns)?;
+        let mut writer = BufWriter::new(OutputWriter::create(path, compression)?);
+
+        // Write CSV header
+        let names = match &projection {
+            Some(projection) => projection.names.clone(),
+            None => row_columns(bbo_only, &columns),
+        };
+        let header: Vec<String> = names.into_iter().map(|(name, _)| name).collect();
+        writer.write_all(header.join(",").as_bytes())?;
+        writer.write_all(b"\n")?;
+
+        Ok(CsvSink { writer, bbo_only, columns, projection, rows: 0 })
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn write_snapshot(&mut self, _symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        if let Some(projection) = &mut self.projection {
+            projection.write_csv(&mut self.writer, snapshot, self.bbo_only, &self.columns)?;
+        } else if self.bbo_only {
+            snapshot.write_bbo_row(&mut self.writer, &self.columns)?;
+        } else {
+            snapshot.write_depth_row(&mut self.writer, MAX_BOOK_DEPTH, &self.columns)?;
+        }
+
+        // Only flush periodically to reduce I/O overhead
+        self.rows += 1;
+        if self.rows.is_multiple_of(100) {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_mut().finish()
+    }
+}
+
+// One JSON object per snapshot per line, in the same format as the WebSocket feed
+pub struct JsonlSink {
+    writer: BufWriter<OutputWriter>,
+    bbo_only: bool,
+    columns: FeatureColumns,
+    projection: Option<Projection>,
+}
+
+impl JsonlSink {
+    pub fn create(path: &Path, bbo_only: bool, columns: FeatureColumns, compression: Option<Compression>) -> io::Result<Self> {
+        let projection = Projection::create(bbo_only, &columns)?;
+        Ok(JsonlSink { writer: BufWriter::new(OutputWriter::create(path, compression)?), bbo_only, columns, projection })
+    }
+}
+
+impl OutputSink for JsonlSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let json = if let Some(projection) = &mut self.projection {
+            projection.json(symbol, snapshot, self.bbo_only, &self.columns)?
+        } else if self.bbo_only {
+            snapshot.to_bbo_json(symbol, &self.columns)
+        } else {
+            snapshot.to_json(symbol, MAX_BOOK_DEPTH, &self.columns)
+        };
+        self.writer.write_all(json.as_bytes())?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_mut().finish()
+    }
+}
+
+// Rows per Parquet row group
+pub(crate) const PARQUET_ROW_GROUP_SIZE: usize = 65536;
+
+enum ColumnData {
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    Text(Vec<ByteArray>),
+}
+
+// Columnar snapshot file with the same columns as the CSV, buffered one row group at a time
+pub struct ParquetSink {
+    writer: Option<SerializedFileWriter<File>>,
+    columns: Vec<ColumnData>,
+    // Position in the full row of each written column
+    selection: Vec<usize>,
+    bbo_only: bool,
+    features: FeatureColumns,
+    rows: usize,
+}
+
+pub(crate) fn parquet_error(e: parquet::errors::ParquetError) -> io::Error {
+    io::Error::other(format!("Parquet error: {}", e))
+}
+
+impl ParquetSink {
+    pub fn create(path: &Path, bbo_only: bool, features: FeatureColumns) -> io::Result<Self> {
+        use ColumnKind::*;
+        let all = row_columns(bbo_only, &features);
+        let selection = selected_columns(&all, features.selection())?.unwrap_or_else(|| (0..all.len()).collect());
+        let fields: Vec<(String, ColumnKind)> = selection.iter().map(|&index| all[index].clone()).collect();
+
+        let schema: Vec<String> = fields.iter()
+            .map(|(name, kind)| match kind {
+                Integer => format!("REQUIRED INT64 {};", name),
+                Double => format!("REQUIRED DOUBLE {};", name),
+                Text => format!("REQUIRED BYTE_ARRAY {} (UTF8);", name),
+            })
+            .collect();
+        let schema = parse_message_type(&format!("message orderbook {{ {} }}", schema.join(" "))).map_err(parquet_error)?;
+        let properties = Arc::new(WriterProperties::builder().build());
+        let writer = SerializedFileWriter::new(File::create(path)?, Arc::new(schema), properties).map_err(parquet_error)?;
+
+        let columns = fields.iter()
+            .map(|(_, kind)| match kind {
+                Integer => ColumnData::Int64(Vec::with_capacity(PARQUET_ROW_GROUP_SIZE)),
+                Double => ColumnData::Double(Vec::with_capacity(PARQUET_ROW_GROUP_SIZE)),
+                Text => ColumnData::Text(Vec::with_capacity(PARQUET_ROW_GROUP_SIZE)),
+            })
+            .collect();
+
+        Ok(ParquetSink { writer: Some(writer), columns, selection, bbo_only, features, rows: 0 })
+    }
+
+    fn write_row_group(&mut self) -> io::Result<()> {
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let mut row_group = writer.next_row_group().map_err(parquet_error)?;
+        for data in &mut self.columns {
+            let Some(mut column) = row_group.next_column().map_err(parquet_error)? else {
+                break;
+            };
+            match data {
+                ColumnData::Int64(values) => {
+                    column.typed::<Int64Type>().write_batch(values, None, None).map_err(parquet_error)?;
+                    values.clear();
+                }
+                ColumnData::Double(values) => {
+                    column.typed::<DoubleType>().write_batch(values, None, None).map_err(parquet_error)?;
+                    values.clear();
+                }
+                ColumnData::Text(values) => {
+                    column.typed::<ByteArrayType>().write_batch(values, None, None).map_err(parquet_error)?;
+                    values.clear();
+                }
+            }
+            column.close().map_err(parquet_error)?;
+        }
+        row_group.close().map_err(parquet_error)?;
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+impl OutputSink for ParquetSink {
+    fn write_snapshot(&mut self, _symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let prices = self.features.prices();
+        let price = |price: u32| match prices {
+            PriceFormat::Decimal => FeatureValue::Price(prices.value(price)),
+            format => FeatureValue::Int(format.integer(price) as i64),
+        };
+
+        // The full row after the timestamp, which is the only column that isn't a number or a state name
+        let mut values = Vec::with_capacity(4 * MAX_BOOK_DEPTH + 2);
+        if self.bbo_only {
+            let (bid, ask, mid, spread) = snapshot.bbo();
+            let spread = match prices {
+                PriceFormat::Decimal => FeatureValue::Price(spread as f64 / 10000.0),
+                format => FeatureValue::Int(format.spread(spread, bid.price)),
+            };
+            values.extend([price(bid.price), FeatureValue::Int(bid.total_volume as i64),
+                           price(ask.price), FeatureValue::Int(ask.total_volume as i64),
+                           FeatureValue::Decimal(mid), spread]);
+        } else {
+            for i in 0..MAX_BOOK_DEPTH {
+                let bid = snapshot.bid_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+                let ask = snapshot.ask_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+                values.extend([price(bid.0), FeatureValue::Int(bid.1 as i64), price(ask.0), FeatureValue::Int(ask.1 as i64)]);
+            }
+            values.extend([FeatureValue::Decimal(snapshot.mid_price), FeatureValue::Decimal(snapshot.imbalance)]);
+        }
+        values.extend(self.features.values(snapshot));
+
+        let timestamps = self.features.timestamps();
+        for (data, &index) in self.columns.iter_mut().zip(&self.selection) {
+            if index == 0 {
+                match data {
+                    ColumnData::Int64(column) => column.push(timestamps.integer(snapshot.timestamp) as i64),
+                    ColumnData::Double(column) => column.push(timestamps.seconds(snapshot.timestamp)),
+                    ColumnData::Text(column) => column.push(timestamps.format(snapshot.timestamp).as_str().into()),
+                }
+                continue;
+            }
+            match (data, values[index - 1]) {
+                (ColumnData::Int64(column), FeatureValue::Int(value)) => column.push(value),
+                (ColumnData::Int64(column), value) => column.push(value.as_f64() as i64),
+                (ColumnData::Double(column), value) => column.push(value.as_f64()),
+                (ColumnData::Text(column), value) => column.push(value.to_string().as_str().into()),
+            }
+        }
+
+        self.rows += 1;
+        if self.rows >= PARQUET_ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    // Row groups are written when full; a partial one would fragment the file
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.write_row_group()?;
+        if let Some(writer) = self.writer.take() {
+            writer.close().map_err(parquet_error)?;
+        }
+        Ok(())
+    }
+}
+
+// Trades only, one CSV row per execution or non-displayed trade
+pub struct TradesCsvSink {
+    writer: BufWriter<File>,
+}
+
+impl TradesCsvSink {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"timestamp,price,shares,side,match_number,message_type,aggressor,sign_method\n")?;
+        Ok(TradesCsvSink { writer })
+    }
+}
+
+impl OutputSink for TradesCsvSink {
+    fn write_snapshot(&mut self, _symbol: &str, _snapshot: &BookSnapshot) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_trade(&mut self, _symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        writeln!(self.writer, "{},{}.{:04},{},{},{},{},{},{}", trade.timestamp,
+                 trade.price / 10000, trade.price % 10000, trade.shares,
+                 if trade.side == Side::Buy { "B" } else { "S" },
+                 trade.match_number, trade.message_type as char,
+                 trade.aggressor_code(), trade.sign_method.name())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Discards everything, for measuring parse throughput without output cost
+pub struct NullSink;
+
+impl OutputSink for NullSink {
+    fn write_snapshot(&mut self, _symbol: &str, _snapshot: &BookSnapshot) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
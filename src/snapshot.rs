@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::batch;
+use crate::cli::SnapshotArgs;
+use crate::file_io;
+use crate::orderbook::{Order, OrderBook, OrderBookOptions};
+use crate::parser::{self, ParserOptions};
+use crate::partition::{self, Partitioning};
+use crate::utils::pad_stock_symbol;
+
+// Format of the order-level snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SnapshotFormat {
+    // One row per resting order
+    #[default]
+    Csv,
+    // One JSON object per symbol per line, with bids and asks arrays
+    Json,
+}
+
+fn write_csv(out: &mut impl Write, book: &OrderBook) -> io::Result<()> {
+    let (bids, asks) = book.resting_orders();
+    for (side, orders) in [("B", bids), ("S", asks)] {
+        // Position counts from the front of the queue at each price level
+        let mut level_price = None;
+        let mut position = 0;
+        for order in orders {
+            if level_price != Some(order.price) {
+                level_price = Some(order.price);
+                position = 0;
+            }
+            position += 1;
+            writeln!(out, "{},{},{}.{:04},{},{},{},{}", book.symbol_name(), side,
+                     order.price / 10000, order.price % 10000, order.shares, position,
+                     order.ref_number, order.timestamp)?;
+        }
+    }
+    Ok(())
+}
+
+fn orders_json(orders: &[&Order]) -> String {
+    let orders: Vec<String> = orders.iter()
+        .map(|o| format!("{{\"price\":{:.4}, \"shares\":{}, \"order_ref\":{}, \"timestamp\":\"{}\"}}",
+                         o.price as f64 / 10000.0, o.shares, o.ref_number, o.timestamp))
+        .collect();
+    format!("[{}]", orders.join(", "))
+}
+
+fn write_json(out: &mut impl Write, book: &OrderBook, at: u64) -> io::Result<()> {
+    let (bids, asks) = book.resting_orders();
+    writeln!(out, "{{\"symbol\":\"{}\", \"timestamp\":\"{}\", \"bids\":{}, \"asks\":{}}}",
+             book.symbol_name(), at, orders_json(&bids), orders_json(&asks))
+}
+
+fn write_file(path: &Path, books: &[OrderBook], format: SnapshotFormat, at: u64) -> io::Result<()> {
+    partition::create_parent(path)?;
+    let mut out = BufWriter::new(File::create(path)?);
+    if format == SnapshotFormat::Csv {
+        out.write_all(b"symbol,side,price,shares,position,order_ref,timestamp\n")?;
+    }
+    for book in books {
+        match format {
+            SnapshotFormat::Csv => write_csv(&mut out, book)?,
+            SnapshotFormat::Json => write_json(&mut out, book, at)?,
+        }
+    }
+    out.flush()?;
+    let symbols: Vec<&str> = books.iter().map(OrderBook::symbol_name).collect();
+    tracing::info!("Wrote snapshot at {} of {} to {}", at, symbols.join(","), path.display());
+    Ok(())
+}
+
+// Process the file up to the snapshot time and write the books of the requested symbols, to one file or with
+// {symbol} in the output path to a file per symbol
+pub fn run(args: SnapshotArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = file_io::load_input(args.input.file()?, args.input.encap)?;
+
+    // The books are only read at the end, so nothing is written while parsing
+    let mut books: Vec<OrderBook> = args.symbols.iter()
+        .map(|symbol| OrderBook::new(pad_stock_symbol(symbol), OrderBookOptions::default()))
+        .collect();
+    let options = ParserOptions { feed: args.input.feed, strict: args.input.strict, events: args.input.events_output.clone(), stop_at: Some(args.at), ..Default::default() };
+    parser::process_itch_file(&input, &mut books, &options)?;
+
+    let date = batch::day_name(args.input.file()?);
+    let path = |symbol: &str| Partitioning { symbol: symbol.to_string(), date: date.clone(), ..Default::default() }.fill(&args.output_file);
+    if partition::routes_symbols(&args.output_file) {
+        for book in &books {
+            write_file(&path(book.symbol_name()), std::slice::from_ref(book), args.format, args.at)?;
+        }
+    } else {
+        write_file(&path(""), &books, args.format, args.at)?;
+    }
+    Ok(())
+}
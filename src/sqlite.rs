@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::features::{ColumnKind, FeatureColumns, FeatureValue};
+use crate::orderbook::{BookSnapshot, MarketEvent, Side, TradeRecord};
+use crate::sink::OutputSink;
+
+// Rows per transaction; SQLite inserts are only fast when batched
+const TRANSACTION_SIZE: usize = 10_000;
+
+const INSERT_TRADE: &str = "INSERT INTO trades (symbol, timestamp, price, shares, side, match_number, message_type, aggressor, sign_method) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+const INSERT_EVENT: &str = "INSERT INTO events (timestamp, message_type, symbol, event) VALUES (?1, ?2, ?3, ?4)";
+
+// Writes snapshots, trades and market events into a single SQLite database
+pub struct SqliteSink {
+    conn: Connection,
+    insert_snapshot: String,
+    bbo_only: bool,
+    depth: usize,
+    features: FeatureColumns,
+    // Rows written in the open transaction
+    pending: usize,
+}
+
+fn sqlite_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("SQLite error: {}", e))
+}
+
+impl SqliteSink {
+    // Create a fresh database, replacing any existing file like the CSV output does
+    pub fn create(path: &Path, bbo_only: bool, depth: usize, features: FeatureColumns) -> io::Result<Self> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let conn = Connection::open(path).map_err(sqlite_error)?;
+
+        // The database is written once from scratch, so durability can be traded for speed
+        conn.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;").map_err(sqlite_error)?;
+
+        let mut columns = vec!["symbol TEXT NOT NULL".to_string(), "timestamp INTEGER NOT NULL".to_string()];
+        if bbo_only {
+            for column in ["bid REAL", "bid_size INTEGER", "ask REAL", "ask_size INTEGER", "mid REAL", "spread REAL"] {
+                columns.push(column.to_string());
+            }
+        } else {
+            for level in 1..=depth {
+                columns.push(format!("bid_price_{} REAL, bid_vol_{} INTEGER, ask_price_{} REAL, ask_vol_{} INTEGER", level, level, level, level));
+            }
+            columns.push("mid_price REAL, orderbook_imbalance REAL".to_string());
+        }
+        for (name, kind) in features.names() {
+            columns.push(format!("{} {}", name, match kind {
+                ColumnKind::Integer => "INTEGER",
+                ColumnKind::Double => "REAL",
+                ColumnKind::Text => "TEXT",
+            }));
+        }
+        let value_count = if bbo_only { 8 } else { 4 + 4 * depth } + features.names().len();
+        let placeholders: Vec<String> = (1..=value_count).map(|i| format!("?{}", i)).collect();
+        let insert_snapshot = format!("INSERT INTO snapshots VALUES ({})", placeholders.join(", "));
+
+        conn.execute_batch(&format!(
+            "CREATE TABLE snapshots ({});
+             CREATE TABLE trades (symbol TEXT NOT NULL, timestamp INTEGER NOT NULL, price REAL, shares INTEGER, side TEXT, match_number INTEGER, message_type TEXT, aggressor TEXT, sign_method TEXT);
+             CREATE TABLE events (timestamp INTEGER NOT NULL, message_type TEXT NOT NULL, symbol TEXT, event TEXT);
+             BEGIN;",
+            columns.join(", "))).map_err(sqlite_error)?;
+
+        Ok(SqliteSink { conn, insert_snapshot, bbo_only, depth, features, pending: 0 })
+    }
+
+    fn row_written(&mut self) -> io::Result<()> {
+        self.pending += 1;
+        if self.pending >= TRANSACTION_SIZE {
+            self.conn.execute_batch("COMMIT; BEGIN;").map_err(sqlite_error)?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for SqliteSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let mut values: Vec<rusqlite::types::Value> = vec![symbol.to_string().into(), (snapshot.timestamp as i64).into()];
+        if self.bbo_only {
+            let (bid, ask, mid, spread) = snapshot.bbo();
+            values.push((bid.price as f64 / 10000.0).into());
+            values.push((bid.total_volume as i64).into());
+            values.push((ask.price as f64 / 10000.0).into());
+            values.push((ask.total_volume as i64).into());
+            values.push(mid.into());
+            values.push((spread as f64 / 10000.0).into());
+        } else {
+            for i in 0..self.depth {
+                let bid = snapshot.bid_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+                let ask = snapshot.ask_levels.get(i).map_or((0, 0), |l| (l.price, l.total_volume));
+                values.push((bid.0 as f64 / 10000.0).into());
+                values.push((bid.1 as i64).into());
+                values.push((ask.0 as f64 / 10000.0).into());
+                values.push((ask.1 as i64).into());
+            }
+            values.push(snapshot.mid_price.into());
+            values.push(snapshot.imbalance.into());
+        }
+        for value in self.features.values(snapshot) {
+            values.push(match value {
+                FeatureValue::Int(value) => value.into(),
+                FeatureValue::Text(text) => text.to_string().into(),
+                value => value.as_f64().into(),
+            });
+        }
+
+        self.conn.prepare_cached(&self.insert_snapshot)
+            .and_then(|mut stmt| stmt.execute(rusqlite::params_from_iter(values)))
+            .map_err(sqlite_error)?;
+        self.row_written()
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.conn.prepare_cached(INSERT_TRADE)
+            .and_then(|mut stmt| stmt.execute(params![
+                symbol,
+                trade.timestamp as i64,
+                trade.price as f64 / 10000.0,
+                trade.shares,
+                if trade.side == Side::Buy { "B" } else { "S" },
+                trade.match_number as i64,
+                (trade.message_type as char).to_string(),
+                trade.aggressor.map(|side| if side == Side::Buy { "B" } else { "S" }),
+                trade.sign_method.name(),
+            ]))
+            .map_err(sqlite_error)?;
+        self.row_written()
+    }
+
+    fn write_event(&mut self, event: &MarketEvent) -> io::Result<()> {
+        self.conn.prepare_cached(INSERT_EVENT)
+            .and_then(|mut stmt| stmt.execute(params![
+                event.timestamp as i64,
+                (event.message_type as char).to_string(),
+                event.symbol.as_deref(),
+                event.description,
+            ]))
+            .map_err(sqlite_error)?;
+        self.row_written()
+    }
+
+    // Rows become visible when their transaction commits
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Commit the last batch and build the indexes once all rows are in
+    fn finalize(&mut self) -> io::Result<()> {
+        self.conn.execute_batch(
+            "COMMIT;
+             CREATE INDEX snapshots_timestamp_idx ON snapshots (timestamp);
+             CREATE INDEX trades_timestamp_idx ON trades (timestamp);
+             CREATE INDEX events_timestamp_idx ON events (timestamp);").map_err(sqlite_error)?;
+        self.pending = 0;
+        Ok(())
+    }
+}
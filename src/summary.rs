@@ -0,0 +1,277 @@
+// Machine-readable summary of a build-book run for --summary-output, so a pipeline can check what a run read
+// and wrote without scraping its log: the input's size and SHA-256, the messages by type, the rows each sink
+// was handed, the book anomalies, the wall time and throughput, and how the run ended. It is written when a
+// run fails too, with what was known by then
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::bars::Bar;
+use crate::integrity::ANOMALY_KINDS;
+use crate::orderbook::{BookSnapshot, Levels, MarketEvent, OrderBook, OrderMessage, TradeRecord};
+use crate::sink::OutputSink;
+
+// Bytes read at a time while hashing the input
+const HASH_CHUNK: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    // Read to the end of the input, or to --max-messages
+    Completed,
+    // Stopped early by SIGINT or SIGTERM
+    Interrupted,
+    Failed,
+}
+
+impl RunStatus {
+    pub fn name(self) -> &'static str {
+        match self {
+            RunStatus::Completed => "completed",
+            RunStatus::Interrupted => "interrupted",
+            RunStatus::Failed => "failed",
+        }
+    }
+
+    // The command line tool's exit status for a run that ended this way
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunStatus::Completed => 0,
+            RunStatus::Interrupted => 130,
+            RunStatus::Failed => 1,
+        }
+    }
+}
+
+// What a sink was handed, counted by CountingSink
+#[derive(Debug, Default)]
+pub struct SinkTally {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    snapshots: AtomicU64,
+    trades: AtomicU64,
+    events: AtomicU64,
+}
+
+impl SinkTally {
+    // Snapshot rows, trades and events written without error
+    pub fn counts(&self) -> (u64, u64, u64) {
+        (self.snapshots.load(Ordering::Relaxed), self.trades.load(Ordering::Relaxed), self.events.load(Ordering::Relaxed))
+    }
+}
+
+// Passes everything on to a sink, counting what it accepted
+pub struct CountingSink {
+    inner: Box<dyn OutputSink>,
+    tally: Arc<SinkTally>,
+}
+
+impl CountingSink {
+    pub fn new(inner: Box<dyn OutputSink>, name: &str, path: Option<&Path>) -> (Self, Arc<SinkTally>) {
+        let tally = Arc::new(SinkTally { name: name.to_string(), path: path.map(Path::to_path_buf), ..Default::default() });
+        (CountingSink { inner, tally: tally.clone() }, tally)
+    }
+}
+
+impl OutputSink for CountingSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        self.inner.write_snapshot(symbol, snapshot)?;
+        self.tally.snapshots.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.inner.write_trade(symbol, trade)?;
+        self.tally.trades.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &MarketEvent) -> io::Result<()> {
+        self.inner.write_event(event)?;
+        self.tally.events.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn write_order(&mut self, symbol: &str, message: &OrderMessage, bids: &Levels, asks: &Levels) -> io::Result<()> {
+        self.inner.write_order(symbol, message, bids, asks)
+    }
+
+    fn wants_orders(&self) -> bool {
+        self.inner.wants_orders()
+    }
+
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> io::Result<()> {
+        self.inner.write_bar(symbol, bar)
+    }
+
+    fn update_counts(&mut self, messages: u64, book_updates: u64, skipped_updates: u64) {
+        self.inner.update_counts(messages, book_updates, skipped_updates);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        self.inner.finalize()
+    }
+}
+
+// Hex SHA-256 of a file's bytes as stored, before any decompression or unwrapping
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; HASH_CHUNK];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Filled in as a run goes; the counts stay empty if it fails before the book is done
+#[derive(Debug)]
+pub struct RunSummary {
+    pub input: PathBuf,
+    pub input_bytes: Option<u64>,
+    pub sha256: Option<String>,
+    pub symbols: Vec<String>,
+    pub start_offset: u64,
+    pub end_offset: Option<u64>,
+    pub messages: u64,
+    pub message_counts: Vec<(u8, u64)>,
+    pub book_updates: u64,
+    pub skipped_updates: u64,
+    pub sinks: Vec<Arc<SinkTally>>,
+    pub anomalies: Option<[u64; ANOMALY_KINDS.len()]>,
+    pub status: RunStatus,
+    pub error: Option<String>,
+    started: Instant,
+    seconds: f64,
+}
+
+impl RunSummary {
+    // Starts the wall clock
+    pub fn new(input: &Path, symbols: Vec<String>) -> Self {
+        RunSummary {
+            input: input.to_path_buf(),
+            input_bytes: None,
+            sha256: None,
+            symbols,
+            start_offset: 0,
+            end_offset: None,
+            messages: 0,
+            message_counts: Vec::new(),
+            book_updates: 0,
+            skipped_updates: 0,
+            sinks: Vec::new(),
+            anomalies: None,
+            status: RunStatus::Completed,
+            error: None,
+            started: Instant::now(),
+            seconds: 0.0,
+        }
+    }
+
+    // Wrap a sink so the rows it is handed are counted in the summary
+    pub fn count(&mut self, sink: Box<dyn OutputSink>, name: &str, path: Option<&Path>) -> Box<dyn OutputSink> {
+        let (sink, tally) = CountingSink::new(sink, name, path);
+        self.sinks.push(tally);
+        Box::new(sink)
+    }
+
+    // Take the counts of the finalized books of one parse; they all saw the same messages
+    pub fn record_books(&mut self, books: &[OrderBook]) {
+        let Some(first) = books.first() else {
+            return;
+        };
+        self.messages = first.counts().0;
+        self.message_counts = first.message_counts();
+        self.end_offset = first.end_offset();
+        self.book_updates = books.iter().map(|book| book.counts().1).sum();
+        self.skipped_updates = books.iter().map(OrderBook::skipped_count).sum();
+        self.anomalies = first.anomaly_counts().map(|_| {
+            let mut totals = [0; ANOMALY_KINDS.len()];
+            for counts in books.iter().filter_map(OrderBook::anomaly_counts) {
+                for (total, count) in totals.iter_mut().zip(counts) {
+                    *total += count;
+                }
+            }
+            totals
+        });
+    }
+
+    // Stop the wall clock and settle how the run ended; an error wins over an interruption
+    pub fn finish(&mut self, error: Option<String>, interrupted: bool) {
+        self.seconds = self.started.elapsed().as_secs_f64();
+        self.status = match (&error, interrupted) {
+            (Some(_), _) => RunStatus::Failed,
+            (None, true) => RunStatus::Interrupted,
+            (None, false) => RunStatus::Completed,
+        };
+        self.error = error;
+    }
+
+    pub fn to_json(&self) -> Value {
+        let by_type: serde_json::Map<String, Value> = self.message_counts.iter()
+            .map(|&(message_type, count)| ((message_type as char).to_string(), count.into()))
+            .collect();
+        let outputs: Vec<Value> = self.sinks.iter().map(|tally| {
+            let (rows, trades, events) = tally.counts();
+            json!({
+                "sink": tally.name,
+                "path": tally.path.as_ref().map(|path| path.display().to_string()),
+                "rows": rows,
+                "trades": trades,
+                "events": events,
+            })
+        }).collect();
+        let anomalies = self.anomalies.map(|counts| {
+            let by_kind: serde_json::Map<String, Value> = ANOMALY_KINDS.iter().zip(counts)
+                .map(|(kind, count)| (kind.name().to_string(), count.into()))
+                .collect();
+            json!({ "total": counts.iter().sum::<u64>(), "by_kind": by_kind })
+        });
+        let bytes = self.end_offset.map_or(0, |end| end.saturating_sub(self.start_offset));
+        let seconds = self.seconds.max(f64::EPSILON);
+        json!({
+            "status": self.status.name(),
+            "exit_code": self.status.exit_code(),
+            "error": self.error,
+            "input": {
+                "path": self.input.display().to_string(),
+                "bytes": self.input_bytes,
+                "sha256": self.sha256,
+            },
+            "symbols": self.symbols,
+            "start_offset": self.start_offset,
+            "end_offset": self.end_offset,
+            "messages": { "total": self.messages, "by_type": by_type },
+            "book_updates": self.book_updates,
+            "skipped_updates": self.skipped_updates,
+            "outputs": outputs,
+            "anomalies": anomalies,
+            "wall_seconds": self.seconds,
+            "throughput": {
+                "messages_per_second": self.messages as f64 / seconds,
+                "mb_per_second": bytes as f64 / (1024.0 * 1024.0) / seconds,
+            },
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut out, &self.to_json())?;
+        writeln!(out)?;
+        out.flush()
+    }
+}
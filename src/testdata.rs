@@ -0,0 +1,326 @@
+// Synthetic ITCH 5.0 feeds for benchmarks and tests, so the parser and book can be measured
+// without a capture file. The flow is random but valid: every execute, cancel, delete and
+// replace refers to a live order with enough shares
+
+use crate::message_types::*;
+
+// Relative weights of the order messages in the feed
+#[derive(Debug, Clone, Copy)]
+pub struct MessageMix {
+    pub add: u32,
+    pub add_with_mpid: u32,
+    pub execute: u32,
+    pub execute_with_price: u32,
+    pub cancel: u32,
+    pub delete: u32,
+    pub replace: u32,
+    pub trade: u32,
+}
+
+impl Default for MessageMix {
+    // Roughly the mix of a real day: mostly adds and deletes, few executions
+    fn default() -> Self {
+        MessageMix { add: 40, add_with_mpid: 2, execute: 3, execute_with_price: 1, cancel: 2, delete: 37, replace: 14, trade: 1 }
+    }
+}
+
+// What to generate
+#[derive(Debug, Clone)]
+pub struct SyntheticFeed {
+    pub symbols: Vec<String>,
+    // Order messages after the directory
+    pub messages: usize,
+    pub mix: MessageMix,
+    // Average order messages per second of market time
+    pub rate: u64,
+    pub seed: u64,
+}
+
+impl Default for SyntheticFeed {
+    fn default() -> Self {
+        SyntheticFeed {
+            symbols: ["AAPL", "MSFT", "AMZN", "GOOG", "META", "NVDA", "TSLA", "INTC"].map(String::from).to_vec(),
+            messages: 1_000_000,
+            mix: MessageMix::default(),
+            rate: 100_000,
+            seed: 1,
+        }
+    }
+}
+
+// splitmix64, good enough for test data and free of dependencies
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, n)
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Add,
+    AddWithMpid,
+    Execute,
+    ExecuteWithPrice,
+    Cancel,
+    Delete,
+    Replace,
+    Trade,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LiveOrder {
+    order_ref: u64,
+    locate: u16,
+    buy: bool,
+    shares: u32,
+    price: u32,
+}
+
+fn stock(symbol: &str) -> [u8; 8] {
+    let mut stock = [b' '; 8];
+    let len = symbol.len().min(8);
+    stock[..len].copy_from_slice(&symbol.as_bytes()[..len]);
+    stock
+}
+
+// Appends the encoded messages, numbering them with the tracking number
+struct Writer {
+    out: Vec<u8>,
+    tracking: u16,
+}
+
+impl Writer {
+    fn tracking_number(&mut self) -> u16 {
+        let tracking = self.tracking;
+        self.tracking = tracking.wrapping_add(1);
+        tracking
+    }
+
+    fn push(&mut self, message: impl WireMessage) {
+        self.out.extend_from_slice(&message.encode());
+    }
+
+    fn system_event(&mut self, timestamp: u64, event_code: u8) {
+        let tracking_number = self.tracking_number();
+        self.push(SystemEventMessage { stock_locate: 0, tracking_number, timestamp, event_code });
+    }
+
+    fn stock_directory(&mut self, timestamp: u64, stock_locate: u16, symbol: &str) {
+        let tracking_number = self.tracking_number();
+        self.push(StockDirectoryMessage {
+            stock_locate,
+            tracking_number,
+            timestamp,
+            stock: stock(symbol),
+            market_category: b'Q',
+            financial_status_indicator: b'N',
+            round_lot_size: 100,
+            round_lots_only: b'N',
+            issue_classification: b'C',
+            issue_sub_type: *b"  ",
+            authenticity: b'P',
+            short_sale_threshold_indicator: b'N',
+            ipo_flag: b'N',
+            luld_reference_price_tier: b'1',
+            etp_flag: b'N',
+            etp_leverage_factor: 0,
+            inverse_indicator: b'N',
+        });
+    }
+
+    fn add(&mut self, timestamp: u64, order: &LiveOrder, symbol: &str, mpid: Option<&[u8; 4]>) {
+        let tracking_number = self.tracking_number();
+        let add = AddOrderMessage {
+            stock_locate: order.locate,
+            tracking_number,
+            timestamp,
+            order_ref_number: order.order_ref,
+            buy_sell_indicator: if order.buy { b'B' } else { b'S' },
+            shares: order.shares,
+            stock: stock(symbol),
+            price: order.price,
+        };
+        match mpid {
+            Some(&attribution) => self.push(AddOrderWithMpidMessage {
+                stock_locate: add.stock_locate,
+                tracking_number,
+                timestamp,
+                order_ref_number: add.order_ref_number,
+                buy_sell_indicator: add.buy_sell_indicator,
+                shares: add.shares,
+                stock: add.stock,
+                price: add.price,
+                attribution,
+            }),
+            None => self.push(add),
+        }
+    }
+
+    fn execute(&mut self, timestamp: u64, order: &LiveOrder, executed_shares: u32, match_number: u64, price: Option<u32>) {
+        let tracking_number = self.tracking_number();
+        let (stock_locate, order_ref_number) = (order.locate, order.order_ref);
+        match price {
+            Some(execution_price) => self.push(OrderExecutedWithPriceMessage {
+                stock_locate, tracking_number, timestamp, order_ref_number, executed_shares, match_number, printable: b'Y', execution_price,
+            }),
+            None => self.push(OrderExecutedMessage { stock_locate, tracking_number, timestamp, order_ref_number, executed_shares, match_number }),
+        }
+    }
+
+    fn cancel(&mut self, timestamp: u64, order: &LiveOrder, cancelled_shares: u32) {
+        let tracking_number = self.tracking_number();
+        self.push(OrderCancelMessage {
+            stock_locate: order.locate, tracking_number, timestamp, order_ref_number: order.order_ref, cancelled_shares,
+        });
+    }
+
+    fn delete(&mut self, timestamp: u64, order: &LiveOrder) {
+        let tracking_number = self.tracking_number();
+        self.push(OrderDeleteMessage { stock_locate: order.locate, tracking_number, timestamp, order_ref_number: order.order_ref });
+    }
+
+    fn replace(&mut self, timestamp: u64, order: &LiveOrder, new: &LiveOrder) {
+        let tracking_number = self.tracking_number();
+        self.push(OrderReplaceMessage {
+            stock_locate: order.locate,
+            tracking_number,
+            timestamp,
+            original_order_ref_number: order.order_ref,
+            new_order_ref_number: new.order_ref,
+            shares: new.shares,
+            price: new.price,
+        });
+    }
+
+    fn trade(&mut self, timestamp: u64, stock_locate: u16, symbol: &str, shares: u32, price: u32, match_number: u64) {
+        let tracking_number = self.tracking_number();
+        self.push(TradeMessage {
+            stock_locate, tracking_number, timestamp, order_ref_number: 0, buy_sell_indicator: b'B', shares, stock: stock(symbol),
+            price, match_number,
+        });
+    }
+}
+
+// Market open, 09:30
+const OPEN: u64 = 34_200_000_000_000;
+const MPIDS: [&[u8; 4]; 3] = [b"GSCO", b"MSCO", b"UBSS"];
+
+impl SyntheticFeed {
+    // The whole feed as one ITCH byte stream: system events, the directory, then the order flow
+    pub fn generate(&self) -> Vec<u8> {
+        let mut rng = Rng(self.seed);
+        let mut w = Writer { out: Vec::with_capacity(self.messages * 32), tracking: 0 };
+        let mix = self.mix;
+        let weights = [
+            (Kind::Add, mix.add),
+            (Kind::AddWithMpid, mix.add_with_mpid),
+            (Kind::Execute, mix.execute),
+            (Kind::ExecuteWithPrice, mix.execute_with_price),
+            (Kind::Cancel, mix.cancel),
+            (Kind::Delete, mix.delete),
+            (Kind::Replace, mix.replace),
+            (Kind::Trade, mix.trade),
+        ];
+        let total_weight: u64 = weights.iter().map(|&(_, weight)| weight as u64).sum::<u64>().max(1);
+        // Twice the average gap, so the mean rate comes out right
+        let max_gap = (2_000_000_000 / self.rate.max(1)).max(1);
+
+        let mut timestamp = OPEN - 3_600_000_000_000;
+        w.system_event(timestamp, b'O');
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            w.stock_directory(timestamp, i as u16 + 1, symbol);
+        }
+        w.system_event(timestamp, b'S');
+        timestamp = OPEN;
+        w.system_event(timestamp, b'Q');
+
+        let mut live: Vec<LiveOrder> = Vec::new();
+        let mut next_ref = 1;
+        let mut next_match = 1;
+        for _ in 0..self.messages {
+            timestamp += 1 + rng.below(max_gap);
+            let mut pick = rng.below(total_weight);
+            let mut kind = weights.iter().find(|&&(_, weight)| {
+                let hit = pick < weight as u64;
+                pick = pick.saturating_sub(weight as u64);
+                hit
+            }).map_or(Kind::Add, |&(kind, _)| kind);
+            // Anything that needs a live order adds one when there is none
+            if live.is_empty() && kind != Kind::AddWithMpid && kind != Kind::Trade {
+                kind = Kind::Add;
+            }
+
+            let symbol_index = rng.below(self.symbols.len().max(1) as u64) as usize;
+            let symbol = self.symbols.get(symbol_index).map_or("TEST", String::as_str);
+            let locate = symbol_index as u16 + 1;
+            let mid = 1_000_000 + symbol_index as u32 * 100_000;
+            let slot = rng.below(live.len().max(1) as u64) as usize;
+            match kind {
+                Kind::Add | Kind::AddWithMpid => {
+                    let buy = rng.below(2) == 0;
+                    let ticks = 1 + rng.below(50) as u32;
+                    let order = LiveOrder {
+                        order_ref: next_ref,
+                        locate,
+                        buy,
+                        shares: 100 * (1 + rng.below(10) as u32),
+                        price: if buy { mid - ticks * 100 } else { mid + ticks * 100 },
+                    };
+                    next_ref += 1;
+                    let mpid = (kind == Kind::AddWithMpid).then(|| MPIDS[rng.below(MPIDS.len() as u64) as usize]);
+                    w.add(timestamp, &order, symbol, mpid);
+                    live.push(order);
+                }
+                Kind::Execute | Kind::ExecuteWithPrice | Kind::Cancel => {
+                    let order = live[slot];
+                    let shares = (100 * (1 + rng.below(5) as u32)).min(order.shares);
+                    match kind {
+                        Kind::Execute => w.execute(timestamp, &order, shares, next_match, None),
+                        Kind::ExecuteWithPrice => w.execute(timestamp, &order, shares, next_match, Some(order.price)),
+                        _ => w.cancel(timestamp, &order, shares),
+                    }
+                    if kind != Kind::Cancel {
+                        next_match += 1;
+                    }
+                    if shares == order.shares {
+                        live.swap_remove(slot);
+                    } else {
+                        live[slot].shares -= shares;
+                    }
+                }
+                Kind::Delete => {
+                    w.delete(timestamp, &live[slot]);
+                    live.swap_remove(slot);
+                }
+                Kind::Replace => {
+                    let order = live[slot];
+                    let step = if order.buy { -100 } else { 100 };
+                    let new = LiveOrder { order_ref: next_ref, price: order.price.saturating_add_signed(step), ..order };
+                    next_ref += 1;
+                    w.replace(timestamp, &order, &new);
+                    live[slot] = new;
+                }
+                Kind::Trade => {
+                    w.trade(timestamp, locate, symbol, 100, mid, next_match);
+                    next_match += 1;
+                }
+            }
+        }
+
+        w.system_event(timestamp + 1, b'M');
+        w.system_event(timestamp + 2, b'E');
+        w.system_event(timestamp + 3, b'C');
+        w.out
+    }
+}
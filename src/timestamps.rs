@@ -0,0 +1,273 @@
+// How the timestamp column of the orderbook output is written. ITCH timestamps are nanoseconds since midnight
+// Eastern time; with a date they can be anchored to the Unix epoch or written as a full ISO 8601 time, and with
+// a time zone each row also gets its UTC time and trading session
+use std::fmt;
+
+use crate::features::ColumnKind;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const NANOS_PER_HOUR: u64 = 3600 * NANOS_PER_SECOND;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// Nasdaq's sessions, in the exchange's local time
+const PRE_MARKET_OPEN: u64 = 4 * NANOS_PER_HOUR;
+const REGULAR_OPEN: u64 = 9 * NANOS_PER_HOUR + NANOS_PER_HOUR / 2;
+const REGULAR_CLOSE: u64 = 16 * NANOS_PER_HOUR;
+const POST_MARKET_CLOSE: u64 = 20 * NANOS_PER_HOUR;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TimestampFormat {
+    // Integer nanoseconds, as in the feed
+    #[default]
+    Ns,
+    // Integer microseconds, truncated
+    Us,
+    // Seconds with 9 decimals
+    Seconds,
+    // HH:MM:SS.fffffffff, or YYYY-MM-DDTHH:MM:SS.fffffffff with the UTC offset when the date is known
+    Iso8601,
+}
+
+// A calendar day, parsed from YYYY-MM-DD
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+}
+
+fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl Date {
+    // Days since 1970-01-01 (Howard Hinnant's days_from_civil)
+    pub fn days_since_epoch(self) -> u64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year } as u64;
+        let era = year / 400;
+        let year_of_era = year - era * 400;
+        let month = self.month as u64;
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as u64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    // 0 for Sunday
+    fn weekday(self) -> u64 {
+        (self.days_since_epoch() + 4) % 7
+    }
+
+    // Day of the month of the month's nth Sunday
+    fn nth_sunday(year: u32, month: u32, n: u32) -> u32 {
+        let first = Date { year, month, day: 1 }.weekday() as u32;
+        1 + (7 - first) % 7 + 7 * (n - 1)
+    }
+
+    // US daylight saving time runs from the second Sunday of March to the first Sunday of November (the rules
+    // since 2007), and the market is closed on both Sundays
+    fn is_us_daylight_saving(self) -> bool {
+        match self.month {
+            4..=10 => true,
+            3 => self.day >= Self::nth_sunday(self.year, 3, 2),
+            11 => self.day < Self::nth_sunday(self.year, 11, 1),
+            _ => false,
+        }
+    }
+
+    // Hours Eastern time is behind UTC on this day
+    pub fn eastern_utc_offset(self) -> u64 {
+        NEW_YORK.hours_behind_utc(self)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+pub fn parse_date(text: &str) -> Result<Date, String> {
+    let invalid = || format!("Invalid date \"{}\", expected YYYY-MM-DD", text);
+    let parts: Vec<&str> = text.split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return Err(invalid());
+    }
+    let [year, month, day] = [year, month, day].map(|part| part.parse::<u32>());
+    let (Ok(year), Ok(month), Ok(day)) = (year, month, day) else {
+        return Err(invalid());
+    };
+    if year < 1970 || !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    Ok(Date { year, month, day })
+}
+
+// Time zone the feed's timestamps are in. Only the US zones and UTC are known, which is enough for the US
+// exchanges' feeds without a time zone database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timezone {
+    pub name: &'static str,
+    // Hours behind UTC in standard time
+    standard_offset: u64,
+    // Whether the zone follows US daylight saving time
+    daylight_saving: bool,
+}
+
+pub const NEW_YORK: Timezone = Timezone { name: "America/New_York", standard_offset: 5, daylight_saving: true };
+
+const TIMEZONES: [Timezone; 6] = [
+    NEW_YORK,
+    Timezone { name: "America/Chicago", standard_offset: 6, daylight_saving: true },
+    Timezone { name: "America/Denver", standard_offset: 7, daylight_saving: true },
+    Timezone { name: "America/Phoenix", standard_offset: 7, daylight_saving: false },
+    Timezone { name: "America/Los_Angeles", standard_offset: 8, daylight_saving: true },
+    Timezone { name: "UTC", standard_offset: 0, daylight_saving: false },
+];
+
+impl Timezone {
+    pub fn hours_behind_utc(self, date: Date) -> u64 {
+        if self.daylight_saving && date.is_us_daylight_saving() { self.standard_offset - 1 } else { self.standard_offset }
+    }
+}
+
+impl Default for Timezone {
+    fn default() -> Self {
+        NEW_YORK
+    }
+}
+
+pub fn parse_timezone(text: &str) -> Result<Timezone, String> {
+    TIMEZONES.iter().find(|zone| zone.name.eq_ignore_ascii_case(text)).copied().ok_or_else(|| {
+        let names: Vec<&str> = TIMEZONES.iter().map(|zone| zone.name).collect();
+        format!("Unknown time zone \"{}\", expected one of {}", text, names.join(", "))
+    })
+}
+
+// Trading session of a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    PreMarket,
+    Regular,
+    PostMarket,
+    // Outside the extended hours
+    Closed,
+}
+
+impl Session {
+    pub fn name(self) -> &'static str {
+        match self {
+            Session::PreMarket => "pre_market",
+            Session::Regular => "regular",
+            Session::PostMarket => "post_market",
+            Session::Closed => "closed",
+        }
+    }
+}
+
+// Start and end of market hours from the feed's system events, which move the regular session off 09:30-16:00
+// on early close days
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MarketHours {
+    pub open: Option<u64>,
+    pub close: Option<u64>,
+}
+
+impl MarketHours {
+    pub fn session(&self, timestamp: u64) -> Session {
+        let open = self.open.unwrap_or(REGULAR_OPEN);
+        let close = self.close.unwrap_or(REGULAR_CLOSE);
+        if !(PRE_MARKET_OPEN..POST_MARKET_CLOSE).contains(&timestamp) {
+            Session::Closed
+        } else if timestamp < open {
+            Session::PreMarket
+        } else if timestamp < close {
+            Session::Regular
+        } else {
+            Session::PostMarket
+        }
+    }
+}
+
+// The format of the timestamp column, the trading day it is anchored to, if any, and the feed's time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Timestamps {
+    pub format: TimestampFormat,
+    // With a date, ns, us and seconds count from the Unix epoch instead of midnight
+    pub date: Option<Date>,
+    // Given (with a date), the rows get utc_timestamp and session columns; New York otherwise
+    pub timezone: Option<Timezone>,
+}
+
+impl Timestamps {
+    // Column type in the typed outputs
+    pub fn kind(&self) -> ColumnKind {
+        match self.format {
+            TimestampFormat::Ns | TimestampFormat::Us => ColumnKind::Integer,
+            TimestampFormat::Seconds => ColumnKind::Double,
+            TimestampFormat::Iso8601 => ColumnKind::Text,
+        }
+    }
+
+    // Whether the rows get the utc_timestamp and session columns
+    pub fn has_sessions(&self) -> bool {
+        self.timezone.is_some() && self.date.is_some()
+    }
+
+    // Nanoseconds since the epoch, when the date is known
+    pub fn utc(&self, timestamp: u64) -> Option<u64> {
+        let date = self.date?;
+        let offset = self.timezone.unwrap_or_default().hours_behind_utc(date);
+        Some(date.days_since_epoch() * SECONDS_PER_DAY * NANOS_PER_SECOND + offset * NANOS_PER_HOUR + timestamp)
+    }
+
+    // Nanoseconds since midnight, or since the epoch when the date is known
+    fn nanos(&self, timestamp: u64) -> u64 {
+        self.utc(timestamp).unwrap_or(timestamp)
+    }
+
+    // The ns or us value
+    pub fn integer(&self, timestamp: u64) -> u64 {
+        match self.format {
+            TimestampFormat::Us => self.nanos(timestamp) / 1000,
+            _ => self.nanos(timestamp),
+        }
+    }
+
+    // Seconds as a double, which keeps about a microsecond of an epoch time
+    pub fn seconds(&self, timestamp: u64) -> f64 {
+        self.nanos(timestamp) as f64 / NANOS_PER_SECOND as f64
+    }
+
+    pub fn format(&self, timestamp: u64) -> String {
+        match self.format {
+            TimestampFormat::Ns | TimestampFormat::Us => self.integer(timestamp).to_string(),
+            TimestampFormat::Seconds => {
+                let nanos = self.nanos(timestamp);
+                format!("{}.{:09}", nanos / NANOS_PER_SECOND, nanos % NANOS_PER_SECOND)
+            }
+            TimestampFormat::Iso8601 => {
+                let seconds = timestamp / NANOS_PER_SECOND;
+                let time = format!("{:02}:{:02}:{:02}.{:09}", seconds / 3600, seconds / 60 % 60, seconds % 60, timestamp % NANOS_PER_SECOND);
+                match self.date {
+                    Some(date) => match self.timezone.unwrap_or_default().hours_behind_utc(date) {
+                        0 => format!("{}T{}+00:00", date, time),
+                        offset => format!("{}T{}-{:02}:00", date, time, offset),
+                    },
+                    None => time,
+                }
+            }
+        }
+    }
+}
@@ -10,4 +10,147 @@ pub fn pad_stock_symbol(symbol: &str) -> [u8; 8] {
     padded[..len].copy_from_slice(&bytes[..len]);
 
     padded
-}
\ No newline at end of file
+}
+
+// Parse a time of day "HH:MM:SS[.fraction]" (or plain nanoseconds) into nanoseconds since midnight
+pub fn parse_time_of_day(text: &str) -> Result<u64, String> {
+    if let Ok(ns) = text.parse::<u64>() {
+        return Ok(ns);
+    }
+
+    let invalid = || format!("Invalid time \"{}\", expected HH:MM:SS[.fraction]", text);
+
+    let (hms, fraction) = match text.split_once('.') {
+        Some((hms, fraction)) => (hms, fraction),
+        None => (text, ""),
+    };
+    let parts: Vec<u64> = hms.split(':')
+        .map(|p| p.parse::<u64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+    if parts.len() != 3 || parts[0] > 23 || parts[1] > 59 || parts[2] > 59 {
+        return Err(invalid());
+    }
+
+    // Pad or truncate the fraction to nanoseconds
+    let mut nanos = 0;
+    if !fraction.is_empty() {
+        if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        nanos = format!("{:0<9}", fraction).parse::<u64>().map_err(|_| invalid())?;
+    }
+
+    Ok(((parts[0] * 60 + parts[1]) * 60 + parts[2]) * 1_000_000_000 + nanos)
+}
+
+// Format nanoseconds since midnight as "HH:MM:SS", with the fraction only when there is one
+pub fn format_time_of_day(ns: u64) -> String {
+    let seconds = ns / 1_000_000_000;
+    let hms = format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match ns % 1_000_000_000 {
+        0 => hms,
+        nanos => format!("{}.{:09}", hms, nanos),
+    }
+}
+
+// Parse a price in dollars such as "150.01" into 1/10000 dollar units, as ITCH prices are
+pub fn parse_price(text: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid price \"{}\", expected dollars with up to 4 decimals", text);
+    let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if integer.is_empty() || fraction.len() > 4 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let integer = integer.parse::<u32>().map_err(|_| invalid())?;
+    let fraction = format!("{:0<4}", fraction).parse::<u32>().map_err(|_| invalid())?;
+    integer.checked_mul(10000).and_then(|price| price.checked_add(fraction)).ok_or_else(invalid)
+}
+
+// Parse a decay rate: a finite, non-negative number
+pub fn parse_decay(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(decay) if decay.is_finite() && decay >= 0.0 => Ok(decay),
+        _ => Err(format!("Invalid decay \"{}\", expected a non-negative number", text)),
+    }
+}
+
+// Parse a replay speed multiplier, a positive number
+pub fn parse_speed(text: &str) -> Result<f64, String> {
+    match text.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        _ => Err(format!("Invalid speed \"{}\", expected a positive number", text)),
+    }
+}
+
+// Parse a duration like "500ms", "30s", "5m" or "1h" (plain numbers are seconds) into nanoseconds
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid duration \"{}\", expected a number with ms, s, m or h", text);
+
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+    let unit_ns = match unit {
+        "ms" => 1_000_000,
+        "" | "s" => 1_000_000_000,
+        "m" => 60 * 1_000_000_000,
+        "h" => 3600 * 1_000_000_000,
+        _ => return Err(invalid()),
+    };
+    match number.checked_mul(unit_ns) {
+        Some(0) | None => Err(invalid()),
+        Some(ns) => Ok(ns),
+    }
+}
+
+// Parse a signed duration like "-15m" or "90s" (0 for none) into nanoseconds
+pub fn parse_offset(text: &str) -> Result<i64, String> {
+    if text == "0" {
+        return Ok(0);
+    }
+    let (sign, duration) = match text.strip_prefix('-') {
+        Some(duration) => (-1, duration),
+        None => (1, text),
+    };
+    let ns = parse_duration(duration)?;
+    i64::try_from(ns).map(|ns| sign * ns).map_err(|_| format!("Invalid offset \"{}\"", text))
+}
+
+// Parse a size like "512MB", "2GB" or "100K" (plain numbers are bytes) into bytes, in powers of 1024
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid size \"{}\", expected a number with K, M or G", text);
+
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+    let unit_bytes: u64 = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(invalid()),
+    };
+    match number.checked_mul(unit_bytes) {
+        Some(0) | None => Err(invalid()),
+        Some(bytes) => Ok(bytes),
+    }
+}
+
+// Format nanoseconds in the largest unit parse_duration accepts that keeps it whole, like "10s" or "1m"
+pub fn format_duration(ns: u64) -> String {
+    for (unit, unit_ns) in [("h", 3600 * 1_000_000_000), ("m", 60 * 1_000_000_000), ("s", 1_000_000_000), ("ms", 1_000_000)] {
+        if ns.is_multiple_of(unit_ns) {
+            return format!("{}{}", ns / unit_ns, unit);
+        }
+    }
+    format!("{}ns", ns)
+}
+
+// Format a time span for people, like "850 ns", "12.3 µs" or "1.25 s"
+pub fn format_nanos(nanos: u64) -> String {
+    match nanos {
+        0..1_000 => format!("{} ns", nanos),
+        1_000..1_000_000 => format!("{:.1} µs", nanos as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.1} ms", nanos as f64 / 1e6),
+        _ => format!("{:.2} s", nanos as f64 / 1e9),
+    }
+}
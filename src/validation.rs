@@ -0,0 +1,105 @@
+use std::io;
+
+use crate::message_types::{FeedVariant, MessageType};
+
+// Problems reported in full; the rest are only counted
+const MAX_EXAMPLES: usize = 20;
+
+// Checks every message in --strict mode and reports what a normal run would skip or mis-parse
+pub struct StrictValidator {
+    feed: FeedVariant,
+    supported: [bool; 256],
+    last_timestamp: u64,
+    unknown_types: u64,
+    unsupported_types: u64,
+    bad_lengths: u64,
+    out_of_order: u64,
+    // Bytes skipped over to resynchronize after a corrupt header
+    skipped_bytes: u64,
+    // Bytes at the end of the file that don't form a complete message
+    trailing_bytes: usize,
+    examples: Vec<String>,
+}
+
+impl StrictValidator {
+    pub fn new(feed: FeedVariant) -> Self {
+        StrictValidator {
+            feed,
+            supported: feed.message_table(),
+            last_timestamp: 0,
+            unknown_types: 0,
+            unsupported_types: 0,
+            bad_lengths: 0,
+            out_of_order: 0,
+            skipped_bytes: 0,
+            trailing_bytes: 0,
+            examples: Vec::new(),
+        }
+    }
+
+    fn problem(&mut self, offset: usize, description: String) {
+        if self.examples.len() < MAX_EXAMPLES {
+            self.examples.push(format!("offset {}: {}", offset, description));
+        }
+    }
+
+    // A corrupt header the parser skipped over to the next well-formed message
+    pub fn resync(&mut self, offset: usize, type_byte: u8, length: u16, skipped: usize) {
+        self.skipped_bytes += skipped as u64;
+        match MessageType::from(type_byte).spec_length() {
+            None => {
+                self.unknown_types += 1;
+                self.problem(offset, format!("unknown message type 0x{:02x}, skipped {} bytes", type_byte, skipped));
+            }
+            Some(expected) => {
+                self.bad_lengths += 1;
+                self.problem(offset, format!("'{}' message is {} bytes, expected {}, skipped {} bytes",
+                                             type_byte as char, length, expected, skipped));
+            }
+        }
+    }
+
+    // Validate one well-framed message (without its type byte); false if it must not be applied to the book
+    pub fn check(&mut self, offset: usize, type_byte: u8, message: &[u8]) -> bool {
+        if !self.supported[type_byte as usize] {
+            self.unsupported_types += 1;
+            self.problem(offset, format!("message type '{}' is not defined for the {:?} feed", type_byte as char, self.feed));
+            return false;
+        }
+
+        // Every message has its timestamp after the stock locate and tracking number
+        let mut timestamp = [0u8; 8];
+        timestamp[2..].copy_from_slice(&message[4..10]);
+        let timestamp = u64::from_be_bytes(timestamp);
+        if timestamp < self.last_timestamp {
+            self.out_of_order += 1;
+            self.problem(offset, format!("timestamp {} is before the previous message's {}", timestamp, self.last_timestamp));
+        } else {
+            self.last_timestamp = timestamp;
+        }
+        true
+    }
+
+    // A length prefix running past the end of the file, or the unframed bytes left over
+    pub fn truncated(&mut self, offset: usize, bytes: usize) {
+        self.trailing_bytes = bytes;
+        self.problem(offset, format!("{} trailing bytes don't form a complete message", bytes));
+    }
+
+    // Log the summary; an error if anything was wrong, so the process exits non-zero
+    pub fn finish(self) -> io::Result<()> {
+        let problems = self.unknown_types + self.unsupported_types + self.bad_lengths + self.out_of_order
+            + (self.trailing_bytes > 0) as u64;
+        if problems == 0 {
+            tracing::info!("Strict validation passed");
+            return Ok(());
+        }
+
+        for example in &self.examples {
+            tracing::error!("{}", example);
+        }
+        tracing::error!("Strict validation failed: {} unknown message types, {} not defined for the feed, {} with bad lengths ({} bytes skipped), {} out of order timestamps, {} trailing bytes",
+                        self.unknown_types, self.unsupported_types, self.bad_lengths, self.skipped_bytes, self.out_of_order, self.trailing_bytes);
+        Err(io::Error::new(io::ErrorKind::InvalidData, format!("Strict validation found {} problems", problems)))
+    }
+}
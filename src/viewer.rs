@@ -0,0 +1,278 @@
+// Terminal depth-of-market viewer (the view subcommand): replays a symbol's book in market time with a price
+// ladder, the latest trade prints and a sparkline of the mid, and keys to pause, change speed and seek
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Deref;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Sparkline, Table};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::cli::ViewArgs;
+use crate::file_io;
+use crate::orderbook::{BookSnapshot, Side, TradeRecord};
+use crate::replay::SnapshotReplay;
+
+// Time between frames when no key is pressed
+const FRAME: Duration = Duration::from_millis(33);
+const NANOS_PER_MINUTE: i64 = 60_000_000_000;
+// Trade prints and mids kept for the display
+const MAX_PRINTS: usize = 200;
+const MAX_MIDS: usize = 1000;
+// Range of the playback speed
+const MIN_SPEED: f64 = 1.0 / 64.0;
+const MAX_SPEED: f64 = 4096.0;
+
+const KEYS: &str = " space pause  +/- speed  \u{2190}/\u{2192} 1 min  PgUp/PgDn 10 min  Home start  q quit";
+
+fn price(price: u32) -> String {
+    format!("{}.{:04}", price / 10000, price % 10000)
+}
+
+// HH:MM:SS.mmm
+fn time(ns: u64) -> String {
+    let seconds = ns / 1_000_000_000;
+    format!("{:02}:{:02}:{:02}.{:03}", seconds / 3600, seconds / 60 % 60, seconds % 60, ns / 1_000_000 % 1000)
+}
+
+// The book of one symbol replayed against a market time clock that runs at `speed` times the wall clock
+pub struct Viewer<D> {
+    replay: SnapshotReplay<D>,
+    symbol: String,
+    levels: usize,
+    // Market time shown, nanoseconds since midnight
+    clock: u64,
+    speed: f64,
+    paused: bool,
+    // The snapshot shown, and the next one once it is read but not due yet
+    snapshot: Option<BookSnapshot>,
+    next: Option<BookSnapshot>,
+    finished: bool,
+    // Trades read but not due yet, and the latest prints, newest last
+    upcoming: VecDeque<TradeRecord>,
+    prints: VecDeque<TradeRecord>,
+    // Mid in dollars of each snapshot shown with both sides, newest last
+    mids: VecDeque<f64>,
+}
+
+impl<D: Deref<Target = [u8]>> Viewer<D> {
+    // At the first snapshot of the symbol
+    pub fn new(input: D, symbol: &str, levels: usize, speed: f64) -> io::Result<Self> {
+        let mut viewer = Viewer {
+            replay: SnapshotReplay::with_trades(input, &[symbol]),
+            symbol: symbol.to_string(),
+            levels,
+            clock: 0,
+            speed,
+            paused: false,
+            snapshot: None,
+            next: None,
+            finished: false,
+            upcoming: VecDeque::new(),
+            prints: VecDeque::new(),
+            mids: VecDeque::new(),
+        };
+        viewer.seek(0)?;
+        Ok(viewer)
+    }
+
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn snapshot(&self) -> Option<&BookSnapshot> {
+        self.snapshot.as_ref()
+    }
+
+    // Newest last
+    pub fn prints(&self) -> &VecDeque<TradeRecord> {
+        &self.prints
+    }
+
+    fn read_next(&mut self) -> io::Result<()> {
+        if self.next.is_none() && !self.finished {
+            match self.replay.next_snapshot()? {
+                Some((_, snapshot)) => self.next = Some(snapshot),
+                None => self.finished = true,
+            }
+            self.upcoming.extend(self.replay.take_trades().into_iter().map(|(_, trade)| trade));
+        }
+        Ok(())
+    }
+
+    // Show everything up to the clock
+    fn catch_up(&mut self) -> io::Result<()> {
+        loop {
+            self.read_next()?;
+            match self.next.take() {
+                Some(next) if next.timestamp <= self.clock => {
+                    // One-sided updates have no mid
+                    if next.mid_price > 0.0 {
+                        self.mids.push_back(next.mid_price);
+                        if self.mids.len() > MAX_MIDS {
+                            self.mids.pop_front();
+                        }
+                    }
+                    self.snapshot = Some(next);
+                }
+                next => {
+                    self.next = next;
+                    break;
+                }
+            }
+        }
+        while let Some(trade) = self.upcoming.front()
+            && trade.timestamp <= self.clock {
+            self.prints.extend(self.upcoming.pop_front());
+            if self.prints.len() > MAX_PRINTS {
+                self.prints.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    // Run the clock for `elapsed` of wall time
+    pub fn advance(&mut self, elapsed: Duration) -> io::Result<()> {
+        if self.paused || (self.finished && self.next.is_none()) {
+            return Ok(());
+        }
+        self.clock += (elapsed.as_nanos() as f64 * self.speed) as u64;
+        self.catch_up()
+    }
+
+    // Move the clock to `target`, or to the first snapshot if that is later; going back replays the input from
+    // the start
+    pub fn seek(&mut self, target: u64) -> io::Result<()> {
+        if target < self.clock || self.snapshot.is_none() {
+            self.replay.rewind();
+            self.snapshot = None;
+            self.next = None;
+            self.finished = false;
+            self.upcoming.clear();
+            self.prints.clear();
+            self.mids.clear();
+            self.read_next()?;
+            self.clock = self.next.as_ref().map_or(target, |first| target.max(first.timestamp));
+        } else {
+            self.clock = target;
+        }
+        self.catch_up()
+    }
+
+    fn seek_by(&mut self, nanos: i64) -> io::Result<()> {
+        self.seek(self.clock.saturating_add_signed(nanos))
+    }
+
+    // Apply a key press; false to quit
+    pub fn handle_key(&mut self, key: KeyEvent) -> io::Result<bool> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.speed = (self.speed * 2.0).min(MAX_SPEED),
+            KeyCode::Char('-') => self.speed = (self.speed / 2.0).max(MIN_SPEED),
+            KeyCode::Right => self.seek_by(NANOS_PER_MINUTE)?,
+            KeyCode::Left => self.seek_by(-NANOS_PER_MINUTE)?,
+            KeyCode::PageDown => self.seek_by(10 * NANOS_PER_MINUTE)?,
+            KeyCode::PageUp => self.seek_by(-10 * NANOS_PER_MINUTE)?,
+            KeyCode::Home => self.seek(0)?,
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    pub fn render(&self, frame: &mut Frame) {
+        let [header, body, chart, footer] = Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(7), Constraint::Length(1)])
+            .areas(frame.area());
+        let [ladder, trades] = Layout::horizontal([Constraint::Length(40), Constraint::Min(0)]).areas(body);
+
+        let state = if self.finished && self.next.is_none() { "end of input" } else if self.paused { "paused" } else { "playing" };
+        let progress = 100.0 * self.replay.offset() as f64 / self.replay.len().max(1) as f64;
+        frame.render_widget(Paragraph::new(format!(" {}  {}  {}x  {}  {:.1}% of the input", self.symbol, time(self.clock), self.speed, state, progress)), header);
+
+        // Asks above the bids, best prices in the middle
+        let mut rows = Vec::with_capacity(2 * self.levels);
+        if let Some(snapshot) = &self.snapshot {
+            for level in snapshot.ask_levels.iter().take(self.levels).rev() {
+                rows.push(Row::new([String::new(), price(level.price), level.total_volume.to_string()]).style(Style::new().fg(Color::Red)));
+            }
+            for level in snapshot.bid_levels.iter().take(self.levels) {
+                rows.push(Row::new([level.total_volume.to_string(), price(level.price), String::new()]).style(Style::new().fg(Color::Green)));
+            }
+        }
+        let widths = [Constraint::Length(10), Constraint::Length(12), Constraint::Length(10)];
+        frame.render_widget(Table::new(rows, widths)
+            .header(Row::new(["bid size", "price", "ask size"]))
+            .block(Block::new().borders(Borders::ALL).title(" Book ")), ladder);
+
+        let prints = self.prints.iter().rev().map(|trade| {
+            let color = match trade.aggressor {
+                Some(Side::Buy) => Color::Green,
+                Some(Side::Sell) => Color::Red,
+                None => Color::Reset,
+            };
+            Row::new([time(trade.timestamp), price(trade.price), trade.shares.to_string(), trade.aggressor_code().to_string()])
+                .style(Style::new().fg(color))
+        });
+        let widths = [Constraint::Length(13), Constraint::Length(12), Constraint::Length(8), Constraint::Length(3)];
+        frame.render_widget(Table::new(prints, widths)
+            .header(Row::new(["time", "price", "shares", ""]))
+            .block(Block::new().borders(Borders::ALL).title(" Trades ")), trades);
+
+        // The latest mids that fit, drawn above their lowest so the moves show
+        let width = chart.width.saturating_sub(2) as usize;
+        let mids: Vec<f64> = self.mids.iter().skip(self.mids.len().saturating_sub(width)).copied().collect();
+        let low = mids.iter().copied().fold(f64::INFINITY, f64::min);
+        let title = match mids.last() {
+            Some(&mid) => format!(" Mid {:.4} ", mid),
+            None => " Mid ".to_string(),
+        };
+        frame.render_widget(Sparkline::default()
+            .data(mids.iter().map(|mid| ((mid - low) * 20000.0).round() as u64 + 1))
+            .block(Block::new().borders(Borders::ALL).title(title)), chart);
+
+        frame.render_widget(Paragraph::new(KEYS), footer);
+    }
+}
+
+fn event_loop<D: Deref<Target = [u8]>>(terminal: &mut DefaultTerminal, viewer: &mut Viewer<D>) -> io::Result<()> {
+    let mut last = Instant::now();
+    loop {
+        terminal.draw(|frame| viewer.render(frame))?;
+        if event::poll(FRAME)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && !viewer.handle_key(key)? {
+            return Ok(());
+        }
+        let now = Instant::now();
+        viewer.advance(now - last)?;
+        last = now;
+    }
+}
+
+pub fn run(args: ViewArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let input = file_io::load_input(args.input.file()?, args.input.encap)?;
+    let mut viewer = Viewer::new(input, &args.symbol, args.levels as usize, args.speed)?;
+    if viewer.snapshot().is_none() {
+        return Err(format!("No book updates of {} in the input", args.symbol).into());
+    }
+    if let Some(start) = args.start {
+        viewer.seek(start)?;
+    }
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut viewer);
+    ratatui::restore();
+    Ok(result?)
+}
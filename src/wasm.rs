@@ -0,0 +1,44 @@
+// Browser bindings (feature `wasm`, built for wasm32-unknown-unknown with wasm-pack): replay the books of an
+// ITCH slice entirely client side, getting the same JSON snapshots the WebSocket server publishes
+
+use wasm_bindgen::prelude::*;
+
+use crate::features::FeatureColumns;
+use crate::orderbook::MAX_BOOK_DEPTH;
+use crate::replay::SnapshotReplay;
+
+#[wasm_bindgen]
+pub struct BookReplay {
+    replay: SnapshotReplay<Vec<u8>>,
+    columns: FeatureColumns,
+}
+
+#[wasm_bindgen]
+impl BookReplay {
+    // The bytes of an ITCH 5.0 file (or a slice of one starting at a message) and comma separated symbols
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>, symbols: &str) -> BookReplay {
+        let symbols: Vec<&str> = symbols.split(',').map(str::trim).filter(|symbol| !symbol.is_empty()).collect();
+        BookReplay { replay: SnapshotReplay::new(data, &symbols), columns: FeatureColumns::default() }
+    }
+
+    // The next snapshot of any of the symbols as JSON, or undefined at the end of the data
+    #[wasm_bindgen(js_name = nextSnapshot)]
+    pub fn next_snapshot(&mut self) -> Result<Option<String>, JsError> {
+        let Some((book, snapshot)) = self.replay.next_snapshot()? else {
+            return Ok(None);
+        };
+        Ok(Some(snapshot.to_json(self.replay.book(book).symbol_name(), MAX_BOOK_DEPTH, &self.columns)))
+    }
+
+    // Bytes applied so far, for progress
+    #[wasm_bindgen(getter)]
+    pub fn offset(&self) -> usize {
+        self.replay.offset()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.replay.len()
+    }
+}
@@ -1,72 +1,504 @@
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::collections::HashSet;
+use std::io;
 use std::net::SocketAddr;
 use std::path::Path;
-use std::thread;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
 use futures_util::{SinkExt, StreamExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader, Lines};
+use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 use tokio::select;
-use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
+use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+
+use crate::auth::{self, AuthTokens};
+use crate::bars::Bar;
+use crate::dashboard;
+use crate::matching::{Execution, MatchingEngine};
+use crate::metrics::METRICS;
+use crate::outbox::{ClientPolicy, Outbox};
+use crate::features::{FeatureColumns, TradeStats};
+use crate::orderbook::{BookSnapshot, Levels, LuldCollar, RetailInterest, Side, MAX_BOOK_DEPTH};
+use crate::shutdown;
+use crate::sink::OutputSink;
+use crate::timestamps::MarketHours;
+use crate::utils::{pad_stock_symbol, parse_time_of_day};
+
+// Capacity of the channel used to distribute messages to all clients
+pub const BROADCAST_CAPACITY: usize = 1000;
+// Longest a shutdown waits for the sessions to send their close frames
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+// Longest a client that needs a token has to send it
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A message distributed to clients
+#[derive(Debug, Clone)]
+pub struct BookUpdate {
+    // Symbol the update belongs to, None for messages every client should receive
+    pub symbol: Option<Arc<str>>,
+    pub json: String,
+    // Structured snapshot for clients using the binary format
+    pub snapshot: Option<Arc<BookSnapshot>>,
+}
+
+// Publishes every written snapshot to the live WebSocket clients
+pub struct LiveSink {
+    tx: broadcast::Sender<BookUpdate>,
+    bbo_only: bool,
+    // Computed columns of --features, the same as in the output file
+    columns: FeatureColumns,
+    // Shared by all updates of the same symbol
+    symbol: Option<Arc<str>>,
+    // Send completed bars too, with --stream-bars
+    bars: bool,
+}
+
+impl LiveSink {
+    pub fn new(tx: broadcast::Sender<BookUpdate>, bbo_only: bool, columns: FeatureColumns) -> Self {
+        LiveSink { tx, bbo_only, columns, symbol: None, bars: false }
+    }
+
+    pub fn with_bars(self) -> Self {
+        LiveSink { bars: true, ..self }
+    }
+
+    fn shared_symbol(&mut self, symbol: &str) -> Arc<str> {
+        match &self.symbol {
+            Some(shared) if &**shared == symbol => shared.clone(),
+            _ => self.symbol.insert(Arc::from(symbol)).clone(),
+        }
+    }
+}
+
+impl OutputSink for LiveSink {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        // Skip serialization when nobody is listening
+        if self.tx.receiver_count() == 0 {
+            return Ok(());
+        }
+        let json = if self.bbo_only {
+            snapshot.to_bbo_json(symbol, &self.columns)
+        } else {
+            snapshot.to_json(symbol, MAX_BOOK_DEPTH, &self.columns)
+        };
+        let shared = self.shared_symbol(symbol);
+        let _ = self.tx.send(BookUpdate {
+            symbol: Some(shared),
+            json,
+            snapshot: Some(Arc::new(snapshot.clone())),
+        });
+        Ok(())
+    }
+
+    // Bars have no snapshot, so conflating clients keep them in order with the book updates
+    fn write_bar(&mut self, symbol: &str, bar: &Bar) -> io::Result<()> {
+        if !self.bars || self.tx.receiver_count() == 0 {
+            return Ok(());
+        }
+        let shared = self.shared_symbol(symbol);
+        let _ = self.tx.send(BookUpdate { symbol: Some(shared), json: bar.to_json(symbol), snapshot: None });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Encoding of orderbook updates, negotiated with `?format=json|binary|delta` on the connection URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    Binary,
+    // A snapshot of the levels, then level changes (see delta.rs), encoded by the client's outbox
+    Delta,
+}
+
+impl WireFormat {
+    // Read the format from the request query string
+    fn from_query(query: Option<&str>) -> Result<Self, String> {
+        let format = query.unwrap_or("")
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "format")
+            .map(|(_, value)| value);
+
+        match format {
+            None | Some("json") => Ok(WireFormat::Json),
+            Some("binary") => Ok(WireFormat::Binary),
+            Some("delta") => Ok(WireFormat::Delta),
+            Some(other) => Err(format!("Unsupported format \"{}\", expected json, binary or delta", other)),
+        }
+    }
+
+    // Whether the client asked for zstd frames with ?compression=zstd, which the server must allow
+    fn compression_from_query(query: Option<&str>, available: bool) -> Result<bool, String> {
+        let compression = query.unwrap_or("")
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == "compression")
+            .map(|(_, value)| value);
+
+        match compression {
+            None | Some("none") => Ok(false),
+            Some("zstd") if available => Ok(true),
+            Some("zstd") => Err("Compression is not enabled on this server (--ws-compression)".to_string()),
+            Some(other) => Err(format!("Unsupported compression \"{}\", expected zstd or none", other)),
+        }
+    }
+
+    // An update as a frame in this format; only snapshots have a binary form
+    pub fn encode(self, update: BookUpdate) -> Message {
+        match (self, &update.snapshot, &update.symbol) {
+            (WireFormat::Binary, Some(snapshot), Some(symbol)) => Message::Binary(snapshot.to_binary(&pad_stock_symbol(symbol))),
+            _ => Message::Text(update.json),
+        }
+    }
+}
+
+// Parse a decimal price such as "150.0100" into 1/10000 dollar units
+fn parse_price(value: &str) -> u32 {
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let integer = integer.parse::<u32>().unwrap_or(0);
+    let fraction = format!("{:0<4}", &fraction[..fraction.len().min(4)]).parse::<u32>().unwrap_or(0);
+    integer * 10000 + fraction
+}
+
+// Where the server gets the messages it sends
+#[derive(Clone)]
+enum Source {
+    // Replay a finished orderbook CSV file for a symbol at a starting speed, paced per client
+    Csv(String, Arc<str>, f64),
+    // Stream snapshots published by the orderbook while parsing
+    Live(broadcast::Sender<BookUpdate>),
+    // Take orders for a matching engine of a symbol and stream its trades and book to every client
+    Matching(Arc<Mutex<MatchingEngine>>, Arc<str>, broadcast::Sender<BookUpdate>),
+}
 
 pub struct WebSocketServer {
-    csv_path: String,
+    source: Source,
     port: u16,
+    // Tokens a client must present, None to serve everyone
+    auth: Option<Arc<AuthTokens>>,
+    // Slow-client handling and heartbeats
+    client_policy: ClientPolicy,
+    // Serve wss:// instead of ws://
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>,
 }
 
 impl WebSocketServer {
-    pub fn new(csv_path: &str, port: u16) -> Self {
+    pub fn new(csv_path: &str, symbol: &str, port: u16, speed: f64) -> Self {
         WebSocketServer {
-            csv_path: csv_path.to_string(),
+            source: Source::Csv(csv_path.to_string(), Arc::from(symbol), speed),
             port,
+            auth: None,
+            client_policy: ClientPolicy::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
         }
     }
 
+    // Serve snapshots sent on `tx` as they are produced
+    pub fn live(tx: broadcast::Sender<BookUpdate>, port: u16) -> Self {
+        WebSocketServer {
+            source: Source::Live(tx),
+            port,
+            auth: None,
+            client_policy: ClientPolicy::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    // Accept orders for the engine from every client
+    pub fn matching(engine: MatchingEngine, symbol: &str, port: u16) -> Self {
+        let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+        WebSocketServer {
+            source: Source::Matching(Arc::new(Mutex::new(engine)), Arc::from(symbol), tx),
+            port,
+            auth: None,
+            client_policy: ClientPolicy::default(),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    pub fn with_client_policy(mut self, policy: ClientPolicy) -> Self {
+        self.client_policy = policy;
+        self
+    }
+
+    // Only serve clients that present one of the tokens
+    pub fn with_auth(mut self, tokens: AuthTokens) -> Self {
+        self.auth = Some(Arc::new(tokens));
+        self
+    }
+
+    // Terminate TLS on every connection, see tls::acceptor
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, acceptor: tokio_rustls::TlsAcceptor) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
     // Start the WebSocket server
     pub async fn start(&self) -> io::Result<()> {
         let addr = SocketAddr::from(([0, 0, 0, 0], self.port));
         let listener = TcpListener::bind(&addr).await?;
 
-        println!("WebSocket server started on: {}", addr);
+        #[cfg(feature = "tls")]
+        let scheme = if self.tls.is_some() { "wss" } else { "ws" };
+        #[cfg(not(feature = "tls"))]
+        let scheme = "ws";
+        tracing::info!("WebSocket server started on: {}://{}", scheme, addr);
 
-        // Create a broadcast channel for distributing messages to all clients
-        let (broadcast_tx, _) = broadcast::channel::<String>(1000);
-        let csv_path = self.csv_path.clone();
-
-        // Start CSV reading task
-        let tx_clone = broadcast_tx.clone();
-        self.start_csv_reader(csv_path, tx_clone);
+        if let Source::Csv(csv_path, _, _) = &self.source {
+            wait_for_file(csv_path).await?;
+        }
 
-        // Accept and handle client connections
-        while let Ok((stream, addr)) = listener.accept().await {
-            println!("New connection from: {}", addr);
+        // Accept and handle client connections until shut down
+        let mut sessions = 0;
+        loop {
+            let (stream, addr) = select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(_) => break,
+                },
+                _ = shutdown::wait() => break,
+            };
+            tracing::info!("New connection from: {}", addr);
+            // Matching engine owner of the connection's orders; 0 is the seeded book
+            sessions += 1;
+            let session = sessions;
 
-            // Clone the broadcast sender for this client
-            let rx = broadcast_tx.subscribe();
+            // Each client either subscribes to the live broadcast or gets its own replay cursor
+            let source = self.source.clone();
+            let auth = self.auth.clone();
+            let policy = self.client_policy;
 
             // Spawn a new task to handle this client
-            tokio::spawn(handle_connection(stream, addr, rx));
+            #[cfg(feature = "tls")]
+            let tls = self.tls.clone();
+            tokio::spawn(async move {
+                #[cfg(feature = "tls")]
+                if let Some(acceptor) = tls {
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => serve_client(stream, addr, source, session, auth, policy).await,
+                        Err(e) => tracing::warn!("TLS handshake with {} failed: {}", addr, e),
+                    }
+                    return;
+                }
+                serve_client(stream, addr, source, session, auth, policy).await;
+            });
         }
 
+        // Give the sessions a moment to send their close frames
+        if shutdown::requested() {
+            shutdown::drain(CLOSE_TIMEOUT, || METRICS.websocket_clients.load(Ordering::Relaxed) <= 0).await;
+        }
         Ok(())
     }
+}
+
+// Run one client's session over a plain or TLS stream; browsers asking for the page get the dashboard
+async fn serve_client<S: AsyncRead + AsyncWrite + Unpin>(stream: S, addr: SocketAddr, source: Source, session: u64, auth: Option<Arc<AuthTokens>>, policy: ClientPolicy) {
+    let stream = match dashboard::route(stream).await {
+        Ok(Some(stream)) => stream,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!("Error reading the request from {}: {}", addr, e);
+            return;
+        }
+    };
+    let (mut ws_stream, format, compressed, authenticated) = match accept(stream, addr, auth.as_deref(), policy.compression.is_some()).await {
+        Some(accepted) => accepted,
+        None => return,
+    };
+    let policy = ClientPolicy { compression: policy.compression.filter(|_| compressed), ..policy };
+    if let Some(tokens) = &auth
+        && !authenticated
+        && !authenticate(&mut ws_stream, addr, tokens).await {
+        return;
+    }
+
+    let feed = match source {
+        Source::Live(tx) => Feed::Live(tx.subscribe()),
+        Source::Matching(engine, symbol, tx) => Feed::Matching(Box::new(MatchingSession { rx: tx.subscribe(), engine, symbol, tx, session })),
+        Source::Csv(csv_path, symbol, speed) => match CsvReplay::open(&csv_path, symbol, speed).await {
+            Ok(replay) => Feed::Replay(Box::new(replay)),
+            Err(e) => {
+                tracing::error!("Error opening CSV file for {}: {}", addr, e);
+                let _ = ws_stream.close(None).await;
+                return;
+            }
+        },
+    };
+    handle_connection(ws_stream, addr, format, feed, policy).await;
+}
+
+// Accept the WebSocket connection, negotiating the wire format and compression from the query string. A wrong
+// token in the query is refused with 401; whether a right one was given is returned with the stream
+async fn accept<S: AsyncRead + AsyncWrite + Unpin>(stream: S, addr: SocketAddr, auth: Option<&AuthTokens>, compression: bool)
+    -> Option<(WebSocketStream<S>, WireFormat, bool, bool)> {
+    let mut format = WireFormat::Json;
+    let mut compressed = false;
+    let mut authenticated = false;
+    // The error type is dictated by tungstenite's handshake callback
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+        let query = request.uri().query();
+        if let Some(tokens) = auth
+            && let Some(token) = auth::query_token(query) {
+            if !tokens.accepts(token) {
+                let mut error = ErrorResponse::new(Some("Invalid token".to_string()));
+                *error.status_mut() = StatusCode::UNAUTHORIZED;
+                return Err(error);
+            }
+            authenticated = true;
+        }
+        match WireFormat::from_query(query).and_then(|requested| Ok((requested, WireFormat::compression_from_query(query, compression)?))) {
+            Ok((requested, requested_compression)) => {
+                format = requested;
+                compressed = requested_compression;
+                Ok(response)
+            }
+            Err(e) => {
+                let mut error = ErrorResponse::new(Some(e));
+                *error.status_mut() = StatusCode::BAD_REQUEST;
+                Err(error)
+            }
+        }
+    };
+
+    match accept_hdr_async(stream, callback).await {
+        Ok(ws) => Some((ws, format, compressed, authenticated)),
+        Err(e) => {
+            tracing::warn!("Error accepting WebSocket connection from {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+// Wait for a client that gave no token in its query to send {"token": "..."} first, and close the
+// connection if it doesn't in time or the token is wrong
+async fn authenticate<S: AsyncRead + AsyncWrite + Unpin>(ws_stream: &mut WebSocketStream<S>, addr: SocketAddr, tokens: &AuthTokens) -> bool {
+    let token = match tokio::time::timeout(AUTH_TIMEOUT, ws_stream.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => auth::message_token(&text),
+        _ => None,
+    };
+    if token.is_some_and(|token| tokens.accepts(&token)) {
+        return ws_stream.send(Message::Text("{\"type\":\"auth\",\"authenticated\":true}".to_string())).await.is_ok();
+    }
+    tracing::warn!("Closing connection from {} without a valid token", addr);
+    let close = CloseFrame { code: CloseCode::Policy, reason: "Authentication required".into() };
+    let _ = ws_stream.send(Message::Close(Some(close))).await;
+    false
+}
+
+// Wait for the CSV file to be created if it doesn't exist yet
+async fn wait_for_file(csv_path: &str) -> io::Result<()> {
+    let mut retry_count = 0;
+    while !Path::new(csv_path).exists() {
+        if retry_count > 30 {
+            return Err(io::Error::new(io::ErrorKind::NotFound,
+                                      format!("CSV file not found after 30 seconds: {}", csv_path)));
+        }
+        tracing::info!("Waiting for CSV file to be created: {}", csv_path);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        retry_count += 1;
+    }
+    Ok(())
+}
+
+// Parse a seek target: "HH:MM:SS[.fraction]" or nanoseconds since midnight
+fn parse_seek_target(value: &serde_json::Value) -> Result<u64, String> {
+    if let Some(ns) = value.as_u64() {
+        return Ok(ns);
+    }
+    let text = value.as_str().ok_or("\"seek\" expects \"HH:MM:SS\" or nanoseconds since midnight")?;
+    parse_time_of_day(text)
+}
+
+// Per-connection replay of a finished orderbook CSV file
+struct CsvReplay {
+    csv_path: String,
+    symbol: Arc<str>,
+    header: Vec<String>,
+    lines: Lines<BufReader<File>>,
+    // Row found while seeking, sent before reading further
+    pending: Option<(u64, String)>,
+    speed: f64,
+    paused: bool,
+    finished: bool,
+    last_timestamp: u64,
+    count: u64,
+}
+
+impl CsvReplay {
+    async fn open(csv_path: &str, symbol: Arc<str>, speed: f64) -> io::Result<Self> {
+        let (header, lines) = Self::open_lines(csv_path).await?;
+
+        Ok(CsvReplay {
+            csv_path: csv_path.to_string(),
+            symbol,
+            header,
+            lines,
+            pending: None,
+            speed,
+            paused: false,
+            finished: false,
+            last_timestamp: 0,
+            count: 0,
+        })
+    }
+
+    // Open the CSV file and read the header line to extract column names
+    async fn open_lines(csv_path: &str) -> io::Result<(Vec<String>, Lines<BufReader<File>>)> {
+        let file = File::open(csv_path).await?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header = match lines.next_line().await? {
+            // Split the header line by commas to get column names
+            Some(header_line) => header_line.split(',').map(|s| s.trim().to_string()).collect::<Vec<String>>(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty CSV file")),
+        };
+
+        Ok((header, lines))
+    }
+
+    // Metadata message with column information, sent when the client connects
+    fn metadata_json(&self) -> String {
+        format!("{{\"type\":\"metadata\",\"columns\":{}}}",
+                serde_json::to_string(&self.header).unwrap_or_else(|_| "[]".to_string()))
+    }
+
+    // Current pacing state, sent back after each replay command
+    fn state_json(&self) -> String {
+        format!("{{\"type\":\"replay\",\"speed\":{},\"paused\":{},\"finished\":{},\"timestamp\":\"{}\"}}",
+                self.speed, self.paused, self.finished, self.last_timestamp)
+    }
 
     // Convert a CSV line with column names to a JSON object
-    fn csv_line_to_json(header: &[String], line: &str) -> String {
+    fn csv_line_to_json(symbol: &str, header: &[String], line: &str) -> String {
         let values: Vec<&str> = line.split(',').collect();
         if values.len() != header.len() {
             return format!("{{\"error\": \"Column count mismatch: expected {}, got {}\"}}",
                            header.len(), values.len());
         }
 
-        let mut json_str = String::from("{");
+        let mut json_str = format!("{{\"symbol\":\"{}\"", symbol);
 
         for (i, (key, value)) in header.iter().zip(values.iter()).enumerate() {
-            if i > 0 {
-                json_str.push_str(", ");
-            }
+            json_str.push_str(", ");
 
             // Handle numeric values (don't quote them in JSON)
             if i == 0 && key == "timestamp" {
@@ -93,147 +525,544 @@ impl WebSocketServer {
             }
         }
 
-        json_str.push_str("}");
+        json_str.push('}');
         json_str
     }
 
-    // Start a thread to read the CSV file and broadcast updates
-    fn start_csv_reader(&self, csv_path: String, tx: broadcast::Sender<String>) {
-        thread::spawn(move || {
-            // Wait for the CSV file to be created if it doesn't exist yet
-            let mut retry_count = 0;
-            while !Path::new(&csv_path).exists() {
-                if retry_count > 30 {
-                    eprintln!("Error: CSV file not found after 30 seconds: {}", csv_path);
-                    return;
+    // Rebuild a snapshot from a full depth or BBO CSV row
+    fn csv_line_to_snapshot(header: &[String], line: &str) -> BookSnapshot {
+        let mut snapshot = BookSnapshot {
+            timestamp: 0,
+            bid_levels: Levels::default(),
+            ask_levels: Levels::default(),
+            mid_price: 0.0,
+            imbalance: 0.0,
+            trades: TradeStats::default(),
+            ofi: 0,
+            windows: Vec::new(),
+            retail_interest: RetailInterest::None,
+            luld: LuldCollar::default(),
+            mwcb_level: 0,
+            short_sale_restricted: false,
+            operational_halts: 0,
+            market_hours: MarketHours::default(),
+        };
+
+        // Sets the price or volume of a level; levels past MAX_BOOK_DEPTH are ignored
+        fn set(levels: &mut Levels, index: usize, price: bool, value: &str) {
+            if let Some(level) = levels.level_mut(index) {
+                if price {
+                    level.price = parse_price(value);
+                } else {
+                    level.total_volume = value.parse().unwrap_or(0);
                 }
-                println!("Waiting for CSV file to be created: {}", csv_path);
-                thread::sleep(Duration::from_secs(1));
-                retry_count += 1;
             }
+        }
 
-            // Open the CSV file for reading
-            let file = match File::open(&csv_path) {
-                Ok(file) => file,
-                Err(e) => {
-                    eprintln!("Error opening CSV file: {}", e);
-                    return;
+        for (key, value) in header.iter().zip(line.split(',')) {
+            match key.as_str() {
+                "timestamp" => snapshot.timestamp = value.parse().unwrap_or(0),
+                "mid_price" | "mid" => snapshot.mid_price = value.parse().unwrap_or(0.0),
+                "orderbook_imbalance" => snapshot.imbalance = value.parse().unwrap_or(0.0),
+                "bid" => set(&mut snapshot.bid_levels, 0, true, value),
+                "bid_size" => set(&mut snapshot.bid_levels, 0, false, value),
+                "ask" => set(&mut snapshot.ask_levels, 0, true, value),
+                "ask_size" => set(&mut snapshot.ask_levels, 0, false, value),
+                _ => {
+                    // Depth columns are named "<level>_<bid|ask>_<price|vol>"
+                    let Some((n, field)) = key.split_once('_') else { continue };
+                    let Some(index) = n.parse::<usize>().ok().and_then(|n| n.checked_sub(1)) else { continue };
+                    match field {
+                        "bid_price" => set(&mut snapshot.bid_levels, index, true, value),
+                        "bid_vol" => set(&mut snapshot.bid_levels, index, false, value),
+                        "ask_price" => set(&mut snapshot.ask_levels, index, true, value),
+                        "ask_vol" => set(&mut snapshot.ask_levels, index, false, value),
+                        _ => {}
+                    }
                 }
-            };
+            }
+        }
+
+        snapshot
+    }
 
-            println!("CSV file opened, starting broadcast: {}", csv_path);
+    // Read the next data row along with its timestamp
+    async fn read_row(&mut self) -> io::Result<Option<(u64, String)>> {
+        if let Some(row) = self.pending.take() {
+            return Ok(Some(row));
+        }
 
-            let reader = BufReader::new(file);
-            let mut lines = reader.lines();
+        while let Some(line) = self.lines.next_line().await? {
+            if line.is_empty() {
+                continue;
+            }
+            let timestamp = line.split(',').next().and_then(|t| t.parse::<u64>().ok()).unwrap_or(0);
+            return Ok(Some((timestamp, line)));
+        }
+        Ok(None)
+    }
 
-            // Get the header line to extract column names
-            let header = match lines.next().transpose() {
-                Ok(Some(header_line)) => {
-                    // Split the header line by commas to get column names
-                    header_line.split(',').map(|s| s.trim().to_string()).collect::<Vec<String>>()
-                },
-                _ => {
-                    eprintln!("Error reading CSV header or empty file");
-                    return;
+    // Wait until the next row is due and return it, never resolving while paused or finished. Rows keep
+    // their original spacing in market time, divided by the speed
+    async fn next(&mut self) -> io::Result<BookUpdate> {
+        if self.paused || self.finished {
+            return std::future::pending().await;
+        }
+
+        match self.read_row().await? {
+            Some((timestamp, line)) => {
+                // Kept as pending so a client command interrupting the wait doesn't drop the row
+                self.pending = Some((timestamp, line));
+                if self.last_timestamp > 0 {
+                    let gap = Duration::from_nanos(timestamp.saturating_sub(self.last_timestamp));
+                    tokio::time::sleep(gap.div_f64(self.speed)).await;
                 }
+                self.emit()
+            }
+            None => {
+                tracing::info!("Finished replaying {} JSON messages from CSV file", self.count);
+                self.finished = true;
+                std::future::pending().await
+            }
+        }
+    }
+
+    // Send the pending row
+    fn emit(&mut self) -> io::Result<BookUpdate> {
+        let (timestamp, line) = self.pending.take().ok_or_else(|| io::Error::other("No row to send"))?;
+        self.last_timestamp = timestamp;
+        self.count += 1;
+        Ok(BookUpdate {
+            symbol: Some(self.symbol.clone()),
+            json: Self::csv_line_to_json(&self.symbol, &self.header, &line),
+            snapshot: Some(Arc::new(Self::csv_line_to_snapshot(&self.header, &line))),
+        })
+    }
+
+    // Position the replay at the first row at or after `target`
+    async fn seek(&mut self, target: u64) -> io::Result<()> {
+        // Rewind by reopening the file when seeking backwards
+        let current = self.pending.as_ref().map_or(self.last_timestamp, |(ts, _)| *ts);
+        if target < current || self.finished {
+            let (header, lines) = Self::open_lines(&self.csv_path).await?;
+            self.header = header;
+            self.lines = lines;
+            self.pending = None;
+            self.finished = false;
+            self.last_timestamp = 0;
+        }
+
+        while let Some((timestamp, line)) = self.read_row().await? {
+            if timestamp >= target {
+                self.pending = Some((timestamp, line));
+                self.last_timestamp = timestamp;
+                return Ok(());
+            }
+            self.last_timestamp = timestamp;
+        }
+
+        self.finished = true;
+        Ok(())
+    }
+}
+
+// A client of the matching engine, which owns the orders it sends
+struct MatchingSession {
+    engine: Arc<Mutex<MatchingEngine>>,
+    symbol: Arc<str>,
+    tx: broadcast::Sender<BookUpdate>,
+    rx: broadcast::Receiver<BookUpdate>,
+    session: u64,
+}
+
+impl MatchingSession {
+    fn book_json(&self) -> String {
+        self.engine.lock().unwrap().book_json(&self.symbol, MAX_BOOK_DEPTH)
+    }
+
+    // Send the trades and the new book to every client; done under the engine lock so updates
+    // from different sessions go out in the order they were applied
+    fn publish(&self, engine: &MatchingEngine, executions: &[Execution]) {
+        let updates = executions.iter()
+            .map(|execution| execution.to_json(&self.symbol))
+            .chain([engine.book_json(&self.symbol, MAX_BOOK_DEPTH)]);
+        for json in updates {
+            let _ = self.tx.send(BookUpdate { symbol: Some(self.symbol.clone()), json, snapshot: None });
+        }
+    }
+
+    fn apply(&self, command: ClientCommand) -> String {
+        let mut engine = self.engine.lock().unwrap();
+        let result = match command {
+            ClientCommand::Order { side, price, shares } => engine.submit(self.session, side, price, shares)
+                .map(|(id, executions)| (id, executions, "accepted")),
+            ClientCommand::Replace { id, price, shares } => engine.replace(self.session, id, price, shares)
+                .map(|executions| (id, executions, "replaced")),
+            ClientCommand::Cancel(id) => engine.cancel(self.session, id)
+                .map(|order| (id, Vec::new(), if order.shares > 0 { "cancelled" } else { "filled" })),
+            _ => return error_json("Only order, cancel and replace commands are accepted by the matching engine"),
+        };
+        match result {
+            Ok((id, executions, status)) => {
+                self.publish(&engine, &executions);
+                let filled: u32 = executions.iter().filter(|execution| execution.aggressor_id == id).map(|execution| execution.shares).sum();
+                let open = engine.order(id).map_or(0, |order| order.shares);
+                format!("{{\"type\":\"{}\",\"id\":{},\"filled\":{},\"open\":{}}}", status, id, filled, open)
+            }
+            Err(e) => error_json(&e.to_string()),
+        }
+    }
+}
+
+// What a connection streams to its client
+enum Feed {
+    Live(broadcast::Receiver<BookUpdate>),
+    Replay(Box<CsvReplay>),
+    Matching(Box<MatchingSession>),
+}
+
+// Why a feed gave no update
+enum FeedError {
+    // The broadcast channel dropped this many updates the client hadn't taken yet
+    Lagged(u64),
+    Ended(String),
+}
+
+// Next message of a broadcast channel, counting the messages a slow client missed
+async fn receive(rx: &mut broadcast::Receiver<BookUpdate>) -> Result<BookUpdate, FeedError> {
+    match rx.recv().await {
+        Ok(update) => {
+            METRICS.broadcast_backlog.observe(rx.len() as u64);
+            Ok(update)
+        }
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+            METRICS.broadcast_lagged.fetch_add(skipped, Ordering::Relaxed);
+            Err(FeedError::Lagged(skipped))
+        }
+        Err(e) => Err(FeedError::Ended(format!("Broadcast channel error: {}", e))),
+    }
+}
+
+impl Feed {
+    // Next update for this client
+    async fn next(&mut self) -> Result<BookUpdate, FeedError> {
+        match self {
+            Feed::Live(rx) => receive(rx).await,
+            Feed::Replay(replay) => replay.next().await.map_err(|e| FeedError::Ended(format!("Error reading CSV line: {}", e))),
+            Feed::Matching(session) => receive(&mut session.rx).await,
+        }
+    }
+}
+
+// Commands a client can send as JSON text messages
+#[derive(Debug, PartialEq)]
+enum ClientCommand {
+    // {"subscribe": ["AAPL", "TSLA"]}
+    Subscribe(Vec<String>),
+    // {"unsubscribe": ["AAPL"]}
+    Unsubscribe(Vec<String>),
+    // {"speed": 10.0}, replay only
+    Speed(f64),
+    // {"pause": true}, replay only
+    Pause(bool),
+    // {"seek": "09:35:00"}, replay only, nanoseconds since midnight
+    Seek(u64),
+    // {"order": {"side": "buy", "price": 150.25, "shares": 100}}, matching engine only
+    Order { side: Side, price: u32, shares: u32 },
+    // {"cancel": 42}, matching engine only
+    Cancel(u64),
+    // {"replace": {"id": 42, "price": 150.3, "shares": 50}}, matching engine only
+    Replace { id: u64, price: u32, shares: u32 },
+}
+
+// Price and shares of an order or replace command
+fn parse_order_fields(value: &serde_json::Value, key: &str) -> Result<(u32, u32), String> {
+    let price = value.get("price").and_then(|price| price.as_f64()).filter(|price| *price > 0.0 && *price * 10000.0 <= u32::MAX as f64)
+        .ok_or_else(|| format!("\"{}\" expects a positive \"price\" in dollars", key))?;
+    let shares = value.get("shares").and_then(|shares| shares.as_u64()).and_then(|shares| u32::try_from(shares).ok()).filter(|&shares| shares > 0)
+        .ok_or_else(|| format!("\"{}\" expects a positive number of \"shares\"", key))?;
+    Ok(((price * 10000.0).round() as u32, shares))
+}
+
+impl ClientCommand {
+    fn parse(text: &str) -> Result<Self, String> {
+        let value: serde_json::Value = serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+
+        if let Some(speed) = value.get("speed") {
+            return match speed.as_f64() {
+                Some(speed) if speed > 0.0 && speed.is_finite() => Ok(ClientCommand::Speed(speed)),
+                _ => Err("\"speed\" expects a positive number".to_string()),
             };
+        }
+        if let Some(pause) = value.get("pause") {
+            return pause.as_bool().map(ClientCommand::Pause).ok_or_else(|| "\"pause\" expects true or false".to_string());
+        }
+        if let Some(seek) = value.get("seek") {
+            return parse_seek_target(seek).map(ClientCommand::Seek);
+        }
+        if let Some(order) = value.get("order") {
+            let side = match order.get("side").and_then(|side| side.as_str()) {
+                Some("buy") => Side::Buy,
+                Some("sell") => Side::Sell,
+                _ => return Err("\"order\" expects a \"side\" of buy or sell".to_string()),
+            };
+            let (price, shares) = parse_order_fields(order, "order")?;
+            return Ok(ClientCommand::Order { side, price, shares });
+        }
+        if let Some(id) = value.get("cancel") {
+            return id.as_u64().map(ClientCommand::Cancel).ok_or_else(|| "\"cancel\" expects an order id".to_string());
+        }
+        if let Some(replace) = value.get("replace") {
+            let id = replace.get("id").and_then(|id| id.as_u64()).ok_or_else(|| "\"replace\" expects an order \"id\"".to_string())?;
+            let (price, shares) = parse_order_fields(replace, "replace")?;
+            return Ok(ClientCommand::Replace { id, price, shares });
+        }
 
-            println!("Parsed CSV header with {} columns", header.len());
-
-            // Send a metadata message to clients with column information
-            let metadata_json = format!("{{\"type\":\"metadata\",\"columns\":{}}}",
-                                        serde_json::to_string(&header).unwrap_or_else(|_| "[]".to_string()));
-            let _ = tx.send(metadata_json);
-
-            // Read and broadcast each line as JSON
-            let mut count = 0;
-            for line in lines {
-                match line {
-                    Ok(data) => {
-                        // Convert CSV line to JSON and broadcast
-                        let json_data = Self::csv_line_to_json(&header, &data);
-                        let _ = tx.send(json_data);
-                        count += 1;
-
-                        // Add a small delay to simulate realistic message flow
-                        thread::sleep(Duration::from_millis(10));
-                    }
-                    Err(e) => {
-                        eprintln!("Error reading CSV line: {}", e);
-                    }
-                }
+        let (key, symbols) = if let Some(symbols) = value.get("subscribe") {
+            ("subscribe", symbols)
+        } else if let Some(symbols) = value.get("unsubscribe") {
+            ("unsubscribe", symbols)
+        } else {
+            return Err("Unknown command, expected \"subscribe\", \"unsubscribe\", \"speed\", \"pause\", \"seek\", \"order\", \"cancel\" or \"replace\"".to_string());
+        };
+
+        let symbols = symbols.as_array()
+            .ok_or_else(|| format!("\"{}\" expects a list of symbols", key))?
+            .iter()
+            .map(|s| s.as_str().map(|s| s.trim().to_uppercase()).ok_or_else(|| format!("\"{}\" expects a list of symbols", key)))
+            .collect::<Result<Vec<String>, String>>()?;
+
+        Ok(if key == "subscribe" {
+            ClientCommand::Subscribe(symbols)
+        } else {
+            ClientCommand::Unsubscribe(symbols)
+        })
+    }
+}
+
+// Symbols a connection receives; clients get every symbol until they subscribe
+#[derive(Debug, Default)]
+struct Subscriptions {
+    // None means all symbols
+    symbols: Option<HashSet<String>>,
+    // Symbols unsubscribed while receiving all symbols
+    excluded: HashSet<String>,
+}
+
+impl Subscriptions {
+    fn subscribe(&mut self, symbols: Vec<String>) {
+        let subscribed = self.symbols.get_or_insert_with(HashSet::new);
+        for symbol in symbols {
+            self.excluded.remove(&symbol);
+            subscribed.insert(symbol);
+        }
+    }
+
+    fn unsubscribe(&mut self, symbols: Vec<String>) {
+        for symbol in symbols {
+            match &mut self.symbols {
+                Some(subscribed) => { subscribed.remove(&symbol); },
+                None => { self.excluded.insert(symbol); },
             }
+        }
+    }
 
-            println!("Finished broadcasting {} JSON messages from CSV file", count);
-        });
+    fn wants(&self, symbol: Option<&str>) -> bool {
+        match (symbol, &self.symbols) {
+            // Messages without a symbol go to everyone
+            (None, _) => true,
+            (Some(symbol), Some(subscribed)) => subscribed.contains(symbol),
+            (Some(symbol), None) => !self.excluded.contains(symbol),
+        }
+    }
+
+    // Acknowledgement sent back after each command
+    fn to_json(&self) -> String {
+        let mut symbols: Vec<&String> = match &self.symbols {
+            Some(subscribed) => subscribed.iter().collect(),
+            None => Vec::new(),
+        };
+        symbols.sort();
+        let mut excluded: Vec<&String> = self.excluded.iter().collect();
+        excluded.sort();
+
+        format!("{{\"type\":\"subscriptions\",\"all\":{},\"symbols\":{},\"excluded\":{}}}",
+                self.symbols.is_none(),
+                serde_json::to_string(&symbols).unwrap_or_else(|_| "[]".to_string()),
+                serde_json::to_string(&excluded).unwrap_or_else(|_| "[]".to_string()))
     }
 }
 
-// Handle a single WebSocket connection
-async fn handle_connection(
-    stream: TcpStream,
-    addr: SocketAddr,
-    mut rx: broadcast::Receiver<String>
-) {
-    // Accept the WebSocket connection
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => ws,
-        Err(e) => {
-            eprintln!("Error accepting WebSocket connection from {}: {}", addr, e);
-            return;
+fn error_json(message: &str) -> String {
+    format!("{{\"type\":\"error\",\"message\":{}}}",
+            serde_json::to_string(message).unwrap_or_else(|_| "\"\"".to_string()))
+}
+
+// Apply a client command and build the reply
+async fn apply_command(command: ClientCommand, subscriptions: &mut Subscriptions, feed: &mut Feed) -> String {
+    match (command, feed) {
+        (ClientCommand::Subscribe(symbols), _) => {
+            subscriptions.subscribe(symbols);
+            subscriptions.to_json()
         }
-    };
+        (ClientCommand::Unsubscribe(symbols), _) => {
+            subscriptions.unsubscribe(symbols);
+            subscriptions.to_json()
+        }
+        (command @ (ClientCommand::Order { .. } | ClientCommand::Cancel(_) | ClientCommand::Replace { .. }), feed) => match feed {
+            Feed::Matching(session) => session.apply(command),
+            _ => error_json("Orders are only accepted by the matching engine (the match subcommand)"),
+        },
+        (ClientCommand::Speed(speed), Feed::Replay(replay)) => {
+            replay.speed = speed;
+            replay.state_json()
+        }
+        (ClientCommand::Pause(paused), Feed::Replay(replay)) => {
+            replay.paused = paused;
+            replay.state_json()
+        }
+        (ClientCommand::Seek(target), Feed::Replay(replay)) => match replay.seek(target).await {
+            Ok(()) => replay.state_json(),
+            Err(e) => error_json(&format!("Seek failed: {}", e)),
+        },
+        (_, Feed::Live(_) | Feed::Matching(_)) => error_json("Replay controls are only available when replaying a recorded file"),
+    }
+}
 
-    println!("WebSocket connection established with: {}", addr);
+// Handle a single WebSocket connection. Everything sent goes through the client's outbox, which a writer
+// drains at the client's pace while this loop keeps taking updates off the feed
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    ws_stream: WebSocketStream<S>,
+    addr: SocketAddr,
+    format: WireFormat,
+    mut feed: Feed,
+    policy: ClientPolicy,
+) {
+    tracing::info!("WebSocket connection established with: {} ({:?}{})", addr, format, if policy.compression.is_some() { ", zstd" } else { "" });
+    METRICS.websocket_clients.fetch_add(1, Ordering::Relaxed);
 
     // Split the WebSocket stream
-    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    let (ws_sender, mut ws_receiver) = ws_stream.split();
+    let outbox = Outbox::new(format, policy);
+    let writer = outbox.write_to(ws_sender);
+    tokio::pin!(writer);
+    let mut subscriptions = Subscriptions::default();
+
+    // Send a metadata message to replay clients with column information, and the book to matching clients
+    let greeting = match &feed {
+        Feed::Replay(replay) => Some(replay.metadata_json()),
+        Feed::Matching(session) => Some(session.book_json()),
+        Feed::Live(_) => None,
+    };
+    if let Some(greeting) = greeting {
+        outbox.push_control(Message::Text(greeting));
+    }
+
+    // Heartbeats: a ping every interval, and a client that hasn't answered the last one by the next is gone
+    let mut heartbeat = policy.ping_interval.map(|interval| tokio::time::interval_at(tokio::time::Instant::now() + interval, interval));
+    let mut awaiting_pong = false;
+    // A replay is paced by the client, so its next row is only read once everything before it is sent
+    let paced_by_client = matches!(feed, Feed::Replay(_));
 
-    // Main client handling loop
-    loop {
-        // Use select! to handle both broadcast messages and socket events
+    // Main client handling loop; a close frame to send ends it
+    let close = loop {
+        // Use select! to handle both orderbook updates and socket events
         select! {
-            // Handle incoming broadcast messages (orderbook updates)
-            data = rx.recv() => {
-                match data {
-                    Ok(msg) => {
-                        // Send the JSON message to the WebSocket client
-                        if ws_sender.send(Message::Text(msg)).await.is_err() {
-                            // If sending fails, break out of the loop
-                            break;
-                        }
-                    },
-                    Err(e) => {
-                        eprintln!("Broadcast channel error: {}", e);
-                        break;
+            // Queue outgoing orderbook updates
+            data = async {
+                if paced_by_client {
+                    outbox.wait_idle().await;
+                }
+                feed.next().await
+            } => {
+                let queued = match data {
+                    // Only forward symbols this client is subscribed to
+                    Ok(update) if !subscriptions.wants(update.symbol.as_deref()) => Ok(()),
+                    Ok(update) => outbox.push_update(update),
+                    Err(FeedError::Lagged(skipped)) => outbox.lagged(skipped),
+                    Err(FeedError::Ended(e)) => {
+                        tracing::warn!("Feed for {} ended: {}", addr, e);
+                        break None;
                     }
+                };
+                if let Err(reason) = queued {
+                    tracing::warn!("Disconnecting {}: {}", addr, reason);
+                    break Some(CloseFrame { code: CloseCode::Policy, reason: reason.into() });
                 }
             }
 
-            // Handle incoming WebSocket messages (just for ping/pong)
+            // Close the session properly when the server shuts down
+            _ = shutdown::wait() => {
+                break Some(CloseFrame { code: CloseCode::Away, reason: "Server shutting down".into() });
+            }
+
+            _ = tick(&mut heartbeat) => {
+                if awaiting_pong {
+                    tracing::warn!("Disconnecting {}: no pong since the last ping", addr);
+                    METRICS.websocket_ping_timeouts.fetch_add(1, Ordering::Relaxed);
+                    break Some(CloseFrame { code: CloseCode::Policy, reason: "Ping timeout".into() });
+                }
+                outbox.push_control(Message::Ping(Vec::new()));
+                awaiting_pong = true;
+            }
+
+            // The writer only returns once the connection can't be written to
+            _ = &mut writer => {
+                break None;
+            }
+
+            // Handle incoming WebSocket messages (ping/pong and client commands)
             ws_msg = ws_receiver.next() => {
+                // Anything from the client shows it is still there
+                awaiting_pong = false;
                 match ws_msg {
-                    Some(Ok(msg)) => {
-                        // Only handle ping messages
-                        if msg.is_ping() {
-                            if ws_sender.send(Message::Pong(vec![])).await.is_err() {
-                                break;
+                    Some(Ok(Message::Text(text))) => {
+                        let reply = match ClientCommand::parse(&text) {
+                            Ok(command) => {
+                                // Newly subscribed symbols start from a full snapshot
+                                if matches!(command, ClientCommand::Subscribe(_)) {
+                                    outbox.resnapshot();
+                                }
+                                // Rows read before a seek are from the old position
+                                if matches!(command, ClientCommand::Seek(_)) {
+                                    outbox.discard_updates();
+                                }
+                                apply_command(command, &mut subscriptions, &mut feed).await
                             }
-                        }
-                        // Ignore all other messages from client
+                            Err(e) => error_json(&e),
+                        };
+                        outbox.push_control(Message::Text(reply));
                     },
+                    Some(Ok(Message::Ping(_))) => outbox.push_control(Message::Pong(Vec::new())),
+                    // Ignore all other messages from client
+                    Some(Ok(_)) => {},
                     Some(Err(e)) => {
-                        eprintln!("WebSocket error from {}: {}", addr, e);
-                        break;
+                        tracing::warn!("WebSocket error from {}: {}", addr, e);
+                        break None;
                     },
-                    None => {
-                        // WebSocket stream has ended
-                        break;
-                    }
+                    // WebSocket stream has ended
+                    None => break None,
                 }
             }
         }
+    };
+
+    // Give the writer a moment to send what was queued before the close frame
+    if let Some(frame) = close {
+        outbox.close(frame);
+        let _ = tokio::time::timeout(CLOSE_TIMEOUT, &mut writer).await;
     }
 
-    println!("Client disconnected: {}", addr);
-}
\ No newline at end of file
+    METRICS.websocket_clients.fetch_sub(1, Ordering::Relaxed);
+    tracing::info!("Client disconnected: {}", addr);
+}
+
+// Wait for the next heartbeat, forever when pings are off
+async fn tick(heartbeat: &mut Option<tokio::time::Interval>) {
+    match heartbeat {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,370 @@
+// Zero-copy views of the ITCH 5.0 messages: each struct lays out the message after its type byte
+// with big endian fields, so handlers read named fields straight from the file's bytes. The views
+// have no alignment and their size is checked against the spec length at compile time
+
+use zerocopy::byteorder::big_endian::{U16, U32, U64};
+use zerocopy::{FromBytes, Immutable, KnownLayout, Unaligned};
+
+use crate::message_types::MessageType;
+
+// Nanoseconds since midnight in 6 bytes
+#[derive(Debug, Clone, Copy, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(transparent)]
+pub struct Timestamp([u8; 6]);
+
+impl Timestamp {
+    #[inline(always)]
+    pub fn get(self) -> u64 {
+        let mut bytes = [0u8; 8];
+        bytes[2..].copy_from_slice(&self.0);
+        u64::from_be_bytes(bytes)
+    }
+}
+
+// Common to all messages
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Header {
+    pub stock_locate: U16,
+    pub tracking_number: U16,
+    pub timestamp: Timestamp,
+}
+
+impl Header {
+    // Header of any message after its type byte, None if the data is too short
+    #[inline(always)]
+    pub fn view(data: &[u8]) -> Option<&Self> {
+        Self::ref_from_prefix(data).ok().map(|(header, _)| header)
+    }
+}
+
+pub trait MessageView: FromBytes + KnownLayout + Immutable + Unaligned {
+    const MESSAGE_TYPE: MessageType;
+
+    // View of the message after its type byte, None if the data is too short
+    #[inline(always)]
+    fn view(data: &[u8]) -> Option<&Self> {
+        Self::ref_from_prefix(data).ok().map(|(message, _)| message)
+    }
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct SystemEvent {
+    pub header: Header,
+    pub event_code: u8,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct StockDirectory {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub market_category: u8,
+    pub financial_status_indicator: u8,
+    pub round_lot_size: U32,
+    pub round_lots_only: u8,
+    pub issue_classification: u8,
+    pub issue_sub_type: [u8; 2],
+    pub authenticity: u8,
+    pub short_sale_threshold_indicator: u8,
+    pub ipo_flag: u8,
+    pub luld_reference_price_tier: u8,
+    pub etp_flag: u8,
+    pub etp_leverage_factor: U32,
+    pub inverse_indicator: u8,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct StockTradingAction {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub trading_state: u8,
+    pub reserved: u8,
+    pub reason: [u8; 4],
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct RegShoRestriction {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub reg_sho_action: u8,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct MarketParticipantPosition {
+    pub header: Header,
+    pub mpid: [u8; 4],
+    pub stock: [u8; 8],
+    pub primary_market_maker: u8,
+    pub market_maker_mode: u8,
+    pub market_participant_state: u8,
+}
+
+// Levels are prices with 8 decimals
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct MwcbDeclineLevel {
+    pub header: Header,
+    pub level_1: U64,
+    pub level_2: U64,
+    pub level_3: U64,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct MwcbStatus {
+    pub header: Header,
+    pub breached_level: u8,
+}
+
+// The release time is in seconds since midnight
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct IpoQuotingPeriodUpdate {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub ipo_quotation_release_time: U32,
+    pub ipo_quotation_release_qualifier: u8,
+    pub ipo_price: U32,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct LuldAuctionCollar {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub auction_collar_reference_price: U32,
+    pub upper_auction_collar_price: U32,
+    pub lower_auction_collar_price: U32,
+    pub auction_collar_extension: U32,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct OperationalHalt {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub market_code: u8,
+    pub operational_halt_action: u8,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct AddOrder {
+    pub header: Header,
+    pub order_ref_number: U64,
+    pub buy_sell_indicator: u8,
+    pub shares: U32,
+    pub stock: [u8; 8],
+    pub price: U32,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct AddOrderWithMpid {
+    pub header: Header,
+    pub order_ref_number: U64,
+    pub buy_sell_indicator: u8,
+    pub shares: U32,
+    pub stock: [u8; 8],
+    pub price: U32,
+    pub attribution: [u8; 4],
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct OrderExecuted {
+    pub header: Header,
+    pub order_ref_number: U64,
+    pub executed_shares: U32,
+    pub match_number: U64,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct OrderExecutedWithPrice {
+    pub header: Header,
+    pub order_ref_number: U64,
+    pub executed_shares: U32,
+    pub match_number: U64,
+    pub printable: u8,
+    pub execution_price: U32,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct OrderCancel {
+    pub header: Header,
+    pub order_ref_number: U64,
+    pub cancelled_shares: U32,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct OrderDelete {
+    pub header: Header,
+    pub order_ref_number: U64,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct OrderReplace {
+    pub header: Header,
+    pub original_order_ref_number: U64,
+    pub new_order_ref_number: U64,
+    pub shares: U32,
+    pub price: U32,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Trade {
+    pub header: Header,
+    pub order_ref_number: U64,
+    pub buy_sell_indicator: u8,
+    pub shares: U32,
+    pub stock: [u8; 8],
+    pub price: U32,
+    pub match_number: U64,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct CrossTrade {
+    pub header: Header,
+    pub shares: U64,
+    pub stock: [u8; 8],
+    pub cross_price: U32,
+    pub match_number: U64,
+    pub cross_type: u8,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct BrokenTrade {
+    pub header: Header,
+    pub match_number: U64,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Noii {
+    pub header: Header,
+    pub paired_shares: U64,
+    pub imbalance_shares: U64,
+    pub imbalance_direction: u8,
+    pub stock: [u8; 8],
+    pub far_price: U32,
+    pub near_price: U32,
+    pub current_reference_price: U32,
+    pub cross_type: u8,
+    pub price_variation_indicator: u8,
+}
+
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Rpii {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub interest_flag: u8,
+}
+
+// The near execution time is in nanoseconds since midnight
+#[derive(Debug, FromBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct DirectListingWithCapitalRaisePriceDiscovery {
+    pub header: Header,
+    pub stock: [u8; 8],
+    pub open_eligibility_status: u8,
+    pub minimum_allowable_price: U32,
+    pub maximum_allowable_price: U32,
+    pub near_execution_price: U32,
+    pub near_execution_time: U64,
+    pub lower_price_range_collar: U32,
+    pub upper_price_range_collar: U32,
+}
+
+// Each view is named after its message type, and with the type byte its size is the spec length.
+// The views also make up the variants of ItchMessage
+macro_rules! message_views {
+    ($($name:ident),* $(,)?) => {
+        $(
+            impl MessageView for $name {
+                const MESSAGE_TYPE: MessageType = MessageType::$name;
+            }
+
+            const _: () = assert!(size_of::<$name>() + 1 == MessageType::$name.spec_length().unwrap());
+        )*
+
+        // Any ITCH 5.0 message as the view of its type, borrowing the data it was decoded from
+        #[derive(Debug, Clone, Copy)]
+        pub enum ItchMessage<'a> {
+            $($name(&'a $name),)*
+            // A type byte that isn't ITCH 5.0, or a message shorter than its type's layout
+            Unknown(&'a [u8]),
+        }
+
+        // The message after its type byte as the view of its type, None for unknown types and short messages
+        pub fn decode_body(message_type: MessageType, body: &[u8]) -> Option<ItchMessage<'_>> {
+            match message_type {
+                $(MessageType::$name => $name::view(body).map(ItchMessage::$name),)*
+                MessageType::Unknown => None,
+            }
+        }
+
+        impl<'a> ItchMessage<'a> {
+            pub fn message_type(&self) -> MessageType {
+                match self {
+                    $(ItchMessage::$name(_) => MessageType::$name,)*
+                    ItchMessage::Unknown(_) => MessageType::Unknown,
+                }
+            }
+
+            pub fn header(&self) -> Option<&'a Header> {
+                match self {
+                    $(ItchMessage::$name(message) => Some(&message.header),)*
+                    ItchMessage::Unknown(_) => None,
+                }
+            }
+        }
+    };
+}
+
+message_views!(SystemEvent, StockDirectory, StockTradingAction, RegShoRestriction, MarketParticipantPosition, MwcbDeclineLevel,
+               MwcbStatus, IpoQuotingPeriodUpdate, LuldAuctionCollar, OperationalHalt, AddOrder, AddOrderWithMpid, OrderExecuted,
+               OrderExecutedWithPrice, OrderCancel, OrderDelete, OrderReplace, Trade, CrossTrade, BrokenTrade, Noii, Rpii,
+               DirectListingWithCapitalRaisePriceDiscovery);
+
+// Decode one message that starts with its type byte, as it follows the length prefix in a file
+pub fn decode(data: &[u8]) -> ItchMessage<'_> {
+    data.split_first()
+        .and_then(|(&type_byte, body)| decode_body(MessageType::from(type_byte), body))
+        .unwrap_or(ItchMessage::Unknown(data))
+}
+
+impl<'a> ItchMessage<'a> {
+    // Stock symbol of the message (space padded), for the types that carry one
+    pub fn stock(&self) -> Option<&'a [u8; 8]> {
+        match self {
+            ItchMessage::StockDirectory(message) => Some(&message.stock),
+            ItchMessage::StockTradingAction(message) => Some(&message.stock),
+            ItchMessage::RegShoRestriction(message) => Some(&message.stock),
+            ItchMessage::MarketParticipantPosition(message) => Some(&message.stock),
+            ItchMessage::IpoQuotingPeriodUpdate(message) => Some(&message.stock),
+            ItchMessage::LuldAuctionCollar(message) => Some(&message.stock),
+            ItchMessage::OperationalHalt(message) => Some(&message.stock),
+            ItchMessage::AddOrder(message) => Some(&message.stock),
+            ItchMessage::AddOrderWithMpid(message) => Some(&message.stock),
+            ItchMessage::Trade(message) => Some(&message.stock),
+            ItchMessage::CrossTrade(message) => Some(&message.stock),
+            ItchMessage::Noii(message) => Some(&message.stock),
+            ItchMessage::Rpii(message) => Some(&message.stock),
+            ItchMessage::DirectListingWithCapitalRaisePriceDiscovery(message) => Some(&message.stock),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// Runs a sink on its own thread, so formatting and writing the output overlap with parsing instead of
+// holding it up. The parser hands over snapshots through a bounded queue; when the disk can't keep up
+// the queue fills and the parser waits, which is counted in the metrics. Items go over in batches, as
+// handing them over one at a time costs more than writing them
+
+use std::io;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::affinity;
+use crate::metrics::METRICS;
+use crate::orderbook::{BookSnapshot, Levels, MarketEvent, OrderMessage, TradeRecord};
+use crate::sink::OutputSink;
+
+// Items per batch sent to the thread
+const BATCH_SIZE: usize = 256;
+
+// Snapshots are most of the items, so boxing them to shrink the rest would only add an allocation each
+#[allow(clippy::large_enum_variant)]
+enum Item {
+    Snapshot(Arc<str>, BookSnapshot),
+    Trade(Arc<str>, TradeRecord),
+    Event(MarketEvent),
+    Order(Arc<str>, OrderMessage, Levels, Levels),
+    Counts(u64, u64, u64),
+    Flush,
+}
+
+pub struct WriterThread {
+    sender: Option<SyncSender<Vec<Item>>>,
+    batch: Vec<Item>,
+    handle: Option<JoinHandle<io::Result<()>>>,
+    // Symbol of the last item, shared by the queued items instead of copied into each
+    symbol: Arc<str>,
+    wants_orders: bool,
+    // Snapshots, trades and events handed over, and how often the queue was full
+    queued: u64,
+    full: u64,
+    blocked_ns: u64,
+}
+
+fn write_items(mut sink: Box<dyn OutputSink>, receiver: Receiver<Vec<Item>>) -> io::Result<()> {
+    for item in receiver.into_iter().flatten() {
+        match item {
+            Item::Snapshot(symbol, snapshot) => sink.write_snapshot(&symbol, &snapshot)?,
+            Item::Trade(symbol, trade) => sink.write_trade(&symbol, &trade)?,
+            Item::Event(event) => sink.write_event(&event)?,
+            Item::Order(symbol, message, bids, asks) => sink.write_order(&symbol, &message, &bids, &asks)?,
+            Item::Counts(messages, book_updates, skipped_updates) => sink.update_counts(messages, book_updates, skipped_updates),
+            Item::Flush => sink.flush()?,
+        }
+    }
+    // The queue closes when the writer is finalized
+    sink.finalize()
+}
+
+impl WriterThread {
+    // Move the sink to a new thread with room for about `capacity` items in the queue
+    pub fn spawn(sink: Box<dyn OutputSink>, capacity: usize) -> io::Result<Self> {
+        WriterThread::spawn_pinned(sink, capacity, None)
+    }
+
+    // Same, with the thread pinned to a core
+    pub fn spawn_pinned(sink: Box<dyn OutputSink>, capacity: usize, core: Option<usize>) -> io::Result<Self> {
+        let wants_orders = sink.wants_orders();
+        let (sender, receiver) = mpsc::sync_channel(capacity.div_ceil(BATCH_SIZE));
+        let handle = thread::Builder::new()
+            .name("output-writer".to_string())
+            .spawn(move || {
+                affinity::pin_current_thread(core.as_slice())?;
+                write_items(sink, receiver)
+            })?;
+        Ok(WriterThread { sender: Some(sender), batch: Vec::with_capacity(BATCH_SIZE), handle: Some(handle), symbol: Arc::from(""), wants_orders, queued: 0, full: 0, blocked_ns: 0 })
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Arc<str> {
+        if &*self.symbol != symbol {
+            self.symbol = Arc::from(symbol);
+        }
+        self.symbol.clone()
+    }
+
+    fn push(&mut self, item: Item) -> io::Result<()> {
+        self.batch.push(item);
+        self.queued += 1;
+        if self.batch.len() >= BATCH_SIZE {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let Some(sender) = &self.sender else {
+            return Err(io::Error::other("Output writer is already finalized"));
+        };
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        let sent = match sender.try_send(batch) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(batch)) => {
+                let start = Instant::now();
+                let sent = sender.send(batch).map_err(|_| ());
+                let blocked_ns = start.elapsed().as_nanos() as u64;
+                self.full += 1;
+                self.blocked_ns += blocked_ns;
+                METRICS.writer_queue_full.fetch_add(1, Ordering::Relaxed);
+                METRICS.writer_blocked_ns.fetch_add(blocked_ns, Ordering::Relaxed);
+                sent
+            }
+            Err(TrySendError::Disconnected(_)) => Err(()),
+        };
+        // The thread only hangs up when writing failed, so its error is the one to report
+        match sent {
+            Ok(()) => Ok(()),
+            Err(()) => self.join().and(Err(io::Error::other("Output writer stopped"))),
+        }
+    }
+
+    fn join(&mut self) -> io::Result<()> {
+        self.sender = None;
+        match self.handle.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("Output writer panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl OutputSink for WriterThread {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let symbol = self.symbol(symbol);
+        self.push(Item::Snapshot(symbol, snapshot.clone()))
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        let symbol = self.symbol(symbol);
+        self.push(Item::Trade(symbol, trade.clone()))
+    }
+
+    fn write_event(&mut self, event: &MarketEvent) -> io::Result<()> {
+        self.push(Item::Event(event.clone()))
+    }
+
+    fn write_order(&mut self, symbol: &str, message: &OrderMessage, bids: &Levels, asks: &Levels) -> io::Result<()> {
+        let symbol = self.symbol(symbol);
+        self.push(Item::Order(symbol, message.clone(), *bids, *asks))
+    }
+
+    fn wants_orders(&self) -> bool {
+        self.wants_orders
+    }
+
+    fn update_counts(&mut self, messages: u64, book_updates: u64, skipped_updates: u64) {
+        // Goes with the next batch
+        self.batch.push(Item::Counts(messages, book_updates, skipped_updates));
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.batch.push(Item::Flush);
+        self.send()
+    }
+
+    // Wait for the queue to drain and the sink to finish
+    fn finalize(&mut self) -> io::Result<()> {
+        self.send()?;
+        self.join()?;
+        tracing::info!("Output writer: {} items written, queue full {} times, parser waited {:.1} ms",
+                       self.queued, self.full, self.blocked_ns as f64 / 1e6);
+        Ok(())
+    }
+}
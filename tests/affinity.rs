@@ -0,0 +1,22 @@
+// --pin-cores hands out its cores to the parser, the writer and the runtime in that order
+use nasdaq_itch_orderbook::affinity::{self, CorePlan};
+
+#[test]
+fn plan() {
+    assert_eq!(CorePlan::new(&[]), CorePlan::default());
+    assert_eq!(CorePlan::new(&[3]), CorePlan { parser: Some(3), writer: None, runtime: Vec::new() });
+    assert_eq!(CorePlan::new(&[0, 2, 4, 6]), CorePlan { parser: Some(0), writer: Some(2), runtime: vec![4, 6] });
+}
+
+#[test]
+fn pin_thread() {
+    assert!(affinity::pin_current_thread(&[]).is_ok());
+    // Runs on a thread of its own so the test harness's threads keep their cores
+    let result = std::thread::spawn(|| affinity::pin_current_thread(&[0])).join().unwrap();
+    if cfg!(target_os = "linux") {
+        result.unwrap();
+        assert!(std::thread::spawn(|| affinity::pin_current_thread(&[usize::MAX])).join().unwrap().is_err());
+    } else {
+        assert!(result.is_err());
+    }
+}
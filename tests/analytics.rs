@@ -0,0 +1,98 @@
+// --stats-output summarizes the symbol's order flow as JSON at the end of the run, with the top of book
+// statistics weighing each best bid and ask by how long it held
+mod common;
+
+use common::{add, cancel, delete, execute, replace, TempFile};
+use nasdaq_itch_orderbook::analytics::OrderAnalytics;
+use nasdaq_itch_orderbook::orderbook::PriceLevel;
+use serde_json::Value;
+
+const MILLISECOND: u64 = 1_000_000;
+const SECOND: u64 = 1_000_000_000;
+
+#[test]
+fn summarizes_order_flow() {
+    let input = TempFile::with("analytics.itch", &[
+        add(MILLISECOND, 1, b'B', 100, 1_500_000),
+        add(2 * MILLISECOND, 2, b'S', 200, 1_501_000),
+        add(2 * MILLISECOND + 500_000, 3, b'B', 50, 1_499_000),
+        execute(3 * MILLISECOND, 1, 100, 1),
+        cancel(4 * MILLISECOND, 2, 50),
+        delete(5 * MILLISECOND, 2),
+        // The new order carries on the replaced one, so it isn't an add
+        replace(6 * MILLISECOND, 3, 4, 60, 1_499_500),
+    ].concat());
+    let output = TempFile::new("analytics.csv");
+    let stats = TempFile::new("analytics.json");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--stats-output", stats.path()]);
+    let stats: Value = serde_json::from_str(&stats.read()).unwrap();
+
+    assert_eq!(stats["symbol"], "AAPL");
+    assert_eq!(stats["orders"], serde_json::json!({
+        "adds": 3, "executes": 1, "cancels": 1, "deletes": 1, "replaces": 1, "resting_at_end": 1,
+        "add_to_cancel_ratio": 1.5, "cancel_rate": 1.0 / 3.0,
+    }));
+    assert_eq!(stats["shares"], serde_json::json!({
+        "added": 350, "executed": 100, "cancelled": 200,
+        "executed_fraction": 100.0 / 350.0, "cancelled_fraction": 200.0 / 350.0,
+    }));
+    let lifetimes = &stats["lifetimes"];
+    assert_eq!(lifetimes["all"]["count"], 3);
+    assert_eq!(lifetimes["all"]["min_ns"], 2 * MILLISECOND);
+    assert_eq!(lifetimes["all"]["max_ns"], 3 * MILLISECOND + 500_000);
+    assert_eq!(lifetimes["executed"]["p50_ns"], 2 * MILLISECOND);
+    assert_eq!(lifetimes["cancelled"]["p50_ns"], 3 * MILLISECOND);
+    assert_eq!(lifetimes["replaced"]["p50_ns"], 3 * MILLISECOND + 500_000);
+    let histogram = lifetimes["all"]["histogram"].as_array().unwrap();
+    assert_eq!(histogram[4], serde_json::json!({ "bucket": "<10ms", "count": 3 }));
+    assert_eq!(stats["symbol_messages"], serde_json::json!({ "A": 3, "D": 1, "E": 1, "U": 1, "X": 1 }));
+    assert_eq!(stats["busiest_milliseconds"][0], serde_json::json!({ "time": "00:00:00.002", "millisecond": 2, "messages": 2 }));
+}
+
+fn level(price: u32, total_volume: u32) -> Option<PriceLevel> {
+    Some(PriceLevel { price, total_volume })
+}
+
+#[test]
+fn weighs_the_top_of_book_by_time() {
+    let path = std::env::temp_dir().join(format!("analytics-{}.json", std::process::id()));
+    let mut analytics = OrderAnalytics::new(&path);
+    // One-sided for 1s, a 2 cent spread for 3s, locked for 1s, crossed for 1s, then 1 cent until the last message
+    analytics.update_top(10 * SECOND, level(1_000_000, 100), None);
+    analytics.update_top(11 * SECOND, level(1_000_000, 100), level(1_000_200, 300));
+    analytics.update_top(14 * SECOND, level(1_000_200, 500), level(1_000_200, 300));
+    analytics.update_top(15 * SECOND, level(1_000_300, 500), level(1_000_200, 300));
+    analytics.update_top(16 * SECOND, level(1_000_000, 100), level(1_000_100, 100));
+    analytics.add(b'A', 1, 20 * SECOND, 100);
+    analytics.finalize("AAPL").unwrap();
+
+    let summary: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let top = &summary["top_of_book"];
+    assert_eq!(top["observed_ns"], 10 * SECOND);
+    assert_eq!(top["two_sided_ns"], 9 * SECOND);
+    let close = |name: &str, expected: f64| {
+        let value = top[name].as_f64().unwrap();
+        assert!((value - expected).abs() < 1e-9, "{} {} != {}", name, value, expected);
+    };
+    close("average_spread", (3.0 * 0.02 - 0.01 + 4.0 * 0.01) / 9.0);
+    close("average_bid_depth", (4.0 * 100.0 + 2.0 * 500.0 + 4.0 * 100.0) / 10.0);
+    close("average_ask_depth", (5.0 * 300.0 + 4.0 * 100.0) / 9.0);
+    close("locked_percent", 100.0 / 9.0);
+    close("crossed_percent", 100.0 / 9.0);
+}
+
+#[test]
+fn leaves_the_averages_empty_without_a_quote() {
+    let path = std::env::temp_dir().join(format!("analytics-empty-{}.json", std::process::id()));
+    let mut analytics = OrderAnalytics::new(&path);
+    analytics.update_top(SECOND, level(1_000_000, 100), None);
+    analytics.update_top(2 * SECOND, level(1_000_000, 100), None);
+    analytics.finalize("AAPL").unwrap();
+    let summary: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let top = &summary["top_of_book"];
+    assert_eq!(top["observed_ns"], SECOND);
+    assert!(top["average_spread"].is_null() && top["average_ask_depth"].is_null() && top["locked_percent"].is_null());
+    assert_eq!(top["average_bid_depth"], 100.0);
+}
@@ -0,0 +1,55 @@
+// --anomalies-output writes every broken book invariant, with the message that broke it
+mod common;
+
+use common::{add, binary, cancel, delete, directory, execute, TempFile, LOCATE, SYMBOL};
+
+#[test]
+fn writes_each_kind_of_anomaly() {
+    let input = TempFile::with("anomalies.itch", &[
+        directory(LOCATE, SYMBOL, 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        add(3, 2, b'S', 200, 1_500_500),
+        // A bid at the ask locks the book, and one above it crosses it only after the lock clears
+        add(4, 3, b'B', 100, 1_500_500),
+        add(5, 4, b'B', 100, 1_500_600),
+        delete(6, 3),
+        delete(7, 4),
+        add(8, 5, b'B', 100, 1_500_600),
+        delete(9, 5),
+        // Orders that were never added, or were already deleted
+        execute(10, 99, 10, 1),
+        cancel(11, 3, 10),
+        // More shares executed than the order holds
+        execute(12, 1, 150, 2),
+    ].concat());
+    let output = TempFile::new("anomalies.csv");
+    let anomalies = TempFile::new("anomalies-found.csv");
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--anomalies-output", anomalies.path()])
+        .output().unwrap();
+    assert!(result.status.success());
+
+    assert_eq!(anomalies.read(), "\
+timestamp,anomaly,message_type,order_ref,price,shares,detail
+4,locked,A,0,150.0500,0,ask 150.0500
+8,crossed,A,0,150.0600,0,ask 150.0500
+10,unknown_order_ref,E,99,0.0000,10,
+11,unknown_order_ref,X,3,0.0000,10,
+12,negative_level,E,1,150.0000,150,removed more shares than the order or level held
+");
+    assert!(String::from_utf8_lossy(&result.stdout)
+        .contains("Found 5 book anomalies (1 crossed, 1 locked, 2 unknown order refs, 1 negative levels, 0 out of band adds)"));
+}
+
+#[test]
+fn ignores_other_symbols() {
+    let input = TempFile::with("anomalies-other.itch", &[
+        directory(LOCATE, SYMBOL, 100),
+        directory(2, "MSFT", 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        common::execute_of(2, 3, 99, 10, 1),
+    ].concat());
+    let output = TempFile::new("anomalies-other.csv");
+    let anomalies = TempFile::new("anomalies-other-found.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--anomalies-output", anomalies.path()]);
+    assert_eq!(anomalies.read().lines().count(), 1);
+}
@@ -0,0 +1,59 @@
+// The HTTP API serves the latest book, the trades in a time range and the counters of the run
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+mod common;
+
+use common::{add, execute, free_port, http_get, system_event, trade, Server, TempFile};
+
+const NINE_THIRTY: u64 = 34_200_000_000_000;
+const SECOND: u64 = 1_000_000_000;
+
+fn get(port: u16, path: &str) -> (u16, Value) {
+    let (status, body) = http_get(port, path);
+    (status, serde_json::from_str(&body).unwrap())
+}
+
+#[test]
+fn serves_the_book_trades_and_stats() {
+    let input = TempFile::with("api.itch", &[
+        add(NINE_THIRTY, 1, b'B', 100, 1_500_000),
+        add(NINE_THIRTY + 1, 2, b'S', 200, 1_500_500),
+        execute(NINE_THIRTY + SECOND, 1, 30, 1),
+        trade(NINE_THIRTY + 60 * SECOND, b'B', 50, 1_500_200, 2),
+        system_event(NINE_THIRTY + 61 * SECOND, b'C'),
+    ].concat());
+    let output = TempFile::new("api.csv");
+    let port = free_port();
+    let _server = Server::start(&["serve", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "-p", &free_port().to_string(),
+                                  "--http-port", &port.to_string()], port);
+
+    let mut stats = Value::Null;
+    for _ in 0..100 {
+        stats = get(port, "/stats").1;
+        if stats["finished"] == true {
+            break;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!((&stats["symbols"], &stats["trades"]), (&serde_json::json!(["AAPL"]), &2.into()));
+    assert!(stats["messages"].as_u64().unwrap() >= 4);
+
+    let (status, book) = get(port, "/book/aapl");
+    assert_eq!(status, 200);
+    assert_eq!((&book["1_bid_price"], &book["1_bid_vol"]), (&150.0.into(), &70.into()));
+    assert_eq!((&book["1_ask_price"], &book["1_ask_vol"]), (&150.05.into(), &200.into()));
+    assert_eq!(get(port, "/book/MSFT").0, 404);
+
+    let (status, trades) = get(port, "/trades/AAPL");
+    assert_eq!(status, 200);
+    let kinds: Vec<&str> = trades.as_array().unwrap().iter().map(|trade| trade["message_type"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["E", "P"]);
+    let (_, later) = get(port, "/trades/AAPL?from=09:30:30");
+    assert_eq!((later[0]["price"].as_f64(), later.as_array().unwrap().len()), (Some(150.02), 1));
+    let (_, earlier) = get(port, "/trades/AAPL?to=09:30:30");
+    assert_eq!((earlier[0]["shares"].as_u64(), earlier.as_array().unwrap().len()), (Some(30), 1));
+    assert_eq!(get(port, "/trades/AAPL?from=noon").0, 400);
+}
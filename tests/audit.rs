@@ -0,0 +1,52 @@
+// --audit follows every symbol's orders and reports, per stock locate, the order messages whose
+// order ref was never added and the orders left resting
+mod common;
+
+use common::{add, add_of, cancel, delete, directory, execute, execute_of, replace, TempFile, LOCATE, SYMBOL};
+
+fn audit(name: &str, contents: &[u8]) -> (String, String) {
+    let input = TempFile::with(&format!("{}.itch", name), contents);
+    let output = TempFile::new(&format!("{}.csv", name));
+    let report = TempFile(output.0.with_extension("audit.csv"));
+    let result = common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--audit"]);
+    (String::from_utf8_lossy(&result.stdout).into_owned(), report.read())
+}
+
+#[test]
+fn reconciles_every_symbol() {
+    let (log, report) = audit("audit", &[
+        directory(LOCATE, SYMBOL, 100),
+        directory(2, "MSFT", 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        add(3, 2, b'S', 200, 1_500_500),
+        execute(4, 1, 30, 1),
+        replace(5, 2, 3, 150, 1_500_400),
+        cancel(6, 3, 50),
+        add_of(2, "MSFT", 7, 10, b'B', 300, 4_000_000),
+        execute_of(2, 8, 10, 300, 2),
+        // MSFT orders from before the file started
+        execute_of(2, 9, 11, 100, 3),
+        common::message(b'D', 2, 10, &[&12u64.to_be_bytes()]),
+        delete(11, 1),
+    ].concat());
+
+    assert_eq!(report, "\
+symbol,stock_locate,adds,executes,cancels,deletes,replaces,unknown_executes,unknown_cancels,unknown_deletes,unknown_replaces,resting_orders,resting_shares
+AAPL,1,2,1,1,1,1,0,0,0,0,1,100
+MSFT,2,1,2,0,1,0,1,0,1,0,0,0
+");
+    assert!(log.contains("1 orders left resting at the end of the file"), "{}", log);
+    assert!(log.contains("2 messages in 1 symbols reference unknown orders (1 executes, 0 cancels, 1 deletes, 0 replaces)"), "{}", log);
+}
+
+#[test]
+fn confirms_a_complete_session() {
+    let (log, report) = audit("audit-complete", &[
+        directory(LOCATE, SYMBOL, 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        execute(3, 1, 100, 1),
+    ].concat());
+
+    assert!(report.ends_with("AAPL,1,1,1,0,0,0,0,0,0,0,0,0\n"), "{}", report);
+    assert!(log.contains("Every order message references a known order"), "{}", log);
+}
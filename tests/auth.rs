@@ -0,0 +1,67 @@
+// With tokens, the WebSocket server only serves clients that present one in the query string or as their first
+// message, and closes every other connection
+#![cfg(feature = "native")]
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use nasdaq_itch_orderbook::auth::AuthTokens;
+use nasdaq_itch_orderbook::websocket::{BookUpdate, WebSocketServer, BROADCAST_CAPACITY};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn next_message(client: &mut Client) -> Message {
+    tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn only_serves_clients_with_a_token() {
+    let port = 18_475;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let server = WebSocketServer::live(tx.clone(), port).with_auth(AuthTokens::new(vec!["s3cret".to_string(), "other".to_string()]));
+    tokio::spawn(async move { server.start().await });
+
+    let url = format!("ws://127.0.0.1:{}", port);
+    let mut with_query = loop {
+        match tokio_tungstenite::connect_async(format!("{}/?token=s3cret", url)).await {
+            Ok((client, _)) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+
+    // A wrong token in the query is refused at the handshake
+    assert!(tokio_tungstenite::connect_async(format!("{}/?token=guess", url)).await.is_err());
+
+    // Without one, the first message must carry it
+    let (mut with_message, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    with_message.send(Message::Text("{\"token\":\"other\"}".to_string())).await.unwrap();
+    assert_eq!(next_message(&mut with_message).await, Message::Text("{\"type\":\"auth\",\"authenticated\":true}".to_string()));
+
+    let (mut without, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    without.send(Message::Text("{\"subscribe\":[\"AAPL\"]}".to_string())).await.unwrap();
+    let Message::Close(Some(frame)) = next_message(&mut without).await else {
+        panic!("expected a close frame");
+    };
+    assert_eq!(frame.code, CloseCode::Policy);
+
+    // Both authenticated clients get the feed
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tx.send(BookUpdate { symbol: None, json: "{\"type\":\"test\"}".to_string(), snapshot: None }).unwrap();
+    for client in [&mut with_query, &mut with_message] {
+        assert_eq!(next_message(client).await, Message::Text("{\"type\":\"test\"}".to_string()));
+    }
+}
+
+#[test]
+fn reads_tokens_file() {
+    let path = std::env::temp_dir().join(format!("tokens-{}.txt", std::process::id()));
+    std::fs::write(&path, "# replay clients\nalpha\n\n  beta  \n").unwrap();
+    let tokens = AuthTokens::new(AuthTokens::read(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert!(tokens.accepts("alpha") && tokens.accepts("beta"));
+    assert!(!tokens.accepts("alph") && !tokens.accepts("") && !tokens.accepts("# replay clients"));
+}
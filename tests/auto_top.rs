@@ -0,0 +1,31 @@
+// --auto-top picks the symbols with the most messages and builds a book of each, with the symbol in its file names
+use std::path::Path;
+
+use nasdaq_itch_orderbook::partition::symbol_output;
+use nasdaq_itch_orderbook::profile::most_active_symbols;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+
+#[test]
+fn picks_the_busiest_symbols() {
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    let all = most_active_symbols(&data, 100, None);
+    assert_eq!(all.len(), 8);
+    assert!(all.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+    let top = most_active_symbols(&data, 3, None);
+    assert_eq!(top, all[..3]);
+
+    // A window counts fewer messages, starting from the first add
+    let early = most_active_symbols(&data, 100, Some(10_000_000));
+    let early_messages: u64 = early.iter().map(|(_, messages)| messages).sum();
+    let all_messages: u64 = all.iter().map(|(_, messages)| messages).sum();
+    assert!(early_messages > 0 && early_messages < all_messages);
+}
+
+#[test]
+fn names_the_outputs_after_the_symbol() {
+    // A template with {symbol} is filled in by the sink
+    assert_eq!(symbol_output(Path::new("books/{symbol}.csv"), "AAPL"), Path::new("books/{symbol}.csv"));
+    assert_eq!(symbol_output(Path::new("books.csv"), "AAPL"), Path::new("books.AAPL.csv"));
+    assert_eq!(symbol_output(Path::new("books"), "AAPL"), Path::new("books.AAPL"));
+}
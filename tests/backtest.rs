@@ -0,0 +1,91 @@
+// Simulated orders follow their queue position against the real flow and fill when the feed trades
+// through them
+use std::io;
+
+mod common;
+
+use common::{add, cancel, execute, TempFile, SYMBOL};
+use nasdaq_itch_orderbook::backtest::{Backtest, Fill, OrderScript, SimulatedOrders, Strategy};
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions, Side};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+const PRICE: u32 = 100_000;
+
+fn feed() -> Vec<u8> {
+    [
+        add(1, 1, b'B', 100, PRICE),
+        add(2, 2, b'B', 200, PRICE),
+        add(3, 3, b'S', 100, PRICE + 1000),
+        // The simulated order is placed here, behind 300 shares
+        cancel(4, 1, 50),
+        add(5, 4, b'B', 100, PRICE),
+        execute(6, 1, 50, 6),
+        execute(7, 2, 200, 7),
+        // Order 4 joined behind the simulated order, so the aggressor reached it first
+        execute(8, 4, 30, 8),
+        // A sell added through the bid would have traded with the rest
+        add(9, 5, b'S', 40, PRICE - 1000),
+    ].concat()
+}
+
+#[derive(Default)]
+struct JoinBid {
+    queue: Vec<(u64, u64)>,
+    rejected: bool,
+    fills: Vec<Fill>,
+}
+
+impl Strategy for JoinBid {
+    fn on_message(&mut self, book: &OrderBook, orders: &mut SimulatedOrders) -> io::Result<()> {
+        if orders.timestamp() == 4 {
+            self.rejected = orders.place(book, Side::Buy, PRICE + 1000, 50).is_err();
+            orders.place(book, Side::Buy, PRICE, 50)?;
+        }
+        if let Some(order) = orders.get(1) {
+            self.queue.push((orders.timestamp(), order.queue_ahead));
+        }
+        Ok(())
+    }
+
+    fn on_fill(&mut self, fill: &Fill) -> io::Result<()> {
+        self.fills.push(fill.clone());
+        Ok(())
+    }
+}
+
+fn run<S: Strategy>(strategy: S) -> Backtest<S> {
+    let book = OrderBook::new(pad_stock_symbol(SYMBOL), OrderBookOptions::default());
+    let mut backtests = [Backtest::new(book, strategy)];
+    parser::process_itch_file(&feed(), &mut backtests, &ParserOptions::default()).unwrap();
+    let [backtest] = backtests;
+    backtest
+}
+
+#[test]
+fn queue_position_and_fills() {
+    let backtest = run(JoinBid::default());
+    let strategy = backtest.strategy();
+    assert!(strategy.rejected);
+    assert_eq!(strategy.queue, [(4, 300), (5, 250), (6, 250), (7, 200), (8, 0), (9, 0)]);
+    assert_eq!(backtest.fills(), [
+        Fill { timestamp: 8, order_id: 1, side: Side::Buy, price: PRICE, shares: 30, remaining: 20, age_ns: 4 },
+        Fill { timestamp: 9, order_id: 1, side: Side::Buy, price: PRICE, shares: 20, remaining: 0, age_ns: 5 },
+    ]);
+    assert_eq!(strategy.fills, backtest.fills());
+    assert_eq!(backtest.orders().open().count(), 0);
+}
+
+#[test]
+fn order_script() {
+    let path = TempFile::with("backtest.csv", b"# time,side,price,shares\n4,buy,10.0,50\n4,sell,10.1,10\n5,sell,9.9,10\n6,cancel,2\n");
+    let script = OrderScript::read(&path.0).unwrap();
+
+    let backtest = run(script);
+    // The sell through the bid was rejected and the other sell cancelled before anything reached it
+    assert_eq!(backtest.strategy().rejected(), 1);
+    assert_eq!(backtest.orders().placed(), 2);
+    let filled: u32 = backtest.fills().iter().map(|fill| fill.shares).sum();
+    assert_eq!(filled, 50);
+    assert!(backtest.fills().iter().all(|fill| fill.order_id == 1));
+}
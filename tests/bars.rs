@@ -0,0 +1,84 @@
+// Printable trades are aggregated into OHLCV bars per interval, which close once market time passes their end
+// and go to the bars file and, with --stream-bars, to the live feed
+#![cfg(feature = "native")]
+use nasdaq_itch_orderbook::bars::{Bar, BarBuilder};
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use nasdaq_itch_orderbook::websocket::LiveSink;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+const SECOND: u64 = 1_000_000_000;
+
+#[test]
+fn aggregates_trades_per_interval() {
+    let mut builder = BarBuilder::new(&[SECOND, 60 * SECOND], None).unwrap();
+    let mut closed = Vec::new();
+    builder.add(SECOND / 2, 1_000_000, 100, &mut closed);
+    builder.add(SECOND / 2 + 1, 1_010_000, 50, &mut closed);
+    builder.add(SECOND - 1, 990_000, 10, &mut closed);
+    assert!(closed.is_empty());
+    assert!(!builder.due(SECOND - 1) && builder.due(SECOND));
+
+    // Market time moving past the end closes the 1s bar without another trade
+    builder.close_until(SECOND, &mut closed);
+    assert_eq!(closed, [Bar {
+        interval: SECOND, start: 0, open: 1_000_000, high: 1_010_000, low: 990_000, close: 990_000, volume: 160,
+        notional: 1_000_000 * 100 + 1_010_000 * 50 + 990_000 * 10, trades: 3,
+    }]);
+    assert!((closed[0].vwap() - 100.25).abs() < 1e-9, "{}", closed[0].vwap());
+
+    // Quiet seconds get no bar, and the minute bar keeps going
+    closed.clear();
+    builder.add(5 * SECOND, 1_020_000, 20, &mut closed);
+    assert!(closed.is_empty());
+    builder.close_all(&mut closed);
+    assert_eq!(closed.len(), 2);
+    assert_eq!((closed[0].start, closed[0].volume, closed[0].trades), (5 * SECOND, 20, 1));
+    assert_eq!((closed[1].interval, closed[1].start, closed[1].open, closed[1].close, closed[1].volume), (60 * SECOND, 0, 1_000_000, 1_020_000, 180));
+    let json: Value = serde_json::from_str(&closed[1].to_json("AAPL")).unwrap();
+    assert_eq!((json["type"].as_str(), json["interval"].as_str(), json["high"].as_f64()), (Some("bar"), Some("1m"), Some(102.0)));
+}
+
+#[test]
+fn bars_add_up_to_the_trades() {
+    let path = std::env::temp_dir().join(format!("bars-{}.csv", std::process::id()));
+    let (tx, mut rx) = broadcast::channel(1_000_000);
+    let sink = Box::new(LiveSink::new(tx, false, FeatureColumns::default()).with_bars());
+    let bars = BarBuilder::new(&[SECOND, 10 * SECOND], Some(&path)).unwrap();
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], bars: Some(bars), ..Default::default() })];
+    let data = SyntheticFeed { messages: 50_000, rate: 1_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    books[0].finalize().unwrap();
+    let stats = books[0].trade_stats();
+    assert!(stats.trades > 0);
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("symbol,interval,start,open,high,low,close,volume,trades,vwap"));
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+    for interval in ["1s", "10s"] {
+        let rows: Vec<_> = rows.iter().filter(|row| row[1] == interval).collect();
+        assert!(rows.len() > 1, "{}", interval);
+        assert!(rows.windows(2).all(|pair| pair[0][2].parse::<u64>().unwrap() < pair[1][2].parse::<u64>().unwrap()));
+        assert_eq!(rows.iter().map(|row| row[7].parse::<u64>().unwrap()).sum::<u64>(), stats.volume);
+        assert_eq!(rows.iter().map(|row| row[8].parse::<u64>().unwrap()).sum::<u64>(), stats.trades);
+        for row in &rows {
+            let [open, high, low, close] = [3, 4, 5, 6].map(|column| row[column].parse::<f64>().unwrap());
+            assert!(low <= open.min(close) && high >= open.max(close), "{:?}", row);
+        }
+    }
+
+    // The same bars on the live feed, between the book updates
+    let streamed: Vec<Value> = std::iter::from_fn(|| rx.try_recv().ok())
+        .filter(|update| update.snapshot.is_none())
+        .map(|update| serde_json::from_str(&update.json).unwrap())
+        .collect();
+    assert_eq!(streamed.len(), rows.len());
+    assert!(streamed.iter().all(|bar| bar["type"] == "bar" && bar["symbol"] == "AAPL"));
+    assert_eq!(streamed.iter().filter(|bar| bar["interval"] == "1s").map(|bar| bar["volume"].as_u64().unwrap()).sum::<u64>(), stats.volume);
+}
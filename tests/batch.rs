@@ -0,0 +1,34 @@
+// --file-glob picks the daily files by name and names each day's outputs after its input
+use std::path::{Path, PathBuf};
+
+use nasdaq_itch_orderbook::batch::{day_name, day_output, expand_glob};
+
+#[test]
+fn expands_wildcards_in_name_order() {
+    let dir = std::env::temp_dir().join(format!("batch-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub.itch")).unwrap();
+    for name in ["20200131.itch", "20200130.itch", "20200130.csv", "2020013.itch"] {
+        std::fs::write(dir.join(name), b"").unwrap();
+    }
+
+    let files = expand_glob(&dir.join("2020013?.itch").to_string_lossy()).unwrap();
+    let star = expand_glob(&dir.join("*.itch").to_string_lossy()).unwrap();
+    let none = expand_glob(&dir.join("*.pcap").to_string_lossy());
+    let nested = expand_glob(&dir.join("*/x.itch").to_string_lossy());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(files, [dir.join("20200130.itch"), dir.join("20200131.itch")]);
+    // Directories don't match
+    assert_eq!(star, [dir.join("2020013.itch"), dir.join("20200130.itch"), dir.join("20200131.itch")]);
+    assert!(none.is_err());
+    assert!(nested.is_err());
+}
+
+#[test]
+fn day_outputs() {
+    let input = Path::new("data/01302020.NASDAQ_ITCH50");
+    assert_eq!(day_name(input), "01302020");
+    assert_eq!(day_output(Path::new("out/AAPL.csv"), input), PathBuf::from("out/AAPL.01302020.csv"));
+    assert_eq!(day_output(Path::new("out/AAPL"), input), PathBuf::from("out/AAPL.01302020"));
+    assert_eq!(day_output(Path::new("out/{file}/AAPL.parquet"), input), PathBuf::from("out/01302020/AAPL.parquet"));
+}
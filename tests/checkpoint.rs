@@ -0,0 +1,267 @@
+// A run resumed from a checkpoint writes the same rows and anomalies after it as a run over the whole file
+mod common;
+
+use rusqlite::Connection;
+
+use common::{add, add_with_mpid, binary, cancel, delete, directory, execute, message, replace, retail_interest, stock, system_event, trade, TempFile,
+             LOCATE, SYMBOL};
+
+const NINE_THIRTY: u64 = 34_200_000_000_000;
+const MILLISECOND: u64 = 1_000_000;
+// The first checkpoint is due 5ms of market time after the first message, and the next one 5ms later
+const CHECKPOINT_TIME: u64 = NINE_THIRTY + 5 * MILLISECOND;
+
+fn at(ms: u64) -> u64 {
+    NINE_THIRTY + ms * MILLISECOND
+}
+
+fn input(name: &str) -> TempFile {
+    TempFile::with(name, &[
+        add(at(0), 1, b'B', 100, 1_500_000),
+        add(at(1), 2, b'S', 200, 1_500_500),
+        add(at(2), 3, b'B', 100, 1_499_000),
+        retail_interest(at(2), b'B'),
+        message(b'J', LOCATE, at(2), &[&stock(SYMBOL), &1_500_000u32.to_be_bytes(), &1_575_000u32.to_be_bytes(),
+                                       &1_425_000u32.to_be_bytes(), &1u32.to_be_bytes()]),
+        message(b'W', 0, at(3), &[b"1"]),
+        message(b'Y', LOCATE, at(3), &[&stock(SYMBOL), b"1"]),
+        message(b'h', LOCATE, at(3), &[&stock(SYMBOL), b"QH"]),
+        execute(at(3), 1, 30, 1),
+        // Locks the book across the checkpoint
+        add(at(4), 4, b'B', 50, 1_500_500),
+        add(at(5), 5, b'S', 100, 1_501_000),
+        // Behind the top, so no row for --write-mode on-top-of-book-change
+        add(at(6), 6, b'B', 100, 1_498_000),
+        delete(at(7), 4),
+        add(at(8), 7, b'B', 10, 1_500_500),
+        cancel(at(9), 2, 100),
+    ].concat())
+}
+
+// Rows (or anomalies) of a CSV file after the checkpoint, with its header
+fn after_checkpoint(csv: &str) -> String {
+    let mut lines = csv.lines();
+    let mut kept = format!("{}\n", lines.next().unwrap());
+    for line in lines.filter(|line| line.split(',').next().unwrap().parse::<u64>().unwrap() > CHECKPOINT_TIME) {
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    kept
+}
+
+// Runs over the whole input writing a checkpoint, then resumes from it, and compares the output after it
+fn assert_resumes(name: &str, options: &[&str]) {
+    let input = input(&format!("{}.itch", name));
+    let checkpoint = TempFile::new(&format!("{}.bin", name));
+    let (whole, whole_anomalies) = (TempFile::new(&format!("{}-whole.csv", name)), TempFile::new(&format!("{}-whole-anomalies.csv", name)));
+    let (resumed, resumed_anomalies) = (TempFile::new(&format!("{}-resumed.csv", name)), TempFile::new(&format!("{}-resumed-anomalies.csv", name)));
+
+    let log = common::run(&[&["build-book", "-f", input.path(), "-o", whole.path(), "--anomalies-output", whole_anomalies.path(),
+                              "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()][..], options].concat());
+    let log = String::from_utf8_lossy(&log.stdout);
+    assert_eq!(log.matches("Wrote checkpoint").count(), 1, "{}", log);
+
+    common::run(&[&["build-book", "-f", input.path(), "-o", resumed.path(), "--anomalies-output", resumed_anomalies.path(),
+                    "--resume-from", checkpoint.path()][..], options].concat());
+    let expected = after_checkpoint(&whole.read());
+    assert_eq!(expected.lines().count(), 4, "{}", expected);
+    assert_eq!(resumed.read(), expected);
+    let expected = after_checkpoint(&whole_anomalies.read());
+    assert_eq!(expected.lines().count(), 2, "{}", expected);
+    assert_eq!(resumed_anomalies.read(), expected);
+}
+
+#[test]
+fn resumes_with_the_same_output() {
+    assert_resumes("checkpoint", &["-s", "AAPL", "--bbo-only", "--write-mode", "on-top-of-book-change"]);
+}
+
+#[test]
+fn resumes_feature_columns() {
+    assert_resumes("checkpoint-features", &["-s", "AAPL", "--bbo-only", "--write-mode", "on-top-of-book-change",
+                                            "--features", "vwap,volume,ofi,microprice,spread-ticks,retail-interest,luld,mwcb,ssr,halts"]);
+}
+
+#[test]
+fn resumes_with_the_fast_filter() {
+    // The restored orders go into the filter, or their later deletes and cancels would be dropped
+    assert_resumes("checkpoint-fast-filter", &["-s", "AAPL", "--bbo-only", "--write-mode", "on-top-of-book-change", "--fast-filter"]);
+}
+
+#[test]
+fn resumes_with_the_excluded_odd_lots() {
+    // With a round lot of 50, the odd-lot bid at 100.01 is kept off the book before the checkpoint, and replaced by
+    // a round lot after it
+    let input = TempFile::with("checkpoint-odd-lots.itch", &[
+        directory(LOCATE, SYMBOL, 50),
+        add(at(0), 1, b'B', 120, 1_000_000),
+        add(at(1), 2, b'B', 30, 1_000_100),
+        add(at(2), 3, b'S', 75, 1_000_500),
+        add(at(5), 4, b'S', 20, 1_000_400),
+        execute(at(6), 4, 10, 1),
+        // A round lot of 50, not of the default 100
+        add(at(7), 5, b'B', 60, 999_900),
+        replace(at(8), 2, 6, 80, 1_000_200),
+        execute(at(9), 6, 50, 2),
+    ].concat());
+    let checkpoint = TempFile::new("checkpoint-odd-lots.bin");
+    let (whole, resumed) = (TempFile::new("checkpoint-odd-lots-whole.csv"), TempFile::new("checkpoint-odd-lots-resumed.csv"));
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", whole.path(), "--odd-lots", "exclude",
+                  "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", resumed.path(), "--odd-lots", "exclude",
+                  "--resume-from", checkpoint.path()]);
+
+    let expected = after_checkpoint(&whole.read());
+    assert_eq!(expected.lines().count(), 4, "{}", expected);
+    assert_eq!(resumed.read(), expected);
+}
+
+#[test]
+fn resumes_with_the_dropped_stub_quotes() {
+    // The one cent stub bid is dropped before the checkpoint, and replaced in the band after it
+    let input = TempFile::with("checkpoint-price-band.itch", &[
+        add(at(0), 1, b'B', 100, 1_000_000),
+        add(at(1), 2, b'S', 100, 1_010_000),
+        add(at(2), 3, b'B', 100, 100),
+        add(at(5), 4, b'B', 100, 990_000),
+        execute(at(6), 3, 50, 1),
+        replace(at(7), 3, 5, 100, 1_001_000),
+        execute(at(8), 5, 40, 2),
+    ].concat());
+    let checkpoint = TempFile::new("checkpoint-price-band.bin");
+    let (whole, resumed) = (TempFile::new("checkpoint-price-band-whole.csv"), TempFile::new("checkpoint-price-band-resumed.csv"));
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", whole.path(), "--price-band", "50", "--price-band-action", "drop",
+                  "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", resumed.path(), "--price-band", "50", "--price-band-action", "drop",
+                  "--resume-from", checkpoint.path()]);
+
+    let expected = after_checkpoint(&whole.read());
+    assert_eq!(expected.lines().count(), 3, "{}", expected);
+    assert_eq!(resumed.read(), expected);
+}
+
+#[test]
+fn resumes_the_market_hours() {
+    // An early end of market hours before the checkpoint makes the rows after it post-market
+    let input = TempFile::with("checkpoint-sessions.itch", &[
+        add(at(0), 1, b'B', 100, 1_000_000),
+        system_event(at(1), b'M'),
+        add(at(5), 2, b'B', 100, 1_000_100),
+        add(at(6), 3, b'B', 100, 1_000_200),
+    ].concat());
+    let checkpoint = TempFile::new("checkpoint-sessions.bin");
+    let (whole, resumed) = (TempFile::new("checkpoint-sessions-whole.csv"), TempFile::new("checkpoint-sessions-resumed.csv"));
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", whole.path(), "--date", "2019-11-29", "--timezone", "America/New_York",
+                  "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", resumed.path(), "--date", "2019-11-29", "--timezone", "America/New_York",
+                  "--resume-from", checkpoint.path()]);
+
+    // With --date the timestamps are since the epoch, so the rows after the checkpoint are the last one
+    let whole = whole.read();
+    let lines: Vec<&str> = whole.lines().collect();
+    assert!(lines[3].ends_with(",post_market"), "{}", whole);
+    assert_eq!(resumed.read(), format!("{}\n{}\n", lines[0], lines[3]));
+}
+
+#[test]
+fn rejects_another_symbols_checkpoint() {
+    let input = input("checkpoint-symbol.itch");
+    let checkpoint = TempFile::new("checkpoint-symbol.bin");
+    let output = TempFile::new("checkpoint-symbol.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "MSFT", "-o", output.path(), "--resume-from", checkpoint.path()]).output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Checkpoint is for symbol AAPL, not MSFT"));
+}
+
+#[test]
+fn rejects_resuming_rolling_windows() {
+    let input = input("checkpoint-windows.itch");
+    let checkpoint = TempFile::new("checkpoint-windows.bin");
+    let output = TempFile::new("checkpoint-windows.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--features", "volatility",
+                                "--resume-from", checkpoint.path()]).output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("--resume-from doesn't support the returns and volatility features"));
+}
+
+#[test]
+fn rejects_resuming_order_analytics() {
+    let input = input("checkpoint-analytics.itch");
+    let checkpoint = TempFile::new("checkpoint-analytics.bin");
+    let output = TempFile::new("checkpoint-analytics.csv");
+    let stats = TempFile::new("checkpoint-analytics.json");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--stats-output", stats.path(),
+                                "--resume-from", checkpoint.path()]).output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("--resume-from doesn't support --stats-output"));
+}
+
+#[test]
+fn rejects_resuming_bars() {
+    let input = input("checkpoint-bars.itch");
+    let checkpoint = TempFile::new("checkpoint-bars.bin");
+    let output = TempFile::new("checkpoint-bars.csv");
+    let bars = TempFile::new("checkpoint-bars-ohlcv.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--bars-output", bars.path(),
+                                "--resume-from", checkpoint.path()]).output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("--resume-from doesn't support --bars-output"));
+}
+
+#[test]
+fn resumes_the_tick_test() {
+    // With only a bid, trades are signed by the tick test: after an uptick before the checkpoint, a trade at the same
+    // price takes its direction, and a lower one is a sell
+    for (price, aggressor) in [(1_000_600, "B"), (1_000_550, "S")] {
+        let input = TempFile::with("checkpoint-tick.itch", &[
+            add(at(0), 1, b'B', 100, 1_000_000),
+            trade(at(1), b'B', 100, 1_000_500, 1),
+            trade(at(5), b'B', 100, 1_000_600, 2),
+            trade(at(7), b'B', 100, price, 3),
+        ].concat());
+        let checkpoint = TempFile::new("checkpoint-tick.bin");
+        let output = TempFile::new("checkpoint-tick.db");
+        common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--output-format", "sqlite",
+                      "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+        let resumed = TempFile::new("checkpoint-tick-resumed.db");
+        common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", resumed.path(), "--output-format", "sqlite",
+                      "--resume-from", checkpoint.path()]);
+
+        let conn = Connection::open(resumed.path()).unwrap();
+        let mut stmt = conn.prepare("SELECT timestamp, aggressor, sign_method FROM trades ORDER BY timestamp").unwrap();
+        let signs: Vec<(i64, String, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap().map(Result::unwrap).collect();
+        assert_eq!(signs, [(at(7) as i64, aggressor.to_string(), "tick".to_string())]);
+    }
+}
+
+#[test]
+fn resumes_the_participant_report() {
+    let input = TempFile::with("checkpoint-participants.itch", &[
+        add_with_mpid(at(0), 1, b'B', 100, 1_500_000, b"GSCO"),
+        add_with_mpid(at(1), 2, b'S', 200, 1_501_000, b"MSCO"),
+        execute(at(5), 1, 30, 1),
+        // After the checkpoint, on orders added before it
+        cancel(at(6), 2, 50),
+        add_with_mpid(at(7), 3, b'B', 100, 1_499_000, b"GSCO"),
+        delete(at(8), 1),
+    ].concat());
+    let checkpoint = TempFile::new("checkpoint-participants.bin");
+    let output = TempFile::new("checkpoint-participants.csv");
+    let (whole, resumed) = (TempFile::new("checkpoint-participants-whole.csv"), TempFile::new("checkpoint-participants-resumed.csv"));
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--participants-output", whole.path(),
+                  "--checkpoint-interval", "5ms", "--checkpoint-file", checkpoint.path()]);
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--participants-output", resumed.path(),
+                  "--resume-from", checkpoint.path()]);
+
+    assert_eq!(resumed.read(), whole.read());
+    assert_eq!(whole.read().lines().nth(1), Some("GSCO,2,200,1,30,1,70,0"));
+}
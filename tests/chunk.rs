@@ -0,0 +1,29 @@
+// A run cut short by max_messages reports where it ended, and a second run from that offset parses exactly
+// the rest of the input
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+fn run(data: &[u8], options: ParserOptions) -> (u64, u64) {
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions::default())];
+    parser::process_itch_file(data, &mut books, &options).unwrap();
+    (books[0].counts().0, books[0].end_offset().unwrap())
+}
+
+#[test]
+fn resumes_where_it_ended() {
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    let (total, end) = run(&data, ParserOptions::default());
+    assert_eq!(end, data.len() as u64);
+
+    let (first, offset) = run(&data, ParserOptions { max_messages: Some(7_000), ..Default::default() });
+    assert_eq!(first, 7_000);
+    assert!(parser::is_message_boundary(&data, offset as usize));
+    assert!(!parser::is_message_boundary(&data, offset as usize + 1));
+
+    let (rest, end) = run(&data, ParserOptions { start_offset: offset as usize, ..Default::default() });
+    assert_eq!(first + rest, total);
+    assert_eq!(end, data.len() as u64);
+    assert!(parser::is_message_boundary(&data, data.len()));
+}
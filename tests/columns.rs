@@ -0,0 +1,69 @@
+// --columns writes only the chosen columns of each row, in the order they were given
+use std::fs::File;
+use std::path::PathBuf;
+
+mod common;
+
+use common::{add, build};
+use nasdaq_itch_orderbook::features::{FeatureColumns, SnapshotFeature};
+use nasdaq_itch_orderbook::orderbook::{OrderBookOptions, OutputFormat};
+use nasdaq_itch_orderbook::sink::create_file_sink;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+
+fn columns(names: &[&str]) -> FeatureColumns {
+    FeatureColumns::new(&[SnapshotFeature::SpreadTicks], &[]).with_selection(names.iter().map(|name| name.to_string()).collect())
+}
+
+// Writes a $150.01 bid and a $150.04 ask to a file and returns its path
+fn write(format: OutputFormat, bbo_only: bool, names: &[&str]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("columns-{:?}-{}-{}", format, bbo_only, std::process::id()));
+    let sink = create_file_sink(format, &path, bbo_only, columns(names), None).unwrap();
+    let data = [add(1, 1, b'B', 100, 1_500_100), add(2, 2, b'S', 100, 1_500_400)].concat();
+    build(&data, OrderBookOptions { bbo_only, sinks: vec![sink], ..Default::default() });
+    path
+}
+
+fn lines(format: OutputFormat, bbo_only: bool, names: &[&str]) -> Vec<String> {
+    let path = write(format, bbo_only, names);
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    text.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn writes_the_chosen_csv_columns() {
+    assert_eq!(lines(OutputFormat::Csv, false, &["timestamp", "1_bid_price", "1_ask_price", "mid_price"]),
+               ["timestamp,1_bid_price,1_ask_price,mid_price", "1,150.0100,0.0000,0.0000", "2,150.0100,150.0400,150.0250"]);
+    // In the given order, computed columns included
+    assert_eq!(lines(OutputFormat::Csv, true, &["spread_ticks", "ask", "timestamp"]), ["spread_ticks,ask,timestamp", "0,0.0000,1", "3,150.0400,2"]);
+}
+
+#[test]
+fn writes_the_chosen_json_fields() {
+    let lines = lines(OutputFormat::Jsonl, true, &["timestamp", "bid", "spread"]);
+    let json: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+    assert_eq!(json, serde_json::json!({"symbol": "AAPL", "timestamp": "2", "bid": 150.01, "spread": 0.03}));
+}
+
+#[test]
+fn writes_the_chosen_parquet_columns() {
+    let path = write(OutputFormat::Parquet, false, &["1_ask_price", "timestamp", "spread_ticks"]);
+    let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+    let names: Vec<String> = reader.metadata().file_metadata().schema_descr().columns().iter().map(|column| column.name().to_string()).collect();
+    let row = reader.get_row_iter(None).unwrap().last().unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(names, ["1_ask_price", "timestamp", "spread_ticks"]);
+    assert_eq!((row.get_double(0).unwrap(), row.get_long(1).unwrap(), row.get_long(2).unwrap()), (150.04, 2, 3));
+}
+
+#[test]
+fn rejects_unknown_and_repeated_columns() {
+    let path = std::env::temp_dir().join(format!("columns-rejected-{}", std::process::id()));
+    let error = create_file_sink(OutputFormat::Csv, &path, true, columns(&["timestamp", "1_bid_price"]), None).err().unwrap();
+    assert_eq!(error.to_string(), "Unknown column \"1_bid_price\" in --columns");
+    let error = create_file_sink(OutputFormat::Parquet, &path, true, columns(&["bid", "bid"]), None).err().unwrap();
+    assert_eq!(error.to_string(), "Column \"bid\" is listed twice in --columns");
+    assert!(create_file_sink(OutputFormat::Sqlite, &path, true, columns(&["bid"]), None).is_err());
+    assert!(!path.exists());
+}
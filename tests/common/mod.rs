@@ -0,0 +1,180 @@
+// ITCH messages, a book driver and a runner of the binary shared by the integration tests; each test file uses a few of them
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+// The symbol most tests follow, and its stock locate
+pub const SYMBOL: &str = "AAPL";
+pub const LOCATE: u16 = 1;
+
+pub fn stock(symbol: &str) -> [u8; 8] {
+    let mut stock = [b' '; 8];
+    stock[..symbol.len()].copy_from_slice(symbol.as_bytes());
+    stock
+}
+
+// A message as stored in the file: its length, then the type, the header fields and the rest of the fields
+pub fn message(message_type: u8, stock_locate: u16, timestamp: u64, fields: &[&[u8]]) -> Vec<u8> {
+    let mut body = vec![message_type];
+    body.extend_from_slice(&stock_locate.to_be_bytes());
+    body.extend_from_slice(&0u16.to_be_bytes());
+    body.extend_from_slice(&timestamp.to_be_bytes()[2..]);
+    for field in fields {
+        body.extend_from_slice(field);
+    }
+    let mut framed = (body.len() as u16).to_be_bytes().to_vec();
+    framed.extend(body);
+    framed
+}
+
+// A stock directory entry, which is how the book learns its symbol's stock locate
+pub fn directory(stock_locate: u16, symbol: &str, round_lot_size: u32) -> Vec<u8> {
+    message(b'R', stock_locate, 1, &[&stock(symbol), b"QN", &round_lot_size.to_be_bytes(), b"NC  PNN1N", &0u32.to_be_bytes(), b"N"])
+}
+
+pub fn add(timestamp: u64, order_ref_number: u64, side: u8, shares: u32, price: u32) -> Vec<u8> {
+    add_of(LOCATE, SYMBOL, timestamp, order_ref_number, side, shares, price)
+}
+
+pub fn add_of(stock_locate: u16, symbol: &str, timestamp: u64, order_ref_number: u64, side: u8, shares: u32, price: u32) -> Vec<u8> {
+    message(b'A', stock_locate, timestamp, &[&order_ref_number.to_be_bytes(), &[side], &shares.to_be_bytes(), &stock(symbol),
+                                            &price.to_be_bytes()])
+}
+
+pub fn add_with_mpid(timestamp: u64, order_ref_number: u64, side: u8, shares: u32, price: u32, mpid: &[u8; 4]) -> Vec<u8> {
+    message(b'F', LOCATE, timestamp, &[&order_ref_number.to_be_bytes(), &[side], &shares.to_be_bytes(), &stock(SYMBOL),
+                                       &price.to_be_bytes(), mpid])
+}
+
+pub fn execute(timestamp: u64, order_ref_number: u64, executed_shares: u32, match_number: u64) -> Vec<u8> {
+    execute_of(LOCATE, timestamp, order_ref_number, executed_shares, match_number)
+}
+
+pub fn execute_of(stock_locate: u16, timestamp: u64, order_ref_number: u64, executed_shares: u32, match_number: u64) -> Vec<u8> {
+    message(b'E', stock_locate, timestamp, &[&order_ref_number.to_be_bytes(), &executed_shares.to_be_bytes(), &match_number.to_be_bytes()])
+}
+
+pub fn cancel(timestamp: u64, order_ref_number: u64, cancelled_shares: u32) -> Vec<u8> {
+    message(b'X', LOCATE, timestamp, &[&order_ref_number.to_be_bytes(), &cancelled_shares.to_be_bytes()])
+}
+
+pub fn delete(timestamp: u64, order_ref_number: u64) -> Vec<u8> {
+    message(b'D', LOCATE, timestamp, &[&order_ref_number.to_be_bytes()])
+}
+
+pub fn replace(timestamp: u64, original_order_ref_number: u64, new_order_ref_number: u64, shares: u32, price: u32) -> Vec<u8> {
+    message(b'U', LOCATE, timestamp, &[&original_order_ref_number.to_be_bytes(), &new_order_ref_number.to_be_bytes(), &shares.to_be_bytes(),
+                                       &price.to_be_bytes()])
+}
+
+pub fn retail_interest(timestamp: u64, interest_flag: u8) -> Vec<u8> {
+    message(b'N', LOCATE, timestamp, &[&stock(SYMBOL), &[interest_flag]])
+}
+
+pub fn system_event(timestamp: u64, event_code: u8) -> Vec<u8> {
+    message(b'S', 0, timestamp, &[&[event_code]])
+}
+
+// A non-cross trade
+pub fn trade(timestamp: u64, side: u8, shares: u32, price: u32, match_number: u64) -> Vec<u8> {
+    message(b'P', LOCATE, timestamp, &[&0u64.to_be_bytes(), &[side], &shares.to_be_bytes(), &stock(SYMBOL), &price.to_be_bytes(),
+                                       &match_number.to_be_bytes()])
+}
+
+// The symbol's book after the data, finalized
+pub fn build(data: &[u8], options: OrderBookOptions) -> OrderBook {
+    let mut books = [OrderBook::new(pad_stock_symbol(SYMBOL), options)];
+    parser::process_itch_file(data, &mut books, &ParserOptions::default()).unwrap();
+    let [mut book] = books;
+    book.finalize().unwrap();
+    book
+}
+
+// A file in the temp directory, removed when dropped
+pub struct TempFile(pub PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str) -> Self {
+        TempFile(std::env::temp_dir().join(format!("{}-{}", std::process::id(), name)))
+    }
+
+    pub fn with(name: &str, contents: &[u8]) -> Self {
+        let file = TempFile::new(name);
+        std::fs::write(&file.0, contents).unwrap();
+        file
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    pub fn read(&self) -> String {
+        std::fs::read_to_string(&self.0).unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+pub fn binary() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_nasdaq-itch-orderbook"))
+}
+
+// Runs the binary to the end, failing the test if it fails
+pub fn run(args: &[&str]) -> Output {
+    let output = binary().args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+// A server started from the binary, killed when dropped
+pub struct Server(Child);
+
+impl Server {
+    // Starts the binary and waits until it accepts connections on the port
+    pub fn start(args: &[&str], port: u16) -> Self {
+        let server = Server(binary().args(args).stdout(Stdio::null()).stderr(Stdio::null()).spawn().unwrap());
+        for _ in 0..200 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return server;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("server didn't start on port {}", port);
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+// A port nothing listens on
+pub fn free_port() -> u16 {
+    TcpListener::bind(("127.0.0.1", 0)).unwrap().local_addr().unwrap().port()
+}
+
+// GET of a path on a local port, as the status code and the body
+pub fn http_get(port: u16, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
@@ -0,0 +1,63 @@
+// compare lines up two orderbook outputs by timestamp, whatever rows and format each one has, and stops at
+// the first timestamp where their books differ
+use std::path::{Path, PathBuf};
+
+use nasdaq_itch_orderbook::compare::{compare, LevelDiff};
+use nasdaq_itch_orderbook::compress::Compression;
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions, OutputFormat, WriteMode};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::sink::create_file_sink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+// Write the synthetic feed's AAPL book to each file
+fn build(files: &[(&Path, OutputFormat, Option<Compression>)], write_mode: WriteMode, bbo_only: bool) {
+    let sinks = files.iter()
+        .map(|&(path, format, compression)| create_file_sink(format, path, bbo_only, FeatureColumns::default(), compression).unwrap())
+        .collect();
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { write_mode, bbo_only, sinks, ..Default::default() })];
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    books[0].finalize().unwrap();
+}
+
+#[test]
+fn matches_across_formats_and_reports_the_first_difference() {
+    let dir = std::env::temp_dir().join(format!("compare-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| -> PathBuf { dir.join(name) };
+    build(&[(&path("all.csv"), OutputFormat::Csv, None), (&path("all.parquet"), OutputFormat::Parquet, None)], WriteMode::EveryMessage, false);
+    build(&[(&path("changes.jsonl.zst"), OutputFormat::Jsonl, Some(Compression::Zstd(3)))], WriteMode::OnChange, false);
+    build(&[(&path("bbo.csv"), OutputFormat::Csv, None)], WriteMode::EveryMessage, true);
+
+    let same = compare(&path("all.csv"), &path("all.parquet"), None).unwrap();
+    assert_eq!((same.depth, same.divergence), (10, None));
+    assert!(same.timestamps > 1000);
+    // Rows only where the book changed describe the same books
+    assert_eq!(compare(&path("all.csv"), &path("changes.jsonl.zst"), None).unwrap().divergence, None);
+    // A BBO file has one level, so only the top is compared
+    let bbo = compare(&path("bbo.csv"), &path("all.csv"), None).unwrap();
+    assert_eq!((bbo.depth, bbo.divergence), (1, None));
+
+    // Change the volume of the best bid in a row from the middle, the last one at its timestamp
+    let csv = std::fs::read_to_string(path("all.csv")).unwrap();
+    let mut lines: Vec<String> = csv.lines().map(str::to_string).collect();
+    let timestamp_of = |line: &str| line.split(',').next().unwrap().to_string();
+    let row = (lines.len() / 2..lines.len() - 1)
+        .find(|&row| lines[row].split(',').nth(2) != Some("0") && timestamp_of(&lines[row]) != timestamp_of(&lines[row + 1]))
+        .unwrap();
+    let mut fields: Vec<String> = lines[row].split(',').map(str::to_string).collect();
+    let (timestamp, price, volume) = (fields[0].parse::<u64>().unwrap(), fields[1].clone(), fields[2].parse::<u32>().unwrap());
+    fields[2] = (volume + 1).to_string();
+    lines[row] = fields.join(",");
+    std::fs::write(path("changed.csv"), lines.join("\n") + "\n").unwrap();
+
+    let changed = compare(&path("all.parquet"), &path("changed.csv"), None).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let divergence = changed.divergence.unwrap();
+    assert_eq!(divergence.timestamp, timestamp);
+    assert_eq!(divergence.right_row, row);
+    let price = (price.parse::<f64>().unwrap() * 10000.0).round() as u32;
+    assert_eq!(divergence.levels, [LevelDiff { side: "bid", level: 1, left: (price, volume), right: (price, volume + 1) }]);
+}
@@ -0,0 +1,51 @@
+// Compressed output files decompress to exactly the uncompressed output
+use std::io::Read;
+use std::path::Path;
+
+use nasdaq_itch_orderbook::compress::{output_path, Compression};
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions, OutputFormat};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::sink::create_file_sink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+fn write(format: OutputFormat, path: &Path, compression: Option<Compression>) -> Vec<u8> {
+    let sink = create_file_sink(format, path, false, FeatureColumns::default(), compression).unwrap();
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], ..Default::default() })];
+    parser::process_itch_file(&SyntheticFeed { messages: 20_000, ..Default::default() }.generate(), &mut books, &ParserOptions::default()).unwrap();
+    books[0].finalize().unwrap();
+    let path = output_path(path, compression);
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    data
+}
+
+#[test]
+fn parses_levels() {
+    assert_eq!(Compression::parse("gzip"), Ok(Compression::Gzip(6)));
+    assert_eq!(Compression::parse("gzip:9"), Ok(Compression::Gzip(9)));
+    assert_eq!(Compression::parse("zstd:19"), Ok(Compression::Zstd(19)));
+    assert!(Compression::parse("zstd:0").is_err());
+    assert!(Compression::parse("gzip:10").is_err());
+    assert!(Compression::parse("lz4").is_err());
+    assert_eq!(output_path(Path::new("AAPL.csv"), Some(Compression::Gzip(6))), Path::new("AAPL.csv.gz"));
+    assert_eq!(output_path(Path::new("AAPL.csv.zst"), Some(Compression::Zstd(3))), Path::new("AAPL.csv.zst"));
+}
+
+#[test]
+fn round_trips() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    for format in [OutputFormat::Csv, OutputFormat::Jsonl] {
+        let plain = write(format, &dir.join(format!("compress-{}.out", id)), None);
+        let gzip = write(format, &dir.join(format!("compress-{}.gz.out", id)), Some(Compression::Gzip(6)));
+        let zstd = write(format, &dir.join(format!("compress-{}.zst.out", id)), Some(Compression::Zstd(3)));
+
+        let mut unzipped = Vec::new();
+        flate2::read::GzDecoder::new(gzip.as_slice()).read_to_end(&mut unzipped).unwrap();
+        assert!(!plain.is_empty());
+        assert_eq!(unzipped, plain);
+        assert_eq!(zstd::decode_all(zstd.as_slice()).unwrap(), plain);
+    }
+}
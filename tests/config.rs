@@ -0,0 +1,51 @@
+// --config fills in the options of a run from a TOML file, under the ones given on the command line
+mod common;
+
+use common::{add, binary, execute, TempFile};
+
+fn input() -> TempFile {
+    TempFile::with("config.itch", &[
+        add(1, 1, b'B', 100, 1_500_000),
+        add(2, 2, b'S', 200, 1_500_500),
+        add(3, 3, b'B', 100, 1_499_000),
+        execute(4, 1, 30, 7),
+    ].concat())
+}
+
+#[test]
+fn runs_the_configured_command() {
+    let input = input();
+    let (configured, expected) = (TempFile::new("config-configured.csv"), TempFile::new("config-expected.csv"));
+    let config = TempFile::with("config.toml", format!(r#"
+command = "build-book"
+file = "{}"
+symbol = "AAPL"
+write_mode = "on-top-of-book-change"
+
+[build-book]
+output_file = "{}"
+bbo_only = true
+
+[snapshot]
+symbols = ["AAPL", "MSFT"]
+at = "10:00:00"
+"#, input.path(), configured.path()).as_bytes());
+
+    common::run(&["--config", config.path()]);
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", expected.path(), "--bbo-only", "--write-mode", "on-top-of-book-change"]);
+    assert_eq!(configured.read(), expected.read());
+    assert_eq!(configured.read().lines().count(), 4);
+
+    // The command line wins over the file
+    let overridden = TempFile::new("config-overridden.csv");
+    common::run(&["build-book", "--config", config.path(), "-o", overridden.path(), "-s", "MSFT"]);
+    assert_eq!(overridden.read(), "timestamp,bid,bid_size,ask,ask_size,mid,spread\n");
+}
+
+#[test]
+fn rejects_unknown_options() {
+    let config = TempFile::with("config-unknown.toml", b"[build-book]\nsymbol = \"AAPL\"\nspeed = 2\n");
+    let result = binary().args(["build-book", "--config", config.path()]).output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Unknown option \"speed\" for build-book in config file"));
+}
@@ -0,0 +1,46 @@
+// The WebSocket port also answers plain HTTP: GET / is the dashboard page, and WebSocket clients are served as
+// before on the same port
+#![cfg(feature = "native")]
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use nasdaq_itch_orderbook::websocket::{BookUpdate, WebSocketServer, BROADCAST_CAPACITY};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message;
+
+async fn get(port: u16, path: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).await.unwrap();
+    let mut response = String::new();
+    tokio::time::timeout(Duration::from_secs(5), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    response
+}
+
+#[tokio::test]
+async fn serves_the_dashboard_next_to_the_websocket() {
+    let port = 18_481;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let server = WebSocketServer::live(tx.clone(), port);
+    tokio::spawn(async move { server.start().await });
+
+    let url = format!("ws://127.0.0.1:{}", port);
+    let mut client = loop {
+        match tokio_tungstenite::connect_async(&url).await {
+            Ok((client, _)) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+
+    let page = get(port, "/?token=abc").await;
+    assert!(page.starts_with("HTTP/1.1 200 OK\r\n"), "{}", page);
+    assert!(page.contains("Content-Type: text/html") && page.contains("new WebSocket("), "{}", page);
+    assert!(get(port, "/metrics").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    // Wait for the session to subscribe before publishing
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tx.send(BookUpdate { symbol: None, json: "{\"type\":\"test\"}".to_string(), snapshot: None }).unwrap();
+    let message = tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap();
+    assert_eq!(message, Message::Text("{\"type\":\"test\"}".to_string()));
+}
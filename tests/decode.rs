@@ -0,0 +1,84 @@
+// Encoded messages decode into the ItchMessage variant of their type with the same fields
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::wire::{self, ItchMessage};
+use proptest::prelude::*;
+
+// Timestamps are 6 bytes on the wire
+const MAX_TIMESTAMP: u64 = (1 << 48) - 1;
+
+// The message after its length prefix, as decode takes it
+fn framed<M: WireMessage>(message: &M) -> Vec<u8> {
+    message.encode()[2..].to_vec()
+}
+
+proptest! {
+    #[test]
+    fn add_order(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, order_ref_number: u64,
+                 buy_sell_indicator: u8, shares: u32, stock: [u8; 8], price: u32, attribution: [u8; 4]) {
+        let data = framed(&AddOrderWithMpidMessage {
+            stock_locate, tracking_number, timestamp, order_ref_number, buy_sell_indicator, shares, stock, price, attribution,
+        });
+        let message = wire::decode(&data);
+        prop_assert_eq!(message.message_type(), MessageType::AddOrderWithMpid);
+        prop_assert_eq!(message.stock(), Some(&stock));
+        let ItchMessage::AddOrderWithMpid(add) = message else {
+            return Err(TestCaseError::fail("not an add with MPID"));
+        };
+        prop_assert_eq!(add.header.stock_locate.get(), stock_locate);
+        prop_assert_eq!(add.header.tracking_number.get(), tracking_number);
+        prop_assert_eq!(add.header.timestamp.get(), timestamp);
+        prop_assert_eq!(add.order_ref_number.get(), order_ref_number);
+        prop_assert_eq!(add.buy_sell_indicator, buy_sell_indicator);
+        prop_assert_eq!(add.shares.get(), shares);
+        prop_assert_eq!(add.price.get(), price);
+        prop_assert_eq!(add.attribution, attribution);
+    }
+
+    #[test]
+    fn order_replace(stock_locate: u16, timestamp in 0..=MAX_TIMESTAMP, original_order_ref_number: u64,
+                     new_order_ref_number: u64, shares: u32, price: u32) {
+        let data = framed(&OrderReplaceMessage {
+            stock_locate, tracking_number: 0, timestamp, original_order_ref_number, new_order_ref_number, shares, price,
+        });
+        let message = wire::decode(&data);
+        prop_assert_eq!(message.stock(), None);
+        let ItchMessage::OrderReplace(replace) = message else {
+            return Err(TestCaseError::fail("not a replace"));
+        };
+        prop_assert_eq!(replace.original_order_ref_number.get(), original_order_ref_number);
+        prop_assert_eq!(replace.new_order_ref_number.get(), new_order_ref_number);
+        prop_assert_eq!(replace.shares.get(), shares);
+        prop_assert_eq!(replace.price.get(), price);
+    }
+
+    #[test]
+    fn trade(timestamp in 0..=MAX_TIMESTAMP, order_ref_number: u64, shares: u32, stock: [u8; 8], price: u32, match_number: u64) {
+        let data = framed(&TradeMessage {
+            stock_locate: 1, tracking_number: 0, timestamp, order_ref_number, buy_sell_indicator: b'B', shares, stock, price,
+            match_number,
+        });
+        let ItchMessage::Trade(trade) = wire::decode(&data) else {
+            return Err(TestCaseError::fail("not a trade"));
+        };
+        prop_assert_eq!(trade.header.timestamp.get(), timestamp);
+        prop_assert_eq!(trade.stock, stock);
+        prop_assert_eq!(trade.price.get(), price);
+        prop_assert_eq!(trade.match_number.get(), match_number);
+    }
+
+    // Anything that isn't a whole message of a known type comes back as Unknown with its bytes
+    #[test]
+    fn unknown(data in prop::collection::vec(any::<u8>(), 0..64)) {
+        let message = wire::decode(&data);
+        let spec_length = data.first().and_then(|&type_byte| MessageType::from(type_byte).spec_length());
+        match spec_length {
+            Some(length) if data.len() >= length => prop_assert_eq!(message.message_type() as u8, data[0]),
+            _ => {
+                let ItchMessage::Unknown(bytes) = message else {
+                    return Err(TestCaseError::fail("decoded a short or unknown message"));
+                };
+                prop_assert_eq!(bytes, &data[..]);
+            }
+        }
+    }
+}
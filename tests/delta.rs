@@ -0,0 +1,124 @@
+// A client that applies the deltas to the first snapshot holds the same levels as the book, and full
+// snapshots come again on schedule and after a subscription change
+#![cfg(feature = "native")]
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use nasdaq_itch_orderbook::delta::DeltaEncoder;
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions, PriceLevel};
+use nasdaq_itch_orderbook::outbox::{ClientPolicy, Outbox};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use nasdaq_itch_orderbook::websocket::{BookUpdate, LiveSink, WireFormat};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message;
+
+fn updates() -> Vec<BookUpdate> {
+    let (tx, mut rx) = broadcast::channel(100_000);
+    let sink = Box::new(LiveSink::new(tx, false, FeatureColumns::default()));
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], ..Default::default() })];
+    let data = SyntheticFeed { messages: 5_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    std::iter::from_fn(|| rx.try_recv().ok()).filter(|update| update.snapshot.is_some()).collect()
+}
+
+// Price to volume of each side, as a client would keep them
+#[derive(Default, Debug, PartialEq)]
+struct ClientBook {
+    bids: BTreeMap<String, u64>,
+    asks: BTreeMap<String, u64>,
+}
+
+impl ClientBook {
+    fn apply(&mut self, message: &Value) {
+        match message["type"].as_str().unwrap() {
+            "snapshot" => {
+                for (side, levels) in [(&mut self.bids, &message["bids"]), (&mut self.asks, &message["asks"])] {
+                    *side = levels.as_array().unwrap().iter().map(|level| (level[0].to_string(), level[1].as_u64().unwrap())).collect();
+                }
+            }
+            "delta" => {
+                for change in message["changes"].as_array().unwrap() {
+                    let side = if change["side"] == "bid" { &mut self.bids } else { &mut self.asks };
+                    let price = change["price"].to_string();
+                    match change["action"].as_str().unwrap() {
+                        "delete" => assert!(side.remove(&price).is_some(), "{}", change),
+                        "add" => assert!(side.insert(price, change["volume"].as_u64().unwrap()).is_none(), "{}", change),
+                        _ => assert!(side.insert(price, change["volume"].as_u64().unwrap()).is_some(), "{}", change),
+                    }
+                }
+            }
+            other => panic!("unexpected message type {}", other),
+        }
+    }
+
+    fn of(snapshot: &BookSnapshot) -> Self {
+        let side = |levels: &[PriceLevel]| levels.iter()
+            .map(|level| (serde_json::json!(level.price as f64 / 10000.0).to_string(), level.total_volume as u64))
+            .collect();
+        ClientBook { bids: side(&snapshot.bid_levels), asks: side(&snapshot.ask_levels) }
+    }
+}
+
+#[test]
+fn deltas_rebuild_the_book() {
+    let updates = updates();
+    let symbol: Arc<str> = Arc::from("AAPL");
+    let mut encoder = DeltaEncoder::new(100);
+    let mut client = ClientBook::default();
+    let mut kinds = Vec::new();
+    let mut seq = 0;
+    for update in &updates {
+        let snapshot = update.snapshot.as_ref().unwrap();
+        if let Some(text) = encoder.encode(&symbol, snapshot) {
+            let message: Value = serde_json::from_str(&text).unwrap();
+            seq += 1;
+            assert_eq!(message["seq"], seq);
+            client.apply(&message);
+            kinds.push(message["type"].as_str().unwrap().to_string());
+        }
+        assert_eq!(client, ClientBook::of(snapshot));
+    }
+
+    // A full snapshot first, then one after every 100 deltas
+    assert!(kinds.len() > 300, "{}", kinds.len());
+    for (index, kind) in kinds.iter().enumerate() {
+        assert_eq!(kind == "snapshot", index % 101 == 0, "message {}", index);
+    }
+
+    // Unchanged levels send nothing, and a resnapshot starts over with a full one
+    assert_eq!(encoder.encode(&symbol, updates.last().unwrap().snapshot.as_ref().unwrap()), None);
+    encoder.resnapshot();
+    let text = encoder.encode(&symbol, updates.last().unwrap().snapshot.as_ref().unwrap()).unwrap();
+    assert!(text.starts_with("{\"type\":\"snapshot\""), "{}", text);
+}
+
+#[tokio::test]
+async fn outbox_sends_deltas() {
+    let updates = updates();
+    let outbox = Outbox::new(WireFormat::Delta, ClientPolicy { ping_interval: None, ..Default::default() });
+    for update in &updates[..3] {
+        outbox.push_update(update.clone()).unwrap();
+    }
+    // Notices without a snapshot go out as they are
+    outbox.push_update(BookUpdate { symbol: None, json: "{\"type\":\"halt\"}".to_string(), snapshot: None }).unwrap();
+
+    let mut sent = Vec::new();
+    let sink = futures_util::sink::unfold(&mut sent, |sent, message| async move {
+        sent.push(message);
+        Ok::<_, std::convert::Infallible>(sent)
+    });
+    let _ = tokio::time::timeout(Duration::from_millis(100), outbox.write_to(Box::pin(sink))).await;
+
+    let texts: Vec<String> = sent.into_iter().map(|message| match message {
+        Message::Text(text) => text,
+        other => panic!("expected text, got {:?}", other),
+    }).collect();
+    assert!(texts[0].starts_with("{\"type\":\"snapshot\",\"symbol\":\"AAPL\",\"seq\":1,"), "{}", texts[0]);
+    assert!(texts[1..texts.len() - 1].iter().all(|text| text.starts_with("{\"type\":\"delta\"")), "{:?}", texts);
+    assert_eq!(texts.last().unwrap(), "{\"type\":\"halt\"}");
+}
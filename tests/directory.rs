@@ -0,0 +1,38 @@
+// The directory subcommand exports every Stock Directory message by stock locate
+mod common;
+
+use common::{add, directory, TempFile};
+
+#[test]
+fn writes_csv() {
+    let input = TempFile::with("directory.itch", &[
+        directory(2, "MSFT", 100),
+        directory(1, "AAPL", 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        // Listed again, so the later message wins
+        directory(2, "MSFT", 10),
+    ].concat());
+    let output = TempFile::new("directory.csv");
+    common::run(&["directory", "-f", input.path(), "-o", output.path()]);
+
+    assert_eq!(output.read(), "\
+stock_locate,symbol,market_category,financial_status,round_lot_size,round_lots_only,issue_classification,issue_subtype,\
+authenticity,short_sale_threshold,ipo_flag,luld_tier,etp_flag,etp_leverage_factor,inverse
+1,AAPL,Q,N,100,N,C,,P,N,N,1,N,0,N
+2,MSFT,Q,N,10,N,C,,P,N,N,1,N,0,N
+");
+}
+
+#[test]
+fn writes_json() {
+    let input = TempFile::with("directory-json.itch", &directory(1, "AAPL", 100));
+    let output = TempFile::new("directory.jsonl");
+    common::run(&["directory", "-f", input.path(), "-o", output.path(), "--format", "json"]);
+
+    let entry: serde_json::Value = serde_json::from_str(output.read().trim()).unwrap();
+    assert_eq!(entry["stock_locate"], 1);
+    assert_eq!(entry["symbol"], "AAPL");
+    assert_eq!(entry["round_lot_size"], 100);
+    assert_eq!(entry["round_lots_only"], false);
+    assert_eq!(entry["luld_tier"], "1");
+}
@@ -0,0 +1,30 @@
+// --events-output writes the administrative messages of every symbol with their decoded fields
+mod common;
+
+use common::{add, directory, message, stock, system_event, TempFile, LOCATE, SYMBOL};
+
+#[test]
+fn writes_administrative_messages() {
+    let input = TempFile::with("events-output.itch", &[
+        system_event(1, b'O'),
+        directory(LOCATE, SYMBOL, 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        message(b'H', LOCATE, 3, &[&stock(SYMBOL), b"H", b" ", b"T1  "]),
+        // Other symbols are written too
+        message(b'Y', 2, 4, &[&stock("MSFT"), b"1"]),
+        message(b'W', 0, 5, &[b"2"]),
+    ].concat());
+    let events = TempFile::new("events-output.csv");
+    common::run(&["stats", "-f", input.path(), "--events-output", events.path()]);
+
+    assert_eq!(events.read(), "\
+timestamp,message_type,stock_locate,symbol,event,fields
+1,S,0,,start_of_messages,
+1,R,1,AAPL,stock_directory,market_category=Q financial_status=N round_lot_size=100 round_lots_only=N \
+issue_classification=C issue_subtype= authenticity=P short_sale_threshold=N ipo_flag=N luld_tier=1 etp_flag=N \
+etp_leverage_factor=0 inverse=N
+3,H,1,AAPL,halted,reason=T1
+4,Y,2,MSFT,reg_sho_restriction_intraday_drop,
+5,W,0,,mwcb_breach,level=2
+");
+}
@@ -0,0 +1,55 @@
+// Executions with price (C) trade at their own price, count towards the volume only when printable, and
+// take the shares off the book at the resting order's price
+use std::io;
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+use common::{add, build};
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBookOptions, TradeRecord};
+use nasdaq_itch_orderbook::sink::OutputSink;
+
+#[derive(Clone, Default)]
+struct Tape(Arc<Mutex<Vec<TradeRecord>>>);
+
+impl OutputSink for Tape {
+    fn write_snapshot(&mut self, _symbol: &str, _snapshot: &BookSnapshot) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_trade(&mut self, _symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.0.lock().unwrap().push(trade.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn execute_with_price(order_ref_number: u64, executed_shares: u32, printable: u8, execution_price: u32) -> Vec<u8> {
+    OrderExecutedWithPriceMessage { stock_locate: 1, tracking_number: 0, timestamp: 2, order_ref_number, executed_shares,
+                                    match_number: order_ref_number * 10 + executed_shares as u64, printable, execution_price }.encode()
+}
+
+#[test]
+fn trades_at_the_execution_price() {
+    let feed = [
+        add(1, 1, b'B', 500, 1_000_000),
+        execute_with_price(1, 100, b'Y', 1_000_500),
+        execute_with_price(1, 200, b'N', 1_001_000),
+    ].concat();
+    let tape = Tape::default();
+    let book = build(&feed, OrderBookOptions { sinks: vec![Box::new(tape.clone())], ..Default::default() });
+
+    let trades = tape.0.lock().unwrap().clone();
+    let prints: Vec<(u32, u32, u8)> = trades.iter().map(|trade| (trade.price, trade.shares, trade.message_type)).collect();
+    assert_eq!(prints, [(1_000_500, 100, b'C'), (1_001_000, 200, b'C')]);
+    // The non-printable execution is on the tape but not in the volume
+    let stats = book.trade_stats();
+    assert_eq!((stats.volume, stats.notional), (100, 100 * 1_000_500));
+    // The order stays at its own price with the shares left
+    let (bids, _) = book.levels(1);
+    assert_eq!((bids[0].price, bids[0].total_volume), (1_000_000, 200));
+}
@@ -0,0 +1,34 @@
+// --fast-filter never drops a message for one of the symbol's orders
+mod common;
+
+use common::{add, add_of, cancel, delete, directory, execute, execute_of, replace, TempFile};
+
+#[test]
+fn matches_the_unfiltered_book() {
+    // Enough orders, with refs spread over the whole u64 range, to fill a good part of the filter
+    let order_ref = |i: u64| i.wrapping_mul(0x2545_F491_4F6C_DD1D) | 1;
+    let mut input = vec![directory(1, "AAPL", 100), directory(2, "MSFT", 100)];
+    for i in 0..20_000u64 {
+        let price = 1_500_000 + (i % 40) as u32 * 100;
+        input.push(add(2 + i, order_ref(i), if i % 2 == 0 { b'B' } else { b'S' }, 300, price));
+        input.push(add_of(2, "MSFT", 2 + i, order_ref(i) & !1, b'B', 100, 4_000_000));
+    }
+    for i in 0..20_000u64 {
+        let timestamp = 30_000 + i;
+        input.push(match i % 4 {
+            0 => execute(timestamp, order_ref(i), 100, i),
+            1 => cancel(timestamp, order_ref(i), 100),
+            2 => delete(timestamp, order_ref(i)),
+            _ => replace(timestamp, order_ref(i), order_ref(i + 20_000), 200, 1_500_000),
+        });
+        input.push(execute_of(2, timestamp, order_ref(i) & !1, 100, 20_000 + i));
+    }
+    let input = TempFile::with("fast-filter.itch", &input.concat());
+
+    let (unfiltered, filtered) = (TempFile::new("fast-filter-off.csv"), TempFile::new("fast-filter-on.csv"));
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", unfiltered.path()]);
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", filtered.path(), "--fast-filter"]);
+    // A row for every add and every later message of those orders
+    assert_eq!(unfiltered.read().lines().count(), 1 + 40_000);
+    assert_eq!(filtered.read(), unfiltered.read());
+}
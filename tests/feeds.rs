@@ -0,0 +1,44 @@
+// --feed bx and --feed psx skip the messages only Nasdaq publishes, and build the same book from the rest
+mod common;
+
+use common::{add, binary, execute, message, stock, system_event, TempFile, LOCATE};
+
+fn cross(timestamp: u64) -> Vec<u8> {
+    message(b'Q', LOCATE, timestamp, &[&500u64.to_be_bytes(), &stock("AAPL"), &1_500_100u32.to_be_bytes(), &9u64.to_be_bytes(), b"O"])
+}
+
+fn run(name: &str, feed: &str) -> (String, String) {
+    let input = TempFile::with(&format!("{}.itch", name), &[
+        add(1, 1, b'B', 100, 1_500_000),
+        cross(2),
+        add(3, 2, b'S', 200, 1_500_500),
+        cross(4),
+        execute(5, 1, 30, 7),
+        system_event(6, b'C'),
+        system_event(7, b'C'),
+    ].concat());
+    let output = TempFile::new(&format!("{}.csv", name));
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--feed", feed]).output().unwrap();
+    assert!(result.status.success());
+    (String::from_utf8_lossy(&result.stdout).into_owned(), output.read())
+}
+
+#[test]
+fn skips_messages_outside_the_feed() {
+    let (nasdaq_log, nasdaq_book) = run("feeds-nasdaq", "nasdaq");
+    assert!(!nasdaq_log.contains("Skipped"));
+
+    for feed in ["bx", "psx"] {
+        let (log, book) = run(&format!("feeds-{}", feed), feed);
+        assert!(log.contains("Skipped 2 messages not defined for the"), "{}", log);
+        assert_eq!(book, nasdaq_book);
+    }
+    assert_eq!(nasdaq_book.lines().count(), 4);
+}
+
+#[test]
+fn rejects_unknown_feeds() {
+    let output = binary().args(["build-book", "-f", "unused.itch", "-s", "AAPL", "-o", "unused.csv", "--feed", "nyse"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid value 'nyse'"));
+}
@@ -0,0 +1,57 @@
+// The C API pulls the same snapshots the book writes when it parses the whole file (cargo test --features ffi)
+#![cfg(feature = "ffi")]
+use std::ffi::{CStr, CString};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use nasdaq_itch_orderbook::ffi::*;
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+struct Timestamps(Arc<Mutex<Vec<u64>>>);
+
+impl OutputSink for Timestamps {
+    fn write_snapshot(&mut self, _symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        self.0.lock().unwrap().push(snapshot.timestamp);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn snapshots_match_the_parser() {
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    let path = std::env::temp_dir().join(format!("itch-ffi-{}.itch", std::process::id()));
+    std::fs::write(&path, &data).unwrap();
+
+    let expected = Arc::new(Mutex::new(Vec::new()));
+    let sink = Box::new(Timestamps(Arc::clone(&expected)));
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], ..Default::default() })];
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+    let mut timestamps = Vec::new();
+    unsafe {
+        let reader = itch_open(c_path.as_ptr(), c"AAPL".as_ptr());
+        assert!(!reader.is_null());
+        let mut snapshot = ItchSnapshot::default();
+        while itch_next_snapshot(reader, &mut snapshot) == 1 {
+            assert_eq!(&snapshot.symbol, b"AAPL    ");
+            assert!(snapshot.bid_count as usize <= ITCH_MAX_LEVELS);
+            timestamps.push(snapshot.timestamp);
+        }
+        itch_close(reader);
+
+        assert!(itch_open(c"/nonexistent.itch".as_ptr(), c"AAPL".as_ptr()).is_null());
+        assert!(!CStr::from_ptr(itch_last_error()).to_bytes().is_empty());
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert!(!timestamps.is_empty());
+    assert_eq!(timestamps, *expected.lock().unwrap());
+}
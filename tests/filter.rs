@@ -0,0 +1,65 @@
+// The filter subcommand keeps the messages of chosen symbols and times, unchanged, as a valid ITCH file
+mod common;
+
+use common::{add_of, directory, execute_of, system_event};
+use nasdaq_itch_orderbook::filter::{filter_messages, FilterOptions};
+use nasdaq_itch_orderbook::message_types::*;
+
+const SECOND: u64 = 1_000_000_000;
+const NINE_THIRTY: u64 = 34_200 * SECOND;
+
+fn replace(stock_locate: u16, timestamp: u64, original_order_ref_number: u64, new_order_ref_number: u64) -> Vec<u8> {
+    OrderReplaceMessage { stock_locate, tracking_number: 0, timestamp, original_order_ref_number, new_order_ref_number, shares: 50, price: 1_000_100 }.encode()
+}
+
+// AAPL (locate 1) and MSFT (locate 2), with an AAPL order before 09:30 that is executed and replaced after it
+fn messages() -> Vec<Vec<u8>> {
+    vec![
+        system_event(0, b'O'),
+        directory(1, "AAPL", 100),
+        directory(2, "MSFT", 100),
+        add_of(1, "AAPL", NINE_THIRTY - SECOND, 1, b'B', 100, 1_000_000),
+        add_of(2, "MSFT", NINE_THIRTY - SECOND, 2, b'B', 100, 1_000_000),
+        system_event(NINE_THIRTY, b'Q'),
+        add_of(1, "AAPL", NINE_THIRTY + SECOND, 3, b'B', 100, 1_000_000),
+        execute_of(1, NINE_THIRTY + 2 * SECOND, 1, 10, 1),
+        execute_of(2, NINE_THIRTY + 2 * SECOND, 2, 10, 2),
+        execute_of(1, NINE_THIRTY + 3 * SECOND, 3, 10, 3),
+        replace(1, NINE_THIRTY + 4 * SECOND, 1, 4),
+        replace(1, NINE_THIRTY + 4 * SECOND, 3, 5),
+        OrderDeleteMessage { stock_locate: 1, tracking_number: 0, timestamp: NINE_THIRTY + 5 * SECOND, order_ref_number: 5 }.encode(),
+        system_event(NINE_THIRTY + 60 * SECOND, b'M'),
+    ]
+}
+
+fn filter(options: FilterOptions) -> Vec<u8> {
+    let mut output = Vec::new();
+    let stats = filter_messages(&messages().concat(), &options, &mut output).unwrap();
+    assert_eq!(stats.read, messages().len() as u64);
+    output
+}
+
+fn pick(indices: &[usize]) -> Vec<u8> {
+    let messages = messages();
+    indices.iter().flat_map(|&i| messages[i].clone()).collect()
+}
+
+#[test]
+fn keeps_the_chosen_symbols() {
+    let options = FilterOptions { symbols: vec!["AAPL".to_string()], ..Default::default() };
+    // Everything of AAPL, order messages picked by their stock locate, and the system events
+    assert_eq!(filter(options), pick(&[0, 1, 3, 5, 6, 7, 9, 10, 11, 12, 13]));
+}
+
+#[test]
+fn keeps_a_time_range() {
+    let options = FilterOptions { from: Some(NINE_THIRTY), to: Some(NINE_THIRTY + 5 * SECOND), ..Default::default() };
+    // Orders added before 09:30 are left out with their executions and replaces, the directory and system
+    // events stay
+    assert_eq!(filter(options), pick(&[0, 1, 2, 5, 6, 9, 11, 13]));
+}
+
+#[test]
+fn keeps_everything_without_filters() {
+    assert_eq!(filter(FilterOptions::default()), messages().concat());
+}
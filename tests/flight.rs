@@ -0,0 +1,86 @@
+// The Arrow Flight server lists a flight per symbol and sends its snapshots, or those of a time range, as
+// record batches with the columns of the CSV output
+#![cfg(feature = "flight")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, UInt64Type};
+use arrow_array::RecordBatch;
+use arrow_flight::{FlightClient, Ticket};
+use futures_util::TryStreamExt;
+use nasdaq_itch_orderbook::flight::{self, FlightSink, FlightStore};
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use tonic::transport::Channel;
+use tonic::Code;
+
+async fn connect(port: u16) -> FlightClient {
+    loop {
+        match Channel::from_shared(format!("http://127.0.0.1:{}", port)).unwrap().connect().await {
+            Ok(channel) => return FlightClient::new(channel),
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+}
+
+async fn pull(client: &mut FlightClient, ticket: &str) -> Result<Vec<RecordBatch>, arrow_flight::error::FlightError> {
+    client.do_get(Ticket::new(ticket.to_string())).await?.try_collect().await
+}
+
+fn timestamps(batches: &[RecordBatch]) -> Vec<u64> {
+    batches.iter().flat_map(|batch| batch.column_by_name("timestamp").unwrap().as_primitive::<UInt64Type>().values().to_vec()).collect()
+}
+
+#[tokio::test]
+async fn serves_snapshots_by_symbol_and_time() {
+    let port = 18_480;
+    let store = Arc::new(FlightStore::default());
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![Box::new(FlightSink(store.clone()))], ..Default::default() })];
+    let data = SyntheticFeed { messages: 5_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    let (_, written) = books[0].counts();
+    let server_store = store.clone();
+    tokio::spawn(async move { flight::serve(server_store, port).await });
+    let mut client = connect(port).await;
+
+    let infos: Vec<_> = client.list_flights(Vec::new()).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].flight_descriptor.as_ref().unwrap().path, vec!["AAPL".to_string()]);
+    assert_eq!(infos[0].total_records, written as i64);
+
+    // The whole day, in timestamp order, with the CSV output's columns
+    let day = pull(&mut client, "{\"symbol\":\"aapl\"}").await.unwrap();
+    let all = timestamps(&day);
+    assert_eq!(all.len() as u64, written);
+    assert!(all.is_sorted());
+    let schema = day[0].schema();
+    assert_eq!(schema.fields().len(), 1 + 4 * 10 + 2);
+    assert_eq!(schema.field(1).name(), "1_bid_price");
+    // The first rows may have one side empty, which shows as zeros
+    let last = day.last().unwrap();
+    let bid = last.column_by_name("1_bid_price").unwrap().as_primitive::<Float64Type>().value(last.num_rows() - 1);
+    let ask = last.column_by_name("1_ask_price").unwrap().as_primitive::<Float64Type>().value(last.num_rows() - 1);
+    assert!(bid > 0.0 && ask > bid, "{} {}", bid, ask);
+    // The mid is halfway between the best prices, and 0 while a side is empty
+    for batch in &day {
+        let column = |name: &str| batch.column_by_name(name).unwrap().as_primitive::<Float64Type>().values().to_vec();
+        for ((bid, ask), mid) in column("1_bid_price").into_iter().zip(column("1_ask_price")).zip(column("mid_price")) {
+            let expected = if bid == 0.0 || ask == 0.0 { 0.0 } else { (bid + ask) / 2.0 };
+            assert!((mid - expected).abs() < 1e-9, "{} {} {}", bid, ask, mid);
+        }
+    }
+
+    // An inclusive time range
+    let (from, to) = (all[all.len() / 4], all[all.len() / 2]);
+    let ticket = format!("{{\"symbol\":\"AAPL\",\"from\":\"{}\",\"to\":\"{}\"}}", from, to);
+    let range = timestamps(&pull(&mut client, &ticket).await.unwrap());
+    assert_eq!(range, all.iter().copied().filter(|t| (from..=to).contains(t)).collect::<Vec<_>>());
+
+    let unknown = pull(&mut client, "{\"symbol\":\"MSFT\"}").await.unwrap_err();
+    assert!(matches!(unknown, arrow_flight::error::FlightError::Tonic(ref status) if status.code() == Code::NotFound), "{:?}", unknown);
+    let invalid = pull(&mut client, "AAPL").await.unwrap_err();
+    assert!(matches!(invalid, arrow_flight::error::FlightError::Tonic(ref status) if status.code() == Code::InvalidArgument), "{:?}", invalid);
+}
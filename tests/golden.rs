@@ -0,0 +1,28 @@
+// The bundled ITCH sample through the whole pipeline must give the expected files byte for byte. After a
+// change that is meant to alter the output, UPDATE_GOLDEN=1 cargo test --test golden rewrites them
+use std::path::Path;
+
+use nasdaq_itch_orderbook::selftest::{self, GOLDEN_FILES};
+
+#[test]
+fn outputs_match_the_golden_files() {
+    let dir = std::env::temp_dir().join(format!("golden-{}", std::process::id()));
+    selftest::write_outputs(&dir).unwrap();
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        for (file, _) in GOLDEN_FILES {
+            std::fs::copy(dir.join(file), Path::new("tests/data/golden").join(file)).unwrap();
+        }
+    }
+    let mismatches = selftest::check_outputs(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let report: Vec<String> = mismatches.iter().map(ToString::to_string).collect();
+    assert!(mismatches.is_empty(), "{}", report.join("\n"));
+}
+
+#[test]
+fn the_sample_covers_every_order_message_type() {
+    let messages = GOLDEN_FILES[0].1;
+    for message_type in ["A", "F", "E", "C", "X", "D", "U", "P", "R", "S"] {
+        assert!(messages.lines().any(|line| line.split(',').nth(1) == Some(message_type)), "{}", message_type);
+    }
+}
@@ -0,0 +1,66 @@
+// The gRPC API streams every book update and trade of the subscribed symbols as the file is parsed, and serves
+// the latest book of a symbol
+#![cfg(feature = "grpc")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::grpc::proto::order_book_client::OrderBookClient;
+use nasdaq_itch_orderbook::grpc::proto::{GetSnapshotRequest, SubscribeRequest};
+use nasdaq_itch_orderbook::grpc::{self, GrpcSink, GrpcState};
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use nasdaq_itch_orderbook::websocket::LiveSink;
+use tokio::sync::broadcast;
+use tonic::Code;
+use tonic::transport::Channel;
+
+async fn connect(port: u16) -> OrderBookClient<Channel> {
+    loop {
+        match OrderBookClient::connect(format!("http://127.0.0.1:{}", port)).await {
+            Ok(client) => return client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn streams_books_and_trades() {
+    let port = 18_479;
+    let state = Arc::new(GrpcState::default());
+    let server_state = state.clone();
+    tokio::spawn(async move { grpc::serve(server_state, port).await });
+
+    let mut client = connect(port).await;
+    let book_request = SubscribeRequest { symbols: vec!["aapl".to_string()] };
+    let mut books = client.subscribe_book(book_request).await.unwrap().into_inner();
+    let mut trades = client.subscribe_trades(SubscribeRequest::default()).await.unwrap().into_inner();
+    let missing = client.get_snapshot(GetSnapshotRequest { symbol: "AAPL".to_string() }).await.unwrap_err();
+    assert_eq!(missing.code(), Code::NotFound);
+
+    // The WebSocket feed of the same book is what the streams should match
+    let (tx, mut rx) = broadcast::channel(100_000);
+    let sinks: Vec<Box<dyn nasdaq_itch_orderbook::sink::OutputSink>> =
+        vec![Box::new(GrpcSink::new(state.clone())), Box::new(LiveSink::new(tx, false, FeatureColumns::default()))];
+    let mut book = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks, ..Default::default() })];
+    let data = SyntheticFeed { messages: 400, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut book, &ParserOptions::default()).unwrap();
+    let expected: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).filter_map(|update| update.snapshot).collect();
+    assert!(expected.len() > 50, "{}", expected.len());
+
+    for snapshot in &expected {
+        let message = tokio::time::timeout(Duration::from_secs(5), books.message()).await.unwrap().unwrap().unwrap();
+        assert_eq!(message.symbol, "AAPL");
+        assert_eq!((message.timestamp, message.mid_price), (snapshot.timestamp, snapshot.mid_price));
+        let bids: Vec<_> = message.bids.iter().map(|level| (level.price, level.volume)).collect();
+        assert_eq!(bids, snapshot.bid_levels.iter().map(|level| (level.price, level.total_volume)).collect::<Vec<_>>());
+    }
+    let trade = tokio::time::timeout(Duration::from_secs(5), trades.message()).await.unwrap().unwrap().unwrap();
+    assert_eq!(trade.symbol, "AAPL");
+    assert!(trade.shares > 0 && trade.price > 0, "{:?}", trade);
+
+    let latest = client.get_snapshot(GetSnapshotRequest { symbol: "aapl".to_string() }).await.unwrap().into_inner();
+    assert_eq!(latest.timestamp, expected.last().unwrap().timestamp);
+}
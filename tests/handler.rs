@@ -0,0 +1,76 @@
+// A custom MessageHandler driven by the parser sees every message of the feed
+use std::io;
+use std::ops::ControlFlow;
+
+use nasdaq_itch_orderbook::handler::MessageHandler;
+use nasdaq_itch_orderbook::message_types::MessageType;
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::wire;
+
+#[derive(Default)]
+struct Counter {
+    messages: u64,
+    adds: u64,
+    added_shares: u64,
+    trades: u64,
+    system_events: Vec<u8>,
+    last_timestamp: u64,
+}
+
+impl MessageHandler for Counter {
+    fn on_message(&mut self, _message_type: MessageType, timestamp: u64) -> io::Result<()> {
+        self.messages += 1;
+        assert!(timestamp >= self.last_timestamp);
+        self.last_timestamp = timestamp;
+        Ok(())
+    }
+
+    fn on_system_event(&mut self, message: &wire::SystemEvent, _timestamp: u64) -> io::Result<()> {
+        self.system_events.push(message.event_code);
+        Ok(())
+    }
+
+    fn on_add_order(&mut self, message: &wire::AddOrder, timestamp: u64) -> io::Result<()> {
+        assert_eq!(message.header.timestamp.get(), timestamp);
+        self.adds += 1;
+        self.added_shares += message.shares.get() as u64;
+        Ok(())
+    }
+
+    fn on_trade(&mut self, _message: &wire::Trade, _timestamp: u64) -> io::Result<()> {
+        self.trades += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn counts_match_a_scan() {
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    let mut handlers = [Counter::default()];
+    parser::process_itch_file(&data, &mut handlers, &ParserOptions::default()).unwrap();
+
+    let mut expected = Counter::default();
+    parser::scan_messages(&data, |message_type, message| {
+        expected.messages += 1;
+        match message_type {
+            MessageType::AddOrder => {
+                expected.adds += 1;
+                expected.added_shares += wire::decode_body(message_type, message).map_or(0, |message| match message {
+                    wire::ItchMessage::AddOrder(add) => add.shares.get() as u64,
+                    _ => 0,
+                });
+            }
+            MessageType::Trade => expected.trades += 1,
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    });
+
+    let [counter] = handlers;
+    assert_eq!(counter.messages, expected.messages);
+    assert_eq!(counter.adds, expected.adds);
+    assert_eq!(counter.added_shares, expected.added_shares);
+    assert_eq!(counter.trades, expected.trades);
+    assert_eq!(counter.system_events, b"OSQMEC");
+}
@@ -0,0 +1,62 @@
+// The heatmap is a dense grid of resting shares per price bucket, one block of rows per interval of market
+// time, which reshapes into a matrix without pivoting
+use std::fs::File;
+
+use nasdaq_itch_orderbook::heatmap::Heatmap;
+use nasdaq_itch_orderbook::orderbook::{Order, OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+
+const SECOND: u64 = 1_000_000_000;
+
+#[test]
+fn writes_a_dense_price_time_grid() {
+    let path = std::env::temp_dir().join(format!("heatmap-{}.parquet", std::process::id()));
+    let (tick, levels) = (500, 20);
+    let heatmap = Heatmap::new(&path, SECOND, tick, levels);
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { heatmap: Some(heatmap), ..Default::default() })];
+    let data = SyntheticFeed { messages: 20_000, rate: 1_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    books[0].finalize().unwrap();
+
+    let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+    let rows: Vec<(i64, f64, i64, i64)> = reader.get_row_iter(None).unwrap()
+        .map(|row| {
+            let row = row.unwrap();
+            (row.get_long(0).unwrap(), row.get_double(1).unwrap(), row.get_long(2).unwrap(), row.get_long(3).unwrap())
+        })
+        .collect();
+    std::fs::remove_file(&path).unwrap();
+
+    // Every interval has the same ascending price axis, one tick apart
+    let axis: Vec<f64> = rows.iter().take_while(|row| row.0 == rows[0].0).map(|row| row.1).collect();
+    assert!(axis.len() > 2 * levels as usize);
+    assert!(axis.windows(2).all(|pair| ((pair[1] - pair[0]) - 0.05).abs() < 1e-9));
+    assert_eq!(rows.len() % axis.len(), 0);
+    let intervals: Vec<&[(i64, f64, i64, i64)]> = rows.chunks(axis.len()).collect();
+    assert!(intervals.len() > 10);
+    for (index, interval) in intervals.iter().enumerate() {
+        assert!(interval.iter().all(|row| row.0 == interval[0].0));
+        assert_eq!(interval.iter().map(|row| row.1).collect::<Vec<_>>(), axis);
+        assert_eq!(interval[0].0 as u64 % SECOND, 0);
+        if index > 0 {
+            assert_eq!(interval[0].0 - intervals[index - 1][0].0, SECOND as i64);
+        }
+    }
+
+    // The last interval holds the book at the end, within the buckets around the mid
+    let (bid, ask) = books[0].best_prices();
+    let mid = (bid.unwrap() as u64 + ask.unwrap() as u64) / 2 / tick as u64;
+    let window = (mid - levels as u64)..=(mid + levels as u64);
+    let (bids, asks) = books[0].resting_orders();
+    let expected = |orders: &[&Order]| -> i64 {
+        orders.iter().filter(|order| window.contains(&(order.price as u64 / tick as u64))).map(|order| order.shares as i64).sum()
+    };
+    let last = intervals.last().unwrap();
+    assert_eq!(last.iter().map(|row| row.2).sum::<i64>(), expected(&bids));
+    assert_eq!(last.iter().map(|row| row.3).sum::<i64>(), expected(&asks));
+    assert!(expected(&bids) > 0 && expected(&asks) > 0);
+}
@@ -0,0 +1,72 @@
+// --hidden-output counts non-displayed trades and executions away from the displayed price as hidden liquidity,
+// by where they traded against the quote
+use nasdaq_itch_orderbook::hidden::{HiddenLiquidityReport, QuoteLocation};
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use serde_json::Value;
+
+fn read(path: &std::path::Path) -> Value {
+    let summary = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    std::fs::remove_file(path).unwrap();
+    summary
+}
+
+#[test]
+fn places_hidden_executions_against_the_quote() {
+    let (bid, ask) = (Some(1_000_000), Some(1_000_400));
+    assert_eq!(QuoteLocation::of(1_000_200, bid, ask), QuoteLocation::Midpoint);
+    assert_eq!(QuoteLocation::of(1_000_100, bid, ask), QuoteLocation::InsideSpread);
+    assert_eq!(QuoteLocation::of(1_000_400, bid, ask), QuoteLocation::AtQuote);
+    assert_eq!(QuoteLocation::of(999_900, bid, ask), QuoteLocation::OutsideQuote);
+    assert_eq!(QuoteLocation::of(1_000_000, bid, None), QuoteLocation::NoQuote);
+    assert_eq!(QuoteLocation::of(1_000_000, ask, bid), QuoteLocation::NoQuote);
+
+    let path = std::env::temp_dir().join(format!("hidden-{}.json", std::process::id()));
+    let mut report = HiddenLiquidityReport::new(&path);
+    report.execution(1_000_000, 1_000_000, 300, bid, ask);
+    report.execution(1_000_400, 1_000_300, 100, bid, ask);
+    report.non_displayed_trade(1_000_200, 200, bid, ask);
+    report.non_displayed_trade(1_000_400, 400, bid, ask);
+    report.finalize("AAPL").unwrap();
+
+    let summary = read(&path);
+    assert_eq!(summary["executed_shares"], 1000);
+    assert_eq!(summary["displayed"]["shares"], 300);
+    assert_eq!(summary["hidden"]["executions"], 3);
+    assert_eq!(summary["hidden"]["shares"], 700);
+    assert_eq!(summary["hidden"]["percent_of_volume"], 70.0);
+    assert_eq!(summary["non_displayed_trades"]["shares"], 600);
+    assert_eq!(summary["off_display_price"]["shares"], 100);
+    assert_eq!(summary["off_display_price"]["average_distance"], 0.01);
+    let locations = &summary["hidden_by_location"];
+    assert_eq!(locations["midpoint"]["shares"], 200);
+    assert_eq!(locations["inside_spread"]["shares"], 100);
+    assert_eq!(locations["at_quote"]["shares"], 400);
+    assert_eq!(locations["outside_quote"]["executions"], 0);
+    assert_eq!(locations["no_quote"]["percent_of_volume"], 0.0);
+}
+
+#[test]
+fn counts_the_non_displayed_trades_of_a_feed() {
+    let path = std::env::temp_dir().join(format!("hidden-feed-{}.json", std::process::id()));
+    let options = OrderBookOptions { hidden: Some(HiddenLiquidityReport::new(&path)), ..Default::default() };
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), options)];
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    books[0].finalize().unwrap();
+
+    let summary = read(&path);
+    // The feed's executions are all at the displayed price, and its trades of 100 shares at the mid
+    let trades = summary["non_displayed_trades"]["executions"].as_u64().unwrap();
+    assert!(trades > 0);
+    assert_eq!(summary["non_displayed_trades"]["shares"], 100 * trades);
+    assert_eq!(summary["off_display_price"]["executions"], 0);
+    assert_eq!(summary["hidden"]["shares"], 100 * trades);
+    let executed = summary["executed_shares"].as_u64().unwrap();
+    assert_eq!(summary["displayed"]["shares"].as_u64().unwrap() + 100 * trades, executed);
+    let by_location: u64 = summary["hidden_by_location"].as_object().unwrap().values().map(|volume| volume["executions"].as_u64().unwrap()).sum();
+    assert_eq!(by_location, trades);
+    assert!(summary["hidden_by_location"]["midpoint"]["executions"].as_u64().unwrap() > 0);
+}
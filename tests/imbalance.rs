@@ -0,0 +1,39 @@
+// --imbalance-weighting, --imbalance-decay and --imbalance-levels change how orderbook_imbalance weights the levels
+mod common;
+
+use common::{add, TempFile};
+
+// orderbook_imbalance of the last row with the given options
+fn imbalance(name: &str, options: &[&str]) -> f64 {
+    let input = TempFile::with(&format!("{}.itch", name), &[
+        add(1, 1, b'B', 100, 1_500_000),
+        add(2, 2, b'B', 300, 1_499_900),
+        add(3, 3, b'S', 200, 1_500_100),
+    ].concat());
+    let output = TempFile::new(&format!("{}.csv", name));
+    common::run(&[&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path()][..], options].concat());
+
+    let csv = output.read();
+    let mut lines = csv.lines();
+    let column = lines.next().unwrap().split(',').position(|name| name == "orderbook_imbalance").unwrap();
+    lines.last().unwrap().split(',').nth(column).unwrap().parse().unwrap()
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-4, "{} != {}", actual, expected);
+}
+
+#[test]
+fn weights_the_levels() {
+    // Flat: (100 + 300 - 200) / (100 + 300 + 200)
+    assert_close(imbalance("imbalance-flat", &[]), 1.0 / 3.0);
+    // The top level of each side only
+    assert_close(imbalance("imbalance-top", &["--imbalance-levels", "1"]), -1.0 / 3.0);
+
+    let second = 300.0 * (-0.5f64).exp();
+    assert_close(imbalance("imbalance-exponential", &["--imbalance-weighting", "exponential"]),
+                 (100.0 + second - 200.0) / (100.0 + second + 200.0));
+    let second = 300.0 * (-2.0f64).exp();
+    assert_close(imbalance("imbalance-decay", &["--imbalance-weighting", "exponential", "--imbalance-decay", "2"]),
+                 (100.0 + second - 200.0) / (100.0 + second + 200.0));
+}
@@ -0,0 +1,56 @@
+// ItchIterator yields the same messages as a scan, and works with the standard adapters
+use std::ops::ControlFlow;
+
+use nasdaq_itch_orderbook::message_types::MessageType;
+use nasdaq_itch_orderbook::parser::{self, ItchIterator};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::wire::ItchMessage;
+
+fn feed() -> Vec<u8> {
+    SyntheticFeed { messages: 20_000, ..Default::default() }.generate()
+}
+
+#[test]
+fn matches_a_scan() {
+    let data = feed();
+    let mut expected = Vec::new();
+    parser::scan_messages(&data, |message_type, message| {
+        expected.push((message_type, message.to_vec()));
+        ControlFlow::Continue(())
+    });
+    let messages: Vec<_> = ItchIterator::new(&data).map(|(_, message_type, message)| (message_type, message.to_vec())).collect();
+    assert_eq!(messages, expected);
+}
+
+#[test]
+fn take_while_by_time() {
+    let data = feed();
+    let all: Vec<u64> = ItchIterator::new(&data).map(|(timestamp, _, _)| timestamp).collect();
+    assert!(all.is_sorted());
+    let cutoff = all[all.len() / 2];
+    let before = ItchIterator::new(&data).take_while(|&(timestamp, _, _)| timestamp <= cutoff).count();
+    assert_eq!(before, all.iter().filter(|&&timestamp| timestamp <= cutoff).count());
+}
+
+#[test]
+fn typed() {
+    let data = feed();
+    let adds = ItchIterator::new(&data).filter(|&(_, message_type, _)| message_type == MessageType::AddOrder).count();
+    let typed: Vec<_> = ItchIterator::new(&data).typed().collect();
+    assert_eq!(typed.len(), ItchIterator::new(&data).count());
+    assert_eq!(typed.iter().filter(|message| matches!(message, ItchMessage::AddOrder(_))).count(), adds);
+    assert!(typed.iter().all(|message| message.message_type() != MessageType::Unknown));
+}
+
+// Garbage between messages is skipped without losing any of them
+#[test]
+fn skips_corruption() {
+    let data = feed();
+    let mut messages = ItchIterator::new(&data);
+    messages.by_ref().take(10).for_each(drop);
+    let boundary = messages.offset();
+    let mut corrupt = data[..boundary].to_vec();
+    corrupt.extend_from_slice(&[0xFF; 7]);
+    corrupt.extend_from_slice(&data[boundary..]);
+    assert_eq!(ItchIterator::new(&corrupt).count(), ItchIterator::new(&data).count());
+}
@@ -0,0 +1,192 @@
+// --kafka publishes the written snapshots and the trades, keyed by symbol, to a broker; the broker here is a stand-in
+// speaking the v0 metadata and produce requests the client sends
+#![cfg(feature = "kafka")]
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+mod common;
+
+use common::{add, binary, execute, free_port, system_event, TempFile};
+
+const METADATA: i16 = 3;
+const PRODUCE: i16 = 0;
+
+// (topic, key, value) of every record produced
+type Records = Arc<Mutex<Vec<(String, String, Vec<u8>)>>>;
+
+struct Request(Vec<u8>, usize);
+
+impl Request {
+    fn bytes(&mut self, n: usize) -> &[u8] {
+        self.1 += n;
+        &self.0[self.1 - n..self.1]
+    }
+
+    fn i16(&mut self) -> i16 {
+        i16::from_be_bytes(self.bytes(2).try_into().unwrap())
+    }
+
+    fn i32(&mut self) -> i32 {
+        i32::from_be_bytes(self.bytes(4).try_into().unwrap())
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.i16() as usize;
+        String::from_utf8(self.bytes(len).to_vec()).unwrap()
+    }
+
+    fn block(&mut self) -> Vec<u8> {
+        match self.i32() {
+            -1 => Vec::new(),
+            len => self.bytes(len as usize).to_vec(),
+        }
+    }
+}
+
+fn string(out: &mut Vec<u8>, value: &str) {
+    out.extend_from_slice(&(value.len() as i16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn serve(mut stream: TcpStream, port: u16, records: Records) -> io::Result<()> {
+    loop {
+        let mut size = [0u8; 4];
+        stream.read_exact(&mut size)?;
+        let mut request = Request(vec![0; i32::from_be_bytes(size) as usize], 0);
+        stream.read_exact(&mut request.0)?;
+        let api_key = request.i16();
+        request.i16();
+        let correlation_id = request.i32();
+        request.string();
+
+        let mut response = correlation_id.to_be_bytes().to_vec();
+        match api_key {
+            METADATA => {
+                let mut topics: Vec<String> = (0..request.i32()).map(|_| request.string()).collect();
+                if topics.is_empty() {
+                    topics = ["books", "books-trades", "prints"].map(String::from).to_vec();
+                }
+                // This broker leads partition 0 of every topic; a request for all topics lists the ones the tests use
+                response.extend_from_slice(&1i32.to_be_bytes());
+                response.extend_from_slice(&0i32.to_be_bytes());
+                string(&mut response, "127.0.0.1");
+                response.extend_from_slice(&(port as i32).to_be_bytes());
+                response.extend_from_slice(&(topics.len() as i32).to_be_bytes());
+                for topic in topics {
+                    response.extend_from_slice(&0i16.to_be_bytes());
+                    string(&mut response, &topic);
+                    response.extend_from_slice(&1i32.to_be_bytes());
+                    for value in [0i16.to_be_bytes().to_vec(), 0i32.to_be_bytes().to_vec(), 0i32.to_be_bytes().to_vec()] {
+                        response.extend_from_slice(&value);
+                    }
+                    for _ in 0..2 {
+                        response.extend_from_slice(&1i32.to_be_bytes());
+                        response.extend_from_slice(&0i32.to_be_bytes());
+                    }
+                }
+            }
+            PRODUCE => {
+                request.i16();
+                request.i32();
+                let topic_count = request.i32();
+                response.extend_from_slice(&topic_count.to_be_bytes());
+                for _ in 0..topic_count {
+                    let topic = request.string();
+                    string(&mut response, &topic);
+                    let partition_count = request.i32();
+                    response.extend_from_slice(&partition_count.to_be_bytes());
+                    for _ in 0..partition_count {
+                        let partition = request.i32();
+                        let mut messages = Request(request.block(), 0);
+                        // Offset, size, crc, magic and attributes, then the key and the value
+                        while messages.1 < messages.0.len() {
+                            messages.bytes(8 + 4 + 4 + 1 + 1);
+                            let key = String::from_utf8(messages.block()).unwrap();
+                            records.lock().unwrap().push((topic.clone(), key, messages.block()));
+                        }
+                        response.extend_from_slice(&partition.to_be_bytes());
+                        response.extend_from_slice(&0i16.to_be_bytes());
+                        response.extend_from_slice(&0i64.to_be_bytes());
+                    }
+                }
+            }
+            other => panic!("unexpected request {}", other),
+        }
+        stream.write_all(&(response.len() as i32).to_be_bytes())?;
+        stream.write_all(&response)?;
+    }
+}
+
+fn broker() -> (u16, Records) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let records = Records::default();
+    let served = records.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let records = served.clone();
+            thread::spawn(move || serve(stream.unwrap(), port, records));
+        }
+    });
+    (port, records)
+}
+
+fn publish(name: &str, options: &str) -> std::process::Output {
+    let input = TempFile::with(&format!("{}.itch", name), &[
+        add(1, 1, b'B', 100, 1_500_000),
+        add(2, 2, b'S', 200, 1_500_500),
+        execute(3, 1, 30, 7),
+        system_event(4, b'C'),
+    ].concat());
+    let output = TempFile::new(&format!("{}.csv", name));
+    binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--kafka", options]).output().unwrap()
+}
+
+#[test]
+fn publishes_snapshots_and_trades() {
+    let (port, records) = broker();
+    let output = publish("kafka-json", &format!("brokers=127.0.0.1:{},topic=books", port));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let records = records.lock().unwrap();
+    let snapshots: Vec<serde_json::Value> = records.iter().filter(|(topic, _, _)| topic == "books")
+        .map(|(_, _, value)| serde_json::from_slice(value).unwrap()).collect();
+    let trades: Vec<serde_json::Value> = records.iter().filter(|(topic, _, _)| topic == "books-trades")
+        .map(|(_, _, value)| serde_json::from_slice(value).unwrap()).collect();
+    assert!(records.iter().all(|(_, key, _)| key == "AAPL"));
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!((&snapshots[2]["1_bid_vol"], &snapshots[2]["1_ask_price"]), (&70.into(), &150.05.into()));
+    assert_eq!(trades.len(), 1);
+    assert_eq!((&trades[0]["shares"], &trades[0]["match_number"]), (&30.into(), &7.into()));
+}
+
+#[test]
+fn publishes_avro_records() {
+    let (port, records) = broker();
+    let output = publish("kafka-avro", &format!("brokers=127.0.0.1:{},topic=books,trades_topic=prints,format=avro", port));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let records = records.lock().unwrap();
+    let (_, _, trade) = records.iter().find(|(topic, _, _)| topic == "prints").unwrap();
+    // "AAPL", then the zig-zag timestamp 3, price 1_500_000 and shares 30
+    assert_eq!(&trade[..6], &[8, b'A', b'A', b'P', b'L', 6]);
+    assert_eq!(&trade[6..10], &[0xc0, 0x8d, 0xb7, 0x01]);
+    assert_eq!(trade[10], 60);
+}
+
+#[test]
+fn fails_without_a_broker() {
+    let output = publish("kafka-down", &format!("brokers=127.0.0.1:{},topic=books", free_port()));
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Kafka error"));
+}
+
+#[test]
+fn rejects_incomplete_options() {
+    let output = publish("kafka-options", "topic=books");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'brokers' is required"));
+}
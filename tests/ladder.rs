@@ -0,0 +1,120 @@
+// The price ladder keeps the same levels as a BTreeMap of price to volume, whether prices land in its
+// array, off the cent grid or far from the inside, and its cached top levels match the map's
+use std::collections::BTreeMap;
+
+use nasdaq_itch_orderbook::ladder::PriceLadder;
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions, PriceLevel, Side, MAX_BOOK_DEPTH};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use proptest::prelude::*;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Add(u32, u32),
+    Reduce(u32, u32),
+}
+
+// Prices around $100 with some a dollar or more away and some off the cent grid
+fn price() -> impl Strategy<Value = u32> {
+    prop_oneof![
+        (9_900u32..10_100).prop_map(|cents| cents * 100),
+        (0u32..20_000).prop_map(|cents| cents * 100),
+        900_000u32..1_100_000,
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (price(), 0u32..1_000).prop_map(|(price, shares)| Op::Add(price, shares)),
+        (price(), 0u32..1_000).prop_map(|(price, shares)| Op::Reduce(price, shares)),
+    ]
+}
+
+// What the book did before the ladder
+fn reduce_level(map: &mut BTreeMap<u32, u32>, price: u32, shares: u32) -> bool {
+    let Some(volume) = map.get_mut(&price) else {
+        return shares == 0;
+    };
+    let enough = *volume >= shares;
+    *volume = volume.saturating_sub(shares);
+    if *volume == 0 {
+        map.remove(&price);
+    }
+    enough
+}
+
+fn best_first(map: &BTreeMap<u32, u32>, side: Side) -> Vec<(u32, u32)> {
+    let levels = map.iter().map(|(&price, &volume)| (price, volume));
+    match side {
+        Side::Buy => levels.rev().collect(),
+        Side::Sell => levels.collect(),
+    }
+}
+
+proptest! {
+    #[test]
+    fn matches_btreemap(ops in prop::collection::vec(op(), 1..400), buy: bool) {
+        let side = if buy { Side::Buy } else { Side::Sell };
+        let mut ladder = PriceLadder::new(side);
+        let mut map = BTreeMap::new();
+        for op in ops {
+            match op {
+                Op::Add(price, shares) => {
+                    ladder.add(price, shares);
+                    if shares > 0 {
+                        *map.entry(price).or_insert(0) += shares;
+                    }
+                }
+                Op::Reduce(price, shares) => {
+                    prop_assert_eq!(ladder.reduce(price, shares), reduce_level(&mut map, price, shares));
+                }
+            }
+            let expected = best_first(&map, side);
+            prop_assert_eq!(ladder.len(), expected.len());
+            prop_assert_eq!(ladder.best(), expected.first().map(|&(price, _)| price));
+            let top: Vec<(u32, u32)> = ladder.top_levels().iter().map(|level| (level.price, level.total_volume)).collect();
+            prop_assert_eq!(&top[..], &expected[..expected.len().min(MAX_BOOK_DEPTH)]);
+            prop_assert_eq!(ladder.top(3).len(), expected.len().min(3));
+        }
+        prop_assert_eq!(ladder.iter().collect::<Vec<_>>(), best_first(&map, side));
+        prop_assert_eq!(ladder.ascending(), map.iter().map(|(&price, &volume)| (price, volume)).collect::<Vec<_>>());
+        for (&price, &volume) in &map {
+            prop_assert_eq!(ladder.get(price), Some(volume));
+        }
+    }
+}
+
+#[test]
+fn follows_the_inside() {
+    // The array recenters on a new best bid far above it, leaving the old levels in the map
+    let mut ladder = PriceLadder::new(Side::Buy);
+    ladder.add(1_000_000, 100);
+    ladder.add(999_900, 200);
+    ladder.add(2_000_000, 300);
+    ladder.add(2_000_050, 50);
+    assert_eq!(ladder.iter().collect::<Vec<_>>(), vec![(2_000_050, 50), (2_000_000, 300), (1_000_000, 100), (999_900, 200)]);
+    assert!(ladder.reduce(2_000_050, 50));
+    assert!(!ladder.reduce(2_000_000, 400));
+    assert_eq!(ladder.best(), Some(1_000_000));
+    assert_eq!(PriceLadder::from_levels(Side::Buy, ladder.ascending()).iter().collect::<Vec<_>>(), ladder.iter().collect::<Vec<_>>());
+}
+
+#[test]
+fn book_top_levels() {
+    let add = |order_ref_number: u64, buy_sell_indicator: u8, price: u32| AddOrderMessage {
+        stock_locate: 1, tracking_number: 0, timestamp: 1_000 + order_ref_number, order_ref_number, buy_sell_indicator, shares: 100,
+        stock: pad_stock_symbol("AAPL"), price,
+    }.encode();
+    let mut data: Vec<u8> = (1..=12).flat_map(|i| add(i, b'B', 1_000_000 - 100 * i as u32)).collect();
+    data.extend(add(13, b'S', 1_000_500));
+    data.extend(OrderDeleteMessage { stock_locate: 1, tracking_number: 0, timestamp: 2_000, order_ref_number: 1 }.encode());
+
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions::default())];
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    let (bids, asks) = books[0].top_levels();
+    let prices: Vec<u32> = bids.iter().map(|level| level.price).collect();
+    assert_eq!(prices, (2..=11).map(|i| 1_000_000 - 100 * i).collect::<Vec<u32>>());
+    assert_eq!(asks.len(), 1);
+    assert_eq!(asks[0], PriceLevel { price: 1_000_500, total_volume: 100 });
+}
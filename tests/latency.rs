@@ -0,0 +1,64 @@
+// The latency histogram's percentiles stay within its precision of the exact ones, and a paced run records
+// one latency per timestamped message and publishes them as metrics
+use nasdaq_itch_orderbook::latency::LatencyHistogram;
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::metrics::METRICS;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+#[test]
+fn quantiles_within_precision() {
+    // Spread over nanoseconds to seconds
+    let mut values: Vec<u64> = (0..100_000u64).map(|i| i * i * 97 % 3_000_000_000).collect();
+    let mut histogram = LatencyHistogram::default();
+    for &value in &values {
+        histogram.record(value);
+    }
+    values.sort_unstable();
+    for fraction in [0.1, 0.5, 0.9, 0.99, 0.999, 1.0] {
+        let exact = values[((fraction * values.len() as f64).ceil() as usize).max(1) - 1];
+        let estimate = histogram.quantile(fraction);
+        assert!(estimate >= exact && estimate as f64 <= exact as f64 * 1.01 + 1.0, "p{} {} vs {}", fraction, estimate, exact);
+    }
+    assert_eq!(histogram.count(), 100_000);
+    assert_eq!(histogram.max(), *values.last().unwrap());
+    assert_eq!(histogram.quantile(1.0), histogram.max());
+}
+
+#[test]
+fn add_and_clear() {
+    let mut first = LatencyHistogram::default();
+    let mut second = LatencyHistogram::default();
+    (1..=50).for_each(|value| first.record(value));
+    (51..=100).for_each(|value| second.record(value * 1_000));
+    first.add(&second);
+    assert_eq!(first.count(), 100);
+    assert_eq!(first.quantile(0.5), 50);
+    assert_eq!(first.max(), 100_000);
+    assert!(first.summary().starts_with("p50 50 ns, p99 "));
+    first.clear();
+    assert_eq!((first.count(), first.sum(), first.quantile(0.5)), (0, 0, 0));
+}
+
+#[test]
+fn paced_run_records_latency() {
+    // 2000 adds 10 µs apart, 20 ms of market time
+    let data: Vec<u8> = (1..=2_000u64)
+        .flat_map(|i| AddOrderMessage {
+            stock_locate: 1, tracking_number: 0, timestamp: 34_200_000_000_000 + i * 10_000, order_ref_number: i,
+            buy_sell_indicator: b'B', shares: 100, stock: pad_stock_symbol("AAPL"), price: 1_000_000 - (i % 50) as u32 * 100,
+        }.encode())
+        .collect();
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions::default())];
+    let options = ParserOptions { pace: Some(1.0), latency_interval: Some(1_000_000), ..Default::default() };
+    parser::process_itch_file(&data, &mut books, &options).unwrap();
+
+    let rendered = METRICS.render();
+    assert!(rendered.contains("itch_processing_latency_nanoseconds{quantile=\"0.99\"}"), "{}", rendered);
+    let count = rendered.lines()
+        .find_map(|line| line.strip_prefix("itch_processing_latency_nanoseconds_count "))
+        .and_then(|count| count.parse::<u64>().ok())
+        .unwrap();
+    assert!(count >= 2_000, "{}", count);
+}
@@ -0,0 +1,35 @@
+// --order-lifecycle writes every order event with the shares queued ahead of the order
+mod common;
+
+use common::{add, cancel, delete, execute, replace, TempFile};
+
+#[test]
+fn tracks_queue_positions() {
+    let input = TempFile::with("lifecycle.itch", &[
+        add(1, 1, b'B', 100, 1_500_000),
+        add(2, 2, b'B', 200, 1_500_000),
+        add(3, 3, b'S', 50, 1_501_000),
+        execute(4, 1, 30, 1),
+        // A partial cancel keeps the order's place, a replace sends the new order to the back
+        cancel(5, 2, 50),
+        replace(6, 2, 4, 150, 1_500_000),
+        delete(7, 3),
+    ].concat());
+    let output = TempFile::new("lifecycle.csv");
+    let lifecycle = TempFile::new("lifecycle-events.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--order-lifecycle", lifecycle.path()]);
+
+    assert_eq!(lifecycle.read(), "\
+timestamp,order_ref,event,side,price,shares,remaining,queue_ahead,level_shares,age_ns
+1,1,add,B,150.0000,100,100,0,100,0
+2,2,add,B,150.0000,200,200,100,300,0
+3,3,add,S,150.1000,50,50,0,50,0
+4,1,execute,B,150.0000,30,70,0,300,3
+5,2,cancel,B,150.0000,50,150,70,270,3
+6,2,replace,B,150.0000,150,0,70,220,4
+6,4,add,B,150.0000,150,150,70,220,0
+7,3,delete,S,150.1000,50,0,0,50,4
+7,1,open,B,150.0000,0,70,0,220,6
+7,4,open,B,150.0000,0,150,70,220,1
+");
+}
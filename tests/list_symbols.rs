@@ -0,0 +1,38 @@
+// list-symbols prints the directory, stopping at the first order unless --whole-file
+mod common;
+
+use common::{add, directory, TempFile};
+
+fn input() -> TempFile {
+    TempFile::with("list-symbols.itch", &[
+        directory(1, "AAPL", 100),
+        directory(2, "MSFT", 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        // Added during the day
+        directory(3, "NVDA", 10),
+    ].concat())
+}
+
+fn list_symbols(args: &[&str]) -> String {
+    let output = common::run(&[&["list-symbols"], args].concat());
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn stops_at_the_first_order() {
+    let input = input();
+    assert!(list_symbols(&["-f", input.path()]).ends_with("\
+Locate  Symbol     Round lot
+1       AAPL             100
+2       MSFT             100
+2 symbols
+"));
+}
+
+#[test]
+fn scans_the_whole_file() {
+    let input = input();
+    let listed = list_symbols(&["-f", input.path(), "--whole-file"]);
+    assert!(listed.contains("3       NVDA              10\n"));
+    assert!(listed.ends_with("3 symbols\n"));
+}
@@ -0,0 +1,39 @@
+// IPO quoting period updates and direct listing price discovery messages of the symbol become events
+use rusqlite::Connection;
+
+mod common;
+
+use common::{add, message, stock, TempFile, LOCATE, SYMBOL};
+
+const NINE_THIRTY: u64 = 34_200_000_000_000;
+
+fn ipo_quoting_period(timestamp: u64, symbol: &str, release_seconds: u32, qualifier: u8, price: u32) -> Vec<u8> {
+    message(b'K', LOCATE, timestamp, &[&stock(symbol), &release_seconds.to_be_bytes(), &[qualifier], &price.to_be_bytes()])
+}
+
+#[test]
+fn writes_listing_events() {
+    let input = TempFile::with("listing-events.itch", &[
+        add(1, 1, b'B', 100, 1_500_000),
+        ipo_quoting_period(2, SYMBOL, 41_400, b'A', 250_000),
+        // Another symbol's release isn't an event of the tracked one
+        ipo_quoting_period(4, "MSFT", 41_400, b'A', 250_000),
+        ipo_quoting_period(5, SYMBOL, 41_400, b'C', 250_000),
+        message(b'O', LOCATE, 6, &[&stock(SYMBOL), b"Y", &240_000u32.to_be_bytes(), &260_000u32.to_be_bytes(),
+                                   &255_000u32.to_be_bytes(), &(NINE_THIRTY + 500_000_000).to_be_bytes(),
+                                   &229_500u32.to_be_bytes(), &280_500u32.to_be_bytes()]),
+    ].concat());
+    let output = TempFile::new("listing-events.db");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--output-format", "sqlite"]);
+
+    let conn = Connection::open(output.path()).unwrap();
+    let mut stmt = conn.prepare("SELECT timestamp, message_type, event FROM events ORDER BY timestamp").unwrap();
+    let events: Vec<(i64, String, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap().map(Result::unwrap).collect();
+    assert_eq!(events, [
+        (2, "K".to_string(), "IPO quotation release at 11:30:00, price 25.0000".to_string()),
+        (5, "K".to_string(), "IPO release canceled or postponed (was 11:30:00, price 25.0000)".to_string()),
+        (6, "O".to_string(), "Direct listing price discovery: eligible to open, allowable 24.0000-26.0000, \
+                              near execution 25.5000 at 09:30:00.500000000, collar 22.9500-28.0500".to_string()),
+    ]);
+}
@@ -0,0 +1,95 @@
+// --output-format lobster writes a LOBSTER message file and, line for line, the levels each message left
+use std::path::Path;
+
+mod common;
+
+use common::{add, build, execute};
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::lobster::lobster_paths;
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::orderbook::{OrderBookOptions, OutputFormat, WriteMode};
+use nasdaq_itch_orderbook::sink::create_file_sink;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use nasdaq_itch_orderbook::writer::WriterThread;
+
+const NINE_THIRTY: u64 = 34_200_000_000_000;
+
+// Every kind of message: adds on both sides, a partial cancel, an execution, a replace, a non-displayed trade,
+// a halt and a delete
+fn feed() -> Vec<u8> {
+    [
+        add(NINE_THIRTY, 1, b'B', 100, 1_500_100),
+        add(NINE_THIRTY + 1, 2, b'S', 200, 1_500_400),
+        add(NINE_THIRTY + 2, 3, b'B', 300, 1_500_000),
+        OrderCancelMessage { stock_locate: 1, tracking_number: 0, timestamp: NINE_THIRTY + 3, order_ref_number: 3, cancelled_shares: 100 }.encode(),
+        execute(NINE_THIRTY + 4, 2, 50, 1),
+        OrderReplaceMessage { stock_locate: 1, tracking_number: 0, timestamp: NINE_THIRTY + 5, original_order_ref_number: 1, new_order_ref_number: 4,
+                              shares: 150, price: 1_500_200 }.encode(),
+        TradeMessage { stock_locate: 1, tracking_number: 0, timestamp: NINE_THIRTY + 6, order_ref_number: 0, buy_sell_indicator: b'S', shares: 25,
+                       stock: pad_stock_symbol("AAPL"), price: 1_500_300, match_number: 2 }.encode(),
+        StockTradingActionMessage { stock_locate: 1, tracking_number: 0, timestamp: NINE_THIRTY + 7, stock: pad_stock_symbol("AAPL"),
+                                    trading_state: b'H', reserved: b' ', reason: *b"T1  " }.encode(),
+        OrderDeleteMessage { stock_locate: 1, tracking_number: 0, timestamp: NINE_THIRTY + 8, order_ref_number: 3 }.encode(),
+    ].concat()
+}
+
+fn write(path: &Path, levels: usize, write_mode: WriteMode, writer_thread: bool) -> (Vec<String>, Vec<String>) {
+    let mut sink = create_file_sink(OutputFormat::Lobster, path, false, FeatureColumns::default().with_levels(levels), None).unwrap();
+    if writer_thread {
+        sink = Box::new(WriterThread::spawn(sink, 16).unwrap());
+    }
+    let options = OrderBookOptions { write_mode, sinks: vec![sink], ..Default::default() };
+    build(&feed(), options);
+    let (messages, orderbook) = lobster_paths(path, levels);
+    let read = |path: &Path| {
+        let text = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        text.lines().map(str::to_string).collect()
+    };
+    (read(&messages), read(&orderbook))
+}
+
+#[test]
+fn names_the_files_like_lobster() {
+    let (messages, orderbook) = lobster_paths(Path::new("out/AAPL_2012-06-21.csv"), 5);
+    assert_eq!(messages, Path::new("out/AAPL_2012-06-21_message_5.csv"));
+    assert_eq!(orderbook, Path::new("out/AAPL_2012-06-21_orderbook_5.csv"));
+}
+
+#[test]
+fn writes_a_message_and_a_book_row_per_order_message() {
+    let path = std::env::temp_dir().join(format!("lobster-{}.csv", std::process::id()));
+    let (messages, orderbook) = write(&path, 2, WriteMode::EveryMessage, false);
+    assert_eq!(messages, [
+        "34200.000000000,1,1,100,1500100,1",
+        "34200.000000001,1,2,200,1500400,-1",
+        "34200.000000002,1,3,300,1500000,1",
+        "34200.000000003,2,3,100,1500000,1",
+        "34200.000000004,4,2,50,1500400,-1",
+        // A replace is the delete of the old order and a new order
+        "34200.000000005,3,1,100,1500100,1",
+        "34200.000000005,1,4,150,1500200,1",
+        "34200.000000006,5,0,25,1500300,-1",
+        "34200.000000007,7,0,0,-1,0",
+        "34200.000000008,3,3,200,1500000,1",
+    ]);
+    assert_eq!(orderbook.len(), messages.len());
+    assert_eq!(orderbook[0], "9999999999,0,1500100,100,9999999999,0,-9999999999,0");
+    assert_eq!(orderbook[4], "1500400,150,1500100,100,9999999999,0,1500000,200");
+    assert_eq!(orderbook[5], "1500400,150,1500000,200,9999999999,0,-9999999999,0");
+    assert_eq!(orderbook[6], "1500400,150,1500200,150,9999999999,0,1500000,200");
+    // The hidden execution and the halt repeat the book
+    assert_eq!(orderbook[7], orderbook[6]);
+    assert_eq!(orderbook[8], orderbook[6]);
+    assert_eq!(orderbook[9], "1500400,150,1500200,150,9999999999,0,-9999999999,0");
+}
+
+// Every order message is written whatever the write mode, and the same from the writer thread
+#[test]
+fn ignores_the_write_mode() {
+    let path = std::env::temp_dir().join(format!("lobster-modes-{}.csv", std::process::id()));
+    let every = write(&path, 10, WriteMode::EveryMessage, false);
+    assert_eq!(every.1[0].split(',').count(), 40);
+    assert_eq!(write(&path, 10, WriteMode::OnTopOfBookChange, false), every);
+    assert_eq!(write(&path, 10, WriteMode::EveryMessage, true), every);
+}
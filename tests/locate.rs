@@ -0,0 +1,40 @@
+// Adds and trades are picked by the symbol until the book learns its stock locate, then by the locate alone
+mod common;
+
+use common::{add_of, build};
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::orderbook::OrderBookOptions;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+fn trade(stock_locate: u16, symbol: &str, shares: u32) -> Vec<u8> {
+    TradeMessage { stock_locate, tracking_number: 0, timestamp: 2, order_ref_number: 0, buy_sell_indicator: b'B', shares,
+                   stock: pad_stock_symbol(symbol), price: 1_000_000, match_number: 1 }.encode()
+}
+
+#[test]
+fn picks_the_symbol_before_the_locate_is_known() {
+    let book = build(&[
+        add_of(2, "MSFT", 1, 1, b'B', 100, 2_000_000),
+        trade(2, "MSFT", 50),
+        add_of(1, "AAPL", 1, 2, b'B', 100, 1_000_000),
+        trade(1, "AAPL", 30),
+    ].concat(), OrderBookOptions::default());
+    assert_eq!(book.best_prices(), (Some(1_000_000), None));
+    assert_eq!(book.trade_stats().volume, 30);
+}
+
+#[test]
+fn picks_the_locate_once_known() {
+    let book = build(&[
+        add_of(1, "AAPL", 1, 1, b'B', 100, 1_000_000),
+        add_of(2, "MSFT", 1, 2, b'B', 100, 2_000_000),
+        trade(2, "MSFT", 50),
+        add_of(1, "AAPL", 1, 3, b'B', 100, 1_000_100),
+        trade(1, "AAPL", 30),
+        // The locate decides, whatever the symbol field says
+        add_of(2, "AAPL", 1, 4, b'B', 100, 3_000_000),
+    ].concat(), OrderBookOptions::default());
+    assert_eq!(book.best_prices(), (Some(1_000_100), None));
+    assert_eq!(book.resting_orders().0.len(), 2);
+    assert_eq!(book.trade_stats().volume, 30);
+}
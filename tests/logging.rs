@@ -0,0 +1,58 @@
+// JSON logs are one object per event, with the time, level, target, message, fields and enclosing spans
+#![cfg(feature = "native")]
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use nasdaq_itch_orderbook::logging::{self, LogFormat};
+use serde_json::Value;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn capture(format: LogFormat, log: impl FnOnce()) -> String {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let subscriber = logging::subscriber(format, BoxMakeWriter::new(move || writer.clone()), false);
+    tracing::subscriber::with_default(subscriber, log);
+    String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+}
+
+#[test]
+fn json_lines() {
+    let output = capture(LogFormat::Json, || {
+        let _span = tracing::info_span!("serve").entered();
+        tracing::info!(messages = 42u64, ratio = 0.5, quoted = "a \"b\"", "Processed {} messages", 42);
+        tracing::warn!("Client disconnected");
+        tracing::debug!("Not logged at the default level");
+    });
+    let lines: Vec<Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 2, "{}", output);
+
+    let first = &lines[0];
+    assert!(output.starts_with("{\"timestamp\":\""));
+    assert_eq!(first["level"], "INFO");
+    assert_eq!(first["target"], "logging");
+    assert_eq!(first["message"], "Processed 42 messages");
+    assert_eq!(first["messages"], 42);
+    assert_eq!(first["ratio"], 0.5);
+    assert_eq!(first["quoted"], "a \"b\"");
+    assert_eq!(first["spans"], serde_json::json!(["serve"]));
+    assert_eq!(lines[1]["level"], "WARN");
+}
+
+#[test]
+fn text_lines() {
+    let output = capture(LogFormat::Text, || tracing::info!("Processed {} messages", 42));
+    assert!(output.trim_end().ends_with("INFO logging: Processed 42 messages"), "{}", output);
+}
@@ -0,0 +1,121 @@
+// Odd lots are orders and trades of fewer shares than the round lot from the stock directory; they can be kept
+// off the book and out of the trades, reported on their own, and level sizes can be written in round lots
+use std::io;
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+use common::{add, build, directory, execute};
+use nasdaq_itch_orderbook::lots::{OddLotMode, OddLotReport};
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions, PriceLevel, TradeRecord};
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+#[derive(Default)]
+struct Written {
+    snapshots: Vec<BookSnapshot>,
+    trades: Vec<(u8, u32)>,
+}
+
+struct Collect(Arc<Mutex<Written>>);
+
+impl OutputSink for Collect {
+    fn write_snapshot(&mut self, _symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        self.0.lock().unwrap().snapshots.push(snapshot.clone());
+        Ok(())
+    }
+
+    fn write_trade(&mut self, _symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.0.lock().unwrap().trades.push((trade.message_type, trade.shares));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// With a round lot of 50: a round lot bid, an odd-lot bid above it, partly executed and then replaced by a
+// round lot, a round lot ask with an odd-lot execution, and an odd-lot non-displayed trade
+fn feed() -> Vec<u8> {
+    [
+        directory(1, "AAPL", 50),
+        add(10, 1, b'B', 120, 1_000_000),
+        add(11, 2, b'B', 30, 1_000_100),
+        add(12, 3, b'S', 75, 1_000_500),
+        execute(13, 3, 10, 1),
+        execute(14, 2, 5, 2),
+        OrderReplaceMessage { stock_locate: 1, tracking_number: 0, timestamp: 15, original_order_ref_number: 2, new_order_ref_number: 4,
+                              shares: 60, price: 1_000_100 }.encode(),
+        TradeMessage { stock_locate: 1, tracking_number: 0, timestamp: 16, order_ref_number: 0, buy_sell_indicator: b'B', shares: 40,
+                       stock: pad_stock_symbol("AAPL"), price: 1_000_300, match_number: 3 }.encode(),
+        execute(17, 1, 100, 4),
+    ].concat()
+}
+
+fn run(options: OrderBookOptions) -> (OrderBook, Written) {
+    let written = Arc::new(Mutex::new(Written::default()));
+    let options = OrderBookOptions { sinks: vec![Box::new(Collect(Arc::clone(&written)))], ..options };
+    let book = build(&feed(), options);
+    let written = std::mem::take(&mut *written.lock().unwrap());
+    (book, written)
+}
+
+fn top(snapshot: &BookSnapshot) -> (Option<PriceLevel>, Option<PriceLevel>) {
+    (snapshot.bid_levels.first().copied(), snapshot.ask_levels.first().copied())
+}
+
+fn level(price: u32, total_volume: u32) -> Option<PriceLevel> {
+    Some(PriceLevel { price, total_volume })
+}
+
+#[test]
+fn includes_odd_lots_by_default() {
+    let (book, written) = run(OrderBookOptions::default());
+    assert_eq!(top(&written.snapshots[1]), (level(1_000_100, 30), None));
+    assert_eq!(written.trades, [(b'E', 10), (b'E', 5), (b'P', 40), (b'E', 100)]);
+    assert_eq!(book.best_prices(), (Some(1_000_100), Some(1_000_500)));
+}
+
+#[test]
+fn excludes_and_reports_odd_lots() {
+    let path = std::env::temp_dir().join(format!("odd-lots-{}.csv", std::process::id()));
+    let options = OrderBookOptions { odd_lots: OddLotMode::Exclude, odd_lot_report: Some(OddLotReport::create(&path).unwrap()), ..Default::default() };
+    let (book, written) = run(options);
+    let report = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // The odd-lot bid never shows, and the round lot that replaces it does
+    let tops: Vec<_> = written.snapshots.iter().map(top).collect();
+    assert_eq!(tops, [
+        (level(1_000_000, 120), None),
+        (level(1_000_000, 120), level(1_000_500, 75)),
+        (level(1_000_000, 120), level(1_000_500, 65)),
+        (level(1_000_100, 60), level(1_000_500, 65)),
+        (level(1_000_100, 60), level(1_000_500, 65)),
+    ]);
+    assert_eq!(written.trades, [(b'E', 100)]);
+    assert_eq!(book.best_prices(), (Some(1_000_100), Some(1_000_500)));
+
+    assert_eq!(report.lines().collect::<Vec<_>>(), [
+        "timestamp,kind,message_type,order_ref,match_number,side,price,shares,round_lot",
+        "11,order,A,2,,B,100.0100,30,50",
+        "13,trade,E,,1,S,100.0500,10,50",
+        "14,trade,E,,2,B,100.0100,5,50",
+        "16,trade,P,,3,B,100.0300,40,50",
+    ]);
+}
+
+#[test]
+fn writes_level_sizes_in_round_lots() {
+    let (_, written) = run(OrderBookOptions { lots: true, ..Default::default() });
+    // 120 shares are 2 lots of 50, and the 30 share bid above them is left out
+    assert_eq!(top(&written.snapshots[0]), (level(1_000_000, 2), None));
+    assert_eq!(top(&written.snapshots[1]), (level(1_000_000, 2), None));
+    assert_eq!(top(&written.snapshots[2]), (level(1_000_000, 2), level(1_000_500, 1)));
+    let last = written.snapshots.last().unwrap();
+    assert_eq!(top(last), (level(1_000_100, 1), level(1_000_500, 1)));
+    // Trades stay in shares
+    assert_eq!(written.trades, [(b'E', 10), (b'E', 5), (b'P', 40), (b'E', 100)]);
+}
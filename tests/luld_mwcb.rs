@@ -0,0 +1,58 @@
+// LULD auction collars and market wide circuit breakers become events and, with --features luld,mwcb, columns
+use rusqlite::Connection;
+
+mod common;
+
+use common::{add, message, stock, TempFile, LOCATE, SYMBOL};
+
+fn input(name: &str) -> TempFile {
+    TempFile::with(name, &[
+        add(1, 1, b'B', 100, 1_500_000),
+        message(b'J', LOCATE, 2, &[&stock(SYMBOL), &1_500_000u32.to_be_bytes(), &1_575_000u32.to_be_bytes(),
+                                   &1_425_000u32.to_be_bytes(), &1u32.to_be_bytes()]),
+        message(b'V', 0, 3, &[&512_345_000_000u64.to_be_bytes(), &476_000_000_000u64.to_be_bytes(), &420_000_000_000u64.to_be_bytes()]),
+        message(b'W', 0, 4, &[b"2"]),
+        // The level only goes up
+        message(b'W', 0, 5, &[b"1"]),
+    ].concat())
+}
+
+#[test]
+fn writes_collar_and_breaker_columns() {
+    let input = input("luld-mwcb.itch");
+    let output = TempFile::new("luld-mwcb.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--features", "luld,mwcb"]);
+
+    let csv = output.read();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().ends_with(",luld_reference_price,luld_upper_collar,luld_lower_collar,luld_extensions,mwcb_level"));
+    let columns: Vec<(&str, Vec<&str>)> = lines.map(|line| {
+        let fields: Vec<&str> = line.split(',').collect();
+        (fields[0], fields[fields.len() - 5..].to_vec())
+    }).collect();
+    // No row for the decline levels, which don't change anything of the symbol
+    assert_eq!(columns, [
+        ("1", vec!["0.0000", "0.0000", "0.0000", "0", "0"]),
+        ("2", vec!["150.0000", "157.5000", "142.5000", "1", "0"]),
+        ("4", vec!["150.0000", "157.5000", "142.5000", "1", "2"]),
+        ("5", vec!["150.0000", "157.5000", "142.5000", "1", "2"]),
+    ]);
+}
+
+#[test]
+fn writes_events() {
+    let input = input("luld-mwcb-events.itch");
+    let output = TempFile::new("luld-mwcb-events.db");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--output-format", "sqlite"]);
+
+    let conn = Connection::open(output.path()).unwrap();
+    let mut stmt = conn.prepare("SELECT timestamp, message_type, event FROM events ORDER BY timestamp").unwrap();
+    let events: Vec<(i64, String, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap().map(Result::unwrap).collect();
+    assert_eq!(events, [
+        (2, "J".to_string(), "LULD auction collar: reference 150.0000, upper 157.5000, lower 142.5000, extension 1".to_string()),
+        (3, "V".to_string(), "MWCB decline levels: 1 at 5123.45, 2 at 4760.00, 3 at 4200.00".to_string()),
+        (4, "W".to_string(), "MWCB level 2 breached".to_string()),
+        (5, "W".to_string(), "MWCB level 1 breached".to_string()),
+    ]);
+}
@@ -0,0 +1,59 @@
+// The matching engine keeps the seeded book's queue order and matches new orders price-time priority
+mod common;
+
+use common::{add, build};
+use nasdaq_itch_orderbook::matching::{MatchingEngine, SEEDED_OWNER};
+use nasdaq_itch_orderbook::orderbook::{OrderBookOptions, PriceLevel, Side};
+
+fn seeded() -> MatchingEngine {
+    let data = [
+        add(1, 10, b'S', 100, 101_000),
+        add(2, 11, b'S', 200, 101_000),
+        add(3, 12, b'S', 100, 102_000),
+        add(4, 13, b'B', 300, 100_000),
+    ].concat();
+    MatchingEngine::from_book(&build(&data, OrderBookOptions::default()))
+}
+
+#[test]
+fn sweeps_in_priority_order() {
+    let mut engine = seeded();
+    let (id, executions) = engine.submit(1, Side::Buy, 102_000, 350).unwrap();
+    assert_eq!(id, 14);
+    let fills: Vec<(u64, u32, u32, u32)> = executions.iter()
+        .map(|execution| (execution.resting_id, execution.price, execution.shares, execution.resting_remaining))
+        .collect();
+    assert_eq!(fills, [(10, 101_000, 100, 0), (11, 101_000, 200, 0), (12, 102_000, 50, 50)]);
+    assert!(executions.iter().all(|execution| execution.aggressor_id == id && execution.resting_owner == SEEDED_OWNER));
+    assert!(engine.order(id).is_none());
+
+    let (bids, asks) = engine.levels(10);
+    assert_eq!(bids, [PriceLevel { price: 100_000, total_volume: 300 }]);
+    assert_eq!(asks, [PriceLevel { price: 102_000, total_volume: 50 }]);
+}
+
+#[test]
+fn rests_cancels_and_replaces() {
+    let mut engine = seeded();
+    // Joins the bid behind the seeded order
+    let (bid, executions) = engine.submit(1, Side::Buy, 100_000, 100).unwrap();
+    assert!(executions.is_empty());
+
+    // Other sessions can't touch it, nor the seeded orders
+    assert!(engine.cancel(2, bid).is_err());
+    assert!(engine.cancel(1, 13).is_err());
+
+    // Reducing keeps its place, so a seller fills the seeded order first
+    assert!(engine.replace(1, bid, 100_000, 50).unwrap().is_empty());
+    let (_, executions) = engine.submit(2, Side::Sell, 100_000, 320).unwrap();
+    let fills: Vec<(u64, u32)> = executions.iter().map(|execution| (execution.resting_id, execution.shares)).collect();
+    assert_eq!(fills, [(13, 300), (bid, 20)]);
+    assert_eq!(engine.order(bid).unwrap().shares, 30);
+
+    // Repricing through the ask trades
+    let executions = engine.replace(1, bid, 101_000, 30).unwrap();
+    assert_eq!(executions.len(), 1);
+    assert_eq!((executions[0].resting_id, executions[0].shares), (10, 30));
+    assert!(engine.order(bid).is_none());
+    assert!(engine.cancel(1, bid).is_err());
+}
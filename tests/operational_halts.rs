@@ -0,0 +1,38 @@
+// Operational halts of the symbol on each market become events and, with --features halts, columns
+mod common;
+
+use common::{add, message, stock, TempFile, LOCATE, SYMBOL};
+
+fn operational_halt(timestamp: u64, market_code: u8, action: u8) -> Vec<u8> {
+    message(b'h', LOCATE, timestamp, &[&stock(SYMBOL), &[market_code, action]])
+}
+
+#[test]
+fn writes_halts_per_market() {
+    let input = TempFile::with("operational-halts.itch", &[
+        add(1, 1, b'B', 100, 1_500_000),
+        operational_halt(2, b'Q', b'H'),
+        operational_halt(3, b'B', b'H'),
+        operational_halt(4, b'Q', b'T'),
+        // Already lifted, so no row
+        operational_halt(5, b'Q', b'T'),
+        operational_halt(6, b'X', b'H'),
+    ].concat());
+    let output = TempFile::new("operational-halts.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--features", "halts"]);
+
+    let csv = output.read();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().ends_with(",halted_nasdaq,halted_bx,halted_psx"));
+    let halts: Vec<(&str, Vec<&str>)> = lines.map(|line| {
+        let fields: Vec<&str> = line.split(',').collect();
+        (fields[0], fields[fields.len() - 3..].to_vec())
+    }).collect();
+    assert_eq!(halts, [
+        ("1", vec!["0", "0", "0"]),
+        ("2", vec!["1", "0", "0"]),
+        ("3", vec!["1", "1", "0"]),
+        ("4", vec!["0", "1", "0"]),
+        ("6", vec!["0", "1", "1"]),
+    ]);
+}
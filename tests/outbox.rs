@@ -0,0 +1,110 @@
+// A full client queue is handled by the slow-client policy, and a client that stops answering pings is
+// disconnected
+#![cfg(feature = "native")]
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::outbox::{ClientPolicy, Outbox, SlowClientPolicy};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use nasdaq_itch_orderbook::websocket::{BookUpdate, LiveSink, WebSocketServer, WireFormat, BROADCAST_CAPACITY};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+// The first snapshots the live feed publishes for a synthetic day
+fn snapshots(count: usize) -> Vec<BookUpdate> {
+    let (tx, mut rx) = broadcast::channel(100_000);
+    let sink = Box::new(LiveSink::new(tx, false, FeatureColumns::default()));
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], ..Default::default() })];
+    let data = SyntheticFeed { messages: 5_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    std::iter::from_fn(|| rx.try_recv().ok()).filter(|update| update.snapshot.is_some()).take(count).collect()
+}
+
+fn notice(text: &str) -> BookUpdate {
+    BookUpdate { symbol: None, json: text.to_string(), snapshot: None }
+}
+
+// Everything the outbox sends until it runs out
+async fn drain(outbox: &Outbox) -> Vec<Message> {
+    let mut sent = Vec::new();
+    let sink = futures_util::sink::unfold(&mut sent, |sent, message| async move {
+        sent.push(message);
+        Ok::<_, std::convert::Infallible>(sent)
+    });
+    let _ = tokio::time::timeout(Duration::from_millis(100), outbox.write_to(Box::pin(sink))).await;
+    sent
+}
+
+fn policy(slow_client: SlowClientPolicy) -> ClientPolicy {
+    ClientPolicy { slow_client, buffer: 2, ping_interval: None, ..Default::default() }
+}
+
+#[tokio::test]
+async fn applies_slow_client_policy() {
+    let updates = snapshots(3);
+    let texts: Vec<Message> = updates.iter().map(|update| Message::Text(update.json.clone())).collect();
+
+    let outbox = Outbox::new(WireFormat::Json, policy(SlowClientPolicy::Disconnect));
+    outbox.push_update(updates[0].clone()).unwrap();
+    outbox.push_update(updates[1].clone()).unwrap();
+    assert!(outbox.push_update(updates[2].clone()).is_err());
+
+    // A close frame goes out after the control messages, without the queued updates
+    outbox.push_control(Message::Pong(Vec::new()));
+    outbox.close(CloseFrame { code: CloseCode::Policy, reason: "Too slow".into() });
+    outbox.push_control(Message::Ping(Vec::new()));
+    let sent = drain(&outbox).await;
+    assert_eq!(sent.len(), 2);
+    assert!(sent[1].is_close());
+
+    // The oldest update goes, and the client is told
+    let outbox = Outbox::new(WireFormat::Json, policy(SlowClientPolicy::Buffer));
+    for update in &updates {
+        outbox.push_update(update.clone()).unwrap();
+    }
+    let lagged = Message::Text("{\"type\":\"lagged\",\"dropped\":1}".to_string());
+    assert_eq!(drain(&outbox).await, vec![lagged, texts[1].clone(), texts[2].clone()]);
+
+    // A newer snapshot of the symbol replaces the queued one, and other messages keep their place
+    let outbox = Outbox::new(WireFormat::Json, policy(SlowClientPolicy::Conflate));
+    outbox.push_update(updates[0].clone()).unwrap();
+    outbox.push_update(notice("{\"type\":\"halt\"}")).unwrap();
+    outbox.push_update(updates[1].clone()).unwrap();
+    outbox.push_update(updates[2].clone()).unwrap();
+    assert_eq!(drain(&outbox).await, vec![Message::Text("{\"type\":\"halt\"}".to_string()), texts[2].clone()]);
+}
+
+#[tokio::test]
+async fn disconnects_clients_that_stop_answering_pings() {
+    let port = 18_476;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let policy = ClientPolicy { ping_interval: Some(Duration::from_millis(100)), ..Default::default() };
+    let server = WebSocketServer::live(tx, port).with_client_policy(policy);
+    tokio::spawn(async move { server.start().await });
+
+    let url = format!("ws://127.0.0.1:{}", port);
+    let mut client = loop {
+        match tokio_tungstenite::connect_async(&url).await {
+            Ok((client, _)) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+    // Not reading means not answering the pings
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    let mut messages = Vec::new();
+    while let Ok(Some(Ok(message))) = tokio::time::timeout(Duration::from_secs(5), client.next()).await {
+        messages.push(message);
+    }
+    assert!(matches!(messages[0], Message::Ping(_)), "{:?}", messages);
+    let Some(Message::Close(Some(frame))) = messages.iter().find(|message| message.is_close()) else {
+        panic!("expected a close frame, got {:?}", messages);
+    };
+    assert_eq!(frame.code, CloseCode::Policy);
+    assert_eq!(frame.reason, "Ping timeout");
+}
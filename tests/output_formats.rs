@@ -0,0 +1,79 @@
+// Every --output-format writes the same snapshots; only the encoding differs
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use std::fs::File;
+
+mod common;
+
+use common::{add, binary, cancel, execute, system_event, TempFile};
+
+// (timestamp, best bid, bid volume, best ask, ask volume) of each snapshot
+type Top = (u64, f64, u64, f64, u64);
+
+const EXPECTED: [Top; 4] = [
+    (1, 150.0, 100, 0.0, 0),
+    (2, 150.0, 100, 150.05, 200),
+    (3, 150.0, 70, 150.05, 200),
+    (4, 150.0, 70, 150.05, 150),
+];
+
+fn write(name: &str, format: &str) -> TempFile {
+    let input = TempFile::with(&format!("{}.itch", name), &[
+        add(1, 1, b'B', 100, 1_500_000),
+        add(2, 2, b'S', 200, 1_500_500),
+        execute(3, 1, 30, 7),
+        cancel(4, 2, 50),
+        system_event(5, b'C'),
+        system_event(6, b'C'),
+    ].concat());
+    let output = TempFile::new(&format!("{}.{}", name, format));
+    let status = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--output-format", format])
+        .status().unwrap();
+    assert!(status.success());
+    output
+}
+
+#[test]
+fn writes_csv() {
+    let output = write("formats-csv", "csv");
+    let contents = output.read();
+    let mut lines = contents.lines();
+    assert!(lines.next().unwrap().starts_with("timestamp,1_bid_price,1_bid_vol,1_ask_price,1_ask_vol,"));
+    let rows: Vec<Top> = lines.map(|line| {
+        let fields: Vec<&str> = line.split(',').collect();
+        (fields[0].parse().unwrap(), fields[1].parse().unwrap(), fields[2].parse().unwrap(),
+         fields[3].parse().unwrap(), fields[4].parse().unwrap())
+    }).collect();
+    assert_eq!(rows, EXPECTED);
+}
+
+#[test]
+fn writes_jsonl() {
+    let output = write("formats-jsonl", "jsonl");
+    let rows: Vec<Top> = output.read().lines().map(|line| {
+        let row: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(row["symbol"], "AAPL");
+        (row["timestamp"].as_str().unwrap().parse().unwrap(), row["1_bid_price"].as_f64().unwrap(), row["1_bid_vol"].as_u64().unwrap(),
+         row["1_ask_price"].as_f64().unwrap(), row["1_ask_vol"].as_u64().unwrap())
+    }).collect();
+    assert_eq!(rows, EXPECTED);
+}
+
+#[test]
+fn writes_parquet() {
+    use parquet::record::RowAccessor;
+
+    let output = write("formats-parquet", "parquet");
+    let reader = SerializedFileReader::new(File::open(output.path()).unwrap()).unwrap();
+    let rows: Vec<Top> = reader.get_row_iter(None).unwrap().map(|row| {
+        let row = row.unwrap();
+        (row.get_long(0).unwrap() as u64, row.get_double(1).unwrap(), row.get_long(2).unwrap() as u64,
+         row.get_double(3).unwrap(), row.get_long(4).unwrap() as u64)
+    }).collect();
+    assert_eq!(rows, EXPECTED);
+}
+
+#[test]
+fn writes_nothing_for_null() {
+    let output = write("formats-null", "null");
+    assert!(!output.0.exists());
+}
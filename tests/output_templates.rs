@@ -0,0 +1,45 @@
+// {symbol} and {date} in an output path route each symbol's output to its own file
+use std::path::{Path, PathBuf};
+
+use nasdaq_itch_orderbook::cli::{InputArgs, SnapshotArgs};
+use nasdaq_itch_orderbook::message_types::FeedVariant;
+use nasdaq_itch_orderbook::partition::{routes_symbols, symbol_output, Partitioning};
+use nasdaq_itch_orderbook::pcap::Encapsulation;
+use nasdaq_itch_orderbook::snapshot::{self, SnapshotFormat};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+
+#[test]
+fn fills_symbol_and_date() {
+    let partitioning = Partitioning { symbol: "AAPL".to_string(), date: "01302020".to_string(), ..Default::default() };
+    assert_eq!(partitioning.fill(Path::new("stats/{symbol}_{date}.json")), PathBuf::from("stats/AAPL_01302020.json"));
+    assert_eq!(partitioning.fill(Path::new("stats.json")), PathBuf::from("stats.json"));
+    assert!(routes_symbols(Path::new("books/{symbol}.csv")));
+    assert!(!routes_symbols(Path::new("books/{date}.csv")));
+    // A template is left for the sink to fill in
+    assert_eq!(symbol_output(Path::new("books/{symbol}_{date}.csv"), "AAPL"), PathBuf::from("books/{symbol}_{date}.csv"));
+}
+
+#[test]
+fn writes_a_snapshot_per_symbol() {
+    let dir = std::env::temp_dir().join(format!("templates-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("01302020.NASDAQ_ITCH50");
+    std::fs::write(&input, SyntheticFeed { messages: 20_000, ..Default::default() }.generate()).unwrap();
+
+    let args = SnapshotArgs {
+        input: InputArgs { file: Some(input), file_glob: None, encap: Encapsulation::Raw, feed: FeedVariant::Nasdaq, strict: false, events_output: None },
+        at: u64::MAX,
+        symbols: vec!["AAPL".to_string(), "MSFT".to_string()],
+        output_file: dir.join("snapshots/{date}/{symbol}.csv"),
+        format: SnapshotFormat::Csv,
+    };
+    snapshot::run(args).unwrap();
+
+    for symbol in ["AAPL", "MSFT"] {
+        let snapshot = std::fs::read_to_string(dir.join("snapshots/01302020").join(format!("{}.csv", symbol))).unwrap();
+        let mut rows = snapshot.lines().skip(1).peekable();
+        assert!(rows.peek().is_some());
+        assert!(rows.all(|row| row.starts_with(&format!("{},", symbol))));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
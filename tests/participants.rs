@@ -0,0 +1,30 @@
+// --participants-output sums the symbol's order activity per MPID
+mod common;
+
+use common::{add, add_with_mpid, cancel, delete, execute, replace, TempFile};
+
+#[test]
+fn reports_activity_per_participant() {
+    let input = TempFile::with("participants.itch", &[
+        add_with_mpid(1, 1, b'B', 100, 1_500_000, b"GSCO"),
+        add_with_mpid(2, 2, b'S', 200, 1_501_000, b"MSCO"),
+        add(3, 3, b'B', 50, 1_499_000),
+        add_with_mpid(4, 4, b'B', 300, 1_498_000, b"GSCO"),
+        execute(5, 1, 30, 1),
+        cancel(6, 2, 50),
+        delete(7, 3),
+        // The new order stays with GSCO without counting as an add
+        replace(8, 4, 5, 250, 1_498_500),
+        delete(9, 5),
+    ].concat());
+    let output = TempFile::new("participants.csv");
+    let participants = TempFile::new("participants-report.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--participants-output", participants.path()]);
+
+    assert_eq!(participants.read(), "\
+mpid,adds,added_shares,executions,executed_shares,cancels,cancelled_shares,replaces
+GSCO,2,400,1,30,1,250,1
+MSCO,1,200,0,0,1,50,0
+,1,50,0,0,1,50,0
+");
+}
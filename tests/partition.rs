@@ -0,0 +1,47 @@
+// Partitioned output fills in the path template and starts a new part at each interval of market time
+use std::path::{Path, PathBuf};
+
+mod common;
+
+use common::{add, build};
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{OrderBookOptions, OutputFormat};
+use nasdaq_itch_orderbook::partition::{create_output_sink, Partitioning};
+
+const SECOND: u64 = 1_000_000_000;
+
+fn partitioning(interval: Option<u64>) -> Partitioning {
+    Partitioning { symbol: "AAPL".to_string(), date: "01302020".to_string(), max_bytes: None, interval }
+}
+
+#[test]
+fn part_paths() {
+    let template = Path::new("out/{symbol}/{date}/part-{part}.parquet");
+    assert_eq!(partitioning(None).part_path(template, 1), PathBuf::from("out/AAPL/01302020/part-0001.parquet"));
+    assert_eq!(partitioning(Some(SECOND)).part_path(template, 12), PathBuf::from("out/AAPL/01302020/part-0012.parquet"));
+    assert_eq!(partitioning(Some(SECOND)).part_path(Path::new("AAPL.csv"), 2), PathBuf::from("AAPL.part-0002.csv"));
+    assert_eq!(partitioning(None).base_path(Path::new("{symbol}.csv")), PathBuf::from("AAPL.csv"));
+    assert!(!partitioning(None).applies_to(Path::new("AAPL.csv")));
+}
+
+#[test]
+fn rotates_by_interval() {
+    let dir = std::env::temp_dir().join(format!("partition-{}", std::process::id()));
+    let sink = create_output_sink(OutputFormat::Csv, &dir.join("{symbol}/part-{part}.csv"), false, FeatureColumns::default(), None,
+                                  partitioning(Some(SECOND))).unwrap();
+    // Nothing happens in the second second, so there is no part for it
+    let data = [
+        add(SECOND / 2, 1, b'B', 100, 100_001),
+        add(SECOND - 1, 2, b'B', 100, 100_002),
+        add(SECOND, 3, b'B', 100, 100_003),
+        add(3 * SECOND, 4, b'B', 100, 100_004),
+    ].concat();
+    build(&data, OrderBookOptions { sinks: vec![sink], ..Default::default() });
+
+    let rows = |part: &str| std::fs::read_to_string(dir.join("AAPL").join(part)).unwrap().lines().count() - 1;
+    let parts = (rows("part-0001.csv"), rows("part-0002.csv"), rows("part-0003.csv"));
+    let fourth = dir.join("AAPL/part-0004.csv").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(parts, (2, 1, 1));
+    assert!(!fourth);
+}
@@ -0,0 +1,157 @@
+// --encap moldudp64 rebuilds the message stream from a pcap capture: whatever the link layer, with
+// redundant feeds deduplicated, and with gaps and truncated packets counted as missing messages
+mod common;
+
+use common::{add, binary, execute, system_event, TempFile};
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+
+fn messages() -> Vec<Vec<u8>> {
+    vec![
+        add(1, 1, b'B', 100, 1_500_000),
+        add(2, 2, b'S', 200, 1_500_500),
+        execute(3, 1, 30, 7),
+        system_event(4, b'C'),
+        system_event(5, b'C'),
+    ]
+}
+
+// A MoldUDP64 packet with consecutive messages from the sequence number on
+fn mold(sequence: u64, messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut packet = b"SESSION001".to_vec();
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(&(messages.len() as u16).to_be_bytes());
+    for message in messages {
+        packet.extend_from_slice(message);
+    }
+    packet
+}
+
+fn ipv4_udp(payload: &[u8]) -> Vec<u8> {
+    let total_len = (20 + 8 + payload.len()) as u16;
+    let mut ip = vec![0x45, 0];
+    ip.extend_from_slice(&total_len.to_be_bytes());
+    ip.extend_from_slice(&[0, 0, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 233, 54, 12, 111]);
+    ip.extend_from_slice(&26477u16.to_be_bytes());
+    ip.extend_from_slice(&26477u16.to_be_bytes());
+    ip.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+    ip.extend_from_slice(&[0, 0]);
+    ip.extend_from_slice(payload);
+    ip
+}
+
+fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x01, 0x00, 0x5e, 0x36, 0x0c, 0x6f, 0x00, 0x1b, 0x21, 0x00, 0x00, 0x01];
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn vlan(payload: &[u8]) -> Vec<u8> {
+    let mut tagged = vec![0x8a, 0x00, 0x08, 0x00];
+    tagged.extend_from_slice(&ipv4_udp(payload));
+    ethernet(0x8100, &tagged)
+}
+
+fn sll(payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0, 0, 0, 1, 0, 6, 0x00, 0x1b, 0x21, 0x00, 0x00, 0x01, 0, 0, 0x08, 0x00];
+    frame.extend_from_slice(&ipv4_udp(payload));
+    frame
+}
+
+// A little endian pcap file; each frame is captured up to its snapshot length
+fn pcap(link_type: u32, frames: &[(Vec<u8>, usize)]) -> Vec<u8> {
+    let mut file = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    file.extend_from_slice(&65535u32.to_le_bytes());
+    file.extend_from_slice(&link_type.to_le_bytes());
+    for (frame, snaplen) in frames {
+        let captured = frame.len().min(*snaplen);
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&(captured as u32).to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&frame[..captured]);
+    }
+    file
+}
+
+fn whole(frames: Vec<Vec<u8>>) -> Vec<(Vec<u8>, usize)> {
+    frames.into_iter().map(|frame| (frame, usize::MAX)).collect()
+}
+
+// The log and the book built from a capture
+fn run(name: &str, capture: &[u8]) -> (String, String) {
+    let input = TempFile::with(&format!("{}.pcap", name), capture);
+    let output = TempFile::new(&format!("{}.csv", name));
+    let result = binary().args(["build-book", "-f", input.path(), "--encap", "moldudp64", "-s", "AAPL", "-o", output.path()]).output().unwrap();
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    (String::from_utf8_lossy(&result.stdout).into_owned(), output.read())
+}
+
+// The book built from the same messages as a plain ITCH file
+fn raw_book(messages: &[Vec<u8>]) -> String {
+    let input = TempFile::with("pcap-raw.itch", &messages.concat());
+    let output = TempFile::new("pcap-raw.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path()]);
+    output.read()
+}
+
+#[test]
+fn reads_every_link_layer() {
+    let messages = messages();
+    let book = raw_book(&messages);
+    let (first, second) = (mold(1, &messages[..2]), mold(3, &messages[2..]));
+    // An ARP frame in between isn't MoldUDP64
+    let arp = ethernet(0x0806, &[0; 28]);
+
+    let captures = [
+        ("pcap-ethernet", pcap(LINKTYPE_ETHERNET, &whole(vec![ethernet(0x0800, &ipv4_udp(&first)), arp, ethernet(0x0800, &ipv4_udp(&second))]))),
+        ("pcap-vlan", pcap(LINKTYPE_ETHERNET, &whole(vec![vlan(&first), vlan(&second)]))),
+        ("pcap-sll", pcap(LINKTYPE_LINUX_SLL, &whole(vec![sll(&first), sll(&second)]))),
+    ];
+    for (name, capture) in captures {
+        let (log, extracted) = run(name, &capture);
+        assert!(log.contains("Extracted 5 messages from"), "{}: {}", name, log);
+        assert!(log.contains("0 duplicate and 0 missing messages"), "{}: {}", name, log);
+        assert_eq!(extracted, book, "{}", name);
+    }
+}
+
+#[test]
+fn drops_duplicates_from_redundant_feeds() {
+    let messages = messages();
+    let (first, second) = (mold(1, &messages[..2]), mold(3, &messages[2..]));
+    // The B feed repeats every packet, and its second one arrives ahead of the A feed's first
+    let frames = [first.clone(), second.clone(), first, second].iter().map(|p| ethernet(0x0800, &ipv4_udp(p))).collect();
+    let (log, extracted) = run("pcap-duplicates", &pcap(LINKTYPE_ETHERNET, &whole(frames)));
+    assert!(log.contains("Extracted 5 messages from 4 packets (0 skipped packets, 5 duplicate and 0 missing messages)"), "{}", log);
+    assert_eq!(extracted, raw_book(&messages));
+}
+
+#[test]
+fn counts_sequence_gaps() {
+    let messages = messages();
+    // Sequence numbers 3 and 4 were never captured
+    let frames = [mold(1, &messages[..2]), mold(5, &messages[2..])].iter().map(|p| ethernet(0x0800, &ipv4_udp(p))).collect();
+    let (log, _) = run("pcap-gap", &pcap(LINKTYPE_ETHERNET, &whole(frames)));
+    assert!(log.contains("Extracted 5 messages from 2 packets (0 skipped packets, 0 duplicate and 2 missing messages)"), "{}", log);
+    assert!(log.contains("Capture has gaps: 2 messages are missing"), "{}", log);
+}
+
+#[test]
+fn counts_messages_cut_by_the_snapshot_length() {
+    let messages = messages();
+    let first = ethernet(0x0800, &ipv4_udp(&mold(1, &messages[..3])));
+    // The capture ends inside the second message of the first packet
+    let snaplen = first.len() - messages[2].len() - 5;
+    let frames = vec![(first, snaplen), (ethernet(0x0800, &ipv4_udp(&mold(4, &messages[3..]))), usize::MAX)];
+    let (log, _) = run("pcap-snaplen", &pcap(LINKTYPE_ETHERNET, &frames));
+    assert!(log.contains("Extracted 3 messages from 2 packets (0 skipped packets, 0 duplicate and 2 missing messages)"), "{}", log);
+
+    // Also when no later packet shows the gap
+    let last = ethernet(0x0800, &ipv4_udp(&mold(3, &messages[2..])));
+    let snaplen = last.len() - messages[4].len() - 5;
+    let frames = vec![(ethernet(0x0800, &ipv4_udp(&mold(1, &messages[..2]))), usize::MAX), (last, snaplen)];
+    let (log, _) = run("pcap-snaplen-last", &pcap(LINKTYPE_ETHERNET, &frames));
+    assert!(log.contains("Extracted 3 messages from 2 packets (0 skipped packets, 0 duplicate and 2 missing messages)"), "{}", log);
+}
@@ -0,0 +1,52 @@
+// The order pool finds resting orders by ref and reuses the slots of removed ones
+use nasdaq_itch_orderbook::orderbook::{Order, Side};
+use nasdaq_itch_orderbook::pool::OrderPool;
+
+fn order(ref_number: u64, shares: u32) -> Order {
+    Order { ref_number, timestamp: 1_000 + ref_number, price: 1_500_000, shares, side: Side::Buy, mpid: None }
+}
+
+#[test]
+fn insert_get_remove() {
+    let mut pool = OrderPool::with_capacity(4);
+    assert!(pool.is_empty());
+    for ref_number in 1..=3 {
+        assert!(pool.insert(order(ref_number, 100)).is_none());
+    }
+    pool.get_mut(2).unwrap().shares = 40;
+    assert_eq!(pool.get(2).map(|o| o.shares), Some(40));
+    assert_eq!(pool.remove(2).map(|o| o.ref_number), Some(2));
+    assert!(pool.get(2).is_none());
+    assert!(pool.remove(2).is_none());
+
+    // The new order takes the freed slot without disturbing the others
+    pool.insert(order(4, 300));
+    assert_eq!(pool.get(4).map(|o| o.shares), Some(300));
+    assert_eq!(pool.get(1).map(|o| o.ref_number), Some(1));
+    let mut refs: Vec<u64> = pool.values().map(|o| o.ref_number).collect();
+    refs.sort();
+    assert_eq!(refs, vec![1, 3, 4]);
+    assert_eq!(pool.len(), 3);
+}
+
+#[test]
+fn insert_replaces_same_ref() {
+    let mut pool = OrderPool::default();
+    pool.insert(order(7, 100));
+    assert_eq!(pool.insert(order(7, 200)).map(|o| o.shares), Some(100));
+    assert_eq!(pool.get(7).map(|o| o.shares), Some(200));
+    assert_eq!(pool.len(), 1);
+}
+
+#[test]
+fn churn() {
+    let mut pool = OrderPool::default();
+    for ref_number in 0..10_000u64 {
+        pool.insert(order(ref_number, 100));
+        if ref_number >= 100 {
+            assert!(pool.remove(ref_number - 100).is_some());
+        }
+    }
+    assert_eq!(pool.len(), 100);
+    assert!((9_900..10_000).all(|ref_number| pool.get(ref_number).is_some_and(|o| o.ref_number == ref_number)));
+}
@@ -0,0 +1,62 @@
+// --postgres loads the snapshot rows into a table it creates; the load needs a server, named by the
+// ITCH_TEST_POSTGRES connection string, and is skipped without one
+#![cfg(feature = "postgres")]
+
+use postgres::{Client, NoTls};
+
+mod common;
+
+use common::{add, binary, execute, free_port, system_event, TempFile};
+
+fn load(name: &str, url: &str, extra: &[&str]) -> std::process::Output {
+    let input = TempFile::with(&format!("{}.itch", name), &[
+        add(1, 1, b'B', 100, 1_500_000),
+        add(2, 2, b'S', 200, 1_500_500),
+        execute(3, 1, 30, 7),
+        system_event(4, b'C'),
+    ].concat());
+    let output = TempFile::new(&format!("{}.csv", name));
+    binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--postgres", url])
+        .args(extra).output().unwrap()
+}
+
+#[test]
+fn loads_snapshot_rows() {
+    let Ok(url) = std::env::var("ITCH_TEST_POSTGRES") else {
+        eprintln!("ITCH_TEST_POSTGRES is not set, skipping");
+        return;
+    };
+    let mut client = Client::connect(&url, NoTls).unwrap();
+    client.batch_execute("DROP TABLE IF EXISTS itch_test_depth, itch_test_bbo").unwrap();
+
+    let output = load("postgres-depth", &url, &["--postgres-table", "itch_test_depth", "--postgres-batch-size", "2"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let rows = client.query("SELECT symbol, timestamp_ns, bid_price_1::TEXT, bid_vol_1, ask_price_1::TEXT, ask_vol_1 FROM itch_test_depth ORDER BY timestamp_ns", &[]).unwrap();
+    let rows: Vec<(String, i64, String, i64, String, i64)> =
+        rows.iter().map(|row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4), row.get(5))).collect();
+    assert_eq!(rows, [
+        ("AAPL".to_string(), 1, "150.0000".to_string(), 100, "0.0000".to_string(), 0),
+        ("AAPL".to_string(), 2, "150.0000".to_string(), 100, "150.0500".to_string(), 200),
+        ("AAPL".to_string(), 3, "150.0000".to_string(), 70, "150.0500".to_string(), 200),
+    ]);
+
+    let output = load("postgres-bbo", &url, &["--postgres-table", "itch_test_bbo", "--bbo-only"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let row = client.query_one("SELECT bid_size, ask::TEXT, spread::TEXT FROM itch_test_bbo ORDER BY timestamp_ns DESC LIMIT 1", &[]).unwrap();
+    assert_eq!((row.get::<_, i64>(0), row.get::<_, String>(1), row.get::<_, String>(2)), (70, "150.0500".to_string(), "0.0500".to_string()));
+    client.batch_execute("DROP TABLE itch_test_depth, itch_test_bbo").unwrap();
+}
+
+#[test]
+fn rejects_bad_table_names() {
+    let output = load("postgres-table", "host=localhost", &["--postgres-table", "books; DROP TABLE books"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid Postgres table name"));
+}
+
+#[test]
+fn fails_without_a_server() {
+    let output = load("postgres-down", &format!("host=127.0.0.1 port={} user=postgres", free_port()), &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Postgres error"));
+}
@@ -0,0 +1,68 @@
+// --prescan builds the same book as parsing every message
+mod common;
+
+use common::{add, add_of, cancel, delete, directory, execute, execute_of, message, replace, system_event, trade, TempFile};
+
+// Builds the book of the input with the extra options, with and without --prescan, and returns both outputs
+fn build_both(name: &str, input: &[u8], options: &[&str]) -> (String, String) {
+    let input = TempFile::with(&format!("{}.itch", name), input);
+    let full = TempFile::new(&format!("{}.csv", name));
+    let prescanned = TempFile::new(&format!("{}-prescan.csv", name));
+    common::run(&[&["build-book", "-f", input.path(), "-s", "AAPL", "-o", full.path()], options].concat());
+    common::run(&[&["build-book", "-f", input.path(), "-s", "AAPL", "-o", prescanned.path(), "--prescan"], options].concat());
+    (full.read(), prescanned.read())
+}
+
+fn replace_of(stock_locate: u16, timestamp: u64, original_order_ref_number: u64, new_order_ref_number: u64, shares: u32, price: u32) -> Vec<u8> {
+    message(b'U', stock_locate, timestamp, &[&original_order_ref_number.to_be_bytes(), &new_order_ref_number.to_be_bytes(),
+                                             &shares.to_be_bytes(), &price.to_be_bytes()])
+}
+
+#[test]
+fn matches_a_full_parse() {
+    let input = [
+        system_event(1, b'O'),
+        directory(1, "AAPL", 100),
+        directory(2, "MSFT", 100),
+        add(2, 1, b'B', 300, 1_500_000),
+        add_of(2, "MSFT", 3, 2, b'B', 100, 4_000_000),
+        add(4, 3, b'S', 200, 1_510_000),
+        // Replaced twice, then executed under its last ref
+        replace(5, 1, 4, 300, 1_501_000),
+        replace_of(2, 6, 2, 5, 100, 4_001_000),
+        replace(7, 4, 6, 250, 1_502_000),
+        execute(8, 6, 50, 1),
+        execute_of(2, 9, 5, 100, 2),
+        cancel(10, 3, 100),
+        trade(11, b'B', 100, 1_505_000, 3),
+        delete(12, 6),
+        delete(13, 3),
+    ].concat();
+    let (full, prescanned) = build_both("prescan", &input, &[]);
+    assert_eq!(full.lines().count(), 9);
+    assert_eq!(prescanned, full);
+}
+
+#[test]
+fn follows_replaces_across_the_file() {
+    // Large enough to be split across threads: the symbol's order is replaced all through the
+    // other symbol's flow and only executed at the end
+    let mut input = vec![directory(1, "AAPL", 100), directory(2, "MSFT", 100), add(2, 1, b'B', 100, 1_500_000)];
+    let mut aapl_ref = 1;
+    let mut next_ref = 2;
+    for i in 0..60_000u64 {
+        let timestamp = 3 + i * 3;
+        input.push(add_of(2, "MSFT", timestamp, next_ref, b'S', 100, 4_000_000 + (i % 50) as u32 * 100));
+        input.push(delete(timestamp + 1, next_ref));
+        next_ref += 1;
+        if i % 1000 == 0 {
+            input.push(replace(timestamp + 2, aapl_ref, next_ref, 100, 1_500_000 + (i / 1000) as u32 * 100));
+            aapl_ref = next_ref;
+            next_ref += 1;
+        }
+    }
+    input.push(execute(200_000, aapl_ref, 100, 1));
+    let (full, prescanned) = build_both("prescan-replaces", &input.concat(), &["--bbo-only"]);
+    assert_eq!(full.lines().count(), 62);
+    assert_eq!(prescanned, full);
+}
@@ -0,0 +1,82 @@
+// --price-band checks adds against the prevailing price, so stub quotes far from it can be flagged or kept
+// out of the depth
+mod common;
+
+use common::{add, build, execute};
+use nasdaq_itch_orderbook::analytics::OrderAnalytics;
+use nasdaq_itch_orderbook::integrity::{BandAction, IntegrityChecker, PriceBand};
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use serde_json::Value;
+
+// A one cent stub bid and a $200 stub ask around a $100.50 mid, and an order within the band
+fn feed() -> Vec<u8> {
+    [
+        add(1, 1, b'B', 100, 1_000_000),
+        // Checked against the bid alone
+        add(2, 2, b'S', 100, 1_010_000),
+        add(3, 3, b'B', 100, 100),
+        add(4, 4, b'S', 100, 2_000_000),
+        add(5, 5, b'B', 100, 990_000),
+        execute(6, 3, 50, 1),
+        OrderDeleteMessage { stock_locate: 1, tracking_number: 0, timestamp: 7, order_ref_number: 4 }.encode(),
+        // The stub bid comes back in the band
+        OrderReplaceMessage { stock_locate: 1, tracking_number: 0, timestamp: 8, original_order_ref_number: 3, new_order_ref_number: 6,
+                              shares: 100, price: 1_001_000 }.encode(),
+    ].concat()
+}
+
+fn run(action: BandAction) -> (OrderBook, Value, String) {
+    let dir = std::env::temp_dir();
+    let stats = dir.join(format!("band-{:?}-{}.json", action, std::process::id()));
+    let anomalies = dir.join(format!("band-{:?}-{}.csv", action, std::process::id()));
+    let options = OrderBookOptions {
+        price_band: Some(PriceBand { percent: 50.0, action }),
+        analytics: Some(OrderAnalytics::new(&stats)),
+        integrity: Some(IntegrityChecker::create(&anomalies).unwrap()),
+        ..Default::default()
+    };
+    let book = build(&feed(), options);
+    let summary = serde_json::from_str(&std::fs::read_to_string(&stats).unwrap()).unwrap();
+    let report = std::fs::read_to_string(&anomalies).unwrap();
+    std::fs::remove_file(&stats).unwrap();
+    std::fs::remove_file(&anomalies).unwrap();
+    (book, summary, report)
+}
+
+#[test]
+fn band_is_relative_to_the_reference() {
+    let band = PriceBand { percent: 50.0, action: BandAction::Flag };
+    assert!(band.contains(1_500_000, 1_000_000));
+    assert!(!band.contains(1_500_100, 1_000_000));
+    assert!(band.contains(500_000, 1_000_000));
+    assert!(!band.contains(499_900, 1_000_000));
+}
+
+#[test]
+fn flags_adds_outside_the_band() {
+    let (book, summary, report) = run(BandAction::Flag);
+    assert_eq!(summary["price_band"]["out_of_band_adds"], 2);
+    assert_eq!(summary["price_band"]["dropped"], 0);
+    assert_eq!(summary["orders"]["adds"], 5);
+    let rows: Vec<&str> = report.lines().skip(1).collect();
+    assert_eq!(rows, ["3,out_of_band,A,3,0.0100,100,reference 100.5000", "4,out_of_band,A,4,200.0000,100,reference 100.5000"]);
+    // Flagged orders stay on the book
+    let (bids, asks) = book.top_levels();
+    assert_eq!(bids.len(), 3);
+    assert_eq!(asks.len(), 1);
+}
+
+#[test]
+fn drops_adds_outside_the_band() {
+    let (book, summary, report) = run(BandAction::Drop);
+    assert_eq!(summary["price_band"]["out_of_band_adds"], 2);
+    assert_eq!(summary["price_band"]["dropped"], 2);
+    assert_eq!(summary["orders"]["adds"], 3);
+    // The dropped orders' execute and delete are no unknown refs
+    assert_eq!(report.lines().filter(|row| row.contains("unknown_order_ref")).count(), 0);
+    assert_eq!(report.lines().filter(|row| row.contains("out_of_band")).count(), 2);
+    let prices: Vec<u32> = book.top_levels().0.iter().map(|level| level.price).collect();
+    assert_eq!(prices, [1_001_000, 1_000_000, 990_000]);
+    assert_eq!(book.best_prices(), (Some(1_001_000), Some(1_010_000)));
+}
@@ -0,0 +1,60 @@
+// --price-format writes the book's prices as decimal dollars, raw 1/10000 dollar integers or integer ticks
+use std::fs::File;
+
+mod common;
+
+use common::{add, build};
+use nasdaq_itch_orderbook::features::{FeatureColumns, PriceFormat};
+use nasdaq_itch_orderbook::orderbook::{OrderBookOptions, OutputFormat};
+use nasdaq_itch_orderbook::sink::create_file_sink;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+
+// Writes a $150.01 bid and a $150.04 ask to a file and returns its path
+fn write(format: OutputFormat, bbo_only: bool, prices: PriceFormat) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("prices-{:?}-{:?}-{}-{}", format, prices, bbo_only, std::process::id()));
+    let sink = create_file_sink(format, &path, bbo_only, FeatureColumns::default().with_prices(prices), None).unwrap();
+    let data = [add(1, 1, b'B', 100, 1_500_100), add(2, 2, b'S', 100, 1_500_400)].concat();
+    build(&data, OrderBookOptions { bbo_only, sinks: vec![sink], ..Default::default() });
+    path
+}
+
+fn last_line(format: OutputFormat, bbo_only: bool, prices: PriceFormat) -> String {
+    let path = write(format, bbo_only, prices);
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    text.lines().last().unwrap().to_string()
+}
+
+#[test]
+fn converts_prices() {
+    assert_eq!(PriceFormat::Raw.integer(1_500_100), 1_500_100);
+    assert_eq!(PriceFormat::Ticks.integer(1_500_100), 15_001);
+    // Below $1 the tick is $0.0001
+    assert_eq!(PriceFormat::Ticks.integer(5_012), 5_012);
+    assert_eq!(PriceFormat::Ticks.spread(-300, 1_500_100), -3);
+    assert_eq!(PriceFormat::Decimal.json(1_500_100), "150.0100");
+}
+
+#[test]
+fn writes_bbo_rows() {
+    assert_eq!(last_line(OutputFormat::Csv, true, PriceFormat::Decimal), "2,150.0100,100,150.0400,100,150.0250,0.0300");
+    assert_eq!(last_line(OutputFormat::Csv, true, PriceFormat::Raw), "2,1500100,100,1500400,100,150.0250,300");
+    assert_eq!(last_line(OutputFormat::Csv, true, PriceFormat::Ticks), "2,15001,100,15004,100,150.0250,3");
+    let json: serde_json::Value = serde_json::from_str(&last_line(OutputFormat::Jsonl, true, PriceFormat::Raw)).unwrap();
+    assert_eq!((json["bid"].as_u64(), json["ask"].as_u64(), json["spread"].as_i64()), (Some(1_500_100), Some(1_500_400), Some(300)));
+}
+
+#[test]
+fn writes_depth_rows() {
+    let row = last_line(OutputFormat::Csv, false, PriceFormat::Ticks);
+    assert!(row.starts_with("2,15001,100,15004,100,0,0,0,0,"));
+    let json: serde_json::Value = serde_json::from_str(&last_line(OutputFormat::Jsonl, false, PriceFormat::Ticks)).unwrap();
+    assert_eq!((json["1_bid_price"].as_u64(), json["1_ask_price"].as_u64(), json["2_bid_price"].as_u64()), (Some(15_001), Some(15_004), Some(0)));
+
+    let path = write(OutputFormat::Parquet, false, PriceFormat::Raw);
+    let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+    let row = reader.get_row_iter(None).unwrap().last().unwrap().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!((row.get_long(1).unwrap(), row.get_long(3).unwrap()), (1_500_100, 1_500_400));
+}
@@ -0,0 +1,46 @@
+// profile counts the messages by type, hour and symbol
+mod common;
+
+use common::{add, directory, system_event, trade, TempFile};
+
+fn input() -> TempFile {
+    TempFile::with("profile.itch", &[
+        system_event(1, b'O'),
+        directory(1, "AAPL", 100),
+        add(2, 1, b'B', 100, 1_500_000),
+        add(3_600_000_000_000, 2, b'S', 100, 1_510_000),
+        trade(3_600_000_000_001, b'B', 100, 1_505_000, 1),
+    ].concat())
+}
+
+fn stdout(args: &[&str]) -> String {
+    String::from_utf8_lossy(&common::run(args).stdout).into_owned()
+}
+
+// The report's lines split into their fields
+fn rows(report: &str) -> Vec<Vec<&str>> {
+    report.lines().map(|line| line.split_whitespace().collect()).collect()
+}
+
+#[test]
+fn counts_by_type_hour_and_symbol() {
+    let input = input();
+    let profile = stdout(&["profile", "-f", input.path()]);
+    let rows = rows(&profile);
+    assert!(rows.contains(&vec!["A", "AddOrder", "2", "40.00%"]));
+    assert!(rows.contains(&vec!["Total", "5"]));
+    assert!(rows.contains(&vec!["00:00-01:00", "3", "60.00%"]));
+    assert!(rows.contains(&vec!["01:00-02:00", "2", "40.00%"]));
+    assert!(rows.contains(&vec!["1", "AAPL", "4", "80.00%"]));
+}
+
+#[test]
+fn names_types_like_stats() {
+    let input = input();
+    let type_rows = |report: &str| report.lines().skip_while(|line| !line.starts_with("Type")).take(5)
+        .map(str::to_string).collect::<Vec<_>>();
+    let profile = stdout(&["profile", "-f", input.path()]);
+    let stats = stdout(&["stats", "-f", input.path()]);
+    assert_eq!(type_rows(&profile), type_rows(&stats));
+    assert!(type_rows(&stats).iter().any(|row| row.starts_with("S     SystemEvent ")));
+}
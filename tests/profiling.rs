@@ -0,0 +1,43 @@
+// Stage timers count every message parsed and applied to the books, and every write to a sink, with the
+// nested stages' time inside their parent's (cargo test --features profiling)
+#![cfg(feature = "profiling")]
+use std::io;
+
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::profiling::{self, STAGES};
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+struct Discard;
+
+impl OutputSink for Discard {
+    fn write_snapshot(&mut self, _symbol: &str, _snapshot: &BookSnapshot) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn counts_every_stage() {
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![Box::new(Discard)], ..Default::default() })];
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    let (messages, updates) = books[0].counts();
+
+    let [parse, book, write] = profiling::totals();
+    assert_eq!(parse.calls, messages);
+    assert_eq!(book.calls, messages);
+    assert!(write.calls >= updates && updates > 0, "{} writes for {} updates", write.calls, updates);
+    assert!(parse.nanos >= book.nanos && book.nanos >= write.nanos);
+    assert_eq!(parse.self_nanos + book.self_nanos + write.self_nanos, parse.nanos);
+
+    let report = profiling::report();
+    for stage in STAGES {
+        assert!(report.lines().any(|line| line.starts_with(stage.name())), "{}", report);
+    }
+}
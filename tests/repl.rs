@@ -0,0 +1,71 @@
+// --interactive answers queries about the books and their trades once the input is processed
+mod common;
+
+use common::{add, build, execute};
+use nasdaq_itch_orderbook::orderbook::OrderBookOptions;
+use nasdaq_itch_orderbook::repl::{Explorer, TradeLog};
+
+const SECOND: u64 = 1_000_000_000;
+const NINE_THIRTY: u64 = 34_200 * SECOND;
+
+// Twelve bid levels and an ask, with one execution before 09:30 and two after
+fn explorer() -> Explorer {
+    let mut data: Vec<Vec<u8>> = (0..12).map(|i| add(1 + i, 1 + i, b'B', 100, 1_000_000 - 100 * i as u32)).collect();
+    data.extend([
+        add(20, 20, b'S', 300, 1_000_500),
+        execute(NINE_THIRTY - SECOND, 20, 100, 1),
+        execute(NINE_THIRTY + SECOND, 20, 50, 2),
+        execute(NINE_THIRTY + 120 * SECOND, 1, 40, 3),
+    ]);
+    let log = TradeLog::default();
+    let options = OrderBookOptions { sinks: vec![Box::new(log.clone())], ..Default::default() };
+    Explorer::new(vec![build(&data.concat(), options)], &[log])
+}
+
+#[test]
+fn shows_the_book() {
+    let answer = explorer().query("book aapl").unwrap();
+    let lines: Vec<&str> = answer.lines().collect();
+    assert_eq!(lines[0], "AAPL: bid 100.0000 x 60, ask 100.0500 x 150, mid 100.0250, spread 0.0500");
+    assert_eq!(lines[2], "    1         60     100.0000 | 100.0500     150");
+    assert_eq!(lines[6], "    5        100      99.9600 |");
+    assert_eq!(lines[7], "13 resting orders (12 bids, 1 asks); 3 trades, 190 shares, vwap 100.0395");
+}
+
+#[test]
+fn shows_depth_past_the_output_levels() {
+    let explorer = explorer();
+    assert_eq!(explorer.query("depth AAPL").unwrap().lines().count(), 11);
+    let answer = explorer.query("depth AAPL 20").unwrap();
+    assert_eq!(answer.lines().count(), 13);
+    assert_eq!(answer.lines().last().unwrap(), "   12        100      99.8900 |");
+}
+
+#[test]
+fn lists_trades_in_a_range() {
+    let explorer = explorer();
+    let answer = explorer.query("trades AAPL 09:30 09:35").unwrap();
+    let lines: Vec<&str> = answer.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1].starts_with("09:30:01 "));
+    assert!(lines[2].starts_with("09:32:00 "));
+    assert_eq!(lines[3], "2 trades, 90 shares, vwap 100.0278");
+    assert_eq!(explorer.query("trades AAPL").unwrap().lines().last().unwrap(), "3 trades, 190 shares, vwap 100.0395");
+}
+
+#[test]
+fn reports_bad_queries() {
+    let explorer = explorer();
+    assert_eq!(explorer.query("book MSFT").unwrap_err(), "No book for MSFT; the books are AAPL");
+    assert!(explorer.query("trades AAPL 09:35 09:30").is_err());
+    assert!(explorer.query("trades AAPL 9h").is_err());
+    assert!(explorer.query("orders AAPL").is_err());
+}
+
+#[test]
+fn runs_until_quit() {
+    let mut output = Vec::new();
+    explorer().run("symbols\n\nbogus\nquit\nsymbols\n".as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output, "Books of AAPL; type help for the queries\n> AAPL\n> > Error: Unknown query \"bogus\"; type help for the queries\n> ");
+}
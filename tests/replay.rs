@@ -0,0 +1,73 @@
+// SnapshotReplay hands out, one at a time, the snapshots the books write when the parser runs over the data
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::replay::SnapshotReplay;
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+struct Collect(Arc<Mutex<Vec<(String, BookSnapshot)>>>);
+
+impl OutputSink for Collect {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        self.0.lock().unwrap().push((symbol.to_string(), snapshot.clone()));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn matches_the_parser() {
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    let symbols = ["AAPL", "MSFT"];
+
+    let expected = Arc::new(Mutex::new(Vec::new()));
+    let mut books: Vec<OrderBook> = symbols.iter()
+        .map(|symbol| {
+            let sink = Box::new(Collect(Arc::clone(&expected)));
+            OrderBook::new(pad_stock_symbol(symbol), OrderBookOptions { sinks: vec![sink], ..Default::default() })
+        })
+        .collect();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+
+    let mut replay = SnapshotReplay::new(data.as_slice(), &symbols);
+    let mut snapshots = Vec::new();
+    while let Some((book, snapshot)) = replay.next_snapshot().unwrap() {
+        snapshots.push((replay.book(book).symbol_name().to_string(), snapshot));
+        assert!(replay.offset() <= replay.len());
+    }
+    assert_eq!(replay.offset(), data.len());
+    assert!(snapshots.iter().any(|(symbol, _)| symbol == "MSFT"));
+    assert_eq!(snapshots, *expected.lock().unwrap());
+}
+
+#[test]
+fn keeps_trades_and_rewinds() {
+    let data = SyntheticFeed { messages: 5_000, ..Default::default() }.generate();
+    let mut replay = SnapshotReplay::with_trades(data.as_slice(), &["AAPL"]);
+    let mut first = Vec::new();
+    let mut trades = Vec::new();
+    while let Some((_, snapshot)) = replay.next_snapshot().unwrap() {
+        first.push(snapshot);
+        trades.extend(replay.take_trades());
+    }
+    assert!(!trades.is_empty());
+    assert!(replay.take_trades().is_empty());
+    // Without with_trades none are kept
+    assert!(SnapshotReplay::new(data.as_slice(), &["AAPL"]).take_trades().is_empty());
+
+    replay.rewind();
+    assert_eq!(replay.offset(), 0);
+    let mut second = Vec::new();
+    while let Some((_, snapshot)) = replay.next_snapshot().unwrap() {
+        second.push(snapshot);
+    }
+    assert_eq!(first, second);
+    assert_eq!(replay.take_trades().len(), trades.len());
+}
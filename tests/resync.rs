@@ -0,0 +1,61 @@
+// A corrupt message header doesn't lose the rest of the file: the parser skips ahead to the next run of
+// well-formed messages and logs how much it skipped
+mod common;
+
+use common::{add, binary, cancel, execute, system_event, TempFile};
+
+fn messages() -> Vec<Vec<u8>> {
+    vec![
+        system_event(1, b'Q'),
+        add(2, 1, b'B', 100, 1_500_000),
+        add(3, 2, b'S', 200, 1_500_500),
+        execute(4, 1, 30, 7),
+        cancel(5, 2, 50),
+        add(6, 3, b'B', 300, 1_499_900),
+    ]
+}
+
+// (log, book)
+fn run(name: &str, contents: &[u8]) -> (String, String) {
+    let input = TempFile::with(&format!("{}.itch", name), contents);
+    let output = TempFile::new(&format!("{}.csv", name));
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path()]).output().unwrap();
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    (String::from_utf8_lossy(&result.stdout).into_owned(), output.read())
+}
+
+#[test]
+fn skips_garbage_between_messages() {
+    let (_, clean) = run("resync-clean", &messages().concat());
+    let mut messages = messages();
+    messages.insert(3, vec![0xff; 7]);
+
+    let (log, book) = run("resync-garbage", &messages.concat());
+    assert!(log.contains("Input is corrupt: resynchronized 1 times, skipping 7 bytes"), "{}", log);
+    assert_eq!(book, clean);
+}
+
+#[test]
+fn skips_a_message_with_a_corrupt_length() {
+    let mut messages = messages();
+    // The execution's length points far past the end of the file
+    messages[3][0] = 0xff;
+    let skipped = messages[3].len();
+
+    let (log, book) = run("resync-length", &messages.concat());
+    assert!(log.contains(&format!("Input is corrupt: resynchronized 1 times, skipping {} bytes", skipped)), "{}", log);
+    // Everything but the execution is applied
+    let last = book.lines().last().unwrap().to_string();
+    assert!(last.starts_with("6,150.0000,100,150.0500,150,149.9900,300,"), "{}", last);
+}
+
+#[test]
+fn ignores_a_truncated_tail() {
+    let mut contents = messages().concat();
+    contents.extend_from_slice(&add(7, 4, b'S', 100, 1_500_600)[..20]);
+
+    let (log, book) = run("resync-tail", &contents);
+    assert!(log.contains("Ignored 20 trailing bytes that don't form a complete message"), "{}", log);
+    assert!(!log.contains("Input is corrupt"), "{}", log);
+    assert_eq!(book.lines().count(), 6);
+}
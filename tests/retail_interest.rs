@@ -0,0 +1,24 @@
+// --features retail-interest adds the symbol's latest Retail Price Improvement interest to every row
+mod common;
+
+use common::{add, retail_interest, TempFile};
+
+#[test]
+fn writes_the_latest_interest() {
+    let input = TempFile::with("retail-interest.itch", &[
+        add(1, 1, b'B', 100, 1_500_000),
+        retail_interest(2, b'B'),
+        add(3, 2, b'S', 200, 1_500_500),
+        retail_interest(4, b'A'),
+        retail_interest(5, b'S'),
+        retail_interest(6, b'N'),
+    ].concat());
+    let output = TempFile::new("retail-interest.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--features", "retail-interest"]);
+
+    let csv = output.read();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().ends_with(",retail_interest"));
+    let interest: Vec<(&str, &str)> = lines.map(|line| (line.split(',').next().unwrap(), line.rsplit(',').next().unwrap())).collect();
+    assert_eq!(interest, [("1", "none"), ("2", "buy"), ("3", "buy"), ("4", "both"), ("5", "sell"), ("6", "none")]);
+}
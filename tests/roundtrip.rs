@@ -0,0 +1,112 @@
+// Every message struct encodes to its spec length and decodes back to itself, alone and inside a stream
+use std::ops::ControlFlow;
+
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::parser;
+use proptest::prelude::*;
+
+// Timestamps are 6 bytes on the wire
+const MAX_TIMESTAMP: u64 = (1 << 48) - 1;
+
+// The encoded message has the spec length and decodes back to the same struct
+fn check<M: WireMessage + PartialEq + std::fmt::Debug>(message: M) -> Result<(), TestCaseError> {
+    let bytes = message.encode();
+    let length = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+    prop_assert_eq!(Some(length), M::MESSAGE_TYPE.spec_length());
+    prop_assert_eq!(bytes.len(), length + 2);
+    prop_assert_eq!(bytes[2], M::MESSAGE_TYPE as u8);
+    prop_assert_eq!(M::decode(&bytes[3..]), Some(message));
+    prop_assert_eq!(M::decode(&bytes[3..bytes.len() - 1]), None);
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn system_event(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, event_code: u8) {
+        check(SystemEventMessage { stock_locate, tracking_number, timestamp, event_code })?;
+    }
+
+    #[test]
+    fn stock_directory(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, stock: [u8; 8],
+                       flags: [u8; 10], round_lot_size: u32, issue_sub_type: [u8; 2], etp_leverage_factor: u32) {
+        check(StockDirectoryMessage {
+            stock_locate, tracking_number, timestamp, stock,
+            market_category: flags[0],
+            financial_status_indicator: flags[1],
+            round_lot_size,
+            round_lots_only: flags[2],
+            issue_classification: flags[3],
+            issue_sub_type,
+            authenticity: flags[4],
+            short_sale_threshold_indicator: flags[5],
+            ipo_flag: flags[6],
+            luld_reference_price_tier: flags[7],
+            etp_flag: flags[8],
+            etp_leverage_factor,
+            inverse_indicator: flags[9],
+        })?;
+    }
+
+    #[test]
+    fn add_order(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, order_ref_number: u64,
+                 buy_sell_indicator: u8, shares: u32, stock: [u8; 8], price: u32, attribution: [u8; 4]) {
+        check(AddOrderMessage { stock_locate, tracking_number, timestamp, order_ref_number, buy_sell_indicator, shares, stock, price })?;
+        check(AddOrderWithMpidMessage {
+            stock_locate, tracking_number, timestamp, order_ref_number, buy_sell_indicator, shares, stock, price, attribution,
+        })?;
+    }
+
+    #[test]
+    fn order_executed(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, order_ref_number: u64,
+                      executed_shares: u32, match_number: u64, printable: u8, execution_price: u32) {
+        check(OrderExecutedMessage { stock_locate, tracking_number, timestamp, order_ref_number, executed_shares, match_number })?;
+        check(OrderExecutedWithPriceMessage {
+            stock_locate, tracking_number, timestamp, order_ref_number, executed_shares, match_number, printable, execution_price,
+        })?;
+    }
+
+    #[test]
+    fn order_cancel_delete_replace(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, order_ref_number: u64,
+                                   new_order_ref_number: u64, shares: u32, price: u32) {
+        check(OrderCancelMessage { stock_locate, tracking_number, timestamp, order_ref_number, cancelled_shares: shares })?;
+        check(OrderDeleteMessage { stock_locate, tracking_number, timestamp, order_ref_number })?;
+        check(OrderReplaceMessage {
+            stock_locate, tracking_number, timestamp, original_order_ref_number: order_ref_number, new_order_ref_number, shares, price,
+        })?;
+    }
+
+    #[test]
+    fn trade(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, order_ref_number: u64,
+             buy_sell_indicator: u8, shares: u32, stock: [u8; 8], price: u32, match_number: u64) {
+        check(TradeMessage {
+            stock_locate, tracking_number, timestamp, order_ref_number, buy_sell_indicator, shares, stock, price, match_number,
+        })?;
+    }
+
+    #[test]
+    fn stock_trading_action(stock_locate: u16, tracking_number: u16, timestamp in 0..=MAX_TIMESTAMP, stock: [u8; 8],
+                            trading_state: u8, reserved: u8, reason: [u8; 4]) {
+        check(StockTradingActionMessage { stock_locate, tracking_number, timestamp, stock, trading_state, reserved, reason })?;
+    }
+
+    // A stream of encoded messages is framed by the parser into the same messages
+    #[test]
+    fn stream(orders in prop::collection::vec((any::<u64>(), any::<u32>(), any::<u32>()), 1..50),
+              timestamp in 0..86_400_000_000_000u64) {
+        let adds: Vec<AddOrderMessage> = orders.iter()
+            .map(|&(order_ref_number, shares, price)| AddOrderMessage {
+                stock_locate: 1, tracking_number: 0, timestamp, order_ref_number, buy_sell_indicator: b'B', shares,
+                stock: *b"AAPL    ", price,
+            })
+            .collect();
+        let data: Vec<u8> = adds.iter().flat_map(|add| add.encode()).collect();
+
+        let mut parsed = Vec::new();
+        parser::scan_messages(&data, |message_type, message| {
+            assert_eq!(message_type, MessageType::AddOrder);
+            parsed.push(AddOrderMessage::decode(message).unwrap());
+            ControlFlow::Continue(())
+        });
+        prop_assert_eq!(parsed, adds);
+    }
+}
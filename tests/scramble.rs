@@ -0,0 +1,135 @@
+// scramble renames the symbols and permutes the order refs and timestamps of a file, and the books built from it
+// move exactly like the original's
+use std::collections::HashSet;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+use common::{add_of, execute_of, system_event};
+use nasdaq_itch_orderbook::message_types::*;
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions, PriceLevel, TradeRecord};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::scramble::{scramble_messages, Scrambler};
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::utils::{pad_stock_symbol, parse_offset};
+use nasdaq_itch_orderbook::wire::{self, ItchMessage};
+
+const SECOND: u64 = 1_000_000_000;
+const NINE_THIRTY: u64 = 34_200 * SECOND;
+
+// Top of the book after each update and the trades, as (timestamp, bid, ask) and (timestamp, price, shares)
+type Level = Option<(u32, u32)>;
+
+#[derive(Clone, Default)]
+struct Recorded {
+    tops: Vec<(u64, Level, Level)>,
+    trades: Vec<(u64, u32, u32)>,
+}
+
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Recorded>>);
+
+impl OutputSink for Recorder {
+    fn write_snapshot(&mut self, _symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let top = |levels: &[PriceLevel]| levels.first().map(|level| (level.price, level.total_volume));
+        self.0.lock().unwrap().tops.push((snapshot.timestamp, top(&snapshot.bid_levels), top(&snapshot.ask_levels)));
+        Ok(())
+    }
+
+    fn write_trade(&mut self, _symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.0.lock().unwrap().trades.push((trade.timestamp, trade.price, trade.shares));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn feed() -> Vec<u8> {
+    [
+        system_event(NINE_THIRTY, b'Q'),
+        add_of(7, "AAPL", NINE_THIRTY + 1, 100, b'B', 100, 1_000_000),
+        add_of(9, "MSFT", NINE_THIRTY + 2, 101, b'S', 100, 2_000_000),
+        add_of(7, "AAPL", NINE_THIRTY + 3, 102, b'S', 100, 1_000_500),
+        execute_of(7, NINE_THIRTY + SECOND, 102, 30, 1),
+        OrderReplaceMessage { stock_locate: 7, tracking_number: 0, timestamp: NINE_THIRTY + 2 * SECOND, original_order_ref_number: 100,
+                              new_order_ref_number: 103, shares: 80, price: 1_000_100 }.encode(),
+        OrderCancelMessage { stock_locate: 7, tracking_number: 0, timestamp: NINE_THIRTY + 3 * SECOND, order_ref_number: 103, cancelled_shares: 20 }.encode(),
+        OrderDeleteMessage { stock_locate: 7, tracking_number: 0, timestamp: NINE_THIRTY + 4 * SECOND, order_ref_number: 102 }.encode(),
+    ].concat()
+}
+
+fn scramble(seed: u64, time_shift: i64) -> (Vec<u8>, Scrambler) {
+    let mut scrambler = Scrambler::new(seed, time_shift);
+    let mut output = Vec::new();
+    assert_eq!(scramble_messages(&feed(), &mut scrambler, &mut output).unwrap(), 8);
+    (output, scrambler)
+}
+
+fn record(data: &[u8], symbol: [u8; 8]) -> Recorder {
+    let recorder = Recorder::default();
+    let mut books = [OrderBook::new(symbol, OrderBookOptions { sinks: vec![Box::new(recorder.clone())], ..Default::default() })];
+    parser::process_itch_file(data, &mut books, &ParserOptions::default()).unwrap();
+    recorder
+}
+
+#[test]
+fn keeps_the_book_dynamics() {
+    let shift = parse_offset("-15m").unwrap();
+    let (scrambled, mut scrambler) = scramble(42, shift);
+    let name = scrambler.symbol(pad_stock_symbol("AAPL"));
+    assert_ne!(name, pad_stock_symbol("AAPL"));
+    assert!(name[..4].iter().all(u8::is_ascii_uppercase));
+
+    let original = record(&feed(), pad_stock_symbol("AAPL")).0.lock().unwrap().clone();
+    let copy = record(&scrambled, name).0.lock().unwrap().clone();
+    assert_eq!(original.tops.len(), 6);
+    let shifted = |t: u64| (t as i64 + shift) as u64;
+    assert_eq!(copy.tops, original.tops.iter().map(|&(t, bid, ask)| (shifted(t), bid, ask)).collect::<Vec<_>>());
+    assert_eq!(copy.trades, original.trades.iter().map(|&(t, price, shares)| (shifted(t), price, shares)).collect::<Vec<_>>());
+}
+
+#[test]
+fn hides_the_identifiers() {
+    let (scrambled, _) = scramble(42, 0);
+    let mut messages = parser::ItchIterator::new(&scrambled).typed();
+    let _ = messages.next();
+    let ItchMessage::AddOrder(first) = messages.next().unwrap() else { panic!("expected an add") };
+    let ItchMessage::AddOrder(second) = messages.next().unwrap() else { panic!("expected an add") };
+    assert_ne!(first.order_ref_number.get(), 100);
+    assert_ne!(first.stock, second.stock);
+    // Locates are renumbered in order of first appearance
+    assert_eq!((first.header.stock_locate.get(), second.header.stock_locate.get()), (1, 2));
+    assert!(scrambled.windows(4).all(|bytes| bytes != b"AAPL" && bytes != b"MSFT"));
+    assert_eq!(wire::decode(&scrambled[2..]).header().unwrap().timestamp.get(), NINE_THIRTY);
+}
+
+#[test]
+fn keeps_a_ref_equal_to_the_seed() {
+    let scrambler = Scrambler::new(100, 0);
+    assert_eq!(scrambler.order_ref(0), 0);
+    let refs: HashSet<u64> = (1..=1000).map(|order_ref| scrambler.order_ref(order_ref)).collect();
+    assert_eq!(refs.len(), 1000);
+    assert!(!refs.contains(&0));
+
+    // The feed's first order has ref 100
+    let (scrambled, mut scrambler) = scramble(100, 0);
+    let original = record(&feed(), pad_stock_symbol("AAPL")).0.lock().unwrap().clone();
+    let copy = record(&scrambled, scrambler.symbol(pad_stock_symbol("AAPL"))).0.lock().unwrap().clone();
+    assert_eq!(copy.tops, original.tops);
+    assert_eq!(copy.trades, original.trades);
+}
+
+#[test]
+fn is_deterministic() {
+    assert_eq!(scramble(7, SECOND as i64).0, scramble(7, SECOND as i64).0);
+    assert_ne!(scramble(7, 0).0, scramble(8, 0).0);
+}
+
+#[test]
+fn rejects_a_shift_out_of_the_day() {
+    let mut scrambler = Scrambler::new(0, -(NINE_THIRTY as i64) - 1);
+    assert!(scramble_messages(&feed(), &mut scrambler, &mut Vec::new()).is_err());
+}
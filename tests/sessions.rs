@@ -0,0 +1,78 @@
+// --timezone with --date adds the UTC time and the trading session of every row, the session following the
+// feed's market hours events on early close days
+mod common;
+
+use common::{add, build, system_event};
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{OrderBookOptions, OutputFormat};
+use nasdaq_itch_orderbook::sink::create_file_sink;
+use nasdaq_itch_orderbook::timestamps::{parse_date, parse_timezone, MarketHours, Session, TimestampFormat, Timestamps};
+use nasdaq_itch_orderbook::utils::parse_time_of_day;
+
+fn at(time: &str) -> u64 {
+    parse_time_of_day(time).unwrap()
+}
+
+#[test]
+fn labels_sessions() {
+    let hours = MarketHours::default();
+    assert_eq!(hours.session(at("03:59:59")), Session::Closed);
+    assert_eq!(hours.session(at("04:00:00")), Session::PreMarket);
+    assert_eq!(hours.session(at("09:29:59.999999999")), Session::PreMarket);
+    assert_eq!(hours.session(at("09:30:00")), Session::Regular);
+    assert_eq!(hours.session(at("16:00:00")), Session::PostMarket);
+    assert_eq!(hours.session(at("20:00:00")), Session::Closed);
+
+    // The day after Thanksgiving closes at 13:00
+    let early_close = MarketHours { open: Some(at("09:30:00")), close: Some(at("13:00:00")) };
+    assert_eq!(early_close.session(at("12:59:59")), Session::Regular);
+    assert_eq!(early_close.session(at("13:00:00")), Session::PostMarket);
+}
+
+#[test]
+fn converts_from_the_time_zone() {
+    let chicago = parse_timezone("America/Chicago").unwrap();
+    assert_eq!(chicago.hours_behind_utc(parse_date("2020-07-01").unwrap()), 5);
+    assert_eq!(chicago.hours_behind_utc(parse_date("2020-01-30").unwrap()), 6);
+    let phoenix = parse_timezone("america/phoenix").unwrap();
+    assert_eq!(phoenix.hours_behind_utc(parse_date("2020-07-01").unwrap()), 7);
+    assert!(parse_timezone("Europe/Paris").is_err());
+
+    let utc = Timestamps { format: TimestampFormat::Iso8601, date: parse_date("2020-01-30").ok(), timezone: parse_timezone("UTC").ok() };
+    assert_eq!(utc.format(at("14:30:00")), "2020-01-30T14:30:00.000000000+00:00");
+    assert_eq!(utc.utc(at("14:30:00")), Some(1_580_394_600_000_000_000));
+}
+
+#[test]
+fn writes_utc_and_session_columns() {
+    let path = std::env::temp_dir().join(format!("sessions-{}.csv", std::process::id()));
+    let timestamps = Timestamps { date: parse_date("2019-11-29").ok(), timezone: parse_timezone("America/New_York").ok(), ..Default::default() };
+    let sink = create_file_sink(OutputFormat::Csv, &path, true, FeatureColumns::default().with_timestamps(timestamps), None).unwrap();
+    let data = [
+        add(at("03:30:00"), 1, b'B', 100, 1_000_001),
+        add(at("08:00:00"), 2, b'B', 100, 1_000_002),
+        system_event(at("09:30:00"), b'Q'),
+        add(at("09:30:00"), 3, b'B', 100, 1_000_003),
+        system_event(at("13:00:00"), b'M'),
+        add(at("13:00:01"), 4, b'B', 100, 1_000_004),
+        add(at("20:00:00"), 5, b'B', 100, 1_000_005),
+    ].concat();
+    build(&data, OrderBookOptions { sinks: vec![sink], ..Default::default() });
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().ends_with(",utc_timestamp,session"));
+    let columns: Vec<(&str, &str)> = lines.map(|row| {
+        let fields: Vec<&str> = row.rsplitn(3, ',').collect();
+        (fields[1], fields[0])
+    }).collect();
+    // 2019-11-29 was in standard time, 5 hours behind UTC
+    assert_eq!(columns, [
+        ("1575016200000000000", "closed"),
+        ("1575032400000000000", "pre_market"),
+        ("1575037800000000000", "regular"),
+        ("1575050401000000000", "post_market"),
+        ("1575075600000000000", "closed"),
+    ]);
+}
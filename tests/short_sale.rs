@@ -0,0 +1,43 @@
+// --features ssr follows the symbol's Reg SHO short sale price test restriction
+mod common;
+
+use common::{add, message, stock, TempFile, LOCATE, SYMBOL};
+
+fn reg_sho(timestamp: u64, action: u8) -> Vec<u8> {
+    message(b'Y', LOCATE, timestamp, &[&stock(SYMBOL), &[action]])
+}
+
+fn input(name: &str) -> TempFile {
+    TempFile::with(name, &[
+        add(1, 1, b'B', 100, 1_500_000),
+        // Intraday price drop, then a repeat that changes nothing and writes no row, then lifted
+        reg_sho(2, b'1'),
+        reg_sho(3, b'2'),
+        reg_sho(4, b'0'),
+    ].concat())
+}
+
+#[test]
+fn writes_the_restriction() {
+    let input = input("short-sale.itch");
+    let output = TempFile::new("short-sale.csv");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--features", "ssr"]);
+
+    let csv = output.read();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().ends_with(",short_sale_restricted"));
+    let restricted: Vec<(&str, &str)> = lines.map(|line| (line.split(',').next().unwrap(), line.rsplit(',').next().unwrap())).collect();
+    assert_eq!(restricted, [("1", "0"), ("2", "1"), ("4", "0")]);
+}
+
+#[test]
+fn adds_the_restriction_to_json_snapshots() {
+    let input = input("short-sale-json.itch");
+    let output = TempFile::new("short-sale.jsonl");
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--output-format", "jsonl", "--features", "ssr"]);
+
+    let restricted: Vec<serde_json::Value> = output.read().lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["short_sale_restricted"].clone())
+        .collect();
+    assert_eq!(restricted, [0, 1, 0]);
+}
@@ -0,0 +1,73 @@
+// A stop request ends the parse at a message boundary with the books finalized as usual, and a shutdown
+// closes WebSocket sessions with a close frame before the server returns
+#![cfg(feature = "native")]
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::shutdown;
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use nasdaq_itch_orderbook::websocket::{WebSocketServer, BROADCAST_CAPACITY};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::Message;
+
+// Asks for a stop after the first snapshot
+struct StopAfterFirst(Arc<AtomicBool>);
+
+impl OutputSink for StopAfterFirst {
+    fn write_snapshot(&mut self, _symbol: &str, _snapshot: &BookSnapshot) -> io::Result<()> {
+        self.0.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn parser_stops_on_request() {
+    let data = SyntheticFeed { messages: 50_000, ..Default::default() }.generate();
+    let stop = Arc::new(AtomicBool::new(false));
+    let sink = Box::new(StopAfterFirst(stop.clone()));
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], ..Default::default() })];
+    let options = ParserOptions { stop: Some(stop), ..Default::default() };
+    parser::process_itch_file(&data, &mut books, &options).unwrap();
+    books[0].finalize().unwrap();
+
+    let (messages, updates) = books[0].counts();
+    assert!(messages > 0 && messages < 50_000, "{} messages", messages);
+    assert!(updates >= 1);
+}
+
+#[tokio::test]
+async fn closes_websocket_sessions() {
+    let port = 18_473;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let server = tokio::spawn(async move { WebSocketServer::live(tx, port).start().await });
+
+    let url = format!("ws://127.0.0.1:{}", port);
+    let mut client = loop {
+        match tokio_tungstenite::connect_async(&url).await {
+            Ok((client, _)) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+    // Wait for the session to be set up before shutting down
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown::request();
+
+    let message = tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap();
+    let Message::Close(Some(frame)) = message else {
+        panic!("expected a close frame, got {:?}", message);
+    };
+    assert_eq!(frame.code, CloseCode::Away);
+    tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap().unwrap();
+}
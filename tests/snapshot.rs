@@ -0,0 +1,50 @@
+// The snapshot subcommand writes every order resting at a point in time, in queue order, for each symbol asked for
+mod common;
+
+use common::{add, add_of, delete, execute, TempFile};
+
+const NINE_THIRTY: u64 = 34_200_000_000_000;
+const MILLISECOND: u64 = 1_000_000;
+
+fn input() -> TempFile {
+    TempFile::with("snapshot.itch", &[
+        add(NINE_THIRTY, 1, b'B', 100, 1_500_000),
+        add(NINE_THIRTY + 1, 2, b'B', 200, 1_500_000),
+        add(NINE_THIRTY + 2, 3, b'B', 300, 1_501_000),
+        add(NINE_THIRTY + 3, 4, b'S', 400, 1_502_000),
+        add_of(2, "MSFT", NINE_THIRTY + 4, 5, b'S', 500, 4_000_000),
+        execute(NINE_THIRTY + 5, 1, 40, 1),
+        // After the snapshot time
+        delete(NINE_THIRTY + 2 * MILLISECOND, 2),
+        add(NINE_THIRTY + 3 * MILLISECOND, 6, b'S', 100, 1_501_500),
+    ].concat())
+}
+
+#[test]
+fn writes_resting_orders_in_queue_order() {
+    let input = input();
+    let output = TempFile::new("snapshot.csv");
+    common::run(&["snapshot", "-f", input.path(), "--at", "09:30:00.001", "--symbols", "AAPL,MSFT", "-o", output.path()]);
+
+    assert_eq!(output.read(), "\
+symbol,side,price,shares,position,order_ref,timestamp
+AAPL,B,150.1000,300,1,3,34200000000002
+AAPL,B,150.0000,60,1,1,34200000000000
+AAPL,B,150.0000,200,2,2,34200000000001
+AAPL,S,150.2000,400,1,4,34200000000003
+MSFT,S,400.0000,500,1,5,34200000000004
+");
+}
+
+#[test]
+fn writes_json() {
+    let input = input();
+    let output = TempFile::new("snapshot.json");
+    common::run(&["snapshot", "-f", input.path(), "--at", "34200002500000", "--symbols", "AAPL", "-o", output.path(), "--format", "json"]);
+
+    let book: serde_json::Value = serde_json::from_str(output.read().trim_end()).unwrap();
+    assert_eq!(book["symbol"], "AAPL");
+    let bids: Vec<u64> = book["bids"].as_array().unwrap().iter().map(|o| o["order_ref"].as_u64().unwrap()).collect();
+    assert_eq!(bids, [3, 1]);
+    assert_eq!(book["asks"].as_array().unwrap().len(), 1);
+}
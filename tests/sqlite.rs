@@ -0,0 +1,65 @@
+// --output-format sqlite writes the snapshots, the trades and the market events into one database
+use rusqlite::Connection;
+
+mod common;
+
+use common::{add, binary, execute, system_event, trade, TempFile};
+
+fn database(name: &str, extra: &[&str]) -> (TempFile, Connection) {
+    let input = TempFile::with(&format!("{}.itch", name), &[
+        system_event(1, b'Q'),
+        add(2, 1, b'B', 100, 1_500_000),
+        add(3, 2, b'S', 200, 1_500_500),
+        execute(4, 1, 30, 7),
+        trade(5, b'S', 50, 1_500_200, 8),
+        system_event(6, b'C'),
+        system_event(7, b'C'),
+    ].concat());
+    let output = TempFile::new(&format!("{}.db", name));
+    let status = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path(), "--output-format", "sqlite"])
+        .args(extra).status().unwrap();
+    assert!(status.success());
+    let conn = Connection::open(output.path()).unwrap();
+    (output, conn)
+}
+
+#[test]
+fn writes_snapshots_trades_and_events() {
+    let (_output, conn) = database("sqlite-depth", &[]);
+
+    let mut stmt = conn.prepare("SELECT symbol, timestamp, bid_price_1, bid_vol_1, ask_price_1, ask_vol_1 FROM snapshots ORDER BY timestamp").unwrap();
+    let snapshots: Vec<(String, i64, f64, i64, f64, i64)> = stmt.query_map([], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+    }).unwrap().map(Result::unwrap).collect();
+    assert_eq!(snapshots, [
+        ("AAPL".to_string(), 2, 150.0, 100, 0.0, 0),
+        ("AAPL".to_string(), 3, 150.0, 100, 150.05, 200),
+        ("AAPL".to_string(), 4, 150.0, 70, 150.05, 200),
+    ]);
+
+    let mut stmt = conn.prepare("SELECT timestamp, price, shares, side, match_number, message_type FROM trades ORDER BY timestamp").unwrap();
+    let trades: Vec<(i64, f64, i64, String, i64, String)> = stmt.query_map([], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+    }).unwrap().map(Result::unwrap).collect();
+    assert_eq!(trades, [
+        (4, 150.0, 30, "B".to_string(), 7, "E".to_string()),
+        (5, 150.02, 50, "S".to_string(), 8, "P".to_string()),
+    ]);
+
+    let (message_type, event): (String, String) = conn.query_row("SELECT message_type, event FROM events ORDER BY timestamp LIMIT 1", [], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    }).unwrap();
+    assert_eq!((message_type.as_str(), event.as_str()), ("S", "Start of Market Hours"));
+
+    let indexes: i64 = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name LIKE '%_timestamp_idx'", [], |row| row.get(0)).unwrap();
+    assert_eq!(indexes, 3);
+}
+
+#[test]
+fn writes_bbo_columns() {
+    let (_output, conn) = database("sqlite-bbo", &["--bbo-only"]);
+    let (bid_size, ask, spread): (i64, f64, f64) = conn.query_row("SELECT bid_size, ask, spread FROM snapshots ORDER BY timestamp DESC LIMIT 1", [], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    }).unwrap();
+    assert_eq!((bid_size, ask, spread), (70, 150.05, 0.05));
+}
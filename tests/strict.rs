@@ -0,0 +1,101 @@
+// --strict checks every message against the spec, reports what's wrong with its offset, leaves malformed
+// messages out of the book and exits non-zero
+mod common;
+
+use common::{add, binary, execute, message, stock, system_event, TempFile, LOCATE};
+
+// (succeeded, log, book)
+fn run(name: &str, contents: &[u8], extra: &[&str]) -> (bool, String, String) {
+    let input = TempFile::with(&format!("{}.itch", name), contents);
+    let output = TempFile::new(&format!("{}.csv", name));
+    let result = binary().args(["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path()]).args(extra).output().unwrap();
+    (result.status.success(), String::from_utf8_lossy(&result.stdout).into_owned(), output.read())
+}
+
+fn valid() -> Vec<Vec<u8>> {
+    vec![
+        system_event(1, b'Q'),
+        add(2, 1, b'B', 100, 1_500_000),
+        add(3, 2, b'S', 200, 1_500_500),
+        execute(4, 1, 30, 7),
+    ]
+}
+
+#[test]
+fn passes_valid_files() {
+    let (succeeded, log, book) = run("strict-valid", &valid().concat(), &["--strict"]);
+    assert!(succeeded);
+    assert!(log.contains("Strict validation passed"), "{}", log);
+    assert_eq!(book.lines().count(), 4);
+}
+
+#[test]
+fn fails_on_unknown_types() {
+    let mut messages = valid();
+    messages.insert(2, message(b'Z', LOCATE, 2, &[&[0; 8]]));
+    let offset: usize = messages[..2].iter().map(Vec::len).sum();
+    let contents = messages.concat();
+
+    let (succeeded, log, book) = run("strict-unknown", &contents, &["--strict"]);
+    assert!(!succeeded);
+    assert!(log.contains(&format!("offset {}: unknown message type 0x5a", offset)), "{}", log);
+    assert!(log.contains("Strict validation failed: 1 unknown message types, 0 not defined for the feed"), "{}", log);
+    // The rest of the file is still applied and written
+    assert_eq!(book.lines().count(), 4);
+
+    let (succeeded, _, lenient) = run("strict-unknown-lenient", &contents, &[]);
+    assert!(succeeded);
+    assert_eq!(lenient, book);
+}
+
+#[test]
+fn fails_on_types_outside_the_feed() {
+    let mut messages = valid();
+    messages.push(message(b'Q', LOCATE, 5, &[&500u64.to_be_bytes(), &stock("AAPL"), &1_500_100u32.to_be_bytes(), &9u64.to_be_bytes(), b"O"]));
+    let contents = messages.concat();
+
+    let (succeeded, log, _) = run("strict-nasdaq", &contents, &["--strict"]);
+    assert!(succeeded, "{}", log);
+    let (succeeded, log, _) = run("strict-bx", &contents, &["--strict", "--feed", "bx"]);
+    assert!(!succeeded);
+    assert!(log.contains("message type 'Q' is not defined for the Bx feed"), "{}", log);
+}
+
+#[test]
+fn fails_on_bad_lengths() {
+    let mut messages = valid();
+    // An add order with a stray byte at the end
+    let mut long_add = add(2, 3, b'B', 100, 1_500_100);
+    long_add.push(0);
+    long_add[1] += 1;
+    messages.insert(2, long_add);
+
+    let (succeeded, log, book) = run("strict-length", &messages.concat(), &["--strict"]);
+    assert!(!succeeded);
+    assert!(log.contains("'A' message is 37 bytes, expected 36, skipped 39 bytes"), "{}", log);
+    assert!(!book.contains("150.0100"), "{}", book);
+    assert_eq!(book.lines().count(), 4);
+}
+
+#[test]
+fn fails_on_out_of_order_timestamps() {
+    let mut messages = valid();
+    messages.push(add(3, 3, b'B', 100, 1_499_000));
+
+    let (succeeded, log, book) = run("strict-order", &messages.concat(), &["--strict"]);
+    assert!(!succeeded);
+    assert!(log.contains("timestamp 3 is before the previous message's 4"), "{}", log);
+    assert!(log.contains("1 out of order timestamps"), "{}", log);
+    // The message is well formed, so it is still applied
+    assert_eq!(book.lines().count(), 5);
+}
+
+#[test]
+fn fails_on_trailing_bytes() {
+    let mut contents = valid().concat();
+    contents.extend_from_slice(&[0, 36, b'A']);
+
+    let (succeeded, log, _) = run("strict-trailing", &contents, &["--strict"]);
+    assert!(!succeeded);
+    assert!(log.contains("3 trailing bytes don't form a complete message"), "{}", log);
+}
@@ -0,0 +1,53 @@
+// The run summary counts what each sink was handed and what the book saw, and hashes the input as stored
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::sink::NullSink;
+use nasdaq_itch_orderbook::summary::{self, RunStatus, RunSummary};
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+
+#[test]
+fn counts_messages_and_rows() {
+    let data = SyntheticFeed { messages: 10_000, ..Default::default() }.generate();
+    let path = std::env::temp_dir().join(format!("summary-{}.itch", std::process::id()));
+    std::fs::write(&path, &data).unwrap();
+
+    let mut run = RunSummary::new(&path, vec!["AAPL".to_string()]);
+    let sink = run.count(Box::new(NullSink), "null", None);
+    let options = OrderBookOptions { sinks: vec![sink], ..Default::default() };
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), options)];
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    books[0].finalize().unwrap();
+    run.record_books(&books);
+    run.sha256 = Some(summary::sha256_file(&path).unwrap());
+    run.finish(None, false);
+    std::fs::remove_file(&path).unwrap();
+
+    let json = run.to_json();
+    let (messages, updates) = books[0].counts();
+    assert_eq!(json["status"], "completed");
+    assert_eq!(json["exit_code"], 0);
+    assert_eq!(json["messages"]["total"], messages);
+    let by_type: u64 = json["messages"]["by_type"].as_object().unwrap().values().map(|count| count.as_u64().unwrap()).sum();
+    assert_eq!(by_type, messages);
+    assert_eq!(json["outputs"][0]["rows"], updates);
+    assert_eq!(json["end_offset"], data.len() as u64);
+    assert_eq!(json["input"]["sha256"].as_str().unwrap().len(), 64);
+}
+
+#[test]
+fn hashes_and_statuses() {
+    let path = std::env::temp_dir().join(format!("summary-abc-{}", std::process::id()));
+    std::fs::write(&path, b"abc").unwrap();
+    let hash = summary::sha256_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(hash, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+
+    let mut run = RunSummary::new(&path, Vec::new());
+    run.finish(None, true);
+    assert_eq!(run.status, RunStatus::Interrupted);
+    assert_eq!(run.to_json()["exit_code"], 130);
+    run.finish(Some("disk full".to_string()), true);
+    assert_eq!(run.status, RunStatus::Failed);
+    assert_eq!(run.to_json()["error"], "disk full");
+}
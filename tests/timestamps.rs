@@ -0,0 +1,87 @@
+// --timestamp-format writes the timestamp column as nanoseconds, microseconds, seconds or ISO 8601 times, and
+// --date anchors them to the trading day
+use std::fs::File;
+
+use nasdaq_itch_orderbook::features::FeatureColumns;
+use nasdaq_itch_orderbook::orderbook::{OrderBook, OrderBookOptions, OutputFormat};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::sink::create_file_sink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::timestamps::{parse_date, TimestampFormat, Timestamps};
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+
+// 09:30:00.123456789
+const OPEN: u64 = 34_200_123_456_789;
+
+fn format(format: TimestampFormat, date: Option<&str>) -> String {
+    Timestamps { format, date: date.map(|date| parse_date(date).unwrap()), timezone: None }.format(OPEN)
+}
+
+#[test]
+fn formats_timestamps() {
+    assert_eq!(format(TimestampFormat::Ns, None), "34200123456789");
+    assert_eq!(format(TimestampFormat::Us, None), "34200123456");
+    assert_eq!(format(TimestampFormat::Seconds, None), "34200.123456789");
+    assert_eq!(format(TimestampFormat::Iso8601, None), "09:30:00.123456789");
+
+    // 09:30 EST is 14:30 UTC
+    assert_eq!(format(TimestampFormat::Ns, Some("2020-01-30")), "1580394600123456789");
+    assert_eq!(format(TimestampFormat::Us, Some("2020-01-30")), "1580394600123456");
+    assert_eq!(format(TimestampFormat::Seconds, Some("2020-01-30")), "1580394600.123456789");
+    assert_eq!(format(TimestampFormat::Iso8601, Some("2020-01-30")), "2020-01-30T09:30:00.123456789-05:00");
+    assert_eq!(format(TimestampFormat::Iso8601, Some("2020-07-01")), "2020-07-01T09:30:00.123456789-04:00");
+}
+
+#[test]
+fn follows_daylight_saving_time() {
+    let offset = |date: &str| parse_date(date).unwrap().eastern_utc_offset();
+    // Daylight saving time started on Sunday March 8 and ended on Sunday November 1 2020
+    assert_eq!(offset("2020-03-06"), 5);
+    assert_eq!(offset("2020-03-09"), 4);
+    assert_eq!(offset("2020-10-30"), 4);
+    assert_eq!(offset("2020-11-02"), 5);
+    assert_eq!(offset("2024-02-29"), 5);
+
+    assert!(parse_date("2020-02-30").is_err());
+    assert!(parse_date("2021-02-29").is_err());
+    assert!(parse_date("2020-1-30").is_err());
+    assert!(parse_date("01302020").is_err());
+}
+
+fn write(format: OutputFormat, extension: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("timestamps-{}.{}", std::process::id(), extension));
+    let timestamps = Timestamps { format: TimestampFormat::Iso8601, date: Some(parse_date("2020-01-30").unwrap()), timezone: None };
+    let sink = create_file_sink(format, &path, true, FeatureColumns::default().with_timestamps(timestamps), None).unwrap();
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], ..Default::default() })];
+    let data = SyntheticFeed { messages: 2_000, ..Default::default() }.generate();
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default()).unwrap();
+    books[0].finalize().unwrap();
+    path
+}
+
+#[test]
+fn writes_the_format_to_every_output() {
+    let path = write(OutputFormat::Csv, "csv");
+    let csv = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let csv_times: Vec<String> = csv.lines().skip(1).map(|row| row.split(',').next().unwrap().to_string()).collect();
+
+    let path = write(OutputFormat::Jsonl, "jsonl");
+    let jsonl = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let json_times: Vec<String> = jsonl.lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["timestamp"].as_str().unwrap().to_string())
+        .collect();
+
+    let path = write(OutputFormat::Parquet, "parquet");
+    let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+    let parquet_times: Vec<String> = reader.get_row_iter(None).unwrap().map(|row| row.unwrap().get_string(0).unwrap().clone()).collect();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(!csv_times.is_empty());
+    assert!(csv_times.iter().all(|time| time.starts_with("2020-01-30T") && time.ends_with("-05:00") && time.len() == 35));
+    assert_eq!(csv_times, json_times);
+    assert_eq!(csv_times, parquet_times);
+}
@@ -0,0 +1,67 @@
+// With a certificate the WebSocket server speaks wss:// and streams updates over it, and refuses plain ws://
+#![cfg(feature = "tls")]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use nasdaq_itch_orderbook::tls;
+use nasdaq_itch_orderbook::websocket::{BookUpdate, WebSocketServer, BROADCAST_CAPACITY};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_tungstenite::tungstenite::Message;
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/localhost.crt");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/localhost.key");
+
+// A client that trusts only the test certificate
+fn connector() -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(CERT).unwrap()).unwrap();
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions().unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+async fn connect(port: u16) -> TcpStream {
+    loop {
+        match TcpStream::connect(("127.0.0.1", port)).await {
+            Ok(stream) => return stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn serves_wss() {
+    let port = 18_474;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let server = WebSocketServer::live(tx.clone(), port).with_tls(tls::acceptor(Path::new(CERT), Path::new(KEY)).unwrap());
+    tokio::spawn(async move { server.start().await });
+
+    let stream = connector().connect(ServerName::try_from("localhost").unwrap(), connect(port).await).await.unwrap();
+    let (mut client, _) = tokio_tungstenite::client_async(format!("wss://localhost:{}", port), stream).await.unwrap();
+    // Wait for the session to subscribe before publishing
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tx.send(BookUpdate { symbol: None, json: "{\"type\":\"test\"}".to_string(), snapshot: None }).unwrap();
+    let message = tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap();
+    assert_eq!(message, Message::Text("{\"type\":\"test\"}".to_string()));
+
+    // A plaintext handshake doesn't get through
+    let plain = tokio_tungstenite::client_async(format!("ws://localhost:{}", port), connect(port).await).await;
+    assert!(plain.is_err());
+}
+
+#[test]
+fn rejects_missing_key() {
+    let missing = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/missing.key");
+    let error = tls::acceptor(Path::new(CERT), &missing).err().unwrap();
+    assert!(error.to_string().contains("missing.key"), "{}", error);
+}
@@ -0,0 +1,37 @@
+// Trades get the side that took liquidity: from the execution for E and C, and by Lee-Ready for P
+mod common;
+
+use common::{add, execute, trade, TempFile};
+
+#[test]
+fn signs_trades() {
+    let input = TempFile::with("trade-sign.itch", &[
+        // Without a quote: nothing to compare the first trade with, then the tick test
+        trade(1, b'B', 100, 1_500_000, 1),
+        trade(2, b'B', 100, 1_500_100, 2),
+        trade(3, b'B', 100, 1_500_100, 3),
+        add(4, 1, b'B', 100, 1_499_000),
+        add(5, 2, b'S', 100, 1_501_000),
+        // Against the 150.0000 mid
+        trade(6, b'B', 100, 1_500_500, 4),
+        trade(7, b'B', 100, 1_499_500, 5),
+        // At the mid the tick test decides, and a zero tick keeps the last direction
+        trade(8, b'B', 100, 1_500_000, 6),
+        trade(9, b'B', 100, 1_500_000, 7),
+        execute(10, 1, 10, 8),
+    ].concat());
+    let output = TempFile::new("trade-sign.csv");
+    common::run(&["trades", "-f", input.path(), "-s", "AAPL", "-o", output.path()]);
+
+    assert_eq!(output.read(), "\
+timestamp,price,shares,side,match_number,message_type,aggressor,sign_method
+1,150.0000,100,B,1,P,,unknown
+2,150.0100,100,B,2,P,B,tick
+3,150.0100,100,B,3,P,B,tick
+6,150.0500,100,B,4,P,B,quote
+7,149.9500,100,B,5,P,S,quote
+8,150.0000,100,B,6,P,B,tick
+9,150.0000,100,B,7,P,B,tick
+10,149.9000,10,B,8,E,S,itch
+");
+}
@@ -0,0 +1,106 @@
+#![cfg(feature = "native")]
+// The view subcommand's viewer replays the book against a market time clock, which keys pause, speed up and
+// move, and draws it as a ladder, trade prints and a mid sparkline
+use std::time::Duration;
+
+mod common;
+
+use common::{add, execute};
+use nasdaq_itch_orderbook::viewer::Viewer;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+use ratatui::Terminal;
+
+const SECOND: u64 = 1_000_000_000;
+const NINE_THIRTY: u64 = 34_200 * SECOND;
+
+// A bid and an ask at 09:30:00, a higher bid a second later, an execution of the ask at 09:30:02 and a
+// new ask two minutes after that
+fn feed() -> Vec<u8> {
+    [
+        add(NINE_THIRTY, 1, b'B', 100, 1_000_000),
+        add(NINE_THIRTY + 1, 2, b'S', 100, 1_000_500),
+        add(NINE_THIRTY + SECOND, 3, b'B', 100, 1_000_100),
+        execute(NINE_THIRTY + 2 * SECOND, 2, 40, 1),
+        add(NINE_THIRTY + 122 * SECOND, 4, b'S', 100, 1_000_400),
+    ].concat()
+}
+
+fn best_bid<D: std::ops::Deref<Target = [u8]>>(viewer: &Viewer<D>) -> u32 {
+    viewer.snapshot().unwrap().bid_levels[0].price
+}
+
+fn key(code: KeyCode) -> KeyEvent {
+    KeyEvent::from(code)
+}
+
+#[test]
+fn plays_in_market_time() {
+    let data = feed();
+    let mut viewer = Viewer::new(data.as_slice(), "AAPL", 10, 1.0).unwrap();
+    assert_eq!(viewer.clock(), NINE_THIRTY);
+    assert_eq!(viewer.snapshot().unwrap().timestamp, NINE_THIRTY);
+
+    viewer.advance(Duration::from_millis(500)).unwrap();
+    assert_eq!(best_bid(&viewer), 1_000_000);
+    assert_eq!(viewer.snapshot().unwrap().ask_levels[0].price, 1_000_500);
+    viewer.advance(Duration::from_millis(500)).unwrap();
+    assert_eq!(best_bid(&viewer), 1_000_100);
+    assert!(viewer.prints().is_empty());
+
+    // Twice as fast, the execution is due after another half second
+    assert!(viewer.handle_key(key(KeyCode::Char('+'))).unwrap());
+    assert_eq!(viewer.speed(), 2.0);
+    viewer.advance(Duration::from_millis(500)).unwrap();
+    assert_eq!(viewer.prints().iter().map(|trade| (trade.price, trade.shares)).collect::<Vec<_>>(), [(1_000_500, 40)]);
+}
+
+#[test]
+fn pauses_and_seeks() {
+    let data = feed();
+    let mut viewer = Viewer::new(data.as_slice(), "AAPL", 10, 1.0).unwrap();
+    viewer.handle_key(key(KeyCode::Char(' '))).unwrap();
+    assert!(viewer.paused());
+    viewer.advance(Duration::from_secs(5)).unwrap();
+    assert_eq!(viewer.clock(), NINE_THIRTY);
+
+    viewer.handle_key(key(KeyCode::Right)).unwrap();
+    assert_eq!(viewer.clock(), NINE_THIRTY + 60 * SECOND);
+    assert_eq!(viewer.prints().len(), 1);
+    viewer.handle_key(key(KeyCode::PageDown)).unwrap();
+    assert_eq!(viewer.snapshot().unwrap().ask_levels[0].price, 1_000_400);
+
+    // Back is replayed from the start, to the same book as playing up to there
+    viewer.handle_key(key(KeyCode::PageUp)).unwrap();
+    assert_eq!(viewer.clock(), NINE_THIRTY + 60 * SECOND);
+    assert_eq!(viewer.snapshot().unwrap().ask_levels[0].price, 1_000_500);
+    assert_eq!(viewer.snapshot().unwrap().ask_levels[0].total_volume, 60);
+    assert_eq!(viewer.prints().len(), 1);
+    // Home goes back to the first update, not midnight
+    viewer.handle_key(key(KeyCode::Home)).unwrap();
+    assert_eq!(viewer.clock(), NINE_THIRTY);
+    assert!(!viewer.handle_key(key(KeyCode::Char('q'))).unwrap());
+}
+
+#[test]
+fn draws_the_ladder_and_the_prints() {
+    let data = feed();
+    let mut viewer = Viewer::new(data.as_slice(), "AAPL", 10, 1.0).unwrap();
+    viewer.seek(NINE_THIRTY + 3 * SECOND).unwrap();
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    terminal.draw(|frame| viewer.render(frame)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let lines: Vec<String> = (0..buffer.area.height)
+        .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+        .collect();
+
+    assert!(lines[0].starts_with(" AAPL  09:30:03.000  1x  playing"));
+    let screen = lines.join("\n");
+    // The ask above the bids
+    let ask = lines.iter().position(|line| line.contains("│           100.0500     60")).unwrap();
+    let bid = lines.iter().position(|line| line.contains("│100        100.0100")).unwrap();
+    assert!(ask < bid);
+    assert!(lines[bid + 1].contains("│100        100.0000"));
+    assert!(screen.contains("09:30:02.000 100.0500     40       B"));
+    assert!(screen.contains("Mid 100.0300"));
+}
@@ -0,0 +1,189 @@
+// Clients of the WebSocket server pick the symbols they receive with subscribe and unsubscribe commands, and pace
+// their own replay with speed, pause and seek, in JSON or binary frames
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+mod common;
+
+use common::{add, free_port, Server, TempFile};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const NINE_THIRTY: u64 = 34_200_000_000_000;
+const MILLISECOND: u64 = 1_000_000;
+
+// A replay of a thousand rows, a millisecond of market time apart from 09:30
+fn replay(name: &str) -> (Server, u16, TempFile, TempFile) {
+    let feed: Vec<u8> = (0..1000u64)
+        .flat_map(|i| add(NINE_THIRTY + i * MILLISECOND, i + 1, if i % 2 == 0 { b'B' } else { b'S' }, 100, 1_000_000 + i as u32))
+        .collect();
+    let input = TempFile::with(&format!("{}.itch", name), &feed);
+    let output = TempFile::new(&format!("{}.csv", name));
+    common::run(&["build-book", "-f", input.path(), "-s", "AAPL", "-o", output.path()]);
+    let port = free_port();
+    let server = Server::start(&["replay", "-i", output.path(), "-s", "AAPL", "-p", &port.to_string()], port);
+    (server, port, input, output)
+}
+
+async fn connect(port: u16) -> Client {
+    connect_async(format!("ws://127.0.0.1:{}/", port)).await.unwrap().0
+}
+
+// The next binary frame, past the JSON metadata
+async fn next_frame(client: &mut Client) -> Vec<u8> {
+    loop {
+        match timeout(Duration::from_secs(5), client.next()).await.expect("no frame") {
+            Some(Ok(Message::Binary(frame))) => return frame,
+            Some(Ok(Message::Text(_))) => continue,
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+}
+
+fn u32_at(frame: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(frame[offset..offset + 4].try_into().unwrap())
+}
+
+async fn send(client: &mut Client, command: Value) {
+    client.send(Message::Text(command.to_string())).await.unwrap();
+}
+
+// The next JSON message, None when nothing comes within the wait
+async fn next(client: &mut Client, wait: Duration) -> Option<Value> {
+    match timeout(wait, client.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => Some(serde_json::from_str(&text).unwrap()),
+        Ok(message) => panic!("unexpected message {:?}", message),
+        Err(_) => None,
+    }
+}
+
+// Reads past the rows and the metadata to the reply of a command
+async fn reply(client: &mut Client) -> Value {
+    loop {
+        let message = next(client, Duration::from_secs(5)).await.expect("no reply");
+        if message.get("type").is_some_and(|kind| kind != "metadata") {
+            return message;
+        }
+    }
+}
+
+// Whether a row of the symbol comes within the wait
+async fn receives_rows(client: &mut Client, symbol: &str) -> bool {
+    while let Some(message) = next(client, Duration::from_millis(300)).await {
+        if message["symbol"] == symbol {
+            return true;
+        }
+    }
+    false
+}
+
+#[tokio::test]
+async fn filters_by_subscription() {
+    let (_server, port, _input, _output) = replay("ws-subscribe");
+    let mut client = connect(port).await;
+
+    send(&mut client, json!({"subscribe": ["msft"]})).await;
+    assert_eq!(reply(&mut client).await, json!({"type": "subscriptions", "all": false, "symbols": ["MSFT"], "excluded": []}));
+    assert!(!receives_rows(&mut client, "AAPL").await);
+
+    send(&mut client, json!({"subscribe": ["AAPL"]})).await;
+    assert_eq!(reply(&mut client).await["symbols"], json!(["AAPL", "MSFT"]));
+    assert!(receives_rows(&mut client, "AAPL").await);
+
+    send(&mut client, json!({"unsubscribe": ["AAPL"]})).await;
+    assert_eq!(reply(&mut client).await["symbols"], json!(["MSFT"]));
+    assert!(!receives_rows(&mut client, "AAPL").await);
+}
+
+#[tokio::test]
+async fn excludes_symbols_while_receiving_all() {
+    let (_server, port, _input, _output) = replay("ws-exclude");
+    let mut client = connect(port).await;
+    assert!(receives_rows(&mut client, "AAPL").await);
+
+    send(&mut client, json!({"unsubscribe": ["AAPL"]})).await;
+    assert_eq!(reply(&mut client).await, json!({"type": "subscriptions", "all": true, "symbols": [], "excluded": ["AAPL"]}));
+    assert!(!receives_rows(&mut client, "AAPL").await);
+}
+
+#[tokio::test]
+async fn rejects_unknown_commands() {
+    let (_server, port, _input, _output) = replay("ws-unknown");
+    let mut client = connect(port).await;
+    send(&mut client, json!({"resubscribe": ["AAPL"]})).await;
+    let reply = reply(&mut client).await;
+    assert_eq!(reply["type"], "error");
+    assert!(reply["message"].as_str().unwrap().starts_with("Unknown command"));
+}
+
+#[tokio::test]
+async fn pauses_and_resumes() {
+    let (_server, port, _input, _output) = replay("ws-pause");
+    let mut client = connect(port).await;
+    send(&mut client, json!({"pause": true})).await;
+    let state = reply(&mut client).await;
+    assert_eq!((&state["type"], &state["paused"], &state["speed"]), (&json!("replay"), &json!(true), &json!(1)));
+    assert!(!receives_rows(&mut client, "AAPL").await);
+
+    send(&mut client, json!({"speed": 10.0})).await;
+    assert_eq!(reply(&mut client).await["speed"], 10.0);
+    send(&mut client, json!({"pause": false})).await;
+    assert_eq!(reply(&mut client).await["paused"], false);
+    assert!(receives_rows(&mut client, "AAPL").await);
+}
+
+#[tokio::test]
+async fn seeks_to_a_time_of_day() {
+    let (_server, port, _input, _output) = replay("ws-seek");
+    let mut client = connect(port).await;
+    let half_past = (NINE_THIRTY + 500 * MILLISECOND).to_string();
+
+    send(&mut client, json!({"seek": "09:30:00.5"})).await;
+    assert_eq!(reply(&mut client).await["timestamp"], half_past.as_str());
+    assert_eq!(next(&mut client, Duration::from_secs(5)).await.unwrap()["timestamp"], half_past.as_str());
+
+    // Back to the start
+    send(&mut client, json!({"seek": 0})).await;
+    assert_eq!(reply(&mut client).await["timestamp"], NINE_THIRTY.to_string());
+    assert_eq!(next(&mut client, Duration::from_secs(5)).await.unwrap()["timestamp"], NINE_THIRTY.to_string());
+}
+
+#[tokio::test]
+async fn rejects_bad_replay_commands() {
+    let (_server, port, _input, _output) = replay("ws-bad-replay");
+    let mut client = connect(port).await;
+    for command in [json!({"speed": -1}), json!({"pause": "yes"}), json!({"seek": "9:75"})] {
+        send(&mut client, command).await;
+        assert_eq!(reply(&mut client).await["type"], "error");
+    }
+}
+
+#[tokio::test]
+async fn sends_binary_frames() {
+    let (_server, port, _input, _output) = replay("ws-binary");
+    let mut client = connect_async(format!("ws://127.0.0.1:{}/?format=binary", port)).await.unwrap().0;
+
+    // The first row has the bid alone, the second the ask too
+    for (bid, ask) in [((1_000_000, 100), (0, 0)), ((1_000_000, 100), (1_000_001, 100))] {
+        let frame = next_frame(&mut client).await;
+        let depth = frame[1] as usize;
+        assert_eq!(frame[0], 1);
+        assert_eq!(frame.len(), 26 + depth * 16);
+        assert_eq!(&frame[2..10], b"AAPL    ");
+        assert_eq!((u32_at(&frame, 26), u32_at(&frame, 30)), bid);
+        assert_eq!((u32_at(&frame, 34), u32_at(&frame, 38)), ask);
+    }
+    let frame = next_frame(&mut client).await;
+    assert_eq!(u64::from_le_bytes(frame[10..18].try_into().unwrap()), NINE_THIRTY + 2 * MILLISECOND);
+}
+
+#[tokio::test]
+async fn rejects_unknown_formats() {
+    let (_server, port, _input, _output) = replay("ws-format");
+    assert!(connect_async(format!("ws://127.0.0.1:{}/?format=xml", port)).await.is_err());
+}
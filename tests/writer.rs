@@ -0,0 +1,68 @@
+// A sink on the writer thread gets everything in order, even through a queue of one
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use nasdaq_itch_orderbook::orderbook::{BookSnapshot, OrderBook, OrderBookOptions, TradeRecord};
+use nasdaq_itch_orderbook::parser::{self, ParserOptions};
+use nasdaq_itch_orderbook::sink::OutputSink;
+use nasdaq_itch_orderbook::testdata::SyntheticFeed;
+use nasdaq_itch_orderbook::utils::pad_stock_symbol;
+use nasdaq_itch_orderbook::writer::WriterThread;
+
+#[derive(Clone, Default)]
+struct Recorder {
+    rows: Arc<Mutex<Vec<String>>>,
+    finalized: Arc<Mutex<bool>>,
+    fail_after: Option<usize>,
+}
+
+impl OutputSink for Recorder {
+    fn write_snapshot(&mut self, symbol: &str, snapshot: &BookSnapshot) -> io::Result<()> {
+        let mut rows = self.rows.lock().unwrap();
+        if self.fail_after.is_some_and(|limit| rows.len() >= limit) {
+            return Err(io::Error::other("disk full"));
+        }
+        rows.push(format!("{} {} {}", symbol, snapshot.timestamp, snapshot.mid_price));
+        Ok(())
+    }
+
+    fn write_trade(&mut self, symbol: &str, trade: &TradeRecord) -> io::Result<()> {
+        self.rows.lock().unwrap().push(format!("{} trade {}", symbol, trade.match_number));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        *self.finalized.lock().unwrap() = true;
+        Ok(())
+    }
+}
+
+fn run(sink: Box<dyn OutputSink>) -> io::Result<()> {
+    let data = SyntheticFeed { messages: 20_000, ..Default::default() }.generate();
+    let mut books = [OrderBook::new(pad_stock_symbol("AAPL"), OrderBookOptions { sinks: vec![sink], ..Default::default() })];
+    parser::process_itch_file(&data, &mut books, &ParserOptions::default())?;
+    books[0].finalize()
+}
+
+#[test]
+fn same_output_as_writing_inline() {
+    let inline = Recorder::default();
+    run(Box::new(inline.clone())).unwrap();
+    let threaded = Recorder::default();
+    run(Box::new(WriterThread::spawn(Box::new(threaded.clone()), 1).unwrap())).unwrap();
+
+    assert!(!inline.rows.lock().unwrap().is_empty());
+    assert_eq!(*threaded.rows.lock().unwrap(), *inline.rows.lock().unwrap());
+    assert!(*threaded.finalized.lock().unwrap());
+}
+
+#[test]
+fn reports_write_errors() {
+    let failing = Recorder { fail_after: Some(10), ..Default::default() };
+    let error = run(Box::new(WriterThread::spawn(Box::new(failing), 4).unwrap())).unwrap_err();
+    assert_eq!(error.to_string(), "disk full");
+}
@@ -0,0 +1,64 @@
+// With --ws-compression, clients that connect with ?compression=zstd get every message as a zstd binary frame,
+// and other clients are served as before
+#![cfg(feature = "native")]
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use nasdaq_itch_orderbook::outbox::ClientPolicy;
+use nasdaq_itch_orderbook::websocket::{BookUpdate, WebSocketServer, BROADCAST_CAPACITY};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+async fn next_message(client: &mut Client) -> Message {
+    tokio::time::timeout(Duration::from_secs(5), client.next()).await.unwrap().unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn compresses_for_clients_that_ask() {
+    let port = 18_477;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let policy = ClientPolicy { compression: Some(3), ..Default::default() };
+    let server = WebSocketServer::live(tx.clone(), port).with_client_policy(policy);
+    tokio::spawn(async move { server.start().await });
+
+    let url = format!("ws://127.0.0.1:{}", port);
+    let mut compressed = loop {
+        match tokio_tungstenite::connect_async(format!("{}/?compression=zstd", url)).await {
+            Ok((client, _)) => break client,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+    let (mut plain, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+    assert!(tokio_tungstenite::connect_async(format!("{}/?compression=brotli", url)).await.is_err());
+
+    // Wait for the sessions to subscribe before publishing
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let json = format!("{{\"symbol\":\"AAPL\",\"bids\":[{}]}}", vec!["[100.4200,500]"; 50].join(","));
+    tx.send(BookUpdate { symbol: Some("AAPL".into()), json: json.clone(), snapshot: None }).unwrap();
+
+    let Message::Binary(frame) = next_message(&mut compressed).await else {
+        panic!("expected a binary frame");
+    };
+    assert!(frame.len() < json.len() / 5, "{} of {} bytes", frame.len(), json.len());
+    assert_eq!(zstd::decode_all(frame.as_slice()).unwrap(), json.as_bytes());
+    assert_eq!(next_message(&mut plain).await, Message::Text(json));
+}
+
+#[tokio::test]
+async fn refuses_compression_unless_enabled() {
+    let port = 18_478;
+    let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+    let server = WebSocketServer::live(tx, port);
+    tokio::spawn(async move { server.start().await });
+
+    let url = format!("ws://127.0.0.1:{}", port);
+    while tokio_tungstenite::connect_async(&url).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let error = tokio_tungstenite::connect_async(format!("{}/?compression=zstd", url)).await.err().unwrap();
+    assert!(error.to_string().contains("400"), "{}", error);
+}